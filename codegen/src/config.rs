@@ -1,18 +1,53 @@
 use crate::types::ShaderType;
+use knuffel::span::Span;
 use knuffel::Decode;
+use std::fmt::{Display, Formatter};
 
+// Nodes other configs refer to by name keep their spans, so that broken references can be reported
+// with a location the same as syntax errors are. Everything containing them has to be decoded with
+// the concrete span type too.
 #[derive(Debug, Decode)]
+#[knuffel(span_type = knuffel::span::Span)]
 pub struct Renderer {
     #[knuffel(children(name = "sampler"))]
     pub samplers: Vec<Sampler>,
-    #[knuffel(child)]
-    pub descriptor_set: DescriptorSet,
+    #[knuffel(children(name = "descriptor-set"))]
+    pub descriptor_sets: Vec<DescriptorSet>,
     #[knuffel(children(name = "pass"))]
     pub passes: Vec<Pass>,
     #[knuffel(children(name = "compute"))]
     pub computes: Vec<Compute>,
     #[knuffel(children(name = "specialization"))]
     pub specializations: Vec<Specialization>,
+    #[knuffel(child)]
+    pub push_constant: Option<PushConstant>,
+    #[knuffel(children(name = "shared-struct"))]
+    pub shared_structs: Vec<SharedStruct>,
+}
+
+// There's only a single pipeline layout shared by every pipeline, so push constants are declared
+// once for all of them, as a single range starting at offset zero. The size is taken from the Rust
+// type, so it can only be checked against device limits at runtime.
+#[derive(Debug, Decode)]
+pub struct PushConstant {
+    #[knuffel(argument)]
+    pub typ: String,
+    #[knuffel(argument)]
+    pub stage: String,
+}
+
+// Structs laid out the same way in Rust and GLSL, which have their field offsets checked at compile
+// time against the ones computed from the definitions in shaders/types. Nested structs are checked
+// together with the struct containing them, and only need to be listed when their Rust name is
+// different, as Rust types are looked up in the uniform module by default.
+#[derive(Debug, Decode)]
+pub struct SharedStruct {
+    #[knuffel(argument)]
+    pub name: String,
+    #[knuffel(argument)]
+    pub layout: String,
+    #[knuffel(property)]
+    pub rust: Option<String>,
 }
 
 #[derive(Debug, Decode)]
@@ -25,18 +60,33 @@ pub struct Sampler {
     pub address_mode: String,
     #[knuffel(child)]
     pub unnormalized_coordinates: bool,
+    #[knuffel(child, unwrap(argument))]
+    pub compare_op: Option<String>,
+    #[knuffel(child, unwrap(argument), default = "FLOAT_TRANSPARENT_BLACK".into())]
+    pub border_color: String,
 }
 
+// Sets are numbered in the order they're declared in, and every pipeline layout includes all of
+// them. Binding names have to be unique across all sets, as the generated update functions are only
+// named after the binding.
 #[derive(Debug, Decode)]
+#[knuffel(span_type = knuffel::span::Span)]
 pub struct DescriptorSet {
+    #[knuffel(argument)]
+    pub name: String,
     #[knuffel(children)]
     pub bindings: Vec<DescriptorBinding>,
 }
 
 #[derive(Debug, Decode)]
+#[knuffel(span_type = knuffel::span::Span)]
 pub enum DescriptorBinding {
     AccelerationStructure(AccelerationStructureBinding),
+    // Same as an image, except that shaders sample it with a direction, so the view has to be
+    // created with the cube view type.
+    Cubemap(ImageBinding),
     Image(ImageBinding),
+    ImageArray(ImageArrayBinding),
     InputAttachment(InputAttachmentBinding),
     StorageBuffer(StorageBufferBinding),
     StorageImage(StorageImageBinding),
@@ -52,7 +102,10 @@ pub struct AccelerationStructureBinding {
 }
 
 #[derive(Debug, Decode)]
+#[knuffel(span_type = knuffel::span::Span)]
 pub struct ImageBinding {
+    #[knuffel(span)]
+    pub span: Span,
     #[knuffel(argument)]
     pub name: String,
     #[knuffel(argument)]
@@ -63,6 +116,24 @@ pub struct ImageBinding {
     pub layout: String,
 }
 
+// Array of images indexed dynamically in shaders, so that materials can refer to textures by index
+// instead of needing separate descriptor sets. Slots are filled in one by one after creating the
+// descriptor set, and unused ones are left unwritten.
+#[derive(Debug, Decode)]
+#[knuffel(span_type = knuffel::span::Span)]
+pub struct ImageArrayBinding {
+    #[knuffel(span)]
+    pub span: Span,
+    #[knuffel(argument)]
+    pub name: String,
+    #[knuffel(argument)]
+    pub stage: String,
+    #[knuffel(argument)]
+    pub sampler: String,
+    #[knuffel(property)]
+    pub count: usize,
+}
+
 #[derive(Debug, Decode)]
 pub struct InputAttachmentBinding {
     #[knuffel(argument)]
@@ -100,6 +171,7 @@ pub struct UniformBinding {
 }
 
 #[derive(Debug, Decode)]
+#[knuffel(span_type = knuffel::span::Span)]
 pub struct Pass {
     #[knuffel(argument)]
     pub name: String,
@@ -109,6 +181,16 @@ pub struct Pass {
     pub debug_color: SdrColor,
     #[knuffel(child)]
     pub msaa: bool,
+    #[knuffel(child)]
+    pub depth_only: bool,
+    #[knuffel(child)]
+    pub color_only: bool,
+    #[knuffel(child)]
+    pub dynamic_viewport: bool,
+    // Passes rendering into intermediate images rather than anything presented can use a different
+    // format than the swapchain one, for example to keep values above one.
+    #[knuffel(child, unwrap(argument))]
+    pub color_format: Option<String>,
     #[knuffel(children(name = "pipeline"))]
     pub pipelines: Vec<Pipeline>,
 }
@@ -124,7 +206,10 @@ pub struct SdrColor {
 }
 
 #[derive(Debug, Decode)]
+#[knuffel(span_type = knuffel::span::Span)]
 pub struct Pipeline {
+    #[knuffel(span)]
+    pub span: Span,
     #[knuffel(argument)]
     pub name: String,
     #[knuffel(property, default = false)]
@@ -147,6 +232,71 @@ pub struct Pipeline {
     pub polygon_mode: String,
     #[knuffel(child, unwrap(argument), default = "BACK".into())]
     pub cull_mode: String,
+    #[knuffel(child, unwrap(argument), default = "TRIANGLE_LIST".into())]
+    pub topology: String,
+    #[knuffel(child, unwrap(argument), default = true)]
+    pub depth_test: bool,
+    #[knuffel(child, unwrap(argument), default = true)]
+    pub depth_write: bool,
+    #[knuffel(child, unwrap(argument), default = "LESS_OR_EQUAL".into())]
+    pub depth_compare: String,
+    #[knuffel(child)]
+    pub additive_blend: bool,
+    // Standard over blending for transparent surfaces, which have to be drawn back-to-front after
+    // everything opaque.
+    #[knuffel(child)]
+    pub alpha_blend: bool,
+    // Explicit list of color attachments for pipelines writing more than one, like a G-buffer. The
+    // blend mode is given per attachment, so it can't be combined with the flags above.
+    #[knuffel(child)]
+    pub color_attachments: Option<ColorAttachments>,
+    #[knuffel(child)]
+    pub push_constants: Option<PushConstantsConfig>,
+}
+
+#[derive(Debug, Decode)]
+pub struct ColorAttachments {
+    #[knuffel(children(name = "attachment"))]
+    pub attachments: Vec<ColorAttachment>,
+}
+
+// The format defaults to the one of the pass, the same as for pipelines with a single attachment.
+#[derive(Debug, Decode)]
+pub struct ColorAttachment {
+    #[knuffel(argument)]
+    pub name: String,
+    #[knuffel(property, default = "none".into())]
+    pub blend: String,
+    #[knuffel(property)]
+    pub format: Option<String>,
+}
+
+// Push constants of a single pipeline, declared either with just a size in bytes or field by field,
+// in which case a Rust struct with the same std430 layout is generated too:
+//
+//     push-constants stages="VERTEX|FRAGMENT" {
+//         field "position" "vec3"
+//         field "scale" "float"
+//     }
+//
+// The pipeline layout is still shared, so the ranges of all pipelines are merged into one, and
+// can't be combined with the push constant block of the whole renderer.
+#[derive(Debug, Decode)]
+pub struct PushConstantsConfig {
+    #[knuffel(property)]
+    pub size: Option<usize>,
+    #[knuffel(property)]
+    pub stages: String,
+    #[knuffel(children(name = "field"))]
+    pub fields: Vec<PushConstantField>,
+}
+
+#[derive(Debug, Decode)]
+pub struct PushConstantField {
+    #[knuffel(argument)]
+    pub name: String,
+    #[knuffel(argument)]
+    pub typ: String,
 }
 
 #[derive(Debug, Decode)]
@@ -160,17 +310,29 @@ pub struct VertexBinding {
 #[derive(Debug, Decode)]
 pub struct VertexAttribute {
     #[knuffel(argument)]
-    pub _name: String,
+    pub name: String,
     #[knuffel(argument)]
     pub format: String,
     #[knuffel(property, default)]
     pub unused: bool,
 }
 
+// Compute shaders get a generated dispatch function, which takes the number of invocations and
+// rounds it up to whole workgroups. The local size is passed to the shader as specialization
+// constants 0 to 2, which the shader can use with local_size_x_id and so on. Shaders declaring the
+// size directly instead are checked against it while validating shaders. Most computes produce buffers consumed by later
+// passes, and as the render graph only tracks images, the barrier for these can be declared here
+// too and is recorded right after the dispatch.
 #[derive(Debug, Decode)]
 pub struct Compute {
     #[knuffel(argument)]
     pub name: String,
+    #[knuffel(child, unwrap(arguments))]
+    pub local_size: Vec<u32>,
+    #[knuffel(child, unwrap(arguments))]
+    pub next_stages: Option<Vec<String>>,
+    #[knuffel(child, unwrap(arguments))]
+    pub next_access: Option<Vec<String>>,
 }
 
 #[derive(Debug, Decode)]
@@ -183,50 +345,127 @@ pub struct Specialization {
     pub shared: bool,
 }
 
+// Problems with renderer.kdl found before looking at any shaders. Everything other than syntax
+// errors points at the node with the broken reference.
+#[derive(Debug)]
+pub enum ConfigError {
+    Syntax(knuffel::Error),
+    DuplicatePipeline {
+        name: String,
+        span: Span,
+        first: Span,
+    },
+    UnknownSampler {
+        binding: String,
+        sampler: String,
+        span: Span,
+    },
+    UndeclaredSpecialization {
+        pipeline: String,
+        specialization: String,
+        span: Span,
+    },
+}
+
 impl Renderer {
     pub fn pipelines(&self) -> impl Iterator<Item = &Pipeline> {
         self.passes.iter().flat_map(|pass| &pass.pipelines)
     }
 
     pub fn shaders(&self) -> impl Iterator<Item = (&str, ShaderType)> {
-        self.pipelines().flat_map(|pipeline| {
-            let task_shader = if pipeline.task_shaders {
-                let task_shader = match &pipeline.task_shader {
-                    Some(path) => path.strip_suffix(".task").unwrap(),
-                    None => pipeline.name.as_str(),
-                };
-                Some((task_shader, ShaderType::Task))
-            } else {
-                None
-            };
-            let mesh_shader = if pipeline.mesh_shaders {
-                let mesh_shader = match &pipeline.mesh_shader {
-                    Some(path) => path.strip_suffix(".mesh").unwrap(),
-                    None => pipeline.name.as_str(),
-                };
-                Some((mesh_shader, ShaderType::Mesh))
-            } else {
-                None
+        self.pipelines().flat_map(Pipeline::shaders)
+    }
+
+    pub fn descriptor_bindings(&self) -> impl Iterator<Item = &DescriptorBinding> {
+        self.descriptor_sets.iter().flat_map(|set| &set.bindings)
+    }
+}
+
+impl Compute {
+    // Missing dimensions are one, the same as in GLSL.
+    pub fn local_size(&self) -> [u32; 3] {
+        assert!(
+            (1..=3).contains(&self.local_size.len()) && !self.local_size.contains(&0),
+            "local-size needs one to three nonzero values, compute: {}",
+            self.name
+        );
+        std::array::from_fn(|axis| self.local_size.get(axis).copied().unwrap_or(1))
+    }
+}
+
+impl Pipeline {
+    pub fn shaders(&self) -> impl Iterator<Item = (&str, ShaderType)> {
+        let task_shader = if self.task_shaders {
+            let task_shader = match &self.task_shader {
+                Some(path) => path.strip_suffix(".task").unwrap(),
+                None => self.name.as_str(),
             };
-            let vertex_shader = if !pipeline.mesh_shaders {
-                let vertex_shader = match &pipeline.vertex_shader {
-                    Some(path) => path.strip_suffix(".vert").unwrap(),
-                    None => pipeline.name.as_str(),
-                };
-                Some((vertex_shader, ShaderType::Vertex))
-            } else {
-                None
+            Some((task_shader, ShaderType::Task))
+        } else {
+            None
+        };
+        let mesh_shader = if self.mesh_shaders {
+            let mesh_shader = match &self.mesh_shader {
+                Some(path) => path.strip_suffix(".mesh").unwrap(),
+                None => self.name.as_str(),
             };
-            let fragment_shader = match &pipeline.fragment_shader {
-                Some(path) => path.strip_suffix(".frag").unwrap(),
-                None => pipeline.name.as_str(),
+            Some((mesh_shader, ShaderType::Mesh))
+        } else {
+            None
+        };
+        let vertex_shader = if !self.mesh_shaders {
+            let vertex_shader = match &self.vertex_shader {
+                Some(path) => path.strip_suffix(".vert").unwrap(),
+                None => self.name.as_str(),
             };
-            let fragment_shader = (fragment_shader, ShaderType::Fragment);
-            task_shader
-                .into_iter()
-                .chain(mesh_shader.into_iter())
-                .chain(vertex_shader.into_iter())
-                .chain(std::iter::once(fragment_shader))
-        })
+            Some((vertex_shader, ShaderType::Vertex))
+        } else {
+            None
+        };
+        let fragment_shader = match &self.fragment_shader {
+            Some(path) => path.strip_suffix(".frag").unwrap(),
+            None => self.name.as_str(),
+        };
+        let fragment_shader = (fragment_shader, ShaderType::Fragment);
+        task_shader
+            .into_iter()
+            .chain(mesh_shader)
+            .chain(vertex_shader)
+            .chain(std::iter::once(fragment_shader))
+    }
+}
+
+impl ConfigError {
+    pub fn span(&self) -> Option<Span> {
+        match self {
+            ConfigError::Syntax(_) => None,
+            ConfigError::DuplicatePipeline { span, .. }
+            | ConfigError::UnknownSampler { span, .. }
+            | ConfigError::UndeclaredSpecialization { span, .. } => Some(*span),
+        }
+    }
+}
+
+// Syntax errors use the debug format, as that's the one listing every problem knuffel found.
+impl Display for ConfigError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ConfigError::Syntax(error) => write!(f, "{error:?}"),
+            ConfigError::DuplicatePipeline { name, .. } => {
+                write!(f, "pipeline name used more than once, name: {name}")
+            }
+            ConfigError::UnknownSampler { binding, sampler, .. } => write!(
+                f,
+                "descriptor binding uses an undeclared sampler, binding: {binding}, sampler: {sampler}"
+            ),
+            ConfigError::UndeclaredSpecialization {
+                pipeline,
+                specialization,
+                ..
+            } => write!(
+                f,
+                "pipeline uses an undeclared specialization constant, pipeline: {pipeline}, specialization: {specialization}"
+            ),
+        }
     }
 }
@@ -1,42 +1,23 @@
 use crate::config::{
-    Compute, DescriptorBinding, Pass, Pipeline, Renderer, Sampler, VertexAttribute,
+    Compute, DescriptorBinding, DescriptorSet, Pass, Pipeline, Renderer, Sampler, VertexAttribute,
 };
 use crate::helper::to_camelcase;
-use crate::types::ShaderType;
+use crate::layout::{
+    is_struct, load_glsl_structs, push_constants_layout, struct_layout, BlockLayout,
+};
+use crate::types::{BindingType, BlendMode, ShaderType};
 use std::borrow::Cow;
-use std::collections::{BTreeSet, HashMap};
+use std::collections::{BTreeSet, HashMap, HashSet, VecDeque};
 use std::fmt::{Display, Formatter};
-use std::fs::File;
 use std::io::Write;
 
-#[derive(Clone, PartialEq)]
-enum BindingType {
-    AccelerationStructure,
-    Image,
-    InputAttachment,
-    StorageBuffer,
-    StorageImage,
-    Uniform,
-}
-
-impl BindingType {
-    fn name(&self) -> &'static str {
-        match self {
-            BindingType::AccelerationStructure => "ACCELERATION_STRUCTURE_KHR",
-            BindingType::Image => "COMBINED_IMAGE_SAMPLER",
-            BindingType::InputAttachment => "INPUT_ATTACHMENT",
-            BindingType::StorageBuffer => "STORAGE_BUFFER",
-            BindingType::StorageImage => "STORAGE_IMAGE",
-            BindingType::Uniform => "UNIFORM_BUFFER",
-        }
-    }
-}
-
 impl DescriptorBinding {
-    fn descriptor_type(&self) -> BindingType {
+    pub fn descriptor_type(&self) -> BindingType {
         match self {
             DescriptorBinding::AccelerationStructure(_) => BindingType::AccelerationStructure,
-            DescriptorBinding::Image(_) => BindingType::Image,
+            DescriptorBinding::Cubemap(_)
+            | DescriptorBinding::Image(_)
+            | DescriptorBinding::ImageArray(_) => BindingType::Image,
             DescriptorBinding::InputAttachment(_) => BindingType::InputAttachment,
             DescriptorBinding::StorageBuffer(_) => BindingType::StorageBuffer,
             DescriptorBinding::StorageImage(_) => BindingType::StorageImage,
@@ -44,10 +25,12 @@ impl DescriptorBinding {
         }
     }
 
-    fn name(&self) -> &str {
+    pub fn name(&self) -> &str {
         match self {
             DescriptorBinding::AccelerationStructure(as_) => &as_.name,
+            DescriptorBinding::Cubemap(cubemap) => &cubemap.name,
             DescriptorBinding::Image(image) => &image.name,
+            DescriptorBinding::ImageArray(array) => &array.name,
             DescriptorBinding::InputAttachment(input) => &input.name,
             DescriptorBinding::StorageBuffer(storage) => &storage.name,
             DescriptorBinding::StorageImage(image) => &image.name,
@@ -55,10 +38,12 @@ impl DescriptorBinding {
         }
     }
 
-    fn stage(&self) -> &str {
+    pub fn stage(&self) -> &str {
         match self {
             DescriptorBinding::AccelerationStructure(as_) => &as_.stage,
+            DescriptorBinding::Cubemap(cubemap) => &cubemap.stage,
             DescriptorBinding::Image(image) => &image.stage,
+            DescriptorBinding::ImageArray(array) => &array.stage,
             DescriptorBinding::InputAttachment(input) => &input.stage,
             DescriptorBinding::StorageBuffer(storage) => &storage.stage,
             DescriptorBinding::StorageImage(image) => &image.stage,
@@ -69,9 +54,11 @@ impl DescriptorBinding {
     fn value_type(&self) -> Cow<'static, str> {
         match self {
             DescriptorBinding::AccelerationStructure(_) => "&Option<RaytraceResources>".into(),
-            DescriptorBinding::Image(_)
+            DescriptorBinding::Cubemap(_)
+            | DescriptorBinding::Image(_)
             | DescriptorBinding::InputAttachment(_)
             | DescriptorBinding::StorageImage(_) => "vk::ImageView".into(),
+            DescriptorBinding::ImageArray(_) => unreachable!(),
             DescriptorBinding::StorageBuffer(storage) => {
                 let typ = &storage.typ;
                 format!("&StorageBuffer<{typ}>").into()
@@ -82,6 +69,30 @@ impl DescriptorBinding {
             }
         }
     }
+
+    fn count(&self) -> usize {
+        match self {
+            DescriptorBinding::ImageArray(array) => array.count,
+            _ => 1,
+        }
+    }
+
+    // Arrays aren't written when allocating the descriptor set, as their slots are only filled in
+    // later as images get registered.
+    fn written_on_alloc(&self) -> bool {
+        !matches!(self, DescriptorBinding::ImageArray(_))
+    }
+
+    // The flags end up in a static initializer, where the | operator of ash flags can't be called,
+    // so they're combined through their raw values instead.
+    fn flags(&self) -> &'static str {
+        match self {
+            DescriptorBinding::ImageArray(_) => {
+                "from_raw(vk::DescriptorBindingFlags::PARTIALLY_BOUND.as_raw() | vk::DescriptorBindingFlags::UPDATE_UNUSED_WHILE_PENDING.as_raw())"
+            }
+            _ => "empty()",
+        }
+    }
 }
 
 impl Display for Compute {
@@ -90,6 +101,12 @@ impl Display for Compute {
     }
 }
 
+impl Display for DescriptorSet {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        self.name.fmt(f)
+    }
+}
+
 impl Display for Pass {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         self.name.fmt(f)
@@ -108,7 +125,9 @@ impl Display for Sampler {
     }
 }
 
-pub fn generate_code(in_path: &str, renderer: &Renderer, mut file: File) {
+// Returns the code rather than writing it, so that tests can compare it without touching OUT_DIR.
+pub fn generate_code(in_path: &str, renderer: &Renderer) -> String {
+    let mut file = Vec::new();
     // clippy::deref_addrof has false positives for *&raw const expressions.
     write!(
         file,
@@ -116,30 +135,12 @@ pub fn generate_code(in_path: &str, renderer: &Renderer, mut file: File) {
 
 #![allow(unused, clippy::deref_addrof)]
 
-use crate::renderer::shader::compile_glsl;
+use crate::renderer::shader::{{check_pipeline, compile_glsl, compile_glsl_or_exit, log_shader_error}};
 #[rustfmt::skip]
 use crate::renderer::uniform::{{"#
     )
     .unwrap();
-    let mut uniform_types = BTreeSet::new();
-    for binding in &renderer.descriptor_set.bindings {
-        if let DescriptorBinding::Uniform(uniform) = binding {
-            uniform_types.insert(uniform.typ.as_str());
-        } else if let DescriptorBinding::StorageBuffer(storage) = binding {
-            uniform_types.insert(
-                storage
-                    .typ
-                    .strip_prefix("[")
-                    .unwrap()
-                    .strip_suffix("]")
-                    .unwrap(),
-            );
-        }
-    }
-    for typ in &uniform_types {
-        if *typ == "u8" || *typ == "u32" || typ.starts_with("crate::") {
-            continue;
-        }
+    for typ in renderer.uniform_module_types() {
         write!(file, "{typ},").unwrap();
     }
     writeln!(
@@ -147,7 +148,7 @@ use crate::renderer::uniform::{{"#
         r#"}};
 use crate::renderer::debug::set_label;
 use crate::renderer::util::{{AsDescriptor, Dev, ImageResources, StorageBuffer, UniformBuffer}};
-use crate::renderer::{{DeviceSupport, Pass, Swapchain, COLOR_FORMAT, DEPTH_FORMAT, FRAMES_IN_FLIGHT}};
+use crate::renderer::{{DeviceSupport, Pass, Swapchain, COLOR_FORMAT, DEPTH_FORMAT}};
 use ash::vk;
 use std::ffi::CStr;
 use std::mem::MaybeUninit;
@@ -254,7 +255,11 @@ pub struct Pipelines {{"#
     for_pipelines(renderer, |_, pipeline| {
         if let Some(specs) = &pipeline.fragment_specialization {
             let pipeline_camelcase = to_camelcase(&pipeline.to_string());
-            writeln!(file, "struct {pipeline_camelcase}Specialization {{").unwrap();
+            writeln!(
+                file,
+                "#[repr(C)]\nstruct {pipeline_camelcase}Specialization {{"
+            )
+            .unwrap();
             for spec in specs {
                 let ty = &renderer.find_specialization(spec).ty;
                 writeln!(file, "    {spec}: {ty},").unwrap();
@@ -270,10 +275,15 @@ struct Scratch {{"#
     )
     .unwrap();
     for sampler in &renderer.samplers {
-        writeln!(file, "    {}_sampler: vk::SamplerCreateInfo,", sampler.name).unwrap();
+        writeln!(
+            file,
+            "    {}_sampler: vk::SamplerCreateInfo<'static>,",
+            sampler.name
+        )
+        .unwrap();
     }
     let mut pool_sizes = Vec::new();
-    for binding in &renderer.descriptor_set.bindings {
+    for binding in renderer.descriptor_bindings() {
         let binding_type = binding.descriptor_type();
         let pool_size = match pool_sizes.iter_mut().find(|(ty, _)| *ty == binding_type) {
             Some(pool_size) => pool_size,
@@ -282,18 +292,26 @@ struct Scratch {{"#
                 pool_sizes.last_mut().unwrap()
             }
         };
-        pool_size.1 += 2;
+        pool_size.1 += binding.count();
+    }
+    for set in &renderer.descriptor_sets {
+        let binding_count = set.bindings.len();
+        writeln!(
+            file,
+            r#"    {set}_descriptor_set_bindings: [vk::DescriptorSetLayoutBinding<'static>; {binding_count}],
+    {set}_descriptor_set_binding_flags: [vk::DescriptorBindingFlags; {binding_count}],
+    {set}_descriptor_set_binding_flags_info: vk::DescriptorSetLayoutBindingFlagsCreateInfo<'static>,
+    {set}_descriptor_set_layout: vk::DescriptorSetLayoutCreateInfo<'static>,"#
+        )
+        .unwrap();
     }
-    let binding_count = renderer.descriptor_set.bindings.len();
     let pool_size_count = pool_sizes.len();
     writeln!(
         file,
-        r#"    descriptor_set_bindings: [vk::DescriptorSetLayoutBinding<'static>; {binding_count}],
-    descriptor_set_layout: vk::DescriptorSetLayoutCreateInfo<'static>,
-    descriptor_pool_sizes: [vk::DescriptorPoolSize; {pool_size_count}],
-    descriptor_pool: vk::DescriptorPoolCreateInfo<'static>,
-    assembly: vk::PipelineInputAssemblyStateCreateInfo<'static>,
-    dynamic_state: vk::PipelineDynamicStateCreateInfo<'static>,"#
+        r#"    descriptor_pool_sizes: [vk::DescriptorPoolSize; {pool_size_count}],
+    dynamic_state: vk::PipelineDynamicStateCreateInfo<'static>,
+    dynamic_viewport_states: [vk::DynamicState; 2],
+    dynamic_viewport_state: vk::PipelineDynamicStateCreateInfo<'static>,"#
     )
     .unwrap();
     for (name, typ) in &shaders {
@@ -304,12 +322,15 @@ struct Scratch {{"#
         )
         .unwrap();
     }
+    let push_constant_range_count = push_constant_range(renderer).iter().len();
     writeln!(
         file,
-        "    pipeline_layout: vk::PipelineLayoutCreateInfo<'static>,"
+        "    push_constant_ranges: [vk::PushConstantRange; {push_constant_range_count}],
+    pipeline_layout: vk::PipelineLayoutCreateInfo<'static>,"
     )
     .unwrap();
-    for_pipelines(renderer, |_, pipeline| {
+    for_pipelines(renderer, |pass, pipeline| {
+        let color_attachment_count = color_attachments(pass, pipeline).len();
         let binding_count = pipeline.vertex_bindings.len();
         let attribute_count = pipeline
             .vertex_bindings
@@ -323,7 +344,7 @@ struct Scratch {{"#
             writeln!(
                 file,
                 r#"    {pipeline}_fragment_specialization_entries: [vk::SpecializationMapEntry; {specialization_count}],
-    {pipeline}_fragment_specialization_info: vk::SpecializationInfo,
+    {pipeline}_fragment_specialization_info: vk::SpecializationInfo<'static>,
     {pipeline}_fragment_specialization_scratch: {pipeline_camelcase}Specialization,"#
             )
                 .unwrap();
@@ -342,15 +363,16 @@ struct Scratch {{"#
         }
         writeln!(
             file,
-            r#"    {pipeline}_viewport: vk::Viewport,
+            r#"    {pipeline}_assembly: vk::PipelineInputAssemblyStateCreateInfo<'static>,
+    {pipeline}_viewport: vk::Viewport,
     {pipeline}_scissor: vk::Rect2D,
     {pipeline}_viewport_state: vk::PipelineViewportStateCreateInfo<'static>,
     {pipeline}_rasterizer: vk::PipelineRasterizationStateCreateInfo<'static>,
     {pipeline}_multisampling: vk::PipelineMultisampleStateCreateInfo<'static>,
-    {pipeline}_blend_attachments: [vk::PipelineColorBlendAttachmentState; 1],
+    {pipeline}_blend_attachments: [vk::PipelineColorBlendAttachmentState; {color_attachment_count}],
     {pipeline}_blend: vk::PipelineColorBlendStateCreateInfo<'static>,
     {pipeline}_depth: vk::PipelineDepthStencilStateCreateInfo<'static>,
-    {pipeline}_color_formats: [vk::Format; 1],
+    {pipeline}_color_formats: [vk::Format; {color_attachment_count}],
     {pipeline}_rendering: vk::PipelineRenderingCreateInfo<'static>,"#,
         )
         .unwrap();
@@ -362,10 +384,21 @@ struct Scratch {{"#
         )
         .unwrap();
     });
+    // Declared apart from the pipelines, as all compute pipelines are created in a single call and
+    // their create infos have to be contiguous.
     for compute in &renderer.computes {
         writeln!(
             file,
-            r#"    {compute}_pipeline: vk::ComputePipelineCreateInfo,"#
+            r#"    {compute}_local_size_entries: [vk::SpecializationMapEntry; 3],
+    {compute}_local_size_info: vk::SpecializationInfo<'static>,
+    {compute}_local_size: [u32; 3],"#
+        )
+        .unwrap();
+    }
+    for compute in &renderer.computes {
+        writeln!(
+            file,
+            r#"    {compute}_pipeline: vk::ComputePipelineCreateInfo<'static>,"#
         )
         .unwrap();
     }
@@ -385,6 +418,11 @@ static mut SCRATCH: Scratch = Scratch {{"#
         } else {
             0
         };
+        let (compare_enable, compare_op) = match &sampler.compare_op {
+            Some(compare_op) => (1, compare_op.as_str()),
+            None => (0, "NEVER"),
+        };
+        let border_color = &sampler.border_color;
         writeln!(
             file,
             r"    {sampler}_sampler: vk::SamplerCreateInfo {{
@@ -400,49 +438,65 @@ static mut SCRATCH: Scratch = Scratch {{"#
         mip_lod_bias: 0.,
         anisotropy_enable: 0,
         max_anisotropy: 0.,
-        compare_enable: 0,
-        compare_op: vk::CompareOp::NEVER,
+        compare_enable: {compare_enable},
+        compare_op: vk::CompareOp::{compare_op},
         min_lod: 0.,
         max_lod: 0.,
-        border_color: vk::BorderColor::FLOAT_TRANSPARENT_BLACK,
+        border_color: vk::BorderColor::{border_color},
         unnormalized_coordinates: {unnormalized_coordinates},
+        _marker: std::marker::PhantomData,
     }},"
         )
         .unwrap();
     }
 
-    writeln!(file, "    descriptor_set_bindings: [").unwrap();
-    for (binding_index, binding) in renderer.descriptor_set.bindings.iter().enumerate() {
-        let typ = binding.descriptor_type().name();
-        let stage = binding.stage();
-        writeln!(
-            file,
-            r#"        vk::DescriptorSetLayoutBinding {{
+    for set in &renderer.descriptor_sets {
+        writeln!(file, "    {set}_descriptor_set_bindings: [").unwrap();
+        for (binding_index, binding) in set.bindings.iter().enumerate() {
+            let typ = binding.descriptor_type().name();
+            let stage_flags = stage_flags(binding.stage());
+            let count = binding.count();
+            writeln!(
+                file,
+                r#"        vk::DescriptorSetLayoutBinding {{
             binding: {binding_index},
             descriptor_type: vk::DescriptorType::{typ},
-            descriptor_count: 1,
-            stage_flags: vk::ShaderStageFlags::{stage},
+            descriptor_count: {count},
+            stage_flags: {stage_flags},
             p_immutable_samplers: std::ptr::null(),
             _marker: std::marker::PhantomData,
         }},"#,
-        )
-        .unwrap();
-    }
-    let binding_count = renderer.descriptor_set.bindings.len();
-    writeln!(
-        file,
-        r"    ],
-    descriptor_set_layout: vk::DescriptorSetLayoutCreateInfo {{
-        s_type: vk::StructureType::DESCRIPTOR_SET_LAYOUT_CREATE_INFO,
+            )
+            .unwrap();
+        }
+        writeln!(file, "    ],\n    {set}_descriptor_set_binding_flags: [").unwrap();
+        for binding in &set.bindings {
+            let flags = binding.flags();
+            writeln!(file, "        vk::DescriptorBindingFlags::{flags},").unwrap();
+        }
+        let binding_count = set.bindings.len();
+        writeln!(
+            file,
+            r"    ],
+    {set}_descriptor_set_binding_flags_info: vk::DescriptorSetLayoutBindingFlagsCreateInfo {{
+        s_type: vk::StructureType::DESCRIPTOR_SET_LAYOUT_BINDING_FLAGS_CREATE_INFO,
         p_next: std::ptr::null(),
-        flags: vk::DescriptorSetLayoutCreateFlags::empty(),
         binding_count: {binding_count},
-        p_bindings: unsafe {{ &raw const (SCRATCH.descriptor_set_bindings[0]) }},
+        p_binding_flags: unsafe {{ &raw const SCRATCH.{set}_descriptor_set_binding_flags[0] }},
         _marker: std::marker::PhantomData,
     }},
-    descriptor_pool_sizes: [",
-    )
-    .unwrap();
+    {set}_descriptor_set_layout: vk::DescriptorSetLayoutCreateInfo {{
+        s_type: vk::StructureType::DESCRIPTOR_SET_LAYOUT_CREATE_INFO,
+        p_next: unsafe {{ &raw const SCRATCH.{set}_descriptor_set_binding_flags_info as *const _ }},
+        flags: vk::DescriptorSetLayoutCreateFlags::empty(),
+        binding_count: {binding_count},
+        p_bindings: unsafe {{ &raw const (SCRATCH.{set}_descriptor_set_bindings[0]) }},
+        _marker: std::marker::PhantomData,
+    }},",
+        )
+        .unwrap();
+    }
+    writeln!(file, "    descriptor_pool_sizes: [").unwrap();
     for (binding_type, size) in &pool_sizes {
         let binding_type_name = binding_type.name();
         writeln!(
@@ -454,36 +508,28 @@ static mut SCRATCH: Scratch = Scratch {{"#
         )
         .unwrap();
     }
-    let max_sets = 2;
-    let pool_size_count = pool_sizes.len();
+    let set_count = renderer.descriptor_sets.len();
+    let push_constant_range = push_constant_range(renderer);
+    let push_constant_range_count = push_constant_range.iter().len();
+    let push_constant_ranges = match &push_constant_range {
+        Some((stage, size)) => format!(
+            "vk::PushConstantRange {{ stage_flags: {}, offset: 0, size: {size} }}",
+            stage_flags(stage),
+        ),
+        None => String::new(),
+    };
     writeln!(
         file,
         r#"    ],
-    descriptor_pool: vk::DescriptorPoolCreateInfo {{
-        s_type: vk::StructureType::DESCRIPTOR_POOL_CREATE_INFO,
-        p_next: std::ptr::null(),
-        flags: vk::DescriptorPoolCreateFlags::empty(),
-        max_sets: {max_sets},
-        pool_size_count: {pool_size_count},
-        p_pool_sizes: unsafe {{ &raw const SCRATCH.descriptor_pool_sizes[0] }},
-        _marker: std::marker::PhantomData,
-    }},
+    push_constant_ranges: [{push_constant_ranges}],
     pipeline_layout: vk::PipelineLayoutCreateInfo {{
         s_type: vk::StructureType::PIPELINE_LAYOUT_CREATE_INFO,
         p_next: std::ptr::null(),
         flags: vk::PipelineLayoutCreateFlags::empty(),
-        set_layout_count: 1,
+        set_layout_count: {set_count},
         p_set_layouts: std::ptr::null(),
-        push_constant_range_count: 0,
-        p_push_constant_ranges: std::ptr::null(),
-        _marker: std::marker::PhantomData,
-    }},
-    assembly: vk::PipelineInputAssemblyStateCreateInfo {{
-        s_type: vk::StructureType::PIPELINE_INPUT_ASSEMBLY_STATE_CREATE_INFO,
-        p_next: std::ptr::null(),
-        flags: vk::PipelineInputAssemblyStateCreateFlags::empty(),
-        topology: vk::PrimitiveTopology::TRIANGLE_LIST,
-        primitive_restart_enable: 0,
+        push_constant_range_count: {push_constant_range_count},
+        p_push_constant_ranges: unsafe {{ (&raw const SCRATCH.push_constant_ranges).cast() }},
         _marker: std::marker::PhantomData,
     }},
     dynamic_state: vk::PipelineDynamicStateCreateInfo {{
@@ -493,6 +539,15 @@ static mut SCRATCH: Scratch = Scratch {{"#
         dynamic_state_count: 0,
         p_dynamic_states: std::ptr::null(),
         _marker: std::marker::PhantomData,
+    }},
+    dynamic_viewport_states: [vk::DynamicState::VIEWPORT, vk::DynamicState::SCISSOR],
+    dynamic_viewport_state: vk::PipelineDynamicStateCreateInfo {{
+        s_type: vk::StructureType::PIPELINE_DYNAMIC_STATE_CREATE_INFO,
+        p_next: std::ptr::null(),
+        flags: vk::PipelineDynamicStateCreateFlags::empty(),
+        dynamic_state_count: 2,
+        p_dynamic_states: unsafe {{ &raw const SCRATCH.dynamic_viewport_states[0] }},
+        _marker: std::marker::PhantomData,
     }},"#
     )
     .unwrap();
@@ -546,6 +601,7 @@ static mut SCRATCH: Scratch = Scratch {{"#
         p_map_entries: unsafe {{ &raw const SCRATCH.{pipeline}_fragment_specialization_entries[0] }},
         data_size: {offset},
         p_data: unsafe {{ (&raw const SCRATCH.{pipeline}_fragment_specialization_scratch) as *const std::ffi::c_void }},
+        _marker: std::marker::PhantomData,
     }},
     {pipeline}_fragment_specialization_scratch: {pipeline_camelcase}Specialization {{"#
             )
@@ -610,7 +666,11 @@ static mut SCRATCH: Scratch = Scratch {{"#
         } else {
             writeln!(file, r#"    {pipeline}_vertex_bindings: ["#).unwrap();
             for (binding_index, binding) in pipeline.vertex_bindings.iter().enumerate() {
-                let raw_stride: usize = binding.attributes.iter().map(attribute_size).sum();
+                let raw_stride: usize = binding
+                    .attributes
+                    .iter()
+                    .map(|attribute| attribute_size(pipeline, attribute))
+                    .sum();
                 let stride = raw_stride.next_multiple_of(4);
                 let rate = &binding.rate;
                 writeln!(
@@ -647,7 +707,7 @@ static mut SCRATCH: Scratch = Scratch {{"#
                         .unwrap();
                         total_locations += 1;
                     }
-                    offset += attribute_size(attribute);
+                    offset += attribute_size(pipeline, attribute);
                 }
             }
             let vertex_binding_count = pipeline.vertex_bindings.len();
@@ -677,12 +737,21 @@ static mut SCRATCH: Scratch = Scratch {{"#
             )
             .unwrap();
         }
+        let topology = &pipeline.topology;
         let polygon_mode = &pipeline.polygon_mode;
         let cull_mode = &pipeline.cull_mode;
         let rasterization_samples = if pass.msaa { "empty()" } else { "TYPE_1" };
         writeln!(
             file,
-            r#"    {pipeline}_viewport: vk::Viewport {{
+            r#"    {pipeline}_assembly: vk::PipelineInputAssemblyStateCreateInfo {{
+        s_type: vk::StructureType::PIPELINE_INPUT_ASSEMBLY_STATE_CREATE_INFO,
+        p_next: std::ptr::null(),
+        flags: vk::PipelineInputAssemblyStateCreateFlags::empty(),
+        topology: vk::PrimitiveTopology::{topology},
+        primitive_restart_enable: 0,
+        _marker: std::marker::PhantomData,
+    }},
+    {pipeline}_viewport: vk::Viewport {{
         x: 0.,
         y: 0.,
         width: 0.,
@@ -735,24 +804,53 @@ static mut SCRATCH: Scratch = Scratch {{"#
     {pipeline}_blend_attachments: ["#
         )
         .unwrap();
-        for _ in [()] {
+        let color_attachments = color_attachments(pass, pipeline);
+        for (blend, _) in &color_attachments {
+            let (blend_enable, src_blend_factor, dst_blend_factor, dst_alpha_blend_factor) =
+                blend.factors();
             writeln!(
                 file,
                 r#"        vk::PipelineColorBlendAttachmentState {{
-            blend_enable: 0,
-            src_color_blend_factor: vk::BlendFactor::ZERO,
-            dst_color_blend_factor: vk::BlendFactor::ZERO,
+            blend_enable: {blend_enable},
+            src_color_blend_factor: vk::BlendFactor::{src_blend_factor},
+            dst_color_blend_factor: vk::BlendFactor::{dst_blend_factor},
             color_blend_op: vk::BlendOp::ADD,
             src_alpha_blend_factor: vk::BlendFactor::ZERO,
-            dst_alpha_blend_factor: vk::BlendFactor::ZERO,
+            dst_alpha_blend_factor: vk::BlendFactor::{dst_alpha_blend_factor},
             alpha_blend_op: vk::BlendOp::ADD,
             color_write_mask: vk::ColorComponentFlags::RGBA,
         }},"#
             )
             .unwrap();
         }
-        let depth_bool = if true { 1 } else { 0 };
-        let color_attachment_count = 1;
+        // Passes without a depth attachment used to just ignore the depth state of their
+        // pipelines, which made it easy to misread what a pipeline does. Now it has to be spelled
+        // out instead.
+        if pass.color_only && (pipeline.depth_test || pipeline.depth_write) {
+            panic!(
+                "pipeline in a color-only pass has to disable depth-test and depth-write, pipeline: {}, pass: {}",
+                pipeline.name, pass.name
+            );
+        }
+        let depth_bool = pipeline.depth_test as u32;
+        // Vulkan only writes depth when the test is enabled, so keep the generated state from
+        // claiming otherwise.
+        let depth_write_bool = (pipeline.depth_test && pipeline.depth_write) as u32;
+        let depth_compare = &pipeline.depth_compare;
+        // Vulkan requires the pipeline formats to match the attachments of the dynamic rendering
+        // pass, so passes without a depth attachment need the format left undefined.
+        let depth_format = if pass.color_only {
+            "vk::Format::UNDEFINED"
+        } else {
+            "DEPTH_FORMAT"
+        };
+        // Vulkan requires the blend state to match the attachments exactly.
+        let color_attachment_count = color_attachments.len();
+        let dynamic_state = if pass.dynamic_viewport {
+            "dynamic_viewport_state"
+        } else {
+            "dynamic_state"
+        };
         let vertex_input_state = if pipeline.mesh_shaders {
             "std::ptr::null()".to_owned()
         } else {
@@ -769,7 +867,7 @@ static mut SCRATCH: Scratch = Scratch {{"#
         logic_op_enable: 0,
         logic_op: vk::LogicOp::CLEAR,
         attachment_count: {color_attachment_count},
-        p_attachments: unsafe {{ &raw const SCRATCH.{pipeline}_blend_attachments[0] }},
+        p_attachments: unsafe {{ (&raw const SCRATCH.{pipeline}_blend_attachments).cast() }},
         blend_constants: [0., 0., 0., 0.],
         _marker: std::marker::PhantomData,
     }},
@@ -778,8 +876,8 @@ static mut SCRATCH: Scratch = Scratch {{"#
         p_next: std::ptr::null(),
         flags: vk::PipelineDepthStencilStateCreateFlags::empty(),
         depth_test_enable: {depth_bool},
-        depth_write_enable: {depth_bool},
-        depth_compare_op: vk::CompareOp::LESS_OR_EQUAL,
+        depth_write_enable: {depth_write_bool},
+        depth_compare_op: vk::CompareOp::{depth_compare},
         depth_bounds_test_enable: 0,
         stencil_test_enable: 0,
         front: vk::StencilOpState {{
@@ -804,14 +902,14 @@ static mut SCRATCH: Scratch = Scratch {{"#
         max_depth_bounds: 1.,
         _marker: std::marker::PhantomData,
     }},
-    {pipeline}_color_formats: [vk::Format::UNDEFINED],
+    {pipeline}_color_formats: [vk::Format::UNDEFINED; {color_attachment_count}],
     {pipeline}_rendering: vk::PipelineRenderingCreateInfo {{
         s_type: vk::StructureType::PIPELINE_RENDERING_CREATE_INFO,
         p_next: std::ptr::null(),
         view_mask: 0,
-        color_attachment_count: 1,
-        p_color_attachment_formats: unsafe {{ &raw const SCRATCH.{pipeline}_color_formats[0] }},
-        depth_attachment_format: DEPTH_FORMAT,
+        color_attachment_count: {color_attachment_count},
+        p_color_attachment_formats: unsafe {{ (&raw const SCRATCH.{pipeline}_color_formats).cast() }},
+        depth_attachment_format: {depth_format},
         stencil_attachment_format: vk::Format::UNDEFINED,
         _marker: std::marker::PhantomData,
     }},
@@ -822,14 +920,14 @@ static mut SCRATCH: Scratch = Scratch {{"#
         stage_count: {shader_stage_count},
         p_stages: unsafe {{ &raw const SCRATCH.{pipeline}_shader_stages[0] }},
         p_vertex_input_state: {vertex_input_state},
-        p_input_assembly_state: unsafe {{ &raw const SCRATCH.assembly }},
+        p_input_assembly_state: unsafe {{ &raw const SCRATCH.{pipeline}_assembly }},
         p_tessellation_state: std::ptr::null(),
         p_viewport_state: unsafe {{ &raw const SCRATCH.{pipeline}_viewport_state }},
         p_rasterization_state: unsafe {{ &raw const SCRATCH.{pipeline}_rasterizer }},
         p_multisample_state: unsafe {{ &raw const SCRATCH.{pipeline}_multisampling }},
         p_depth_stencil_state: unsafe {{ &raw const SCRATCH.{pipeline}_depth }},
         p_color_blend_state: unsafe {{ &raw const SCRATCH.{pipeline}_blend }},
-        p_dynamic_state: unsafe {{ &raw const SCRATCH.dynamic_state }},
+        p_dynamic_state: unsafe {{ &raw const SCRATCH.{dynamic_state} }},
         layout: vk::PipelineLayout::null(),
         render_pass: vk::RenderPass::null(),
         subpass: 0,
@@ -840,6 +938,26 @@ static mut SCRATCH: Scratch = Scratch {{"#
         )
         .unwrap();
     });
+    for compute in &renderer.computes {
+        let [x, y, z] = compute.local_size();
+        writeln!(
+            file,
+            r#"    {compute}_local_size_entries: [
+        vk::SpecializationMapEntry {{ constant_id: 0, offset: 0, size: 4 }},
+        vk::SpecializationMapEntry {{ constant_id: 1, offset: 4, size: 4 }},
+        vk::SpecializationMapEntry {{ constant_id: 2, offset: 8, size: 4 }},
+    ],
+    {compute}_local_size_info: vk::SpecializationInfo {{
+        map_entry_count: 3,
+        p_map_entries: unsafe {{ &raw const SCRATCH.{compute}_local_size_entries[0] }},
+        data_size: 12,
+        p_data: unsafe {{ (&raw const SCRATCH.{compute}_local_size) as *const std::ffi::c_void }},
+        _marker: std::marker::PhantomData,
+    }},
+    {compute}_local_size: [{x}, {y}, {z}],"#
+        )
+        .unwrap();
+    }
     for compute in &renderer.computes {
         writeln!(
             file,
@@ -854,11 +972,13 @@ static mut SCRATCH: Scratch = Scratch {{"#
             stage: vk::ShaderStageFlags::COMPUTE,
             module: vk::ShaderModule::null(),
             p_name: c"main".as_ptr(),
-            p_specialization_info: std::ptr::null(),
+            p_specialization_info: unsafe {{ &raw const SCRATCH.{compute}_local_size_info }},
+            _marker: std::marker::PhantomData,
         }},
         layout: vk::PipelineLayout::null(),
         base_pipeline_handle: vk::Pipeline::null(),
         base_pipeline_index: 0,
+        _marker: std::marker::PhantomData,
     }},"#
         )
         .unwrap();
@@ -882,267 +1002,155 @@ impl Samplers {{
     writeln!(
         file,
         r#"    }}
-}}
-
-#[rustfmt::skip]
-pub fn alloc_descriptor_set("#
+}}"#
     )
     .unwrap();
-    for binding in &renderer.descriptor_set.bindings {
-        let name = binding.name();
-        let typ = binding.value_type();
-        writeln!(file, "    {name}: {typ},").unwrap();
+    for (set_index, set) in renderer.descriptor_sets.iter().enumerate() {
+        generate_descriptor_set(set_index, set, &mut file);
     }
-    write!(
+    writeln!(
         file,
-        r#"    dev: &Dev,
-    layout: vk::DescriptorSetLayout,
-    pool: vk::DescriptorPool,
-) -> [vk::DescriptorSet; FRAMES_IN_FLIGHT] {{
-    let layouts = [layout; FRAMES_IN_FLIGHT];
-    let descriptor_set_alloc_info = vk::DescriptorSetAllocateInfo::default()
-        .descriptor_pool(pool)
-        .set_layouts(&layouts);
-    let descriptors: [vk::DescriptorSet; FRAMES_IN_FLIGHT] =
-        unsafe {{ dev.allocate_descriptor_sets(&descriptor_set_alloc_info) }}
-            .unwrap()
-            .try_into()
-            .unwrap();
-    update_descriptor_set(&descriptors"#
+        r#"
+impl ShaderModules {{
+    pub fn cleanup(&self, dev: &Dev) {{"#
     )
     .unwrap();
-    for binding in &renderer.descriptor_set.bindings {
-        let name = binding.name();
-        write!(file, ", {name}").unwrap();
+    for (name, typ) in &shaders {
+        let typ_lowercase = typ.lowercase();
+        writeln!(
+            file,
+            r#"        unsafe {{ dev.destroy_shader_module(self.{name}_{typ_lowercase}, None) }};"#
+        )
+        .unwrap();
     }
-
     writeln!(
         file,
-        r#", dev);
-    descriptors
+        r#"    }}
 }}
 
-#[allow(clippy::unused_enumerate_index)]
-pub fn update_descriptor_set(
-    descriptors: &[vk::DescriptorSet; FRAMES_IN_FLIGHT],"#
+impl Pipelines {{
+    pub fn cleanup(&self, dev: &Dev) {{"#
     )
     .unwrap();
-    let mut only_tlas = None;
-    for (binding_index, binding) in renderer.descriptor_set.bindings.iter().enumerate() {
-        let name = binding.name();
-        let typ = binding.value_type();
-        writeln!(file, "        {name}: {typ},").unwrap();
-        if binding.descriptor_type() == BindingType::AccelerationStructure {
-            assert!(only_tlas.is_none());
-            assert_eq!(binding_index, renderer.descriptor_set.bindings.len() - 1);
-            only_tlas = Some(name);
-        }
+    for_pipelines(renderer, |_, pipeline| {
+        writeln!(
+            file,
+            "        unsafe {{ dev.destroy_pipeline(self.{pipeline}, None) }};"
+        )
+        .unwrap();
+    });
+    for compute in &renderer.computes {
+        writeln!(
+            file,
+            "        unsafe {{ dev.destroy_pipeline(self.{compute}, None) }};"
+        )
+        .unwrap();
     }
     writeln!(
         file,
-        r#"    dev: &Dev,
-    ) {{"#
+        r#"    }}
+}}
+
+#[rustfmt::skip]
+pub fn create_samplers(dev: &Dev) -> Samplers {{"#
     )
     .unwrap();
-    if let Some(tlas) = only_tlas.as_ref() {
-        writeln!(file, r#"    let supports_raytracing = {tlas}.is_some();"#).unwrap();
+    for sampler in &renderer.samplers {
+        writeln!(file, "    let {} = unsafe {{ dev.create_sampler(&*&raw const SCRATCH.{}_sampler, None).unwrap_unchecked() }};", sampler.name, sampler.name).unwrap();
+    }
+    writeln!(file, "    Samplers {{").unwrap();
+    for sampler in &renderer.samplers {
+        writeln!(file, "        {},", sampler.name).unwrap();
     }
     writeln!(
         file,
-        r#"    for (_flight_index, descriptor) in descriptors.iter().enumerate() {{"#
+        r#"    }}
+}}
+
+pub const DESCRIPTOR_SET_COUNT: usize = {set_count};
+
+#[rustfmt::skip]
+pub fn create_descriptor_set_layouts(_samplers: &Samplers, dev: &Dev) -> [vk::DescriptorSetLayout; DESCRIPTOR_SET_COUNT] {{"#
     )
     .unwrap();
-    for (binding_index, binding) in renderer.descriptor_set.bindings.iter().enumerate() {
-        let binding_name = binding.name();
-        let binding_type = binding.descriptor_type().name();
-        let write_mutable = match binding {
-            DescriptorBinding::AccelerationStructure(_) => "mut ",
-            _ => "",
-        };
-        match binding {
-            DescriptorBinding::AccelerationStructure(_) => writeln!(
-                file,
-                r#"        let mut {binding_name}_acceleration_structure = *vk::WriteDescriptorSetAccelerationStructureKHR::default()
-            .acceleration_structures({binding_name}.as_ref().map(|as_| std::slice::from_ref(&as_.acceleration_structure)).unwrap_or_default());"#
-            )
-                .unwrap(),
-            DescriptorBinding::Image(image) => {
-                let layout = &image.layout;
+    for set in &renderer.descriptor_sets {
+        for (binding_index, binding) in set.bindings.iter().enumerate() {
+            if let DescriptorBinding::Cubemap(image) | DescriptorBinding::Image(image) = binding {
                 writeln!(
                     file,
-                    r#"        let {binding_name}_image = *vk::DescriptorImageInfo::default()
-            .image_layout(vk::ImageLayout::{layout})
-            .image_view({binding_name});"#
+                    "    unsafe {{ SCRATCH.{set}_descriptor_set_bindings[{binding_index}].p_immutable_samplers = &_samplers.{} }};",
+                    image.sampler,
                 )
-                    .unwrap()
+                    .unwrap();
             }
-            DescriptorBinding::InputAttachment(_) => writeln!(
-                file,
-                r#"        let {binding_name}_image = *vk::DescriptorImageInfo::default()
-            .image_layout(vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL)
-            .image_view({binding_name});"#
-            )
-                .unwrap(),
-            DescriptorBinding::StorageBuffer(_) => writeln!(file, r#"        let {binding_name}_buffer = {binding_name}.descriptor(_flight_index);"#).unwrap(),
-            DescriptorBinding::StorageImage(_) => writeln!(file,
-                                                           r#"        let {binding_name}_image = *vk::DescriptorImageInfo::default()
-            .image_layout(vk::ImageLayout::GENERAL)
-            .image_view({binding_name});"#
-            ).unwrap(),
-            DescriptorBinding::Uniform(_) => writeln!(
+        }
+    }
+    write!(file, "    [").unwrap();
+    for set in &renderer.descriptor_sets {
+        write!(file, "\n        unsafe {{ dev.create_descriptor_set_layout(&*&raw const SCRATCH.{set}_descriptor_set_layout, None).unwrap_unchecked() }},").unwrap();
+    }
+    writeln!(
+        file,
+        r#"
+    ]
+}}
+
+// Pool sizes are generated for a single frame, and every set is allocated once per frame in flight.
+#[rustfmt::skip]
+pub fn create_descriptor_pool(frames_in_flight: usize, dev: &Dev) -> vk::DescriptorPool {{
+    let mut pool_sizes = unsafe {{ SCRATCH.descriptor_pool_sizes }};
+    for pool_size in &mut pool_sizes {{
+        pool_size.descriptor_count *= frames_in_flight as u32;
+    }}
+    let create_info = vk::DescriptorPoolCreateInfo::default()
+        .max_sets(DESCRIPTOR_SET_COUNT as u32 * frames_in_flight as u32)
+        .pool_sizes(&pool_sizes);
+    unsafe {{ dev.create_descriptor_pool(&create_info, None).unwrap_unchecked() }}
+}}
+
+#[rustfmt::skip]
+pub fn create_pipeline_layout(descriptor_set_layouts: &[vk::DescriptorSetLayout; DESCRIPTOR_SET_COUNT], dev: &Dev) -> vk::PipelineLayout {{
+    unsafe {{ SCRATCH.pipeline_layout.p_set_layouts = descriptor_set_layouts.as_ptr() }};
+    unsafe {{ dev.create_pipeline_layout(&*&raw const SCRATCH.pipeline_layout, None).unwrap_unchecked() }}
+}}"#
+    )
+    .unwrap();
+    // Devices are required to support at least 128 bytes of push constants, but anything larger has
+    // to be checked during device selection, so the size is exported even if nothing is pushed.
+    match &renderer.push_constant {
+        Some(push_constant) => {
+            let typ = &push_constant.typ;
+            let stage_flags = stage_flags(&push_constant.stage);
+            writeln!(
                 file,
-                r#"        let {binding_name}_buffer = {binding_name}.descriptor(_flight_index);"#
+                r#"
+pub const PUSH_CONSTANT_SIZE: Option<u32> = Some(std::mem::size_of::<{typ}>() as u32);
+
+#[rustfmt::skip]
+pub fn cmd_push_constants(buf: vk::CommandBuffer, layout: vk::PipelineLayout, constants: &{typ}, dev: &Dev) {{
+    let bytes = unsafe {{ std::slice::from_raw_parts((constants as *const {typ}).cast::<u8>(), std::mem::size_of::<{typ}>()) }};
+    unsafe {{ dev.cmd_push_constants(buf, layout, {stage_flags}, 0, bytes) }};
+}}"#
             )
-                .unwrap(),
+            .unwrap();
         }
-        writeln!(
-            file,
-            r#"        let {write_mutable}{binding_name} = vk::WriteDescriptorSet::default()
-            .dst_set(*descriptor)
-            .dst_binding({binding_index})
-            .descriptor_type(vk::DescriptorType::{binding_type})"#
-        )
-        .unwrap();
-        match binding {
-            DescriptorBinding::AccelerationStructure(_) => writeln!(
-                file,
-                r#"            .push_next(&mut {binding_name}_acceleration_structure);
-        {binding_name}.descriptor_count = 1;"#
-            )
-            .unwrap(),
-            DescriptorBinding::Image(_)
-            | DescriptorBinding::InputAttachment(_)
-            | DescriptorBinding::StorageImage(_) => writeln!(
-                file,
-                r#"            .image_info(std::slice::from_ref(&{binding_name}_image));"#
-            )
-            .unwrap(),
-            DescriptorBinding::StorageBuffer(_) => writeln!(
-                file,
-                r#"            .buffer_info(std::slice::from_ref(&{binding_name}_buffer));"#
-            )
-            .unwrap(),
-            DescriptorBinding::Uniform(_) => writeln!(
-                file,
-                r#"            .buffer_info(std::slice::from_ref(&{binding_name}_buffer));"#
-            )
-            .unwrap(),
-        }
-    }
-    let write_writes = |file: &mut File, bindings: &[DescriptorBinding]| {
-        write!(file, r"[").unwrap();
-        for (binding_index, binding) in bindings.iter().enumerate() {
-            let binding_name = binding.name();
-            write!(file, "{binding_name}").unwrap();
-            if binding_index != bindings.len() - 1 {
-                write!(file, ", ").unwrap();
-            }
-        }
-        write!(file, "]").unwrap();
-    };
-    write!(file, r#"        let writes = "#).unwrap();
-    write_writes(&mut file, &renderer.descriptor_set.bindings);
-    writeln!(file, r#";"#).unwrap();
-    if only_tlas.is_some() {
-        let count_without_raytracing = renderer.descriptor_set.bindings.len() - 1;
-        writeln!(
-            file,
-            r#"        let writes = if supports_raytracing {{
-            &writes
-        }} else {{
-            &writes[..{count_without_raytracing}]
-        }};"#
-        )
-        .unwrap();
-    } else {
-        writeln!(file, "        let writes = &writes;").unwrap();
-    }
-    writeln!(
-        file,
-        r#"        unsafe {{ dev.update_descriptor_sets(writes, &[]) }};
-    }}
-}}
-
-impl ShaderModules {{
-    pub fn cleanup(&self, dev: &Dev) {{"#
-    )
-    .unwrap();
-    for (name, typ) in &shaders {
-        let typ_lowercase = typ.lowercase();
-        writeln!(
-            file,
-            r#"        unsafe {{ dev.destroy_shader_module(self.{name}_{typ_lowercase}, None) }};"#
-        )
-        .unwrap();
-    }
-    writeln!(
-        file,
-        r#"    }}
-}}
-
-impl Pipelines {{
-    pub fn cleanup(&self, dev: &Dev) {{"#
-    )
-    .unwrap();
-    for_pipelines(renderer, |_, pipeline| {
-        writeln!(
-            file,
-            "        unsafe {{ dev.destroy_pipeline(self.{pipeline}, None) }};"
-        )
-        .unwrap();
-    });
-    for compute in &renderer.computes {
-        writeln!(
-            file,
-            "        unsafe {{ dev.destroy_pipeline(self.{compute}, None) }};"
-        )
-        .unwrap();
-    }
-    writeln!(
-        file,
-        r#"    }}
-}}
-
-#[rustfmt::skip]
-pub fn create_samplers(dev: &Dev) -> Samplers {{"#
-    )
-    .unwrap();
-    for sampler in &renderer.samplers {
-        writeln!(file, "    let {} = unsafe {{ dev.create_sampler(&*&raw const SCRATCH.{}_sampler, None).unwrap_unchecked() }};", sampler.name, sampler.name).unwrap();
-    }
-    writeln!(file, "    Samplers {{").unwrap();
-    for sampler in &renderer.samplers {
-        writeln!(file, "        {},", sampler.name).unwrap();
-    }
-    writeln!(
-        file,
-        r#"    }}
-}}
-
-#[rustfmt::skip]
-pub fn create_descriptor_set_layout(_samplers: &Samplers, dev: &Dev) -> vk::DescriptorSetLayout {{"#
-    )
-    .unwrap();
-    for (binding_index, binding) in renderer.descriptor_set.bindings.iter().enumerate() {
-        if let DescriptorBinding::Image(image) = binding {
-            writeln!(
-                file,
-                "    unsafe {{ SCRATCH.descriptor_set_bindings[{binding_index}].p_immutable_samplers = &_samplers.{} }};",
-                image.sampler,
-            )
+        None => match renderer.pipeline_push_constant_range() {
+            Some((stages, size)) => {
+                writeln!(
+                    file,
+                    "\npub const PUSH_CONSTANT_SIZE: Option<u32> = Some({size});"
+                )
                 .unwrap();
-        }
+                generate_push_constants(renderer, &stages, &mut file);
+            }
+            None => writeln!(file, "\npub const PUSH_CONSTANT_SIZE: Option<u32> = None;").unwrap(),
+        },
     }
-    writeln!(file, "    unsafe {{ dev.create_descriptor_set_layout(&*&raw const SCRATCH.descriptor_set_layout, None).unwrap_unchecked() }}").unwrap();
+    generate_dispatches(renderer, &mut file);
+    generate_layout_checks(renderer, &mut file);
     writeln!(
         file,
-        r#"}}
-
-#[rustfmt::skip]
-pub fn create_descriptor_pool(layout: vk::DescriptorSetLayout, dev: &Dev) -> vk::DescriptorPool {{
-    unsafe {{ dev.create_descriptor_pool(&*&raw const SCRATCH.descriptor_pool, None).unwrap_unchecked() }}
-}}
-
+        r#"
 #[allow(unused_mut)]
 #[allow(clippy::identity_op)]
 #[rustfmt::skip]
@@ -1171,10 +1179,19 @@ pub fn create_render_passes(
     for pass in &renderer.passes {
         writeln!(file, "        {pass},").unwrap();
     }
+    writeln!(file, "    }}\n}}").unwrap();
+    // Shaders without precompiled SPIR-V are read from disk when the renderer is created, so the
+    // app checks they exist before creating anything.
+    writeln!(file, "\npub const RUNTIME_SHADERS: &[&str] = &[").unwrap();
+    for (name, typ) in &shaders {
+        let ext = typ.extension();
+        if !std::fs::exists(format!("shaders/{name}.{ext}.spv")).unwrap() {
+            writeln!(file, "    \"shaders/{name}.{ext}\",").unwrap();
+        }
+    }
     writeln!(
         file,
-        r#"    }}
-}}
+        r#"];
 
 #[rustfmt::skip]
 pub fn create_shaders(device_support: &DeviceSupport) -> Shaders {{"#
@@ -1185,7 +1202,7 @@ pub fn create_shaders(device_support: &DeviceSupport) -> Shaders {{"#
         let typ_camelcase = typ.camelcase();
         let ext = typ.extension();
         let spirv = if !std::fs::exists(format!("shaders/{name}.{ext}.spv")).unwrap() {
-            format!("compile_glsl(\"shaders/{name}.{ext}\", shaderc::ShaderKind::{typ_camelcase})")
+            format!("compile_glsl_or_exit(\"shaders/{name}.{ext}\", shaderc::ShaderKind::{typ_camelcase})")
         } else {
             format!("ash::util::read_spv(&mut std::io::Cursor::new(include_bytes!(\"../../shaders/{name}.{ext}.spv\"))).unwrap()")
         };
@@ -1205,6 +1222,40 @@ pub fn create_shaders(device_support: &DeviceSupport) -> Shaders {{"#
         r#"    }}
 }}
 
+// Replaces the SPIR-V of a single runtime shader, returning whether it compiled. Precompiled shaders
+// are embedded in the binary, so there's nothing to reload for them.
+#[rustfmt::skip]
+pub fn recompile_shader(shaders: &mut Shaders, path: &str, device_support: &DeviceSupport) -> bool {{
+    match path {{"#
+    )
+    .unwrap();
+    for (name, typ) in &shaders {
+        let typ_lowercase = typ.lowercase();
+        let typ_camelcase = typ.camelcase();
+        let ext = typ.extension();
+        if std::fs::exists(format!("shaders/{name}.{ext}.spv")).unwrap() {
+            continue;
+        }
+        let guard = if typ.requires_mesh_shaders() {
+            " if device_support.mesh_shaders"
+        } else {
+            ""
+        };
+        writeln!(file, r#"        "shaders/{name}.{ext}"{guard} => match compile_glsl("shaders/{name}.{ext}", shaderc::ShaderKind::{typ_camelcase}) {{
+            Ok(spirv) => shaders.{name}_{typ_lowercase} = spirv,
+            Err(error) => {{
+                log_shader_error(&error);
+                return false;
+            }}
+        }},"#).unwrap();
+    }
+    writeln!(
+        file,
+        r#"        _ => (),
+    }}
+    true
+}}
+
 #[rustfmt::skip]
 pub fn create_shader_modules(shaders: &Shaders, dev: &Dev) -> ShaderModules {{"#
     )
@@ -1272,8 +1323,10 @@ pub fn create_pipelines(
     writeln!(
         file,
         r#"    swapchain: &Swapchain,
+    extent: vk::Extent2D,
     shader_modules: &ShaderModules,
     layout: vk::PipelineLayout,
+    cache: vk::PipelineCache,
     dev: &Dev,
 ) -> Pipelines {{"#
     )
@@ -1317,20 +1370,26 @@ pub fn create_pipelines(
         writeln!(
             file,
             r#"    unsafe {{ SCRATCH.{pipeline}_shader_stages[{fragment_stage_index}].module = shader_modules.{fragment_shader}_fragment }};
-    unsafe {{ SCRATCH.{pipeline}_viewport.width = swapchain.extent.width as f32 }};
-    unsafe {{ SCRATCH.{pipeline}_viewport.height = swapchain.extent.height as f32 }};
-    unsafe {{ SCRATCH.{pipeline}_scissor.extent.width = swapchain.extent.width }};
-    unsafe {{ SCRATCH.{pipeline}_scissor.extent.height = swapchain.extent.height }};"#
+    unsafe {{ SCRATCH.{pipeline}_viewport.width = extent.width as f32 }};
+    unsafe {{ SCRATCH.{pipeline}_viewport.height = extent.height as f32 }};
+    unsafe {{ SCRATCH.{pipeline}_scissor.extent.width = extent.width }};
+    unsafe {{ SCRATCH.{pipeline}_scissor.extent.height = extent.height }};"#
         )
             .unwrap();
         if pass.msaa {
             writeln!(file, "    unsafe {{ SCRATCH.{pipeline}_multisampling.rasterization_samples = _msaa_samples }};").unwrap();
         }
-        writeln!(
-            file,
-            r#"    unsafe {{ SCRATCH.{pipeline}_color_formats[0] = swapchain.format.format }};"#
-        )
-        .unwrap();
+        for (index, (_, format)) in color_attachments(pass, pipeline).iter().enumerate() {
+            let color_format = match format {
+                Some(format) => format!("vk::Format::{format}"),
+                None => "swapchain.format.format".to_owned(),
+            };
+            writeln!(
+                file,
+                r#"    unsafe {{ SCRATCH.{pipeline}_color_formats[{index}] = {color_format} }};"#
+            )
+            .unwrap();
+        }
     });
     for_pipelines(renderer, |_, pipeline| {
         writeln!(
@@ -1374,14 +1433,15 @@ pub fn create_pipelines(
         };
         writeln!(
             file,
-            r#"    let _ = unsafe {{ (dev.fp_v1_0().create_graphics_pipelines)(
+            r#"    let result = unsafe {{ (dev.fp_v1_0().create_graphics_pipelines)(
 {tab}        dev.handle(),
-{tab}        vk::PipelineCache::null(),
+{tab}        cache,
 {tab}        1,
 {tab}        &*&raw const SCRATCH.{pipeline}_pipeline,
 {tab}        std::ptr::null(),
 {tab}        &mut pipelines.{pipeline},
-{tab}    ) }};"#
+{tab}    ) }};
+{tab}    check_pipeline(result, "{pipeline}");"#
         )
         .unwrap();
         if pipeline.mesh_shaders {
@@ -1393,16 +1453,24 @@ pub fn create_pipelines(
         let first_compute_pipeline = &renderer.computes[0].name;
         writeln!(
             file,
-            r#"    let _ = unsafe {{ (dev.fp_v1_0().create_compute_pipelines)(
+            r#"    let result = unsafe {{ (dev.fp_v1_0().create_compute_pipelines)(
         dev.handle(),
-        vk::PipelineCache::null(),
+        cache,
         {compute_pipeline_count},
         &*&raw const SCRATCH.{first_compute_pipeline}_pipeline,
         std::ptr::null(),
-        (pipelines.as_mut_ptr() as *mut vk::Pipeline).offset({pipeline_count}),
+        (&raw mut pipelines as *mut vk::Pipeline).offset({pipeline_count}),
     ) }};"#
         )
         .unwrap();
+        // Compute pipelines are created in a single call, and the ones that failed are left null.
+        for compute in &renderer.computes {
+            writeln!(
+                file,
+                r#"    if pipelines.{compute} == vk::Pipeline::null() {{ check_pipeline(result, "{compute}") }}"#
+            )
+            .unwrap();
+        }
     }
     writeln!(
         file,
@@ -1410,6 +1478,584 @@ pub fn create_pipelines(
 }}"#
     )
     .unwrap();
+    String::from_utf8(file).unwrap()
+}
+
+// Workgroup sizes are also limited by the device, so they are exported for device selection the same
+// as the push constant size.
+// Each set gets its own functions for allocating and updating it, as the resources bound in
+// different sets change at different rates.
+fn generate_descriptor_set(set_index: usize, set: &DescriptorSet, file: &mut Vec<u8>) {
+    let set_uppercase = set.name.to_uppercase();
+    writeln!(
+        file,
+        r#"
+pub const {set_uppercase}_DESCRIPTOR_SET: u32 = {set_index};
+
+#[rustfmt::skip]
+pub fn alloc_{set}_descriptor_set("#
+    )
+    .unwrap();
+    for binding in &set.bindings {
+        if !binding.written_on_alloc() {
+            continue;
+        }
+        let name = binding.name();
+        let typ = binding.value_type();
+        writeln!(file, "    {name}: {typ},").unwrap();
+    }
+    write!(
+        file,
+        r#"    frames_in_flight: usize,
+    dev: &Dev,
+    layout: vk::DescriptorSetLayout,
+    pool: vk::DescriptorPool,
+) -> Vec<vk::DescriptorSet> {{
+    let layouts = vec![layout; frames_in_flight];
+    let descriptor_set_alloc_info = vk::DescriptorSetAllocateInfo::default()
+        .descriptor_pool(pool)
+        .set_layouts(&layouts);
+    let descriptors = unsafe {{ dev.allocate_descriptor_sets(&descriptor_set_alloc_info) }}.unwrap();
+    update_{set}_descriptor_set(&descriptors"#
+    )
+    .unwrap();
+    for binding in &set.bindings {
+        if !binding.written_on_alloc() {
+            continue;
+        }
+        let name = binding.name();
+        write!(file, ", {name}").unwrap();
+    }
+
+    writeln!(
+        file,
+        r#", dev);
+    descriptors
+}}
+
+#[allow(clippy::unused_enumerate_index)]
+pub fn update_{set}_descriptor_set(
+    descriptors: &[vk::DescriptorSet],"#
+    )
+    .unwrap();
+    let mut only_tlas = None;
+    for (binding_index, binding) in set.bindings.iter().enumerate() {
+        if !binding.written_on_alloc() {
+            continue;
+        }
+        let name = binding.name();
+        let typ = binding.value_type();
+        writeln!(file, "        {name}: {typ},").unwrap();
+        if binding.descriptor_type() == BindingType::AccelerationStructure {
+            assert!(only_tlas.is_none());
+            assert_eq!(binding_index, set.bindings.len() - 1);
+            only_tlas = Some(name);
+        }
+    }
+    writeln!(
+        file,
+        r#"    dev: &Dev,
+    ) {{"#
+    )
+    .unwrap();
+    if let Some(tlas) = only_tlas.as_ref() {
+        writeln!(file, r#"    let supports_raytracing = {tlas}.is_some();"#).unwrap();
+    }
+    writeln!(
+        file,
+        r#"    for (_flight_index, descriptor) in descriptors.iter().enumerate() {{"#
+    )
+    .unwrap();
+    for (binding_index, binding) in set.bindings.iter().enumerate() {
+        if !binding.written_on_alloc() {
+            continue;
+        }
+        let binding_name = binding.name();
+        let binding_type = binding.descriptor_type().name();
+        let write_mutable = match binding {
+            DescriptorBinding::AccelerationStructure(_) => "mut ",
+            _ => "",
+        };
+        match binding {
+            DescriptorBinding::AccelerationStructure(_) => writeln!(
+                file,
+                r#"        let mut {binding_name}_acceleration_structure = *vk::WriteDescriptorSetAccelerationStructureKHR::default()
+            .acceleration_structures({binding_name}.as_ref().map(|as_| std::slice::from_ref(&as_.acceleration_structure)).unwrap_or_default());"#
+            )
+                .unwrap(),
+            DescriptorBinding::Cubemap(image) | DescriptorBinding::Image(image) => {
+                let layout = &image.layout;
+                writeln!(
+                    file,
+                    r#"        let {binding_name}_image = vk::DescriptorImageInfo::default()
+            .image_layout(vk::ImageLayout::{layout})
+            .image_view({binding_name});"#
+                )
+                    .unwrap()
+            }
+            DescriptorBinding::ImageArray(_) => unreachable!(),
+            DescriptorBinding::InputAttachment(_) => writeln!(
+                file,
+                r#"        let {binding_name}_image = vk::DescriptorImageInfo::default()
+            .image_layout(vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL)
+            .image_view({binding_name});"#
+            )
+                .unwrap(),
+            DescriptorBinding::StorageBuffer(_) => writeln!(file, r#"        let {binding_name}_buffer = {binding_name}.descriptor(_flight_index);"#).unwrap(),
+            DescriptorBinding::StorageImage(_) => writeln!(file,
+                                                           r#"        let {binding_name}_image = vk::DescriptorImageInfo::default()
+            .image_layout(vk::ImageLayout::GENERAL)
+            .image_view({binding_name});"#
+            ).unwrap(),
+            DescriptorBinding::Uniform(_) => writeln!(
+                file,
+                r#"        let {binding_name}_buffer = {binding_name}.descriptor(_flight_index);"#
+            )
+                .unwrap(),
+        }
+        writeln!(
+            file,
+            r#"        let {write_mutable}{binding_name} = vk::WriteDescriptorSet::default()
+            .dst_set(*descriptor)
+            .dst_binding({binding_index})
+            .descriptor_type(vk::DescriptorType::{binding_type})"#
+        )
+        .unwrap();
+        match binding {
+            DescriptorBinding::AccelerationStructure(_) => writeln!(
+                file,
+                r#"            .push_next(&mut {binding_name}_acceleration_structure);
+        {binding_name}.descriptor_count = 1;"#
+            )
+            .unwrap(),
+            DescriptorBinding::Cubemap(_)
+            | DescriptorBinding::Image(_)
+            | DescriptorBinding::InputAttachment(_)
+            | DescriptorBinding::StorageImage(_) => writeln!(
+                file,
+                r#"            .image_info(std::slice::from_ref(&{binding_name}_image));"#
+            )
+            .unwrap(),
+            DescriptorBinding::ImageArray(_) => unreachable!(),
+            DescriptorBinding::StorageBuffer(_) => writeln!(
+                file,
+                r#"            .buffer_info(std::slice::from_ref(&{binding_name}_buffer));"#
+            )
+            .unwrap(),
+            DescriptorBinding::Uniform(_) => writeln!(
+                file,
+                r#"            .buffer_info(std::slice::from_ref(&{binding_name}_buffer));"#
+            )
+            .unwrap(),
+        }
+    }
+    let written_bindings: Vec<_> = set
+        .bindings
+        .iter()
+        .filter(|binding| binding.written_on_alloc())
+        .collect();
+    let write_writes = |file: &mut Vec<u8>, bindings: &[&DescriptorBinding]| {
+        write!(file, r"[").unwrap();
+        for (binding_index, binding) in bindings.iter().enumerate() {
+            let binding_name = binding.name();
+            write!(file, "{binding_name}").unwrap();
+            if binding_index != bindings.len() - 1 {
+                write!(file, ", ").unwrap();
+            }
+        }
+        write!(file, "]").unwrap();
+    };
+    write!(file, r#"        let writes = "#).unwrap();
+    write_writes(file, &written_bindings);
+    writeln!(file, r#";"#).unwrap();
+    if only_tlas.is_some() {
+        let count_without_raytracing = written_bindings.len() - 1;
+        writeln!(
+            file,
+            r#"        let writes = if supports_raytracing {{
+            &writes
+        }} else {{
+            &writes[..{count_without_raytracing}]
+        }};"#
+        )
+        .unwrap();
+    } else {
+        writeln!(file, "        let writes = &writes;").unwrap();
+    }
+    writeln!(
+        file,
+        r#"        unsafe {{ dev.update_descriptor_sets(writes, &[]) }};
+    }}
+}}"#
+    )
+    .unwrap();
+    // Images can be recreated independently of everything else, for example when their resolution
+    // changes, so they get separate functions for updating just their own descriptors.
+    for (binding_index, binding) in set.bindings.iter().enumerate() {
+        if let DescriptorBinding::ImageArray(array) = binding {
+            let binding_name = &array.name;
+            let binding_name_uppercase = binding_name.to_uppercase();
+            let sampler = &array.sampler;
+            let count = array.count;
+            writeln!(
+                file,
+                r#"
+pub const {binding_name_uppercase}_CAPACITY: u32 = {count};
+
+pub fn update_{binding_name}_descriptor(
+    descriptors: &[vk::DescriptorSet],
+    index: u32,
+    {binding_name}: vk::ImageView,
+    samplers: &Samplers,
+    dev: &Dev,
+) {{
+    let {binding_name}_image = vk::DescriptorImageInfo::default()
+        .image_layout(vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL)
+        .image_view({binding_name})
+        .sampler(samplers.{sampler});
+    for descriptor in descriptors {{
+        let write = vk::WriteDescriptorSet::default()
+            .dst_set(*descriptor)
+            .dst_binding({binding_index})
+            .dst_array_element(index)
+            .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+            .image_info(std::slice::from_ref(&{binding_name}_image));
+        unsafe {{ dev.update_descriptor_sets(&[write], &[]) }};
+    }}
+}}"#
+            )
+            .unwrap();
+            continue;
+        }
+        if let DescriptorBinding::StorageImage(image) = binding {
+            let binding_name = &image.name;
+            writeln!(
+                file,
+                r#"
+pub fn update_{binding_name}_descriptor(
+    descriptors: &[vk::DescriptorSet],
+    {binding_name}: vk::ImageView,
+    dev: &Dev,
+) {{
+    let {binding_name}_image = vk::DescriptorImageInfo::default()
+        .image_layout(vk::ImageLayout::GENERAL)
+        .image_view({binding_name});
+    for descriptor in descriptors {{
+        let write = vk::WriteDescriptorSet::default()
+            .dst_set(*descriptor)
+            .dst_binding({binding_index})
+            .descriptor_type(vk::DescriptorType::STORAGE_IMAGE)
+            .image_info(std::slice::from_ref(&{binding_name}_image));
+        unsafe {{ dev.update_descriptor_sets(&[write], &[]) }};
+    }}
+}}"#
+            )
+            .unwrap();
+            continue;
+        }
+        let (DescriptorBinding::Cubemap(image) | DescriptorBinding::Image(image)) = binding else {
+            continue;
+        };
+        let binding_name = &image.name;
+        let layout = &image.layout;
+        writeln!(
+            file,
+            r#"
+pub fn update_{binding_name}_descriptor(
+    descriptors: &[vk::DescriptorSet],
+    {binding_name}: vk::ImageView,
+    dev: &Dev,
+) {{
+    let {binding_name}_image = vk::DescriptorImageInfo::default()
+        .image_layout(vk::ImageLayout::{layout})
+        .image_view({binding_name});
+    for descriptor in descriptors {{
+        let write = vk::WriteDescriptorSet::default()
+            .dst_set(*descriptor)
+            .dst_binding({binding_index})
+            .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+            .image_info(std::slice::from_ref(&{binding_name}_image));
+        unsafe {{ dev.update_descriptor_sets(&[write], &[]) }};
+    }}
+}}"#
+        )
+        .unwrap();
+    }
+}
+
+fn generate_dispatches(renderer: &Renderer, file: &mut Vec<u8>) {
+    writeln!(
+        file,
+        "\npub const COMPUTE_LOCAL_SIZES: &[(&str, [u32; 3])] = &["
+    )
+    .unwrap();
+    for compute in &renderer.computes {
+        let [x, y, z] = compute.local_size();
+        writeln!(file, r#"    ("{compute}", [{x}, {y}, {z}]),"#).unwrap();
+    }
+    writeln!(file, "];").unwrap();
+    for compute in &renderer.computes {
+        let [x, y, z] = compute.local_size();
+        writeln!(
+            file,
+            r#"
+#[rustfmt::skip]
+pub fn dispatch_{compute}(buf: vk::CommandBuffer, invocations: [u32; 3], dev: &Dev) {{
+    unsafe {{ dev.cmd_dispatch(buf, invocations[0].div_ceil({x}), invocations[1].div_ceil({y}), invocations[2].div_ceil({z})) }};"#
+        )
+        .unwrap();
+        match (&compute.next_stages, &compute.next_access) {
+            (Some(stages), Some(access)) => {
+                let stages = flags("PipelineStageFlags2", stages);
+                let access = flags("AccessFlags2", access);
+                writeln!(
+                    file,
+                    r#"    let barrier = vk::MemoryBarrier2::default()
+        .src_stage_mask(vk::PipelineStageFlags2::COMPUTE_SHADER)
+        .src_access_mask(vk::AccessFlags2::SHADER_STORAGE_WRITE)
+        .dst_stage_mask({stages})
+        .dst_access_mask({access});
+    let dependency_info = vk::DependencyInfo::default().memory_barriers(std::array::from_ref(&barrier));
+    unsafe {{ dev.cmd_pipeline_barrier2(buf, &dependency_info) }};"#
+                )
+                .unwrap();
+            }
+            (None, None) => (),
+            _ => panic!(
+                "next-stages and next-access have to be declared together, compute: {compute}"
+            ),
+        }
+        writeln!(file, "}}").unwrap();
+    }
+}
+
+// Pushes have to use the stages of the whole shared range rather than the ones of the pipeline, as
+// every byte of a push must be visible to all stages of the ranges overlapping it. Fields are laid
+// out with explicit padding, so that the typed structs can be pushed as plain bytes.
+fn generate_push_constants(renderer: &Renderer, stages: &str, file: &mut Vec<u8>) {
+    let stage_flags = stage_flags(stages);
+    for_pipelines(renderer, |_, pipeline| {
+        let Some(push_constants) = &pipeline.push_constants else {
+            return;
+        };
+        let name = &pipeline.name;
+        let layout = push_constants_layout(name, push_constants);
+        let size = layout.size;
+        writeln!(
+            file,
+            r#"
+#[rustfmt::skip]
+pub fn push_{name}_constants(buf: vk::CommandBuffer, layout: vk::PipelineLayout, data: &[u8; {size}], dev: &Dev) {{
+    unsafe {{ dev.cmd_push_constants(buf, layout, {stage_flags}, 0, data) }};
+}}"#
+        )
+        .unwrap();
+        if layout.members.is_empty() {
+            return;
+        }
+        let typ = format!("{}PushConstants", to_camelcase(name));
+        writeln!(
+            file,
+            "\n#[repr(C)]\n#[derive(Clone, Copy, Debug, Default)]\npub struct {typ} {{"
+        )
+        .unwrap();
+        let mut offset = 0;
+        let mut pad_count = 0;
+        let mut write_padding = |file: &mut Vec<u8>, offset: usize, next_offset: usize| {
+            if next_offset > offset {
+                let pad = next_offset - offset;
+                writeln!(file, "    pub _pad{pad_count}: [u8; {pad}],").unwrap();
+                pad_count += 1;
+            }
+        };
+        for (field, member) in push_constants.fields.iter().zip(&layout.members) {
+            write_padding(file, offset, member.offset);
+            let rust_type = push_constant_rust_type(name, &field.typ);
+            writeln!(file, "    pub {}: {rust_type},", field.name).unwrap();
+            offset = member.offset + member.size;
+        }
+        write_padding(file, offset, size);
+        writeln!(
+            file,
+            r#"}}
+
+const _: [(); {size}] = [(); std::mem::size_of::<{typ}>()];
+
+#[rustfmt::skip]
+pub fn push_{name}_constants_typed(buf: vk::CommandBuffer, layout: vk::PipelineLayout, constants: &{typ}, dev: &Dev) {{
+    let data = unsafe {{ &*(constants as *const {typ}).cast::<[u8; {size}]>() }};
+    push_{name}_constants(buf, layout, data, dev);
+}}"#
+        )
+        .unwrap();
+    });
+}
+
+// Matrices are stored as arrays of their columns, which are padded the same way as vectors.
+fn push_constant_rust_type(pipeline: &str, typ: &str) -> String {
+    if let Some(columns) = typ.strip_prefix("mat") {
+        let columns: usize = columns.parse().unwrap();
+        return format!("[[f32; {}]; {columns}]", columns.next_power_of_two());
+    }
+    for (prefix, scalar) in [
+        ("vec", "f32"),
+        ("ivec", "i32"),
+        ("uvec", "u32"),
+        ("bvec", "u32"),
+    ] {
+        if let Some(components) = typ.strip_prefix(prefix) {
+            return format!("[{scalar}; {components}]");
+        }
+    }
+    match typ {
+        "float" => "f32",
+        "int" => "i32",
+        "uint" | "bool" => "u32",
+        _ => panic!("unsupported push constant field type, pipeline: {pipeline}, type: {typ}"),
+    }
+    .to_owned()
+}
+
+// Stages and size of the single push constant range in the shared pipeline layout, with the size
+// as a Rust expression, as the size of the renderer-wide type is only known to the compiler.
+fn push_constant_range(renderer: &Renderer) -> Option<(String, String)> {
+    let pipeline_range = renderer.pipeline_push_constant_range();
+    match (&renderer.push_constant, pipeline_range) {
+        (Some(_), Some(_)) => {
+            panic!("push constants can't be declared both for the renderer and for pipelines")
+        }
+        (Some(push_constant), None) => Some((
+            push_constant.stage.clone(),
+            format!("std::mem::size_of::<{}>() as u32", push_constant.typ),
+        )),
+        (None, Some((stages, size))) => Some((stages, size.to_string())),
+        (None, None) => None,
+    }
+}
+
+// Offsets are compared through array lengths rather than assertions, as the resulting type error
+// shows both the expected and the actual value, and the line it points at names the field.
+fn generate_layout_checks(renderer: &Renderer, file: &mut Vec<u8>) {
+    // Configs without shared structs don't need the shaders directory, which tests don't have.
+    let structs = if renderer.shared_structs.is_empty() {
+        HashMap::new()
+    } else {
+        load_glsl_structs()
+    };
+    let mut binding_types = HashSet::new();
+    for binding in renderer.descriptor_bindings() {
+        if let DescriptorBinding::Uniform(uniform) = binding {
+            binding_types.insert(uniform.typ.as_str());
+        } else if let DescriptorBinding::StorageBuffer(storage) = binding {
+            binding_types.insert(storage.typ.trim_start_matches('[').trim_end_matches(']'));
+        }
+    }
+    writeln!(
+        file,
+        r#"
+#[rustfmt::skip]
+mod layout_checks {{
+use ash::vk;
+use std::mem::MaybeUninit;
+
+const fn field_size<T>(_: *const T) -> usize {{
+    std::mem::size_of::<T>()
+}}"#
+    )
+    .unwrap();
+    let mut queue: VecDeque<_> = renderer
+        .shared_structs
+        .iter()
+        .map(|shared| (shared.name.as_str(), BlockLayout::from_name(&shared.layout)))
+        .collect();
+    let mut checked = HashSet::new();
+    while let Some((name, layout)) = queue.pop_front() {
+        if !checked.insert((name, layout)) {
+            continue;
+        }
+        let (typ, path) = renderer.shared_struct_rust_type(name);
+        let layout_name = match layout {
+            BlockLayout::Std140 => "std140",
+            BlockLayout::Std430 => "std430",
+        };
+        writeln!(file, "\n// {name}, {layout_name}.").unwrap();
+        let reflected = struct_layout(name, layout, &structs);
+        let glsl_struct = &structs[name];
+        for (member, member_layout) in glsl_struct.members.iter().zip(&reflected.members) {
+            let field = &member.name;
+            let offset = member_layout.offset;
+            writeln!(
+                file,
+                "const _: [(); {offset}] = [(); std::mem::offset_of!({path}, {field})];"
+            )
+            .unwrap();
+            // The stride of arrays can only be checked through the size of the whole array.
+            if member.array.is_some() {
+                let size = member_layout.size;
+                writeln!(file, "const _: [(); {size}] = [(); field_size(unsafe {{ &raw const (*MaybeUninit::<{path}>::uninit().as_ptr()).{field} }})];").unwrap();
+            }
+            if is_struct(&member.typ, &structs) {
+                queue.push_back((&member.typ, layout));
+            }
+        }
+        // Nested structs can have less tail padding in Rust, which doesn't matter as the following
+        // fields are checked anyway, but the size of whole buffer elements does.
+        if binding_types.contains(typ) {
+            let size = reflected.size;
+            writeln!(
+                file,
+                "const _: [(); {size}] = [(); std::mem::size_of::<{path}>()];"
+            )
+            .unwrap();
+        }
+    }
+    writeln!(file, "}}").unwrap();
+}
+
+fn flags(typ: &str, names: &[String]) -> String {
+    names
+        .iter()
+        .map(|name| format!("vk::{typ}::{name}"))
+        .collect::<Vec<_>>()
+        .join(" | ")
+}
+
+// Pipelines without an explicit list have a single attachment blended according to the
+// additive-blend and alpha-blend flags, except in depth-only passes like shadow maps, which have no
+// color attachments at all. Formats are None when the swapchain format is used.
+fn color_attachments<'a>(
+    pass: &'a Pass,
+    pipeline: &'a Pipeline,
+) -> Vec<(BlendMode, Option<&'a str>)> {
+    let pass_format = pass.color_format.as_deref();
+    let Some(color_attachments) = &pipeline.color_attachments else {
+        if pass.depth_only {
+            return Vec::new();
+        }
+        let blend = if pipeline.additive_blend {
+            BlendMode::Additive
+        } else if pipeline.alpha_blend {
+            BlendMode::Alpha
+        } else {
+            BlendMode::None
+        };
+        return vec![(blend, pass_format)];
+    };
+    let name = &pipeline.name;
+    if pipeline.additive_blend || pipeline.alpha_blend {
+        panic!("pipeline with color-attachments has to set blend modes per attachment, pipeline: {name}");
+    }
+    if pass.depth_only && !color_attachments.attachments.is_empty() {
+        panic!("pipeline in a depth-only pass can't have color attachments, pipeline: {name}");
+    }
+    color_attachments
+        .attachments
+        .iter()
+        .map(|attachment| {
+            let blend = BlendMode::from_name(&attachment.blend);
+            (blend, attachment.format.as_deref().or(pass_format))
+        })
+        .collect()
 }
 
 fn for_pipelines<'a>(renderer: &'a Renderer, mut f: impl FnMut(&'a Pass, &'a Pipeline)) {
@@ -1420,12 +2066,152 @@ fn for_pipelines<'a>(renderer: &'a Renderer, mut f: impl FnMut(&'a Pass, &'a Pip
     }
 }
 
-fn attribute_size(attribute: &VertexAttribute) -> usize {
-    match attribute.format.as_str() {
-        "R16_UINT" => 2,
-        "R32_SFLOAT" => 4,
-        "R32G32B32_SFLOAT" => 12,
-        "R32G32B32A32_SFLOAT" => 16,
-        _ => todo!("attribute_size({:?})", attribute.format),
+// Combined stages like "VERTEX|FRAGMENT" can't be written with the bitwise or operator, as it isn't
+// const and the create infos are built in a static. Used for both descriptor bindings and push
+// constants.
+fn stage_flags(stage: &str) -> String {
+    if !stage.contains('|') {
+        return format!("vk::ShaderStageFlags::{stage}");
+    }
+    let flags: Vec<_> = stage
+        .split('|')
+        .map(|flag| format!("vk::ShaderStageFlags::{}.as_raw()", flag.trim()))
+        .collect();
+    format!("vk::ShaderStageFlags::from_raw({})", flags.join(" | "))
+}
+
+fn attribute_size(pipeline: &Pipeline, attribute: &VertexAttribute) -> usize {
+    let Some(size) = format_size(&attribute.format) else {
+        panic!(
+            "unsupported vertex attribute format, pipeline: {}, attribute: {}, format: {}",
+            pipeline.name, attribute.name, attribute.format
+        );
+    };
+    size
+}
+
+// Only formats that are commonly supported for vertex input are listed. Packed and compressed
+// formats would need their own handling, and three-component 8-bit and 16-bit formats are left out
+// as many implementations can't read them from vertex buffers.
+fn format_size(format: &str) -> Option<usize> {
+    let (channels, numeric) = format.split_once('_')?;
+    let channel_bits = match channels {
+        "R8" | "R8G8" | "R8G8B8A8" => 8,
+        "R16" | "R16G16" | "R16G16B16A16" => 16,
+        "R32" | "R32G32" | "R32G32B32" | "R32G32B32A32" => 32,
+        _ => return None,
+    };
+    let supported = match channel_bits {
+        8 => matches!(
+            numeric,
+            "UNORM" | "SNORM" | "USCALED" | "SSCALED" | "UINT" | "SINT" | "SRGB"
+        ),
+        16 => matches!(
+            numeric,
+            "UNORM" | "SNORM" | "USCALED" | "SSCALED" | "UINT" | "SINT" | "SFLOAT"
+        ),
+        _ => matches!(numeric, "UINT" | "SINT" | "SFLOAT"),
+    };
+    if !supported {
+        return None;
+    }
+    let channel_count = channels.chars().filter(|c| c.is_ascii_alphabetic()).count();
+    Some(channel_count * channel_bits / 8)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn vertex_format_sizes() {
+        let sizes = [
+            ("R8_UNORM", 1),
+            ("R8_SINT", 1),
+            ("R8G8_SNORM", 2),
+            ("R8G8B8A8_UNORM", 4),
+            ("R8G8B8A8_SRGB", 4),
+            ("R8G8B8A8_USCALED", 4),
+            ("R16_UINT", 2),
+            ("R16G16_SFLOAT", 4),
+            ("R16G16_SSCALED", 4),
+            ("R16G16B16A16_SFLOAT", 8),
+            ("R16G16B16A16_UNORM", 8),
+            ("R32_UINT", 4),
+            ("R32_SFLOAT", 4),
+            ("R32G32_SFLOAT", 8),
+            ("R32G32B32_SFLOAT", 12),
+            ("R32G32B32_SINT", 12),
+            ("R32G32B32A32_SFLOAT", 16),
+            ("R32G32B32A32_UINT", 16),
+        ];
+        for (format, size) in sizes {
+            assert_eq!(format_size(format), Some(size), "{format}");
+        }
+    }
+
+    #[test]
+    fn color_attachment_counts() {
+        let renderer: Renderer = knuffel::parse(
+            "test.kdl",
+            r#"
+            pass "shadow" {
+                debug-name "Shadow pass"
+                debug-color 0 0 0
+                depth-only
+                pipeline "shadow"
+            }
+            pass "bloom" {
+                debug-name "Bloom pass"
+                debug-color 0 0 0
+                pipeline "bloom_composite" {
+                    additive-blend
+                }
+            }
+            pass "gbuffer" {
+                debug-name "G-buffer pass"
+                debug-color 0 0 0
+                color-format "R8G8B8A8_UNORM"
+                pipeline "gbuffer" {
+                    color-attachments {
+                        attachment "albedo"
+                        attachment "normal" format="A2B10G10R10_UNORM_PACK32"
+                        attachment "material" blend="premultiplied"
+                    }
+                }
+            }
+            "#,
+        )
+        .unwrap();
+        let attachments = |pass: usize| {
+            let pass = &renderer.passes[pass];
+            color_attachments(pass, &pass.pipelines[0])
+        };
+        assert!(attachments(0).is_empty());
+        assert_eq!(attachments(1), [(BlendMode::Additive, None)]);
+        assert_eq!(
+            attachments(2),
+            [
+                (BlendMode::None, Some("R8G8B8A8_UNORM")),
+                (BlendMode::None, Some("A2B10G10R10_UNORM_PACK32")),
+                (BlendMode::Premultiplied, Some("R8G8B8A8_UNORM")),
+            ]
+        );
+    }
+
+    #[test]
+    fn unsupported_vertex_formats() {
+        for format in [
+            "R8G8B8_UNORM",
+            "R16G16B16_SFLOAT",
+            "R8_SFLOAT",
+            "R32_UNORM",
+            "R16_SRGB",
+            "A2R10G10B10_UNORM_PACK32",
+            "BC1_RGB_UNORM_BLOCK",
+            "D32_SFLOAT",
+        ] {
+            assert_eq!(format_size(format), None, "{format}");
+        }
     }
 }
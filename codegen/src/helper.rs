@@ -1,6 +1,69 @@
-use crate::config::{Renderer, Specialization};
+use crate::config::{DescriptorBinding, Renderer, Specialization};
+use crate::layout::push_constants_layout;
+use std::collections::BTreeSet;
 
 impl Renderer {
+    // Returns the Rust type as written in renderer.kdl, and the path it can be referred to with.
+    pub fn shared_struct_rust_type<'a>(&'a self, name: &'a str) -> (&'a str, String) {
+        let typ = self
+            .shared_structs
+            .iter()
+            .find(|shared| shared.name == name)
+            .and_then(|shared| shared.rust.as_deref())
+            .unwrap_or(name);
+        let path = if typ.contains("::") {
+            typ.to_owned()
+        } else {
+            format!("crate::renderer::uniform::{typ}")
+        };
+        (typ, path)
+    }
+
+    // Returns the stages and size of the single push constant range covering the push constants of
+    // every pipeline. Each pipeline pushes starting at offset zero, and all pushes have to use the
+    // stages of the whole range, as they overlap.
+    pub fn pipeline_push_constant_range(&self) -> Option<(String, usize)> {
+        let mut stages = BTreeSet::new();
+        let mut size = 0;
+        for pipeline in self.pipelines() {
+            let Some(push_constants) = &pipeline.push_constants else {
+                continue;
+            };
+            stages.extend(push_constants.stages.split('|').map(str::trim));
+            size = size.max(push_constants_layout(&pipeline.name, push_constants).size);
+        }
+        if stages.is_empty() {
+            return None;
+        }
+        let stages: Vec<&str> = stages.into_iter().collect();
+        Some((stages.join("|"), size))
+    }
+
+    // Types of uniforms, storage buffer elements and push constants that are imported from the
+    // uniform module by the generated code, as opposed to primitives or types written with a path.
+    pub fn uniform_module_types(&self) -> BTreeSet<&str> {
+        let mut types = BTreeSet::new();
+        for binding in self.descriptor_bindings() {
+            if let DescriptorBinding::Uniform(uniform) = binding {
+                types.insert(uniform.typ.as_str());
+            } else if let DescriptorBinding::StorageBuffer(storage) = binding {
+                types.insert(
+                    storage
+                        .typ
+                        .strip_prefix("[")
+                        .unwrap()
+                        .strip_suffix("]")
+                        .unwrap(),
+                );
+            }
+        }
+        if let Some(push_constant) = &self.push_constant {
+            types.insert(push_constant.typ.as_str());
+        }
+        types.retain(|typ| *typ != "u8" && *typ != "u32" && !typ.contains("::"));
+        types
+    }
+
     pub fn find_specialization(&self, name: &str) -> &Specialization {
         self.specializations
             .iter()
@@ -37,3 +100,14 @@ pub fn to_camelcase(name: &str) -> String {
     }
     result
 }
+
+pub fn to_snakecase(name: &str) -> String {
+    let mut result = String::new();
+    for (index, c) in name.chars().enumerate() {
+        if c.is_ascii_uppercase() && index > 0 {
+            result.push('_');
+        }
+        result.push(c.to_ascii_lowercase());
+    }
+    result
+}
@@ -0,0 +1,380 @@
+use crate::config::PushConstantsConfig;
+use crate::reflect::strip_comments;
+use std::collections::HashMap;
+use std::path::Path;
+
+const TYPES_DIRECTORY: &str = "shaders/types";
+
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum BlockLayout {
+    Std140,
+    Std430,
+}
+
+#[derive(Debug)]
+pub struct GlslStruct {
+    pub name: String,
+    pub members: Vec<GlslMember>,
+}
+
+#[derive(Debug)]
+pub struct GlslMember {
+    pub typ: String,
+    pub name: String,
+    pub array: Option<usize>,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct StructLayout {
+    pub members: Vec<MemberLayout>,
+    pub size: usize,
+    pub align: usize,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct MemberLayout {
+    pub name: String,
+    pub offset: usize,
+    pub size: usize,
+    pub array_stride: Option<usize>,
+}
+
+impl BlockLayout {
+    pub fn from_name(name: &str) -> BlockLayout {
+        match name {
+            "std140" => BlockLayout::Std140,
+            "std430" => BlockLayout::Std430,
+            _ => panic!("unknown block layout, expected std140 or std430, got: {name}"),
+        }
+    }
+
+    // std140 rounds up the alignment of arrays and structs to that of a vec4, which is the only
+    // difference between the two that matters here.
+    fn aggregate_align(&self, align: usize) -> usize {
+        match self {
+            BlockLayout::Std140 => align.next_multiple_of(16),
+            BlockLayout::Std430 => align,
+        }
+    }
+}
+
+// Structs shared with Rust are all defined in the types directory, either as plain structs or as
+// the bodies of the block macros in uniform.glsl.
+pub fn load_glsl_structs() -> HashMap<String, GlslStruct> {
+    let mut paths: Vec<_> = std::fs::read_dir(TYPES_DIRECTORY)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .collect();
+    paths.sort();
+    let mut structs = HashMap::new();
+    for path in paths {
+        println!("cargo:rerun-if-changed={}", path.display());
+        for glsl_struct in parse_glsl_structs(&std::fs::read_to_string(&path).unwrap(), &path) {
+            structs.insert(glsl_struct.name.clone(), glsl_struct);
+        }
+    }
+    structs
+}
+
+fn parse_glsl_structs(source: &str, path: &Path) -> Vec<GlslStruct> {
+    let source = strip_comments(source).replace("\\\n", " ");
+    let mut structs = Vec::new();
+    let mut rest = source.as_str();
+    while let Some(start) = rest.find('{') {
+        let header: Vec<&str> = rest[..start].split_whitespace().collect();
+        let Some(end) = rest[start..].find('}') else {
+            break;
+        };
+        let body = &rest[start + 1..start + end];
+        rest = &rest[start + end + 1..];
+        let is_struct = header.len() >= 2 && header[header.len() - 2] == "struct";
+        let is_macro = header.len() >= 3 && header[header.len() - 3] == "#define";
+        if !is_struct && !is_macro {
+            continue;
+        }
+        let name = header[header.len() - 1].to_owned();
+        let members = body
+            .split(';')
+            .map(str::trim)
+            .filter(|member| !member.is_empty())
+            .map(|member| parse_member(member, &name, path))
+            .collect();
+        structs.push(GlslStruct { name, members });
+    }
+    structs
+}
+
+fn parse_member(member: &str, struct_name: &str, path: &Path) -> GlslMember {
+    let path = path.display();
+    let Some((typ, declarator)) = member.split_once(char::is_whitespace) else {
+        panic!("unsupported struct member, file: {path}, struct: {struct_name}, member: {member}");
+    };
+    let declarator = declarator.trim();
+    let (name, array) = match declarator.split_once('[') {
+        Some((name, count)) => {
+            let count = count
+                .strip_suffix(']')
+                .and_then(|count| count.trim().parse().ok());
+            let Some(count) = count else {
+                panic!("array members need a literal size, file: {path}, struct: {struct_name}, member: {member}");
+            };
+            (name.trim(), Some(count))
+        }
+        None => (declarator, None),
+    };
+    GlslMember {
+        typ: typ.to_owned(),
+        name: name.to_owned(),
+        array,
+    }
+}
+
+pub fn struct_layout(
+    name: &str,
+    layout: BlockLayout,
+    structs: &HashMap<String, GlslStruct>,
+) -> StructLayout {
+    let Some(glsl_struct) = structs.get(name) else {
+        panic!("struct not found in {TYPES_DIRECTORY}, name: {name}");
+    };
+    let mut members = Vec::new();
+    let mut offset = 0usize;
+    let mut struct_align = 1;
+    for member in &glsl_struct.members {
+        let (mut size, mut align) = type_layout(&member.typ, layout, structs);
+        let mut array_stride = None;
+        if let Some(count) = member.array {
+            align = layout.aggregate_align(align);
+            let stride = size.next_multiple_of(align);
+            size = stride * count;
+            array_stride = Some(stride);
+        }
+        offset = offset.next_multiple_of(align);
+        members.push(MemberLayout {
+            name: member.name.clone(),
+            offset,
+            size,
+            array_stride,
+        });
+        offset += size;
+        struct_align = struct_align.max(align);
+    }
+    let align = layout.aggregate_align(struct_align);
+    StructLayout {
+        members,
+        size: offset.next_multiple_of(align),
+        align,
+    }
+}
+
+// Returns the size and alignment of a type outside of arrays. Matrices are laid out as arrays of
+// their column vectors, as they are column-major by default.
+fn type_layout(
+    typ: &str,
+    layout: BlockLayout,
+    structs: &HashMap<String, GlslStruct>,
+) -> (usize, usize) {
+    if let Some(scalar) = scalar_size(typ) {
+        return (scalar, scalar);
+    }
+    if let Some(columns) = typ.strip_prefix("mat") {
+        let columns: usize = columns.parse().unwrap();
+        let align = layout.aggregate_align(4 * columns.next_power_of_two());
+        return (align * columns, align);
+    }
+    for (prefix, scalar) in [
+        ("vec", 4),
+        ("ivec", 4),
+        ("uvec", 4),
+        ("bvec", 4),
+        ("i16vec", 2),
+        ("u16vec", 2),
+        ("i8vec", 1),
+        ("u8vec", 1),
+    ] {
+        if let Some(components) = typ.strip_prefix(prefix) {
+            let components: usize = components.parse().unwrap();
+            return (scalar * components, scalar * components.next_power_of_two());
+        }
+    }
+    let nested = struct_layout(typ, layout, structs);
+    (nested.size, nested.align)
+}
+
+fn scalar_size(typ: &str) -> Option<usize> {
+    match typ {
+        "float" | "int" | "uint" | "bool" => Some(4),
+        "int16_t" | "uint16_t" => Some(2),
+        "int8_t" | "uint8_t" => Some(1),
+        _ => None,
+    }
+}
+
+pub fn is_struct(typ: &str, structs: &HashMap<String, GlslStruct>) -> bool {
+    structs.contains_key(typ)
+}
+
+// Push constant blocks use std430 by default, so declared fields are laid out the same way as the
+// members of a std430 struct. A declared size has to match the fields, if there are any.
+pub fn push_constants_layout(pipeline: &str, push_constants: &PushConstantsConfig) -> StructLayout {
+    let layout = if push_constants.fields.is_empty() {
+        let Some(size) = push_constants.size else {
+            panic!("push constants need a size or fields, pipeline: {pipeline}");
+        };
+        StructLayout {
+            members: Vec::new(),
+            size,
+            align: 4,
+        }
+    } else {
+        let members = push_constants
+            .fields
+            .iter()
+            .map(|field| GlslMember {
+                typ: field.typ.clone(),
+                name: field.name.clone(),
+                array: None,
+            })
+            .collect();
+        let glsl_struct = GlslStruct {
+            name: pipeline.to_owned(),
+            members,
+        };
+        let structs = HashMap::from([(pipeline.to_owned(), glsl_struct)]);
+        let layout = struct_layout(pipeline, BlockLayout::Std430, &structs);
+        if let Some(size) = push_constants.size {
+            assert_eq!(
+                size, layout.size,
+                "push constant size doesn't match the fields, pipeline: {pipeline}"
+            );
+        }
+        layout
+    };
+    // Required by the Vulkan specification for both the range and every push.
+    assert!(
+        layout.size > 0 && layout.size % 4 == 0,
+        "push constant size must be a nonzero multiple of 4, pipeline: {pipeline}, size: {}",
+        layout.size
+    );
+    layout
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::PushConstantField;
+
+    fn structs(source: &str) -> HashMap<String, GlslStruct> {
+        parse_glsl_structs(source, Path::new("test.glsl"))
+            .into_iter()
+            .map(|glsl_struct| (glsl_struct.name.clone(), glsl_struct))
+            .collect()
+    }
+
+    fn offsets(layout: &StructLayout) -> Vec<(&str, usize)> {
+        let members = layout.members.iter();
+        members
+            .map(|member| (member.name.as_str(), member.offset))
+            .collect()
+    }
+
+    #[test]
+    fn vec3_followed_by_scalar() {
+        let structs = structs("struct Light { vec3 color; float intensity; vec3 position; };");
+        let layout = struct_layout("Light", BlockLayout::Std430, &structs);
+        assert_eq!(
+            offsets(&layout),
+            [("color", 0), ("intensity", 12), ("position", 16)]
+        );
+        assert_eq!((layout.size, layout.align), (32, 16));
+    }
+
+    #[test]
+    fn std140_arrays_and_nested_structs() {
+        let structs = structs(
+            "struct Inner { float value; };
+            struct Outer { float a[3]; Inner inner; float b; mat4 m; uvec2 c; };",
+        );
+        let std140 = struct_layout("Outer", BlockLayout::Std140, &structs);
+        assert_eq!(
+            offsets(&std140),
+            [("a", 0), ("inner", 48), ("b", 64), ("m", 80), ("c", 144)]
+        );
+        assert_eq!(std140.members[0].array_stride, Some(16));
+        assert_eq!(std140.size, 160);
+        let std430 = struct_layout("Outer", BlockLayout::Std430, &structs);
+        assert_eq!(
+            offsets(&std430),
+            [("a", 0), ("inner", 12), ("b", 16), ("m", 32), ("c", 96)]
+        );
+        assert_eq!(std430.members[0].array_stride, Some(4));
+        assert_eq!(std430.size, 112);
+    }
+
+    #[test]
+    fn block_macros() {
+        let structs = structs(
+            "// Comment with a { brace }.
+            #define GLOBAL_UNIFORM_TYPE \\
+                Global { \\
+                    uint count; \\
+                    vec4 planes[6]; \\
+             }
+            const uint CONSTANT = 0;",
+        );
+        let layout = struct_layout("Global", BlockLayout::Std140, &structs);
+        assert_eq!(offsets(&layout), [("count", 0), ("planes", 16)]);
+        assert_eq!(layout.size, 112);
+    }
+
+    fn push_constants(size: Option<usize>, fields: &[(&str, &str)]) -> PushConstantsConfig {
+        let fields = fields.iter().map(|(name, typ)| PushConstantField {
+            name: name.to_string(),
+            typ: typ.to_string(),
+        });
+        PushConstantsConfig {
+            size,
+            stages: "VERTEX".to_owned(),
+            fields: fields.collect(),
+        }
+    }
+
+    #[test]
+    fn push_constant_fields() {
+        let fields = [("position", "vec3"), ("scale", "float"), ("offset", "vec2")];
+        let layout = push_constants_layout("test", &push_constants(Some(32), &fields));
+        assert_eq!(
+            offsets(&layout),
+            [("position", 0), ("scale", 12), ("offset", 16)]
+        );
+        assert_eq!(layout.size, 32);
+        let layout = push_constants_layout("test", &push_constants(Some(64), &[]));
+        assert_eq!((layout.members.len(), layout.size), (0, 64));
+    }
+
+    #[test]
+    #[should_panic(expected = "push constant size doesn't match the fields")]
+    fn push_constant_size_mismatch() {
+        let fields = [("position", "vec3"), ("scale", "float")];
+        push_constants_layout("test", &push_constants(Some(12), &fields));
+    }
+
+    #[test]
+    fn small_integer_vectors() {
+        let structs = structs(
+            "struct Meshlet { uint offset; i16vec3 chunk; int16_t _pad0; u8vec3 base; uint8_t _pad1; };",
+        );
+        let layout = struct_layout("Meshlet", BlockLayout::Std430, &structs);
+        assert_eq!(
+            offsets(&layout),
+            [
+                ("offset", 0),
+                ("chunk", 8),
+                ("_pad0", 14),
+                ("base", 16),
+                ("_pad1", 19)
+            ]
+        );
+        assert_eq!(layout.size, 24);
+    }
+}
@@ -3,27 +3,191 @@
 mod config;
 mod generate;
 mod helper;
+mod layout;
+mod reflect;
 mod shaders;
 mod types;
+mod validate;
 
-use crate::config::Renderer;
+use crate::config::{ConfigError, Renderer};
 use crate::generate::generate_code;
-use crate::shaders::compile_shaders;
-use std::fs::File;
+use crate::shaders::{compile_shaders, shader_dependencies};
+use crate::validate::{validate_config, validate_shaders};
+use knuffel::span::Span;
+use std::path::{Path, PathBuf};
 
 pub fn build_script(in_path: &str, out_path: &str) {
     let text = std::fs::read_to_string(in_path).unwrap();
-    let renderer: Renderer = knuffel::parse(in_path, &text).unwrap();
-    let out_file = File::create(out_path).unwrap();
+    let renderer = match parse_renderer(in_path, &text) {
+        Ok(renderer) => renderer,
+        Err(error) => panic!("{}", describe_error(&error, in_path, &text)),
+    };
     compile_shaders(&renderer);
-    generate_code(in_path, &renderer, out_file);
+    validate_shaders(&renderer);
+    std::fs::write(out_path, generate_code(in_path, &renderer)).unwrap();
     println!("cargo:rerun-if-changed={in_path}");
-    println!("cargo:rerun-if-changed=shaders/main.slang");
-    for shader in renderer.shaders() {
-        println!(
-            "cargo:rerun-if-changed=shaders/{}.{}",
-            shader.0,
-            shader.1.extension()
+    let mut dependencies = shader_dependencies(Path::new("shaders/main.slang"));
+    for (shader_name, shader_type) in renderer.shaders() {
+        let path = PathBuf::from(format!("shaders/{shader_name}.{}", shader_type.extension()));
+        if path.exists() {
+            dependencies.extend(shader_dependencies(&path));
+        }
+    }
+    for dependency in dependencies {
+        println!("cargo:rerun-if-changed={}", dependency.display());
+    }
+}
+
+// Everything that can be checked without shaders, shaderc or OUT_DIR, so that tests can run it on
+// configs of their own.
+fn parse_renderer(path: &str, text: &str) -> Result<Renderer, ConfigError> {
+    let renderer: Renderer = knuffel::parse(path, text).map_err(ConfigError::Syntax)?;
+    validate_config(&renderer)?;
+    Ok(renderer)
+}
+
+fn describe_error(error: &ConfigError, path: &str, text: &str) -> String {
+    let Some(span) = error.span() else {
+        return error.to_string();
+    };
+    let mut message = format!("{}, {path}:{}", error, line_column(text, span));
+    if let ConfigError::DuplicatePipeline { first, .. } = error {
+        message += &format!(", first declared at {path}:{}", line_column(text, *first));
+    }
+    message
+}
+
+fn line_column(text: &str, span: Span) -> String {
+    let before = &text[..span.0];
+    let line = before.matches('\n').count() + 1;
+    let column = before.len() - before.rfind('\n').map_or(0, |newline| newline + 1) + 1;
+    format!("{line}:{column}")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Configs covering the different shapes of generated code, each compared against the output
+    // from when it was last reviewed. Set UPDATE_GOLDEN to overwrite the golden files with the
+    // current output instead, which is also how the golden file for a new fixture is created.
+    const FIXTURES: &[&str] = &[
+        "basic",
+        "mesh_shaders",
+        "compute_only",
+        "no_vertex_bindings",
+    ];
+
+    #[test]
+    fn generated_code_matches_golden() {
+        let directory = Path::new(env!("CARGO_MANIFEST_DIR")).join("testdata");
+        let update = std::env::var_os("UPDATE_GOLDEN").is_some();
+        for fixture in FIXTURES {
+            let in_path = format!("{fixture}.kdl");
+            let text = std::fs::read_to_string(directory.join(&in_path)).unwrap();
+            let renderer = parse_renderer(&in_path, &text).unwrap();
+            let code = generate_code(&in_path, &renderer);
+            // Syntax errors would otherwise only show up when building the main crate.
+            if let Err(error) = syn::parse_file(&code) {
+                panic!("generated code doesn't parse, fixture: {fixture}, error: {error}");
+            }
+            let golden_path = directory.join(format!("{fixture}.golden"));
+            if update {
+                std::fs::write(&golden_path, &code).unwrap();
+                continue;
+            }
+            let Ok(golden) = std::fs::read_to_string(&golden_path) else {
+                panic!(
+                    "golden file is missing, set UPDATE_GOLDEN to create it, fixture: {fixture}"
+                );
+            };
+            assert!(
+                code == golden,
+                "generated code differs from the golden file, fixture: {fixture}"
+            );
+        }
+    }
+
+    fn parse_error(text: &str) -> (ConfigError, String) {
+        let error = parse_renderer("test.kdl", text).unwrap_err();
+        let description = describe_error(&error, "test.kdl", text);
+        (error, description)
+    }
+
+    #[test]
+    fn syntax_error() {
+        let (error, _) = parse_error(r#"pass "render""#);
+        assert!(matches!(error, ConfigError::Syntax(_)));
+    }
+
+    #[test]
+    fn duplicate_pipeline() {
+        let (error, description) = parse_error(
+            r#"pass "shadow" {
+    debug-name "Shadow pass"
+    debug-color 0 0 0
+    pipeline "shadow"
+}
+pass "render" {
+    debug-name "Forward rendering pass"
+    debug-color 0 0 0
+    pipeline "shadow"
+}"#,
         );
+        assert!(matches!(&error, ConfigError::DuplicatePipeline { name, .. } if name == "shadow"));
+        assert!(description.contains("test.kdl:9:"), "{description}");
+        assert!(
+            description.contains("first declared at test.kdl:4:"),
+            "{description}"
+        );
+    }
+
+    #[test]
+    fn unknown_sampler() {
+        let (error, description) = parse_error(
+            r#"sampler "screen" {
+    filter "LINEAR"
+    address-mode "CLAMP_TO_EDGE"
+}
+descriptor-set "global" {
+    image "scene_color" "FRAGMENT" "screen"
+    image-array "history" "FRAGMENT" "linear" count=2
+}"#,
+        );
+        assert!(matches!(
+            &error,
+            ConfigError::UnknownSampler { binding, sampler, .. }
+                if binding == "history" && sampler == "linear"
+        ));
+        assert!(description.contains("test.kdl:7:"), "{description}");
+    }
+
+    #[test]
+    fn undeclared_specialization() {
+        let (error, description) = parse_error(
+            r#"specialization "radius" "f32"
+pass "bloom" {
+    debug-name "Bloom pass"
+    debug-color 0 0 0
+    pipeline "bloom_blur" {
+        fragment-specialization "radius" "axis"
+    }
+}"#,
+        );
+        assert!(matches!(
+            &error,
+            ConfigError::UndeclaredSpecialization { pipeline, specialization, .. }
+                if pipeline == "bloom_blur" && specialization == "axis"
+        ));
+        assert!(description.contains("test.kdl:5:"), "{description}");
+    }
+
+    #[test]
+    fn line_columns() {
+        let text = "a\nbc\n\ndef";
+        assert_eq!(line_column(text, Span(0, 1)), "1:1");
+        assert_eq!(line_column(text, Span(3, 4)), "2:2");
+        assert_eq!(line_column(text, Span(6, 7)), "4:1");
+        assert_eq!(line_column(text, Span(8, 9)), "4:3");
     }
 }
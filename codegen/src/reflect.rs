@@ -0,0 +1,636 @@
+use crate::helper::to_snakecase;
+use crate::shaders::shader_dependencies;
+use crate::types::{BindingType, ShaderType};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+// Resources a single shader expects from the pipeline, used for checking renderer.kdl against the
+// shaders at build time. GLSL shaders are only compiled at runtime, so for these the declarations
+// are read straight from the source, while Slang shaders are reflected from the SPIR-V compiled by
+// the build script.
+#[derive(Debug, Default)]
+pub struct ShaderInterface {
+    pub path: PathBuf,
+    pub bindings: Vec<ReflectedBinding>,
+    pub inputs: Vec<ReflectedInput>,
+    pub push_constants: bool,
+    pub local_size: Option<[LocalSize; 3]>,
+}
+
+// Either written in the shader directly, or taken from a specialization constant with the given ID.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum LocalSize {
+    Literal(u32),
+    Specialized(u32),
+}
+
+#[derive(Clone, Debug)]
+pub struct ReflectedBinding {
+    pub set: usize,
+    pub binding: usize,
+    pub typ: BindingType,
+    pub array: bool,
+    pub cube: bool,
+    // Named the same way as in renderer.kdl, so blocks without an instance name go by their block
+    // name converted to snake case. Missing if the SPIR-V was stripped of debug names.
+    pub name: Option<String>,
+    // Name of the struct a uniform block is declared with, which is also the Rust type.
+    pub uniform_type: Option<String>,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct ReflectedInput {
+    pub location: usize,
+    pub format: InputFormat,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct InputFormat {
+    pub components: usize,
+    pub scalar: ScalarType,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ScalarType {
+    Float,
+    Int,
+    Uint,
+}
+
+impl InputFormat {
+    // Normalized, scaled and sRGB formats are read as floats in shaders, so only the component
+    // count and the numeric suffix of the format matter. Packed and compressed formats can't be
+    // vertex attributes here, and neither can anything else not named like a Vulkan format.
+    pub fn from_vulkan(format: &str) -> Option<InputFormat> {
+        let (channels, numeric) = format.split_once('_')?;
+        let components = channels
+            .chars()
+            .filter(|c| matches!(c, 'R' | 'G' | 'B' | 'A'))
+            .count();
+        let scalar = match numeric {
+            "SFLOAT" | "UNORM" | "SNORM" | "USCALED" | "SSCALED" | "SRGB" => ScalarType::Float,
+            "SINT" => ScalarType::Int,
+            "UINT" => ScalarType::Uint,
+            _ => return None,
+        };
+        let valid_channels = channels
+            .chars()
+            .all(|c| matches!(c, 'R' | 'G' | 'B' | 'A') || c.is_ascii_digit());
+        if components == 0 || !valid_channels {
+            return None;
+        }
+        Some(InputFormat { components, scalar })
+    }
+
+    fn from_glsl(typ: &str) -> Option<InputFormat> {
+        let (scalar, components) = match typ {
+            "float" => (ScalarType::Float, "1"),
+            "int" => (ScalarType::Int, "1"),
+            "uint" => (ScalarType::Uint, "1"),
+            _ if typ.starts_with("vec") => (ScalarType::Float, &typ[3..]),
+            _ if typ.starts_with("ivec") => (ScalarType::Int, &typ[4..]),
+            _ if typ.starts_with("uvec") => (ScalarType::Uint, &typ[4..]),
+            _ => return None,
+        };
+        Some(InputFormat {
+            components: components.parse().ok()?,
+            scalar,
+        })
+    }
+}
+
+pub fn reflect_shader(shader_name: &str, shader_type: ShaderType) -> ShaderInterface {
+    let path = PathBuf::from(format!("shaders/{shader_name}.{}", shader_type.extension()));
+    let mut interface = if path.exists() {
+        reflect_glsl(&path)
+    } else {
+        let spirv_path = path.with_added_extension("spv");
+        let bytes = std::fs::read(&spirv_path).unwrap();
+        let words: Vec<u32> = bytes
+            .chunks_exact(4)
+            .map(|word| u32::from_le_bytes(word.try_into().unwrap()))
+            .collect();
+        reflect_spirv(&words)
+    };
+    interface.path = path;
+    interface
+}
+
+// Only understands the declaration styles used in this repository, which is a single declaration
+// per layout qualifier. Included files are scanned too, as that's where most shared bindings are.
+fn reflect_glsl(path: &Path) -> ShaderInterface {
+    let mut interface = ShaderInterface::default();
+    let mut defines = HashMap::new();
+    for dependency in shader_dependencies(path) {
+        let source = strip_comments(&std::fs::read_to_string(&dependency).unwrap());
+        collect_glsl_defines(&source, &mut defines);
+        let mut rest = source.as_str();
+        while let Some(start) = rest.find("layout") {
+            rest = rest[start + "layout".len()..].trim_start();
+            let Some(qualifiers) = rest.strip_prefix('(') else {
+                continue;
+            };
+            let Some((qualifiers, declaration)) = qualifiers.split_once(')') else {
+                break;
+            };
+            let end = declaration.find([';', '{']).unwrap_or(declaration.len());
+            let is_block = declaration[end..].starts_with('{');
+            reflect_glsl_declaration(qualifiers, &declaration[..end], is_block, &mut interface);
+            rest = &declaration[end..];
+        }
+    }
+    // Uniform types shared between shaders are declared through macros expanding to the whole
+    // block, so the struct name is the first word of the expansion.
+    for binding in &mut interface.bindings {
+        if let Some(expansion) = binding
+            .uniform_type
+            .as_ref()
+            .and_then(|typ| defines.get(typ))
+        {
+            binding.uniform_type = expansion.split_whitespace().next().map(str::to_owned);
+        }
+    }
+    interface
+}
+
+fn collect_glsl_defines(source: &str, defines: &mut HashMap<String, String>) {
+    let source = source.replace("\\\n", " ");
+    for line in source.lines() {
+        let Some(define) = line.trim_start().strip_prefix("#define") else {
+            continue;
+        };
+        let mut words = define.trim().splitn(2, char::is_whitespace);
+        if let (Some(name), Some(expansion)) = (words.next(), words.next()) {
+            defines.insert(name.to_owned(), expansion.trim().to_owned());
+        }
+    }
+}
+
+fn reflect_glsl_declaration(
+    qualifiers: &str,
+    declaration: &str,
+    is_block: bool,
+    interface: &mut ShaderInterface,
+) {
+    let mut set = 0;
+    let mut binding = None;
+    let mut location = None;
+    for qualifier in qualifiers.split(',') {
+        let (key, value) = match qualifier.split_once('=') {
+            Some((key, value)) => (key.trim(), Some(value.trim())),
+            None => (qualifier.trim(), None),
+        };
+        let axis = match key.strip_suffix("_id").unwrap_or(key) {
+            "local_size_x" => Some(0),
+            "local_size_y" => Some(1),
+            "local_size_z" => Some(2),
+            _ => None,
+        };
+        match (key, value, axis) {
+            ("set", Some(value), _) => set = value.parse().unwrap(),
+            ("binding", Some(value), _) => binding = Some(value.parse().unwrap()),
+            ("location", Some(value), _) => location = Some(value.parse().unwrap()),
+            ("push_constant", None, _) => interface.push_constants = true,
+            (_, Some(value), Some(axis)) => {
+                let value = value.parse().unwrap();
+                let local_size = interface
+                    .local_size
+                    .get_or_insert([LocalSize::Literal(1); 3]);
+                local_size[axis] = if key.ends_with("_id") {
+                    LocalSize::Specialized(value)
+                } else {
+                    LocalSize::Literal(value)
+                };
+            }
+            _ => (),
+        }
+    }
+    let tokens: Vec<&str> = declaration
+        .split(|c: char| c.is_whitespace() || c == '[')
+        .filter(|token| !token.is_empty())
+        .collect();
+    if let Some(binding) = binding {
+        // Array sizes come after the name, so they're cut off before looking for it.
+        let words: Vec<&str> = declaration
+            .split('[')
+            .next()
+            .unwrap()
+            .split_whitespace()
+            .collect();
+        let name = words.last().map(|name| {
+            if is_block {
+                to_snakecase(name)
+            } else {
+                (*name).to_owned()
+            }
+        });
+        let typ = if tokens.contains(&"buffer") {
+            BindingType::StorageBuffer
+        } else if is_block {
+            BindingType::Uniform
+        } else {
+            match tokens.iter().find_map(|token| opaque_binding_type(token)) {
+                Some(typ) => typ,
+                None => BindingType::Uniform,
+            }
+        };
+        interface.bindings.push(ReflectedBinding {
+            set,
+            binding,
+            typ,
+            array: !is_block && declaration.contains('['),
+            cube: tokens
+                .iter()
+                .any(|token| token.starts_with("samplerCube") || token.starts_with("textureCube")),
+            name,
+            uniform_type: match (typ, is_block) {
+                (BindingType::Uniform, true) => words.last().map(|typ| (*typ).to_owned()),
+                (BindingType::Uniform, false) => {
+                    words.iter().rev().nth(1).map(|typ| (*typ).to_owned())
+                }
+                _ => None,
+            },
+        });
+    } else if let Some(location) = location {
+        let Some(index) = tokens.iter().position(|token| *token == "in") else {
+            return;
+        };
+        if let Some(format) = tokens
+            .get(index + 1)
+            .and_then(|typ| InputFormat::from_glsl(typ))
+        {
+            interface.inputs.push(ReflectedInput { location, format });
+        }
+    }
+}
+
+fn opaque_binding_type(token: &str) -> Option<BindingType> {
+    if token.starts_with("sampler") || token.starts_with("texture") {
+        Some(BindingType::Image)
+    } else if token.starts_with("image") {
+        Some(BindingType::StorageImage)
+    } else if token.starts_with("subpassInput") {
+        Some(BindingType::InputAttachment)
+    } else if token.starts_with("accelerationStructure") {
+        Some(BindingType::AccelerationStructure)
+    } else {
+        None
+    }
+}
+
+pub fn strip_comments(source: &str) -> String {
+    let mut result = String::with_capacity(source.len());
+    let mut rest = source;
+    while let Some(start) = rest.find('/') {
+        result += &rest[..start];
+        let comment = &rest[start..];
+        rest = if comment.starts_with("//") {
+            comment.find('\n').map_or("", |end| &comment[end..])
+        } else if comment.starts_with("/*") {
+            comment.find("*/").map_or("", |end| &comment[end + 2..])
+        } else {
+            result.push('/');
+            &comment[1..]
+        };
+    }
+    result + rest
+}
+
+const OP_NAME: u32 = 5;
+const OP_TYPE_INT: u32 = 21;
+const OP_TYPE_FLOAT: u32 = 22;
+const OP_TYPE_VECTOR: u32 = 23;
+const OP_TYPE_IMAGE: u32 = 25;
+const OP_TYPE_SAMPLED_IMAGE: u32 = 27;
+const OP_TYPE_ARRAY: u32 = 28;
+const OP_TYPE_RUNTIME_ARRAY: u32 = 29;
+const OP_TYPE_POINTER: u32 = 32;
+const OP_EXECUTION_MODE: u32 = 16;
+const OP_VARIABLE: u32 = 59;
+const OP_DECORATE: u32 = 71;
+const OP_TYPE_ACCELERATION_STRUCTURE: u32 = 5341;
+
+const EXECUTION_MODE_LOCAL_SIZE: u32 = 17;
+
+const DECORATION_BUFFER_BLOCK: u32 = 3;
+const DECORATION_BUILT_IN: u32 = 11;
+const DECORATION_LOCATION: u32 = 30;
+const DECORATION_BINDING: u32 = 33;
+const DECORATION_DESCRIPTOR_SET: u32 = 34;
+
+const STORAGE_CLASS_UNIFORM_CONSTANT: u32 = 0;
+const STORAGE_CLASS_INPUT: u32 = 1;
+const STORAGE_CLASS_UNIFORM: u32 = 2;
+const STORAGE_CLASS_PUSH_CONSTANT: u32 = 9;
+const STORAGE_CLASS_STORAGE_BUFFER: u32 = 12;
+
+const DIM_CUBE: u32 = 3;
+const DIM_SUBPASS_DATA: u32 = 6;
+
+// Walks the instruction stream once to collect the decorations and types, and then resolves the
+// variables against them. Only the instructions needed for the checks are understood at all.
+fn reflect_spirv(words: &[u32]) -> ShaderInterface {
+    assert_eq!(words[0], 0x07230203, "invalid SPIR-V magic number");
+    let bound = words[3] as usize;
+    let mut names: Vec<Option<String>> = vec![None; bound];
+    let mut bindings = vec![None; bound];
+    let mut sets = vec![0; bound];
+    let mut locations = vec![None; bound];
+    let mut built_in = vec![false; bound];
+    let mut buffer_block = vec![false; bound];
+    let mut types: Vec<Option<(u32, Vec<u32>)>> = vec![None; bound];
+    let mut variables = Vec::new();
+    let mut local_size = None;
+    let mut offset = 5;
+    while offset < words.len() {
+        let length = (words[offset] >> 16) as usize;
+        let opcode = words[offset] & 0xFFFF;
+        let operands = &words[offset + 1..offset + length];
+        match opcode {
+            OP_NAME => names[operands[0] as usize] = spirv_string(&operands[1..]),
+            OP_EXECUTION_MODE if operands[1] == EXECUTION_MODE_LOCAL_SIZE => {
+                local_size = Some([operands[2], operands[3], operands[4]].map(LocalSize::Literal));
+            }
+            OP_DECORATE => {
+                let target = operands[0] as usize;
+                match operands[1] {
+                    DECORATION_BINDING => bindings[target] = Some(operands[2] as usize),
+                    DECORATION_DESCRIPTOR_SET => sets[target] = operands[2] as usize,
+                    DECORATION_LOCATION => locations[target] = Some(operands[2] as usize),
+                    DECORATION_BUILT_IN => built_in[target] = true,
+                    DECORATION_BUFFER_BLOCK => buffer_block[target] = true,
+                    _ => (),
+                }
+            }
+            OP_TYPE_INT
+            | OP_TYPE_FLOAT
+            | OP_TYPE_VECTOR
+            | OP_TYPE_IMAGE
+            | OP_TYPE_SAMPLED_IMAGE
+            | OP_TYPE_ARRAY
+            | OP_TYPE_RUNTIME_ARRAY
+            | OP_TYPE_POINTER
+            | OP_TYPE_ACCELERATION_STRUCTURE => {
+                types[operands[0] as usize] = Some((opcode, operands[1..].to_vec()));
+            }
+            OP_VARIABLE => {
+                variables.push((operands[0] as usize, operands[1] as usize, operands[2]))
+            }
+            _ => (),
+        }
+        offset += length;
+    }
+
+    let mut interface = ShaderInterface {
+        local_size,
+        ..ShaderInterface::default()
+    };
+    for (pointer_type, id, storage_class) in variables {
+        let (_, pointer) = types[pointer_type].as_ref().unwrap();
+        let mut pointee = pointer[1] as usize;
+        let mut array = false;
+        while let Some((OP_TYPE_ARRAY | OP_TYPE_RUNTIME_ARRAY, operands)) = &types[pointee] {
+            pointee = operands[0] as usize;
+            array = true;
+        }
+        match storage_class {
+            STORAGE_CLASS_PUSH_CONSTANT => interface.push_constants = true,
+            STORAGE_CLASS_INPUT if !built_in[id] && !built_in[pointee] => {
+                if let (Some(location), Some(format)) =
+                    (locations[id], spirv_format(pointee, &types))
+                {
+                    interface.inputs.push(ReflectedInput { location, format });
+                }
+            }
+            STORAGE_CLASS_UNIFORM_CONSTANT
+            | STORAGE_CLASS_UNIFORM
+            | STORAGE_CLASS_STORAGE_BUFFER => {
+                let Some(binding) = bindings[id] else {
+                    continue;
+                };
+                let typ = match (storage_class, &types[pointee]) {
+                    (STORAGE_CLASS_STORAGE_BUFFER, _) => BindingType::StorageBuffer,
+                    (STORAGE_CLASS_UNIFORM, _) if buffer_block[pointee] => {
+                        BindingType::StorageBuffer
+                    }
+                    (STORAGE_CLASS_UNIFORM, _) => BindingType::Uniform,
+                    (_, Some((OP_TYPE_SAMPLED_IMAGE, _))) => BindingType::Image,
+                    (_, Some((OP_TYPE_ACCELERATION_STRUCTURE, _))) => {
+                        BindingType::AccelerationStructure
+                    }
+                    (_, Some((OP_TYPE_IMAGE, operands))) if operands[1] == DIM_SUBPASS_DATA => {
+                        BindingType::InputAttachment
+                    }
+                    (_, Some((OP_TYPE_IMAGE, operands))) if operands[5] == 2 => {
+                        BindingType::StorageImage
+                    }
+                    _ => continue,
+                };
+                interface.bindings.push(ReflectedBinding {
+                    set: sets[id],
+                    binding,
+                    typ,
+                    array,
+                    cube: image_dim(pointee, &types) == Some(DIM_CUBE),
+                    // Blocks without an instance name only have their type named. Struct names
+                    // are left unchecked, as Slang appends layout suffixes to them.
+                    name: match &names[id] {
+                        Some(name) => Some(strip_slang_suffix(name).to_owned()),
+                        None => names[pointee].as_deref().map(to_snakecase),
+                    },
+                    uniform_type: None,
+                });
+            }
+            _ => (),
+        }
+    }
+    interface
+}
+
+// Literal strings are packed four bytes per word, and end with a null byte.
+fn spirv_string(words: &[u32]) -> Option<String> {
+    let bytes: Vec<u8> = words
+        .iter()
+        .flat_map(|word| word.to_le_bytes())
+        .take_while(|byte| *byte != 0)
+        .collect();
+    let name = String::from_utf8(bytes).ok()?;
+    (!name.is_empty()).then_some(name)
+}
+
+// Slang makes global names unique by appending a numbered suffix, even when there's no conflict.
+fn strip_slang_suffix(name: &str) -> &str {
+    match name.rsplit_once('_') {
+        Some((base, suffix))
+            if !base.is_empty() && suffix.bytes().all(|byte| byte.is_ascii_digit()) =>
+        {
+            base
+        }
+        _ => name,
+    }
+}
+
+// Sampled images only refer to the image type, which is where the dimensionality is.
+fn image_dim(id: usize, types: &[Option<(u32, Vec<u32>)>]) -> Option<u32> {
+    match &types[id] {
+        Some((OP_TYPE_SAMPLED_IMAGE, operands)) => image_dim(operands[0] as usize, types),
+        Some((OP_TYPE_IMAGE, operands)) => Some(operands[1]),
+        _ => None,
+    }
+}
+
+fn spirv_format(id: usize, types: &[Option<(u32, Vec<u32>)>]) -> Option<InputFormat> {
+    match &types[id] {
+        Some((OP_TYPE_VECTOR, operands)) => Some(InputFormat {
+            components: operands[1] as usize,
+            ..spirv_format(operands[0] as usize, types)?
+        }),
+        Some((OP_TYPE_FLOAT, _)) => Some(InputFormat {
+            components: 1,
+            scalar: ScalarType::Float,
+        }),
+        Some((OP_TYPE_INT, operands)) => Some(InputFormat {
+            components: 1,
+            scalar: if operands[1] != 0 {
+                ScalarType::Int
+            } else {
+                ScalarType::Uint
+            },
+        }),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn vulkan_formats() {
+        let float = |components| {
+            Some(InputFormat {
+                components,
+                scalar: ScalarType::Float,
+            })
+        };
+        assert_eq!(InputFormat::from_vulkan("R32G32B32_SFLOAT"), float(3));
+        assert_eq!(InputFormat::from_vulkan("R8G8B8A8_UNORM"), float(4));
+        assert_eq!(InputFormat::from_vulkan("R16G16_USCALED"), float(2));
+        assert_eq!(InputFormat::from_vulkan("R8_SSCALED"), float(1));
+        assert_eq!(InputFormat::from_vulkan("B8G8R8A8_SRGB"), float(4));
+        assert_eq!(
+            InputFormat::from_vulkan("R32G32_UINT"),
+            Some(InputFormat {
+                components: 2,
+                scalar: ScalarType::Uint,
+            })
+        );
+    }
+
+    #[test]
+    fn unsupported_vulkan_formats() {
+        assert_eq!(InputFormat::from_vulkan("A2R10G10B10_UNORM_PACK32"), None);
+        assert_eq!(InputFormat::from_vulkan("BC1_RGB_UNORM_BLOCK"), None);
+        assert_eq!(InputFormat::from_vulkan("D32_SFLOAT"), None);
+        assert_eq!(InputFormat::from_vulkan("R32G32B32"), None);
+    }
+
+    #[test]
+    fn glsl_descriptor_sets() {
+        let mut interface = ShaderInterface::default();
+        reflect_glsl_declaration(
+            "binding = 3",
+            " uniform sampler2D color",
+            false,
+            &mut interface,
+        );
+        reflect_glsl_declaration(
+            "set = 1, binding = 2",
+            " buffer Lights ",
+            true,
+            &mut interface,
+        );
+        let bindings: Vec<_> = interface
+            .bindings
+            .iter()
+            .map(|binding| (binding.set, binding.binding))
+            .collect();
+        assert_eq!(bindings, [(0, 3), (1, 2)]);
+    }
+
+    #[test]
+    fn glsl_binding_names() {
+        let mut interface = ShaderInterface::default();
+        reflect_glsl_declaration(
+            "binding = 0",
+            " uniform GLOBAL_UNIFORM_TYPE global",
+            false,
+            &mut interface,
+        );
+        reflect_glsl_declaration(
+            "binding = 12",
+            " uniform sampler2D bloom_images[2]",
+            false,
+            &mut interface,
+        );
+        reflect_glsl_declaration(
+            "binding = 10",
+            " writeonly buffer VisibleStars ",
+            true,
+            &mut interface,
+        );
+        let names: Vec<_> = interface
+            .bindings
+            .iter()
+            .map(|binding| binding.name.as_deref().unwrap())
+            .collect();
+        assert_eq!(names, ["global", "bloom_images", "visible_stars"]);
+        assert_eq!(
+            interface.bindings[0].uniform_type.as_deref(),
+            Some("GLOBAL_UNIFORM_TYPE")
+        );
+        assert_eq!(interface.bindings[1].uniform_type, None);
+
+        let mut defines = HashMap::new();
+        collect_glsl_defines(
+            "#define GLOBAL_UNIFORM_TYPE \\\n    Global { \\\n        uint x; \\\n }\n",
+            &mut defines,
+        );
+        assert_eq!(
+            defines["GLOBAL_UNIFORM_TYPE"].split_whitespace().next(),
+            Some("Global")
+        );
+    }
+
+    #[test]
+    fn spirv_names() {
+        let words: Vec<u32> = [b"glob", b"al_0", b"\0\0\0\0"]
+            .iter()
+            .map(|bytes| u32::from_le_bytes(**bytes))
+            .collect();
+        let name = spirv_string(&words).unwrap();
+        assert_eq!(name, "global_0");
+        assert_eq!(strip_slang_suffix(&name), "global");
+        assert_eq!(strip_slang_suffix("scene_color"), "scene_color");
+        assert_eq!(spirv_string(&[0]), None);
+    }
+
+    #[test]
+    fn glsl_local_size() {
+        let mut interface = ShaderInterface::default();
+        reflect_glsl_declaration(
+            "local_size_x = 64, local_size_y_id = 1",
+            " in",
+            false,
+            &mut interface,
+        );
+        assert_eq!(
+            interface.local_size,
+            Some([
+                LocalSize::Literal(64),
+                LocalSize::Specialized(1),
+                LocalSize::Literal(1)
+            ])
+        );
+    }
+}
@@ -1,31 +1,171 @@
 use crate::config::Renderer;
-use std::path::PathBuf;
+use crate::types::ShaderType;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::BTreeSet;
+use std::hash::{Hash, Hasher};
+use std::path::{Path, PathBuf};
 use std::process::Command;
+use std::sync::atomic::{AtomicUsize, Ordering};
 
+const SLANG_MAIN: &str = "shaders/main.slang";
+
+// Set to skip spirv-opt, which takes a while and doesn't matter much for debugging shaders.
+const NO_SPIRV_OPT_VAR: &str = "NO_SPIRV_OPT";
+
+// Only compiles the Slang entry points. Shaders with a GLSL source are compiled by shaderc when the
+// renderer is created instead, which keeps shaderc out of the build script, so their compile time
+// isn't helped by anything here. Cached SPIR-V is reused as long as main.slang and everything it
+// pulls in with #include is unchanged. Slang import declarations aren't followed, so modules split
+// out of main.slang would have to be added to collect_dependencies first.
 pub fn compile_shaders(renderer: &Renderer) {
-    for (shader_name, shader_type) in renderer.shaders() {
-        let glsl_path = PathBuf::from(format!("shaders/{shader_name}.{}", shader_type.extension()));
-        if !glsl_path.exists() {
-            let spirv_path = glsl_path.with_added_extension("spv");
-            let entry_point = format!("{shader_name}_{}", shader_type.extension());
-            let status = Command::new("slangc")
-                .args([
-                    "shaders/main.slang",
-                    "-profile",
-                    "glsl_460",
-                    "-entry",
-                    entry_point.as_str(),
-                    "-target",
-                    "spirv",
-                    // TODO: Slangc should infer this based on mesh shader usage, report as bug.
-                    "-capability",
-                    "spirv_1_4",
-                    "-o",
-                ])
-                .arg(spirv_path)
-                .status()
-                .unwrap();
-            assert!(status.success());
+    let jobs: Vec<_> = renderer
+        .shaders()
+        .filter(|(shader_name, shader_type)| !glsl_path(shader_name, *shader_type).exists())
+        .collect::<BTreeSet<_>>()
+        .into_iter()
+        .collect();
+    let out_dir = PathBuf::from(std::env::var("OUT_DIR").unwrap());
+    let source_hash = hash_with_dependencies(Path::new(SLANG_MAIN));
+    let optimize = std::env::var_os(NO_SPIRV_OPT_VAR).is_none();
+    println!("cargo:rerun-if-env-changed={NO_SPIRV_OPT_VAR}");
+
+    // Every slangc invocation parses the entire main.slang file, so running them in parallel helps
+    // quite a lot with clean builds. Jobs are distributed dynamically, as compile times of
+    // different entry points vary a lot.
+    let next_job = AtomicUsize::new(0);
+    let thread_count = std::thread::available_parallelism().map_or(1, |n| n.get());
+    std::thread::scope(|scope| {
+        for _ in 0..thread_count.min(jobs.len()) {
+            scope.spawn(|| {
+                while let Some((shader_name, shader_type)) =
+                    jobs.get(next_job.fetch_add(1, Ordering::Relaxed))
+                {
+                    compile_slang(shader_name, *shader_type, source_hash, optimize, &out_dir);
+                }
+            });
         }
+    });
+}
+
+// Returns all files that the given shader depends on, including itself, so that the build script
+// can be rerun when any of them changes. Only GLSL-style #include directives are supported.
+pub fn shader_dependencies(path: &Path) -> BTreeSet<PathBuf> {
+    let mut dependencies = BTreeSet::new();
+    collect_dependencies(path, &mut dependencies);
+    dependencies
+}
+
+fn compile_slang(
+    shader_name: &str,
+    shader_type: ShaderType,
+    source_hash: u64,
+    optimize: bool,
+    out_dir: &Path,
+) {
+    let spirv_path = glsl_path(shader_name, shader_type).with_added_extension("spv");
+    let entry_point = format!("{shader_name}_{}", shader_type.extension());
+
+    // The hash of preprocessed sources is stored in OUT_DIR rather than next to the SPIR-V, so that
+    // it gets invalidated together with the rest of the build artifacts by cargo clean.
+    let mut hasher = DefaultHasher::new();
+    source_hash.hash(&mut hasher);
+    entry_point.hash(&mut hasher);
+    optimize.hash(&mut hasher);
+    let hash = format!("{:016x}", hasher.finish());
+    let hash_path = out_dir.join(format!("{entry_point}.hash"));
+    if spirv_path.exists() && std::fs::read_to_string(&hash_path).ok().as_ref() == Some(&hash) {
+        return;
+    }
+
+    let output = Command::new("slangc")
+        .args([
+            SLANG_MAIN,
+            "-profile",
+            "glsl_460",
+            "-entry",
+            entry_point.as_str(),
+            "-target",
+            "spirv",
+            // TODO: Slangc should infer this based on mesh shader usage, report as bug.
+            "-capability",
+            "spirv_1_4",
+            "-o",
+        ])
+        .arg(&spirv_path)
+        .output()
+        .unwrap();
+    if !output.status.success() {
+        panic!(
+            "shader compilation failed, entry point: {entry_point}, file: {SLANG_MAIN}\n{}",
+            String::from_utf8_lossy(&output.stderr)
+        );
     }
+    if optimize {
+        optimize_spirv(&spirv_path, &entry_point, out_dir);
+    }
+    std::fs::write(hash_path, hash).unwrap();
+}
+
+// Runs the same performance preset as spirv-opt -O, which is mostly inlining, scalar replacement and
+// dead code elimination. Slang output has a lot of all of these, so this usually makes shaders
+// noticeably smaller, which also makes pipeline creation faster on the driver side.
+fn optimize_spirv(spirv_path: &Path, entry_point: &str, out_dir: &Path) {
+    let optimized_path = out_dir.join(format!("{entry_point}.spv"));
+    let output = match Command::new("spirv-opt")
+        .args(["-O", "--target-env=vulkan1.3"])
+        .arg(spirv_path)
+        .arg("-o")
+        .arg(&optimized_path)
+        .output()
+    {
+        Ok(output) => output,
+        // spirv-opt comes with the Vulkan SDK same as slangc, but unlike slangc isn't strictly
+        // required for the build to work.
+        Err(error) if error.kind() == std::io::ErrorKind::NotFound => {
+            println!("cargo:warning=spirv-opt not found, shaders will not be optimized, set {NO_SPIRV_OPT_VAR} to silence this");
+            return;
+        }
+        Err(error) => panic!("failed to run spirv-opt: {error}"),
+    };
+    if !output.status.success() {
+        panic!(
+            "shader optimization failed, entry point: {entry_point}\n{}",
+            String::from_utf8_lossy(&output.stderr)
+        );
+    }
+    let original_size = std::fs::metadata(spirv_path).unwrap().len();
+    let optimized_size = std::fs::metadata(&optimized_path).unwrap().len();
+    if optimized_size > original_size {
+        println!("cargo:warning=spirv-opt made {entry_point} larger, from {original_size} to {optimized_size} bytes");
+    }
+    std::fs::copy(&optimized_path, spirv_path).unwrap();
+}
+
+fn hash_with_dependencies(path: &Path) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    for dependency in shader_dependencies(path) {
+        dependency.hash(&mut hasher);
+        std::fs::read(&dependency).unwrap().hash(&mut hasher);
+    }
+    hasher.finish()
+}
+
+fn collect_dependencies(path: &Path, dependencies: &mut BTreeSet<PathBuf>) {
+    if !dependencies.insert(path.to_owned()) {
+        return;
+    }
+    let source = std::fs::read_to_string(path).unwrap();
+    for line in source.lines() {
+        let Some(include) = line.trim().strip_prefix("#include") else {
+            continue;
+        };
+        // Include paths are resolved relative to the shaders directory, the same way as the
+        // include callback in the runtime GLSL compiler does.
+        let include = include.trim().trim_matches('"');
+        collect_dependencies(&Path::new("shaders").join(include), dependencies);
+    }
+}
+
+fn glsl_path(shader_name: &str, shader_type: ShaderType) -> PathBuf {
+    PathBuf::from(format!("shaders/{shader_name}.{}", shader_type.extension()))
 }
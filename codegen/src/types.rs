@@ -1,4 +1,22 @@
-#[derive(Debug, Eq, Ord, PartialEq, PartialOrd)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum BindingType {
+    AccelerationStructure,
+    Image,
+    InputAttachment,
+    StorageBuffer,
+    StorageImage,
+    Uniform,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum BlendMode {
+    None,
+    Alpha,
+    Additive,
+    Premultiplied,
+}
+
+#[derive(Clone, Copy, Debug, Eq, Ord, PartialEq, PartialOrd)]
 pub enum ShaderType {
     Compute,
     Mesh,
@@ -7,6 +25,48 @@ pub enum ShaderType {
     Fragment,
 }
 
+impl BindingType {
+    pub fn name(&self) -> &'static str {
+        match self {
+            BindingType::AccelerationStructure => "ACCELERATION_STRUCTURE_KHR",
+            BindingType::Image => "COMBINED_IMAGE_SAMPLER",
+            BindingType::InputAttachment => "INPUT_ATTACHMENT",
+            BindingType::StorageBuffer => "STORAGE_BUFFER",
+            BindingType::StorageImage => "STORAGE_IMAGE",
+            BindingType::Uniform => "UNIFORM_BUFFER",
+        }
+    }
+}
+
+impl BlendMode {
+    pub fn from_name(name: &str) -> BlendMode {
+        match name {
+            "none" => BlendMode::None,
+            "alpha" => BlendMode::Alpha,
+            "additive" => BlendMode::Additive,
+            "premultiplied" => BlendMode::Premultiplied,
+            _ => panic!(
+                "unknown blend mode, expected none, alpha, additive or premultiplied, got: {name}"
+            ),
+        }
+    }
+
+    // Returns whether blending is enabled, followed by the source and destination color factors
+    // and the destination alpha factor. Blending is only used for compositing effects like bloom
+    // and for transparent surfaces, so the alpha channel is always left alone. The scene stores the
+    // reflectivity of surfaces there for screen-space reflections, so it has to survive the
+    // composite, and reflections are traced against the opaque depth behind transparent surfaces
+    // anyway.
+    pub fn factors(&self) -> (u32, &'static str, &'static str, &'static str) {
+        match self {
+            BlendMode::None => (0, "ZERO", "ZERO", "ZERO"),
+            BlendMode::Alpha => (1, "SRC_ALPHA", "ONE_MINUS_SRC_ALPHA", "ONE"),
+            BlendMode::Additive => (1, "ONE", "ONE", "ONE"),
+            BlendMode::Premultiplied => (1, "ONE", "ONE_MINUS_SRC_ALPHA", "ONE"),
+        }
+    }
+}
+
 impl ShaderType {
     pub fn lowercase(&self) -> &'static str {
         match self {
@@ -38,6 +98,16 @@ impl ShaderType {
         }
     }
 
+    pub fn stage_flag(&self) -> &'static str {
+        match self {
+            ShaderType::Compute => "COMPUTE",
+            ShaderType::Fragment => "FRAGMENT",
+            ShaderType::Mesh => "MESH_EXT",
+            ShaderType::Task => "TASK_EXT",
+            ShaderType::Vertex => "VERTEX",
+        }
+    }
+
     pub fn requires_mesh_shaders(&self) -> bool {
         matches!(self, ShaderType::Mesh | ShaderType::Task)
     }
@@ -0,0 +1,337 @@
+use crate::config::{Compute, ConfigError, DescriptorBinding, DescriptorSet, Pipeline, Renderer};
+use crate::reflect::{reflect_shader, strip_comments, InputFormat, LocalSize, ShaderInterface};
+use crate::types::ShaderType;
+use std::collections::{BTreeSet, HashMap};
+
+const UNIFORM_MODULE: &str = "src/renderer/uniform.rs";
+
+// Mismatches between renderer.kdl and the shaders otherwise only show up at runtime, either as
+// validation layer errors far removed from the actual cause or as shaders silently reading garbage.
+// Checking them here turns these into build errors pointing at the exact binding or location.
+pub fn validate_shaders(renderer: &Renderer) {
+    validate_binding_names(renderer);
+    validate_uniform_types(renderer);
+    let sets = &renderer.descriptor_sets;
+    let mut used_bindings = BTreeSet::new();
+    let renderer_push_stage = renderer
+        .push_constant
+        .as_ref()
+        .map(|push| push.stage.as_str());
+    for pipeline in renderer.pipelines() {
+        let push_stage = match &pipeline.push_constants {
+            Some(push_constants) => Some(push_constants.stages.as_str()),
+            None => renderer_push_stage,
+        };
+        for (shader_name, shader_type) in pipeline.shaders() {
+            let interface = reflect_shader(shader_name, shader_type);
+            validate_bindings(&interface, shader_type, sets, &mut used_bindings);
+            validate_push_constants(&interface, shader_type, push_stage);
+            if shader_type == ShaderType::Vertex {
+                validate_vertex_inputs(&interface, pipeline);
+            }
+        }
+    }
+    for compute in &renderer.computes {
+        let interface = reflect_shader(&compute.name, ShaderType::Compute);
+        validate_bindings(&interface, ShaderType::Compute, sets, &mut used_bindings);
+        validate_push_constants(&interface, ShaderType::Compute, renderer_push_stage);
+        validate_local_size(&interface, compute);
+    }
+    for (set_index, set) in sets.iter().enumerate() {
+        for (index, binding) in set.bindings.iter().enumerate() {
+            if !used_bindings.contains(&(set_index, index)) {
+                println!(
+                    "cargo:warning=descriptor binding not used by any shader, set: {set}, binding: {index}, name: {}",
+                    binding.name()
+                );
+            }
+        }
+    }
+}
+
+// References between nodes of renderer.kdl, which knuffel can't check by itself. Unlike the rest,
+// these don't need the shaders, and the generated code assumes them, so they're checked first.
+pub fn validate_config(renderer: &Renderer) -> Result<(), ConfigError> {
+    let mut pipelines = HashMap::new();
+    for pipeline in renderer.pipelines() {
+        if let Some(first) = pipelines.insert(pipeline.name.as_str(), pipeline.span) {
+            return Err(ConfigError::DuplicatePipeline {
+                name: pipeline.name.clone(),
+                span: pipeline.span,
+                first,
+            });
+        }
+        for specialization in pipeline.fragment_specialization.iter().flatten() {
+            if !renderer
+                .specializations
+                .iter()
+                .any(|declared| declared.name == *specialization)
+            {
+                return Err(ConfigError::UndeclaredSpecialization {
+                    pipeline: pipeline.name.clone(),
+                    specialization: specialization.clone(),
+                    span: pipeline.span,
+                });
+            }
+        }
+    }
+    for binding in renderer.descriptor_bindings() {
+        let (sampler, span) = match binding {
+            DescriptorBinding::Cubemap(image) | DescriptorBinding::Image(image) => {
+                (&image.sampler, image.span)
+            }
+            DescriptorBinding::ImageArray(array) => (&array.sampler, array.span),
+            _ => continue,
+        };
+        if !renderer
+            .samplers
+            .iter()
+            .any(|declared| declared.name == *sampler)
+        {
+            return Err(ConfigError::UnknownSampler {
+                binding: binding.name().to_owned(),
+                sampler: sampler.clone(),
+                span,
+            });
+        }
+    }
+    Ok(())
+}
+
+// The generated update functions for single bindings are only named after the binding, so the
+// names have to be unique across all sets.
+fn validate_binding_names(renderer: &Renderer) {
+    let mut names = BTreeSet::new();
+    for binding in renderer.descriptor_bindings() {
+        if !names.insert(binding.name()) {
+            panic!(
+                "descriptor binding name used more than once, name: {}",
+                binding.name()
+            );
+        }
+    }
+}
+
+// The generated code imports these from the uniform module, so a typo would otherwise only show up
+// as an unresolved import somewhere in the generated file. Only struct declarations are looked
+// for, which is all the module contains.
+fn validate_uniform_types(renderer: &Renderer) {
+    let source = strip_comments(&std::fs::read_to_string(UNIFORM_MODULE).unwrap());
+    let words: Vec<&str> = source.split_whitespace().collect();
+    let declared: BTreeSet<&str> = words
+        .windows(2)
+        .filter(|pair| pair[0] == "struct")
+        .filter_map(|pair| {
+            pair[1]
+                .split(|c: char| !c.is_alphanumeric() && c != '_')
+                .next()
+        })
+        .collect();
+    for typ in renderer.uniform_module_types() {
+        if !declared.contains(typ) {
+            panic!("type from renderer.kdl not declared in {UNIFORM_MODULE}, type: {typ}");
+        }
+    }
+}
+
+fn validate_local_size(interface: &ShaderInterface, compute: &Compute) {
+    let path = interface.path.display();
+    let declared = compute.local_size();
+    let Some(reflected) = interface.local_size else {
+        panic!("compute shader doesn't declare a local size, file: {path}");
+    };
+    for (axis, (reflected, declared)) in reflected.into_iter().zip(declared).enumerate() {
+        match reflected {
+            LocalSize::Literal(size) if size == declared => (),
+            LocalSize::Specialized(id) if id as usize == axis => (),
+            LocalSize::Literal(size) => panic!(
+                "compute local size doesn't match renderer.kdl, file: {path}, axis: {axis}, shader: {size}, renderer.kdl: {declared}"
+            ),
+            LocalSize::Specialized(id) => panic!(
+                "compute local size specialization constant has the wrong ID, file: {path}, axis: {axis}, expected: {axis}, got: {id}"
+            ),
+        }
+    }
+}
+
+fn validate_bindings(
+    interface: &ShaderInterface,
+    shader_type: ShaderType,
+    sets: &[DescriptorSet],
+    used_bindings: &mut BTreeSet<(usize, usize)>,
+) {
+    let path = interface.path.display();
+    for reflected in &interface.bindings {
+        let Some(set) = sets.get(reflected.set) else {
+            panic!(
+                "shader uses a descriptor set missing from renderer.kdl, file: {path}, set: {}",
+                reflected.set
+            );
+        };
+        let Some(binding) = set.bindings.get(reflected.binding) else {
+            panic!(
+                "shader uses a binding missing from renderer.kdl, file: {path}, set: {set}, binding: {}, shader: {}",
+                reflected.binding,
+                reflected.typ.name()
+            );
+        };
+        used_bindings.insert((reflected.set, reflected.binding));
+        if binding.descriptor_type() != reflected.typ {
+            panic!(
+                "descriptor type mismatch, file: {path}, binding: {} ({}), renderer.kdl: {}, shader: {}",
+                reflected.binding,
+                binding.name(),
+                binding.descriptor_type().name(),
+                reflected.typ.name()
+            );
+        }
+        let kdl_array = matches!(binding, DescriptorBinding::ImageArray(_));
+        if kdl_array != reflected.array {
+            panic!(
+                "descriptor array mismatch, file: {path}, binding: {} ({}), renderer.kdl: {}, shader: {}",
+                reflected.binding,
+                binding.name(),
+                array_label(kdl_array),
+                array_label(reflected.array)
+            );
+        }
+        let kdl_cube = matches!(binding, DescriptorBinding::Cubemap(_));
+        if kdl_cube != reflected.cube {
+            panic!(
+                "descriptor cubemap mismatch, file: {path}, binding: {} ({}), renderer.kdl: {}, shader: {}",
+                reflected.binding,
+                binding.name(),
+                cube_label(kdl_cube),
+                cube_label(reflected.cube)
+            );
+        }
+        if let Some(name) = &reflected.name {
+            if name != binding.name() {
+                panic!(
+                    "descriptor name mismatch, file: {path}, binding: {}, renderer.kdl: {}, shader: {name}",
+                    reflected.binding,
+                    binding.name()
+                );
+            }
+        }
+        if let (DescriptorBinding::Uniform(uniform), Some(typ)) = (binding, &reflected.uniform_type)
+        {
+            if *typ != uniform.typ {
+                panic!(
+                    "uniform type mismatch, file: {path}, binding: {} ({}), renderer.kdl: {}, shader: {typ}",
+                    reflected.binding,
+                    binding.name(),
+                    uniform.typ
+                );
+            }
+        }
+        let stage = binding.stage();
+        if stage != "ALL"
+            && !stage
+                .split('|')
+                .any(|flag| flag.trim() == shader_type.stage_flag())
+        {
+            panic!(
+                "descriptor stage mismatch, file: {path}, binding: {} ({}), renderer.kdl: {stage}, shader: {}",
+                reflected.binding,
+                binding.name(),
+                shader_type.stage_flag()
+            );
+        }
+    }
+}
+
+// Sizes can't be compared here, as the size of the Rust type isn't known to the build script, but
+// a shader using push constants not declared for its stage would still fail pipeline creation.
+fn validate_push_constants(
+    interface: &ShaderInterface,
+    shader_type: ShaderType,
+    stage: Option<&str>,
+) {
+    if !interface.push_constants {
+        return;
+    }
+    let path = interface.path.display();
+    let Some(stage) = stage else {
+        panic!("push constant block missing from renderer.kdl, file: {path}");
+    };
+    if stage != "ALL"
+        && !stage
+            .split('|')
+            .any(|flag| flag.trim() == shader_type.stage_flag())
+    {
+        panic!(
+            "push constant stage mismatch, file: {path}, renderer.kdl: {stage}, shader: {}",
+            shader_type.stage_flag()
+        );
+    }
+}
+
+// Locations are assigned to attributes in order, skipping the unused ones, the same way as when
+// generating the vertex input state.
+fn validate_vertex_inputs(interface: &ShaderInterface, pipeline: &Pipeline) {
+    let path = interface.path.display();
+    let attributes: Vec<_> = pipeline
+        .vertex_bindings
+        .iter()
+        .flat_map(|binding| &binding.attributes)
+        .filter(|attribute| !attribute.unused)
+        .collect();
+    for input in &interface.inputs {
+        let Some(attribute) = attributes.get(input.location) else {
+            panic!(
+                "vertex input missing from renderer.kdl, file: {path}, pipeline: {}, location: {}, shader: {}",
+                pipeline.name,
+                input.location,
+                format_label(input.format)
+            );
+        };
+        let Some(format) = InputFormat::from_vulkan(&attribute.format) else {
+            panic!(
+                "vertex attribute format not supported, pipeline: {}, attribute: {}, format: {}",
+                pipeline.name, attribute.name, attribute.format
+            );
+        };
+        if format != input.format {
+            panic!(
+                "vertex input format mismatch, file: {path}, pipeline: {}, location: {}, renderer.kdl: {}, shader: {}",
+                pipeline.name,
+                input.location,
+                attribute.format,
+                format_label(input.format)
+            );
+        }
+    }
+    for location in 0..attributes.len() {
+        if !interface
+            .inputs
+            .iter()
+            .any(|input| input.location == location)
+        {
+            println!(
+                "cargo:warning=vertex attribute not used by shader, mark it as unused, file: {path}, pipeline: {}, location: {location}",
+                pipeline.name
+            );
+        }
+    }
+}
+
+fn array_label(array: bool) -> &'static str {
+    if array {
+        "array"
+    } else {
+        "single"
+    }
+}
+
+fn cube_label(cube: bool) -> &'static str {
+    if cube {
+        "cubemap"
+    } else {
+        "not cubemap"
+    }
+}
+
+fn format_label(format: InputFormat) -> String {
+    format!("{}x{:?}", format.components, format.scalar)
+}
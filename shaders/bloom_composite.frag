@@ -0,0 +1,19 @@
+#version 460
+
+#extension GL_EXT_shader_8bit_storage : require
+#extension GL_EXT_shader_16bit_storage : require
+
+#include "types/uniform.glsl"
+
+layout(binding = 0) uniform GLOBAL_UNIFORM_TYPE global;
+layout(binding = 12) uniform sampler2D bloom_images[2];
+
+layout(location = 0) in vec2 frag_uv;
+
+layout(location = 0) out vec4 out_color;
+
+// Blended additively onto the scene by the pipeline, so the alpha is ignored.
+void main() {
+    vec3 bloom = texture(bloom_images[0], frag_uv).rgb;
+    out_color = vec4(global.postprocessing.bloom_intensity * bloom, 0);
+}
@@ -0,0 +1,26 @@
+#version 460
+
+#extension GL_EXT_shader_8bit_storage : require
+#extension GL_EXT_shader_16bit_storage : require
+
+#include "types/uniform.glsl"
+
+layout(binding = 0) uniform GLOBAL_UNIFORM_TYPE global;
+layout(binding = 11) uniform sampler2D scene_color;
+
+layout(location = 0) in vec2 frag_uv;
+
+layout(location = 0) out vec4 out_color;
+
+// A hard cutoff makes bloom pop in and out as pixels cross the threshold, so the knee blends it in
+// quadratically over a small range below it instead.
+void main() {
+    vec3 color = texture(scene_color, frag_uv).rgb;
+    float brightness = max(color.r, max(color.g, color.b));
+    float threshold = global.postprocessing.bloom_threshold;
+    float knee = global.postprocessing.bloom_knee;
+    float soft = clamp(brightness - threshold + knee, 0, 2 * knee);
+    soft = soft * soft / (4 * knee + 0.00001);
+    float contribution = max(soft, brightness - threshold) / max(brightness, 0.00001);
+    out_color = vec4(color * contribution, 1);
+}
@@ -0,0 +1,20 @@
+#version 460
+
+#extension GL_EXT_shader_8bit_storage : require
+#extension GL_EXT_shader_16bit_storage : require
+
+#include "types/debug.glsl"
+#include "types/uniform.glsl"
+
+layout(binding = 0) uniform GLOBAL_UNIFORM_TYPE global;
+layout(binding = 6) readonly buffer DebugVertices {
+    DebugVertex debug_vertices[];
+};
+
+layout(location = 0) out vec3 frag_color;
+
+void main() {
+    DebugVertex vertex = debug_vertices[gl_VertexIndex];
+    gl_Position = global.camera.projection_matrix * global.camera.view_matrix * vec4(vertex.position, 1);
+    frag_color = vertex.color;
+}
@@ -0,0 +1,57 @@
+#version 460
+
+#extension GL_EXT_shader_8bit_storage : require
+#extension GL_EXT_shader_16bit_storage : require
+
+#include "types/uniform.glsl"
+
+const uint SAMPLE_COUNT = 32;
+const float GOLDEN_ANGLE = 2.39996323;
+// Surfaces right in front of the camera would otherwise get arbitrarily large circles of confusion,
+// and the disk gets too sparse to cover them long before that.
+const float MAX_RADIUS = 16;
+
+layout(binding = 0) uniform GLOBAL_UNIFORM_TYPE global;
+layout(binding = 11) uniform sampler2D scene_color;
+
+layout(location = 0) in vec2 frag_uv;
+
+layout(location = 0) out vec4 out_color;
+
+#include "util/depth.glsl"
+
+float view_distance(ivec2 texel) {
+    vec2 uv = (vec2(texel) + 0.5) / vec2(textureSize(scene_depth, 0));
+    return -view_space_from_uv(uv, scene_depth_at(texel)).z;
+}
+
+// Radius in pixels of the disk a point at the given distance is blurred into. The aperture is the
+// radius for infinitely distant points, as the size grows with the relative distance from focus.
+float circle_of_confusion(float distance, float focus) {
+    return min(global.dof.aperture * abs(distance - focus) / distance, MAX_RADIUS);
+}
+
+// Gathers from a disk of the size of this pixel's circle of confusion, with samples on a golden
+// angle spiral so that they cover it evenly. Each sample only counts if its own circle reaches this
+// pixel, which keeps sharp surfaces from bleeding into the blurred ones around them.
+void main() {
+    ivec2 size = textureSize(scene_depth, 0);
+    float focus = global.dof.focus_distance;
+    if (global.dof.autofocus) {
+        focus = view_distance(size / 2);
+    }
+    float radius = circle_of_confusion(view_distance(ivec2(frag_uv * size)), focus);
+    vec2 texel_size = 1 / vec2(size);
+    vec3 sum = texture(scene_color, frag_uv).rgb;
+    float weight_sum = 1;
+    for (uint i = 0; i < SAMPLE_COUNT; ++i) {
+        float angle = float(i) * GOLDEN_ANGLE;
+        vec2 offset = vec2(cos(angle), sin(angle)) * sqrt((float(i) + 0.5) / SAMPLE_COUNT) * radius;
+        vec2 uv = frag_uv + offset * texel_size;
+        float sample_radius = circle_of_confusion(view_distance(ivec2(uv * size)), focus);
+        float weight = clamp(sample_radius - length(offset) + 1, 0, 1);
+        sum += texture(scene_color, uv).rgb * weight;
+        weight_sum += weight;
+    }
+    out_color = vec4(sum / weight_sum, 1);
+}
@@ -0,0 +1,11 @@
+#version 460
+
+layout(binding = 26) uniform sampler2D dof_image;
+
+layout(location = 0) in vec2 frag_uv;
+
+layout(location = 0) out vec4 out_color;
+
+void main() {
+    out_color = texture(dof_image, frag_uv);
+}
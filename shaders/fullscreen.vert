@@ -0,0 +1,18 @@
+#version 460
+
+layout(location = 0) out vec2 frag_uv;
+
+const vec2 POSITIONS[6] = vec2[](
+    vec2(1, 1),
+    vec2(1, -1),
+    vec2(-1, -1),
+    vec2(1, 1),
+    vec2(-1, -1),
+    vec2(-1, 1)
+);
+
+void main() {
+    vec2 position = POSITIONS[gl_VertexIndex];
+    gl_Position = vec4(position, 0, 1);
+    frag_uv = position * 0.5 + 0.5;
+}
@@ -0,0 +1,29 @@
+#version 460
+
+layout(constant_id = 0) const float RADIUS = 1;
+// 0 blurs horizontally from the first bloom image, 1 blurs vertically from the second one.
+layout(constant_id = 1) const uint AXIS = 0;
+
+layout(binding = 12) uniform sampler2D bloom_images[2];
+
+layout(location = 0) in vec2 frag_uv;
+
+layout(location = 0) out vec4 out_color;
+
+void main() {
+    vec2 texel_size = 1 / vec2(textureSize(bloom_images[AXIS], 0));
+    vec2 direction = AXIS == 0 ? vec2(texel_size.x, 0) : vec2(0, texel_size.y);
+    // Most of the kernel weight is within three standard deviations, so this makes the radius cover
+    // nearly all of it without wasting samples on the tail. The weights follow the radius exactly,
+    // so fractional radii still change the blur smoothly even though the tap count is rounded.
+    float sigma = max(RADIUS / 3, 0.5);
+    int taps = int(ceil(RADIUS));
+    vec3 sum = vec3(0);
+    float weight_sum = 0;
+    for (int i = -taps; i <= taps; ++i) {
+        float weight = exp(-float(i * i) / (2 * sigma * sigma));
+        sum += weight * texture(bloom_images[AXIS], frag_uv + float(i) * direction).rgb;
+        weight_sum += weight;
+    }
+    out_color = vec4(sum / weight_sum, 1);
+}
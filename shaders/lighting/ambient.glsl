@@ -0,0 +1,12 @@
+// Irradiance of the captured sky, sampled with the same axis swap as the skybox.
+layout(binding = 30) uniform samplerCube sky_irradiance;
+
+// Diffuse only, as the capture is far too blurry to show up in reflections. Metals have no diffuse
+// reflection, so they only get what the direct lights reflect.
+vec3 ambient_light(vec3 normal, vec3 albedo, float metallic) {
+    if (!global.ambient.enable) {
+        return vec3(0);
+    }
+    vec3 irradiance = texture(sky_irradiance, normal.xzy).rgb;
+    return (1 - metallic) * albedo * irradiance * global.ambient.intensity;
+}
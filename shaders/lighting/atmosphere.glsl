@@ -1,3 +1,8 @@
+// Known at compile time, so that the sampling loops can be unrolled. Constant IDs 0 and 1 are taken by
+// these in every fragment shader including this file.
+layout(constant_id = 0) const uint IN_SCATTERING_SAMPLES = 10;
+layout(constant_id = 1) const uint OPTICAL_DEPTH_SAMPLES = 3;
+
 vec2 ray_sphere(vec3 sphere_centre, float sphere_radius, vec3 ray_origin, vec3 ray_direction) {
     vec3 offset = ray_origin - sphere_centre;
     float a = 1;
@@ -27,10 +32,10 @@ float density_at_point(vec3 point) {
 // these values later.
 
 float optical_depth(vec3 ray_origin, vec3 ray_direction, float ray_length) {
-    float step_length = ray_length / global.atmosphere.optical_depth_point_count;
+    float step_length = ray_length / OPTICAL_DEPTH_SAMPLES;
     vec3 sample_point = ray_origin + ray_direction * step_length / 2;
     float optical_depth = 0;
-    for (uint i = 0; i < global.atmosphere.optical_depth_point_count; ++i) {
+    for (uint i = 0; i < OPTICAL_DEPTH_SAMPLES; ++i) {
         float local_density = density_at_point(sample_point);
         optical_depth += local_density * step_length;
         sample_point += ray_direction * step_length;
@@ -59,17 +64,17 @@ vec3 calculate_light(vec3 ray_origin, vec3 ray_direction, float ray_length, vec3
         pow(400 / global.atmosphere.wavelengths.g, 4),
         pow(400 / global.atmosphere.wavelengths.b, 4)
     );
-    float step_length = ray_length / global.atmosphere.scatter_point_count;
+    float step_length = ray_length / IN_SCATTERING_SAMPLES;
     vec3 in_scatter_point = ray_origin + ray_direction * step_length / 2;
     vec3 in_scattered_light = vec3(0);
-    for (uint i = 0; i < global.atmosphere.scatter_point_count; ++i) {
+    for (uint i = 0; i < IN_SCATTERING_SAMPLES; ++i) {
         // This is kind of wrong because the sun ray ignores the planet, which results in sunsets being red regardless
         // of the direction you look in (rather than black color when looking away from the sun). Naive approach with
         // ray_sphere results in color banding for some reason? Probably I should be smarter when integrating over
         // in_scatter_point, so that I don't waste precision on scattering points where light is obstructed by the
         // planet.
         // TODO: Account for planet obstructing sun rays.
-        vec3 sun_direction = normalize(global.atmosphere.sun_position - in_scatter_point);
+        vec3 sun_direction = global.atmosphere.sun_direction;
         float sun_ray_length = ray_sphere(global.atmosphere.planet_position, global.atmosphere.scale * global.atmosphere.planet_radius, in_scatter_point, sun_direction).y;
         float sun_ray_optical_depth = optical_depth(in_scatter_point, sun_direction, sun_ray_length);
         float view_ray_optical_depth = optical_depth(in_scatter_point, -ray_direction, step_length * i);
@@ -33,17 +33,9 @@ vec3 fresnel_schlick(float cos_theta, vec3 f0) {
     return f0 + (1 - f0) * pow(clamp(1 - cos_theta, 0, 1), 5);
 }
 
-vec3 pbr(vec3 position, vec3 normal, vec3 albedo, float metallic, float roughness) {
-    vec3 view = normalize(global.camera.position - position);
-
+vec3 brdf(vec3 view, vec3 light, vec3 radiance, vec3 normal, vec3 albedo, float metallic, float roughness) {
     vec3 f0 = mix(vec3(0.04), albedo, metallic);
-
-    vec3 light = normalize(global.light.position - position);
     vec3 halfway = normalize(view + light);
-    vec3 radiance = global.light.color * global.light.intensity;
-    if (dot(light, vec3(0, 0, 1)) <= 0) {
-        radiance = vec3(0);
-    }
 
     float ndf = distribution_ggx(normal, halfway, roughness);
     float g = geometry_smith(normal, view, light, roughness);
@@ -61,4 +53,50 @@ vec3 pbr(vec3 position, vec3 normal, vec3 albedo, float metallic, float roughnes
     vec3 radiance_out = (kd * albedo / PI + specular) * radiance * ndotl;
 
     return radiance_out;
+}
+
+vec3 pbr(vec3 position, vec3 normal, vec3 albedo, float metallic, float roughness) {
+    vec3 view = normalize(global.camera.position - position);
+    vec3 light = normalize(global.light.position - position);
+    vec3 radiance = global.light.color * global.light.intensity;
+    if (dot(light, vec3(0, 0, 1)) <= 0) {
+        radiance = vec3(0);
+    }
+    return brdf(view, light, radiance, normal, albedo, metallic, roughness);
+}
+
+// Inverse-square falloff, multiplied by a window that brings it smoothly to zero at the radius so
+// that lights have a bounded range. This is the same window as in Unreal's point lights, from Brian
+// Karis's "Real Shading in Unreal Engine 4". The one in the denominator keeps surfaces right next to
+// the light from blowing up.
+float point_light_attenuation(float distance, float radius) {
+    float ratio = distance / radius;
+    float window = clamp(1 - ratio * ratio * ratio * ratio, 0, 1);
+    return window * window / (distance * distance + 1);
+}
+
+// Point lights don't cast shadows, so unlike the sun they are kept out of pbr, which callers
+// multiply by the shadow map visibility.
+vec3 point_lights(vec3 position, vec3 normal, vec3 albedo, float metallic, float roughness) {
+    vec3 view = normalize(global.camera.position - position);
+    vec3 radiance_out = vec3(0);
+    for (uint i = 0; i < global.light_count; ++i) {
+        PointLight point_light = global.lights[i];
+        vec3 to_light = point_light.position - position;
+        float distance = length(to_light);
+        if (distance >= point_light.radius) {
+            continue;
+        }
+        vec3 radiance = point_light.color * point_light.intensity * point_light_attenuation(distance, point_light.radius);
+        radiance_out += brdf(view, to_light / distance, radiance, normal, albedo, metallic, roughness);
+    }
+    return radiance_out;
+}
+
+// Stored in the alpha channel of the scene for screen-space reflections. Uses the reflectance at
+// normal incidence, as the Fresnel term would need the view direction the postprocess pass doesn't
+// have, and fades out with roughness as rough surfaces blur reflections beyond recognition.
+float specular_reflectivity(float metallic, float roughness) {
+    float smoothness = 1 - roughness;
+    return mix(0.04, 1, metallic) * smoothness * smoothness;
 }
\ No newline at end of file
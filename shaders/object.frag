@@ -0,0 +1,37 @@
+#version 460
+
+#extension GL_EXT_shader_8bit_storage : require
+#extension GL_EXT_shader_16bit_storage : require
+
+#include "types/uniform.glsl"
+
+layout(binding = 0) uniform GLOBAL_UNIFORM_TYPE global;
+
+layout(location = 0) in vec3 frag_position;
+layout(location = 1) in vec3 frag_normal;
+layout(location = 2) flat in vec4 frag_color;
+
+layout(location = 0) out vec4 out_color;
+
+#include "lighting/ambient.glsl"
+#include "lighting/atmosphere.glsl"
+#include "lighting/pbr.glsl"
+
+const float OBJECT_METALLIC = 0;
+const float OBJECT_ROUGHNESS = 0.5;
+
+void main() {
+    // Transparent objects are drawn with culling disabled, so the back faces need flipped normals.
+    vec3 normal = normalize(frag_normal);
+    if (!gl_FrontFacing) {
+        normal = -normal;
+    }
+    vec3 color_at_object = pbr(frag_position, normal, frag_color.rgb, OBJECT_METALLIC, OBJECT_ROUGHNESS);
+    color_at_object += point_lights(frag_position, normal, frag_color.rgb, OBJECT_METALLIC, OBJECT_ROUGHNESS);
+    color_at_object += ambient_light(normal, frag_color.rgb, OBJECT_METALLIC);
+    vec3 color_at_camera = compute_atmosphere(color_at_object, frag_position);
+    // Only objects with alpha below one are drawn in the transparent pass, where alpha is the blend
+    // factor. Opaque objects store their reflectivity there instead, like the voxels.
+    float alpha = frag_color.a < 1 ? frag_color.a : specular_reflectivity(OBJECT_METALLIC, OBJECT_ROUGHNESS);
+    out_color = vec4(color_at_camera, alpha);
+}
@@ -0,0 +1,34 @@
+#version 460
+
+#extension GL_EXT_shader_8bit_storage : require
+#extension GL_EXT_shader_16bit_storage : require
+
+#include "types/object.glsl"
+#include "types/uniform.glsl"
+
+layout(binding = 0) uniform GLOBAL_UNIFORM_TYPE global;
+layout(binding = 17) readonly buffer Objects {
+    ObjectInstance objects[];
+};
+
+// The depth pre-pass draws objects with this shader too, and the forward pass only shades fragments
+// with exactly the depth written there.
+invariant gl_Position;
+
+layout(location = 0) in vec3 in_position;
+layout(location = 1) in vec3 in_normal;
+
+layout(location = 0) out vec3 frag_position;
+layout(location = 1) out vec3 frag_normal;
+layout(location = 2) flat out vec4 frag_color;
+
+void main() {
+    // Every object is a separate draw with the first instance pointing at its entry, which is in the
+    // region of the object list belonging to this frame.
+    ObjectInstance object = objects[gl_InstanceIndex];
+    vec4 world_space = object.model * vec4(in_position, 1);
+    gl_Position = global.camera.projection_matrix * global.camera.view_matrix * world_space;
+    frag_position = world_space.xyz;
+    frag_normal = mat3(object.model) * in_normal;
+    frag_color = object.color;
+}
@@ -0,0 +1,20 @@
+#version 460
+
+#extension GL_EXT_shader_8bit_storage : require
+#extension GL_EXT_shader_16bit_storage : require
+
+#include "types/object.glsl"
+#include "types/uniform.glsl"
+
+layout(binding = 0) uniform GLOBAL_UNIFORM_TYPE global;
+layout(binding = 17) readonly buffer Objects {
+    ObjectInstance objects[];
+};
+
+layout(location = 0) in vec3 in_position;
+
+// Same as object.vert, but projected into the first shadow cascade and without any outputs other than the position.
+void main() {
+    ObjectInstance object = objects[gl_InstanceIndex];
+    gl_Position = global.shadow.cascades[0].view_projection * object.model * vec4(in_position, 1);
+}
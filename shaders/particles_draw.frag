@@ -0,0 +1,13 @@
+#version 460
+
+layout(location = 0) in vec2 frag_offset;
+layout(location = 1) flat in vec4 frag_color;
+
+layout(location = 0) out vec4 out_color;
+
+void main() {
+    // Blending is additive, so premultiplying by alpha is what makes particles fade out, and the
+    // falloff towards the edge of the quad makes them look round.
+    float falloff = 1 - smoothstep(0.5, 1, length(frag_offset));
+    out_color = vec4(frag_color.rgb * frag_color.a * falloff, 0);
+}
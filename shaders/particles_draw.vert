@@ -0,0 +1,39 @@
+#version 460
+
+#extension GL_EXT_shader_8bit_storage : require
+#extension GL_EXT_shader_16bit_storage : require
+
+#include "types/particle.glsl"
+#include "types/uniform.glsl"
+
+layout(binding = 0) uniform GLOBAL_UNIFORM_TYPE global;
+layout(binding = 18) readonly buffer Particles {
+    Particle particles[];
+};
+
+layout(location = 0) out vec2 frag_offset;
+layout(location = 1) flat out vec4 frag_color;
+
+const float PARTICLE_SIZE = 0.05;
+// Fading out over the last moments of the lifetime avoids particles popping out of existence.
+const float PARTICLE_FADE_TIME = 0.5;
+
+const vec2 QUAD_CORNERS[6] = vec2[](
+    vec2(-1, -1), vec2(1, -1), vec2(1, 1),
+    vec2(-1, -1), vec2(1, 1), vec2(-1, 1)
+);
+
+void main() {
+    // Particles are drawn as camera-facing quads, built from the vertex index with no vertex buffer.
+    // Instance indices start at the particle region written this frame, as the first instance of
+    // each draw command is set up once when creating the buffers.
+    Particle particle = particles[gl_InstanceIndex];
+    vec2 corner = QUAD_CORNERS[gl_VertexIndex];
+    vec3 right = global.camera.inverse_view_matrix[0].xyz;
+    vec3 up = global.camera.inverse_view_matrix[1].xyz;
+    vec3 world_space = particle.position + PARTICLE_SIZE * (corner.x * right + corner.y * up);
+    gl_Position = global.camera.projection_matrix * global.camera.view_matrix * vec4(world_space, 1);
+    frag_offset = corner;
+    frag_color = particle.color;
+    frag_color.a *= min(particle.lifetime / PARTICLE_FADE_TIME, 1);
+}
@@ -0,0 +1,70 @@
+#version 460
+
+#extension GL_EXT_shader_8bit_storage : require
+#extension GL_EXT_shader_16bit_storage : require
+
+#include "types/uniform.glsl"
+
+layout(constant_id = 0) const float GRAIN_STRENGTH = 0;
+layout(constant_id = 1) const float VIGNETTE_STRENGTH = 0;
+layout(constant_id = 2) const float VIGNETTE_RADIUS = 0;
+layout(constant_id = 3) const float ABERRATION_STRENGTH = 0;
+layout(constant_id = 4) const float COLOR_LUT_STRENGTH = 0;
+layout(constant_id = 5) const float SSAO_INTENSITY = 0;
+
+layout(binding = 0) uniform GLOBAL_UNIFORM_TYPE global;
+layout(binding = 11) uniform sampler2D scene_color;
+layout(binding = 13) uniform sampler3D color_lut;
+layout(binding = 16) uniform sampler2D ssr_image;
+layout(binding = 25) uniform sampler2D ssao_images[2];
+
+layout(location = 0) in vec2 frag_uv;
+
+layout(location = 0) out vec4 out_color;
+
+// All effects are controlled by specialization constants, so the branches below are resolved when
+// the pipeline is created and disabled effects cost nothing.
+void main() {
+    vec3 color;
+    if (ABERRATION_STRENGTH != 0) {
+        // Offsetting along the direction from the screen center makes the fringes grow towards the
+        // edges, like with a real lens.
+        vec2 offset = (frag_uv - 0.5) * ABERRATION_STRENGTH;
+        color.r = texture(scene_color, frag_uv + offset).r;
+        color.g = texture(scene_color, frag_uv).g;
+        color.b = texture(scene_color, frag_uv - offset).b;
+    } else {
+        color = texture(scene_color, frag_uv).rgb;
+    }
+    if (SSAO_INTENSITY != 0) {
+        // Raising the occlusion to a power keeps fully lit areas unchanged, while darkening the
+        // occluded ones more the higher the intensity is.
+        color *= pow(texture(ssao_images[1], frag_uv).r, SSAO_INTENSITY);
+    }
+    // The scene stores how reflective each surface is in the alpha channel, while the SSR pass
+    // stores how confident it is in the reflection it found.
+    vec4 reflection = texture(ssr_image, frag_uv);
+    color = mix(color, reflection.rgb, reflection.a * texture(scene_color, frag_uv).a);
+    if (global.ssr.debug_overlay) {
+        color = reflection.rgb * reflection.a;
+    }
+    if (COLOR_LUT_STRENGTH != 0) {
+        // Texel centers are half a texel away from the edges, so the coordinates are remapped to
+        // make 0 and 1 land exactly on the first and last entries of the table.
+        vec3 coords = clamp(color, 0, 1) * (31. / 32.) + 0.5 / 32.;
+        color = mix(color, texture(color_lut, coords).rgb, COLOR_LUT_STRENGTH);
+    }
+    if (VIGNETTE_STRENGTH != 0) {
+        // Distances are measured in units of the screen height, so that the vignette stays circular
+        // instead of stretching along with the window.
+        vec2 offset = (frag_uv - 0.5) * vec2(global.postprocessing.aspect_ratio, 1);
+        float vignette = smoothstep(VIGNETTE_RADIUS + 0.5, VIGNETTE_RADIUS - 0.5, length(offset));
+        color *= mix(1, vignette, VIGNETTE_STRENGTH);
+    }
+    if (GRAIN_STRENGTH != 0) {
+        // Centered around zero, so that the grain doesn't brighten the image overall.
+        float noise = fract(sin(dot(frag_uv + global.postprocessing.time, vec2(127.1, 311.7))) * 43758.5453);
+        color += (noise - 0.5) * GRAIN_STRENGTH;
+    }
+    out_color = vec4(color, 1);
+}
@@ -0,0 +1,6 @@
+#version 460
+
+// Shadow maps only need depth, which is written by fixed-function hardware. Vulkan allows pipelines without a fragment
+// shader, but the pipeline generator always expects one.
+void main() {
+}
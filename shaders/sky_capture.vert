@@ -0,0 +1,27 @@
+#version 460
+
+#include "util/cubemap.glsl"
+
+layout(push_constant) uniform SkyCapturePushConstants {
+    uint face;
+};
+
+layout(location = 0) out vec3 frag_direction;
+
+const vec2 POSITIONS[6] = vec2[](
+    vec2(1, 1),
+    vec2(1, -1),
+    vec2(-1, -1),
+    vec2(1, 1),
+    vec2(-1, -1),
+    vec2(-1, 1)
+);
+
+void main() {
+    vec2 position = POSITIONS[gl_VertexIndex];
+    gl_Position = vec4(position, 0, 1);
+    // Clip space y points down in Vulkan, the same as on the faces. All corners are scaled equally
+    // by the normalization, so the interpolated directions are still exact. Swapping Y and Z
+    // converts from the cubemap space to the world, see the skybox.
+    frag_direction = normalize(cubemap_direction(face, position)).xzy;
+}
@@ -12,7 +12,12 @@ layout(location = 0) in vec3 frag_direction;
 layout(location = 0) out vec4 out_color;
 
 #include "lighting/atmosphere.glsl"
+#include "util/skybox.glsl"
 
 void main() {
-    out_color = vec4(compute_atmosphere_impl(vec3(0), frag_direction, 1 / 0), 1);
+    if (!global.atmosphere.enable) {
+        out_color = vec4(skybox_color(frag_direction), 0);
+        return;
+    }
+    out_color = vec4(compute_atmosphere_impl(vec3(0), frag_direction, 1 / 0), 0);
 }
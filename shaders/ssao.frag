@@ -0,0 +1,70 @@
+#version 460
+
+#extension GL_EXT_shader_8bit_storage : require
+#extension GL_EXT_shader_16bit_storage : require
+
+#include "types/uniform.glsl"
+
+layout(constant_id = 0) const float RADIUS = 0.5;
+
+const uint SAMPLE_COUNT = 16;
+const float GOLDEN_ANGLE = 2.39996323;
+
+layout(binding = 0) uniform GLOBAL_UNIFORM_TYPE global;
+
+layout(location = 0) in vec2 frag_uv;
+
+layout(location = 0) out float out_occlusion;
+
+#include "util/depth.glsl"
+
+// Kernel samples spiral around the normal and get denser towards the center, so that occluders
+// close to the surface count more than the distant ones.
+vec3 kernel_sample(uint i) {
+    float t = (float(i) + 0.5) / SAMPLE_COUNT;
+    float z = sqrt(1 - t);
+    float r = sqrt(t);
+    float angle = float(i) * GOLDEN_ANGLE;
+    float scale = mix(0.1, 1, t * t);
+    return vec3(r * cos(angle), r * sin(angle), z) * scale;
+}
+
+// The kernel is rotated by a different angle in each texel of a 4x4 tile, which trades banding for
+// noise that the blur pass then averages out exactly.
+float rotation_at(ivec2 texel) {
+    ivec2 tile = texel & 3;
+    return fract(52.9829189 * fract(0.06711056 * tile.x + 0.00583715 * tile.y)) * 6.28318531;
+}
+
+void main() {
+    ivec2 texel = ivec2(frag_uv * textureSize(scene_depth, 0));
+    float depth = scene_depth_at(texel);
+    if (depth >= 1) {
+        out_occlusion = 1;
+        return;
+    }
+    vec3 position = view_space_from_uv(frag_uv, depth);
+    vec3 normal = reconstruct_normal(texel, position);
+    float rotation = rotation_at(texel);
+    vec3 random = vec3(cos(rotation), sin(rotation), 0);
+    vec3 tangent = normalize(random - normal * dot(random, normal));
+    vec3 bitangent = cross(normal, tangent);
+    mat3 tbn = mat3(tangent, bitangent, normal);
+
+    float occlusion = 0;
+    for (uint i = 0; i < SAMPLE_COUNT; ++i) {
+        vec3 sample_position = position + tbn * kernel_sample(i) * RADIUS;
+        vec4 clip_space = global.camera.projection_matrix * vec4(sample_position, 1);
+        vec2 uv = clip_space.xy / clip_space.w * 0.5 + 0.5;
+        ivec2 sample_texel = ivec2(uv * textureSize(scene_depth, 0));
+        float sample_depth = scene_depth_at(sample_texel);
+        vec3 surface = view_space_from_uv(uv, sample_depth);
+        // View space looks towards negative z, so the sample is occluded when the surface is closer
+        // to the camera. Surfaces far in front are separate objects and shouldn't darken this one.
+        float range = smoothstep(0, 1, RADIUS / abs(position.z - surface.z));
+        if (surface.z >= sample_position.z + 0.02) {
+            occlusion += range;
+        }
+    }
+    out_occlusion = 1 - occlusion / SAMPLE_COUNT;
+}
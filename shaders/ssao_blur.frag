@@ -0,0 +1,20 @@
+#version 460
+
+layout(binding = 25) uniform sampler2D ssao_images[2];
+
+layout(location = 0) in vec2 frag_uv;
+
+layout(location = 0) out float out_occlusion;
+
+// The noise pattern of the SSAO pass repeats every 4x4 texels, so averaging exactly that many
+// removes it completely.
+void main() {
+    vec2 texel_size = 1 / vec2(textureSize(ssao_images[0], 0));
+    float sum = 0;
+    for (int y = -2; y < 2; ++y) {
+        for (int x = -2; x < 2; ++x) {
+            sum += texture(ssao_images[0], frag_uv + vec2(x, y) * texel_size).r;
+        }
+    }
+    out_occlusion = sum / 16;
+}
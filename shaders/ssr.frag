@@ -0,0 +1,77 @@
+#version 460
+
+#extension GL_EXT_shader_8bit_storage : require
+#extension GL_EXT_shader_16bit_storage : require
+
+#include "types/uniform.glsl"
+
+layout(constant_id = 2) const uint MAX_STEPS = 64;
+
+layout(binding = 0) uniform GLOBAL_UNIFORM_TYPE global;
+layout(binding = 11) uniform sampler2D scene_color;
+layout(binding = 15, r32f) uniform readonly image2D hiz;
+
+layout(location = 0) in vec2 frag_uv;
+
+layout(location = 0) out vec4 out_color;
+
+#include "lighting/atmosphere.glsl"
+#include "util/depth.glsl"
+#include "util/skybox.glsl"
+
+// Same as what the skybox shows in the given direction, so that rays escaping the screen or hitting
+// the sky blend in with the actual sky.
+vec3 environment_color(vec3 view_direction) {
+    vec3 direction = mat3(global.camera.inverse_view_matrix) * view_direction;
+    if (!global.atmosphere.enable) {
+        return skybox_color(direction);
+    }
+    return compute_atmosphere_impl(vec3(0), direction, 1 / 0);
+}
+
+// The ray is marched in view space with fixed steps, and each step is projected to the screen and
+// snapped to a texel of the half resolution Hi-Z buffer. The Hi-Z buffer stores the closest depth
+// of the texels it covers, so thin geometry isn't skipped over between full resolution texels.
+void main() {
+    ivec2 texel = ivec2(frag_uv * textureSize(scene_depth, 0));
+    float depth = scene_depth_at(texel);
+    // Sky pixels have nothing to reflect from, the sky itself is left as is.
+    if (depth >= 1) {
+        out_color = vec4(0);
+        return;
+    }
+    vec3 origin = view_space_from_uv(frag_uv, depth);
+    vec3 normal = reconstruct_normal(texel, origin);
+    vec3 direction = reflect(normalize(origin), normal);
+
+    ivec2 hiz_size = imageSize(hiz);
+    vec3 position = origin;
+    for (uint i = 0; i < MAX_STEPS; ++i) {
+        position += direction * global.ssr.step_size;
+        vec4 clip_space = global.camera.projection_matrix * vec4(position, 1);
+        if (clip_space.w <= 0) {
+            break;
+        }
+        vec2 uv = clip_space.xy / clip_space.w * 0.5 + 0.5;
+        if (any(lessThan(uv, vec2(0))) || any(greaterThan(uv, vec2(1)))) {
+            break;
+        }
+        ivec2 hiz_texel = ivec2(uv * hiz_size);
+        float hiz_depth = imageLoad(hiz, hiz_texel).r;
+        if (hiz_depth >= 1) {
+            continue;
+        }
+        vec3 surface = view_space_from_uv((vec2(hiz_texel) + 0.5) / hiz_size, hiz_depth);
+        // View space looks towards negative z, so the ray is behind the surface when it's further
+        // along it. Rays that went far behind passed behind an object rather than hitting it.
+        float behind = surface.z - position.z;
+        if (behind > 0 && behind < global.ssr.thickness) {
+            vec2 edge = min(uv, 1 - uv);
+            float edge_fade = smoothstep(0, 0.1, min(edge.x, edge.y));
+            float distance_fade = 1 - clamp(distance(origin, position) / global.ssr.fade_distance, 0, 1);
+            out_color = vec4(texture(scene_color, uv).rgb, edge_fade * distance_fade);
+            return;
+        }
+    }
+    out_color = vec4(environment_color(direction), 1);
+}
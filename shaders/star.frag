@@ -14,7 +14,13 @@ layout(location = 0) out vec4 out_color;
 #include "lighting/atmosphere.glsl"
 
 void main() {
-    vec3 color_at_object = vec3(10);
+    // Stars are drawn before the sky, so during the day they would show up as dark dots against it
+    // instead of being invisible. They fade in during twilight and are skipped entirely before.
+    float visibility = smoothstep(0.05, -0.15, global.atmosphere.sun_direction.z);
+    if (visibility == 0) {
+        discard;
+    }
+    vec3 color_at_object = vec3(10) * visibility;
     vec3 color_at_camera = compute_atmosphere(color_at_object, frag_position);
-    out_color = vec4(color_at_camera, 1);
+    out_color = vec4(color_at_camera, 0);
 }
@@ -10,13 +10,18 @@ layout(binding = 0) uniform GLOBAL_UNIFORM_TYPE global;
 layout(binding = 1, std140) readonly buffer Stars {
     Star stars[];
 };
+layout(binding = 10) readonly buffer VisibleStars {
+    uint visible_stars[];
+};
 
 layout(location = 0) in vec3 in_position;
 
 layout(location = 0) out vec3 frag_position;
 
 void main() {
-    mat4 star_model = stars[gl_InstanceIndex].model;
+    // Instance indices start at the region of the visible star list belonging to this frame, as the
+    // culling shader sets the first instance of the draw command.
+    mat4 star_model = stars[visible_stars[gl_InstanceIndex]].model;
     vec4 world_space = star_model * vec4(in_position, 1);
     gl_Position = global.camera.projection_matrix * global.camera.view_matrix * world_space;
     frag_position = world_space.xyz;
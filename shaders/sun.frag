@@ -17,5 +17,5 @@ layout(location = 0) out vec4 out_color;
 void main() {
     vec3 color_at_object = vec3(100);
     vec3 color_at_camera = compute_atmosphere(color_at_object, frag_position);
-    out_color = vec4(color_at_camera, 1);
+    out_color = vec4(color_at_camera, 0);
 }
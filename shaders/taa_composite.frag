@@ -0,0 +1,26 @@
+#version 460
+
+#extension GL_EXT_shader_8bit_storage : require
+#extension GL_EXT_shader_16bit_storage : require
+
+#include "types/uniform.glsl"
+
+layout(binding = 0) uniform GLOBAL_UNIFORM_TYPE global;
+
+layout(location = 0) in vec2 frag_uv;
+
+layout(location = 0) out vec4 out_color;
+
+#include "util/depth.glsl"
+#include "util/taa.glsl"
+
+// The debug view is applied here rather than in the resolve, so that the history keeps
+// accumulating the actual scene while it's shown. Reflectivity is zeroed, so that postprocessing
+// doesn't blend reflections over it.
+void main() {
+    if (global.taa.debug_view) {
+        out_color = vec4(vec3(taa_history_blend(taa_reproject(frag_uv))), 0);
+        return;
+    }
+    out_color = texture(taa_history[global.taa.history_index], frag_uv);
+}
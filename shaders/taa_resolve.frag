@@ -0,0 +1,46 @@
+#version 460
+
+#extension GL_EXT_shader_8bit_storage : require
+#extension GL_EXT_shader_16bit_storage : require
+
+#include "types/uniform.glsl"
+
+layout(binding = 0) uniform GLOBAL_UNIFORM_TYPE global;
+layout(binding = 11) uniform sampler2D scene_color;
+
+layout(location = 0) in vec2 frag_uv;
+
+layout(location = 0) out vec4 out_color;
+
+#include "util/depth.glsl"
+#include "util/taa.glsl"
+
+// The history is clamped to the range of colors around this pixel in the current frame before
+// blending. Whatever the history shows outside of that range most likely belongs to a surface that
+// was disoccluded or moved, so this is what keeps ghosting in check.
+void main() {
+    ivec2 size = textureSize(scene_color, 0);
+    ivec2 texel = ivec2(frag_uv * size);
+    vec4 current = texelFetch(scene_color, texel, 0);
+    vec3 neighbourhood_min = current.rgb;
+    vec3 neighbourhood_max = current.rgb;
+    for (int y = -1; y <= 1; ++y) {
+        for (int x = -1; x <= 1; ++x) {
+            vec3 neighbour = texelFetch(scene_color, clamp(texel + ivec2(x, y), ivec2(0), size - 1), 0).rgb;
+            neighbourhood_min = min(neighbourhood_min, neighbour);
+            neighbourhood_max = max(neighbourhood_max, neighbour);
+        }
+    }
+    vec2 previous_uv = taa_reproject(frag_uv);
+    float blend = taa_history_blend(previous_uv);
+    // The history of a newly created image is garbage and could contain NaNs, which mixing with a
+    // zero weight wouldn't get rid of.
+    vec3 color = current.rgb;
+    if (blend > 0) {
+        vec3 history = texture(taa_history[1 - global.taa.history_index], previous_uv).rgb;
+        color = mix(color, clamp(history, neighbourhood_min, neighbourhood_max), blend);
+    }
+    // The alpha channel holds the reflectivity read by postprocessing, which doesn't alias the way
+    // colors do, so it's passed through as it is.
+    out_color = vec4(color, current.a);
+}
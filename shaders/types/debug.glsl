@@ -0,0 +1,4 @@
+struct DebugVertex {
+    vec3 position;
+    vec3 color;
+};
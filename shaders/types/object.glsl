@@ -0,0 +1,18 @@
+struct ObjectInstance {
+    mat4 model;
+    vec4 color;
+};
+
+// Matches VkDrawIndexedIndirectCommand.
+struct ObjectDrawCommand {
+    uint index_count;
+    uint instance_count;
+    uint first_index;
+    int vertex_offset;
+    uint first_instance;
+};
+
+struct ObjectDrawCall {
+    vec4 bounds;
+    ObjectDrawCommand command;
+};
@@ -0,0 +1,14 @@
+struct Particle {
+    vec3 position;
+    vec3 velocity;
+    float lifetime;
+    vec4 color;
+};
+
+// Matches VkDrawIndirectCommand.
+struct ParticleDrawCommand {
+    uint vertex_count;
+    uint instance_count;
+    uint first_vertex;
+    uint first_instance;
+};
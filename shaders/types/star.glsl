@@ -1,3 +1,12 @@
 struct Star {
     mat4 model;
 };
+
+// Matches VkDrawIndexedIndirectCommand.
+struct StarDrawCommand {
+    uint index_count;
+    uint instance_count;
+    uint first_index;
+    int vertex_offset;
+    uint first_instance;
+};
@@ -6,10 +6,18 @@
     Global { \
         Voxels voxels; \
         Light light; \
+        PointLight lights[64]; \
+        uint light_count; \
+        Shadow shadow; \
         Atmosphere atmosphere; \
+        Ambient ambient; \
         Postprocessing postprocessing; \
+        Ssr ssr; \
+        Dof dof; \
+        Taa taa; \
+        Particles particles; \
         Camera camera; \
-        VoxelMaterial materials[256]; \
+        Culling culling; \
         Debug debug; \
  }
 
@@ -27,6 +35,9 @@ struct Voxels {
     uint root_svo_index;
     uint root_svo_side;
     uvec3 root_svo_base;
+    float ambient_occlusion_strength;
+    uint first_material;
+    uint active_session;
 };
 
 struct Light {
@@ -36,24 +47,86 @@ struct Light {
     float scale;
 };
 
+struct PointLight {
+    vec3 position;
+    float radius;
+    vec3 color;
+    float intensity;
+};
+
+struct ShadowCascade {
+    mat4 view_projection;
+    float split_distance;
+};
+
+struct Shadow {
+    ShadowCascade cascades[4];
+    uint cascade_count;
+    float texel_size;
+    float bias;
+    bool enable;
+};
+
 struct Atmosphere {
     bool enable;
-    uint scatter_point_count;
-    uint optical_depth_point_count;
     float density_falloff;
     vec3 planet_position;
     float planet_radius;
-    vec3 sun_position;
+    vec3 sun_direction;
     float scale;
     vec3 wavelengths;
     float scattering_strength;
     float henyey_greenstein_g;
+    vec3 sky_color;
+    bool skybox_texture;
+};
+
+struct Ambient {
+    bool enable;
+    float intensity;
 };
 
 struct Postprocessing {
     float exposure;
     uint tonemapper;
     float gamma;
+    float bloom_threshold;
+    float bloom_knee;
+    float bloom_intensity;
+    float time;
+    float aspect_ratio;
+};
+
+struct Ssr {
+    float step_size;
+    float thickness;
+    float fade_distance;
+    bool debug_overlay;
+};
+
+struct Dof {
+    float focus_distance;
+    float aperture;
+    bool autofocus;
+};
+
+struct Taa {
+    mat4 previous_view_projection;
+    uint history_index;
+    float history_weight;
+    bool history_valid;
+    bool debug_view;
+};
+
+// Particles are ping-ponged between two regions of the particle buffer, with the source one holding
+// the particles from the previous frame.
+struct Particles {
+    uint source;
+    uint emitted_offset;
+    uint emitted_count;
+    uint capacity;
+    float delta_time;
+    float gravity;
 };
 
 struct Camera {
@@ -68,11 +141,21 @@ struct Camera {
     vec3 direction;
 };
 
+struct Culling {
+    vec4 planes[6];
+    uint star_count;
+    uint object_count;
+    uint first_object;
+    uint flight_index;
+    bool enable;
+};
+
 struct VoxelMaterial {
     vec3 albedo;
     float roughness;
     vec3 emit;
     float metallic;
+    uint flags;
 };
 
 struct Debug {
@@ -6,21 +6,23 @@ struct VoxelMeshlet {
     i16vec3 chunk;
     int16_t _pad0;
     u8vec3 bound_base;
-    uint8_t _pad1;
+    // Bit mask of the face directions in the meshlet, matching the convention of the DIRECTION array.
+    uint8_t normals;
     u8vec3 bound_size;
-    uint8_t _pad2;
+    // Only meshlets of the active session are drawn, see VoxelMeshletMemory for why.
+    uint8_t session;
 };
 
 struct VoxelVertex {
     u8vec3 position;
-    // Two lowest bits encode an ambient occlusion value (00 => 0, 11 => 3/4), the other bits are unused.
+    // Two lowest bits encode an ambient occlusion value (00 => 0, 11 => 1), which is later scaled by
+    // the configured strength, the six highest bits are used for material ID.
     uint8_t data;
 };
 
 struct VoxelTriangle {
     u8vec3 indices;
-    // Three lowest bits encode a normal matching the convention of the DIRECTION array, five highest bits are used for
-    // material ID. Subject to heavy changes later.
+    // Three lowest bits encode a normal matching the convention of the DIRECTION array, the other bits are unused.
     uint8_t data;
 };
 
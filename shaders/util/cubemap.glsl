@@ -0,0 +1,18 @@
+// Direction through a point on a cubemap face, in the face order and orientation Vulkan samples
+// them in, with the point given in [-1, 1] coordinates whose y axis points down the face.
+vec3 cubemap_direction(uint face, vec2 point) {
+    switch (face) {
+    case 0u:
+        return vec3(1, -point.y, -point.x);
+    case 1u:
+        return vec3(-1, -point.y, point.x);
+    case 2u:
+        return vec3(point.x, 1, point.y);
+    case 3u:
+        return vec3(point.x, -1, -point.y);
+    case 4u:
+        return vec3(point.x, -point.y, 1);
+    default:
+        return vec3(-point.x, -point.y, -1);
+    }
+}
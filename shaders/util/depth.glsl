@@ -0,0 +1,23 @@
+// Shared by the screen-space passes, which all work with view space positions reconstructed from
+// the depth attachment of the forward pass.
+layout(binding = 14) uniform sampler2D scene_depth;
+
+vec3 view_space_from_uv(vec2 uv, float depth) {
+    vec4 view_space = global.camera.inverse_projection_matrix * vec4(2 * uv - 1, depth, 1);
+    return view_space.xyz / view_space.w;
+}
+
+float scene_depth_at(ivec2 texel) {
+    return texelFetch(scene_depth, clamp(texel, ivec2(0), textureSize(scene_depth, 0) - 1), 0).r;
+}
+
+// The scene is rendered forward, so there is no normal buffer and normals are reconstructed from
+// the neighbouring depth values instead. Voxel faces are flat, so this is only wrong along edges.
+vec3 reconstruct_normal(ivec2 texel, vec3 position) {
+    vec2 texel_size = 1 / vec2(textureSize(scene_depth, 0));
+    vec2 uv = (vec2(texel) + 0.5) * texel_size;
+    vec3 right = view_space_from_uv(uv + vec2(texel_size.x, 0), scene_depth_at(texel + ivec2(1, 0)));
+    vec3 down = view_space_from_uv(uv + vec2(0, texel_size.y), scene_depth_at(texel + ivec2(0, 1)));
+    vec3 normal = normalize(cross(right - position, down - position));
+    return dot(normal, position) > 0 ? -normal : normal;
+}
@@ -0,0 +1,24 @@
+// Shared by the star, object and voxel meshlet culling, which test bounding spheres or boxes against
+// the frustum planes in the global uniform. They aren't push constants, as all culling passes use the
+// same planes and it's simpler to upload them once per frame. Shaders declare the global uniform
+// before including this file.
+bool frustum_cull(vec3 center, float radius) {
+    for (int i = 0; i < 6; ++i) {
+        if (dot(global.culling.planes[i].xyz, center) + global.culling.planes[i].w < -radius) {
+            return true;
+        }
+    }
+    return false;
+}
+
+// Only the corner furthest along each plane normal is tested, so boxes near the corners of the
+// frustum can be kept despite being outside, but a box that's even partially inside never is culled.
+bool frustum_cull_aabb(vec3 min_coords, vec3 max_coords) {
+    for (int i = 0; i < 6; ++i) {
+        vec3 corner = mix(min_coords, max_coords, greaterThan(global.culling.planes[i].xyz, vec3(0)));
+        if (dot(global.culling.planes[i].xyz, corner) + global.culling.planes[i].w < 0) {
+            return true;
+        }
+    }
+    return false;
+}
@@ -0,0 +1,10 @@
+// Cubemaps are sampled in a left-handed Y-up space, while the world is right-handed with Z up, so
+// swapping Y and Z converts between the two and keeps the top face of the texture above.
+layout(binding = 24) uniform samplerCube skybox;
+
+vec3 skybox_color(vec3 direction) {
+    if (!global.atmosphere.skybox_texture) {
+        return global.atmosphere.sky_color;
+    }
+    return texture(skybox, direction.xzy).rgb;
+}
@@ -0,0 +1,19 @@
+// Shared by the resolve and the composite, which shows the blend factor in the debug view and
+// needs to compute it the same way. Requires util/depth.glsl.
+layout(binding = 27) uniform sampler2D taa_history[2];
+
+// Where the surface seen through this pixel was on the screen in the previous frame. Only camera
+// motion is accounted for, so moving objects ghost somewhat more than the static scene.
+vec2 taa_reproject(vec2 uv) {
+    float depth = scene_depth_at(ivec2(uv * textureSize(scene_depth, 0)));
+    vec4 world_position = global.camera.inverse_view_matrix * vec4(view_space_from_uv(uv, depth), 1);
+    vec4 previous_clip = global.taa.previous_view_projection * world_position;
+    return previous_clip.xy / previous_clip.w * 0.5 + 0.5;
+}
+
+// Surfaces that were off-screen in the previous frame have no history to blend with, and neither
+// does anything in the first frame after the history images were recreated.
+float taa_history_blend(vec2 previous_uv) {
+    bool on_screen = all(greaterThanEqual(previous_uv, vec2(0))) && all(lessThanEqual(previous_uv, vec2(1)));
+    return global.taa.history_valid && on_screen ? global.taa.history_weight : 0;
+}
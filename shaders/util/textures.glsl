@@ -0,0 +1,14 @@
+// All textures are bound at once, and materials refer to them using the 16 lowest bits of their flags. The index can
+// differ between invocations, so shaders including this have to enable GL_EXT_nonuniform_qualifier.
+layout(binding = 8, set = 0) uniform sampler2D textures[];
+
+// Has to match the constant in textures.rs.
+const uint MATERIAL_TEXTURED = 1 << 16;
+
+bool has_material_texture(uint material_flags) {
+    return (material_flags & MATERIAL_TEXTURED) != 0;
+}
+
+vec4 sample_material_texture(uint material_flags, vec2 uv) {
+    return texture(textures[nonuniformEXT(material_flags & 0xFFFF)], uv);
+}
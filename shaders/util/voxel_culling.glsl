@@ -0,0 +1,36 @@
+// Shared by the task shaders of the forward pass and the depth pre-pass, which have to agree on
+// which meshlets are drawn. Shaders declare the global uniform and the meshlet buffer, and include
+// util/frustum.glsl before including this file.
+// Faces of a direction can only be seen from the side their normal points to, so they all face away
+// from the camera if it's behind the plane of the face closest to it. Exact for axis-aligned faces,
+// so there's no popping when the camera crosses the bounds.
+bool backface_cull(uint normals, vec3 min_coords, vec3 max_coords) {
+    bvec3 positive_visible = greaterThan(global.camera.position, min_coords);
+    bvec3 negative_visible = lessThan(global.camera.position, max_coords);
+    for (int axis = 0; axis < 3; ++axis) {
+        if ((normals & (1u << (2 * axis))) != 0 && positive_visible[axis]) {
+            return false;
+        }
+        if ((normals & (2u << (2 * axis))) != 0 && negative_visible[axis]) {
+            return false;
+        }
+    }
+    return true;
+}
+
+bool cull(uint meshlet_index) {
+    if (meshlet_index >= global.voxels.meshlet_count) {
+        return true;
+    }
+    VoxelMeshlet meshlet = meshlets[meshlet_index];
+    // Empty slots left behind by unloaded chunks have no faces at all.
+    if (meshlet.triangle_count == 0 || meshlet.session != global.voxels.active_session) {
+        return true;
+    }
+    if (!global.culling.enable) {
+        return false;
+    }
+    vec3 min_coords = global.voxels.chunk_size * vec3(meshlet.chunk) + meshlet.bound_base;
+    vec3 max_coords = min_coords + meshlet.bound_size;
+    return backface_cull(uint(meshlet.normals), min_coords, max_coords) || frustum_cull_aabb(min_coords, max_coords);
+}
@@ -1,21 +1,29 @@
 #version 460
 
 #extension GL_EXT_mesh_shader : require
+#extension GL_EXT_nonuniform_qualifier : require
 #extension GL_EXT_shader_8bit_storage : require
 #extension GL_EXT_shader_16bit_storage : require
 
 #include "types/uniform.glsl"
 
 layout(binding = 0, set = 0) uniform GLOBAL_UNIFORM_TYPE global;
+layout(binding = 7, set = 0) uniform sampler2DShadow shadow_map;
+layout(binding = 23, set = 0) readonly buffer VoxelMaterials {
+    VoxelMaterial voxel_materials[];
+};
 
 layout(location = 0) in float ambient_occlusion;
 layout(location = 1) perprimitiveEXT flat in uint triangle_data;
+layout(location = 2) flat in uint material_id;
 
 layout(location = 0) out vec4 out_color;
 
+#include "lighting/ambient.glsl"
 #include "lighting/atmosphere.glsl"
 #include "lighting/pbr.glsl"
 #include "util/camera.glsl"
+#include "util/textures.glsl"
 
 const vec3 NORMALS[6] = vec3[](
     vec3(1, 0, 0),
@@ -26,12 +34,54 @@ const vec3 NORMALS[6] = vec3[](
     vec3(0, 0, -1)
 );
 
+// The sampler does a 2x2 comparison with bilinear filtering in hardware, and averaging a 3x3 grid of these on top softens
+// the edges further.
+float shadow_visibility(vec3 position) {
+    if (!global.shadow.enable) {
+        return 1;
+    }
+    ShadowCascade cascade = global.shadow.cascades[0];
+    if (distance(position, global.camera.position) > cascade.split_distance) {
+        return 1;
+    }
+    vec4 light_space = cascade.view_projection * vec4(position, 1);
+    vec2 uv = light_space.xy * 0.5 + 0.5;
+    float reference = light_space.z - global.shadow.bias;
+    float visibility = 0;
+    for (int dx = -1; dx <= 1; ++dx) {
+        for (int dy = -1; dy <= 1; ++dy) {
+            vec2 offset = vec2(dx, dy) * global.shadow.texel_size;
+            visibility += texture(shadow_map, vec3(uv + offset, reference));
+        }
+    }
+    return visibility / 9;
+}
+
+// Textures are projected along the face normal, so that each one covers exactly one voxel face.
+vec2 face_uv(vec3 position, uint normal_index) {
+    if (normal_index < 2) {
+        return position.yz;
+    } else if (normal_index < 4) {
+        return position.xz;
+    } else {
+        return position.xy;
+    }
+}
+
 void main() {
     vec3 position = world_space_from_depth(gl_FragCoord.z, global.camera);
-    vec3 normal = NORMALS[uint(triangle_data) & 7u];
-    VoxelMaterial material = global.materials[uint(triangle_data) >> 3];
+    uint normal_index = uint(triangle_data) & 7u;
+    vec3 normal = NORMALS[normal_index];
+    VoxelMaterial material = voxel_materials[global.voxels.first_material + material_id];
+    if (has_material_texture(material.flags)) {
+        material.albedo *= sample_material_texture(material.flags, face_uv(position, normal_index)).rgb;
+    }
     vec3 reflected_color = pbr(position, normal, material.albedo, material.metallic, material.roughness);
-    vec3 color_at_object = (1 - ambient_occlusion) * reflected_color + material.emit;
+    reflected_color *= shadow_visibility(position);
+    reflected_color += point_lights(position, normal, material.albedo, material.metallic, material.roughness);
+    reflected_color += ambient_light(normal, material.albedo, material.metallic);
+    float occlusion = global.voxels.ambient_occlusion_strength * ambient_occlusion;
+    vec3 color_at_object = (1 - occlusion) * reflected_color + material.emit;
     vec3 color_at_camera = compute_atmosphere(color_at_object, position);
-    out_color = vec4(color_at_camera, 1);
+    out_color = vec4(color_at_camera, specular_reflectivity(material.metallic, material.roughness));
 }
@@ -7,9 +7,12 @@
 #include "util/camera.glsl"
 
 layout(binding = 0) uniform GLOBAL_UNIFORM_TYPE global;
-layout(binding = 5) readonly buffer SvoNodes { SvoNode svo_nodes[]; };
+layout(binding = 5) readonly buffer VoxelOctrees { SvoNode svo_nodes[]; };
+layout(binding = 23) readonly buffer VoxelMaterials { VoxelMaterial voxel_materials[]; };
 layout(location = 0) out vec4 out_color;
 
+#include "lighting/pbr.glsl"
+
 bool is_voxel_inside_root_svo(uvec3 voxel) {
     return
         voxel.x >= global.voxels.root_svo_base.x &&
@@ -92,7 +95,7 @@ void main() {
     vec3 view_direction = normalize(world_space_from_depth(1, global.camera));
     uvec3 voxel = ray_trace(ivec3(floor(global.camera.position)), camera_position_within_cube, view_direction);
     uint material_index = find_svo(voxel);
-    VoxelMaterial material = global.materials[material_index];
+    VoxelMaterial material = voxel_materials[global.voxels.first_material + material_index];
     vec3 color = material.albedo;
-    out_color = vec4(color, 1);
+    out_color = vec4(color, specular_reflectivity(material.metallic, material.roughness));
 }
@@ -0,0 +1,97 @@
+use log::error;
+use std::fmt::{Display, Formatter};
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+// Assets loaded unconditionally, named relative to the base directory. Shaders compiled at runtime
+// are required too, but their list comes from the generated renderer code.
+pub const REQUIRED_ASSETS: &[&str] = &["assets/tetrahedron.obj", "assets/icosahedron.obj"];
+
+static BASE_DIRECTORIES: OnceLock<Vec<PathBuf>> = OnceLock::new();
+
+pub struct MissingAsset {
+    name: String,
+    tried: Vec<PathBuf>,
+    base: Option<PathBuf>,
+}
+
+// Base directories contain both assets/ and shaders/, like the repository root. An explicit
+// --assets directory is the only one tried. Otherwise, the executable directory comes first so
+// that packaged builds work from anywhere, then the crate directory for debug builds which cargo
+// puts deep inside target/, and finally the working directory like before.
+pub fn initialize_assets(assets_override: Option<PathBuf>) {
+    let mut directories = Vec::new();
+    if let Some(directory) = assets_override {
+        directories.push(directory);
+    } else {
+        let executable = std::env::current_exe().ok();
+        directories.extend(executable.and_then(|path| path.parent().map(Path::to_owned)));
+        #[cfg(debug_assertions)]
+        directories.push(PathBuf::from(env!("CARGO_MANIFEST_DIR")));
+        directories.extend(std::env::current_dir().ok());
+    }
+    BASE_DIRECTORIES.set(directories).unwrap();
+}
+
+pub fn resolve_asset(name: impl AsRef<Path>) -> Result<PathBuf, MissingAsset> {
+    let name = name.as_ref();
+    let directories = BASE_DIRECTORIES.get().unwrap();
+    let tried: Vec<PathBuf> = directories
+        .iter()
+        .map(|directory| directory.join(name))
+        .collect();
+    if let Some(path) = tried.iter().find(|path| path.exists()) {
+        return Ok(path.clone());
+    }
+    let base = directories
+        .iter()
+        .find(|directory| directory.join("assets").is_dir())
+        .cloned();
+    Err(MissingAsset {
+        name: name.display().to_string(),
+        tried,
+        base,
+    })
+}
+
+// Used by loaders of assets that were already verified at startup, so a missing file means it was
+// removed while the game was running. Exiting with the same message beats an io error from deep
+// inside some loader.
+pub fn asset_path(name: impl AsRef<Path>) -> PathBuf {
+    match resolve_asset(name) {
+        Ok(path) => path,
+        Err(error) => {
+            error!("{error}");
+            std::process::exit(1);
+        }
+    }
+}
+
+// Checked before any window or Vulkan objects are created, so that a broken installation fails
+// immediately rather than whenever the asset is first needed.
+pub fn verify_assets<'a>(names: impl IntoIterator<Item = &'a str>) -> Result<(), MissingAsset> {
+    for name in names {
+        resolve_asset(name)?;
+    }
+    Ok(())
+}
+
+impl Display for MissingAsset {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        let tried: Vec<_> = self
+            .tried
+            .iter()
+            .map(|path| path.display().to_string())
+            .collect();
+        let base = match &self.base {
+            Some(base) => base.display().to_string(),
+            None => "none found".to_owned(),
+        };
+        write!(
+            f,
+            "asset not found, pass --assets with the directory containing assets/ and shaders/, \x1B[1mname\x1B[0m: {}, \x1B[1mbase\x1B[0m: {base}, \x1B[1mtried\x1B[0m: {}",
+            self.name,
+            tried.join(", ")
+        )
+    }
+}
@@ -0,0 +1,225 @@
+use crate::physics::PHYSICS_DT;
+use log::{error, info, warn};
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+// Measured frames, not counting the warmup ones.
+pub const BENCHMARK_FRAMES: usize = 1000;
+
+pub const DEFAULT_BENCHMARK_WARMUP_FRAMES: usize = 50;
+
+// Simulation advances by a fixed step rather than the measured frame time, so that every run
+// renders the same sequence of frames no matter how fast the GPU is. Exactly one physics tick, so
+// that there's nothing left over for the camera to interpolate.
+pub const BENCHMARK_DELTA_TIME: f32 = PHYSICS_DT;
+
+// Times come from the GPU timestamp queries, which are read back a few frames after the frame was
+// submitted. Frames without results, like the first few, are not counted at all.
+pub struct Benchmark {
+    csv: Option<PathBuf>,
+    threshold_p99: Option<f32>,
+    warmup_frames: usize,
+    // Results of frames received so far, including the warmup ones.
+    received: usize,
+    // CPU times of frames whose GPU results haven't arrived yet, oldest first.
+    pending_cpu_frame_times: VecDeque<Duration>,
+    frames: Vec<FrameSample>,
+}
+
+struct FrameSample {
+    frame_index: usize,
+    cpu_frame_time: Duration,
+    gpu_frame_time: Duration,
+    pass_times: Vec<(&'static str, Duration)>,
+}
+
+struct BenchmarkSummary {
+    p50: f32,
+    p95: f32,
+    p99: f32,
+}
+
+impl Benchmark {
+    pub fn new(
+        csv: Option<PathBuf>,
+        threshold_p99: Option<f32>,
+        warmup_frames: usize,
+    ) -> Benchmark {
+        Benchmark {
+            csv,
+            threshold_p99,
+            warmup_frames,
+            received: 0,
+            pending_cpu_frame_times: VecDeque::new(),
+            frames: Vec::with_capacity(BENCHMARK_FRAMES),
+        }
+    }
+
+    // GPU times arrive a few frames late, but in the same order as the frames, so they are paired
+    // with the CPU time of the oldest frame still waiting for them.
+    pub fn record(
+        &mut self,
+        cpu_frame_time: Duration,
+        gpu_frame_time: Option<Duration>,
+        pass_times: &[(&'static str, Duration)],
+    ) {
+        self.pending_cpu_frame_times.push_back(cpu_frame_time);
+        let Some(gpu_frame_time) = gpu_frame_time else {
+            return;
+        };
+        let cpu_frame_time = self.pending_cpu_frame_times.pop_front().unwrap();
+        let frame_index = self.received;
+        self.received += 1;
+        if frame_index < self.warmup_frames || self.is_finished() {
+            return;
+        }
+        self.frames.push(FrameSample {
+            frame_index,
+            cpu_frame_time,
+            gpu_frame_time,
+            pass_times: pass_times.to_vec(),
+        });
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.frames.len() == BENCHMARK_FRAMES
+    }
+
+    // Writes the results and logs the summary. Returns whether the run passed, which is always the
+    // case without a threshold.
+    pub fn finish(&self) -> bool {
+        self.save();
+        let summary = summarize(&self.frames);
+        info!(
+            "benchmark finished, \x1B[1mframes\x1B[0m: {}, \x1B[1mp50\x1B[0m: {:.3}ms, \x1B[1mp95\x1B[0m: {:.3}ms, \x1B[1mp99\x1B[0m: {:.3}ms",
+            self.frames.len(),
+            summary.p50,
+            summary.p95,
+            summary.p99
+        );
+        match self.threshold_p99 {
+            Some(threshold) if summary.p99 > threshold => {
+                error!(
+                    "benchmark regressed, \x1B[1mp99\x1B[0m: {:.3}ms, \x1B[1mthreshold\x1B[0m: {threshold:.3}ms",
+                    summary.p99
+                );
+                false
+            }
+            _ => true,
+        }
+    }
+
+    // Writes the frames measured so far, if a CSV was requested.
+    pub fn save(&self) {
+        let Some(path) = &self.csv else {
+            return;
+        };
+        match write_csv(path, &self.frames) {
+            Ok(()) => info!("benchmark results saved, \x1B[1mpath\x1B[0m: {}", path.display()),
+            Err(error) => warn!(
+                "failed to save benchmark results, \x1B[1mpath\x1B[0m: {}, \x1B[1merror\x1B[0m: {error}",
+                path.display()
+            ),
+        }
+    }
+}
+
+fn write_csv(path: &Path, frames: &[FrameSample]) -> std::io::Result<()> {
+    let mut file = BufWriter::new(File::create(path)?);
+    write_rows(&mut file, frames)?;
+    file.flush()
+}
+
+// One row per pass, with the frame columns repeated, so that the file can be loaded as a single
+// table. Frames without any timed passes still get a row with the pass columns left empty.
+fn write_rows(out: &mut impl Write, frames: &[FrameSample]) -> std::io::Result<()> {
+    writeln!(
+        out,
+        "frame_index,cpu_frame_time_ms,gpu_frame_time_ms,pass_name,pass_time_ms"
+    )?;
+    for frame in frames {
+        let frame_columns = format!(
+            "{},{:.4},{:.4}",
+            frame.frame_index,
+            milliseconds(frame.cpu_frame_time),
+            milliseconds(frame.gpu_frame_time)
+        );
+        if frame.pass_times.is_empty() {
+            writeln!(out, "{frame_columns},,")?;
+        }
+        for (pass_name, pass_time) in &frame.pass_times {
+            let pass_time = milliseconds(*pass_time);
+            writeln!(out, "{frame_columns},{pass_name},{pass_time:.4}")?;
+        }
+    }
+    Ok(())
+}
+
+fn summarize(frames: &[FrameSample]) -> BenchmarkSummary {
+    let mut frame_times: Vec<f32> = frames
+        .iter()
+        .map(|frame| milliseconds(frame.gpu_frame_time))
+        .collect();
+    frame_times.sort_by(f32::total_cmp);
+    BenchmarkSummary {
+        p50: percentile(&frame_times, 0.50),
+        p95: percentile(&frame_times, 0.95),
+        p99: percentile(&frame_times, 0.99),
+    }
+}
+
+// Nearest-rank percentile of sorted values, so the result is always one of the measured times.
+fn percentile(sorted: &[f32], fraction: f32) -> f32 {
+    if sorted.is_empty() {
+        return 0.;
+    }
+    let rank = (fraction * sorted.len() as f32).ceil() as usize;
+    sorted[rank.clamp(1, sorted.len()) - 1]
+}
+
+fn milliseconds(duration: Duration) -> f32 {
+    duration.as_secs_f32() * 1000.
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn nearest_rank_percentiles() {
+        let sorted: Vec<f32> = (1..=100).map(|value| value as f32).collect();
+        assert_eq!(percentile(&sorted, 0.50), 50.);
+        assert_eq!(percentile(&sorted, 0.99), 99.);
+        assert_eq!(percentile(&sorted[..10], 0.95), 10.);
+        assert_eq!(percentile(&[3.], 0.), 3.);
+        assert_eq!(percentile(&[], 0.5), 0.);
+    }
+
+    #[test]
+    fn warmup_discarded_and_rows_written() {
+        let mut benchmark = Benchmark::new(None, Some(1.5), 2);
+        let pass = [("scene", Duration::from_micros(500))];
+        let cpu_frame_time = |frame: u64| Duration::from_millis(10 + frame);
+        benchmark.record(cpu_frame_time(0), None, &pass);
+        for frame in 1..4 {
+            benchmark.record(cpu_frame_time(frame), Some(Duration::from_millis(2)), &pass);
+        }
+        benchmark.record(cpu_frame_time(4), Some(Duration::from_millis(1)), &[]);
+        assert_eq!(benchmark.frames.len(), 2);
+        assert_eq!(benchmark.frames[0].frame_index, 2);
+
+        // The GPU results lag a frame behind, so the third result belongs to the third frame.
+        let mut csv = Vec::new();
+        write_rows(&mut csv, &benchmark.frames).unwrap();
+        assert_eq!(
+            String::from_utf8(csv).unwrap(),
+            "frame_index,cpu_frame_time_ms,gpu_frame_time_ms,pass_name,pass_time_ms\n\
+             2,12.0000,2.0000,scene,0.5000\n\
+             3,13.0000,1.0000,,\n"
+        );
+        assert!(!benchmark.finish());
+    }
+}
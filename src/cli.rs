@@ -1,29 +1,681 @@
+use crate::benchmark::DEFAULT_BENCHMARK_WARMUP_FRAMES;
+use crate::config::DEFAULT_FRAMES_IN_FLIGHT;
+use crate::config_io::default_config_path;
+use crate::voxel::{random_seed, MAX_SEED};
+use log::error;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use winit::dpi::PhysicalSize;
+
 pub struct Args {
+    // Directory containing assets/ and shaders/, used instead of searching for one.
+    pub assets: Option<PathBuf>,
+    pub benchmark: bool,
+    pub benchmark_csv: Option<PathBuf>,
+    // Spawned around the camera at startup, on top of whatever the world has.
+    pub benchmark_objects: usize,
+    // In milliseconds.
+    pub benchmark_threshold_p99: Option<f32>,
+    pub benchmark_warmup_frames: usize,
     pub disable_validation: bool,
+    // Requested count, the renderer lowers it if the swapchain can't have enough images.
+    pub frames_in_flight: usize,
+    // Renders without a window instead of opening one, if set.
+    pub headless: Option<HeadlessArgs>,
+    pub record: Option<PathBuf>,
+    pub seed: Option<u64>,
+    // Renderer and voxel settings file, which is also where the interface saves them.
+    pub settings: Option<PathBuf>,
+    pub time_scale: Option<f32>,
+    // In bytes, although the flag takes megabytes.
+    pub vram_limit: Option<u64>,
+    // Also enables verbose validation layer messages, which are logged at debug level.
+    pub vulkan_verbose: bool,
+    #[cfg(feature = "dev-menu")]
+    pub watch_shaders: bool,
     pub window_protocol: Option<WindowProtocol>,
 }
 
+pub struct HeadlessArgs {
+    pub frames: usize,
+    // Directory the frames are saved to, as numbered PNGs.
+    pub out: PathBuf,
+    pub resolution: PhysicalSize<u32>,
+}
+
+#[derive(Debug, PartialEq)]
 pub enum WindowProtocol {
     Wayland,
     X11,
 }
 
+struct OptionSpec {
+    name: &'static str,
+    kind: OptionKind,
+    // Only shown in the help, and computed when printing it, as some depend on the environment.
+    default: Option<fn() -> String>,
+    help: &'static str,
+}
+
+#[derive(Clone, Copy)]
+enum OptionKind {
+    Switch,
+    // Placeholder for the value shown in the help.
+    Value(&'static str),
+    // Shorthand for setting another option to a fixed value.
+    Alias(&'static str, &'static str),
+}
+
+// Every option can be given both on the command line and in the file passed to --config, except
+// for --config and --help themselves.
+const OPTIONS: &[OptionSpec] = &[
+    OptionSpec {
+        name: "assets",
+        kind: OptionKind::Value("PATH"),
+        default: None,
+        help: "Directory containing assets/ and shaders/, instead of searching for one",
+    },
+    OptionSpec {
+        name: "benchmark",
+        kind: OptionKind::Switch,
+        default: None,
+        help: "Render a fixed number of frames with a fixed time step and exit, failing if the 99th percentile frame time is above the threshold",
+    },
+    OptionSpec {
+        name: "benchmark-csv",
+        kind: OptionKind::Value("PATH"),
+        default: None,
+        help: "Save the CPU frame times and GPU frame and pass times of the benchmark as CSV",
+    },
+    OptionSpec {
+        name: "benchmark-objects",
+        kind: OptionKind::Value("COUNT"),
+        default: None,
+        help: "Spawn objects in a cube around the camera, for measuring how culling scales",
+    },
+    OptionSpec {
+        name: "benchmark-threshold-p99",
+        kind: OptionKind::Value("MS"),
+        default: None,
+        help: "Fail the benchmark if the 99th percentile frame time is above this",
+    },
+    OptionSpec {
+        name: "benchmark-warmup-frames",
+        kind: OptionKind::Value("COUNT"),
+        default: Some(|| DEFAULT_BENCHMARK_WARMUP_FRAMES.to_string()),
+        help: "Frames rendered before the benchmark starts measuring",
+    },
+    OptionSpec {
+        name: "config",
+        kind: OptionKind::Value("PATH"),
+        default: None,
+        help: "Load options from a KDL file, with one option per line, overridden by the ones given on the command line",
+    },
+    OptionSpec {
+        name: "disable-validation",
+        kind: OptionKind::Switch,
+        default: None,
+        help: "Don't enable the Vulkan validation layers",
+    },
+    OptionSpec {
+        name: "frames-in-flight",
+        kind: OptionKind::Value("COUNT"),
+        default: Some(|| DEFAULT_FRAMES_IN_FLIGHT.to_string()),
+        help: "Frames the CPU can prepare ahead of the GPU, each adding a frame of input latency",
+    },
+    OptionSpec {
+        name: "frames",
+        kind: OptionKind::Value("COUNT"),
+        default: Some(|| DEFAULT_HEADLESS_FRAMES.to_string()),
+        help: "Frames rendered in headless mode",
+    },
+    OptionSpec {
+        name: "headless",
+        kind: OptionKind::Switch,
+        default: None,
+        help: "Render without a window, with a fixed time step, saving every frame to the --out directory and exiting",
+    },
+    OptionSpec {
+        name: "help",
+        kind: OptionKind::Switch,
+        default: None,
+        help: "Print this message and exit",
+    },
+    OptionSpec {
+        name: "out",
+        kind: OptionKind::Value("PATH"),
+        default: None,
+        help: "Directory headless mode saves the frames to, as numbered PNGs",
+    },
+    OptionSpec {
+        name: "record",
+        kind: OptionKind::Value("PATH"),
+        default: None,
+        help: "Start recording as soon as the window opens, also used as the base path for recordings toggled with the hotkey",
+    },
+    OptionSpec {
+        name: "resolution",
+        kind: OptionKind::Value("WIDTHxHEIGHT"),
+        default: Some(|| {
+            format!(
+                "{}x{}",
+                DEFAULT_HEADLESS_RESOLUTION.width, DEFAULT_HEADLESS_RESOLUTION.height
+            )
+        }),
+        help: "Size of the frames rendered in headless mode",
+    },
+    OptionSpec {
+        name: "seed",
+        kind: OptionKind::Value("SEED"),
+        default: None,
+        help: "World seed as an integer or \"random\", instead of the one from the settings",
+    },
+    OptionSpec {
+        name: "settings",
+        kind: OptionKind::Value("PATH"),
+        default: Some(|| default_config_path().display().to_string()),
+        help: "Renderer and voxel settings file, also used for saving them",
+    },
+    OptionSpec {
+        name: "time-scale",
+        kind: OptionKind::Value("SCALE"),
+        default: None,
+        help: "Day-night cycle speed, also unpauses the sun",
+    },
+    OptionSpec {
+        name: "vram-limit",
+        kind: OptionKind::Value("MB"),
+        default: None,
+        help: "Pretend the GPU has only this much memory, to test how voxel streaming copes with running out of it",
+    },
+    OptionSpec {
+        name: "vulkan-verbose",
+        kind: OptionKind::Switch,
+        default: None,
+        help: "Log verbose validation layer messages",
+    },
+    #[cfg(feature = "dev-menu")]
+    OptionSpec {
+        name: "watch-shaders",
+        kind: OptionKind::Switch,
+        default: None,
+        help: "Recompile GLSL shaders when they change and swap in the new pipelines",
+    },
+    OptionSpec {
+        name: "wayland",
+        kind: OptionKind::Alias("window-protocol", "wayland"),
+        default: None,
+        help: "Same as --window-protocol wayland",
+    },
+    OptionSpec {
+        name: "window-protocol",
+        kind: OptionKind::Value("wayland|x11"),
+        default: None,
+        help: "Window system protocol, chosen by winit if not given",
+    },
+    OptionSpec {
+        name: "x11",
+        kind: OptionKind::Alias("window-protocol", "x11"),
+        default: None,
+        help: "Same as --window-protocol x11",
+    },
+];
+
+const DEFAULT_HEADLESS_FRAMES: usize = 1;
+const DEFAULT_HEADLESS_RESOLUTION: PhysicalSize<u32> = PhysicalSize::new(1920, 1080);
+
+// Option values by name, as given on the command line or in the config file, before parsing them
+// into their types. Switches are stored as "true" or "false".
+type RawOptions = BTreeMap<&'static str, String>;
+
 impl Args {
     pub fn parse() -> Args {
-        let wayland = std::env::args().any(|arg| arg == "--wayland");
-        let x11 = std::env::args().any(|arg| arg == "--x11");
-        let window_protocol = if wayland && x11 {
-            panic!("can't specify both --wayland and --x11");
-        } else if wayland {
-            Some(WindowProtocol::Wayland)
-        } else if x11 {
-            Some(WindowProtocol::X11)
+        let command_line: Vec<String> = std::env::args().skip(1).collect();
+        match Args::parse_from(&command_line, |path| std::fs::read_to_string(path)) {
+            Ok(Some(args)) => args,
+            Ok(None) => {
+                print!("{}", help());
+                std::process::exit(0);
+            }
+            Err(error) => {
+                error!("{error}");
+                std::process::exit(2);
+            }
+        }
+    }
+
+    // Returns None if the help was requested, in which case nothing else is checked.
+    fn parse_from(
+        command_line: &[String],
+        read_config: impl FnOnce(&Path) -> std::io::Result<String>,
+    ) -> Result<Option<Args>, String> {
+        let Some(command_line) = parse_command_line(command_line)? else {
+            return Ok(None);
+        };
+        let mut options = match command_line.get("config") {
+            Some(path) => {
+                let text = read_config(Path::new(path))
+                    .map_err(|error| format!("failed to read --config {path}: {error}"))?;
+                parse_config_file(&text).map_err(|error| format!("{path}:{error}"))?
+            }
+            None => RawOptions::new(),
+        };
+        options.extend(command_line);
+        Args::from_options(&options).map(Some)
+    }
+
+    fn from_options(options: &RawOptions) -> Result<Args, String> {
+        // Without the flag, the seed from the settings is used, which is fixed by default. The
+        // effective seed is always logged, so a world generated from "random" can be reproduced.
+        let expected_seed = format!("an integer from 0 to {MAX_SEED}");
+        let seed = parse_value(options, "seed", &expected_seed, |seed| match seed {
+            "random" => Some(random_seed()),
+            _ => seed.parse().ok().filter(|seed| *seed <= MAX_SEED),
+        })?;
+        let window_protocol = parse_value(
+            options,
+            "window-protocol",
+            "wayland or x11",
+            |protocol| match protocol {
+                "wayland" => Some(WindowProtocol::Wayland),
+                "x11" => Some(WindowProtocol::X11),
+                _ => None,
+            },
+        )?;
+        let headless = if parse_switch(options, "headless")? {
+            Some(HeadlessArgs {
+                frames: parse_value(options, "frames", "an unsigned integer", |frames| {
+                    frames.parse().ok()
+                })?
+                .unwrap_or(DEFAULT_HEADLESS_FRAMES),
+                out: parse_path(options, "out")
+                    .ok_or_else(|| "--headless requires --out".to_owned())?,
+                resolution: parse_value(
+                    options,
+                    "resolution",
+                    "a size like 1920x1080",
+                    parse_resolution,
+                )?
+                .unwrap_or(DEFAULT_HEADLESS_RESOLUTION),
+            })
+        } else if let Some(name) = ["frames", "out", "resolution"]
+            .into_iter()
+            .find(|name| options.contains_key(name))
+        {
+            return Err(format!("--{name} requires --headless"));
         } else {
             None
         };
-        Args {
-            disable_validation: std::env::args().any(|arg| arg == "--disable-validation"),
+        Ok(Args {
+            assets: parse_path(options, "assets"),
+            benchmark: parse_switch(options, "benchmark")?,
+            benchmark_csv: parse_path(options, "benchmark-csv"),
+            benchmark_objects: parse_value(
+                options,
+                "benchmark-objects",
+                "an unsigned integer",
+                |count| count.parse().ok(),
+            )?
+            .unwrap_or(0),
+            benchmark_threshold_p99: parse_value(
+                options,
+                "benchmark-threshold-p99",
+                "a time in ms",
+                |threshold| threshold.parse().ok(),
+            )?,
+            benchmark_warmup_frames: parse_value(
+                options,
+                "benchmark-warmup-frames",
+                "an unsigned integer",
+                |warmup_frames| warmup_frames.parse().ok(),
+            )?
+            .unwrap_or(DEFAULT_BENCHMARK_WARMUP_FRAMES),
+            disable_validation: parse_switch(options, "disable-validation")?,
+            frames_in_flight: parse_value(
+                options,
+                "frames-in-flight",
+                "a positive integer",
+                |frames_in_flight| {
+                    frames_in_flight
+                        .parse::<usize>()
+                        .ok()
+                        .filter(|count| *count > 0)
+                },
+            )?
+            .unwrap_or(DEFAULT_FRAMES_IN_FLIGHT),
+            headless,
+            record: parse_path(options, "record"),
+            seed,
+            settings: parse_path(options, "settings"),
+            time_scale: parse_value(options, "time-scale", "a number", |time_scale| {
+                time_scale.parse().ok()
+            })?,
+            vram_limit: parse_value(options, "vram-limit", "a size in MB", |vram_limit| {
+                vram_limit.parse::<u64>().ok()
+            })?
+            .map(|megabytes| megabytes * 1024 * 1024),
+            vulkan_verbose: parse_switch(options, "vulkan-verbose")?,
+            #[cfg(feature = "dev-menu")]
+            watch_shaders: parse_switch(options, "watch-shaders")?,
             window_protocol,
+        })
+    }
+}
+
+// Accepts both "--flag value" and "--flag=value". Returns None as soon as --help is found.
+fn parse_command_line(command_line: &[String]) -> Result<Option<RawOptions>, String> {
+    let mut options = RawOptions::new();
+    let mut args = command_line.iter();
+    while let Some(arg) = args.next() {
+        let Some(flag) = arg.strip_prefix("--") else {
+            return Err(format!("unexpected argument {arg}, {}", valid_flags()));
+        };
+        let (name, inline_value) = match flag.split_once('=') {
+            Some((name, value)) => (name, Some(value)),
+            None => (flag, None),
+        };
+        let spec = find_option(name).ok_or_else(|| unknown_flag(name))?;
+        if spec.name == "help" {
+            return Ok(None);
+        }
+        match spec.kind {
+            OptionKind::Value(_) => {
+                let value = match inline_value {
+                    Some(value) => value,
+                    None => args
+                        .next()
+                        .filter(|value| !value.starts_with("--"))
+                        .ok_or_else(|| format!("--{name} requires a value"))?,
+                };
+                options.insert(spec.name, value.to_owned());
+            }
+            _ if inline_value.is_some() => {
+                return Err(format!("--{name} doesn't take a value"));
+            }
+            OptionKind::Switch => {
+                options.insert(spec.name, "true".to_owned());
+            }
+            OptionKind::Alias(target, value) => {
+                options.insert(target, value.to_owned());
+            }
+        }
+    }
+    Ok(Some(options))
+}
+
+// Only the subset of KDL needed for this is supported, which is one node per line named after the
+// flag, with the value as its only argument. Switches can have a true or false argument, or none
+// at all to enable them.
+fn parse_config_file(text: &str) -> Result<RawOptions, String> {
+    let mut options = RawOptions::new();
+    for (index, line) in text.lines().enumerate() {
+        let line_number = index + 1;
+        let tokens = tokenize_line(line).map_err(|error| format!("{line_number}: {error}"))?;
+        let Some((name, arguments)) = tokens.split_first() else {
+            continue;
+        };
+        let spec = find_option(name)
+            .filter(|spec| spec.name != "config" && spec.name != "help")
+            .ok_or_else(|| format!("{line_number}: unknown option {name}"))?;
+        match (spec.kind, arguments) {
+            (OptionKind::Switch, []) => {
+                options.insert(spec.name, "true".to_owned());
+            }
+            (OptionKind::Switch, [value]) if parse_bool(value).is_some() => {
+                options.insert(spec.name, value.trim_start_matches('#').to_owned());
+            }
+            (OptionKind::Value(_), [value]) => {
+                options.insert(spec.name, value.clone());
+            }
+            (OptionKind::Alias(target, value), []) => {
+                options.insert(target, value.to_owned());
+            }
+            (OptionKind::Value(_), _) => {
+                return Err(format!("{line_number}: {name} requires a single value"));
+            }
+            _ => return Err(format!("{line_number}: {name} doesn't take a value")),
+        }
+    }
+    Ok(options)
+}
+
+// Splits a line into bare words and quoted strings, stopping at a comment.
+fn tokenize_line(line: &str) -> Result<Vec<String>, String> {
+    let mut tokens = Vec::new();
+    let mut chars = line.trim_end_matches(';').chars().peekable();
+    while let Some(&c) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+        } else if chars.clone().take(2).eq("//".chars()) {
+            break;
+        } else if c == '"' {
+            chars.next();
+            let mut token = String::new();
+            loop {
+                match chars.next() {
+                    Some('"') => break,
+                    Some('\\') => match chars.next() {
+                        Some('n') => token.push('\n'),
+                        Some('t') => token.push('\t'),
+                        Some(escaped @ ('"' | '\\' | '/')) => token.push(escaped),
+                        _ => return Err("invalid escape in string".to_owned()),
+                    },
+                    Some(c) => token.push(c),
+                    None => return Err("unterminated string".to_owned()),
+                }
+            }
+            tokens.push(token);
+        } else {
+            let mut token = String::new();
+            while let Some(c) = chars.next_if(|c| !c.is_whitespace()) {
+                token.push(c);
+            }
+            tokens.push(token);
+        }
+    }
+    Ok(tokens)
+}
+
+fn parse_value<T>(
+    options: &RawOptions,
+    name: &str,
+    expected: &str,
+    parse: impl FnOnce(&str) -> Option<T>,
+) -> Result<Option<T>, String> {
+    let Some(value) = options.get(name) else {
+        return Ok(None);
+    };
+    match parse(value) {
+        Some(parsed) => Ok(Some(parsed)),
+        None => Err(format!("--{name} requires {expected}, got {value}")),
+    }
+}
+
+fn parse_switch(options: &RawOptions, name: &str) -> Result<bool, String> {
+    parse_value(options, name, "true or false", parse_bool).map(|value| value.unwrap_or(false))
+}
+
+fn parse_path(options: &RawOptions, name: &str) -> Option<PathBuf> {
+    options.get(name).map(PathBuf::from)
+}
+
+fn parse_resolution(resolution: &str) -> Option<PhysicalSize<u32>> {
+    let (width, height) = resolution.split_once('x')?;
+    let size = PhysicalSize::new(width.parse().ok()?, height.parse().ok()?);
+    (size.width > 0 && size.height > 0).then_some(size)
+}
+
+// KDL 1 writes booleans as bare words, while KDL 2 prefixes them with a hash.
+fn parse_bool(value: &str) -> Option<bool> {
+    match value.trim_start_matches('#') {
+        "true" => Some(true),
+        "false" => Some(false),
+        _ => None,
+    }
+}
+
+fn find_option(name: &str) -> Option<&'static OptionSpec> {
+    OPTIONS.iter().find(|spec| spec.name == name)
+}
+
+fn unknown_flag(name: &str) -> String {
+    format!("unknown flag --{name}, {}", valid_flags())
+}
+
+fn valid_flags() -> String {
+    let flags: Vec<String> = OPTIONS
+        .iter()
+        .map(|spec| format!("--{}", spec.name))
+        .collect();
+    format!("valid flags are {}", flags.join(", "))
+}
+
+fn help() -> String {
+    let usages: Vec<String> = OPTIONS
+        .iter()
+        .map(|spec| match spec.kind {
+            OptionKind::Value(placeholder) => format!("--{} <{placeholder}>", spec.name),
+            _ => format!("--{}", spec.name),
+        })
+        .collect();
+    let width = usages.iter().map(String::len).max().unwrap_or(0);
+    let mut help = "Usage: vulkthing [OPTIONS]\n\nOptions:\n".to_owned();
+    for (spec, usage) in OPTIONS.iter().zip(&usages) {
+        help += &format!("  {usage:width$}  {}", spec.help);
+        if let Some(default) = spec.default {
+            help += &format!(" [default: {}]", default());
+        }
+        help.push('\n');
+    }
+    help
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::ErrorKind;
+
+    fn parse(command_line: &[&str], config: Option<&str>) -> Result<Option<Args>, String> {
+        let command_line: Vec<String> = command_line.iter().map(|arg| arg.to_string()).collect();
+        Args::parse_from(&command_line, |_| {
+            config
+                .map(str::to_owned)
+                .ok_or_else(|| ErrorKind::NotFound.into())
+        })
+    }
+
+    #[test]
+    fn both_value_syntaxes() {
+        let args = parse(
+            &[
+                "--window-protocol=wayland",
+                "--frames-in-flight",
+                "3",
+                "--benchmark",
+            ],
+            None,
+        )
+        .unwrap()
+        .unwrap();
+        assert_eq!(args.window_protocol, Some(WindowProtocol::Wayland));
+        assert_eq!(args.frames_in_flight, 3);
+        assert!(args.benchmark);
+        assert!(!args.disable_validation);
+        assert_eq!(
+            args.benchmark_warmup_frames,
+            DEFAULT_BENCHMARK_WARMUP_FRAMES
+        );
+
+        let args = parse(&["--x11", "--vram-limit=2"], None).unwrap().unwrap();
+        assert_eq!(args.window_protocol, Some(WindowProtocol::X11));
+        assert_eq!(args.vram_limit, Some(2 * 1024 * 1024));
+        assert!(args.headless.is_none());
+    }
+
+    #[test]
+    fn headless() {
+        let args = parse(&["--headless", "--out", "frames/"], None)
+            .unwrap()
+            .unwrap();
+        let headless = args.headless.unwrap();
+        assert_eq!(headless.frames, DEFAULT_HEADLESS_FRAMES);
+        assert_eq!(headless.out, PathBuf::from("frames/"));
+        assert_eq!(headless.resolution, DEFAULT_HEADLESS_RESOLUTION);
+
+        let args = parse(
+            &[
+                "--headless",
+                "--frames=60",
+                "--out=ci",
+                "--resolution=640x360",
+            ],
+            None,
+        )
+        .unwrap()
+        .unwrap();
+        let headless = args.headless.unwrap();
+        assert_eq!(headless.frames, 60);
+        assert_eq!(headless.resolution, PhysicalSize::new(640, 360));
+
+        assert!(parse(&["--headless"], None).is_err());
+        assert!(parse(&["--out", "frames/"], None).is_err());
+        assert!(parse(&["--headless", "--out=ci", "--resolution=640"], None).is_err());
+        assert!(parse(&["--headless", "--out=ci", "--resolution=0x360"], None).is_err());
+    }
+
+    #[test]
+    fn command_line_overrides_config() {
+        let config = r#"
+            // Comments and blank lines are skipped.
+            frames-in-flight 4
+            record "captures/run one.mkv"
+            benchmark
+            disable-validation #false
+            wayland
+        "#;
+        let args = parse(
+            &["--config", "run.kdl", "--frames-in-flight=2", "--x11"],
+            Some(config),
+        )
+        .unwrap()
+        .unwrap();
+        assert_eq!(args.frames_in_flight, 2);
+        assert_eq!(args.window_protocol, Some(WindowProtocol::X11));
+        assert_eq!(args.record, Some(PathBuf::from("captures/run one.mkv")));
+        assert!(args.benchmark);
+        assert!(!args.disable_validation);
+    }
+
+    #[test]
+    fn invalid_arguments() {
+        let error = parse(&["--window_protocol", "wayland"], None)
+            .err()
+            .unwrap();
+        assert!(error.starts_with("unknown flag --window_protocol"));
+        assert!(error.contains("--window-protocol"));
+        assert!(parse(&["wayland"], None).is_err());
+        assert!(parse(&["--seed"], None).is_err());
+        assert!(parse(&["--seed", &(MAX_SEED + 1).to_string()], None).is_err());
+        assert!(parse(&["--record", "--benchmark"], None).is_err());
+        assert!(parse(&["--benchmark=false"], None).is_err());
+        assert!(parse(&["--frames-in-flight=0"], None).is_err());
+        assert!(parse(&["--window-protocol", "windows"], None).is_err());
+        assert!(parse(&["--config", "missing.kdl"], None).is_err());
+        assert!(parse(&["--config=run.kdl"], Some("config other.kdl")).is_err());
+        assert!(parse(&["--config=run.kdl"], Some("seed 1 2")).is_err());
+        assert!(parse(&["--config=run.kdl"], Some("benchmark 1")).is_err());
+        assert!(parse(&["--config=run.kdl"], Some("record \"unterminated")).is_err());
+    }
+
+    #[test]
+    fn help_lists_every_option() {
+        assert!(parse(&["--benchmark", "--help", "--unknown"], None)
+            .unwrap()
+            .is_none());
+        let help = help();
+        for spec in OPTIONS {
+            assert!(help.contains(&format!("--{} ", spec.name)));
         }
+        assert!(help.contains(&format!("[default: {DEFAULT_FRAMES_IN_FLIGHT}]")));
     }
 }
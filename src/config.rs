@@ -1,6 +1,10 @@
 use crate::camera::first_person::FirstPersonCamera;
-use crate::renderer::uniform::Tonemapper;
-use crate::renderer::{PostprocessSettings, RendererSettings, VoxelRendering};
+use crate::renderer::uniform::{Tonemapper, VoxelMaterial};
+use crate::renderer::{
+    AtmosphereQuality, BloomSettings, DofSettings, PassSettings, PostprocessSettings, PresentMode,
+    RendererSettings, ShadowSettings, SkyLightingSettings, SsaoSettings, SsrSettings, TaaSettings,
+    VoxelRendering,
+};
 use crate::voxel::meshing::MeshingAlgorithmKind;
 use crate::voxel::VoxelsConfig;
 use nalgebra::Vector3;
@@ -10,7 +14,12 @@ pub const DEFAULT_SUN_POSITION: Vector3<f32> = Vector3::new(0., 0., DEFAULT_SUN_
 
 pub const DEFAULT_SUN_RADIUS: f32 = 2000.;
 
-pub const DEFAULT_SUN_SPEED: f32 = 0.1;
+pub const DEFAULT_DAY_LENGTH_SECONDS: f32 = 60.;
+
+// Earth's, so that the sun doesn't pass exactly overhead at noon.
+pub const DEFAULT_AXIAL_TILT: f32 = 23.44 * PI / 180.;
+
+pub const DEFAULT_ATMOSPHERE_SCATTERING_STRENGTH: f32 = 0.03;
 
 pub const DEFAULT_STAR_COUNT: usize = 2048;
 pub const DEFAULT_STAR_RADIUS: f32 = 30000.;
@@ -26,6 +35,7 @@ pub const DEFAULT_CAMERA: FirstPersonCamera = FirstPersonCamera {
 
 pub const DEFAULT_RENDERER_SETTINGS: RendererSettings = RendererSettings {
     voxel_rendering: VoxelRendering::RayTracing,
+    atmosphere_quality: AtmosphereQuality::Medium,
     atmosphere_in_scattering_samples: 10,
     atmosphere_optical_depth_samples: 3,
     atmosphere_wavelengths: Vector3::new(700., 530., 440.),
@@ -36,6 +46,122 @@ pub const DEFAULT_RENDERER_SETTINGS: RendererSettings = RendererSettings {
         exposure: 1.,
         tonemapper: Tonemapper::HillAces,
         gamma: 1.,
+        grain_strength: 0.,
+        vignette_strength: 0.,
+        vignette_radius: 0.75,
+        aberration_strength: 0.,
+        color_lut_strength: 0.,
+    },
+    bloom: BloomSettings {
+        threshold: 0.8,
+        knee: 0.1,
+        intensity: 0.5,
+        passes: 1,
+        radius: 8.,
+    },
+    ssao: SsaoSettings {
+        enabled: true,
+        radius: 1.,
+        intensity: 1.,
+    },
+    ssr: SsrSettings {
+        max_steps: 64,
+        step_size: 0.5,
+        thickness: 1.,
+        fade_distance: 32.,
+        enabled: true,
+        debug_overlay: false,
+    },
+    dof: DofSettings {
+        enabled: false,
+        autofocus: true,
+        focus_distance: 16.,
+        aperture: 8.,
+    },
+    taa: TaaSettings {
+        enabled: false,
+        history_weight: 0.9,
+        debug_view: false,
+    },
+    sky_lighting: SkyLightingSettings {
+        enabled: true,
+        intensity: 1.,
+    },
+    color_lut: None,
+    skybox: None,
+    voxel_materials: [
+        // Air, never actually rendered.
+        VoxelMaterial {
+            albedo: Vector3::new(0., 0., 0.),
+            roughness: 0.,
+            emit: Vector3::new(0., 0., 0.),
+            metallic: 0.,
+            flags: 0,
+        },
+        // Stone.
+        VoxelMaterial {
+            albedo: Vector3::new(0.55, 0.6, 0.66),
+            roughness: 1.,
+            emit: Vector3::new(0., 0., 0.),
+            metallic: 0.,
+            flags: 0,
+        },
+        // Dirt.
+        VoxelMaterial {
+            albedo: Vector3::new(0.62, 0.4, 0.24),
+            roughness: 1.,
+            emit: Vector3::new(0., 0., 0.),
+            metallic: 0.,
+            flags: 0,
+        },
+        // Grass.
+        VoxelMaterial {
+            albedo: Vector3::new(0.63, 0.81, 0.42),
+            roughness: 1.,
+            emit: Vector3::new(0., 0., 0.),
+            metallic: 0.,
+            flags: 0,
+        },
+        // Sand.
+        VoxelMaterial {
+            albedo: Vector3::new(0.86, 0.78, 0.55),
+            roughness: 1.,
+            emit: Vector3::new(0., 0., 0.),
+            metallic: 0.,
+            flags: 0,
+        },
+        // Snow.
+        VoxelMaterial {
+            albedo: Vector3::new(0.95, 0.96, 0.98),
+            roughness: 0.7,
+            emit: Vector3::new(0., 0., 0.),
+            metallic: 0.,
+            flags: 0,
+        },
+    ],
+    debug_draw: false,
+    render_scale: 1.,
+    dynamic_resolution: false,
+    target_frame_time_ms: 16.6,
+    min_render_scale: 0.5,
+    max_render_scale: 1.,
+    parallel_recording: true,
+    present_mode: PresentMode::Fifo,
+    gpu_culling: true,
+    depth_prepass: false,
+    time_scale: 1.,
+    shadows: ShadowSettings {
+        enable: true,
+        resolution: 2048,
+        bias: 0.0005,
+        distance: 128.,
+    },
+    passes: PassSettings {
+        shadow: true,
+        scene: true,
+        transparent: true,
+        bloom: true,
+        upscale: true,
     },
 };
 
@@ -45,10 +171,27 @@ pub const DEFAULT_VOXEL_CONFIG: VoxelsConfig = VoxelsConfig {
     heightmap_amplitude: 32.,
     heightmap_frequency: 0.01,
     heightmap_bias: 0.,
+    sand_max_altitude: -12,
+    snow_min_altitude: 20,
+    rock_min_slope: 3,
     render_distance_horizontal: 1024,
     render_distance_vertical: 64,
+    unload_distance_horizontal: 1152,
+    unload_distance_vertical: 128,
+    max_chunks_per_frame: 4,
     meshing_algorithm: MeshingAlgorithmKind::Culled,
+    ambient_occlusion: true,
+    ambient_occlusion_strength: 0.75,
 };
+pub const DEFAULT_DEBUG_LINE_MAX_COUNT: usize = 64 * 1024;
+pub const DEFAULT_FRAMES_IN_FLIGHT: usize = 2;
+// Enough for the --benchmark-objects scenes, the object buffers for it only take a few megabytes.
+pub const DEFAULT_OBJECT_MAX_COUNT: usize = 16 * 1024;
+pub const DEFAULT_PARTICLE_MAX_COUNT: usize = 64 * 1024;
+pub const DEFAULT_PARTICLE_EMIT_MAX_COUNT: usize = 4 * 1024;
+pub const DEFAULT_PARTICLE_GRAVITY: f32 = 9.81;
+pub const DEFAULT_STAGING_RING_SIZE: usize = 16 * 1024 * 1024;
+
 pub const DEFAULT_VOXEL_TRIANGLE_MAX_COUNT: usize = 3 * 256 * DEFAULT_VOXEL_MESHLET_MAX_COUNT;
 pub const DEFAULT_VOXEL_MESHLET_MAX_COUNT: usize = 1024 * 1024;
 pub const DEFAULT_VOXEL_VERTEX_MAX_COUNT: usize = 128 * DEFAULT_VOXEL_MESHLET_MAX_COUNT;
@@ -0,0 +1,268 @@
+use crate::config::{DEFAULT_RENDERER_SETTINGS, DEFAULT_VOXEL_CONFIG};
+use crate::renderer::RendererSettings;
+use crate::voxel::VoxelsConfig;
+use log::warn;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+#[derive(Deserialize, Serialize)]
+struct ConfigFile {
+    renderer: RendererSettings,
+    voxels: VoxelsConfig,
+}
+
+// Settings files are expected to outlive the settings they were saved with, so rather than failing
+// on the first problem, every value from the file is applied over the defaults separately. Values
+// that are missing keep their default, and values that are unknown or don't deserialize are
+// skipped with a warning.
+pub fn load_config(path: &Path) -> (RendererSettings, VoxelsConfig) {
+    let defaults = ConfigFile {
+        renderer: DEFAULT_RENDERER_SETTINGS,
+        voxels: DEFAULT_VOXEL_CONFIG,
+    };
+    let text = match std::fs::read_to_string(path) {
+        Ok(text) => text,
+        Err(error) => {
+            warn!(
+                "failed to read config, \x1B[1mpath\x1B[0m: {}, \x1B[1merror\x1B[0m: {error}",
+                path.display()
+            );
+            return (defaults.renderer, defaults.voxels);
+        }
+    };
+    let file: toml::Table = match text.parse() {
+        Ok(file) => file,
+        Err(error) => {
+            warn!(
+                "failed to parse config, \x1B[1mpath\x1B[0m: {}, \x1B[1merror\x1B[0m: {error}",
+                path.display()
+            );
+            return (defaults.renderer, defaults.voxels);
+        }
+    };
+
+    let mut merged = toml::Table::try_from(&defaults).unwrap();
+    let mut values = Vec::new();
+    collect_values(&file, &mut Vec::new(), &mut values);
+    for (key, value) in values {
+        let mut candidate = merged.clone();
+        if !replace_value(&mut candidate, &key, value) {
+            warn!("unknown config value, \x1B[1mkey\x1B[0m: {}", key.join("."));
+            continue;
+        }
+        let config = match ConfigFile::deserialize(toml::Value::Table(candidate.clone())) {
+            Ok(config) => config,
+            Err(error) => {
+                warn!(
+                    "invalid config value, \x1B[1mkey\x1B[0m: {}, \x1B[1merror\x1B[0m: {error}",
+                    key.join(".")
+                );
+                continue;
+            }
+        };
+        // Unknown keys are silently ignored by serde, so the only way to notice them is to check
+        // whether the key survives a round trip.
+        if !contains_value(&toml::Table::try_from(&config).unwrap(), &key) {
+            warn!("unknown config value, \x1B[1mkey\x1B[0m: {}", key.join("."));
+            continue;
+        }
+        merged = candidate;
+    }
+    let config = ConfigFile::deserialize(toml::Value::Table(merged)).unwrap();
+    (config.renderer, config.voxels)
+}
+
+pub fn save_config(path: &Path, renderer: &RendererSettings, voxels: &VoxelsConfig) {
+    let config = ConfigFile {
+        renderer: renderer.clone(),
+        voxels: voxels.clone(),
+    };
+    let text = match toml::to_string_pretty(&config) {
+        Ok(text) => text,
+        Err(error) => {
+            warn!("failed to serialize config, \x1B[1merror\x1B[0m: {error}");
+            return;
+        }
+    };
+    if let Some(parent) = path.parent() {
+        if let Err(error) = std::fs::create_dir_all(parent) {
+            warn!("failed to create config directory, \x1B[1merror\x1B[0m: {error}");
+        }
+    }
+    if let Err(error) = std::fs::write(path, text) {
+        warn!(
+            "failed to save config, \x1B[1mpath\x1B[0m: {}, \x1B[1merror\x1B[0m: {error}",
+            path.display()
+        );
+    }
+}
+
+// Changes a single value the same way loading the config file does, with the key using the same
+// dotted path as the file, like renderer.depth_far. Values are parsed as TOML, falling back to a
+// plain string so that enum variants and paths don't need quotes.
+#[cfg(feature = "dev-menu")]
+pub fn set_config_value(
+    renderer: &mut RendererSettings,
+    voxels: &mut VoxelsConfig,
+    key: &str,
+    value: &str,
+) -> Result<(), String> {
+    let config = ConfigFile {
+        renderer: renderer.clone(),
+        voxels: voxels.clone(),
+    };
+    let mut table = toml::Table::try_from(&config).unwrap();
+    let key: Vec<String> = key.split('.').map(str::to_owned).collect();
+    let value = match format!("value = {value}").parse::<toml::Table>() {
+        Ok(mut parsed) => parsed.remove("value").unwrap(),
+        Err(_) => toml::Value::String(value.to_owned()),
+    };
+    if !replace_value(&mut table, &key, value) {
+        return Err(format!("unknown config value {}", key.join(".")));
+    }
+    let config =
+        ConfigFile::deserialize(toml::Value::Table(table)).map_err(|error| error.to_string())?;
+    if !contains_value(&toml::Table::try_from(&config).unwrap(), &key) {
+        return Err(format!("unknown config value {}", key.join(".")));
+    }
+    *renderer = config.renderer;
+    *voxels = config.voxels;
+    Ok(())
+}
+
+// Used when no config file was passed on the command line, following the XDG base directory spec.
+// Without HOME there's no good place for it, so it falls back to the working directory.
+pub fn default_config_path() -> PathBuf {
+    let config_home = match std::env::var_os("XDG_CONFIG_HOME") {
+        Some(path) if !path.is_empty() => Some(PathBuf::from(path)),
+        _ => std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")),
+    };
+    match config_home {
+        Some(config_home) => config_home.join("vulkthing").join("settings.toml"),
+        None => PathBuf::from("settings.toml"),
+    }
+}
+
+// Tables are descended into, everything else including arrays is treated as a single value. Arrays
+// only hold things like vectors and material lists, which are always replaced as a whole.
+fn collect_values(
+    table: &toml::Table,
+    key: &mut Vec<String>,
+    out: &mut Vec<(Vec<String>, toml::Value)>,
+) {
+    for (name, value) in table {
+        key.push(name.clone());
+        match value {
+            toml::Value::Table(table) => collect_values(table, key, out),
+            _ => out.push((key.clone(), value.clone())),
+        }
+        key.pop();
+    }
+}
+
+// Optional values that are unset don't appear in the serialized defaults at all, so missing keys
+// are inserted as long as the table containing them exists.
+fn replace_value(table: &mut toml::Table, key: &[String], value: toml::Value) -> bool {
+    let (last, path) = key.split_last().unwrap();
+    let mut table = table;
+    for name in path {
+        let Some(toml::Value::Table(inner)) = table.get_mut(name) else {
+            return false;
+        };
+        table = inner;
+    }
+    table.insert(last.clone(), value);
+    true
+}
+
+fn contains_value(table: &toml::Table, key: &[String]) -> bool {
+    let (last, path) = key.split_last().unwrap();
+    let mut table = table;
+    for name in path {
+        let Some(toml::Value::Table(inner)) = table.get(name) else {
+            return false;
+        };
+        table = inner;
+    }
+    table.contains_key(last)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::voxel::MAX_SEED;
+
+    fn load_text(name: &str, text: &str) -> (RendererSettings, VoxelsConfig) {
+        let path = std::env::temp_dir().join(format!("vulkthing-test-{name}.toml"));
+        std::fs::write(&path, text).unwrap();
+        let config = load_config(&path);
+        std::fs::remove_file(&path).unwrap();
+        config
+    }
+
+    #[test]
+    fn load_partial_keeps_defaults() {
+        let text = "[renderer]\ndepth_far = 5000.0\n\n[renderer.shadows]\nenable = false\n";
+        let (renderer, voxels) = load_text("partial", text);
+        let mut expected = DEFAULT_RENDERER_SETTINGS;
+        expected.depth_far = 5000.;
+        expected.shadows.enable = false;
+        assert_eq!(renderer, expected);
+        assert_eq!(voxels.seed, DEFAULT_VOXEL_CONFIG.seed);
+    }
+
+    #[test]
+    fn load_skips_unknown_and_invalid() {
+        let text = "[renderer]\nnonexistent = 1\ndepth_far = \"far\"\ndepth_near = 0.5\n\n\
+                    [unknown]\nvalue = 1\n\n[voxels]\nseed = -1\nchunk_size = 32\n";
+        let (renderer, voxels) = load_text("invalid", text);
+        let mut expected = DEFAULT_RENDERER_SETTINGS;
+        expected.depth_near = 0.5;
+        assert_eq!(renderer, expected);
+        assert_eq!(voxels.seed, DEFAULT_VOXEL_CONFIG.seed);
+        assert_eq!(voxels.chunk_size, 32);
+    }
+
+    #[test]
+    fn save_and_load_largest_seed() {
+        let path = std::env::temp_dir().join("vulkthing-test-seed.toml");
+        let voxels = VoxelsConfig {
+            seed: MAX_SEED,
+            ..DEFAULT_VOXEL_CONFIG
+        };
+        save_config(&path, &DEFAULT_RENDERER_SETTINGS, &voxels);
+        let (_, loaded) = load_config(&path);
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(loaded.seed, MAX_SEED);
+    }
+
+    #[test]
+    fn load_malformed_or_missing_uses_defaults() {
+        let (renderer, _) = load_text("malformed", "[renderer\ndepth_far = ");
+        assert_eq!(renderer, DEFAULT_RENDERER_SETTINGS);
+        let path = std::env::temp_dir().join("vulkthing-test-missing.toml");
+        let (renderer, _) = load_config(&path);
+        assert_eq!(renderer, DEFAULT_RENDERER_SETTINGS);
+    }
+
+    #[cfg(feature = "dev-menu")]
+    #[test]
+    fn set_value_by_key() {
+        let mut renderer = DEFAULT_RENDERER_SETTINGS;
+        let mut voxels = DEFAULT_VOXEL_CONFIG;
+        set_config_value(&mut renderer, &mut voxels, "renderer.depth_far", "5000").unwrap();
+        set_config_value(&mut renderer, &mut voxels, "voxels.seed", "42").unwrap();
+        assert_eq!(renderer.depth_far, 5000.);
+        assert_eq!(voxels.seed, 42);
+    }
+
+    #[cfg(feature = "dev-menu")]
+    #[test]
+    fn set_value_rejects_unknown_and_invalid() {
+        let mut renderer = DEFAULT_RENDERER_SETTINGS;
+        let mut voxels = DEFAULT_VOXEL_CONFIG;
+        assert!(set_config_value(&mut renderer, &mut voxels, "renderer.nonexistent", "1").is_err());
+        assert!(set_config_value(&mut renderer, &mut voxels, "renderer.depth_far", "far").is_err());
+        assert_eq!(renderer, DEFAULT_RENDERER_SETTINGS);
+    }
+}
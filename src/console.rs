@@ -0,0 +1,249 @@
+use crate::renderer::{Renderer, RendererSettings};
+use crate::voxel::{Voxels, VoxelsConfig};
+use crate::world::World;
+use std::collections::VecDeque;
+use std::path::Path;
+use winit::event_loop::ActiveEventLoop;
+
+mod commands;
+
+// Handled by the console itself rather than registered, as it's the only command that needs to
+// see the registry.
+const HELP_COMMAND: &str = "help";
+
+// Oldest lines are dropped past this, as the scrollback is redrawn in full every frame.
+const SCROLLBACK_LENGTH: usize = 512;
+
+// Commands are registered with the console rather than known by it, so adding one only needs a new
+// implementation of this trait passed to Console::register.
+pub trait ConsoleCommand {
+    fn name(&self) -> &'static str;
+
+    // Arguments shown next to the name by the help command.
+    fn usage(&self) -> &'static str {
+        ""
+    }
+
+    // The returned string is shown in the scrollback either way, split into lines. Errors are only
+    // highlighted differently.
+    fn run(&self, ctx: &mut ConsoleContext, args: &[&str]) -> Result<String, String>;
+}
+
+// Everything the commands are allowed to touch, borrowed from the app state for the duration of a
+// single command.
+pub struct ConsoleContext<'a> {
+    pub world: &'a mut World,
+    pub renderer: &'a mut Renderer,
+    pub renderer_settings: &'a mut RendererSettings,
+    pub voxels: &'a Voxels,
+    pub voxels_config: &'a mut VoxelsConfig,
+    pub config_path: &'a Path,
+    pub event_loop: &'a ActiveEventLoop,
+}
+
+pub struct Console {
+    commands: Vec<Box<dyn ConsoleCommand>>,
+    pub input: String,
+    pub history: History,
+    scrollback: VecDeque<ConsoleLine>,
+    // Set whenever a line is added, so that the interface can scroll down to it.
+    pub scroll_to_bottom: bool,
+}
+
+pub enum ConsoleLine {
+    Input(String),
+    Output(String),
+    Error(String),
+}
+
+pub struct History {
+    entries: Vec<String>,
+    // Entry currently shown in the input while browsing with the arrow keys, or None when editing
+    // a new line.
+    cursor: Option<usize>,
+}
+
+impl Console {
+    pub fn new() -> Console {
+        let mut console = Console {
+            commands: Vec::new(),
+            input: String::new(),
+            history: History::new(),
+            scrollback: VecDeque::new(),
+            scroll_to_bottom: false,
+        };
+        commands::register_builtin(&mut console);
+        console
+    }
+
+    pub fn register(&mut self, command: impl ConsoleCommand + 'static) {
+        assert!(
+            self.find(command.name()).is_none(),
+            "console command registered twice, name: {}",
+            command.name()
+        );
+        self.commands.push(Box::new(command));
+    }
+
+    pub fn execute(&mut self, line: &str, ctx: &mut ConsoleContext) {
+        let line = line.trim();
+        if line.is_empty() {
+            return;
+        }
+        self.history.push(line);
+        self.push(ConsoleLine::Input(line.to_owned()));
+        let mut words = line.split_whitespace();
+        let name = words.next().unwrap();
+        let args: Vec<&str> = words.collect();
+        let result = match self.find(name) {
+            Some(command) => command.run(ctx, &args),
+            None if name == HELP_COMMAND => Ok(self.help()),
+            None => Err(format!("unknown command {name}, try {HELP_COMMAND}")),
+        };
+        match result {
+            Ok(output) => {
+                for line in output.lines() {
+                    self.push(ConsoleLine::Output(line.to_owned()));
+                }
+            }
+            Err(error) => {
+                for line in error.lines() {
+                    self.push(ConsoleLine::Error(line.to_owned()));
+                }
+            }
+        }
+    }
+
+    // Only the command name is completed, as arguments are too different between commands. When
+    // the prefix is ambiguous, it's extended as far as all matches agree and the matches are
+    // listed in the scrollback.
+    pub fn complete(&mut self, input: &str) -> Option<String> {
+        if input.contains(char::is_whitespace) {
+            return None;
+        }
+        let mut matches: Vec<&str> = self
+            .commands()
+            .map(|command| command.name())
+            .chain([HELP_COMMAND])
+            .filter(|name| name.starts_with(input))
+            .collect();
+        matches.sort_unstable();
+        match matches.as_slice() {
+            [] => None,
+            [name] => Some(format!("{name} ")),
+            [first, rest @ ..] => {
+                let common = rest.iter().fold(first.len(), |length, name| {
+                    first
+                        .bytes()
+                        .zip(name.bytes())
+                        .take(length)
+                        .take_while(|(a, b)| a == b)
+                        .count()
+                });
+                let list = matches.join("  ");
+                self.push(ConsoleLine::Output(list));
+                (common > input.len()).then(|| first[..common].to_owned())
+            }
+        }
+    }
+
+    pub fn scrollback(&self) -> impl Iterator<Item = &ConsoleLine> {
+        self.scrollback.iter()
+    }
+
+    fn help(&self) -> String {
+        let lines: Vec<String> = self
+            .commands()
+            .map(|command| {
+                format!("{} {}", command.name(), command.usage())
+                    .trim_end()
+                    .to_owned()
+            })
+            .collect();
+        lines.join("\n")
+    }
+
+    fn commands(&self) -> impl Iterator<Item = &dyn ConsoleCommand> {
+        self.commands.iter().map(|command| command.as_ref())
+    }
+
+    fn find(&self, name: &str) -> Option<&dyn ConsoleCommand> {
+        self.commands().find(|command| command.name() == name)
+    }
+
+    fn push(&mut self, line: ConsoleLine) {
+        if self.scrollback.len() == SCROLLBACK_LENGTH {
+            self.scrollback.pop_front();
+        }
+        self.scrollback.push_back(line);
+        self.scroll_to_bottom = true;
+    }
+}
+
+impl History {
+    fn new() -> History {
+        History {
+            entries: Vec::new(),
+            cursor: None,
+        }
+    }
+
+    // Repeating the previous line doesn't add anything, same as in most shells.
+    fn push(&mut self, line: &str) {
+        if self.entries.last().map(String::as_str) != Some(line) {
+            self.entries.push(line.to_owned());
+        }
+        self.cursor = None;
+    }
+
+    // Stops at the oldest entry rather than wrapping around.
+    pub fn previous(&mut self) -> Option<&str> {
+        let index = match self.cursor {
+            Some(index) => index.saturating_sub(1),
+            None => self.entries.len().checked_sub(1)?,
+        };
+        self.cursor = Some(index);
+        Some(&self.entries[index])
+    }
+
+    // Going past the newest entry returns to an empty line.
+    pub fn next(&mut self) -> Option<&str> {
+        let index = self.cursor? + 1;
+        if index == self.entries.len() {
+            self.cursor = None;
+            return Some("");
+        }
+        self.cursor = Some(index);
+        Some(&self.entries[index])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn complete_unique_and_ambiguous() {
+        let mut console = Console::new();
+        assert_eq!(console.complete("scr").as_deref(), Some("screenshot "));
+        assert_eq!(console.complete("se"), None);
+        let listed = console.scrollback().last();
+        assert!(matches!(listed, Some(ConsoleLine::Output(list)) if list == "seed  set"));
+        assert_eq!(console.complete("xyz"), None);
+        assert_eq!(console.complete("tp 1"), None);
+    }
+
+    #[test]
+    fn history_navigation() {
+        let mut history = History::new();
+        history.push("first");
+        history.push("second");
+        history.push("second");
+        assert_eq!(history.previous(), Some("second"));
+        assert_eq!(history.previous(), Some("first"));
+        assert_eq!(history.previous(), Some("first"));
+        assert_eq!(history.next(), Some("second"));
+        assert_eq!(history.next(), Some(""));
+        assert_eq!(history.next(), None);
+    }
+}
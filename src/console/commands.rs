@@ -0,0 +1,138 @@
+use crate::config_io::{load_config, set_config_value};
+use crate::console::{Console, ConsoleCommand, ConsoleContext};
+use crate::recording::recording_path;
+use nalgebra::Vector3;
+use std::path::Path;
+
+const DEFAULT_SCREENSHOT_PATH: &str = "screenshot.png";
+
+struct Set;
+struct ReloadVoxelConfig;
+struct Teleport;
+struct Seed;
+struct Screenshot;
+struct Quit;
+
+pub fn register_builtin(console: &mut Console) {
+    console.register(Set);
+    console.register(ReloadVoxelConfig);
+    console.register(Teleport);
+    console.register(Seed);
+    console.register(Screenshot);
+    console.register(Quit);
+}
+
+// Renderer settings are checked for changes every frame, so only the voxels have to be told about
+// the new value explicitly.
+impl ConsoleCommand for Set {
+    fn name(&self) -> &'static str {
+        "set"
+    }
+
+    fn usage(&self) -> &'static str {
+        "<key> <value>"
+    }
+
+    fn run(&self, ctx: &mut ConsoleContext, args: &[&str]) -> Result<String, String> {
+        let [key, value @ ..] = args else {
+            return Err(format!("usage: set {}", self.usage()));
+        };
+        if value.is_empty() {
+            return Err(format!("usage: set {}", self.usage()));
+        }
+        let value = value.join(" ");
+        set_config_value(ctx.renderer_settings, ctx.voxels_config, key, &value)?;
+        if key.starts_with("voxels.") {
+            ctx.voxels.update_config(ctx.voxels_config.clone());
+        }
+        Ok(format!("{key} = {value}"))
+    }
+}
+
+// Only the voxel part of the settings file is applied, so that tweaking world generation in an
+// editor doesn't also revert unsaved renderer changes.
+impl ConsoleCommand for ReloadVoxelConfig {
+    fn name(&self) -> &'static str {
+        "voxel.reload_config"
+    }
+
+    fn run(&self, ctx: &mut ConsoleContext, _: &[&str]) -> Result<String, String> {
+        if !ctx.config_path.exists() {
+            return Err(format!("{} doesn't exist", ctx.config_path.display()));
+        }
+        let (_, voxels_config) = load_config(ctx.config_path);
+        *ctx.voxels_config = voxels_config;
+        ctx.voxels.update_config(ctx.voxels_config.clone());
+        Ok(format!("loaded {}", ctx.config_path.display()))
+    }
+}
+
+impl ConsoleCommand for Teleport {
+    fn name(&self) -> &'static str {
+        "tp"
+    }
+
+    fn usage(&self) -> &'static str {
+        "<x> <y> <z>"
+    }
+
+    fn run(&self, ctx: &mut ConsoleContext, args: &[&str]) -> Result<String, String> {
+        let [x, y, z] = args else {
+            return Err(format!("usage: tp {}", self.usage()));
+        };
+        let parse = |value: &str| {
+            value
+                .parse::<f32>()
+                .map_err(|_| format!("{value} is not a number"))
+        };
+        let position = Vector3::new(parse(x)?, parse(y)?, parse(z)?);
+        ctx.world.teleport(position);
+        Ok(format!("teleported to {x} {y} {z}"))
+    }
+}
+
+impl ConsoleCommand for Seed {
+    fn name(&self) -> &'static str {
+        "seed"
+    }
+
+    fn run(&self, ctx: &mut ConsoleContext, _: &[&str]) -> Result<String, String> {
+        Ok(ctx.voxels_config.seed.to_string())
+    }
+}
+
+// Numbered the same way as recordings, but skipping over existing files instead of counting, as
+// screenshots are usually kept around between sessions.
+impl ConsoleCommand for Screenshot {
+    fn name(&self) -> &'static str {
+        "screenshot"
+    }
+
+    fn usage(&self) -> &'static str {
+        "[path]"
+    }
+
+    fn run(&self, ctx: &mut ConsoleContext, args: &[&str]) -> Result<String, String> {
+        let path = match args {
+            [] => (0..)
+                .map(|index| recording_path(Path::new(DEFAULT_SCREENSHOT_PATH), index))
+                .find(|path| !path.exists())
+                .unwrap(),
+            [path] => Path::new(path).to_owned(),
+            _ => return Err(format!("usage: screenshot {}", self.usage())),
+        };
+        ctx.renderer.take_screenshot(&path)?;
+        Ok(format!("saving {}", path.display()))
+    }
+}
+
+impl ConsoleCommand for Quit {
+    fn name(&self) -> &'static str {
+        "quit"
+    }
+
+    fn run(&self, ctx: &mut ConsoleContext, _: &[&str]) -> Result<String, String> {
+        ctx.event_loop.exit();
+        Ok(String::new())
+    }
+}
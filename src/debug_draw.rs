@@ -0,0 +1,150 @@
+use crate::renderer::uniform::DebugVertex;
+use nalgebra::Vector3;
+use std::f32::consts::PI;
+use std::mem::MaybeUninit;
+
+// Collects debug shapes during the frame, so that they can be drawn as a single line list by the
+// renderer. Everything is decomposed into line segments immediately, as this is only used for
+// debugging and the shapes are simple anyway.
+pub struct DebugDraw {
+    lines: Vec<DebugLine>,
+}
+
+#[derive(Clone, Copy)]
+pub struct DebugLine {
+    pub start: Vector3<f32>,
+    pub end: Vector3<f32>,
+    pub color: Vector3<f32>,
+}
+
+impl DebugDraw {
+    pub fn new() -> DebugDraw {
+        DebugDraw { lines: Vec::new() }
+    }
+
+    pub fn clear(&mut self) {
+        self.lines.clear();
+    }
+
+    pub fn lines(&self) -> &[DebugLine] {
+        &self.lines
+    }
+
+    // Lines that don't fit are dropped. Returns the number of lines written, which is half the
+    // vertex count of the draw.
+    pub fn write_vertices(&self, region: &mut [MaybeUninit<DebugVertex>]) -> usize {
+        let count = self.lines.len().min(region.len() / 2);
+        for (line, vertices) in self.lines[..count].iter().zip(region.chunks_exact_mut(2)) {
+            for (vertex, position) in vertices.iter_mut().zip([line.start, line.end]) {
+                vertex.write(DebugVertex {
+                    position,
+                    _pad0: 0.,
+                    color: line.color,
+                    _pad1: 0.,
+                });
+            }
+        }
+        count
+    }
+
+    pub fn add_line(&mut self, start: Vector3<f32>, end: Vector3<f32>, color: Vector3<f32>) {
+        self.lines.push(DebugLine { start, end, color });
+    }
+
+    pub fn add_aabb(&mut self, min: Vector3<f32>, max: Vector3<f32>, color: Vector3<f32>) {
+        let corner = |index: usize| {
+            Vector3::new(
+                if index & 1 != 0 { max.x } else { min.x },
+                if index & 2 != 0 { max.y } else { min.y },
+                if index & 4 != 0 { max.z } else { min.z },
+            )
+        };
+        // Each edge of the box connects two corners that differ in exactly one coordinate.
+        for index in 0..8 {
+            for axis in [1, 2, 4] {
+                if index & axis == 0 {
+                    self.add_line(corner(index), corner(index | axis), color);
+                }
+            }
+        }
+    }
+
+    pub fn add_sphere(
+        &mut self,
+        center: Vector3<f32>,
+        radius: f32,
+        segments: usize,
+        color: Vector3<f32>,
+    ) {
+        let circle_point = |index: usize, axis: usize| {
+            let angle = 2. * PI * index as f32 / segments as f32;
+            let (sin, cos) = angle.sin_cos();
+            let offset = match axis {
+                0 => Vector3::new(cos, sin, 0.),
+                1 => Vector3::new(cos, 0., sin),
+                _ => Vector3::new(0., cos, sin),
+            };
+            center + radius * offset
+        };
+        for axis in 0..3 {
+            for index in 0..segments {
+                self.add_line(
+                    circle_point(index, axis),
+                    circle_point(index + 1, axis),
+                    color,
+                );
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hundred_aabbs() {
+        let mut debug_draw = DebugDraw::new();
+        for i in 0..100 {
+            let min = Vector3::new(i as f32, 0., 0.);
+            debug_draw.add_aabb(min, min + Vector3::repeat(0.5), Vector3::new(1., 0., 0.));
+        }
+        assert_eq!(debug_draw.lines().len(), 1200);
+
+        // Laid out like the renderer's vertex buffer, with the lines written to the region of the
+        // second frame in flight. Vertices that weren't written keep the marker position.
+        let region_size = 2 * 2048;
+        let unwritten = DebugVertex {
+            position: Vector3::repeat(-1.),
+            _pad0: 0.,
+            color: Vector3::zeros(),
+            _pad1: 0.,
+        };
+        let mut buffer = vec![MaybeUninit::new(unwritten); 2 * region_size];
+        let line_count = debug_draw.write_vertices(&mut buffer[region_size..]);
+        let buffer: Vec<_> = buffer.iter().map(|v| unsafe { v.assume_init() }).collect();
+        let written = buffer.iter().filter(|v| v.position != unwritten.position);
+        assert_eq!(written.count(), 2400);
+
+        // Everything is drawn with a single draw of two vertices per line, starting at the region.
+        assert_eq!(line_count, 1200);
+        let vertices = &buffer[region_size..region_size + 2 * line_count];
+        for (line, vertices) in debug_draw.lines().iter().zip(vertices.chunks_exact(2)) {
+            assert_eq!(vertices[0].position, line.start);
+            assert_eq!(vertices[1].position, line.end);
+            assert_eq!(vertices[0].color, Vector3::new(1., 0., 0.));
+        }
+        assert_eq!(
+            vertices.last().unwrap().position,
+            Vector3::new(99.5, 0.5, 0.5)
+        );
+    }
+
+    #[test]
+    fn lines_past_capacity_are_dropped() {
+        let mut debug_draw = DebugDraw::new();
+        debug_draw.add_aabb(Vector3::zeros(), Vector3::repeat(1.), Vector3::zeros());
+        let mut region = vec![MaybeUninit::uninit(); 2 * 5];
+        assert_eq!(debug_draw.write_vertices(&mut region), 5);
+    }
+}
@@ -0,0 +1,174 @@
+use crate::renderer::RendererSettings;
+use std::collections::VecDeque;
+
+// Frames averaged before lowering the render scale. Only a few, so that a sudden drop in frame rate
+// is dealt with before it's noticeable.
+const DOWNSCALE_FRAMES: usize = 10;
+
+// Frames averaged before raising the render scale. More than for lowering, so that a short run of
+// fast frames doesn't make the scale oscillate.
+const UPSCALE_FRAMES: usize = 60;
+
+// The scale changes by a twentieth at a time.
+const SCALE_STEPS: f32 = 20.;
+
+// Adjusts the render scale to keep frame times near the target. Changing the scale only recreates
+// the render targets, not the swapchain, so it's cheap enough to do while playing.
+pub struct DynamicResolution {
+    // Frame times in milliseconds since the last change, oldest first.
+    frame_times: VecDeque<f32>,
+    trend: ScaleTrend,
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum ScaleTrend {
+    Lowering,
+    Steady,
+    Raising,
+}
+
+impl DynamicResolution {
+    pub fn new() -> DynamicResolution {
+        DynamicResolution {
+            frame_times: VecDeque::new(),
+            trend: ScaleTrend::Steady,
+        }
+    }
+
+    #[cfg_attr(not(feature = "dev-menu"), allow(dead_code))]
+    pub fn trend(&self) -> ScaleTrend {
+        self.trend
+    }
+
+    // Called once per frame. Frame times measured before a change say nothing about the new scale,
+    // so the history starts over after every change.
+    pub fn update(&mut self, frame_time_ms: f32, settings: &mut RendererSettings) {
+        if !settings.dynamic_resolution {
+            self.frame_times.clear();
+            self.trend = ScaleTrend::Steady;
+            return;
+        }
+        if self.frame_times.len() == UPSCALE_FRAMES {
+            self.frame_times.pop_front();
+        }
+        self.frame_times.push_back(frame_time_ms);
+        let target = settings.target_frame_time_ms;
+        let scale = settings.render_scale;
+        let new_scale = if self.frame_times.len() >= DOWNSCALE_FRAMES
+            && average(self.frame_times.iter().rev().take(DOWNSCALE_FRAMES)) > target * 1.1
+        {
+            round_to_step(scale - 1. / SCALE_STEPS).max(settings.min_render_scale)
+        } else if self.frame_times.len() == UPSCALE_FRAMES
+            && average(self.frame_times.iter()) < target * 0.9
+        {
+            round_to_step(scale + 1. / SCALE_STEPS).min(settings.max_render_scale)
+        } else {
+            scale
+        };
+        if new_scale < scale {
+            self.trend = ScaleTrend::Lowering;
+        } else if new_scale > scale {
+            self.trend = ScaleTrend::Raising;
+        } else {
+            // A full window without a change, either because frame times are within the target or
+            // because the scale hit one of its limits.
+            if self.frame_times.len() == UPSCALE_FRAMES {
+                self.trend = ScaleTrend::Steady;
+            }
+            return;
+        }
+        settings.render_scale = new_scale;
+        self.frame_times.clear();
+    }
+}
+
+fn average<'a>(frame_times: impl ExactSizeIterator<Item = &'a f32>) -> f32 {
+    let count = frame_times.len();
+    frame_times.sum::<f32>() / count as f32
+}
+
+// Keeps the scale on multiples of the step, so that floating point errors don't pile up.
+fn round_to_step(scale: f32) -> f32 {
+    (scale * SCALE_STEPS).round() / SCALE_STEPS
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::DEFAULT_RENDERER_SETTINGS;
+
+    fn settings() -> RendererSettings {
+        RendererSettings {
+            dynamic_resolution: true,
+            target_frame_time_ms: 10.,
+            min_render_scale: 0.5,
+            max_render_scale: 1.,
+            ..DEFAULT_RENDERER_SETTINGS
+        }
+    }
+
+    #[test]
+    fn slow_frames_lower_scale() {
+        let mut settings = settings();
+        let mut dynamic = DynamicResolution::new();
+        for _ in 0..DOWNSCALE_FRAMES - 1 {
+            dynamic.update(20., &mut settings);
+        }
+        assert_eq!(settings.render_scale, 1.);
+        dynamic.update(20., &mut settings);
+        assert_eq!(settings.render_scale, 0.95);
+        assert_eq!(dynamic.trend(), ScaleTrend::Lowering);
+        for _ in 0..100 * DOWNSCALE_FRAMES {
+            dynamic.update(20., &mut settings);
+        }
+        assert_eq!(settings.render_scale, 0.5);
+        assert_eq!(dynamic.trend(), ScaleTrend::Steady);
+    }
+
+    #[test]
+    fn fast_frames_raise_scale() {
+        let mut settings = RendererSettings {
+            render_scale: 0.5,
+            ..settings()
+        };
+        let mut dynamic = DynamicResolution::new();
+        for _ in 0..UPSCALE_FRAMES - 1 {
+            dynamic.update(5., &mut settings);
+        }
+        assert_eq!(settings.render_scale, 0.5);
+        dynamic.update(5., &mut settings);
+        assert_eq!(settings.render_scale, 0.55);
+        assert_eq!(dynamic.trend(), ScaleTrend::Raising);
+        for _ in 0..100 * UPSCALE_FRAMES {
+            dynamic.update(5., &mut settings);
+        }
+        assert_eq!(settings.render_scale, 1.);
+    }
+
+    #[test]
+    fn frames_near_target_keep_scale() {
+        let mut settings = RendererSettings {
+            render_scale: 0.75,
+            ..settings()
+        };
+        let mut dynamic = DynamicResolution::new();
+        for _ in 0..10 * UPSCALE_FRAMES {
+            dynamic.update(10.5, &mut settings);
+        }
+        assert_eq!(settings.render_scale, 0.75);
+        assert_eq!(dynamic.trend(), ScaleTrend::Steady);
+    }
+
+    #[test]
+    fn disabled_keeps_scale() {
+        let mut settings = RendererSettings {
+            dynamic_resolution: false,
+            ..settings()
+        };
+        let mut dynamic = DynamicResolution::new();
+        for _ in 0..10 * UPSCALE_FRAMES {
+            dynamic.update(100., &mut settings);
+        }
+        assert_eq!(settings.render_scale, 1.);
+    }
+}
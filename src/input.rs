@@ -1,5 +1,8 @@
+use crate::input::bindings::{key_bindings_path, Action, KeyBindings};
 use winit::event::{ElementState, KeyEvent};
-use winit::keyboard::{Key, NamedKey};
+use winit::keyboard::{KeyCode, PhysicalKey};
+
+pub mod bindings;
 
 pub struct InputState {
     left_pressed: bool,
@@ -10,9 +13,18 @@ pub struct InputState {
     roll_neg_pressed: bool,
     jump: Click,
     sprint: bool,
+    toggle_physics_mode: Click,
+    toggle_recording: Click,
+    capture_frame: Click,
+    toggle_console: Click,
+    switch_session: Click,
     mouse_dx: f32,
     mouse_dy: f32,
     pub camera_lock: bool,
+    // The console takes over the keyboard while open, leaving only its own key working.
+    pub console_open: bool,
+    pub bindings: KeyBindings,
+    pub rebinding: Option<Action>,
 }
 
 #[derive(Default)]
@@ -32,27 +44,70 @@ impl InputState {
             roll_neg_pressed: false,
             jump: Click::default(),
             sprint: false,
+            toggle_physics_mode: Click::default(),
+            toggle_recording: Click::default(),
+            capture_frame: Click::default(),
+            toggle_console: Click::default(),
+            switch_session: Click::default(),
             mouse_dx: 0.,
             mouse_dy: 0.,
             camera_lock: false,
+            console_open: false,
+            bindings: KeyBindings::load(key_bindings_path()),
+            rebinding: None,
         }
     }
 
     pub fn apply_keyboard(&mut self, input: KeyEvent) {
-        match input.logical_key {
-            Key::Character(chr) => match chr.as_str() {
-                "w" => self.forward_pressed = input.state == ElementState::Pressed,
-                "a" => self.left_pressed = input.state == ElementState::Pressed,
-                "s" => self.backward_pressed = input.state == ElementState::Pressed,
-                "d" => self.right_pressed = input.state == ElementState::Pressed,
-                "q" => self.roll_neg_pressed = input.state == ElementState::Pressed,
-                "e" => self.roll_pos_pressed = input.state == ElementState::Pressed,
-                "f" => self.camera_lock = input.state == ElementState::Pressed,
-                _ => (),
-            },
-            Key::Named(NamedKey::Space) => self.jump.apply(input.state),
-            Key::Named(NamedKey::Shift) => self.sprint = input.state == ElementState::Pressed,
-            _ => (),
+        let PhysicalKey::Code(key) = input.physical_key else {
+            return;
+        };
+        self.apply_key(key, input.state);
+    }
+
+    // Split from apply_keyboard, as KeyEvent can't be constructed outside of winit.
+    fn apply_key(&mut self, key: KeyCode, state: ElementState) {
+        let pressed = state == ElementState::Pressed;
+        // Releases still go through while waiting for a new key, so that the key held to unlock
+        // the cursor doesn't get stuck.
+        if let Some(action) = self.rebinding {
+            if pressed {
+                if KeyBindings::is_supported(key) {
+                    self.bindings.set(action, key);
+                    self.bindings.save(key_bindings_path());
+                    self.rebinding = None;
+                }
+                return;
+            }
+        }
+        let action = self.bindings.action(key);
+        if self.console_open && action != Some(Action::ToggleConsole) {
+            return;
+        }
+        match action {
+            Some(Action::Forward) => self.forward_pressed = pressed,
+            Some(Action::Backward) => self.backward_pressed = pressed,
+            Some(Action::Left) => self.left_pressed = pressed,
+            Some(Action::Right) => self.right_pressed = pressed,
+            Some(Action::RollPositive) => self.roll_pos_pressed = pressed,
+            Some(Action::RollNegative) => self.roll_neg_pressed = pressed,
+            Some(Action::Jump) => self.jump.apply(state),
+            Some(Action::Sprint) => self.sprint = pressed,
+            Some(Action::TogglePhysicsMode) => self.toggle_physics_mode.apply(state),
+            Some(Action::CameraLock) => self.camera_lock = pressed,
+            Some(Action::ToggleRecording) => self.toggle_recording.apply(state),
+            Some(Action::CaptureFrame) => self.capture_frame.apply(state),
+            Some(Action::SwitchSession) => self.switch_session.apply(state),
+            // The console is drawn by the interface, so there's nothing to open without it.
+            Some(Action::ToggleConsole) if cfg!(feature = "dev-menu") => {
+                if pressed && !self.toggle_console.pressed {
+                    self.console_open = !self.console_open;
+                    // Releases of keys held while opening would go to the console instead.
+                    self.release_all();
+                }
+                self.toggle_console.apply(state);
+            }
+            Some(Action::ToggleConsole) | None => (),
         }
     }
 
@@ -61,10 +116,34 @@ impl InputState {
         self.mouse_dy = delta.1 as f32;
     }
 
+    // Key releases aren't delivered once the window loses focus, so if the user alt-tabs while
+    // holding a key it would stay pressed forever. Forgetting about all held keys in that case is
+    // the only reasonable option.
+    pub fn release_all(&mut self) {
+        self.left_pressed = false;
+        self.right_pressed = false;
+        self.forward_pressed = false;
+        self.backward_pressed = false;
+        self.roll_pos_pressed = false;
+        self.roll_neg_pressed = false;
+        self.jump.pressed = false;
+        self.sprint = false;
+        self.toggle_physics_mode.pressed = false;
+        self.toggle_recording.pressed = false;
+        self.capture_frame.pressed = false;
+        self.toggle_console.pressed = false;
+        self.switch_session.pressed = false;
+        self.camera_lock = false;
+    }
+
     pub fn reset_after_frame(&mut self) {
         self.mouse_dx = 0.;
         self.mouse_dy = 0.;
         self.jump.queued_count = 0;
+        self.toggle_physics_mode.queued_count = 0;
+        self.toggle_recording.queued_count = 0;
+        self.capture_frame.queued_count = 0;
+        self.switch_session.queued_count = 0;
     }
 
     pub fn movement_horizontal(&self) -> f32 {
@@ -97,8 +176,26 @@ impl InputState {
         self.sprint
     }
 
+    pub fn physics_mode_toggles(&self) -> usize {
+        self.toggle_physics_mode.queued_count
+    }
+
+    pub fn recording_toggles(&self) -> usize {
+        self.toggle_recording.queued_count
+    }
+
+    pub fn session_switches(&self) -> usize {
+        self.switch_session.queued_count
+    }
+
+    // Only handled when built with RenderDoc support, otherwise the key does nothing.
+    #[cfg_attr(not(feature = "renderdoc"), allow(dead_code))]
+    pub fn frame_captures(&self) -> usize {
+        self.capture_frame.queued_count
+    }
+
     pub fn camera_yaw(&self) -> f32 {
-        if !self.camera_lock {
+        if !self.camera_lock && !self.console_open {
             self.mouse_dx
         } else {
             0.
@@ -106,7 +203,7 @@ impl InputState {
     }
 
     pub fn camera_pitch(&self) -> f32 {
-        if !self.camera_lock {
+        if !self.camera_lock && !self.console_open {
             self.mouse_dy
         } else {
             0.
@@ -136,3 +233,51 @@ impl Click {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn focus_loss_releases_held_keys() {
+        let mut input = InputState::new();
+        input.bindings = KeyBindings::new();
+        input.apply_key(KeyCode::KeyW, ElementState::Pressed);
+        input.apply_key(KeyCode::KeyD, ElementState::Pressed);
+        input.apply_key(KeyCode::ShiftRight, ElementState::Pressed);
+        input.apply_key(KeyCode::KeyF, ElementState::Pressed);
+        assert_eq!(input.movement_depth(), 1.);
+        assert!(input.movement_sprint());
+        input.release_all();
+        assert_eq!(input.movement_depth(), 0.);
+        assert_eq!(input.movement_horizontal(), 0.);
+        assert!(!input.movement_sprint());
+        assert!(!input.camera_lock);
+    }
+
+    #[test]
+    fn click_after_focus_loss_counts_again() {
+        let mut input = InputState::new();
+        input.bindings = KeyBindings::new();
+        input.apply_key(KeyCode::Space, ElementState::Pressed);
+        input.release_all();
+        input.apply_key(KeyCode::Space, ElementState::Pressed);
+        assert_eq!(input.movement_jumps(), 2);
+    }
+
+    #[cfg(feature = "dev-menu")]
+    #[test]
+    fn console_suppresses_movement() {
+        let mut input = InputState::new();
+        input.bindings = KeyBindings::new();
+        input.apply_key(KeyCode::KeyW, ElementState::Pressed);
+        input.apply_key(KeyCode::Backquote, ElementState::Pressed);
+        input.apply_key(KeyCode::Backquote, ElementState::Released);
+        assert!(input.console_open);
+        assert_eq!(input.movement_depth(), 0.);
+        input.apply_key(KeyCode::KeyW, ElementState::Pressed);
+        assert_eq!(input.movement_depth(), 0.);
+        input.apply_key(KeyCode::Backquote, ElementState::Pressed);
+        assert!(!input.console_open);
+    }
+}
@@ -0,0 +1,313 @@
+use crate::config_io::default_config_path;
+use log::{debug, warn};
+use std::path::{Path, PathBuf};
+use winit::keyboard::KeyCode;
+
+const KEY_BINDINGS_FILE: &str = "controls.txt";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Action {
+    Forward,
+    Backward,
+    Left,
+    Right,
+    RollPositive,
+    RollNegative,
+    Jump,
+    Sprint,
+    TogglePhysicsMode,
+    CameraLock,
+    ToggleRecording,
+    CaptureFrame,
+    ToggleConsole,
+    SwitchSession,
+}
+
+// Every action can have several keys, mostly so that modifiers work on both sides of the keyboard.
+// Rebinding an action from the interface replaces all of them with the single key pressed.
+#[derive(Clone)]
+pub struct KeyBindings {
+    keys: [Vec<KeyCode>; Action::ALL.len()],
+}
+
+// Only keys listed here can be saved to and loaded from the config file. KeyCode doesn't implement
+// FromStr, and pulling in serde just for this seemed excessive, so the names are just matched
+// against the Debug representation.
+const SUPPORTED_KEYS: &[KeyCode] = &[
+    KeyCode::KeyA,
+    KeyCode::KeyB,
+    KeyCode::KeyC,
+    KeyCode::KeyD,
+    KeyCode::KeyE,
+    KeyCode::KeyF,
+    KeyCode::KeyG,
+    KeyCode::KeyH,
+    KeyCode::KeyI,
+    KeyCode::KeyJ,
+    KeyCode::KeyK,
+    KeyCode::KeyL,
+    KeyCode::KeyM,
+    KeyCode::KeyN,
+    KeyCode::KeyO,
+    KeyCode::KeyP,
+    KeyCode::KeyQ,
+    KeyCode::KeyR,
+    KeyCode::KeyS,
+    KeyCode::KeyT,
+    KeyCode::KeyU,
+    KeyCode::KeyV,
+    KeyCode::KeyW,
+    KeyCode::KeyX,
+    KeyCode::KeyY,
+    KeyCode::KeyZ,
+    KeyCode::Digit0,
+    KeyCode::Digit1,
+    KeyCode::Digit2,
+    KeyCode::Digit3,
+    KeyCode::Digit4,
+    KeyCode::Digit5,
+    KeyCode::Digit6,
+    KeyCode::Digit7,
+    KeyCode::Digit8,
+    KeyCode::Digit9,
+    KeyCode::Space,
+    KeyCode::Tab,
+    KeyCode::Backquote,
+    KeyCode::ShiftLeft,
+    KeyCode::ShiftRight,
+    KeyCode::ControlLeft,
+    KeyCode::ControlRight,
+    KeyCode::AltLeft,
+    KeyCode::AltRight,
+    KeyCode::ArrowUp,
+    KeyCode::ArrowDown,
+    KeyCode::ArrowLeft,
+    KeyCode::ArrowRight,
+    KeyCode::F1,
+    KeyCode::F2,
+    KeyCode::F3,
+    KeyCode::F4,
+    KeyCode::F5,
+    KeyCode::F6,
+    KeyCode::F7,
+    KeyCode::F8,
+    KeyCode::F9,
+    KeyCode::F10,
+    KeyCode::F11,
+    KeyCode::F12,
+];
+
+impl Action {
+    pub const ALL: [Action; 14] = [
+        Action::Forward,
+        Action::Backward,
+        Action::Left,
+        Action::Right,
+        Action::RollPositive,
+        Action::RollNegative,
+        Action::Jump,
+        Action::Sprint,
+        Action::TogglePhysicsMode,
+        Action::CameraLock,
+        Action::ToggleRecording,
+        Action::CaptureFrame,
+        Action::ToggleConsole,
+        Action::SwitchSession,
+    ];
+
+    #[cfg(feature = "dev-menu")]
+    pub fn label(&self) -> &'static str {
+        match self {
+            Action::Forward => "Forward",
+            Action::Backward => "Backward",
+            Action::Left => "Left",
+            Action::Right => "Right",
+            Action::RollPositive => "Roll right",
+            Action::RollNegative => "Roll left",
+            Action::Jump => "Jump",
+            Action::Sprint => "Sprint",
+            Action::TogglePhysicsMode => "Toggle walking",
+            Action::CameraLock => "Camera lock",
+            Action::ToggleRecording => "Toggle recording",
+            Action::CaptureFrame => "Capture frame",
+            Action::ToggleConsole => "Toggle console",
+            Action::SwitchSession => "Switch session",
+        }
+    }
+
+    fn config_name(&self) -> &'static str {
+        match self {
+            Action::Forward => "forward",
+            Action::Backward => "backward",
+            Action::Left => "left",
+            Action::Right => "right",
+            Action::RollPositive => "roll_positive",
+            Action::RollNegative => "roll_negative",
+            Action::Jump => "jump",
+            Action::Sprint => "sprint",
+            Action::TogglePhysicsMode => "toggle_physics_mode",
+            Action::CameraLock => "camera_lock",
+            Action::ToggleRecording => "toggle_recording",
+            Action::CaptureFrame => "capture_frame",
+            Action::ToggleConsole => "toggle_console",
+            Action::SwitchSession => "switch_session",
+        }
+    }
+
+    fn index(&self) -> usize {
+        Action::ALL
+            .iter()
+            .position(|action| action == self)
+            .unwrap()
+    }
+}
+
+impl KeyBindings {
+    pub fn new() -> KeyBindings {
+        let mut bindings = KeyBindings {
+            keys: std::array::from_fn(|_| Vec::new()),
+        };
+        bindings.set(Action::Forward, KeyCode::KeyW);
+        bindings.set(Action::Backward, KeyCode::KeyS);
+        bindings.set(Action::Left, KeyCode::KeyA);
+        bindings.set(Action::Right, KeyCode::KeyD);
+        bindings.set(Action::RollPositive, KeyCode::KeyE);
+        bindings.set(Action::RollNegative, KeyCode::KeyQ);
+        bindings.set(Action::Jump, KeyCode::Space);
+        bindings.set_all(Action::Sprint, &[KeyCode::ShiftLeft, KeyCode::ShiftRight]);
+        bindings.set(Action::TogglePhysicsMode, KeyCode::KeyG);
+        bindings.set(Action::CameraLock, KeyCode::KeyF);
+        bindings.set(Action::ToggleRecording, KeyCode::KeyR);
+        bindings.set(Action::CaptureFrame, KeyCode::F9);
+        bindings.set(Action::ToggleConsole, KeyCode::Backquote);
+        bindings.set(Action::SwitchSession, KeyCode::Tab);
+        bindings
+    }
+
+    // A missing or broken config file shouldn't prevent the game from starting, so any problems
+    // are only logged and the default binding is used for the affected actions.
+    pub fn load(path: impl AsRef<Path>) -> KeyBindings {
+        let mut bindings = KeyBindings::new();
+        let Ok(config) = std::fs::read_to_string(path.as_ref()) else {
+            debug!(
+                "key bindings not found, \x1B[1mpath\x1B[0m: {}",
+                path.as_ref().display()
+            );
+            return bindings;
+        };
+        for line in config.lines() {
+            let line = line.trim();
+            if line.is_empty() {
+                continue;
+            }
+            let Some((name, keys)) = line.split_once('=') else {
+                warn!("malformed key binding, \x1B[1mline\x1B[0m: {line}");
+                continue;
+            };
+            let name = name.trim();
+            let Some(action) = Action::ALL.iter().find(|a| a.config_name() == name) else {
+                warn!("unknown key binding action, \x1B[1maction\x1B[0m: {name}");
+                continue;
+            };
+            let mut parsed = Vec::new();
+            for key in keys.split(',') {
+                let key = key.trim();
+                let Some(key) = SUPPORTED_KEYS.iter().find(|k| key_name(**k) == key) else {
+                    warn!("unsupported key binding key, \x1B[1mkey\x1B[0m: {key}");
+                    continue;
+                };
+                parsed.push(*key);
+            }
+            // Keep the default rather than leaving the action without any key.
+            if !parsed.is_empty() {
+                bindings.set_all(*action, &parsed);
+            }
+        }
+        bindings
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) {
+        let mut config = String::new();
+        for action in Action::ALL {
+            config += &format!("{} = {}\n", action.config_name(), self.names(action));
+        }
+        if let Some(parent) = path.as_ref().parent() {
+            if let Err(error) = std::fs::create_dir_all(parent) {
+                warn!("failed to create config directory, \x1B[1merror\x1B[0m: {error}");
+            }
+        }
+        if let Err(error) = std::fs::write(path.as_ref(), config) {
+            warn!("failed to save key bindings, \x1B[1merror\x1B[0m: {error}");
+        }
+    }
+
+    pub fn get(&self, action: Action) -> &[KeyCode] {
+        &self.keys[action.index()]
+    }
+
+    pub fn set(&mut self, action: Action, key: KeyCode) {
+        self.set_all(action, &[key]);
+    }
+
+    pub fn set_all(&mut self, action: Action, keys: &[KeyCode]) {
+        self.keys[action.index()] = keys.to_vec();
+    }
+
+    pub fn names(&self, action: Action) -> String {
+        let names: Vec<_> = self.get(action).iter().map(|key| key_name(*key)).collect();
+        names.join(", ")
+    }
+
+    pub fn action(&self, key: KeyCode) -> Option<Action> {
+        Action::ALL
+            .into_iter()
+            .find(|action| self.get(*action).contains(&key))
+    }
+
+    pub fn is_supported(key: KeyCode) -> bool {
+        SUPPORTED_KEYS.contains(&key)
+    }
+}
+
+// Kept next to the settings file, so that both end up in the same place regardless of where the game
+// was started from.
+pub fn key_bindings_path() -> PathBuf {
+    default_config_path().with_file_name(KEY_BINDINGS_FILE)
+}
+
+fn key_name(key: KeyCode) -> String {
+    format!("{key:?}")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sprint_defaults_to_both_shifts() {
+        let bindings = KeyBindings::new();
+        assert_eq!(bindings.action(KeyCode::ShiftLeft), Some(Action::Sprint));
+        assert_eq!(bindings.action(KeyCode::ShiftRight), Some(Action::Sprint));
+    }
+
+    #[test]
+    fn load_several_keys() {
+        let path = std::env::temp_dir().join("vulkthing-test-controls.txt");
+        std::fs::write(&path, "jump = KeyJ, KeyK\nsprint = Nonexistent\n").unwrap();
+        let bindings = KeyBindings::load(&path);
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(bindings.get(Action::Jump), [KeyCode::KeyJ, KeyCode::KeyK]);
+        assert_eq!(
+            bindings.get(Action::Sprint),
+            [KeyCode::ShiftLeft, KeyCode::ShiftRight]
+        );
+    }
+
+    #[test]
+    fn rebinding_replaces_all_keys() {
+        let mut bindings = KeyBindings::new();
+        bindings.set(Action::Sprint, KeyCode::ControlLeft);
+        assert_eq!(bindings.action(KeyCode::ShiftRight), None);
+        assert_eq!(bindings.names(Action::Sprint), "ControlLeft");
+    }
+}
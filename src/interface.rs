@@ -1,14 +1,43 @@
-use crate::renderer::{PostprocessSettings, RendererSettings};
-use crate::voxel::VoxelsConfig;
+use crate::config::{
+    DEFAULT_ATMOSPHERE_SCATTERING_STRENGTH, DEFAULT_RENDERER_SETTINGS, DEFAULT_VOXEL_CONFIG,
+};
+use crate::console::Console;
+use crate::dynamic_resolution::ScaleTrend;
+use crate::input::bindings::Action;
+use crate::input::InputState;
+use crate::interface::chunk_inspector::ChunkInspector;
+use crate::interface::console::build_console;
+use crate::particles::EmitSettings;
+use crate::renderer::uniform::{PointLight, VoxelMaterial};
+use crate::renderer::util::{MemoryStats, MEMORY_BUDGET_CRITICAL, MEMORY_BUDGET_WARNING};
+use crate::renderer::{
+    AtmosphereQuality, BloomSettings, DofSettings, PostprocessSettings, RendererSettings,
+    RendererStats, SkyLightingSettings, SsaoSettings, SsrSettings, TaaSettings, VoxelRendering,
+    BLOOM_MAX_PASSES,
+};
+use crate::voxel::material::{VoxelMaterialId, MATERIAL_COUNT};
+use crate::voxel::{
+    random_seed, ChunkTimingStats, VoxelsConfig, VoxelsHealth, VoxelsStats, MAX_SEED,
+};
 use crate::world::World;
 use ash::vk;
-use imgui::{Condition, Context, Drag, SliderFlags, TreeNodeFlags, Ui};
+use imgui::{Condition, Context, Direction, Drag, ProgressBar, SliderFlags, TreeNodeFlags, Ui};
+use nalgebra::{Vector3, Vector4};
 use std::borrow::Cow;
 use std::f32::consts::PI;
-use std::time::Duration;
+use std::path::PathBuf;
 
+mod chunk_inspector;
+mod console;
 pub mod integration;
 
+const FRAMETIME_HISTORY_LENGTH: usize = 512;
+// Fraction of the frametime range left empty above and below the graph.
+const FRAMETIME_GRAPH_HEADROOM: f32 = 0.1;
+// Index of the icosahedron in the meshes passed to the renderer.
+const SPHERE_MESH: usize = 1;
+const PARTICLE_EMIT_DISTANCE: f32 = 4.;
+
 pub trait EnumInterface: Sized + 'static {
     const VALUES: &'static [Self];
 
@@ -18,13 +47,23 @@ pub trait EnumInterface: Sized + 'static {
 pub struct Interface {
     pub ctx: Context,
     cursor_visible: bool,
+    frametime_history: FrameTimeHistory,
+    // Text typed into the color lookup table path field, only applied to the settings once loaded.
+    color_lut_path: String,
+    // Same for the skybox directory.
+    skybox_path: String,
+    pub chunk_inspector: ChunkInspector,
+    pub console: Console,
 }
 
 pub struct InterfaceEvents {
-    pub planet_changed: bool,
     pub rebuild_swapchain: bool,
     pub rebuild_pipelines: bool,
     pub rebuild_voxels: bool,
+    pub save_settings: bool,
+    pub console_command: Option<String>,
+    pub switch_session: Option<usize>,
+    pub clone_session: bool,
 }
 
 impl Interface {
@@ -33,20 +72,60 @@ impl Interface {
         world: &mut World,
         renderer: &mut RendererSettings,
         voxels: &mut VoxelsConfig,
-        frametime: Option<Duration>,
+        input: &mut InputState,
+        stats: RendererStats,
+        voxels_stats: VoxelsStats,
+        session_seeds: &[u64],
+        active_session: usize,
+        render_scale_trend: ScaleTrend,
     ) -> InterfaceEvents {
+        if let Some(frametime) = stats.frametime {
+            self.frametime_history.push(frametime.as_secs_f32() * 1000.);
+        }
+        let frametime_history = &mut self.frametime_history;
+        let color_lut_path = &mut self.color_lut_path;
+        let skybox_path = &mut self.skybox_path;
+        let chunk_inspector = &mut self.chunk_inspector;
+        let console = &mut self.console;
         let ui = self.ctx.frame();
         let mut events = InterfaceEvents {
-            planet_changed: false,
             rebuild_swapchain: false,
             rebuild_pipelines: false,
             rebuild_voxels: false,
+            save_settings: false,
+            console_command: None,
+            switch_session: None,
+            clone_session: false,
         };
+        if input.console_open {
+            events.console_command = build_console(ui, console);
+        }
         ui.window("Debugging")
             .size([0., 0.], Condition::Always)
             .build(|| {
+                if ui.collapsing_header("Settings", TreeNodeFlags::empty()) {
+                    if ui.button("Save settings") {
+                        events.save_settings = true;
+                    }
+                    // Renderer resources that depend on the settings are checked for changes
+                    // every frame, so only the voxels need an explicit rebuild.
+                    if ui.button("Reset to defaults") {
+                        *renderer = DEFAULT_RENDERER_SETTINGS;
+                        *voxels = DEFAULT_VOXEL_CONFIG;
+                        events.rebuild_voxels = true;
+                    }
+                }
+                if ui.collapsing_header("Sessions", TreeNodeFlags::empty()) {
+                    build_sessions(ui, session_seeds, active_session, &mut events);
+                }
                 if ui.collapsing_header("Voxels", TreeNodeFlags::empty()) {
                     let mut changed = false;
+                    changed |= ui.input_scalar("Seed", &mut voxels.seed).build();
+                    voxels.seed = voxels.seed.min(MAX_SEED);
+                    if ui.button("Randomize seed") {
+                        voxels.seed = random_seed();
+                        changed = true;
+                    }
                     let mut chunk_size_log2 = 63 - voxels.chunk_size.leading_zeros();
                     changed |= ui.slider("Chunk size", 0, 10, &mut chunk_size_log2);
                     voxels.chunk_size = 1 << chunk_size_log2;
@@ -61,6 +140,19 @@ impl Interface {
                         .flags(SliderFlags::LOGARITHMIC)
                         .build(&mut voxels.heightmap_frequency);
                     changed |= ui.slider("Heightmap bias", -1., 1., &mut voxels.heightmap_bias);
+                    changed |= ui.slider(
+                        "Sand max altitude",
+                        -256,
+                        256,
+                        &mut voxels.sand_max_altitude,
+                    );
+                    changed |= ui.slider(
+                        "Snow min altitude",
+                        -256,
+                        256,
+                        &mut voxels.snow_min_altitude,
+                    );
+                    changed |= ui.slider("Rock min slope", 1, 16, &mut voxels.rock_min_slope);
                     changed |= ui.slider(
                         "Render distance (horizontal)",
                         1,
@@ -73,22 +165,105 @@ impl Interface {
                         1024,
                         &mut voxels.render_distance_vertical,
                     );
+                    changed |= ui.slider(
+                        "Unload distance (horizontal)",
+                        1,
+                        1152,
+                        &mut voxels.unload_distance_horizontal,
+                    );
+                    changed |= ui.slider(
+                        "Unload distance (vertical)",
+                        1,
+                        1152,
+                        &mut voxels.unload_distance_vertical,
+                    );
                     changed |= enum_combo(ui, "Meshing algorithm", &mut voxels.meshing_algorithm);
-                    events.rebuild_voxels = changed;
+                    events.rebuild_voxels |= changed;
+                    ui.slider(
+                        "Max chunks per frame",
+                        1,
+                        64,
+                        &mut voxels.max_chunks_per_frame,
+                    );
+                    ui.checkbox("Ambient occlusion", &mut voxels.ambient_occlusion);
+                    ui.slider(
+                        "Ambient occlusion strength",
+                        0.,
+                        1.,
+                        &mut voxels.ambient_occlusion_strength,
+                    );
+                    build_voxels_health(ui, voxels_stats.health);
+                }
+                if ui.collapsing_header("Voxel statistics", TreeNodeFlags::empty()) {
+                    ui.label_text("Rendering", renderer.voxel_rendering.label());
+                    ui.label_text("Loaded chunks", voxels_stats.loaded_chunks.to_string());
+                    ui.label_text("Pending chunks", voxels_stats.pending_chunks.to_string());
+                    ui.label_text("Chunk loads", voxels_stats.streaming.loads.to_string());
+                    ui.label_text("Chunk unloads", voxels_stats.streaming.unloads.to_string());
+                    ui.label_text(
+                        "Chunk cache re-adds",
+                        voxels_stats.streaming.cache_readds.to_string(),
+                    );
+                    ui.label_text("Vertices", voxels_stats.vertex_count.to_string());
+                    ui.label_text("Triangles", voxels_stats.triangle_count.to_string());
+                    ui.label_text("Meshlets", stats.voxel_meshlet_count.to_string());
+                    // Only the task shader counts the meshlets left after culling.
+                    if renderer.voxel_rendering == VoxelRendering::MeshShaders {
+                        ui.label_text(
+                            "Visible meshlets",
+                            format!(
+                                "{}/{}",
+                                stats.visible_voxel_meshlets, stats.voxel_meshlet_count
+                            ),
+                        );
+                    }
+                    build_chunk_timings(ui, voxels_stats.timings);
+                    for (name, pool) in &voxels_stats.pools {
+                        ui.label_text(
+                            format!("{name} pool"),
+                            format!(
+                                "{}/{} ({:.0}% fragmented)",
+                                pool.used,
+                                pool.capacity,
+                                100. * pool.fragmentation()
+                            ),
+                        );
+                    }
+                }
+                if ui.collapsing_header("Chunk inspector", TreeNodeFlags::empty()) {
+                    chunk_inspector.build(
+                        ui,
+                        world.camera.position(),
+                        voxels.chunk_size,
+                        renderer.debug_draw,
+                    );
+                }
+                if ui.collapsing_header("Materials", TreeNodeFlags::empty()) {
+                    build_materials(ui, &mut renderer.voxel_materials);
                 }
                 if ui.collapsing_header("Sun", TreeNodeFlags::empty()) {
-                    Drag::new("Time of day")
-                        .speed(0.01)
-                        .build(ui, &mut world.time_of_day);
-                    world.time_of_day = world.time_of_day.rem_euclid(2. * PI);
+                    ui.slider("Time of day", 0., 1., &mut world.time_of_day);
                     ui.slider_config("Intensity", 0.001, 10000000.)
                         .flags(SliderFlags::LOGARITHMIC)
                         .build(&mut world.sun_intensity);
                     ui.slider("Orbit radius", 0., 4000., &mut world.sun_radius);
+                    ui.slider("Axial tilt", 0., 0.5 * PI, &mut world.axial_tilt);
                     ui.checkbox("Pause movement", &mut world.sun_pause);
-                    ui.slider_config("Speed", 0.001, 10.)
+                    ui.slider_config("Day length", 1., 3600.)
+                        .flags(SliderFlags::LOGARITHMIC)
+                        .build(&mut world.day_length_seconds);
+                    ui.slider_config("Time scale", 0.01, 100.)
                         .flags(SliderFlags::LOGARITHMIC)
-                        .build(&mut world.sun_speed);
+                        .build(&mut renderer.time_scale);
+                }
+                if ui.collapsing_header("Objects", TreeNodeFlags::empty()) {
+                    build_objects(ui, world);
+                }
+                if ui.collapsing_header("Lights", TreeNodeFlags::empty()) {
+                    build_lights(ui, world);
+                }
+                if ui.collapsing_header("Particles", TreeNodeFlags::empty()) {
+                    build_particles(ui, world, &stats);
                 }
                 if ui.collapsing_header("Renderer", TreeNodeFlags::empty()) {
                     enum_combo(ui, "Voxel rendering", &mut renderer.voxel_rendering);
@@ -98,21 +273,54 @@ impl Interface {
                     ui.slider_config("Depth far plane", 16., 1048576.)
                         .flags(SliderFlags::LOGARITHMIC)
                         .build(&mut renderer.depth_far);
+                    ui.checkbox("Debug draw", &mut renderer.debug_draw);
+                    ui.slider("Render scale", 0.25, 1., &mut renderer.render_scale);
+                    build_dynamic_resolution(ui, renderer, render_scale_trend);
+                    ui.checkbox("Parallel recording", &mut renderer.parallel_recording);
+                    enum_combo(ui, "Present mode", &mut renderer.present_mode);
+                    ui.checkbox("GPU culling", &mut renderer.gpu_culling);
+                    ui.checkbox("Depth pre-pass", &mut renderer.depth_prepass);
+                }
+                if ui.collapsing_header("Passes", TreeNodeFlags::empty()) {
+                    // Atmosphere is computed while shading objects rather than in a separate pass,
+                    // so it's toggled in its own section instead.
+                    ui.checkbox("Shadow", &mut renderer.passes.shadow);
+                    ui.checkbox("Scene", &mut renderer.passes.scene);
+                    ui.checkbox("Transparent", &mut renderer.passes.transparent);
+                    ui.checkbox("Bloom", &mut renderer.passes.bloom);
+                    ui.checkbox("Postprocess", &mut renderer.passes.upscale);
+                }
+                if ui.collapsing_header("Shadows", TreeNodeFlags::empty()) {
+                    let shadows = &mut renderer.shadows;
+                    ui.checkbox("Enable", &mut shadows.enable);
+                    let mut resolution_log2 = shadows.resolution.ilog2();
+                    ui.slider("Resolution", 8, 13, &mut resolution_log2);
+                    shadows.resolution = 1 << resolution_log2;
+                    ui.slider("Distance", 8., 1024., &mut shadows.distance);
+                    // Too low bias causes acne on lit surfaces, too high detaches the shadows from
+                    // their casters (peter-panning), and the right value depends on the distance.
+                    ui.slider_config("Bias", 0.00001, 0.01)
+                        .flags(SliderFlags::LOGARITHMIC)
+                        .build(&mut shadows.bias);
                 }
                 if ui.collapsing_header("Atmosphere", TreeNodeFlags::empty()) {
                     ui.checkbox("Enable", &mut renderer.enable_atmosphere);
-                    ui.slider(
-                        "In scattering samples",
-                        1,
-                        32,
-                        &mut renderer.atmosphere_in_scattering_samples,
-                    );
-                    ui.slider(
-                        "Optical depth samples",
-                        1,
-                        32,
-                        &mut renderer.atmosphere_optical_depth_samples,
-                    );
+                    build_skybox(ui, skybox_path, &mut renderer.skybox);
+                    enum_combo(ui, "Quality", &mut renderer.atmosphere_quality);
+                    if renderer.atmosphere_quality == AtmosphereQuality::Custom {
+                        ui.slider(
+                            "In scattering samples",
+                            1,
+                            32,
+                            &mut renderer.atmosphere_in_scattering_samples,
+                        );
+                        ui.slider(
+                            "Optical depth samples",
+                            1,
+                            32,
+                            &mut renderer.atmosphere_optical_depth_samples,
+                        );
+                    }
                     ui.slider_config("Planet radius", 10., 4000.)
                         .flags(SliderFlags::LOGARITHMIC)
                         .build(&mut world.atmosphere.planet_radius);
@@ -120,11 +328,11 @@ impl Interface {
                         .flags(SliderFlags::LOGARITHMIC)
                         .build(&mut world.atmosphere.density_falloff);
                     ui.slider("Scale", 1., 3., &mut world.atmosphere.scale);
-                    Drag::new("Wavelengths")
-                        .build_array(ui, renderer.atmosphere_wavelengths.as_mut_slice());
-                    ui.slider_config("Scattering strength", 0.001, 100.)
-                        .flags(SliderFlags::LOGARITHMIC)
-                        .build(&mut world.atmosphere.scattering_strength);
+                    build_atmosphere_wavelengths(
+                        ui,
+                        &mut renderer.atmosphere_wavelengths,
+                        &mut world.atmosphere.scattering_strength,
+                    );
                     ui.slider(
                         "Henyey-Greenstein g",
                         -1.,
@@ -134,20 +342,188 @@ impl Interface {
                 }
                 if ui.collapsing_header("Post-processing", TreeNodeFlags::empty()) {
                     build_postprocess(ui, &mut renderer.postprocess);
+                    build_color_lut(
+                        ui,
+                        color_lut_path,
+                        &mut renderer.color_lut,
+                        &mut renderer.postprocess.color_lut_strength,
+                    );
+                }
+                if ui.collapsing_header("Bloom", TreeNodeFlags::empty()) {
+                    build_bloom(ui, &mut renderer.bloom);
+                }
+                if ui.collapsing_header("Ambient occlusion", TreeNodeFlags::empty()) {
+                    build_ssao(ui, &mut renderer.ssao);
+                }
+                if ui.collapsing_header("Screen-space reflections", TreeNodeFlags::empty()) {
+                    build_ssr(ui, &mut renderer.ssr);
+                }
+                if ui.collapsing_header("Depth of field", TreeNodeFlags::empty()) {
+                    build_dof(ui, &mut renderer.dof);
+                }
+                if ui.collapsing_header("Temporal anti-aliasing", TreeNodeFlags::empty()) {
+                    build_taa(ui, &mut renderer.taa);
+                }
+                if ui.collapsing_header("Sky lighting", TreeNodeFlags::empty()) {
+                    build_sky_lighting(ui, &mut renderer.sky_lighting);
+                }
+                if ui.collapsing_header("Controls", TreeNodeFlags::empty()) {
+                    let mut physics_mode = world.physics_mode();
+                    if enum_combo(ui, "Physics mode", &mut physics_mode) {
+                        world.set_physics_mode(physics_mode);
+                    }
+                    ui.checkbox("Fly collision", &mut world.fly_collision);
+                    build_controls(ui, input);
+                }
+                if ui.collapsing_header("Memory", TreeNodeFlags::empty()) {
+                    build_memory(ui, &stats.memory, stats.staging_ring);
                 }
                 if ui.collapsing_header("Performance", TreeNodeFlags::empty()) {
-                    if let Some(frametime) = frametime {
+                    if let Some(frametime) = stats.frametime {
                         ui.label_text(
                             "Frametime",
                             format!("{:.2}ms", frametime.as_secs_f64() * 1000.),
                         );
                     }
+                    ui.label_text("Present mode", format!("{:?}", stats.present_mode));
+                    if let Some(present_interval) = stats.present_interval {
+                        ui.label_text(
+                            "Present interval",
+                            format!("{:.2}ms", present_interval.as_secs_f64() * 1000.),
+                        );
+                    }
+                    ui.label_text(
+                        "Visible objects",
+                        format!("{}/{}", stats.visible_objects, stats.total_objects),
+                    );
+                    ui.label_text(
+                        "Visible stars",
+                        format!("{}/{}", stats.visible_stars, stats.total_stars),
+                    );
+                    build_frametime_history(ui, frametime_history);
+                    build_pass_times(ui, &stats);
                 }
             });
         events
     }
 }
 
+// Shown in red when anything went wrong, as otherwise it's easy to mistake missing chunks for a
+// world generation quirk.
+fn build_voxels_health(ui: &Ui, health: VoxelsHealth) {
+    let text = format!(
+        "Failed chunks: {}, retried: {}, dead workers: {}, evicted: {}, stale: {}",
+        health.failed_chunks,
+        health.retried_chunks,
+        health.dead_workers,
+        health.evicted_chunks,
+        health.stale_chunks
+    );
+    if health.failed_chunks > 0 || health.retried_chunks > 0 || health.dead_workers > 0 {
+        ui.text_colored([1., 0.3, 0.3, 1.], text);
+    } else {
+        ui.text(text);
+    }
+}
+
+// Averages and maximums over the most recent chunks, to show which step is worth optimizing.
+fn build_chunk_timings(ui: &Ui, timings: ChunkTimingStats) {
+    if let Some(_table) = ui.begin_table("Chunk timings", 3) {
+        ui.table_setup_column("Step");
+        ui.table_setup_column("Average");
+        ui.table_setup_column("Max");
+        ui.table_headers_row();
+        for (step, average, max) in [
+            (
+                "Heightmap",
+                timings.average.heightmap_us,
+                timings.max.heightmap_us,
+            ),
+            ("SVO", timings.average.svo_us, timings.max.svo_us),
+            ("Mesh", timings.average.mesh_us, timings.max.mesh_us),
+        ] {
+            ui.table_next_row();
+            ui.table_next_column();
+            ui.text(step);
+            ui.table_next_column();
+            ui.text(format!("{average} µs"));
+            ui.table_next_column();
+            ui.text(format!("{max} µs"));
+        }
+    }
+}
+
+// Frametimes of the most recent frames in milliseconds. It's a ring buffer rather than a deque, as
+// imgui can plot it directly by starting at the oldest entry.
+struct FrameTimeHistory {
+    frametimes: [f32; FRAMETIME_HISTORY_LENGTH],
+    head: usize,
+    len: usize,
+}
+
+impl FrameTimeHistory {
+    fn new() -> FrameTimeHistory {
+        FrameTimeHistory {
+            frametimes: [0.; FRAMETIME_HISTORY_LENGTH],
+            head: 0,
+            len: 0,
+        }
+    }
+
+    fn push(&mut self, frametime: f32) {
+        self.frametimes[self.head] = frametime;
+        self.head = (self.head + 1) % FRAMETIME_HISTORY_LENGTH;
+        self.len = (self.len + 1).min(FRAMETIME_HISTORY_LENGTH);
+    }
+
+    fn clear(&mut self) {
+        self.head = 0;
+        self.len = 0;
+    }
+
+    fn frametimes(&self) -> &[f32] {
+        &self.frametimes[..self.len]
+    }
+}
+
+// Averages hide the occasional long frame from shader reloads or chunk uploads, so the higher
+// percentiles are shown next to the median.
+fn build_frametime_history(ui: &Ui, history: &mut FrameTimeHistory) {
+    let frametimes = history.frametimes();
+    if frametimes.is_empty() {
+        return;
+    }
+    let mut sorted = frametimes.to_vec();
+    sorted.sort_by(f32::total_cmp);
+    let percentile = |p: f32| sorted[((sorted.len() - 1) as f32 * p).round() as usize];
+    let min = sorted[0];
+    let max = sorted[sorted.len() - 1];
+    // Fitting the scale to the range makes small variations visible, and the headroom keeps spikes
+    // from touching the edges of the graph.
+    let headroom = (max - min).max(0.1) * FRAMETIME_GRAPH_HEADROOM;
+    let min_y = (min - headroom).max(0.);
+    let max_y = max + headroom;
+    ui.plot_lines("Frametime history", frametimes)
+        .values_offset(history.head)
+        .scale_min(min_y)
+        .scale_max(max_y)
+        .graph_size([0., 80.])
+        .build();
+    ui.label_text(
+        "p50 / p95 / p99",
+        format!(
+            "{:.2}ms / {:.2}ms / {:.2}ms",
+            percentile(0.5),
+            percentile(0.95),
+            percentile(0.99)
+        ),
+    );
+    ui.label_text("Min / max", format!("{min:.2}ms / {max:.2}ms"));
+    if ui.button("Clear history") {
+        history.clear();
+    }
+}
+
 impl EnumInterface for vk::SampleCountFlags {
     const VALUES: &'static [Self] = &[
         vk::SampleCountFlags::TYPE_2,
@@ -168,6 +544,19 @@ impl EnumInterface for vk::SampleCountFlags {
     }
 }
 
+// Bars are relative to the whole frame, so that the gaps between passes are visible too.
+fn build_pass_times(ui: &Ui, stats: &RendererStats) {
+    let Some(frametime) = stats.frametime else {
+        return;
+    };
+    ui.text("GPU passes");
+    for (name, duration) in &stats.pass_times {
+        let fraction = duration.as_secs_f32() / frametime.as_secs_f32();
+        let overlay = format!("{name}: {:.3}ms", duration.as_secs_f64() * 1000.);
+        ProgressBar::new(fraction).overlay_text(overlay).build(ui);
+    }
+}
+
 fn build_postprocess(ui: &Ui, postprocess: &mut PostprocessSettings) {
     ui.slider_config("Exposure", 0.001, 100.)
         .flags(SliderFlags::LOGARITHMIC)
@@ -177,6 +566,376 @@ fn build_postprocess(ui: &Ui, postprocess: &mut PostprocessSettings) {
         .range(0., f32::INFINITY)
         .speed(0.01)
         .build(ui, &mut postprocess.gamma);
+    ui.slider("Film grain", 0., 0.5, &mut postprocess.grain_strength);
+    ui.slider(
+        "Vignette strength",
+        0.,
+        1.,
+        &mut postprocess.vignette_strength,
+    );
+    ui.slider("Vignette radius", 0., 1.5, &mut postprocess.vignette_radius);
+    ui.slider(
+        "Chromatic aberration",
+        0.,
+        0.05,
+        &mut postprocess.aberration_strength,
+    );
+}
+
+// The renderer notices the path changing and reloads the table by itself. Loading the same path
+// again doesn't do anything, so the table has to be cleared first to pick up changes to the file.
+fn build_color_lut(ui: &Ui, path: &mut String, lut: &mut Option<PathBuf>, strength: &mut f32) {
+    ui.input_text("Color LUT", path).build();
+    if ui.button("Load LUT") && !path.is_empty() {
+        *lut = Some(PathBuf::from(path.as_str()));
+    }
+    ui.same_line();
+    if ui.button("Clear LUT") {
+        *lut = None;
+    }
+    ui.slider("Color LUT strength", 0., 1., strength);
+}
+
+// The skybox only shows up with the atmosphere disabled, and reloads like the lookup table.
+fn build_skybox(ui: &Ui, path: &mut String, skybox: &mut Option<PathBuf>) {
+    ui.input_text("Skybox", path).build();
+    if ui.button("Load skybox") && !path.is_empty() {
+        *skybox = Some(PathBuf::from(path.as_str()));
+    }
+    ui.same_line();
+    if ui.button("Clear skybox") {
+        *skybox = None;
+    }
+}
+
+// The radii are baked into the pipelines, which the renderer recreates by itself when they change.
+// The render scale slider above still works while this is enabled, but only until the next change.
+fn build_dynamic_resolution(ui: &Ui, renderer: &mut RendererSettings, trend: ScaleTrend) {
+    ui.checkbox("Dynamic resolution", &mut renderer.dynamic_resolution);
+    if !renderer.dynamic_resolution {
+        return;
+    }
+    ui.slider_config("Target frame time", 4., 50.)
+        .display_format("%.1f ms")
+        .build(&mut renderer.target_frame_time_ms);
+    ui.slider("Min render scale", 0.25, 1., &mut renderer.min_render_scale);
+    ui.slider("Max render scale", 0.25, 1., &mut renderer.max_render_scale);
+    let direction = match trend {
+        ScaleTrend::Lowering => Direction::Down,
+        ScaleTrend::Steady => Direction::Right,
+        ScaleTrend::Raising => Direction::Up,
+    };
+    ui.arrow_button("##Render scale trend", direction);
+    ui.same_line();
+    ui.text(format!("Render scale {:.2}", renderer.render_scale));
+}
+
+// Both are only used through the atmosphere uniform, so changes show up in the next frame without
+// recreating any pipelines.
+fn build_atmosphere_wavelengths(
+    ui: &Ui,
+    wavelengths: &mut Vector3<f32>,
+    scattering_strength: &mut f32,
+) {
+    for (channel, wavelength) in ["Red", "Green", "Blue"].iter().zip(wavelengths.iter_mut()) {
+        ui.color_button(format!("##{channel} color"), wavelength_color(*wavelength));
+        ui.same_line();
+        ui.slider_config(format!("{channel} wavelength"), 380., 780.)
+            .display_format("%.0f nm")
+            .build(wavelength);
+    }
+    ui.slider_config("Scattering strength", 0.001, 100.)
+        .flags(SliderFlags::LOGARITHMIC)
+        .build(scattering_strength);
+    // The coefficients are what actually tints the sky, so they are easier to reason about than
+    // the wavelengths they are derived from.
+    let coefficients = rayleigh_coefficients(*wavelengths, *scattering_strength);
+    ui.label_text(
+        "Scattering coefficients",
+        format!(
+            "{:.4} {:.4} {:.4}",
+            coefficients.x, coefficients.y, coefficients.z
+        ),
+    );
+    if ui.button("Earth default") {
+        *wavelengths = DEFAULT_RENDERER_SETTINGS.atmosphere_wavelengths;
+        *scattering_strength = DEFAULT_ATMOSPHERE_SCATTERING_STRENGTH;
+    }
+}
+
+// Same as in calculate_light in atmosphere.glsl.
+fn rayleigh_coefficients(wavelengths: Vector3<f32>, scattering_strength: f32) -> Vector3<f32> {
+    scattering_strength * wavelengths.map(|wavelength| (400. / wavelength).powi(4))
+}
+
+// Rough color of light of a single wavelength, only meant for telling the channels apart at a
+// glance. Piecewise linear in the hue, dimming towards the edges of the visible spectrum.
+fn wavelength_color(wavelength: f32) -> [f32; 4] {
+    let wavelength = wavelength.clamp(380., 780.);
+    let (r, g, b) = match wavelength {
+        w if w < 440. => ((440. - w) / 60., 0., 1.),
+        w if w < 490. => (0., (w - 440.) / 50., 1.),
+        w if w < 510. => (0., 1., (510. - w) / 20.),
+        w if w < 580. => ((w - 510.) / 70., 1., 0.),
+        w if w < 645. => (1., (645. - w) / 65., 0.),
+        _ => (1., 0., 0.),
+    };
+    let intensity = if wavelength < 420. {
+        0.3 + 0.7 * (wavelength - 380.) / 40.
+    } else if wavelength > 700. {
+        0.3 + 0.7 * (780. - wavelength) / 80.
+    } else {
+        1.
+    };
+    [r * intensity, g * intensity, b * intensity, 1.]
+}
+
+fn build_bloom(ui: &Ui, bloom: &mut BloomSettings) {
+    ui.slider("Threshold", 0., 1., &mut bloom.threshold);
+    ui.slider("Knee", 0., 1., &mut bloom.knee);
+    ui.slider("Intensity", 0., 4., &mut bloom.intensity);
+    ui.slider("Passes", 1, BLOOM_MAX_PASSES, &mut bloom.passes);
+    ui.slider("Radius", 1., 32., &mut bloom.radius);
+}
+
+fn build_ssao(ui: &Ui, ssao: &mut SsaoSettings) {
+    ui.checkbox("Enable", &mut ssao.enabled);
+    ui.slider("Radius", 0.1, 4., &mut ssao.radius);
+    ui.slider("Intensity", 0.1, 4., &mut ssao.intensity);
+}
+
+// Only surfaces with low roughness reflect anything noticeable, so the effect is easiest to judge
+// with the overlay showing the reflections alone.
+
+fn build_ssr(ui: &Ui, ssr: &mut SsrSettings) {
+    ui.checkbox("Enable", &mut ssr.enabled);
+    ui.checkbox("Debug overlay", &mut ssr.debug_overlay);
+    ui.slider("Max steps", 8, 256, &mut ssr.max_steps);
+    ui.slider_config("Step size", 0.05, 4.)
+        .flags(SliderFlags::LOGARITHMIC)
+        .build(&mut ssr.step_size);
+    ui.slider("Thickness", 0.05, 8., &mut ssr.thickness);
+    ui.slider("Fade distance", 1., 256., &mut ssr.fade_distance);
+}
+
+fn build_dof(ui: &Ui, dof: &mut DofSettings) {
+    ui.checkbox("Enable", &mut dof.enabled);
+    ui.checkbox("Autofocus", &mut dof.autofocus);
+    ui.disabled(dof.autofocus, || {
+        ui.slider_config("Focus distance", 0.5, 1024.)
+            .flags(SliderFlags::LOGARITHMIC)
+            .build(&mut dof.focus_distance);
+    });
+    ui.slider("Aperture", 0., 16., &mut dof.aperture);
+}
+
+fn build_taa(ui: &Ui, taa: &mut TaaSettings) {
+    ui.checkbox("Enable", &mut taa.enabled);
+    ui.checkbox("Debug view", &mut taa.debug_view);
+    ui.slider("History weight", 0.5, 0.98, &mut taa.history_weight);
+}
+
+fn build_sky_lighting(ui: &Ui, sky_lighting: &mut SkyLightingSettings) {
+    ui.checkbox("Enable", &mut sky_lighting.enabled);
+    ui.slider("Intensity", 0., 4., &mut sky_lighting.intensity);
+}
+
+// The clone starts with the same config, and switching to it right away means the voxel settings
+// above tweak the clone rather than the original.
+fn build_sessions(
+    ui: &Ui,
+    session_seeds: &[u64],
+    active_session: usize,
+    events: &mut InterfaceEvents,
+) {
+    for (index, seed) in session_seeds.iter().enumerate() {
+        if ui
+            .selectable_config(format!("Session {index}, seed {seed}"))
+            .selected(index == active_session)
+            .build()
+        {
+            events.switch_session = Some(index);
+        }
+    }
+    if ui.button("Clone current session") {
+        events.clone_session = true;
+    }
+}
+
+fn build_objects(ui: &Ui, world: &mut World) {
+    ui.text(format!("Count: {}", world.objects.len()));
+    if ui.button("Spawn sphere") {
+        world.spawn_object(SPHERE_MESH, Vector4::new(0.8, 0.3, 0.2, 1.));
+    }
+    if ui.button("Spawn glass sphere") {
+        world.spawn_object(SPHERE_MESH, Vector4::new(0.6, 0.8, 1., 0.3));
+    }
+    if ui.button("Clear objects") {
+        world.objects.clear();
+    }
+}
+
+fn build_lights(ui: &Ui, world: &mut World) {
+    let mut removed = None;
+    for (index, light) in world.point_lights.iter_mut().enumerate() {
+        if let Some(_node) = ui.tree_node(format!("Light {index}")) {
+            Drag::new("Position")
+                .speed(0.1)
+                .build_array(ui, light.position.as_mut_slice());
+            let mut color: [f32; 3] = light.color.into();
+            ui.color_edit3("Color", &mut color);
+            light.color = color.into();
+            ui.slider("Radius", 0.1, 64., &mut light.radius);
+            ui.slider_config("Intensity", 0.1, 1000.)
+                .flags(SliderFlags::LOGARITHMIC)
+                .build(&mut light.intensity);
+            let mut attached = world.camera_light == Some(index);
+            if ui.checkbox("Attach to camera", &mut attached) {
+                world.camera_light = attached.then_some(index);
+            }
+            if ui.button("Remove") {
+                removed = Some(index);
+            }
+        }
+    }
+    if let Some(index) = removed {
+        world.remove_point_light(index);
+    }
+    if ui.button("Add light") {
+        world.add_point_light(PointLight {
+            position: world.camera.position(),
+            radius: 16.,
+            color: Vector3::new(1., 0.8, 0.6),
+            intensity: 50.,
+        });
+    }
+}
+
+fn build_particles(ui: &Ui, world: &mut World, stats: &RendererStats) {
+    ui.label_text("Live particles", stats.particle_count.to_string());
+    if ui.button("Emit burst") {
+        let origin =
+            world.camera.position() + PARTICLE_EMIT_DISTANCE * world.camera.view_direction();
+        world.emit_particles(
+            origin,
+            1024,
+            EmitSettings {
+                speed: 6.,
+                spread: 0.5,
+                lifetime: 3.,
+                color: Vector4::new(1., 0.6, 0.2, 1.),
+            },
+        );
+    }
+}
+
+fn build_memory(ui: &Ui, memory: &MemoryStats, staging_ring: (usize, usize)) {
+    for (tag, bytes) in &memory.tags {
+        ui.label_text(tag.label(), format_bytes(*bytes));
+    }
+    ui.label_text("Total", format_bytes(memory.tracked()));
+    ui.separator();
+    if let Some(_table) = ui.begin_table("Heaps", 5) {
+        ui.table_setup_column("Heap");
+        ui.table_setup_column("Size");
+        ui.table_setup_column("Allocated");
+        ui.table_setup_column("Usage");
+        ui.table_setup_column("Budget");
+        ui.table_headers_row();
+        for (index, heap) in memory.heaps.iter().enumerate() {
+            let kind = if heap.device_local { "VRAM" } else { "system" };
+            ui.table_next_row();
+            ui.table_next_column();
+            ui.text(format!("{index} ({kind})"));
+            ui.table_next_column();
+            ui.text(format_bytes(heap.size));
+            ui.table_next_column();
+            ui.text(format_bytes(heap.allocated));
+            ui.table_next_column();
+            match (heap.usage, heap.budget) {
+                (Some(usage), Some(budget)) => {
+                    ui.text_colored(budget_color(usage, budget), format_bytes(usage));
+                    ui.table_next_column();
+                    ui.text(format_bytes(budget));
+                }
+                _ => {
+                    ui.text_disabled("-");
+                    ui.table_next_column();
+                    ui.text_disabled("-");
+                }
+            }
+        }
+    }
+    // Other processes share the budget too, so the driver-reported usage can be well above what
+    // was allocated here.
+    match memory.vram_budget() {
+        Some((usage, budget)) => ui.text_colored(
+            budget_color(usage, budget),
+            format!(
+                "VRAM: {} used of {} budget",
+                format_bytes(usage),
+                format_bytes(budget)
+            ),
+        ),
+        None => ui.text_disabled("Budget is only known with VK_EXT_memory_budget."),
+    }
+    let (used, capacity) = staging_ring;
+    let overlay = format!(
+        "Staging ring: {} of {}",
+        format_bytes(used as u64),
+        format_bytes(capacity as u64)
+    );
+    ProgressBar::new(used as f32 / capacity as f32)
+        .overlay_text(overlay)
+        .build(ui);
+}
+
+// Green while there's plenty of room, yellow when approaching the budget and red once the driver
+// is likely to start moving memory out of VRAM.
+fn budget_color(usage: u64, budget: u64) -> [f32; 4] {
+    let fraction = usage as f64 / budget.max(1) as f64;
+    if fraction < MEMORY_BUDGET_WARNING {
+        [0.3, 1., 0.3, 1.]
+    } else if fraction < MEMORY_BUDGET_CRITICAL {
+        [1., 1., 0.3, 1.]
+    } else {
+        [1., 0.3, 0.3, 1.]
+    }
+}
+
+fn format_bytes(bytes: u64) -> String {
+    format!("{:.1} MiB", bytes as f64 / (1024. * 1024.))
+}
+
+fn build_materials(ui: &Ui, materials: &mut [VoxelMaterial; MATERIAL_COUNT]) {
+    for material in VoxelMaterialId::SOLID {
+        if let Some(_node) = ui.tree_node(material.label()) {
+            let material = &mut materials[material.0 as usize];
+            let mut albedo: [f32; 3] = material.albedo.into();
+            ui.color_edit3("Albedo", &mut albedo);
+            material.albedo = albedo.into();
+            ui.slider("Roughness", 0., 1., &mut material.roughness);
+            ui.slider("Metallic", 0., 1., &mut material.metallic);
+            let mut emit: [f32; 3] = material.emit.into();
+            ui.color_edit3("Emit", &mut emit);
+            material.emit = emit.into();
+        }
+    }
+}
+
+fn build_controls(ui: &Ui, input: &mut InputState) {
+    for action in Action::ALL {
+        let key = if input.rebinding == Some(action) {
+            "<press a key>".to_owned()
+        } else {
+            input.bindings.names(action)
+        };
+        if ui.button(format!("{key}##{}", action.label())) {
+            input.rebinding = Some(action);
+        }
+        ui.same_line();
+        ui.text(action.label());
+    }
 }
 
 fn enum_combo<T: Copy + EnumInterface + PartialEq>(ui: &Ui, label: &str, value: &mut T) -> bool {
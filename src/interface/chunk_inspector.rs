@@ -0,0 +1,134 @@
+use crate::debug_draw::DebugDraw;
+use crate::voxel::{chunk_from_position, ChunkInfo, ChunkState, Voxels};
+use imgui::{ListClipper, Ui};
+use nalgebra::Vector3;
+
+const CHUNK_LIST_HEIGHT: f32 = 300.;
+const SELECTED_COLOR: Vector3<f32> = Vector3::new(1., 0., 1.);
+
+// Lists the chunk table of the voxel system, for debugging streaming. The snapshot is only taken
+// while something actually uses it, as it copies the whole table every frame.
+pub struct ChunkInspector {
+    filter: String,
+    selected: Option<Vector3<i64>>,
+    color_by_state: bool,
+    // Whether the panel was open during the last frame, as whether it's open this frame is only
+    // known while building the interface, after the snapshot was already taken.
+    open: bool,
+    built: bool,
+    chunks: Vec<ChunkInfo>,
+}
+
+impl ChunkInspector {
+    pub fn new() -> ChunkInspector {
+        ChunkInspector {
+            filter: String::new(),
+            selected: None,
+            color_by_state: false,
+            open: false,
+            built: false,
+            chunks: Vec::new(),
+        }
+    }
+
+    pub fn update(&mut self, voxels: &Voxels) {
+        if self.open || self.color_by_state || self.selected.is_some() {
+            self.chunks = voxels.chunk_snapshot();
+        } else {
+            self.chunks.clear();
+        }
+    }
+
+    pub fn build(&mut self, ui: &Ui, camera: Vector3<f32>, chunk_size: usize, debug_draw: bool) {
+        self.built = true;
+        if !debug_draw {
+            ui.text_disabled("Overlays are only shown with debug draw enabled.");
+        }
+        ui.checkbox("Color all chunks by state", &mut self.color_by_state);
+        if self.selected.is_some() && ui.button("Clear selection") {
+            self.selected = None;
+        }
+        ui.input_text("Filter", &mut self.filter).build();
+
+        let camera = chunk_from_position(camera, chunk_size);
+        self.chunks
+            .sort_by_key(|info| (info.chunk - camera).map(|coord| coord * coord).sum());
+        let filter = self.filter.to_lowercase();
+        let rows: Vec<_> = self
+            .chunks
+            .iter()
+            .map(|info| {
+                let label = format!(
+                    "{:>5} {:>5} {:>5}  {:<10} {:>8} tris {:>8.1} KiB",
+                    info.chunk.x,
+                    info.chunk.y,
+                    info.chunk.z,
+                    state_label(info.state),
+                    info.triangle_count,
+                    info.gpu_memory as f32 / 1024.
+                );
+                (info.chunk, label)
+            })
+            .filter(|(_, label)| label.to_lowercase().contains(&filter))
+            .collect();
+        ui.text(format!("Chunks: {}/{}", rows.len(), self.chunks.len()));
+
+        ui.child_window("Chunk list")
+            .size([0., CHUNK_LIST_HEIGHT])
+            .build(|| {
+                // Thousands of chunks can be loaded at once, so only the visible rows are built.
+                let clipper = ListClipper::new(rows.len() as i32).begin(ui);
+                for index in clipper.iter() {
+                    let (chunk, label) = &rows[index as usize];
+                    let selected = self.selected == Some(*chunk);
+                    if ui
+                        .selectable_config(format!("{label}##{index}"))
+                        .selected(selected)
+                        .build()
+                    {
+                        self.selected = if selected { None } else { Some(*chunk) };
+                    }
+                }
+            });
+    }
+
+    // Called every frame after building the interface, as that's when it's known whether the panel
+    // was open.
+    pub fn end_frame(&mut self, debug: &mut DebugDraw, chunk_size: usize) {
+        let chunk_aabb = |chunk: Vector3<i64>| {
+            let min = (chunk * chunk_size as i64).map(|coord| coord as f32);
+            (min, min.add_scalar(chunk_size as f32))
+        };
+        if self.color_by_state {
+            for info in &self.chunks {
+                let (min, max) = chunk_aabb(info.chunk);
+                debug.add_aabb(min, max, state_color(info.state));
+            }
+        }
+        if let Some(chunk) = self.selected {
+            let (min, max) = chunk_aabb(chunk);
+            debug.add_aabb(min, max, SELECTED_COLOR);
+        }
+        self.open = std::mem::take(&mut self.built);
+    }
+}
+
+fn state_label(state: ChunkState) -> &'static str {
+    match state {
+        ChunkState::Queued => "queued",
+        ChunkState::Generating => "generating",
+        ChunkState::Generated => "generated",
+        ChunkState::Uploaded => "uploaded",
+        ChunkState::Failed => "failed",
+    }
+}
+
+fn state_color(state: ChunkState) -> Vector3<f32> {
+    match state {
+        ChunkState::Queued => Vector3::new(0.5, 0.5, 0.5),
+        ChunkState::Generating => Vector3::new(1., 0.8, 0.),
+        ChunkState::Generated => Vector3::new(0.2, 0.4, 1.),
+        ChunkState::Uploaded => Vector3::new(0.2, 1., 0.2),
+        ChunkState::Failed => Vector3::new(1., 0.1, 0.1),
+    }
+}
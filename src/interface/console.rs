@@ -0,0 +1,89 @@
+use crate::console::{Console, ConsoleLine};
+use imgui::{
+    Condition, HistoryDirection, InputTextCallback, InputTextCallbackHandler, TextCallbackData, Ui,
+};
+
+const CONSOLE_POSITION: [f32; 2] = [480., 16.];
+const CONSOLE_SIZE: [f32; 2] = [720., 320.];
+const INPUT_COLOR: [f32; 4] = [0.6, 0.8, 1., 1.];
+const ERROR_COLOR: [f32; 4] = [1., 0.4, 0.4, 1.];
+
+struct ConsoleInput<'a> {
+    console: &'a mut Console,
+}
+
+// Returns the line submitted this frame, which is executed by the app once the interface is built,
+// as the commands need access to everything else.
+pub fn build_console(ui: &Ui, console: &mut Console) -> Option<String> {
+    let mut submitted = None;
+    ui.window("Console")
+        .position(CONSOLE_POSITION, Condition::FirstUseEver)
+        .size(CONSOLE_SIZE, Condition::FirstUseEver)
+        .build(|| {
+            let footer_height = ui.frame_height_with_spacing();
+            ui.child_window("Scrollback")
+                .size([0., -footer_height])
+                .build(|| {
+                    for line in console.scrollback() {
+                        match line {
+                            ConsoleLine::Input(text) => {
+                                ui.text_colored(INPUT_COLOR, format!("> {text}"))
+                            }
+                            ConsoleLine::Output(text) => ui.text(text),
+                            ConsoleLine::Error(text) => ui.text_colored(ERROR_COLOR, text),
+                        }
+                    }
+                    if console.scroll_to_bottom {
+                        ui.set_scroll_here_y_with_ratio(1.);
+                        console.scroll_to_bottom = false;
+                    }
+                });
+            // The input is taken out for the duration, as the callbacks need the rest of the
+            // console for completion and history.
+            let mut input = std::mem::take(&mut console.input);
+            ui.set_next_item_width(-1.);
+            ui.set_keyboard_focus_here();
+            let entered = ui
+                .input_text("##input", &mut input)
+                .enter_returns_true(true)
+                .callback(
+                    InputTextCallback::COMPLETION
+                        | InputTextCallback::HISTORY
+                        | InputTextCallback::CHAR_FILTER,
+                    ConsoleInput {
+                        console: &mut *console,
+                    },
+                )
+                .build();
+            if entered {
+                submitted = Some(std::mem::take(&mut input));
+            }
+            console.input = input;
+        });
+    submitted
+}
+
+impl InputTextCallbackHandler for ConsoleInput<'_> {
+    // The key opening the console would otherwise end up typed into it.
+    fn char_filter(&mut self, c: char) -> Option<char> {
+        (c != '`').then_some(c)
+    }
+
+    fn on_completion(&mut self, mut data: TextCallbackData) {
+        if let Some(completed) = self.console.complete(data.str()) {
+            data.clear();
+            data.push_str(&completed);
+        }
+    }
+
+    fn on_history(&mut self, direction: HistoryDirection, mut data: TextCallbackData) {
+        let entry = match direction {
+            HistoryDirection::Up => self.console.history.previous(),
+            HistoryDirection::Down => self.console.history.next(),
+        };
+        if let Some(entry) = entry {
+            data.clear();
+            data.push_str(entry);
+        }
+    }
+}
@@ -1,7 +1,10 @@
-use crate::interface::Interface;
-use imgui::{Context, DrawData, FontSource};
+use crate::console::Console;
+use crate::interface::chunk_inspector::ChunkInspector;
+use crate::interface::{FrameTimeHistory, Interface};
+use imgui::{Context, DrawData, FontSource, Key};
 use winit::dpi::PhysicalPosition;
 use winit::event::{ElementState, MouseButton, WindowEvent};
+use winit::keyboard::{KeyCode, PhysicalKey};
 use winit::window::{CursorGrabMode, Window};
 
 impl Interface {
@@ -15,6 +18,11 @@ impl Interface {
         Interface {
             ctx,
             cursor_visible: false,
+            frametime_history: FrameTimeHistory::new(),
+            color_lut_path: String::new(),
+            skybox_path: String::new(),
+            chunk_inspector: ChunkInspector::new(),
+            console: Console::new(),
         }
     }
 
@@ -32,6 +40,28 @@ impl Interface {
                     io.add_mouse_button_event(mouse, *state == ElementState::Pressed);
                 }
             }
+            // Text goes through characters, while keys are only needed for editing and navigating
+            // within text fields.
+            WindowEvent::KeyboardInput { event, .. } => {
+                let pressed = event.state == ElementState::Pressed;
+                if let PhysicalKey::Code(key) = event.physical_key {
+                    if let Some(key) = to_imgui_key(key) {
+                        io.add_key_event(key, pressed);
+                    }
+                }
+                if let Some(text) = event.text.as_ref().filter(|_| pressed) {
+                    for c in text.chars().filter(|c| !c.is_control()) {
+                        io.add_input_character(c);
+                    }
+                }
+            }
+            WindowEvent::ModifiersChanged(modifiers) => {
+                let state = modifiers.state();
+                io.add_key_event(Key::ModCtrl, state.control_key());
+                io.add_key_event(Key::ModShift, state.shift_key());
+                io.add_key_event(Key::ModAlt, state.alt_key());
+                io.add_key_event(Key::ModSuper, state.super_key());
+            }
             WindowEvent::Resized(new_size) => {
                 io.display_size = [new_size.width as f32, new_size.height as f32];
             }
@@ -86,3 +116,31 @@ fn to_imgui_mouse(button: &MouseButton) -> Option<imgui::MouseButton> {
         _ => None,
     }
 }
+
+fn to_imgui_key(key: KeyCode) -> Option<Key> {
+    Some(match key {
+        KeyCode::Tab => Key::Tab,
+        KeyCode::ArrowLeft => Key::LeftArrow,
+        KeyCode::ArrowRight => Key::RightArrow,
+        KeyCode::ArrowUp => Key::UpArrow,
+        KeyCode::ArrowDown => Key::DownArrow,
+        KeyCode::PageUp => Key::PageUp,
+        KeyCode::PageDown => Key::PageDown,
+        KeyCode::Home => Key::Home,
+        KeyCode::End => Key::End,
+        KeyCode::Insert => Key::Insert,
+        KeyCode::Delete => Key::Delete,
+        KeyCode::Backspace => Key::Backspace,
+        KeyCode::Enter => Key::Enter,
+        KeyCode::NumpadEnter => Key::KeypadEnter,
+        KeyCode::Escape => Key::Escape,
+        // Letters used by the editing shortcuts, like copying and undoing.
+        KeyCode::KeyA => Key::A,
+        KeyCode::KeyC => Key::C,
+        KeyCode::KeyV => Key::V,
+        KeyCode::KeyX => Key::X,
+        KeyCode::KeyY => Key::Y,
+        KeyCode::KeyZ => Key::Z,
+        _ => return None,
+    })
+}
@@ -1,16 +1,29 @@
 #![allow(clippy::too_many_arguments)]
 
-use crate::cli::{Args, WindowProtocol};
+use crate::assets::{initialize_assets, verify_assets, REQUIRED_ASSETS};
+use crate::benchmark::{Benchmark, BENCHMARK_DELTA_TIME};
+use crate::cli::{Args, HeadlessArgs, WindowProtocol};
 use crate::config::{DEFAULT_RENDERER_SETTINGS, DEFAULT_VOXEL_CONFIG};
+use crate::config_io::{default_config_path, load_config, save_config};
+#[cfg(feature = "dev-menu")]
+use crate::console::ConsoleContext;
+#[cfg(feature = "dev-menu")]
+use crate::debug_draw::DebugDraw;
+use crate::dynamic_resolution::DynamicResolution;
 use crate::input::InputState;
 #[cfg(feature = "dev-menu")]
 use crate::interface::Interface;
 use crate::logger::{initialize_logger, initialize_panic_hook};
-use crate::mesh::load_mesh;
-use crate::renderer::{Renderer, RendererSettings};
+use crate::mesh::{load_mesh, MeshLoadOptions};
+use crate::recording::{recording_path, DEFAULT_RECORDING_PATH};
+use crate::renderer::codegen::RUNTIME_SHADERS;
+use crate::renderer::{RenderOutput, Renderer, RendererSettings};
 use crate::voxel::{Voxels, VoxelsConfig};
 use crate::world::World;
-use log::{debug, warn};
+use log::{debug, error, info, warn};
+#[cfg(feature = "renderdoc")]
+use renderdoc::{RenderDoc, V141};
+use std::path::{Path, PathBuf};
 use std::time::Instant;
 use winit::application::ApplicationHandler;
 use winit::dpi::PhysicalSize;
@@ -20,15 +33,25 @@ use winit::platform::wayland::EventLoopBuilderExtWayland;
 use winit::platform::x11::EventLoopBuilderExtX11;
 use winit::window::{CursorGrabMode, Fullscreen, Window, WindowId};
 
+mod assets;
+mod benchmark;
 mod camera;
 mod cli;
 mod config;
+mod config_io;
+#[cfg(feature = "dev-menu")]
+mod console;
+#[cfg(feature = "dev-menu")]
+mod debug_draw;
+mod dynamic_resolution;
 mod input;
 #[cfg(feature = "dev-menu")]
 mod interface;
 mod logger;
 mod mesh;
+mod particles;
 mod physics;
+mod recording;
 mod renderer;
 mod util;
 pub mod voxel;
@@ -44,27 +67,59 @@ const VULKAN_ENGINE_VERSION: (u32, u32, u32) = (0, 0, 0);
 const WALK_SPEED: f32 = 25.;
 const SPRINT_SPEED: f32 = 100.;
 const CAMERA_SENSITIVITY: f32 = 0.01;
+// Index of the tetrahedron in the meshes passed to the renderer, which is the cheapest to draw, so
+// that benchmarks with many objects mostly measure the cost per object.
+const BENCHMARK_OBJECT_MESH: usize = 0;
+
+// Every session has its own voxel worker threads, and meshlets only have a byte for telling sessions
+// apart, but memory is what runs out first anyway.
+#[cfg(feature = "dev-menu")]
+const MAX_SESSIONS: usize = 4;
 
 struct AppState {
     window: Option<Window>,
-    world: World,
-    // This depends on the lifetime of Renderer, but there isn't a good way to represent this in
-    // Rust and I actually had a segfault because of this. Do I have to go with self-referential
-    // structs here? Or do I need to fold everything using Vulkan resources into the renderer
-    // struct? Thinking about it, it's probably Arc, because there are worker threads involved.
-    voxels: Option<Voxels>,
-    voxels_config: VoxelsConfig,
+    sessions: Vec<Session>,
+    active_session: usize,
     renderer: Option<Renderer>,
     renderer_settings: RendererSettings,
+    // Values from the settings file that the command line overrode. Saving on exit writes these
+    // back unless they were changed during the run, so that an override only lasts for one run.
+    file_seed: u64,
+    file_time_scale: f32,
     input_state: InputState,
     #[cfg(feature = "dev-menu")]
     interface: Option<Interface>,
+    #[cfg(feature = "dev-menu")]
+    debug_draw: DebugDraw,
     last_window_size: Option<PhysicalSize<u32>>,
     last_frame_timestamp: Instant,
+    dynamic_resolution: DynamicResolution,
     frame_index: usize,
+    // Number of recordings started so far, used to give each one a separate file.
+    recording_count: usize,
+    benchmark: Option<Benchmark>,
+    // Set when a benchmark finished above its threshold, so the process can exit with an error
+    // once the event loop has shut everything down.
+    benchmark_failed: bool,
+    // Only available when the game was started from RenderDoc or with its layer injected.
+    #[cfg(feature = "renderdoc")]
+    renderdoc: Option<RenderDoc<V141>>,
     args: Args,
 }
 
+// A world with its own voxels, so that several can be kept in memory for comparing voxel config
+// changes. Only the active session is updated and drawn, the others keep their chunks resident but
+// don't generate anything new until they are switched to again.
+struct Session {
+    world: World,
+    // This depends on the lifetime of Renderer, but there isn't a good way to represent this in
+    // Rust and I actually had a segfault because of this. Do I have to go with self-referential
+    // structs here? Or do I need to fold everything using Vulkan resources into the renderer
+    // struct? Thinking about it, it's probably Arc, because there are worker threads involved.
+    voxels: Option<Voxels>,
+    voxels_config: VoxelsConfig,
+}
+
 impl ApplicationHandler for AppState {
     fn new_events(&mut self, event_loop: &ActiveEventLoop, cause: StartCause) {
         if cause == StartCause::Init {
@@ -87,14 +142,9 @@ impl ApplicationHandler for AppState {
         }
         window.set_cursor_visible(false);
 
-        let tetrahedron_mesh = load_mesh("assets/tetrahedron.obj");
-        let icosahedron_mesh = load_mesh("assets/icosahedron.obj");
-        let mut renderer = Renderer::new(
-            &window,
-            &[&tetrahedron_mesh, &icosahedron_mesh],
-            &self.world,
-            &self.args,
-        );
+        let session = &mut self.sessions[self.active_session];
+        let mut renderer =
+            create_renderer(RenderOutput::Window(&window), &session.world, &self.args);
 
         #[cfg(feature = "dev-menu")]
         {
@@ -106,17 +156,20 @@ impl ApplicationHandler for AppState {
             self.interface = Some(interface);
         }
 
-        let voxels = Voxels::new(
-            self.voxels_config.clone(),
-            self.world.camera.position(),
-            renderer.voxel_gpu_memory.take().unwrap(),
-            std::thread::available_parallelism().unwrap().get() - 1,
-        );
+        session.voxels = Some(create_voxels(
+            &session.voxels_config,
+            &session.world,
+            &renderer,
+            self.active_session,
+        ));
 
         self.last_window_size = Some(window.inner_size());
         self.window = Some(window);
         self.renderer = Some(renderer);
-        self.voxels = Some(voxels);
+
+        if self.args.record.is_some() {
+            self.toggle_recording();
+        }
     }
 
     fn window_event(&mut self, event_loop: &ActiveEventLoop, _: WindowId, event: WindowEvent) {
@@ -124,7 +177,18 @@ impl ApplicationHandler for AppState {
         self.interface.as_mut().unwrap().apply_window(&event);
         match event {
             WindowEvent::KeyboardInput { event, .. } => self.input_state.apply_keyboard(event),
+            WindowEvent::Focused(false) | WindowEvent::CursorLeft { .. } => {
+                self.input_state.release_all()
+            }
             WindowEvent::Resized(new_size) => {
+                // There's nothing to draw while minimized, so there's no reason to spin the event
+                // loop until the window is restored.
+                let minimized = new_size.width == 0 || new_size.height == 0;
+                event_loop.set_control_flow(if minimized {
+                    ControlFlow::Wait
+                } else {
+                    ControlFlow::Poll
+                });
                 // On app launch under GNOME/Wayland, winit will send a resize event even if
                 // the size happens to be the same (the focus status also seems to change).
                 // Let's avoid rebuilding the pipelines in this case.
@@ -152,7 +216,6 @@ impl ApplicationHandler for AppState {
     }
 
     fn device_event(&mut self, _: &ActiveEventLoop, _: DeviceId, event: DeviceEvent) {
-        // TODO: Handle key release events outside of the window.
         if let DeviceEvent::MouseMotion { delta } = event {
             self.input_state.apply_mouse(delta);
         }
@@ -161,32 +224,77 @@ impl ApplicationHandler for AppState {
     // Desktop applications shouldn't render here according to winit documentation, but this
     // is a game so it's necessary for the game to render even if the camera is not moving.
     // Though I think this approach actually has a problem with input lag. The renderer has
-    // to wait on Vulkan fences internally, so rather, this waiting should be done in a
+    // to wait on Vulkan semaphores internally, so rather, this waiting should be done in a
     // background thread and notifications integrated into winit's event loop?
-    fn about_to_wait(&mut self, _: &ActiveEventLoop) {
+    fn about_to_wait(&mut self, event_loop: &ActiveEventLoop) {
         let current_frame_timestamp = Instant::now();
-        let delta_time = (current_frame_timestamp - self.last_frame_timestamp).as_secs_f32();
+        let cpu_frame_time = current_frame_timestamp - self.last_frame_timestamp;
+        let delta_time = if self.benchmark.is_some() {
+            BENCHMARK_DELTA_TIME
+        } else {
+            cpu_frame_time.as_secs_f32()
+        };
         self.last_frame_timestamp = current_frame_timestamp;
-        self.world.update(delta_time, &self.input_state);
-        self.voxels
-            .as_mut()
-            .unwrap()
-            .update_camera(self.world.camera.position());
+        // Benchmarks compare frame times between runs, which only makes sense at a fixed scale.
+        if self.benchmark.is_none() {
+            self.dynamic_resolution.update(
+                cpu_frame_time.as_secs_f32() * 1000.,
+                &mut self.renderer_settings,
+            );
+        }
+        let session = &mut self.sessions[self.active_session];
+        let voxels = session.voxels.as_ref().unwrap();
+        session.world.update(
+            delta_time,
+            self.renderer_settings.time_scale,
+            &self.input_state,
+            voxels,
+        );
+        voxels.update_camera(
+            session.world.camera.position(),
+            session.voxels_config.max_chunks_per_frame,
+        );
+        voxels.reclaim_gpu_memory();
+        if self.input_state.recording_toggles() % 2 == 1 {
+            self.toggle_recording();
+        }
+        let session_switches = self.input_state.session_switches();
+        if session_switches > 0 {
+            self.switch_session((self.active_session + session_switches) % self.sessions.len());
+        }
+        #[cfg(feature = "renderdoc")]
+        let capture_frame = self.input_state.frame_captures() > 0 && self.renderdoc.is_some();
 
         self.input_state.reset_after_frame();
         #[cfg(feature = "dev-menu")]
         {
+            let config_path = self.settings_path();
+            let session_seeds: Vec<u64> = self
+                .sessions
+                .iter()
+                .map(|session| session.voxels_config.seed)
+                .collect();
+            let session = &mut self.sessions[self.active_session];
             self.interface
                 .as_mut()
                 .unwrap()
                 .apply_cursor(self.input_state.camera_lock, self.window.as_ref().unwrap());
+            self.interface
+                .as_mut()
+                .unwrap()
+                .chunk_inspector
+                .update(session.voxels.as_ref().unwrap());
             let interface_events = self.interface.as_mut().unwrap().build(
-                &mut self.world,
+                &mut session.world,
                 &mut self.renderer_settings,
-                &mut self.voxels_config,
-                self.renderer.as_ref().unwrap().frametime,
+                &mut session.voxels_config,
+                &mut self.input_state,
+                self.renderer.as_ref().unwrap().stats(),
+                session.voxels.as_ref().unwrap().stats(),
+                &session_seeds,
+                self.active_session,
+                self.dynamic_resolution.trend(),
             );
-            assert!(!interface_events.planet_changed);
             if interface_events.rebuild_swapchain {
                 self.renderer
                     .as_mut()
@@ -196,33 +304,212 @@ impl ApplicationHandler for AppState {
                 self.renderer.as_mut().unwrap().recreate_pipelines();
             }
             if interface_events.rebuild_voxels {
-                self.voxels
-                    .as_mut()
+                session
+                    .voxels
+                    .as_ref()
                     .unwrap()
-                    .update_config(self.voxels_config.clone());
+                    .update_config(session.voxels_config.clone());
+            }
+            if interface_events.save_settings {
+                save_config(
+                    &config_path,
+                    &self.renderer_settings,
+                    &session.voxels_config,
+                );
+            }
+            if let Some(line) = interface_events.console_command {
+                self.interface.as_mut().unwrap().console.execute(
+                    &line,
+                    &mut ConsoleContext {
+                        world: &mut session.world,
+                        renderer: self.renderer.as_mut().unwrap(),
+                        renderer_settings: &mut self.renderer_settings,
+                        voxels: session.voxels.as_ref().unwrap(),
+                        voxels_config: &mut session.voxels_config,
+                        config_path: &config_path,
+                        event_loop,
+                    },
+                );
+            }
+
+            self.debug_draw.clear();
+            if self.renderer_settings.debug_draw {
+                session.world.debug_draw(&mut self.debug_draw);
+            }
+            self.interface
+                .as_mut()
+                .unwrap()
+                .chunk_inspector
+                .end_frame(&mut self.debug_draw, session.voxels_config.chunk_size);
+
+            if interface_events.clone_session {
+                self.clone_session();
+            } else if let Some(index) = interface_events.switch_session {
+                self.switch_session(index);
             }
         }
 
+        #[cfg(feature = "renderdoc")]
+        if capture_frame {
+            let renderdoc = self.renderdoc.as_mut().unwrap();
+            renderdoc.start_frame_capture(std::ptr::null(), std::ptr::null());
+        }
+        let session = &mut self.sessions[self.active_session];
         self.renderer.as_mut().unwrap().draw_frame(
-            &self.world,
-            &self.voxels_config,
+            &session.world,
+            &session.voxels_config,
             &self.renderer_settings,
             self.window.as_ref().unwrap().inner_size(),
             #[cfg(feature = "dev-menu")]
-            self.interface.as_mut().unwrap().draw_data(),
+            Some(self.interface.as_mut().unwrap().draw_data()),
+            #[cfg(feature = "dev-menu")]
+            &self.debug_draw,
         );
+        #[cfg(feature = "renderdoc")]
+        if capture_frame {
+            self.end_frame_capture();
+        }
+        session.world.emitted_particles.clear();
 
         if self.renderer.as_ref().unwrap().just_completed_first_render {
             self.window.as_mut().unwrap().set_visible(true);
         }
 
+        if let Some(benchmark) = &mut self.benchmark {
+            let stats = self.renderer.as_ref().unwrap().stats();
+            benchmark.record(cpu_frame_time, stats.frametime, &stats.pass_times);
+            if benchmark.is_finished() {
+                self.benchmark_failed = !benchmark.finish();
+                self.benchmark = None;
+                event_loop.exit();
+            }
+        }
+
         self.frame_index += 1;
     }
 
+    // Benchmarks are meant to run unattended with the same settings every time, so they leave the
+    // settings file alone.
     fn exiting(&mut self, _: &ActiveEventLoop) {
         if let Some(renderer) = self.renderer.take() {
             renderer.wait_idle();
-            self.voxels.take().unwrap().shutdown();
+            for session in &mut self.sessions {
+                if let Some(voxels) = session.voxels.take() {
+                    voxels.shutdown();
+                }
+            }
+        }
+        // Closing the window midway still saves what was measured so far, but doesn't count as
+        // passing.
+        if let Some(benchmark) = self.benchmark.take() {
+            warn!("benchmark interrupted");
+            benchmark.save();
+            self.benchmark_failed = true;
+        }
+        if !self.args.benchmark {
+            self.save_settings_on_exit();
+        }
+    }
+}
+
+impl AppState {
+    fn settings_path(&self) -> PathBuf {
+        self.args
+            .settings
+            .clone()
+            .unwrap_or_else(default_config_path)
+    }
+
+    // The active session is the one whose voxel config gets saved, same as with the save button.
+    fn save_settings_on_exit(&self) {
+        let mut renderer_settings = self.renderer_settings.clone();
+        let mut voxels_config = self.sessions[self.active_session].voxels_config.clone();
+        if self.args.seed == Some(voxels_config.seed) {
+            voxels_config.seed = self.file_seed;
+        }
+        if self.args.time_scale == Some(renderer_settings.time_scale) {
+            renderer_settings.time_scale = self.file_time_scale;
+        }
+        save_config(&self.settings_path(), &renderer_settings, &voxels_config);
+    }
+
+    fn switch_session(&mut self, index: usize) {
+        if index == self.active_session {
+            return;
+        }
+        self.sessions[self.active_session]
+            .voxels
+            .as_ref()
+            .unwrap()
+            .pause();
+        self.active_session = index;
+        let session = &self.sessions[index];
+        session.voxels.as_ref().unwrap().resume();
+        self.renderer
+            .as_mut()
+            .unwrap()
+            .set_active_voxel_session(index as u8);
+        info!(
+            "session switched, \x1B[1msession\x1B[0m: {index}, \x1B[1mseed\x1B[0m: {}",
+            session.voxels_config.seed
+        );
+    }
+
+    // The clone starts from the camera of the current session, so that switching between the two
+    // shows the same view of both worlds.
+    #[cfg(feature = "dev-menu")]
+    fn clone_session(&mut self) {
+        if self.sessions.len() >= MAX_SESSIONS {
+            warn!("session limit reached, \x1B[1mlimit\x1B[0m: {MAX_SESSIONS}");
+            return;
+        }
+        let current = &self.sessions[self.active_session];
+        let voxels_config = current.voxels_config.clone();
+        let mut world = World::new(voxels_config.seed);
+        world.set_physics_mode(current.world.physics_mode());
+        world.teleport(current.world.camera.position());
+        world.time_of_day = current.world.time_of_day;
+        world.sun_pause = current.world.sun_pause;
+        let voxels = create_voxels(
+            &voxels_config,
+            &world,
+            self.renderer.as_ref().unwrap(),
+            self.sessions.len(),
+        );
+        self.sessions.push(Session {
+            world,
+            voxels: Some(voxels),
+            voxels_config,
+        });
+        self.switch_session(self.sessions.len() - 1);
+    }
+
+    fn toggle_recording(&mut self) {
+        let renderer = self.renderer.as_mut().unwrap();
+        if renderer.is_recording() {
+            renderer.stop_recording();
+            return;
+        }
+        let base = self
+            .args
+            .record
+            .as_deref()
+            .unwrap_or(Path::new(DEFAULT_RECORDING_PATH));
+        renderer.start_recording(&recording_path(base, self.recording_count));
+        self.recording_count += 1;
+    }
+
+    #[cfg(feature = "renderdoc")]
+    fn end_frame_capture(&mut self) {
+        let renderdoc = self.renderdoc.as_mut().unwrap();
+        renderdoc.end_frame_capture(std::ptr::null(), std::ptr::null());
+        let capture_count = renderdoc.get_num_captures();
+        match capture_count
+            .checked_sub(1)
+            .and_then(|index| renderdoc.get_capture(index))
+        {
+            Some((path, _)) => info!("frame captured, \x1B[1mpath\x1B[0m: {}", path.display()),
+            None => warn!("frame capture failed"),
         }
     }
 }
@@ -231,24 +518,155 @@ pub fn main() {
     initialize_logger();
     initialize_panic_hook();
     let args = Args::parse();
+    initialize_assets(args.assets.clone());
+    if let Err(error) = verify_assets(REQUIRED_ASSETS.iter().chain(RUNTIME_SHADERS).copied()) {
+        error!("{error}");
+        std::process::exit(1);
+    }
+    // Everything that depends on the settings is created from them later, or checks them for
+    // changes every frame, so there's nothing else to update after loading. A missing file is
+    // only worth a warning when it was asked for explicitly.
+    let config_path = args.settings.clone().unwrap_or_else(default_config_path);
+    let (mut renderer_settings, mut voxels_config) =
+        if args.settings.is_some() || config_path.exists() {
+            load_config(&config_path)
+        } else {
+            (DEFAULT_RENDERER_SETTINGS, DEFAULT_VOXEL_CONFIG)
+        };
+    let file_seed = voxels_config.seed;
+    let file_time_scale = renderer_settings.time_scale;
+    if let Some(seed) = args.seed {
+        voxels_config.seed = seed;
+    }
+    info!("world seed, \x1B[1mseed\x1B[0m: {}", voxels_config.seed);
+    // The sun starts paused, so asking for a time scale wouldn't do anything without also starting
+    // the day-night cycle.
+    let mut world = World::new(voxels_config.seed);
+    world.spawn_benchmark_objects(args.benchmark_objects, BENCHMARK_OBJECT_MESH);
+    if let Some(time_scale) = args.time_scale {
+        renderer_settings.time_scale = time_scale;
+        world.sun_pause = false;
+    }
+    if let Some(headless) = &args.headless {
+        let success = run_headless(headless, world, &renderer_settings, &voxels_config, &args);
+        std::process::exit(if success { 0 } else { 1 });
+    }
+
     let event_loop = create_event_loop(&args);
 
     let mut app_state = AppState {
         window: None,
-        world: World::new(),
-        voxels: None,
-        voxels_config: DEFAULT_VOXEL_CONFIG,
+        sessions: vec![Session {
+            world,
+            voxels: None,
+            voxels_config,
+        }],
+        active_session: 0,
         input_state: InputState::new(),
         last_window_size: None,
         last_frame_timestamp: Instant::now(),
+        dynamic_resolution: DynamicResolution::new(),
         renderer: None,
-        renderer_settings: DEFAULT_RENDERER_SETTINGS,
+        renderer_settings,
+        file_seed,
+        file_time_scale,
         #[cfg(feature = "dev-menu")]
         interface: None,
+        #[cfg(feature = "dev-menu")]
+        debug_draw: DebugDraw::new(),
         frame_index: 0,
+        recording_count: 0,
+        benchmark: args.benchmark.then(|| {
+            Benchmark::new(
+                args.benchmark_csv.clone(),
+                args.benchmark_threshold_p99,
+                args.benchmark_warmup_frames,
+            )
+        }),
+        benchmark_failed: false,
+        // Has to be loaded before creating the Vulkan instance, so that the capture layer is
+        // already hooked in by the time the renderer starts.
+        #[cfg(feature = "renderdoc")]
+        renderdoc: match RenderDoc::new() {
+            Ok(renderdoc) => Some(renderdoc),
+            Err(error) => {
+                debug!("renderdoc unavailable, \x1B[1merror\x1B[0m: {error}");
+                None
+            }
+        },
         args,
     };
     event_loop.run_app(&mut app_state).unwrap();
+    if app_state.benchmark_failed {
+        std::process::exit(1);
+    }
+}
+
+// Renders a fixed number of frames with a fixed time step, so the same arguments always produce the
+// same sequence. The exception is voxel meshing, which happens on worker threads, so chunks can
+// show up a frame or two earlier or later depending on the machine. Returns whether the output
+// directory could be created.
+fn run_headless(
+    headless: &HeadlessArgs,
+    mut world: World,
+    renderer_settings: &RendererSettings,
+    voxels_config: &VoxelsConfig,
+    args: &Args,
+) -> bool {
+    let mut renderer = create_renderer(RenderOutput::Offscreen(headless.resolution), &world, args);
+    let voxels = create_voxels(voxels_config, &world, &renderer, 0);
+    let started = renderer.start_image_sequence(&headless.out);
+    if let Err(error) = &started {
+        error!("{error}");
+    } else {
+        let input_state = InputState::new();
+        #[cfg(feature = "dev-menu")]
+        let debug_draw = DebugDraw::new();
+        for _ in 0..headless.frames {
+            world.update(
+                BENCHMARK_DELTA_TIME,
+                renderer_settings.time_scale,
+                &input_state,
+                &voxels,
+            );
+            voxels.update_camera(world.camera.position(), voxels_config.max_chunks_per_frame);
+            renderer.draw_frame(
+                &world,
+                voxels_config,
+                renderer_settings,
+                headless.resolution,
+                #[cfg(feature = "dev-menu")]
+                None,
+                #[cfg(feature = "dev-menu")]
+                &debug_draw,
+            );
+            world.emitted_particles.clear();
+        }
+        renderer.stop_recording();
+    }
+    renderer.wait_idle();
+    voxels.shutdown();
+    started.is_ok()
+}
+
+fn create_renderer(output: RenderOutput, world: &World, args: &Args) -> Renderer {
+    let tetrahedron_mesh = load_mesh("assets/tetrahedron.obj", &MeshLoadOptions::FLAT);
+    let icosahedron_mesh = load_mesh("assets/icosahedron.obj", &MeshLoadOptions::FLAT);
+    Renderer::new(output, &[&tetrahedron_mesh, &icosahedron_mesh], world, args)
+}
+
+fn create_voxels(
+    voxels_config: &VoxelsConfig,
+    world: &World,
+    renderer: &Renderer,
+    session: usize,
+) -> Voxels {
+    Voxels::new(
+        voxels_config.clone(),
+        world.camera.position(),
+        renderer.voxel_session_memory(session as u8),
+        std::thread::available_parallelism().unwrap().get() - 1,
+    )
 }
 
 fn create_event_loop(args: &Args) -> EventLoop<()> {
@@ -1,48 +1,184 @@
+use crate::renderer::RendererSettings;
+use ash::vk;
 use log::{error, Level, LevelFilter, Metadata, Record};
-use std::panic::PanicHookInfo;
-use std::sync::OnceLock;
-use std::time::Instant;
+use std::any::Any;
+use std::backtrace::Backtrace;
+use std::cell::Cell;
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::Write;
+use std::panic::{AssertUnwindSafe, PanicHookInfo};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Mutex, MutexGuard, OnceLock};
+use std::time::{Instant, SystemTime};
 
 struct Logger {
     time_start: Instant,
+    filter: LogFilter,
+    file: Mutex<Option<File>>,
+    recent: Mutex<VecDeque<String>>,
+}
+
+// Information about the renderer included in crash reports. Most GPU-related crashes only happen
+// on some hardware or with some settings, and the logs alone usually don't say which.
+pub struct RendererContext {
+    pub device_name: String,
+    pub swapchain_extent: vk::Extent2D,
+    pub settings: Option<RendererSettings>,
+}
+
+// Per-module maximum levels parsed from RUST_LOG, like vulkthing::renderer::debug=warn. The most
+// specific matching module wins, and a directive without a module sets the default.
+#[derive(Debug, PartialEq)]
+struct LogFilter {
+    default: LevelFilter,
+    modules: Vec<(String, LevelFilter)>,
+}
+
+const LOG_FILE_NAME: &str = "log.txt";
+const LOG_FILE_KEPT_RUNS: usize = 3;
+const RECENT_LINE_COUNT: usize = 200;
+
+static LOGGER: OnceLock<Logger> = OnceLock::new();
+static RENDERER_CONTEXT: Mutex<Option<RendererContext>> = Mutex::new(None);
+static FRAME_INDEX: AtomicUsize = AtomicUsize::new(0);
+
+thread_local! {
+    // Set while running code whose panics are caught and recovered from, which are still logged
+    // but aren't crashes, so they shouldn't leave crash reports behind.
+    static CATCHING_PANICS: Cell<bool> = const { Cell::new(false) };
 }
 
 impl log::Log for Logger {
     fn enabled(&self, metadata: &Metadata) -> bool {
         metadata.target().starts_with(env!("CARGO_PKG_NAME"))
+            && metadata.level() <= self.filter.max_level(metadata.target())
     }
 
     fn log(&self, record: &Record) {
         if self.enabled(record.metadata()) {
             let time_now = Instant::now();
             let time = (time_now - self.time_start).as_secs_f64();
-            let level = match record.level() {
-                Level::Error => "\x1B[1;31mERRO\x1B[0m",
-                Level::Warn => "\x1B[1;33mWARN\x1B[0m",
-                Level::Info => "\x1B[1;32mINFO\x1B[0m",
-                Level::Debug => "\x1B[1;36mDEBG\x1B[0m",
-                Level::Trace => "\x1B[1;34mTRCE\x1B[0m",
+            let (level, color) = match record.level() {
+                Level::Error => ("ERRO", "1;31"),
+                Level::Warn => ("WARN", "1;33"),
+                Level::Info => ("INFO", "1;32"),
+                Level::Debug => ("DEBG", "1;36"),
+                Level::Trace => ("TRCE", "1;34"),
             };
-            println!("[{time:>12.6}] {level} {}", record.args());
+            let message = record.args().to_string();
+            println!("[{time:>12.6}] \x1B[{color}m{level}\x1B[0m {message}");
+
+            // Files shouldn't contain terminal escape codes, as they are mostly going to be read in
+            // text editors or attached to bug reports.
+            let line = format!("[{time:>12.6}] {level} {}", strip_escape_codes(&message));
+            if let Some(file) = lock_ignoring_poison(&self.file).as_mut() {
+                let _ = writeln!(file, "{line}");
+            }
+            let mut recent = lock_ignoring_poison(&self.recent);
+            if recent.len() == RECENT_LINE_COUNT {
+                recent.pop_front();
+            }
+            recent.push_back(line);
         }
     }
 
-    fn flush(&self) {}
+    fn flush(&self) {
+        if let Some(file) = lock_ignoring_poison(&self.file).as_mut() {
+            let _ = file.flush();
+        }
+    }
 }
 
-static LOGGER: OnceLock<Logger> = OnceLock::new();
-
 pub fn initialize_logger() {
     let time_start = Instant::now();
-    let logger = LOGGER.get_or_init(|| Logger { time_start });
+    let file = open_log_file();
+    let filter = LogFilter::parse(&std::env::var("RUST_LOG").unwrap_or_default());
+    let max_level = filter.max_level_overall();
+    let logger = LOGGER.get_or_init(|| Logger {
+        time_start,
+        filter,
+        file: Mutex::new(file),
+        recent: Mutex::new(VecDeque::with_capacity(RECENT_LINE_COUNT)),
+    });
     log::set_logger(logger).unwrap();
-    log::set_max_level(LevelFilter::Trace);
+    log::set_max_level(max_level);
 }
 
 pub fn initialize_panic_hook() {
     std::panic::set_hook(Box::new(panic_hook));
 }
 
+pub fn set_renderer_context(context: RendererContext) {
+    *lock_ignoring_poison(&RENDERER_CONTEXT) = Some(context);
+}
+
+// Called every frame, so the settings are only copied when they actually changed. Comparing them is
+// much cheaper than cloning, as it doesn't allocate.
+pub fn update_renderer_context(frame_index: usize, settings: &RendererSettings) {
+    FRAME_INDEX.store(frame_index, Ordering::Relaxed);
+    if let Some(context) = lock_ignoring_poison(&RENDERER_CONTEXT).as_mut() {
+        if context.settings.as_ref() != Some(settings) {
+            context.settings = Some(settings.clone());
+        }
+    }
+}
+
+pub fn catch_unwind_silently<R>(f: impl FnOnce() -> R) -> Result<R, Box<dyn Any + Send>> {
+    let was_catching = CATCHING_PANICS.replace(true);
+    let result = std::panic::catch_unwind(AssertUnwindSafe(f));
+    CATCHING_PANICS.set(was_catching);
+    result
+}
+
+pub fn update_renderer_swapchain_extent(swapchain_extent: vk::Extent2D) {
+    if let Some(context) = lock_ignoring_poison(&RENDERER_CONTEXT).as_mut() {
+        context.swapchain_extent = swapchain_extent;
+    }
+}
+
+impl LogFilter {
+    // Everything is logged by default, and invalid directives are ignored, as there's no logger
+    // to report them to yet.
+    fn parse(spec: &str) -> LogFilter {
+        let mut filter = LogFilter {
+            default: LevelFilter::Trace,
+            modules: Vec::new(),
+        };
+        for directive in spec.split(',').map(str::trim).filter(|d| !d.is_empty()) {
+            match directive.split_once('=') {
+                Some((module, level)) => {
+                    if let Ok(level) = level.parse() {
+                        filter.modules.push((module.to_owned(), level));
+                    }
+                }
+                None => {
+                    if let Ok(level) = directive.parse() {
+                        filter.default = level;
+                    }
+                }
+            }
+        }
+        filter
+    }
+
+    fn max_level(&self, target: &str) -> LevelFilter {
+        self.modules
+            .iter()
+            .filter(|(module, _)| target == module || target.starts_with(&format!("{module}::")))
+            .max_by_key(|(module, _)| module.len())
+            .map_or(self.default, |(_, level)| *level)
+    }
+
+    fn max_level_overall(&self) -> LevelFilter {
+        self.modules
+            .iter()
+            .map(|(_, level)| *level)
+            .fold(self.default, Ord::max)
+    }
+}
+
 fn panic_hook(info: &PanicHookInfo) {
     let full_message = info.to_string();
     let message = if let Some((_, message)) = full_message.split_once('\n') {
@@ -55,4 +191,141 @@ fn panic_hook(info: &PanicHookInfo) {
     } else {
         error!("{message}");
     }
+    log::logger().flush();
+    if CATCHING_PANICS.get() {
+        return;
+    }
+
+    // The window usually covers the terminal when the game crashes in fullscreen, so the important
+    // details are also saved to a file. Stderr only gets the panic message with its location and
+    // the path, as the full report with the backtrace is rather long.
+    let report = crash_report(&full_message);
+    let Some(directory) = state_directory() else {
+        eprintln!("{report}");
+        return;
+    };
+    let timestamp = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map_or(0, |duration| duration.as_secs());
+    let path = directory.join(format!("crash-{timestamp}.txt"));
+    match std::fs::write(&path, &report) {
+        Ok(()) => eprintln!("{full_message}\ncrash report saved to {}", path.display()),
+        Err(_) => eprintln!("{report}"),
+    }
+}
+
+fn crash_report(panic_message: &str) -> String {
+    let mut report = String::new();
+    report += &format!("{panic_message}\n\n");
+    report += &format!("Backtrace:\n{}\n\n", Backtrace::force_capture());
+    report += &format!("Frame index: {}\n", FRAME_INDEX.load(Ordering::Relaxed));
+    // The panic could have happened while the context was being updated, but it's still better to
+    // have possibly stale information than none at all.
+    if let Some(context) = lock_ignoring_poison(&RENDERER_CONTEXT).as_ref() {
+        report += &format!("Device: {}\n", context.device_name);
+        report += &format!(
+            "Swapchain extent: {}x{}\n",
+            context.swapchain_extent.width, context.swapchain_extent.height
+        );
+        report += &format!("Renderer settings: {:#?}\n", context.settings);
+    }
+    report += "\nRecent log:\n";
+    if let Some(logger) = LOGGER.get() {
+        for line in lock_ignoring_poison(&logger.recent).iter() {
+            report += line;
+            report += "\n";
+        }
+    }
+    report
+}
+
+// Logs from the previous few runs are kept around, as the interesting run is often not the latest
+// one by the time someone looks at them.
+fn open_log_file() -> Option<File> {
+    let directory = state_directory()?;
+    let log_path = |index: usize| match index {
+        0 => directory.join(LOG_FILE_NAME),
+        _ => directory.join(format!("log.{index}.txt")),
+    };
+    for index in (1..LOG_FILE_KEPT_RUNS).rev() {
+        let _ = std::fs::rename(log_path(index - 1), log_path(index));
+    }
+    File::create(log_path(0)).ok()
+}
+
+fn state_directory() -> Option<PathBuf> {
+    let base = match std::env::var_os("XDG_STATE_HOME") {
+        Some(state_home) => PathBuf::from(state_home),
+        None => PathBuf::from(std::env::var_os("HOME")?).join(".local/state"),
+    };
+    let directory = base.join(env!("CARGO_PKG_NAME"));
+    std::fs::create_dir_all(&directory).ok()?;
+    Some(directory)
+}
+
+fn strip_escape_codes(text: &str) -> String {
+    let mut stripped = String::with_capacity(text.len());
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        if c == '\x1B' {
+            for c in chars.by_ref() {
+                if c.is_ascii_alphabetic() {
+                    break;
+                }
+            }
+        } else {
+            stripped.push(c);
+        }
+    }
+    stripped
+}
+
+// Logging and crash reporting must keep working even if some thread panicked while holding one of
+// these locks, as that's exactly when they are the most useful.
+fn lock_ignoring_poison<T>(mutex: &Mutex<T>) -> MutexGuard<T> {
+    mutex
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn catching_panics_is_scoped() {
+        let result = catch_unwind_silently(|| {
+            assert!(CATCHING_PANICS.get());
+            let inner = catch_unwind_silently(|| panic!("chunk generation failed"));
+            assert!(inner.is_err());
+            assert!(CATCHING_PANICS.get());
+        });
+        assert!(result.is_ok());
+        assert!(!CATCHING_PANICS.get());
+    }
+
+    #[test]
+    fn log_filter_modules() {
+        let filter = LogFilter::parse("info,vulkthing::renderer::debug=warn,vulkthing::voxel=off");
+        assert_eq!(filter.max_level("vulkthing::world"), LevelFilter::Info);
+        assert_eq!(
+            filter.max_level("vulkthing::renderer::debug"),
+            LevelFilter::Warn
+        );
+        assert_eq!(filter.max_level("vulkthing::voxel::gpu"), LevelFilter::Off);
+        assert_eq!(filter.max_level("vulkthing::voxels"), LevelFilter::Info);
+        assert_eq!(filter.max_level_overall(), LevelFilter::Info);
+    }
+
+    #[test]
+    fn log_filter_default() {
+        assert_eq!(
+            LogFilter::parse("").max_level("vulkthing"),
+            LevelFilter::Trace
+        );
+        assert_eq!(
+            LogFilter::parse("debug,=,nonsense=loud").max_level("vulkthing"),
+            LevelFilter::Debug
+        );
+    }
 }
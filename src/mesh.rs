@@ -1,15 +1,69 @@
+use crate::assets::asset_path;
 use crate::renderer::vertex::Vertex;
-use log::debug;
-use nalgebra::Vector3;
+use gltf::mesh::Mode;
+use log::{debug, warn};
+use nalgebra::{Vector3, Vector4};
+use std::collections::HashMap;
+use std::path::Path;
 use tobj::LoadOptions;
 
 #[derive(Clone, Debug)]
 pub struct MeshData<V> {
     pub vertices: Vec<V>,
     pub indices: Vec<u32>,
+    pub primitives: Vec<MeshPrimitive>,
 }
 
-pub fn load_mesh(obj_path: &str) -> MeshData<Vertex> {
+// Meshes made of multiple primitives are merged into a single vertex buffer, and this keeps track
+// of which part of it came from where. The renderer doesn't support materials yet, so apart from
+// the base color this is only kept around for later.
+#[allow(dead_code)]
+#[derive(Clone, Debug)]
+pub struct MeshPrimitive {
+    pub first_index: usize,
+    pub index_count: usize,
+    pub base_color: Vector4<f32>,
+}
+
+pub struct MeshLoadOptions {
+    // Normals are only computed if the file doesn't contain them. Faces sharing a vertex are
+    // smoothed together if the angle between them is at most this, so that sharp edges of
+    // otherwise smooth meshes stay sharp. Zero results in flat shading.
+    pub smoothing_angle: f32,
+    // Loaders emit a separate vertex for each triangle corner, which is simple but wasteful.
+    // Deduplicating them and reordering triangles for the post-transform vertex cache is worth it
+    // for anything loaded once, but meshes generated at runtime might want to skip it.
+    pub optimize: bool,
+}
+
+impl MeshLoadOptions {
+    pub const FLAT: MeshLoadOptions = MeshLoadOptions {
+        smoothing_angle: 0.,
+        optimize: true,
+    };
+}
+
+// Takes the asset name relative to the base directory, like assets/tetrahedron.obj.
+pub fn load_mesh(name: &str, options: &MeshLoadOptions) -> MeshData<Vertex> {
+    let path = asset_path(name);
+    let mut mesh = match path.extension().and_then(|extension| extension.to_str()) {
+        Some("obj") => load_obj(&path, options),
+        Some("gltf" | "glb") => load_gltf(&path, options),
+        _ => panic!("unsupported mesh format, file: {name}"),
+    };
+    if options.optimize {
+        let old_vertex_count = mesh.vertices.len();
+        optimize_mesh(&mut mesh);
+        debug!(
+            "mesh optimized, \x1B[1mfile\x1B[0m: {name}, \x1B[1mvertices\x1B[0m: {old_vertex_count} -> {}",
+            mesh.vertices.len()
+        );
+    }
+    mesh
+}
+
+fn load_obj(path: &Path, options: &MeshLoadOptions) -> MeshData<Vertex> {
+    let obj_path = path.display();
     let load_options = LoadOptions {
         // Faces can sometimes be given as arbitrary (convex?) polygons, but we only render
         // triangles so let's get the loader to split them up for us.
@@ -19,8 +73,8 @@ pub fn load_mesh(obj_path: &str) -> MeshData<Vertex> {
         single_index: true,
         ..Default::default()
     };
-    let meshes = tobj::load_obj(obj_path, &load_options).unwrap().0;
-    let mesh = flatten_meshes(&meshes);
+    let meshes = tobj::load_obj(path, &load_options).unwrap().0;
+    let mesh = flatten_meshes(&meshes, options);
     debug!(
         "mesh OBJ loaded, \x1B[1mfile\x1B[0m: {obj_path}, \x1B[1mvertices\x1B[0m: {}",
         mesh.vertices.len()
@@ -28,34 +82,250 @@ pub fn load_mesh(obj_path: &str) -> MeshData<Vertex> {
     mesh
 }
 
-fn flatten_meshes(models: &[tobj::Model]) -> MeshData<Vertex> {
+fn flatten_meshes(models: &[tobj::Model], options: &MeshLoadOptions) -> MeshData<Vertex> {
     // OBJ format supports quite complex meshes with many materials and meshes, but temporarily
     // let's just throw all of it into a single vertex buffer.
     let mut vertices = Vec::new();
     for model in models {
+        let has_normals = !model.mesh.normals.is_empty();
+        let first_vertex = vertices.len();
         for index in &model.mesh.indices {
-            // Position vectors are stored as unpacked arrays of floats.
+            // Position and normal vectors are stored as unpacked arrays of floats.
             let offset = (3 * *index) as usize;
             let position = Vector3::new(
                 model.mesh.positions[offset],
                 model.mesh.positions[offset + 1],
                 model.mesh.positions[offset + 2],
             );
-            // Will be computed from triangle positions later.
-            let normal = Vector3::zeros();
+            let normal = if has_normals {
+                Vector3::new(
+                    model.mesh.normals[offset],
+                    model.mesh.normals[offset + 1],
+                    model.mesh.normals[offset + 2],
+                )
+            } else {
+                // Will be computed from triangle positions later.
+                Vector3::zeros()
+            };
             let vertex = Vertex { position, normal };
             vertices.push(vertex);
         }
+        if !has_normals {
+            compute_normals(
+                &mut vertices[first_vertex..],
+                &model.mesh.indices,
+                options.smoothing_angle,
+            );
+        }
+    }
+    let indices = (0..vertices.len() as u32).collect();
+    let primitives = vec![MeshPrimitive {
+        first_index: 0,
+        index_count: vertices.len(),
+        base_color: Vector4::new(1., 1., 1., 1.),
+    }];
+    MeshData {
+        vertices,
+        indices,
+        primitives,
+    }
+}
+
+// Both the text format with external buffers and the binary format are handled by the gltf crate.
+// Only mesh geometry is loaded, without applying node transforms, as all the assets so far are
+// single objects exported at the origin anyway.
+fn load_gltf(path: &Path, options: &MeshLoadOptions) -> MeshData<Vertex> {
+    let gltf_path = path.display();
+    let (document, buffers, _) = gltf::import(path).unwrap();
+    if document.skins().next().is_some() {
+        warn!("glTF skins are not supported, \x1B[1mfile\x1B[0m: {gltf_path}");
+    }
+    if document.animations().next().is_some() {
+        warn!("glTF animations are not supported, \x1B[1mfile\x1B[0m: {gltf_path}");
+    }
+    let mut vertices = Vec::new();
+    let mut primitives = Vec::new();
+    for mesh in document.meshes() {
+        for primitive in mesh.primitives() {
+            if primitive.mode() != Mode::Triangles {
+                warn!(
+                    "glTF primitive mode not supported, \x1B[1mfile\x1B[0m: {gltf_path}, \x1B[1mmode\x1B[0m: {:?}",
+                    primitive.mode()
+                );
+                continue;
+            }
+            let reader = primitive.reader(|buffer| Some(&buffers[buffer.index()]));
+            let positions: Vec<_> = reader.read_positions().unwrap().collect();
+            let normals: Option<Vec<_>> = reader.read_normals().map(|normals| normals.collect());
+            let source_indices: Vec<u32> = match reader.read_indices() {
+                Some(indices) => indices.into_u32().collect(),
+                None => (0..positions.len() as u32).collect(),
+            };
+            let first_vertex = vertices.len();
+            for index in &source_indices {
+                let index = *index as usize;
+                let normal = match &normals {
+                    Some(normals) => Vector3::from(normals[index]),
+                    None => Vector3::zeros(),
+                };
+                vertices.push(Vertex {
+                    position: Vector3::from(positions[index]),
+                    normal,
+                });
+            }
+            if normals.is_none() {
+                compute_normals(
+                    &mut vertices[first_vertex..],
+                    &source_indices,
+                    options.smoothing_angle,
+                );
+            }
+            let base_color = primitive
+                .material()
+                .pbr_metallic_roughness()
+                .base_color_factor();
+            primitives.push(MeshPrimitive {
+                first_index: first_vertex,
+                index_count: source_indices.len(),
+                base_color: Vector4::from(base_color),
+            });
+        }
     }
+    debug!(
+        "mesh glTF loaded, \x1B[1mfile\x1B[0m: {gltf_path}, \x1B[1mvertices\x1B[0m: {}",
+        vertices.len()
+    );
     let indices = (0..vertices.len() as u32).collect();
-    for v123 in vertices.chunks_mut(3) {
-        let [v1, v2, v3] = v123 else { unreachable!() };
-        let normal = (v2.position - v1.position)
-            .cross(&(v3.position - v1.position))
-            .normalize();
-        v1.normal = normal;
-        v2.normal = normal;
-        v3.normal = normal;
-    }
-    MeshData { vertices, indices }
+    MeshData {
+        vertices,
+        indices,
+        primitives,
+    }
+}
+
+// Vertices are given as a triangle list, with the indices from the source file used to find which
+// triangles share a vertex. Face normals are weighted by triangle area when averaging, which comes
+// for free by not normalizing the cross product. Degenerate triangles have no direction, so they
+// don't contribute to their neighbours, and vertices left without any normal point up rather than
+// becoming NaN.
+fn compute_normals(vertices: &mut [Vertex], source_indices: &[u32], smoothing_angle: f32) {
+    let face_normals: Vec<Vector3<f32>> = vertices
+        .chunks(3)
+        .map(|v123| {
+            let [v1, v2, v3] = v123 else { unreachable!() };
+            (v2.position - v1.position).cross(&(v3.position - v1.position))
+        })
+        .collect();
+    let mut adjacent_faces: HashMap<u32, Vec<usize>> = HashMap::new();
+    for (corner, source_index) in source_indices.iter().enumerate() {
+        adjacent_faces
+            .entry(*source_index)
+            .or_default()
+            .push(corner / 3);
+    }
+    let min_cos = smoothing_angle.cos();
+    for (corner, vertex) in vertices.iter_mut().enumerate() {
+        let face = face_normals[corner / 3];
+        let mut normal = face;
+        if let Some(face_direction) = face.try_normalize(f32::EPSILON) {
+            if smoothing_angle > 0. {
+                normal = Vector3::zeros();
+                for adjacent in &adjacent_faces[&source_indices[corner]] {
+                    let adjacent = face_normals[*adjacent];
+                    let Some(adjacent_direction) = adjacent.try_normalize(f32::EPSILON) else {
+                        continue;
+                    };
+                    if face_direction.dot(&adjacent_direction) >= min_cos {
+                        normal += adjacent;
+                    }
+                }
+            }
+        }
+        vertex.normal = normal
+            .try_normalize(f32::EPSILON)
+            .unwrap_or_else(Vector3::z);
+    }
+}
+
+// Vertices are compared bitwise, which is fine as duplicates always come from the same values in
+// the source file. Triangles are only reordered within each primitive, so that the primitive index
+// ranges stay valid.
+fn optimize_mesh(mesh: &mut MeshData<Vertex>) {
+    let mut unique_vertices = Vec::new();
+    let mut vertex_indices = HashMap::new();
+    let mut indices = Vec::with_capacity(mesh.indices.len());
+    for index in &mesh.indices {
+        let vertex = mesh.vertices[*index as usize];
+        let key = [
+            vertex.position.x.to_bits(),
+            vertex.position.y.to_bits(),
+            vertex.position.z.to_bits(),
+            vertex.normal.x.to_bits(),
+            vertex.normal.y.to_bits(),
+            vertex.normal.z.to_bits(),
+        ];
+        let unique_index = *vertex_indices.entry(key).or_insert_with(|| {
+            unique_vertices.push(vertex);
+            unique_vertices.len() as u32 - 1
+        });
+        indices.push(unique_index);
+    }
+    for primitive in &mesh.primitives {
+        let range = primitive.first_index..primitive.first_index + primitive.index_count;
+        let optimized =
+            meshopt::optimize_vertex_cache(&indices[range.clone()], unique_vertices.len());
+        indices[range].copy_from_slice(&optimized);
+    }
+    mesh.vertices = unique_vertices;
+    mesh.indices = indices;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn triangle_list(positions: &[Vector3<f32>], indices: &[u32]) -> Vec<Vertex> {
+        indices
+            .iter()
+            .map(|index| Vertex {
+                position: positions[*index as usize],
+                normal: Vector3::zeros(),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn quad_without_normals() {
+        let positions = [
+            Vector3::new(0., 0., 0.),
+            Vector3::new(1., 0., 0.),
+            Vector3::new(1., 1., 0.),
+            Vector3::new(0., 1., 0.),
+        ];
+        let indices = [0, 1, 2, 0, 2, 3];
+        for smoothing_angle in [0., 0.5] {
+            let mut vertices = triangle_list(&positions, &indices);
+            compute_normals(&mut vertices, &indices, smoothing_angle);
+            for vertex in &vertices {
+                assert_eq!(vertex.normal, Vector3::z());
+            }
+        }
+    }
+
+    #[test]
+    fn degenerate_triangle_normals_are_finite() {
+        let positions = [
+            Vector3::new(0., 0., 0.),
+            Vector3::new(1., 0., 0.),
+            Vector3::new(2., 0., 0.),
+            Vector3::new(0., 1., 0.),
+        ];
+        let indices = [0, 1, 2, 0, 1, 3];
+        let mut vertices = triangle_list(&positions, &indices);
+        compute_normals(&mut vertices, &indices, 0.5);
+        for vertex in &vertices {
+            assert!(vertex.normal.iter().all(|x| x.is_finite()));
+        }
+        assert_eq!(vertices[3].normal, Vector3::z());
+    }
 }
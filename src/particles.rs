@@ -0,0 +1,162 @@
+use crate::config::{
+    DEFAULT_PARTICLE_EMIT_MAX_COUNT, DEFAULT_PARTICLE_GRAVITY, DEFAULT_PARTICLE_MAX_COUNT,
+};
+use crate::renderer::uniform::ParticlesUniform;
+use crate::renderer::util::{Dev, MemoryTag, StorageBuffer};
+use crate::renderer::VRAM_VIA_BAR;
+use crate::util::RandomDirection;
+use ash::{vk, Device};
+use log::warn;
+use nalgebra::{Vector3, Vector4};
+use rand::Rng;
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+pub struct Particle {
+    pub position: Vector3<f32>,
+    pub _pad0: f32,
+    pub velocity: Vector3<f32>,
+    pub lifetime: f32,
+    pub color: Vector4<f32>,
+}
+
+#[derive(Clone, Debug)]
+pub struct EmitSettings {
+    pub speed: f32,
+    // Fraction of the speed that is randomized, so that the particles don't all move as one shell.
+    pub spread: f32,
+    pub lifetime: f32,
+    pub color: Vector4<f32>,
+}
+
+// Particles live entirely on the GPU, and are simulated by a compute shader that reads the previous
+// frame's particles from one region of the buffer and appends the survivors to the other one. The
+// count of each region is kept in the instance count of its draw command, so the particles can be
+// drawn without the CPU ever knowing how many are alive. Particles emitted on the CPU are written
+// to a per-flight staging area and appended by the same shader.
+pub struct ParticleSystem {
+    particles: StorageBuffer<[Particle]>,
+    emitted: StorageBuffer<[Particle]>,
+    draw_commands: StorageBuffer<[vk::DrawIndirectCommand]>,
+    // Only used for statistics, and lags behind by a few frames as it's read back from the GPU.
+    pub particle_count: u32,
+    // Region holding the particles from the previous frame, which the next update reads from.
+    source: usize,
+    emitted_count: usize,
+    last_time: Option<f32>,
+    delta_time: f32,
+}
+
+impl ParticleSystem {
+    pub fn new(frames_in_flight: usize, dev: &Dev) -> ParticleSystem {
+        let particles = StorageBuffer::new_array(
+            vk::MemoryPropertyFlags::DEVICE_LOCAL,
+            2 * DEFAULT_PARTICLE_MAX_COUNT,
+            MemoryTag::Storage,
+            dev,
+        );
+        let emitted = StorageBuffer::new_array(
+            VRAM_VIA_BAR,
+            DEFAULT_PARTICLE_EMIT_MAX_COUNT * frames_in_flight,
+            MemoryTag::Storage,
+            dev,
+        );
+        let mut draw_commands = StorageBuffer::new_indirect_array(2, dev);
+        draw_commands.generate(|region| vk::DrawIndirectCommand {
+            vertex_count: 6,
+            instance_count: 0,
+            first_vertex: 0,
+            first_instance: (region * DEFAULT_PARTICLE_MAX_COUNT) as u32,
+        });
+        ParticleSystem {
+            particles,
+            emitted,
+            draw_commands,
+            particle_count: 0,
+            source: 0,
+            emitted_count: 0,
+            last_time: None,
+            delta_time: 0.,
+        }
+    }
+
+    // Has to be called after waiting for the previous frame of the flight index, so that the GPU is
+    // done reading this flight's region of the staging area. The world time is used for stepping
+    // the simulation, so it stops along with the rest of the world.
+    pub fn upload(&mut self, emitted: &[Particle], time: f32, flight_index: usize) {
+        if emitted.len() > DEFAULT_PARTICLE_EMIT_MAX_COUNT {
+            warn!(
+                "too many emitted particles, \x1B[1mcount\x1B[0m: {}, \x1B[1mmax\x1B[0m: {DEFAULT_PARTICLE_EMIT_MAX_COUNT}",
+                emitted.len()
+            );
+        }
+        let emitted = &emitted[..emitted.len().min(DEFAULT_PARTICLE_EMIT_MAX_COUNT)];
+        let region_start = DEFAULT_PARTICLE_EMIT_MAX_COUNT * flight_index;
+        let region = &mut self.emitted.mapped()[region_start..];
+        for (slot, particle) in region.iter_mut().zip(emitted) {
+            slot.write(*particle);
+        }
+        self.emitted_count = emitted.len();
+        self.delta_time = self.last_time.map_or(0., |last_time| time - last_time);
+        self.last_time = Some(time);
+        self.particle_count =
+            unsafe { self.draw_commands.mapped()[self.source].assume_init_read() }.instance_count;
+    }
+
+    pub fn uniform(&self, flight_index: usize) -> ParticlesUniform {
+        ParticlesUniform {
+            source: self.source as u32,
+            emitted_offset: (DEFAULT_PARTICLE_EMIT_MAX_COUNT * flight_index) as u32,
+            emitted_count: self.emitted_count as u32,
+            capacity: DEFAULT_PARTICLE_MAX_COUNT as u32,
+            delta_time: self.delta_time,
+            gravity: DEFAULT_PARTICLE_GRAVITY,
+        }
+    }
+
+    // Every live particle could survive and every emitted one has to be appended, and the shader
+    // can't know in advance which threads will have work.
+    pub fn dispatch_size(&self) -> usize {
+        DEFAULT_PARTICLE_MAX_COUNT + self.emitted_count
+    }
+
+    pub fn destination(&self) -> usize {
+        1 - self.source
+    }
+
+    // Called after recording the frame, so that the next frame reads what this one wrote.
+    pub fn swap(&mut self) {
+        self.source = self.destination();
+    }
+
+    pub fn particles(&self) -> &StorageBuffer<[Particle]> {
+        &self.particles
+    }
+
+    pub fn emitted(&self) -> &StorageBuffer<[Particle]> {
+        &self.emitted
+    }
+
+    pub fn draw_commands(&self) -> &StorageBuffer<[vk::DrawIndirectCommand]> {
+        &self.draw_commands
+    }
+
+    pub fn cleanup(&self, dev: &Device) {
+        self.particles.cleanup(dev);
+        self.emitted.cleanup(dev);
+        self.draw_commands.cleanup(dev);
+    }
+}
+
+impl EmitSettings {
+    pub fn particle(&self, origin: Vector3<f32>, rng: &mut impl Rng) -> Particle {
+        let speed = self.speed * (1. - self.spread * rng.gen::<f32>());
+        Particle {
+            position: origin,
+            _pad0: 0.,
+            velocity: speed * rng.sample(RandomDirection),
+            lifetime: self.lifetime,
+            color: self.color,
+        }
+    }
+}
@@ -1,8 +1,37 @@
+#[cfg(feature = "dev-menu")]
+use crate::debug_draw::DebugDraw;
+#[cfg(feature = "dev-menu")]
+use crate::interface::EnumInterface;
 use crate::mesh::MeshData;
 use crate::renderer::vertex::Vertex;
+use crate::voxel::{VoxelCollision, Voxels};
 use nalgebra::{Point3, Vector3};
 use rapier3d::prelude::*;
 
+pub mod walking;
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum PhysicsMode {
+    Flying,
+    Walking,
+}
+
+// Result of moving a sphere through the voxel terrain. The fraction is how much of the motion can
+// be travelled before touching something, and the collision says what was touched, if anything.
+#[derive(Clone, Copy, Debug)]
+pub struct SweepResult {
+    pub fraction: f32,
+    pub collision: VoxelCollision,
+}
+
+// Length of a physics tick. Simulation always advances by whole ticks, and the camera is
+// interpolated between the last two to hide that from the frame rate.
+pub const PHYSICS_DT: f32 = 0.016;
+
+// Number of bisection steps used to find the contact point once an overlap was found, which gets
+// the error well below a millimeter for any step length used.
+const SWEEP_REFINE_STEPS: usize = 10;
+
 pub struct Physics {
     integration_parameters: IntegrationParameters,
     physics_pipeline: PhysicsPipeline,
@@ -39,8 +68,10 @@ impl Physics {
             .iter()
             .map(|v| Point3::from(v.position.component_mul(scale)))
             .collect();
-        let indices = (0..vertices.len() as u32 / 3)
-            .map(|i| [3 * i, 3 * i + 1, 3 * i + 2])
+        let indices = model
+            .indices
+            .chunks_exact(3)
+            .map(|triangle| [triangle[0], triangle[1], triangle[2]])
             .collect();
         ColliderBuilder::trimesh(vertices, indices)
     }
@@ -69,7 +100,87 @@ impl Physics {
         );
     }
 
+    #[cfg(feature = "dev-menu")]
+    pub fn debug_draw(&self, debug: &mut DebugDraw) {
+        for (_, collider) in self.collider_set.iter() {
+            let aabb = collider.compute_aabb();
+            debug.add_aabb(aabb.mins.coords, aabb.maxs.coords, Vector3::new(0., 1., 0.));
+            if let Some(capsule) = collider.shape().as_capsule() {
+                let a = collider.position() * capsule.segment.a;
+                let b = collider.position() * capsule.segment.b;
+                let color = Vector3::new(1., 0., 1.);
+                debug.add_sphere(a.coords, capsule.radius, 16, color);
+                debug.add_sphere(b.coords, capsule.radius, 16, color);
+                debug.add_line(a.coords, b.coords, color);
+            }
+        }
+    }
+
     pub fn get_translation(&self, rigid_body: RigidBodyHandle) -> Vector3<f32> {
         *self.rigid_body_set[rigid_body].translation()
     }
 }
+
+// Moves the sphere in steps short enough that it can't skip over a voxel, and then bisects between
+// the last free and the first overlapping position. This is less precise than an analytic sweep
+// against the voxel boxes, but it only needs the overlap test and is plenty for camera movement.
+// Unloaded chunks only stop the sweep if the caller asks for it, as otherwise touching one would hide
+// the solid voxels further along the motion.
+pub fn sweep_sphere(
+    origin: Vector3<f32>,
+    radius: f32,
+    motion: Vector3<f32>,
+    unloaded_blocks: bool,
+    voxels: &Voxels,
+) -> SweepResult {
+    // A zero radius would take infinitely many steps.
+    assert!(
+        radius > 0.,
+        "sweep radius must be positive, radius: {radius}"
+    );
+    let blocks = |collision| match collision {
+        VoxelCollision::Free => false,
+        VoxelCollision::Solid => true,
+        VoxelCollision::Unloaded => unloaded_blocks,
+    };
+    let step_length = 0.5 * radius.min(1.);
+    let steps = (motion.norm() / step_length).ceil().max(1.) as usize;
+    let mut free = 0.;
+    for step in 1..=steps {
+        let fraction = step as f32 / steps as f32;
+        let collision = voxels.collide_sphere(origin + fraction * motion, radius);
+        if !blocks(collision) {
+            free = fraction;
+            continue;
+        }
+        let mut blocked = fraction;
+        for _ in 0..SWEEP_REFINE_STEPS {
+            let middle = (free + blocked) / 2.;
+            if !blocks(voxels.collide_sphere(origin + middle * motion, radius)) {
+                free = middle;
+            } else {
+                blocked = middle;
+            }
+        }
+        return SweepResult {
+            fraction: free,
+            collision,
+        };
+    }
+    SweepResult {
+        fraction: 1.,
+        collision: VoxelCollision::Free,
+    }
+}
+
+#[cfg(feature = "dev-menu")]
+impl EnumInterface for PhysicsMode {
+    const VALUES: &'static [PhysicsMode] = &[PhysicsMode::Flying, PhysicsMode::Walking];
+
+    fn label(&self) -> std::borrow::Cow<str> {
+        std::borrow::Cow::Borrowed(match self {
+            PhysicsMode::Flying => "Flying",
+            PhysicsMode::Walking => "Walking",
+        })
+    }
+}
@@ -0,0 +1,187 @@
+use crate::voxel::{VoxelCollision, Voxels};
+use nalgebra::Vector3;
+
+const GRAVITY: f32 = 20.;
+const JUMP_SPEED: f32 = 7.;
+const WALK_SPEED: f32 = 4.;
+const SPRINT_SPEED: f32 = 8.;
+const STEP_HEIGHT: f32 = 1.;
+
+// Voxels are exactly one unit large, so as long as the player moves by less than that in a single
+// substep, checking just the destination is enough to never skip over a voxel.
+const MAX_SUBSTEP: f32 = 0.25;
+
+// Distance kept between the player and the voxel they collided with. Without it, the box would end
+// exactly on the voxel boundary and the next collision check would consider them overlapping.
+const SKIN: f32 = 0.001;
+
+// Player in walking mode, collided directly against the voxel terrain. Rapier isn't used for this,
+// as keeping a collider for each loaded chunk in sync with the voxel threads would be a lot of
+// work, while a box against a voxel grid is simple enough to do by hand.
+pub struct Walker {
+    pub feet: Vector3<f32>,
+    pub velocity: Vector3<f32>,
+    pub on_ground: bool,
+    half_width: f32,
+    height: f32,
+}
+
+impl Walker {
+    pub fn new(feet: Vector3<f32>, width: f32, height: f32) -> Walker {
+        Walker {
+            feet,
+            velocity: Vector3::zeros(),
+            on_ground: false,
+            half_width: width / 2.,
+            height,
+        }
+    }
+
+    pub fn teleport(&mut self, feet: Vector3<f32>) {
+        self.feet = feet;
+        self.velocity = Vector3::zeros();
+        self.on_ground = false;
+    }
+
+    pub fn update(
+        &mut self,
+        walk_direction: Vector3<f32>,
+        sprint: bool,
+        jumps: usize,
+        delta_time: f32,
+        voxels: &Voxels,
+    ) {
+        match self.collision(self.feet, voxels) {
+            VoxelCollision::Free => (),
+            // Terrain around the player isn't generated yet, either because the game just started
+            // or voxel settings were changed. Just wait in place until it's there.
+            VoxelCollision::Unloaded => {
+                self.velocity = Vector3::zeros();
+                return;
+            }
+            // This can happen after switching from flying mode while inside the terrain, or if the
+            // terrain was regenerated with different settings. Climbing out one voxel per tick
+            // looks slightly silly, but it's better than getting stuck.
+            VoxelCollision::Solid => {
+                self.feet.z = self.feet.z.floor() + 1. + SKIN;
+                self.velocity = Vector3::zeros();
+                return;
+            }
+        }
+        let speed = if sprint { SPRINT_SPEED } else { WALK_SPEED };
+        self.velocity.x = walk_direction.x * speed;
+        self.velocity.y = walk_direction.y * speed;
+        self.velocity.z -= GRAVITY * delta_time;
+        if self.on_ground && jumps > 0 {
+            self.velocity.z = JUMP_SPEED;
+        }
+        let displacement = self.velocity * delta_time;
+        let substeps = (displacement.abs().max() / MAX_SUBSTEP).ceil().max(1.) as usize;
+        let was_on_ground = self.on_ground;
+        self.on_ground = false;
+        for _ in 0..substeps {
+            let step = displacement / substeps as f32;
+            // Vertical movement goes first, so that the step up logic knows whether the player is
+            // standing on something.
+            self.move_axis(2, step.z, was_on_ground, voxels);
+            self.move_axis(0, step.x, was_on_ground, voxels);
+            self.move_axis(1, step.y, was_on_ground, voxels);
+        }
+    }
+
+    pub fn bounds(&self, feet: Vector3<f32>) -> (Vector3<f32>, Vector3<f32>) {
+        let min = feet - Vector3::new(self.half_width, self.half_width, 0.);
+        let max = feet + Vector3::new(self.half_width, self.half_width, self.height);
+        (min, max)
+    }
+
+    fn move_axis(&mut self, axis: usize, distance: f32, was_on_ground: bool, voxels: &Voxels) {
+        if distance == 0. || self.velocity[axis] == 0. {
+            return;
+        }
+        let mut target = self.feet;
+        target[axis] += distance;
+        if self.collision(target, voxels) == VoxelCollision::Free {
+            self.feet = target;
+            return;
+        }
+        // Walking into a ledge one voxel high just climbs it. Otherwise every bump in the terrain
+        // would require jumping, which makes walking around quite unbearable.
+        if axis != 2 && (self.on_ground || was_on_ground) {
+            let up = Vector3::new(0., 0., STEP_HEIGHT);
+            if self.collision(self.feet + up, voxels) == VoxelCollision::Free
+                && self.collision(target + up, voxels) == VoxelCollision::Free
+            {
+                self.feet = target + up;
+                return;
+            }
+        }
+        // Move right up to the voxel that was hit. Substeps are shorter than a voxel, so the only
+        // blocking voxels can be in the layer containing the far side of the destination box.
+        let (min, max) = self.bounds(self.feet);
+        if distance > 0. {
+            self.feet[axis] += ((max[axis] + distance).floor() - SKIN - max[axis]).max(0.);
+        } else {
+            self.feet[axis] += ((min[axis] + distance).floor() + 1. + SKIN - min[axis]).min(0.);
+            if axis == 2 {
+                self.on_ground = true;
+            }
+        }
+        self.velocity[axis] = 0.;
+    }
+
+    fn collision(&self, feet: Vector3<f32>, voxels: &Voxels) -> VoxelCollision {
+        let (min, max) = self.bounds(feet);
+        voxels.collide_aabb(min, max)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A floor with the given wall or ledge, starting at x = 8 and spanning the whole single chunk
+    // along y. The walker starts on the floor a few voxels away from it and walks towards it for
+    // two seconds, which is more than enough to reach it.
+    fn walk_towards(obstacle_height: i64) -> Walker {
+        let voxels = Voxels::from_fn(16, &[Vector3::zeros()], |voxel| {
+            voxel.z < 1 || (voxel.x >= 8 && voxel.z < 1 + obstacle_height)
+        });
+        let mut walker = Walker::new(Vector3::new(4.5, 8., 1.), 0.5, 1.75);
+        for _ in 0..120 {
+            walker.update(Vector3::new(1., 0., 0.), false, 0, 1. / 60., &voxels);
+        }
+        walker
+    }
+
+    #[test]
+    fn walking_into_wall_stops() {
+        let walker = walk_towards(3);
+        assert!(
+            walker.feet.x > 7.7 && walker.feet.x < 7.75,
+            "{:?}",
+            walker.feet
+        );
+        assert!((walker.feet.z - 1.).abs() < 0.01, "{:?}", walker.feet);
+        assert!(walker.on_ground);
+    }
+
+    #[test]
+    fn walking_into_ledge_steps_up() {
+        let walker = walk_towards(1);
+        assert!(walker.feet.x > 9., "{:?}", walker.feet);
+        assert!((walker.feet.z - 2.).abs() < 0.01, "{:?}", walker.feet);
+        assert!(walker.on_ground);
+    }
+
+    #[test]
+    fn walking_into_two_voxel_ledge_stops() {
+        let walker = walk_towards(2);
+        assert!(
+            walker.feet.x > 7.7 && walker.feet.x < 7.75,
+            "{:?}",
+            walker.feet
+        );
+        assert!((walker.feet.z - 1.).abs() < 0.01, "{:?}", walker.feet);
+    }
+}
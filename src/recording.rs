@@ -0,0 +1,157 @@
+use log::{info, warn};
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Child, ChildStdin, Command, Stdio};
+
+// Frames aren't paced in any way, every rendered frame becomes one video frame. With FIFO present
+// mode on a 60 Hz monitor this ends up playing at the right speed, which is good enough for demo
+// clips.
+pub const RECORDING_FPS: u32 = 60;
+
+pub const DEFAULT_RECORDING_PATH: &str = "recording.mp4";
+
+const RAW_MAGIC: &[u8; 8] = b"VKTHRGB\0";
+
+pub struct Recorder {
+    sink: RecorderSink,
+    path: PathBuf,
+    // Whether the frames come in BGRA rather than RGBA order, depending on the swapchain format.
+    bgra: bool,
+    frame_count: usize,
+}
+
+enum RecorderSink {
+    // Paths ending with .rgb get an RGB24 stream preceded by a small header, which consists of the
+    // RAW_MAGIC bytes followed by the width, height and frame rate as little-endian u32s.
+    Raw(BufWriter<File>),
+    // Everything else is encoded by ffmpeg, with the container picked from the extension.
+    Ffmpeg { child: Child, stdin: ChildStdin },
+}
+
+impl Recorder {
+    pub fn start(path: &Path, width: u32, height: u32, bgra: bool) -> Option<Recorder> {
+        let sink = if path.extension().is_some_and(|extension| extension == "rgb") {
+            start_raw(path, width, height)
+        } else {
+            start_ffmpeg(path, width, height, bgra)
+        };
+        let sink = match sink {
+            Ok(sink) => sink,
+            Err(error) => {
+                warn!(
+                    "failed to start recording, \x1B[1mpath\x1B[0m: {}, \x1B[1merror\x1B[0m: {error}",
+                    path.display()
+                );
+                return None;
+            }
+        };
+        info!(
+            "recording started, \x1B[1mpath\x1B[0m: {}, \x1B[1mresolution\x1B[0m: {width}x{height}",
+            path.display()
+        );
+        Some(Recorder {
+            sink,
+            path: path.to_owned(),
+            bgra,
+            frame_count: 0,
+        })
+    }
+
+    pub fn write_frame(&mut self, pixels: &[u8]) {
+        let result = match &mut self.sink {
+            RecorderSink::Raw(writer) => {
+                let mut rgb = Vec::with_capacity(pixels.len() / 4 * 3);
+                for pixel in pixels.chunks_exact(4) {
+                    if self.bgra {
+                        rgb.extend_from_slice(&[pixel[2], pixel[1], pixel[0]]);
+                    } else {
+                        rgb.extend_from_slice(&pixel[..3]);
+                    }
+                }
+                writer.write_all(&rgb)
+            }
+            RecorderSink::Ffmpeg { stdin, .. } => stdin.write_all(pixels),
+        };
+        if let Err(error) = result {
+            warn!(
+                "failed to write recorded frame, \x1B[1mpath\x1B[0m: {}, \x1B[1merror\x1B[0m: {error}",
+                self.path.display()
+            );
+        }
+        self.frame_count += 1;
+    }
+
+    // Closing stdin is what tells ffmpeg the stream ended, after which it still has to write out
+    // the remaining frames and the container trailer, so it has to be waited for.
+    pub fn finish(self) {
+        match self.sink {
+            RecorderSink::Raw(mut writer) => {
+                if let Err(error) = writer.flush() {
+                    warn!("failed to flush recording, \x1B[1merror\x1B[0m: {error}");
+                }
+            }
+            RecorderSink::Ffmpeg { mut child, stdin } => {
+                drop(stdin);
+                match child.wait() {
+                    Ok(status) if !status.success() => {
+                        warn!("ffmpeg failed, \x1B[1mstatus\x1B[0m: {status}")
+                    }
+                    Err(error) => warn!("failed to wait for ffmpeg, \x1B[1merror\x1B[0m: {error}"),
+                    Ok(_) => (),
+                }
+            }
+        }
+        info!(
+            "recording finished, \x1B[1mpath\x1B[0m: {}, \x1B[1mframes\x1B[0m: {}",
+            self.path.display(),
+            self.frame_count
+        );
+    }
+}
+
+fn start_raw(path: &Path, width: u32, height: u32) -> std::io::Result<RecorderSink> {
+    let mut writer = BufWriter::new(File::create(path)?);
+    writer.write_all(RAW_MAGIC)?;
+    for value in [width, height, RECORDING_FPS] {
+        writer.write_all(&value.to_le_bytes())?;
+    }
+    Ok(RecorderSink::Raw(writer))
+}
+
+fn start_ffmpeg(path: &Path, width: u32, height: u32, bgra: bool) -> std::io::Result<RecorderSink> {
+    let pixel_format = if bgra { "bgra" } else { "rgba" };
+    let mut child = Command::new("ffmpeg")
+        .args([
+            "-loglevel",
+            "error",
+            "-y",
+            "-f",
+            "rawvideo",
+            "-pix_fmt",
+            pixel_format,
+        ])
+        .args(["-s", &format!("{width}x{height}")])
+        .args(["-r", &RECORDING_FPS.to_string()])
+        .args(["-i", "-", "-pix_fmt", "yuv420p"])
+        .arg(path)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .spawn()?;
+    let stdin = child.stdin.take().unwrap();
+    Ok(RecorderSink::Ffmpeg { child, stdin })
+}
+
+// Every recording after the first one in a session gets a number appended, so that toggling the
+// recording doesn't overwrite the previous clip.
+pub fn recording_path(base: &Path, index: usize) -> PathBuf {
+    if index == 0 {
+        return base.to_owned();
+    }
+    let stem = base.file_stem().unwrap_or_default().to_string_lossy();
+    let file_name = match base.extension() {
+        Some(extension) => format!("{stem}-{index}.{}", extension.to_string_lossy()),
+        None => format!("{stem}-{index}"),
+    };
+    base.with_file_name(file_name)
+}
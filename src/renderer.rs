@@ -1,46 +1,98 @@
 mod barrier;
+mod bloom;
+mod capture;
 pub mod codegen;
+mod culling;
 pub mod debug;
+mod depth_prepass;
 mod device;
+mod dof;
+mod draw;
+mod graph;
 pub mod lifecycle;
+pub mod lut;
+mod objects;
+mod particles;
 mod pass;
 mod shader;
+#[cfg(feature = "dev-menu")]
+mod shader_watch;
+mod shadow;
+mod sky;
+mod skybox;
+mod ssao;
+mod ssr;
 mod swapchain;
+mod taa;
+mod textures;
 pub mod uniform;
 pub mod util;
 pub mod vertex;
 
+#[cfg(feature = "dev-menu")]
+use crate::config::DEFAULT_DEBUG_LINE_MAX_COUNT;
+#[cfg(feature = "dev-menu")]
+use crate::debug_draw::DebugDraw;
 #[cfg(feature = "dev-menu")]
 use crate::interface::EnumInterface;
-use crate::renderer::codegen::{Passes, Pipelines, Samplers};
-use crate::renderer::debug::{begin_label, end_label};
+use crate::logger::update_renderer_context;
+use crate::particles::ParticleSystem;
+use crate::renderer::capture::FrameCapture;
+use crate::renderer::codegen::{
+    update_textures_descriptor, Passes, Pipelines, Samplers, DESCRIPTOR_SET_COUNT,
+    TEXTURES_CAPACITY,
+};
+use crate::renderer::draw::{DrawGroup, DrawRecorder};
+use crate::renderer::graph::{RenderGraph, ResourceHandle, ResourceNode};
+use crate::renderer::lifecycle::PipelineCacheManager;
+use crate::renderer::objects::ObjectDraw;
 use crate::renderer::pass::Pass;
+#[cfg(feature = "dev-menu")]
+use crate::renderer::shader_watch::ShaderWatcher;
+use crate::renderer::sky::{SkyLighting, SkyWork};
 use crate::renderer::swapchain::Swapchain;
 use crate::renderer::uniform::{
-    Atmosphere, Camera, Debug, Global, PostprocessUniform, Star, Tonemapper, VoxelMaterial, Voxels,
+    Atmosphere, Camera, Debug, DebugVertex, Dof, Global, ObjectDrawCall, ObjectInstance,
+    PointLight, PostprocessUniform, Ssr, Star, Tonemapper, VoxelMaterial, Voxels,
+    POINT_LIGHT_MAX_COUNT,
 };
 use crate::renderer::util::{
-    timestamp_difference_to_duration, Buffer, Dev, ImageResources, StorageBuffer, UniformBuffer,
+    timestamp_difference_to_duration, wait_timeline, Buffer, Dev, ImageResources, MemoryStats,
+    StagingPool, StagingRing, StorageBuffer, UniformBuffer, MEMORY_BUDGET_CRITICAL,
 };
+use crate::voxel::gpu::meshlets::{VoxelMeshletMemory, VoxelSessionMemory};
 use crate::voxel::gpu::VoxelGpuMemory;
+use crate::voxel::material::{VoxelMaterialId, MATERIAL_COUNT};
 use crate::voxel::VoxelsConfig;
 use crate::world::World;
 use ash::{vk, Entry};
 #[cfg(feature = "dev-menu")]
 use imgui::DrawData;
-use nalgebra::{Matrix4, Vector2, Vector3};
+use log::warn;
+use nalgebra::{Matrix4, Vector2, Vector3, Vector4};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use std::f32::consts::FRAC_PI_4;
-use std::sync::atomic::{AtomicU32, Ordering};
-use std::sync::Arc;
-use std::time::Duration;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU32, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::{Duration, Instant};
 use winit::dpi::PhysicalSize;
+use winit::window::Window;
+
+// Where finished frames go. Offscreen rendering has no surface at all, and the images that would
+// be presented stay with the renderer instead, which is only useful together with a frame capture.
+pub enum RenderOutput<'a> {
+    Window(&'a Window),
+    Offscreen(PhysicalSize<u32>),
+}
 
 pub struct Renderer {
     // Immutable parts of the renderer. These can't change in the current design, but recovering
     // from GPU crashes might require doing something with these later?
     _entry: Entry,
     debug_messenger: vk::DebugUtilsMessengerEXT,
-    surface: vk::SurfaceKHR,
+    surface: Option<vk::SurfaceKHR>,
     pub dev: Dev,
     queue: vk::Queue,
     properties: vk::PhysicalDeviceProperties,
@@ -50,58 +102,190 @@ pub struct Renderer {
 
     // Description of the main render pass. Doesn't contain any information about the objects yet,
     // only low-level data format descriptions.
-    descriptor_set_layout: vk::DescriptorSetLayout,
+    descriptor_set_layouts: [vk::DescriptorSetLayout; DESCRIPTOR_SET_COUNT],
     descriptor_pool: vk::DescriptorPool,
     pipeline_layout: vk::PipelineLayout,
+    // Shared by all pipeline creations, and saved on exit so that later runs skip most of the shader
+    // compilation in the driver.
+    pipeline_cache: vk::PipelineCache,
+    pipeline_cache_path: PathBuf,
     passes: Passes,
 
     // All resources that depend on swapchain extent (window size). So swapchain description, memory
     // used for all framebuffer attachments, framebuffers, and the mentioned postprocess descriptor
-    // set. Projection matrix depends on the monitor aspect ratio, so it's included too.
+    // set. Projection matrix depends on the monitor aspect ratio, so it's included too. The scene
+    // itself is rendered at a scaled down resolution and then upscaled to the swapchain, so that
+    // weaker GPUs can keep the interface sharp while rendering less pixels.
     pub swapchain: Swapchain,
+    present_mode: PresentMode,
+    // Set when acquiring or presenting reports the swapchain no longer matches the surface, or when
+    // the window was minimized and the swapchain couldn't be recreated. Drawing the next frame
+    // recreates it first.
+    swapchain_outdated: bool,
     pipelines: Pipelines,
+    render_scale: f32,
+    render_extent: vk::Extent2D,
+    color: ImageResources,
     depth: ImageResources,
+    // Bloom is blurred at half the render resolution, ping-ponging between the two images for the
+    // separate horizontal and vertical passes. Repeating the blur only adds passes to the graph, so
+    // the same two images are enough for any number of them.
+    bloom_extent: vk::Extent2D,
+    bloom_images: [ImageResources; 2],
+    bloom_radius: f32,
+    postprocess_effects: [f32; 5],
+    // Reflections are traced at half the render resolution too, against a Hi-Z buffer of the same
+    // size. The step count is baked into the pipeline, like the bloom radius.
+    ssr_extent: vk::Extent2D,
+    hiz: ImageResources,
+    ssr_image: ImageResources,
+    ssr_max_steps: u32,
+    // In-scattering and optical depth sample counts the atmosphere pipelines were created with.
+    atmosphere_samples: (u32, u32),
+    // Occlusion is computed at the full render resolution and blurred into the second image. The
+    // radius and intensity are baked into the pipelines, with zero intensity when disabled.
+    ssao_images: [ImageResources; 2],
+    ssao_effects: (f32, f32),
+    // Same format and resolution as the color attachment, which it's copied back into.
+    dof_image: ImageResources,
+    // Same as the depth of field image, except that there are two swapping roles every frame, and
+    // that they are only allocated while TAA is enabled.
+    taa_history: Option<[ImageResources; 2]>,
+    // Cleared whenever the history images are recreated, so that the first frame afterwards
+    // doesn't blend in whatever they happened to contain.
+    taa_history_valid: bool,
+    // Unjittered, for reprojecting the history onto the current frame.
+    previous_view_projection: Matrix4<f32>,
+    // Sampled directly by the postprocess pass, so swapping it only needs a descriptor update. The
+    // path is kept to notice when the settings point at a different table.
+    color_lut: ImageResources,
+    color_lut_path: Option<PathBuf>,
+    // Same as the lookup table, except that failing to load it leaves the placeholder in place and
+    // the shaders showing the plain sky color instead.
+    skybox: ImageResources,
+    skybox_path: Option<PathBuf>,
+    skybox_loaded: bool,
+    // Sky captured around the camera for the ambient term, updated a face at a time as the sun
+    // moves.
+    sky: SkyLighting,
+
+    // The shadow map doesn't depend on the window size, only on the configured resolution, so it's
+    // recreated separately from the render targets.
+    shadow_resolution: u32,
+    shadow_map: ImageResources,
 
     // Vulkan objects actually used for command recording and synchronization. Also internal
     // renderer state for keeping track of concurrent frames.
-    command_pools: [vk::CommandPool; FRAMES_IN_FLIGHT],
-    command_buffers: [vk::CommandBuffer; FRAMES_IN_FLIGHT],
+    // Chosen once at startup, as nearly every per-frame resource is sized by it. More frames in
+    // flight let the CPU run further ahead of the GPU, at the cost of input latency.
+    frames_in_flight: usize,
+    command_pools: Vec<vk::CommandPool>,
+    command_buffers: Vec<vk::CommandBuffer>,
+    // Command pools can't be used from multiple threads at once, so every draw group gets its own.
+    secondary_command_pools: Vec<[vk::CommandPool; DrawGroup::ALL.len()]>,
+    secondary_command_buffers: Vec<[vk::CommandBuffer; DrawGroup::ALL.len()]>,
+    recording_threads: rayon::ThreadPool,
     sync: Synchronization,
     flight_index: usize,
 
     // And finally resources specific to this renderer. So various buffers related to objects we
     // actually render, their descriptor sets and the like.
     mesh_objects: Vec<MeshObject>,
+    staging: StagingPool,
+    staging_ring: StagingRing,
     stars: StorageBuffer<[Star]>,
+    debug_vertices: StorageBuffer<[DebugVertex]>,
+    debug_line_count: usize,
+    // Rewritten every frame from the world, with the draws of opaque objects followed by the
+    // transparent ones.
+    objects: StorageBuffer<[ObjectInstance]>,
+    object_draws: Vec<ObjectDraw>,
+    opaque_object_count: usize,
+    // Laid out the same way as the object list, with the draw of each object at the same index as
+    // its instance. Visible objects are counted on the GPU and read back like the visible stars.
+    object_draw_calls: StorageBuffer<[ObjectDrawCall]>,
+    visible_object_counts: StorageBuffer<[u32]>,
+    visible_object_count: u32,
+    particles: ParticleSystem,
+    // One draw command per flight index, while the visible star list is split into regions of the
+    // star count each.
+    star_count: usize,
+    star_draw_commands: StorageBuffer<[vk::DrawIndexedIndirectCommand]>,
+    visible_stars: StorageBuffer<[u32]>,
+    visible_star_count: u32,
     global: UniformBuffer<Global>,
-    descriptor_sets: [vk::DescriptorSet; FRAMES_IN_FLIGHT],
-
+    descriptor_sets: Vec<vk::DescriptorSet>,
+    // Textures are all bound at once as a single array in the global descriptor set, and materials
+    // refer to them by index. Slots are never freed, as textures are only loaded at startup.
+    texture_handles: HashMap<String, u32>,
+    next_texture_slot: u32,
+    textures: Vec<ImageResources>,
+
+    // Palette indexed by the material IDs in voxel vertices and octrees.
+    voxel_materials: StorageBuffer<[VoxelMaterial]>,
     voxel_meshlet_count: Arc<AtomicU32>,
-    pub voxel_gpu_memory: Option<Box<dyn VoxelGpuMemory>>,
+    // Meshlets left after culling in the task shader, counted on the GPU and read back like the
+    // visible objects.
+    visible_meshlet_counts: StorageBuffer<[u32]>,
+    visible_meshlet_count: u32,
+    // Copy of the frame index for the voxel GPU memory, which needs it to tell when memory freed by
+    // the worker threads stops being used by frames in flight.
+    voxel_frame_index: Arc<AtomicUsize>,
+    // Shared by the voxel workers of every session, each getting its own view of it.
+    voxel_memory: Arc<Mutex<VoxelMeshletMemory>>,
+    active_voxel_session: u8,
 
     query_pool: vk::QueryPool,
     frame_index: usize,
     pub frametime: Option<Duration>,
+    // GPU time taken by each pass, in the order they were executed.
+    pub pass_times: Vec<(&'static str, Duration)>,
+    // Execution order of the render graph for each frame in flight, which is needed to tell which
+    // timestamps belong to which pass once the results are available.
+    timed_passes: Vec<Vec<&'static str>>,
+    last_present: Option<Instant>,
+    present_interval: Option<Duration>,
+    // Whether VRAM usage was above the critical threshold last frame, so that crossing it is only
+    // logged once rather than every frame while chunks stream in near the limit.
+    memory_pressure: bool,
     pub just_completed_first_render: bool,
+    // Only present while a video is being recorded, see the capture module.
+    capture: Option<FrameCapture>,
 
     #[cfg(feature = "dev-menu")]
     interface_renderer: Option<imgui_rs_vulkan_renderer::Renderer>,
+    // Only present with --watch-shaders.
+    #[cfg(feature = "dev-menu")]
+    shader_watcher: Option<ShaderWatcher>,
 }
 
+// Binary semaphores are only used for acquiring and presenting, as the swapchain doesn't accept
+// timeline ones. Everything else waits on the frame timeline, which every graphics submit signals
+// with the frame index plus one, so that zero means no frame has finished yet.
 struct Synchronization {
-    image_available: [vk::Semaphore; FRAMES_IN_FLIGHT],
-    render_finished: [vk::Semaphore; FRAMES_IN_FLIGHT],
-    in_flight: [vk::Fence; FRAMES_IN_FLIGHT],
+    image_available: Vec<vk::Semaphore>,
+    render_finished: Vec<vk::Semaphore>,
+    frame_timeline: vk::Semaphore,
 }
 
 pub struct MeshObject {
     triangle_count: usize,
+    // Around the origin of the mesh rather than its center, so that it's only scaled and translated
+    // along with the object.
+    bounding_radius: f32,
     vertex: Buffer,
     index: Buffer,
+    // Taken from the first primitive, as materials aren't supported yet. Meshes with any primitive
+    // that isn't fully opaque are drawn in the transparent pass.
+    base_color: Vector4<f32>,
+    is_transparent: bool,
 }
 
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
 pub struct RendererSettings {
     pub voxel_rendering: VoxelRendering,
+    pub atmosphere_quality: AtmosphereQuality,
+    // Only used with the custom atmosphere quality.
     pub atmosphere_in_scattering_samples: usize,
     pub atmosphere_optical_depth_samples: usize,
     pub atmosphere_wavelengths: Vector3<f32>,
@@ -109,25 +293,203 @@ pub struct RendererSettings {
     pub depth_far: f32,
     pub enable_atmosphere: bool,
     pub postprocess: PostprocessSettings,
+    pub bloom: BloomSettings,
+    pub ssao: SsaoSettings,
+    pub ssr: SsrSettings,
+    pub dof: DofSettings,
+    pub taa: TaaSettings,
+    pub sky_lighting: SkyLightingSettings,
+    // Color grading lookup table in the 1024x32 strip format, or the identity table if unset.
+    pub color_lut: Option<PathBuf>,
+    // Directory with the six cubemap faces shown when the atmosphere is disabled, or the plain sky
+    // color if unset.
+    pub skybox: Option<PathBuf>,
+    pub voxel_materials: [VoxelMaterial; MATERIAL_COUNT],
+    // Only the dev menu collects debug lines, so without it this does nothing.
+    #[cfg_attr(not(feature = "dev-menu"), allow(dead_code))]
+    pub debug_draw: bool,
+    pub render_scale: f32,
+    // Adjusts the render scale within the limits below to keep frame times near the target.
+    pub dynamic_resolution: bool,
+    pub target_frame_time_ms: f32,
+    pub min_render_scale: f32,
+    pub max_render_scale: f32,
+    // Records the draw groups of the main pass on the recording thread pool instead of one after
+    // another on the render thread.
+    pub parallel_recording: bool,
+    pub present_mode: PresentMode,
+    pub gpu_culling: bool,
+    // Draws the depth of opaque geometry before the forward pass, so that it only shades visible
+    // fragments.
+    pub depth_prepass: bool,
+    // Multiplier for how fast the day-night cycle advances.
+    pub time_scale: f32,
+    pub shadows: ShadowSettings,
+    pub passes: PassSettings,
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize)]
+pub enum PresentMode {
+    Fifo,
+    FifoRelaxed,
+    Mailbox,
+    Immediate,
+}
+
+#[cfg_attr(not(feature = "dev-menu"), allow(dead_code))]
+#[derive(Clone, Debug)]
+pub struct RendererStats {
+    pub frametime: Option<Duration>,
+    pub pass_times: Vec<(&'static str, Duration)>,
+    pub present_mode: vk::PresentModeKHR,
+    pub present_interval: Option<Duration>,
+    pub visible_objects: u32,
+    pub total_objects: u32,
+    pub visible_stars: u32,
+    pub total_stars: u32,
+    pub particle_count: u32,
+    pub voxel_meshlet_count: u32,
+    pub visible_voxel_meshlets: u32,
+    pub memory: MemoryStats,
+    // Used and total bytes of the staging ring.
+    pub staging_ring: (usize, usize),
 }
 
 #[allow(dead_code)]
-#[derive(Clone, Copy, PartialEq)]
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize)]
 pub enum VoxelRendering {
     Classic,
     MeshShaders,
     RayTracing,
 }
 
+// Sample counts are baked into the atmosphere shaders as specialization constants, so that the
+// compiler knows the loop bounds, but changing them means recreating the pipelines.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize)]
+pub enum AtmosphereQuality {
+    Low,
+    Medium,
+    High,
+    Custom,
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
 pub struct PostprocessSettings {
     pub exposure: f32,
     pub tonemapper: Tonemapper,
     pub gamma: f32,
+    // These are baked into the postprocess pipeline as specialization constants, so that the
+    // driver can remove the effects entirely when they are disabled.
+    pub grain_strength: f32,
+    pub vignette_strength: f32,
+    pub vignette_radius: f32,
+    pub aberration_strength: f32,
+    pub color_lut_strength: f32,
+}
+
+// Threshold, knee and intensity are passed through the global uniform, so they can be changed every
+// frame. The blur radius is baked into the pipelines as a specialization constant, which lets the
+// compiler unroll the kernel, but changing it means recreating the pipelines. The pass count is
+// only read while building the render graph, so it can change every frame too.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct BloomSettings {
+    pub threshold: f32,
+    pub knee: f32,
+    pub intensity: f32,
+    // Number of times the horizontal and vertical blur are repeated, each widening the blur by
+    // roughly the square root of two.
+    pub passes: usize,
+    // Kernel radius in texels of the half resolution bloom images.
+    pub radius: f32,
+}
+
+// Both parameters are specialization constants, so changing them rebuilds the pipelines and a
+// disabled effect costs nothing during postprocessing.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct SsaoSettings {
+    pub enabled: bool,
+    // View space distance around each pixel in which other surfaces count as occluders.
+    pub radius: f32,
+    pub intensity: f32,
+}
+
+// Everything except the step count goes through the global uniform. The step count is a
+// specialization constant, so that the compiler can unroll the march loop.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct SsrSettings {
+    pub max_steps: u32,
+    pub step_size: f32,
+    // How far behind the depth buffer a ray can be and still count as a hit, rather than passing
+    // behind the object.
+    pub thickness: f32,
+    pub fade_distance: f32,
+    pub enabled: bool,
+    // Shows the reflections on their own instead of the scene.
+    pub debug_overlay: bool,
+}
+
+// Everything goes through the global uniform, as the focus changes every frame with autofocus
+// anyway. Disabled by default, as it's mostly meant for screenshots.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct DofSettings {
+    pub enabled: bool,
+    // Focuses on whatever is in the center of the screen instead of the fixed distance.
+    pub autofocus: bool,
+    // View space distance at which the scene is completely sharp.
+    pub focus_distance: f32,
+    // Blur radius in pixels for infinitely distant surfaces, so the overall strength of the effect.
+    pub aperture: f32,
+}
+
+// The jitter and the blend go through the global uniform, so only toggling the effect does
+// anything expensive, which is allocating or freeing the history images. Helps with the shading
+// aliasing MSAA wouldn't touch, like specular shimmer on the voxel terrain.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct TaaSettings {
+    pub enabled: bool,
+    // Weight of the history in every resolved frame. Higher values smooth out more aliasing, but
+    // take longer to converge and ghost more behind moving objects.
+    pub history_weight: f32,
+    // Shows the blend factor used for each pixel instead of the scene, with black where the history
+    // was rejected.
+    pub debug_view: bool,
+}
+
+// Ambient light from the sky, with the same captured sky lighting every surface regardless of its
+// position. Disabling it leaves only the sun and point lights, so shadowed surfaces go black.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct SkyLightingSettings {
+    pub enabled: bool,
+    pub intensity: f32,
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct ShadowSettings {
+    pub enable: bool,
+    pub resolution: u32,
+    pub bias: f32,
+    // Distance from the camera covered by the shadow map. Larger distances make the shadows
+    // blurrier, as the same number of texels is spread over a larger area.
+    pub distance: f32,
+}
+
+// Disabled passes still run, but only clear their outputs, so that the passes after them keep
+// working and the final image stays valid. Meant for checking what each pass contributes to the
+// frame or how long it takes, without having to rebuild anything. The atmosphere isn't a pass of its
+// own but part of shading in the scene pass, so it's toggled by enable_atmosphere instead.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct PassSettings {
+    pub shadow: bool,
+    pub scene: bool,
+    pub transparent: bool,
+    pub bloom: bool,
+    pub upscale: bool,
 }
 
 #[derive(Clone)]
 pub struct DeviceSupport {
     mesh_shaders: bool,
+    memory_budget: bool,
 }
 
 pub const VRAM_VIA_BAR: vk::MemoryPropertyFlags = vk::MemoryPropertyFlags::from_raw(
@@ -136,7 +498,15 @@ pub const VRAM_VIA_BAR: vk::MemoryPropertyFlags = vk::MemoryPropertyFlags::from_
         | vk::MemoryPropertyFlags::HOST_COHERENT.as_raw(),
 );
 
-pub const FRAMES_IN_FLIGHT: usize = 2;
+// Each bloom pass adds a horizontal and a vertical blur to the graph, so the count is limited to
+// keep the number of timed passes bounded.
+pub const BLOOM_MAX_PASSES: usize = 4;
+
+// There are fewer passes than this, but the limit only costs a few queries and passes get added
+// often enough.
+const TIMED_PASS_MAX_COUNT: usize = 32;
+// Two timestamps for the whole frame, followed by two for each pass.
+const TIMESTAMPS_PER_FRAME: usize = 2 + 2 * TIMED_PASS_MAX_COUNT;
 
 // Format used for passing HDR data between render passes to enable realistic differences in
 // lighting parameters and improve postprocessing effect quality, not related to monitor HDR.
@@ -146,6 +516,12 @@ const COLOR_FORMAT: vk::Format = vk::Format::R16G16B16A16_SFLOAT;
 
 const DEPTH_FORMAT: vk::Format = vk::Format::D32_SFLOAT;
 
+#[cfg(feature = "dev-menu")]
+const INTERFACE_PASS: Pass = Pass {
+    debug_name: "Interface pass",
+    debug_color: [63, 70, 73],
+};
+
 impl Renderer {
     pub fn draw_frame(
         &mut self,
@@ -153,87 +529,171 @@ impl Renderer {
         voxels: &VoxelsConfig,
         settings: &RendererSettings,
         window_size: PhysicalSize<u32>,
-        #[cfg(feature = "dev-menu")] ui_draw: &DrawData,
+        #[cfg(feature = "dev-menu")] ui_draw: Option<&DrawData>,
+        #[cfg(feature = "dev-menu")] debug_draw: &DebugDraw,
     ) {
+        // Minimized windows have a zero size, so there's nothing to draw into until they are
+        // restored. Acquiring would either fail or block, depending on the platform.
+        if window_size.width == 0 || window_size.height == 0 {
+            return;
+        }
+        if self.swapchain_outdated {
+            self.recreate_swapchain(window_size);
+        }
+        #[cfg(feature = "dev-menu")]
+        self.reload_changed_shaders();
+        if settings.render_scale != self.render_scale {
+            self.recreate_render_targets(settings.render_scale);
+        }
+        // Acquiring happens synchronously right below, so there is never an acquire on the old
+        // swapchain that could complete after switching.
+        if settings.present_mode != self.present_mode {
+            self.present_mode = settings.present_mode;
+            self.recreate_swapchain(window_size);
+        }
+        if settings.taa.enabled != self.taa_history.is_some() {
+            self.recreate_taa_history(settings.taa.enabled);
+        }
+        if settings.shadows.resolution != self.shadow_resolution {
+            self.recreate_shadow_map(settings.shadows.resolution);
+        }
+        let postprocess_effects = postprocess_effects(&settings.postprocess);
+        let ssao_effects = ssao_effects(&settings.ssao);
+        if settings.bloom.radius != self.bloom_radius
+            || postprocess_effects != self.postprocess_effects
+            || ssao_effects != self.ssao_effects
+            || settings.ssr.max_steps != self.ssr_max_steps
+            || atmosphere_samples(settings) != self.atmosphere_samples
+        {
+            self.bloom_radius = settings.bloom.radius;
+            self.postprocess_effects = postprocess_effects;
+            self.ssao_effects = ssao_effects;
+            self.ssr_max_steps = settings.ssr.max_steps;
+            self.atmosphere_samples = atmosphere_samples(settings);
+            self.recreate_pipelines();
+        }
+        if settings.color_lut != self.color_lut_path {
+            self.reload_color_lut(settings.color_lut.clone());
+        }
+        if settings.skybox != self.skybox_path {
+            self.reload_skybox(settings.skybox.clone());
+        }
         let Some(image_index) = (unsafe { self.prepare_command_buffer(window_size) }) else {
             return;
         };
+        self.reset_star_culling();
+        self.reset_object_culling();
+        self.reset_voxel_culling();
+        self.upload_objects(world, settings);
+        self.upload_voxel_materials(settings);
+        self.particles
+            .upload(&world.emitted_particles, world.time, self.flight_index);
+        #[cfg(feature = "dev-menu")]
+        self.upload_debug_draw(debug_draw, settings);
+        // Has to happen before recording, which replaces the pass order of this flight index.
+        self.frametime = self.query_timestamp();
+        self.pass_times = self.query_pass_times();
+        let sky_work = self.schedule_sky_capture(world.sun_direction(), settings);
         unsafe {
             self.record_command_buffer(
                 image_index,
-                world,
                 settings,
+                sky_work,
                 #[cfg(feature = "dev-menu")]
                 ui_draw,
             )
         };
-        self.frametime = self.query_timestamp();
         self.update_global_uniform(
             world,
             voxels,
             self.voxel_meshlet_count.load(Ordering::SeqCst),
             settings,
-            window_size,
         );
         self.submit_graphics();
         self.submit_present(image_index);
+        self.particles.swap();
+        self.check_memory_pressure();
 
-        self.flight_index = (self.flight_index + 1) % FRAMES_IN_FLIGHT;
+        self.flight_index = (self.flight_index + 1) % self.frames_in_flight;
         self.frame_index += 1;
+        self.voxel_frame_index
+            .store(self.frame_index, Ordering::SeqCst);
+        update_renderer_context(self.frame_index, settings);
     }
 
     unsafe fn prepare_command_buffer(&mut self, window_size: PhysicalSize<u32>) -> Option<usize> {
-        let image_available = self.sync.image_available[self.flight_index];
-        let in_flight = self.sync.in_flight[self.flight_index];
-
-        self.dev
-            .wait_for_fences(&[in_flight], true, u64::MAX)
-            .unwrap();
-
-        self.just_completed_first_render = self.frame_index == FRAMES_IN_FLIGHT;
-
-        let acquire_result = self.dev.swapchain_ext.acquire_next_image(
-            self.swapchain.handle,
-            u64::MAX,
-            image_available,
-            vk::Fence::null(),
-        );
-        if acquire_result == Err(vk::Result::ERROR_OUT_OF_DATE_KHR) {
-            self.recreate_swapchain(window_size);
-            return None;
-        }
-        let (image_index, _is_suboptimal) = acquire_result.unwrap();
+        // Resources of this flight index were last used frames_in_flight frames ago, and all frames
+        // before that one finished too, as the timeline only counts up.
+        let previous_use = (self.frame_index + 1).saturating_sub(self.frames_in_flight);
+        wait_timeline(self.sync.frame_timeline, previous_use as u64, &self.dev);
+        self.read_back_frame();
+
+        self.just_completed_first_render = self.frame_index == self.frames_in_flight;
+
+        let image_index = match self.swapchain.handle {
+            Some(handle) => {
+                let acquire_result = self.dev.swapchain_ext.acquire_next_image(
+                    handle,
+                    u64::MAX,
+                    self.sync.image_available[self.flight_index],
+                    vk::Fence::null(),
+                );
+                if acquire_result == Err(vk::Result::ERROR_OUT_OF_DATE_KHR) {
+                    self.recreate_swapchain(window_size);
+                    return None;
+                }
+                // Suboptimal images can still be presented, and the semaphore is already signalled,
+                // so the frame is drawn anyway and the swapchain is recreated before the next one.
+                let (image_index, is_suboptimal) = acquire_result.unwrap();
+                if is_suboptimal {
+                    self.swapchain_outdated = true;
+                }
+                image_index as usize
+            }
+            // Offscreen there's an image for every frame in flight, which the timeline wait above
+            // already made available.
+            None => self.flight_index,
+        };
 
-        self.dev.reset_fences(&[in_flight]).unwrap();
+        self.staging.reclaim(self.flight_index);
+        self.staging_ring.poll(&self.dev);
         self.dev
             .reset_command_pool(
                 self.command_pools[self.flight_index],
                 vk::CommandPoolResetFlags::empty(),
             )
             .unwrap();
+        for pool in self.secondary_command_pools[self.flight_index] {
+            self.dev
+                .reset_command_pool(pool, vk::CommandPoolResetFlags::empty())
+                .unwrap();
+        }
 
-        Some(image_index as usize)
+        Some(image_index)
     }
 
     unsafe fn record_command_buffer(
         &mut self,
         image_index: usize,
-        world: &World,
         settings: &RendererSettings,
-        #[cfg(feature = "dev-menu")] ui_draw: &DrawData,
+        sky_work: SkyWork,
+        #[cfg(feature = "dev-menu")] ui_draw: Option<&DrawData>,
     ) {
         let buf = self.command_buffers[self.flight_index];
 
         let begin_info = vk::CommandBufferBeginInfo::default()
             .flags(vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT);
         self.dev.begin_command_buffer(buf, &begin_info).unwrap();
+        self.staging_ring.record_acquires(buf, &self.dev);
+        self.global
+            .record_upload(buf, self.flight_index, &mut self.staging, &self.dev);
         self.reset_timestamps(buf);
         self.write_timestamp(buf, 0, vk::PipelineStageFlags::ALL_COMMANDS);
-        self.record_render_pass(
+        self.record_passes(
             image_index,
             buf,
-            world,
             settings,
+            sky_work,
             #[cfg(feature = "dev-menu")]
             ui_draw,
         );
@@ -241,231 +701,846 @@ impl Renderer {
         self.dev.end_command_buffer(buf).unwrap();
     }
 
-    unsafe fn record_render_pass(
+    // All passes of the frame are described as a render graph, which inserts the barriers between
+    // them. The pass closures only record their own commands.
+    unsafe fn record_passes(
         &mut self,
         image_index: usize,
         buf: vk::CommandBuffer,
-        world: &World,
         settings: &RendererSettings,
-        #[cfg(feature = "dev-menu")] ui_draw: &DrawData,
+        sky_work: SkyWork,
+        #[cfg(feature = "dev-menu")] ui_draw: Option<&DrawData>,
     ) {
-        let color = &self.swapchain.images[image_index];
-        let depth = &self.depth;
+        // The interface renderer is the only thing that needs mutable access during recording, so
+        // it's taken out for the duration to let the other passes borrow the renderer.
+        #[cfg(feature = "dev-menu")]
+        let mut interface_renderer = self.interface_renderer.take();
+
+        let mut graph = RenderGraph::new();
+        // The previous frame might still be postprocessing from the color attachment or testing
+        // against and tracing reflections through the depth one, but the contents aren't needed
+        // anymore.
+        let color = graph.add_resource(ResourceNode {
+            image: &self.color,
+            previous_stage: vk::PipelineStageFlags2::FRAGMENT_SHADER,
+            discard: true,
+            layout_at_end: None,
+        });
+        let depth = graph.add_resource(ResourceNode {
+            image: &self.depth,
+            previous_stage: vk::PipelineStageFlags2::LATE_FRAGMENT_TESTS
+                | vk::PipelineStageFlags2::COMPUTE_SHADER
+                | vk::PipelineStageFlags2::FRAGMENT_SHADER,
+            discard: true,
+            layout_at_end: None,
+        });
+        // The shadow map is only sampled by the previous frame's render pass, and is completely
+        // redrawn every frame.
+        let shadow_map = graph.add_resource(ResourceNode {
+            image: &self.shadow_map,
+            previous_stage: vk::PipelineStageFlags2::FRAGMENT_SHADER,
+            discard: true,
+            layout_at_end: None,
+        });
+        // The Hi-Z buffer and the reflections are rebuilt from scratch every frame, and last used by
+        // the previous frame's SSR and postprocess passes respectively.
+        let hiz = graph.add_resource(ResourceNode {
+            image: &self.hiz,
+            previous_stage: vk::PipelineStageFlags2::FRAGMENT_SHADER,
+            discard: true,
+            layout_at_end: None,
+        });
+        let ssr_image = graph.add_resource(ResourceNode {
+            image: &self.ssr_image,
+            previous_stage: vk::PipelineStageFlags2::FRAGMENT_SHADER,
+            discard: true,
+            layout_at_end: None,
+        });
+        // Same with the occlusion, which is last sampled by the previous frame's blur and
+        // postprocess passes.
+        let ssao_raw = graph.add_resource(ResourceNode {
+            image: &self.ssao_images[0],
+            previous_stage: vk::PipelineStageFlags2::FRAGMENT_SHADER,
+            discard: true,
+            layout_at_end: None,
+        });
+        let ssao_blurred = graph.add_resource(ResourceNode {
+            image: &self.ssao_images[1],
+            previous_stage: vk::PipelineStageFlags2::FRAGMENT_SHADER,
+            discard: true,
+            layout_at_end: None,
+        });
+        // Bloom images are last sampled by the previous frame's composite, and everything in them is
+        // derived from the current frame.
+        // The blurred scene is last sampled by the previous frame's composite, and fully redrawn.
+        let dof_image = graph.add_resource(ResourceNode {
+            image: &self.dof_image,
+            previous_stage: vk::PipelineStageFlags2::FRAGMENT_SHADER,
+            discard: true,
+            layout_at_end: None,
+        });
+        let bloom_ping = graph.add_resource(ResourceNode {
+            image: &self.bloom_images[0],
+            previous_stage: vk::PipelineStageFlags2::FRAGMENT_SHADER,
+            discard: true,
+            layout_at_end: None,
+        });
+        let bloom_pong = graph.add_resource(ResourceNode {
+            image: &self.bloom_images[1],
+            previous_stage: vk::PipelineStageFlags2::FRAGMENT_SHADER,
+            discard: true,
+            layout_at_end: None,
+        });
+        // Unlike the other render targets, both sky images are kept across frames, as a capture takes
+        // several frames and the irradiance is only updated once it's complete. The capture is last
+        // sampled by the convolution and the irradiance by the previous frame's scene passes.
+        let sky_capture = graph.add_resource(ResourceNode {
+            image: &self.sky.capture,
+            previous_stage: vk::PipelineStageFlags2::COMPUTE_SHADER,
+            discard: false,
+            layout_at_end: None,
+        });
+        let sky_irradiance = graph.add_resource(ResourceNode {
+            image: &self.sky.irradiance,
+            previous_stage: vk::PipelineStageFlags2::FRAGMENT_SHADER,
+            discard: false,
+            layout_at_end: None,
+        });
+        // Swapchain images are only available once the acquire semaphore is signalled, which
+        // happens at the color attachment stage as the first thing done to them is postprocessing.
+        // Offscreen images are never presented, so they are left in whatever layout the last pass
+        // used.
+        let target = graph.add_resource(ResourceNode {
+            image: &self.swapchain.images[image_index],
+            previous_stage: vk::PipelineStageFlags2::COLOR_ATTACHMENT_OUTPUT,
+            discard: true,
+            layout_at_end: self.swapchain.handle.map(|_| {
+                (
+                    vk::ImageLayout::PRESENT_SRC_KHR,
+                    vk::PipelineStageFlags2::BOTTOM_OF_PIPE,
+                )
+            }),
+        });
+
+        // Culling and the particle simulation don't use any images, so the graph has nothing to order
+        // them by and they simply run first, as they were added first.
+        graph.add_pass("Star culling", &[], &[], |buf| {
+            self.record_star_culling(buf)
+        });
+        if settings.gpu_culling && !self.object_draws.is_empty() {
+            graph.add_pass("Object culling", &[], &[], |buf| {
+                self.record_object_culling(buf)
+            });
+        }
+        graph.add_pass("Particle simulation", &[], &[], |buf| {
+            self.record_particles_update(buf)
+        });
 
-        self.barriers(
-            buf,
+        if let Some(face) = &sky_work.face {
+            graph.add_pass(
+                self.passes.sky_capture.debug_name,
+                &[],
+                &[sky_capture.usage(
+                    vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+                    vk::PipelineStageFlags2::COLOR_ATTACHMENT_OUTPUT,
+                )],
+                |buf| self.record_sky_capture(buf, *face),
+            );
+        }
+        if sky_work.convolve {
+            graph.add_pass(
+                "Sky irradiance",
+                &[sky_capture.usage(
+                    vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+                    vk::PipelineStageFlags2::COMPUTE_SHADER,
+                )],
+                &[sky_irradiance.usage(
+                    vk::ImageLayout::GENERAL,
+                    vk::PipelineStageFlags2::COMPUTE_SHADER,
+                )],
+                |buf| self.record_sky_irradiance(buf),
+            );
+        }
+        let irradiance_read = sky_irradiance.usage(
+            vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+            vk::PipelineStageFlags2::FRAGMENT_SHADER,
+        );
+
+        graph.add_pass(
+            self.passes.shadow.debug_name,
+            &[],
+            &[shadow_map.usage(
+                vk::ImageLayout::DEPTH_ATTACHMENT_OPTIMAL,
+                vk::PipelineStageFlags2::EARLY_FRAGMENT_TESTS
+                    | vk::PipelineStageFlags2::LATE_FRAGMENT_TESTS,
+            )],
+            |buf| self.record_shadow_pass(buf, settings),
+        );
+
+        // Without the pre-pass the scene isn't drawn at all, so there's no depth to lay down.
+        let depth_prepass = settings.depth_prepass && settings.passes.scene;
+        if depth_prepass {
+            graph.add_pass(
+                self.passes.depth_prepass.debug_name,
+                &[],
+                &[depth.usage(
+                    vk::ImageLayout::DEPTH_ATTACHMENT_OPTIMAL,
+                    vk::PipelineStageFlags2::EARLY_FRAGMENT_TESTS
+                        | vk::PipelineStageFlags2::LATE_FRAGMENT_TESTS,
+                )],
+                |buf| self.record_depth_prepass(buf, settings),
+            );
+        }
+        let depth_load_op = if depth_prepass {
+            vk::AttachmentLoadOp::LOAD
+        } else {
+            vk::AttachmentLoadOp::CLEAR
+        };
+
+        graph.add_pass(
+            self.passes.render.debug_name,
+            &[
+                shadow_map.usage(
+                    vk::ImageLayout::DEPTH_READ_ONLY_OPTIMAL,
+                    vk::PipelineStageFlags2::FRAGMENT_SHADER,
+                ),
+                irradiance_read,
+            ],
             &[
-                color.from_undefined().to_color_write(),
-                depth.from_undefined().to_depth(),
+                color.usage(
+                    vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+                    vk::PipelineStageFlags2::COLOR_ATTACHMENT_OUTPUT,
+                ),
+                depth.usage(
+                    vk::ImageLayout::DEPTH_ATTACHMENT_OPTIMAL,
+                    vk::PipelineStageFlags2::EARLY_FRAGMENT_TESTS
+                        | vk::PipelineStageFlags2::LATE_FRAGMENT_TESTS,
+                ),
             ],
+            |buf| {
+                self.passes.render.begin(
+                    buf,
+                    &self.color,
+                    &self.depth,
+                    depth_load_op,
+                    self.render_extent,
+                    &self.dev,
+                );
+                if settings.passes.scene {
+                    self.record_draws(settings);
+                    let secondary_buffers = &self.secondary_command_buffers[self.flight_index];
+                    unsafe { self.dev.cmd_execute_commands(buf, secondary_buffers) };
+                }
+                self.passes.render.end(buf, &self.dev);
+            },
         );
 
-        self.passes
-            .render
-            .begin(buf, color, depth, self.swapchain.extent, &self.dev);
-
-        self.bind_descriptor_set(buf);
-
-        match settings.voxel_rendering {
-            VoxelRendering::Classic => todo!(),
-            VoxelRendering::MeshShaders => {
-                let voxel_meshlet_count = self.voxel_meshlet_count.load(Ordering::SeqCst);
-                begin_label(buf, "Voxel draws (mesh shaders)", [255, 0, 0], &self.dev);
-                self.bind_graphics_pipeline(buf, self.pipelines.voxel);
-                self.draw_mesh_shaders(buf, voxel_meshlet_count.div_ceil(64));
-                end_label(buf, &self.dev);
-
-                if voxel_meshlet_count > 0 {
-                    begin_label(buf, "Debug voxel triangle draw", [238, 186, 11], &self.dev);
-                    self.bind_graphics_pipeline(buf, self.pipelines.debug_voxel_triangle);
-                    self.draw_mesh_shaders(buf, 1);
-                    end_label(buf, &self.dev);
-
-                    begin_label(buf, "Debug voxel world bound draw", [255, 78, 0], &self.dev);
-                    self.bind_graphics_pipeline(buf, self.pipelines.debug_voxel_world_bound);
-                    self.draw_mesh_shaders(buf, 1);
-                    end_label(buf, &self.dev);
-
-                    begin_label(buf, "Debug voxel screen bound draw", [113, 0, 0], &self.dev);
-                    self.bind_graphics_pipeline(buf, self.pipelines.debug_voxel_screen_bound);
-                    self.draw_mesh_shaders(buf, 1);
-                    end_label(buf, &self.dev);
-                }
-            }
-            VoxelRendering::RayTracing => {
-                begin_label(buf, "Voxel draws (ray tracing)", [255, 0, 0], &self.dev);
-                self.bind_graphics_pipeline(buf, self.pipelines.voxel_rt);
-                unsafe { self.dev.cmd_draw(buf, 6, 1, 0, 0) };
-                end_label(buf, &self.dev);
-            }
+        // Transparent surfaces are only tested against the depth of the opaque ones, so the later
+        // passes see the same depth they would without them.
+        graph.add_pass(
+            self.passes.transparent.debug_name,
+            &[
+                depth.usage(
+                    vk::ImageLayout::DEPTH_READ_ONLY_OPTIMAL,
+                    vk::PipelineStageFlags2::EARLY_FRAGMENT_TESTS
+                        | vk::PipelineStageFlags2::LATE_FRAGMENT_TESTS,
+                ),
+                irradiance_read,
+            ],
+            &[color.usage(
+                vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+                vk::PipelineStageFlags2::COLOR_ATTACHMENT_OUTPUT,
+            )],
+            |buf| self.record_transparent_pass(buf, settings),
+        );
+
+        let sampled = |image: ResourceHandle| {
+            image.usage(
+                vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+                vk::PipelineStageFlags2::FRAGMENT_SHADER,
+            )
+        };
+        let attachment = |image: ResourceHandle| {
+            image.usage(
+                vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+                vk::PipelineStageFlags2::COLOR_ATTACHMENT_OUTPUT,
+            )
+        };
+        // Resolved as soon as the scene is complete, so that the effects after it work with the
+        // antialiased image, and so that they don't end up accumulated in the history.
+        if let Some(history) = &self.taa_history {
+            let history_index = self.frame_index % 2;
+            // Last sampled by the previous frame's composite, and the only render target whose
+            // contents are needed across frames.
+            let previous_history = graph.add_resource(ResourceNode {
+                image: &history[1 - history_index],
+                previous_stage: vk::PipelineStageFlags2::FRAGMENT_SHADER,
+                discard: false,
+                layout_at_end: None,
+            });
+            let current_image = &history[history_index];
+            let current_history = graph.add_resource(ResourceNode {
+                image: current_image,
+                previous_stage: vk::PipelineStageFlags2::FRAGMENT_SHADER,
+                discard: true,
+                layout_at_end: None,
+            });
+            let depth_read = depth.usage(
+                vk::ImageLayout::DEPTH_READ_ONLY_OPTIMAL,
+                vk::PipelineStageFlags2::FRAGMENT_SHADER,
+            );
+            graph.add_pass(
+                self.passes.taa.debug_name,
+                &[sampled(color), depth_read, sampled(previous_history)],
+                &[attachment(current_history)],
+                |buf| self.record_taa_resolve(buf, current_image),
+            );
+            graph.add_pass(
+                "TAA composite",
+                &[sampled(current_history), depth_read],
+                &[attachment(color)],
+                |buf| self.record_taa_composite(buf),
+            );
         }
+        graph.add_pass(
+            self.passes.ssao.debug_name,
+            &[depth.usage(
+                vk::ImageLayout::DEPTH_READ_ONLY_OPTIMAL,
+                vk::PipelineStageFlags2::FRAGMENT_SHADER,
+            )],
+            &[attachment(ssao_raw)],
+            |buf| self.record_ssao(buf, settings),
+        );
+        graph.add_pass(
+            "SSAO blur",
+            &[sampled(ssao_raw)],
+            &[attachment(ssao_blurred)],
+            |buf| self.record_ssao_blur(buf, settings),
+        );
+        // Added before bloom, as the composite writes into the color image the reflections sample.
+        graph.add_pass(
+            "Hi-Z",
+            &[depth.usage(
+                vk::ImageLayout::DEPTH_READ_ONLY_OPTIMAL,
+                vk::PipelineStageFlags2::COMPUTE_SHADER,
+            )],
+            &[hiz.usage(
+                vk::ImageLayout::GENERAL,
+                vk::PipelineStageFlags2::COMPUTE_SHADER,
+            )],
+            |buf| self.record_hiz(buf, settings),
+        );
+        graph.add_pass(
+            self.passes.ssr.debug_name,
+            &[
+                sampled(color),
+                depth.usage(
+                    vk::ImageLayout::DEPTH_READ_ONLY_OPTIMAL,
+                    vk::PipelineStageFlags2::FRAGMENT_SHADER,
+                ),
+                hiz.usage(
+                    vk::ImageLayout::GENERAL,
+                    vk::PipelineStageFlags2::FRAGMENT_SHADER,
+                ),
+            ],
+            &[attachment(ssr_image)],
+            |buf| self.record_ssr(buf, settings),
+        );
 
-        begin_label(buf, "Sun draw", [156, 85, 35], &self.dev);
-        self.bind_graphics_pipeline(buf, self.pipelines.sun);
-        self.mesh_objects[1].bind_vertex(buf, &self.dev);
-        self.mesh_objects[1].draw(1, buf, &self.dev);
-        end_label(buf, &self.dev);
+        // Added after the reflections, which trace against the sharp scene, and before bloom.
+        graph.add_pass(
+            self.passes.dof.debug_name,
+            &[
+                sampled(color),
+                depth.usage(
+                    vk::ImageLayout::DEPTH_READ_ONLY_OPTIMAL,
+                    vk::PipelineStageFlags2::FRAGMENT_SHADER,
+                ),
+            ],
+            &[attachment(dof_image)],
+            |buf| self.record_dof(buf, settings),
+        );
+        graph.add_pass(
+            "Depth of field composite",
+            &[sampled(dof_image)],
+            &[attachment(color)],
+            |buf| self.record_dof_composite(buf, settings),
+        );
 
-        begin_label(buf, "Star draws", [213, 204, 184], &self.dev);
-        self.bind_graphics_pipeline(buf, self.pipelines.star);
-        self.mesh_objects[0].bind_vertex(buf, &self.dev);
-        self.mesh_objects[0].draw(world.stars.len(), buf, &self.dev);
-        end_label(buf, &self.dev);
+        graph.add_pass(
+            "Bloom threshold",
+            &[sampled(color)],
+            &[attachment(bloom_ping)],
+            |buf| self.record_bloom_threshold(buf, settings),
+        );
+        // Every pass ends back in the first image, which is where the composite reads from.
+        for _ in 0..settings.bloom.passes.clamp(1, BLOOM_MAX_PASSES) {
+            graph.add_pass(
+                "Bloom horizontal blur",
+                &[sampled(bloom_ping)],
+                &[attachment(bloom_pong)],
+                |buf| {
+                    self.record_bloom_blur(buf, self.pipelines.bloom_blur_horizontal, 1, settings)
+                },
+            );
+            graph.add_pass(
+                "Bloom vertical blur",
+                &[sampled(bloom_pong)],
+                &[attachment(bloom_ping)],
+                |buf| self.record_bloom_blur(buf, self.pipelines.bloom_blur_vertical, 0, settings),
+            );
+        }
+        graph.add_pass(
+            "Bloom composite",
+            &[sampled(bloom_ping)],
+            &[attachment(color)],
+            |buf| self.record_bloom_composite(buf, settings),
+        );
 
-        begin_label(buf, "Skybox draw", [129, 147, 164], &self.dev);
-        self.bind_graphics_pipeline(buf, self.pipelines.skybox);
-        unsafe { self.dev.cmd_draw(buf, 6, 1, 0, 0) };
-        end_label(buf, &self.dev);
+        // Upscaling happens as part of postprocessing, as the pass samples the scene with a
+        // bilinear sampler while drawing at the swapchain resolution anyway.
+        graph.add_pass(
+            self.passes.postprocess.debug_name,
+            &[sampled(color), sampled(ssr_image), sampled(ssao_blurred)],
+            &[attachment(target)],
+            |buf| {
+                self.record_fullscreen(
+                    buf,
+                    &self.passes.postprocess,
+                    self.pipelines.postprocess,
+                    &self.swapchain.images[image_index],
+                    self.swapchain.extent,
+                    vk::AttachmentLoadOp::CLEAR,
+                    settings.passes.upscale,
+                )
+            },
+        );
+
+        // Offscreen rendering has no interface, so there's nothing to draw.
+        #[cfg(feature = "dev-menu")]
+        if let (Some(interface_renderer), Some(ui_draw)) = (&mut interface_renderer, ui_draw) {
+            graph.add_pass(
+                INTERFACE_PASS.debug_name,
+                &[],
+                &[target.usage(
+                    vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+                    vk::PipelineStageFlags2::COLOR_ATTACHMENT_OUTPUT,
+                )],
+                |buf| {
+                    let target = &self.swapchain.images[image_index];
+                    // TODO: Fix drawing SRGB interface to linear color space.
+                    INTERFACE_PASS.begin_overlay(buf, target, self.swapchain.extent, &self.dev);
+                    interface_renderer.cmd_draw(buf, ui_draw).unwrap();
+                    INTERFACE_PASS.end(buf, &self.dev);
+                },
+            );
+        }
+
+        // Added last, so that the recording contains exactly what gets presented, interface
+        // included.
+        if let Some(capture) = &self.capture {
+            graph.add_pass(
+                "Frame capture",
+                &[target.usage(
+                    vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+                    vk::PipelineStageFlags2::COPY,
+                )],
+                &[],
+                |buf| {
+                    capture.ring.record_copy(
+                        buf,
+                        &self.swapchain.images[image_index],
+                        self.flight_index,
+                        &self.dev,
+                    )
+                },
+            );
+        }
+
+        let order = graph.compile().execute(buf, &self.dev, |buf, index| {
+            assert!(
+                index < 2 * TIMED_PASS_MAX_COUNT,
+                "too many passes to time, max: {TIMED_PASS_MAX_COUNT}"
+            );
+            self.write_timestamp(buf, 2 + index, vk::PipelineStageFlags::ALL_COMMANDS);
+        });
+        self.timed_passes[self.flight_index] = order;
 
         #[cfg(feature = "dev-menu")]
         {
-            // TODO: Fix drawing SRGB interface to linear color space.
-            begin_label(buf, "Debugging interface draw", [63, 70, 73], &self.dev);
-            self.interface_renderer
-                .as_mut()
-                .unwrap()
-                .cmd_draw(buf, ui_draw)
-                .unwrap();
-            end_label(buf, &self.dev);
+            self.interface_renderer = interface_renderer;
         }
+    }
 
-        self.passes.render.end(buf, &self.dev);
+    // Recording is spread over a thread pool, with each draw group going into its own secondary
+    // command buffer. There aren't many draw calls yet so the gains are small, but the voxel draws
+    // especially are expected to grow. Whether it pays off over the thread pool overhead depends on
+    // the scene, so it can be turned off to compare frame times.
+    fn record_draws(&self, settings: &RendererSettings) {
+        let recorder = DrawRecorder {
+            dev: &self.dev,
+            pipelines: &self.pipelines,
+            pipeline_layout: self.pipeline_layout,
+            descriptor_set: self.descriptor_sets[self.flight_index],
+            color_format: self.swapchain.format.format,
+            mesh_objects: &self.mesh_objects,
+            object_draws: &self.object_draws[..self.opaque_object_count],
+            voxel_rendering: settings.voxel_rendering,
+            depth_prepass: settings.depth_prepass,
+            voxel_meshlet_count: self.voxel_meshlet_count.load(Ordering::SeqCst),
+            object_draw_calls: self.object_draw_calls.handle(),
+            star_draw_commands: self.star_draw_commands.handle(),
+            debug_line_count: self.debug_line_count,
+            flight_index: self.flight_index,
+        };
+        let secondary_buffers = &self.secondary_command_buffers[self.flight_index];
+        if !settings.parallel_recording {
+            for (group, buf) in DrawGroup::ALL.into_iter().zip(secondary_buffers) {
+                recorder.record(group, *buf);
+            }
+            return;
+        }
+        self.recording_threads.scope(|scope| {
+            for (group, buf) in DrawGroup::ALL.into_iter().zip(secondary_buffers) {
+                let recorder = &recorder;
+                scope.spawn(move |_| recorder.record(group, *buf));
+            }
+        });
+    }
 
-        self.barriers(buf, &[color.from_color_write().to_present()]);
+    pub(super) fn record_fullscreen(
+        &self,
+        buf: vk::CommandBuffer,
+        pass: &Pass,
+        pipeline: vk::Pipeline,
+        target: &ImageResources,
+        extent: vk::Extent2D,
+        load_op: vk::AttachmentLoadOp,
+        enable: bool,
+    ) {
+        pass.begin_color_only(buf, target, extent, load_op, &self.dev);
+        if enable {
+            unsafe {
+                self.dev
+                    .cmd_bind_pipeline(buf, vk::PipelineBindPoint::GRAPHICS, pipeline);
+                self.dev.cmd_bind_descriptor_sets(
+                    buf,
+                    vk::PipelineBindPoint::GRAPHICS,
+                    self.pipeline_layout,
+                    0,
+                    &[self.descriptor_sets[self.flight_index]],
+                    &[],
+                );
+                self.dev.cmd_draw(buf, 6, 1, 0, 0);
+            }
+        }
+        pass.end(buf, &self.dev);
+    }
+
+    // The debug vertex buffer is split into a region per frame in flight, so that writing lines for
+    // this frame doesn't race with the GPU still reading the previous one. This has to happen
+    // after waiting for the previous frame of the flight index.
+    #[cfg(feature = "dev-menu")]
+    fn upload_debug_draw(&mut self, debug_draw: &DebugDraw, settings: &RendererSettings) {
+        if !settings.debug_draw {
+            self.debug_line_count = 0;
+            return;
+        }
+        let lines = debug_draw.lines();
+        if lines.len() > DEFAULT_DEBUG_LINE_MAX_COUNT {
+            warn!(
+                "too many debug lines, \x1B[1mcount\x1B[0m: {}, \x1B[1mmax\x1B[0m: {DEFAULT_DEBUG_LINE_MAX_COUNT}",
+                lines.len()
+            );
+        }
+        let region_start = 2 * DEFAULT_DEBUG_LINE_MAX_COUNT * self.flight_index;
+        let region_end = region_start + 2 * DEFAULT_DEBUG_LINE_MAX_COUNT;
+        let region = &mut self.debug_vertices.mapped()[region_start..region_end];
+        self.debug_line_count = debug_draw.write_vertices(region);
+    }
+
+    // The palette is small enough to be rewritten every frame, so edits from the dev menu show up
+    // without remeshing anything. Split into regions per flight index like the global uniform.
+    fn upload_voxel_materials(&mut self, settings: &RendererSettings) {
+        let flags: [u32; MATERIAL_COUNT] =
+            std::array::from_fn(|index| self.material_flags(VoxelMaterialId(index as u8)));
+        let region_start = MATERIAL_COUNT * self.flight_index;
+        let region =
+            &mut self.voxel_materials.mapped()[region_start..region_start + MATERIAL_COUNT];
+        for ((slot, material), flags) in region.iter_mut().zip(&settings.voxel_materials).zip(flags)
+        {
+            slot.write(VoxelMaterial { flags, ..*material });
+        }
     }
 
     fn update_global_uniform(
-        &self,
+        &mut self,
         world: &World,
         voxels: &VoxelsConfig,
         voxel_meshlet_count: u32,
         settings: &RendererSettings,
-        window_size: PhysicalSize<u32>,
     ) {
-        let mut materials = [VoxelMaterial {
-            albedo: Vector3::zeros(),
-            roughness: 0.,
-            emit: Vector3::zeros(),
-            metallic: 0.,
-        }; 256];
-        materials[1] = VoxelMaterial {
-            albedo: Vector3::new(0.55, 0.6, 0.66),
-            roughness: 1.,
-            emit: Vector3::zeros(),
-            metallic: 0.,
-        };
-        materials[2] = VoxelMaterial {
-            albedo: Vector3::new(0.62, 0.4, 0.24),
-            roughness: 1.,
-            emit: Vector3::zeros(),
-            metallic: 0.,
-        };
-        materials[3] = VoxelMaterial {
-            albedo: Vector3::new(0.63, 0.81, 0.42),
-            roughness: 1.,
-            emit: Vector3::zeros(),
-            metallic: 0.,
-        };
-        self.global.write(
-            self.flight_index,
-            &Global {
-                voxels: Voxels {
-                    chunk_size: voxels.chunk_size as u32,
-                    meshlet_count: voxel_meshlet_count,
-                    root_svo_index: 0,
-                    root_svo_side: 64,
-                    root_svo_base: Vector3::zeros(),
-                },
-                light: world.light(),
-                atmosphere: Atmosphere {
-                    enable: settings.enable_atmosphere,
-                    _pad0: [0; 3],
-                    scatter_point_count: settings.atmosphere_in_scattering_samples as u32,
-                    optical_depth_point_count: settings.atmosphere_optical_depth_samples as u32,
-                    density_falloff: world.atmosphere.density_falloff,
-                    planet_position: Vector3::new(
-                        world.camera.position().x,
-                        world.camera.position().y,
-                        -world.atmosphere.planet_radius,
-                    ),
-                    planet_radius: world.atmosphere.planet_radius,
-                    sun_position: world.sun().transform.translation,
-                    scale: world.atmosphere.scale,
-                    wavelengths: settings.atmosphere_wavelengths,
-                    scattering_strength: world.atmosphere.scattering_strength,
-                    henyey_greenstein_g: world.atmosphere.henyey_greenstein_g,
+        // World::add_point_light already refuses lights past the limit, so this only matters if the
+        // list is modified directly.
+        let light_count = world.point_lights.len().min(POINT_LIGHT_MAX_COUNT);
+        let mut point_lights = [PointLight {
+            position: Vector3::zeros(),
+            radius: 0.,
+            color: Vector3::zeros(),
+            intensity: 0.,
+        }; POINT_LIGHT_MAX_COUNT];
+        point_lights[..light_count].copy_from_slice(&world.point_lights[..light_count]);
+        let projection = self.jittered_projection_matrix(settings);
+        let global = Global {
+            voxels: Voxels {
+                chunk_size: voxels.chunk_size as u32,
+                meshlet_count: voxel_meshlet_count,
+                root_svo_index: 0,
+                root_svo_side: 64,
+                root_svo_base: Vector3::zeros(),
+                ambient_occlusion_strength: if voxels.ambient_occlusion {
+                    voxels.ambient_occlusion_strength
+                } else {
+                    0.
                 },
-                postprocessing: PostprocessUniform {
-                    exposure: settings.postprocess.exposure,
-                    tonemapper: settings.postprocess.tonemapper,
-                    gamma: settings.postprocess.gamma,
-                },
-                camera: Camera {
-                    view_matrix: world.view_matrix(),
-                    projection_matrix: self.projection_matrix(settings),
-                    inverse_view_matrix: world.view_matrix().try_inverse().unwrap(),
-                    inverse_projection_matrix: self
-                        .projection_matrix(settings)
-                        .try_inverse()
-                        .unwrap(),
-                    resolution: Vector2::new(window_size.width as f32, window_size.height as f32),
-                    depth_near: settings.depth_near,
-                    depth_far: settings.depth_far,
-                    position: world.camera.position(),
-                    _pad1: 0.,
-                    direction: world.camera.view_direction(),
-                },
-                materials,
-                debug: Debug { meshlet_id: 0 },
+                first_material: (MATERIAL_COUNT * self.flight_index) as u32,
+                active_session: self.active_voxel_session as u32,
             },
-        );
+            light: world.light(),
+            lights: point_lights,
+            light_count: light_count as u32,
+            shadow: self.shadow_uniform(world, settings),
+            atmosphere: Atmosphere {
+                enable: settings.enable_atmosphere,
+                _pad0: [0; 3],
+                density_falloff: world.atmosphere.density_falloff,
+                _pad1: [0.; 2],
+                planet_position: Vector3::new(
+                    world.camera.position().x,
+                    world.camera.position().y,
+                    -world.atmosphere.planet_radius,
+                ),
+                planet_radius: world.atmosphere.planet_radius,
+                sun_direction: world.sun_direction(),
+                scale: world.atmosphere.scale,
+                wavelengths: settings.atmosphere_wavelengths,
+                scattering_strength: world.atmosphere.scattering_strength,
+                henyey_greenstein_g: world.atmosphere.henyey_greenstein_g,
+                _pad2: [0.; 3],
+                sky_color: world.sky_color(),
+                skybox_texture: self.skybox_loaded,
+                _pad3: [0; 3],
+            },
+            ambient: self.ambient_uniform(settings),
+            postprocessing: PostprocessUniform {
+                exposure: settings.postprocess.exposure,
+                tonemapper: settings.postprocess.tonemapper,
+                gamma: settings.postprocess.gamma,
+                bloom_threshold: settings.bloom.threshold,
+                bloom_knee: settings.bloom.knee,
+                bloom_intensity: settings.bloom.intensity,
+                time: world.time,
+                aspect_ratio: self.swapchain.extent.width as f32
+                    / self.swapchain.extent.height as f32,
+            },
+            ssr: Ssr {
+                step_size: settings.ssr.step_size,
+                thickness: settings.ssr.thickness,
+                fade_distance: settings.ssr.fade_distance,
+                debug_overlay: settings.ssr.debug_overlay,
+                _pad0: [0; 3],
+            },
+            dof: Dof {
+                focus_distance: settings.dof.focus_distance,
+                aperture: settings.dof.aperture,
+                autofocus: settings.dof.autofocus,
+                _pad0: [0; 3],
+            },
+            taa: self.taa_uniform(world, settings),
+            particles: self.particles.uniform(self.flight_index),
+            camera: Camera {
+                view_matrix: world.view_matrix(),
+                projection_matrix: projection,
+                inverse_view_matrix: world.view_matrix().try_inverse().unwrap(),
+                inverse_projection_matrix: projection.try_inverse().unwrap(),
+                resolution: Vector2::new(
+                    self.render_extent.width as f32,
+                    self.render_extent.height as f32,
+                ),
+                depth_near: settings.depth_near,
+                depth_far: settings.depth_far,
+                position: world.camera.position(),
+                _pad1: 0.,
+                direction: world.camera.view_direction(),
+            },
+            culling: self.culling_uniform(world, settings),
+            debug: Debug { meshlet_id: 0 },
+        };
+        self.global
+            .write(self.flight_index, &global, &mut self.staging);
     }
 
+    // Also waits for everything uploaded through the staging ring so far, which is the only thing
+    // rendering depends on that isn't recorded in the frame itself.
     fn submit_graphics(&self) {
-        let command_buffer = self.command_buffers[self.flight_index];
-        let image_available = self.sync.image_available[self.flight_index];
-        let render_finished = self.sync.render_finished[self.flight_index];
-
-        let wait_semaphores = [image_available];
-        let command_buffers = [command_buffer];
-        let signal_semaphores = [render_finished];
-        let submit_info = vk::SubmitInfo::default()
-            .wait_semaphores(&wait_semaphores)
-            .wait_dst_stage_mask(&[vk::PipelineStageFlags::FRAGMENT_SHADER])
-            .command_buffers(&command_buffers)
-            .signal_semaphores(&signal_semaphores);
+        let command_buffer_info = vk::CommandBufferSubmitInfo::default()
+            .command_buffer(self.command_buffers[self.flight_index]);
+        let wait_infos = [
+            self.staging_ring.wait_info(),
+            vk::SemaphoreSubmitInfo::default()
+                .semaphore(self.sync.image_available[self.flight_index])
+                .stage_mask(
+                    vk::PipelineStageFlags2::FRAGMENT_SHADER
+                        | vk::PipelineStageFlags2::COLOR_ATTACHMENT_OUTPUT,
+                ),
+        ];
+        let signal_infos = [
+            vk::SemaphoreSubmitInfo::default()
+                .semaphore(self.sync.frame_timeline)
+                .value(self.frame_index as u64 + 1)
+                .stage_mask(vk::PipelineStageFlags2::ALL_COMMANDS),
+            vk::SemaphoreSubmitInfo::default()
+                .semaphore(self.sync.render_finished[self.flight_index])
+                .stage_mask(vk::PipelineStageFlags2::ALL_COMMANDS),
+        ];
+        // Without a swapchain nothing signals the acquire semaphore or waits for the present one,
+        // and a binary semaphore that's signalled but never waited for can't be signalled again.
+        let semaphore_count = if self.swapchain.handle.is_some() {
+            2
+        } else {
+            1
+        };
+        let submit_info = vk::SubmitInfo2::default()
+            .wait_semaphore_infos(&wait_infos[..semaphore_count])
+            .command_buffer_infos(std::slice::from_ref(&command_buffer_info))
+            .signal_semaphore_infos(&signal_infos[..semaphore_count]);
         unsafe {
-            self.dev.queue_submit(
-                self.queue,
-                &[submit_info],
-                self.sync.in_flight[self.flight_index],
-            )
+            self.dev
+                .queue_submit2(self.queue, &[submit_info], vk::Fence::null())
         }
         .unwrap();
     }
 
-    fn submit_present(&self, image_index: usize) {
+    fn submit_present(&mut self, image_index: usize) {
+        let Some(handle) = self.swapchain.handle else {
+            return;
+        };
         let render_finished = self.sync.render_finished[self.flight_index];
 
         let wait_semaphores = [render_finished];
-        let swapchains = [self.swapchain.handle];
+        let swapchains = [handle];
         let image_indices = [image_index as u32];
         let present_info = vk::PresentInfoKHR::default()
             .wait_semaphores(&wait_semaphores)
             .swapchains(&swapchains)
             .image_indices(&image_indices);
-        unsafe {
+        let present_result = unsafe {
             self.dev
                 .swapchain_ext
                 .queue_present(self.queue, &present_info)
+        };
+        // Happens when the window is resized or moved between monitors with different scale
+        // factors, sometimes before winit reports anything.
+        match present_result {
+            Ok(true) | Err(vk::Result::ERROR_OUT_OF_DATE_KHR) => self.swapchain_outdated = true,
+            result => {
+                result.unwrap();
+            }
+        }
+
+        // This measures when presents are queued rather than when images actually appear on the
+        // screen, but the queueing is throttled by the presentation engine, so with frames
+        // rendering fast enough the intervals still show how the present mode paces them.
+        let now = Instant::now();
+        self.present_interval = self.last_present.map(|last_present| now - last_present);
+        self.last_present = Some(now);
+    }
+
+    pub fn stats(&self) -> RendererStats {
+        RendererStats {
+            frametime: self.frametime,
+            pass_times: self.pass_times.clone(),
+            present_mode: self.swapchain.present_mode,
+            present_interval: self.present_interval,
+            visible_objects: self.visible_object_count,
+            total_objects: self.object_draws.len() as u32,
+            visible_stars: self.visible_star_count,
+            total_stars: self.star_count as u32,
+            particle_count: self.particles.particle_count,
+            voxel_meshlet_count: self.voxel_meshlet_count.load(Ordering::SeqCst),
+            visible_voxel_meshlets: self.visible_meshlet_count,
+            memory: self.memory_report(),
+            staging_ring: self.staging_ring.occupancy(),
         }
-        .unwrap();
+    }
+
+    // Queries the driver for the budget, which is cheap enough to do every frame, but still takes the
+    // allocator lock, so callers shouldn't go much beyond that.
+    pub fn memory_report(&self) -> MemoryStats {
+        self.dev.allocator.stats(&self.dev)
+    }
+
+    fn check_memory_pressure(&mut self) {
+        let pressure = self.memory_report().vram_pressure();
+        let memory_pressure = pressure > MEMORY_BUDGET_CRITICAL;
+        if memory_pressure && !self.memory_pressure {
+            warn!(
+                "vram almost full, \x1B[1musage\x1B[0m: {:.0}%",
+                pressure * 100.
+            );
+        }
+        self.memory_pressure = memory_pressure;
+    }
+
+    // Makes the texture available to shaders under the returned index. The image has to be in the
+    // shader read-only layout whenever it's used, and has to outlive the renderer.
+    pub fn register_texture(&mut self, name: &str, image_view: vk::ImageView) -> u32 {
+        assert!(
+            !self.texture_handles.contains_key(name),
+            "texture {name} registered twice"
+        );
+        assert!(
+            self.next_texture_slot < TEXTURES_CAPACITY,
+            "too many textures, capacity: {TEXTURES_CAPACITY}"
+        );
+        let slot = self.next_texture_slot;
+        self.next_texture_slot += 1;
+        update_textures_descriptor(
+            &self.descriptor_sets,
+            slot,
+            image_view,
+            &self.samplers,
+            &self.dev,
+        );
+        self.texture_handles.insert(name.to_owned(), slot);
+        slot
+    }
+
+    pub fn texture_handle(&self, name: &str) -> Option<u32> {
+        self.texture_handles.get(name).copied()
+    }
+
+    // Sessions are identified by the index they were created with, which also tags their meshlets
+    // in the shared buffers.
+    pub fn voxel_session_memory(&self, session: u8) -> Box<dyn VoxelGpuMemory> {
+        Box::new(VoxelSessionMemory::new(self.voxel_memory.clone(), session))
+    }
+
+    // Takes effect from the next frame on. Chunks of the previous session stay in memory, but are
+    // evicted first whenever the new one runs out of space.
+    pub fn set_active_voxel_session(&mut self, session: u8) {
+        self.active_voxel_session = session;
+        self.voxel_memory
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .set_active_session(session);
     }
 
     pub fn wait_idle(&self) {
         unsafe {
             self.dev.device_wait_idle().unwrap();
         };
+        PipelineCacheManager::save(&self.dev, self.pipeline_cache, &self.pipeline_cache_path);
     }
 
     fn projection_matrix(&self, settings: &RendererSettings) -> Matrix4<f32> {
@@ -480,14 +1555,6 @@ impl Renderer {
         proj
     }
 
-    fn bind_graphics_pipeline(&self, buf: vk::CommandBuffer, pipeline: vk::Pipeline) {
-        unsafe {
-            self.dev
-                .cmd_bind_pipeline(buf, vk::PipelineBindPoint::GRAPHICS, pipeline)
-        };
-    }
-
-    #[allow(dead_code)]
     fn bind_compute_pipeline(&self, buf: vk::CommandBuffer, pipeline: vk::Pipeline) {
         unsafe {
             self.dev
@@ -495,50 +1562,32 @@ impl Renderer {
         };
     }
 
-    fn bind_descriptor_set(&self, buf: vk::CommandBuffer) {
+    fn reset_timestamps(&self, buf: vk::CommandBuffer) {
         unsafe {
-            self.dev.cmd_bind_descriptor_sets(
+            self.dev.cmd_reset_query_pool(
                 buf,
-                vk::PipelineBindPoint::GRAPHICS,
-                self.pipeline_layout,
-                0,
-                &[self.descriptor_sets[self.flight_index]],
-                &[],
+                self.query_pool,
+                (TIMESTAMPS_PER_FRAME * self.flight_index) as u32,
+                TIMESTAMPS_PER_FRAME as u32,
             )
         };
     }
 
-    fn draw_mesh_shaders(&self, buf: vk::CommandBuffer, count: u32) {
-        unsafe { self.dev.mesh_ext.cmd_draw_mesh_tasks(buf, count, 1, 1) };
-    }
-
-    fn barriers(&self, buf: vk::CommandBuffer, barriers: &[vk::ImageMemoryBarrier2]) {
-        let dependency_info = vk::DependencyInfo::default().image_memory_barriers(barriers);
-        unsafe { self.dev.cmd_pipeline_barrier2(buf, &dependency_info) }
-    }
-
-    fn reset_timestamps(&self, buf: vk::CommandBuffer) {
-        unsafe {
-            self.dev
-                .cmd_reset_query_pool(buf, self.query_pool, (2 * self.flight_index) as u32, 2)
-        };
-    }
-
     fn write_timestamp(&self, buf: vk::CommandBuffer, index: usize, stage: vk::PipelineStageFlags) {
         unsafe {
             self.dev.cmd_write_timestamp(
                 buf,
                 stage,
                 self.query_pool,
-                (2 * self.flight_index + index) as u32,
+                (TIMESTAMPS_PER_FRAME * self.flight_index + index) as u32,
             )
         };
     }
 
     fn query_timestamp(&self) -> Option<Duration> {
         // CPU can't wait for current frame metrics because it has to prepare command buffers for
-        // the next frame, the query results are delayed by FRAMES_IN_FLIGHT frames.
-        if self.frame_index < FRAMES_IN_FLIGHT {
+        // the next frame, the query results are delayed by frames_in_flight frames.
+        if self.frame_index < self.frames_in_flight {
             return None;
         }
 
@@ -546,7 +1595,7 @@ impl Renderer {
         unsafe {
             self.dev.get_query_pool_results(
                 self.query_pool,
-                (2 * self.flight_index) as u32,
+                (TIMESTAMPS_PER_FRAME * self.flight_index) as u32,
                 &mut timestamps,
                 vk::QueryResultFlags::TYPE_64,
             )
@@ -558,6 +1607,34 @@ impl Renderer {
             &self.properties,
         ))
     }
+
+    fn query_pass_times(&self) -> Vec<(&'static str, Duration)> {
+        let passes = &self.timed_passes[self.flight_index];
+        if self.frame_index < self.frames_in_flight || passes.is_empty() {
+            return Vec::new();
+        }
+
+        let mut timestamps = vec![0; 2 * passes.len()];
+        unsafe {
+            self.dev.get_query_pool_results(
+                self.query_pool,
+                (TIMESTAMPS_PER_FRAME * self.flight_index + 2) as u32,
+                &mut timestamps,
+                vk::QueryResultFlags::TYPE_64,
+            )
+        }
+        .unwrap();
+
+        passes
+            .iter()
+            .zip(timestamps.chunks_exact(2))
+            .map(|(name, pair)| {
+                let duration =
+                    timestamp_difference_to_duration(pair[1] - pair[0], &self.properties);
+                (*name, duration)
+            })
+            .collect()
+    }
 }
 
 impl MeshObject {
@@ -566,6 +1643,24 @@ impl MeshObject {
         unsafe { dev.cmd_bind_index_buffer(buf, self.index.buffer, 0, vk::IndexType::UINT32) };
     }
 
+    fn draw_indirect(&self, commands: vk::Buffer, index: usize, buf: vk::CommandBuffer, dev: &Dev) {
+        let stride = std::mem::size_of::<vk::DrawIndexedIndirectCommand>();
+        unsafe {
+            dev.cmd_draw_indexed_indirect(buf, commands, (index * stride) as u64, 1, stride as u32)
+        };
+    }
+
+    // Draw calls are read straight from the object draw call buffer, skipping the bounding sphere.
+    fn draw_call(&self, draw_calls: vk::Buffer, index: usize, buf: vk::CommandBuffer, dev: &Dev) {
+        let stride = std::mem::size_of::<ObjectDrawCall>();
+        let offset = index * stride + std::mem::offset_of!(ObjectDrawCall, command);
+        unsafe { dev.cmd_draw_indexed_indirect(buf, draw_calls, offset as u64, 1, stride as u32) };
+    }
+
+    fn draw_instance(&self, instance: u32, buf: vk::CommandBuffer, dev: &Dev) {
+        unsafe { dev.cmd_draw_indexed(buf, 3 * self.triangle_count as u32, 1, 0, 0, instance) };
+    }
+
     fn draw(&self, instance_count: usize, buf: vk::CommandBuffer, dev: &Dev) {
         unsafe {
             dev.cmd_draw_indexed(
@@ -596,3 +1691,88 @@ impl EnumInterface for VoxelRendering {
         })
     }
 }
+
+#[cfg(feature = "dev-menu")]
+impl EnumInterface for AtmosphereQuality {
+    const VALUES: &'static [Self] = &[
+        AtmosphereQuality::Low,
+        AtmosphereQuality::Medium,
+        AtmosphereQuality::High,
+        AtmosphereQuality::Custom,
+    ];
+
+    fn label(&self) -> std::borrow::Cow<str> {
+        std::borrow::Cow::Borrowed(match self {
+            AtmosphereQuality::Low => "Low",
+            AtmosphereQuality::Medium => "Medium",
+            AtmosphereQuality::High => "High",
+            AtmosphereQuality::Custom => "Custom",
+        })
+    }
+}
+
+impl PresentMode {
+    fn vk(self) -> vk::PresentModeKHR {
+        match self {
+            PresentMode::Fifo => vk::PresentModeKHR::FIFO,
+            PresentMode::FifoRelaxed => vk::PresentModeKHR::FIFO_RELAXED,
+            PresentMode::Mailbox => vk::PresentModeKHR::MAILBOX,
+            PresentMode::Immediate => vk::PresentModeKHR::IMMEDIATE,
+        }
+    }
+}
+
+#[cfg(feature = "dev-menu")]
+impl EnumInterface for PresentMode {
+    const VALUES: &'static [Self] = &[
+        PresentMode::Fifo,
+        PresentMode::FifoRelaxed,
+        PresentMode::Mailbox,
+        PresentMode::Immediate,
+    ];
+
+    fn label(&self) -> std::borrow::Cow<str> {
+        std::borrow::Cow::Borrowed(match self {
+            PresentMode::Fifo => "FIFO",
+            PresentMode::FifoRelaxed => "FIFO relaxed",
+            PresentMode::Mailbox => "Mailbox",
+            PresentMode::Immediate => "Immediate",
+        })
+    }
+}
+
+fn postprocess_effects(postprocess: &PostprocessSettings) -> [f32; 5] {
+    [
+        postprocess.grain_strength,
+        postprocess.vignette_strength,
+        postprocess.vignette_radius,
+        postprocess.aberration_strength,
+        postprocess.color_lut_strength,
+    ]
+}
+
+// Every in-scattering sample integrates the optical depth along two rays, so the cost grows with the
+// product of both counts, and custom values past this can stall the GPU long enough for the driver
+// to reset it.
+const MAX_ATMOSPHERE_SAMPLES: usize = 32;
+
+fn atmosphere_samples(settings: &RendererSettings) -> (u32, u32) {
+    match settings.atmosphere_quality {
+        AtmosphereQuality::Low => (6, 2),
+        AtmosphereQuality::Medium => (10, 3),
+        AtmosphereQuality::High => (16, 6),
+        AtmosphereQuality::Custom => (
+            settings
+                .atmosphere_in_scattering_samples
+                .clamp(1, MAX_ATMOSPHERE_SAMPLES) as u32,
+            settings
+                .atmosphere_optical_depth_samples
+                .clamp(1, MAX_ATMOSPHERE_SAMPLES) as u32,
+        ),
+    }
+}
+
+fn ssao_effects(ssao: &SsaoSettings) -> (f32, f32) {
+    let intensity = if ssao.enabled { ssao.intensity } else { 0. };
+    (ssao.radius, intensity)
+}
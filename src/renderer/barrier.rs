@@ -1,70 +1,184 @@
-#![allow(clippy::wrong_self_convention)]
-
-use crate::renderer::util::ImageResources;
+use crate::renderer::util::{Dev, ImageResources};
 use ash::vk;
 
-pub struct ImageBarrier(vk::ImageMemoryBarrier2<'static>);
-
 impl ImageResources {
-    pub fn from_undefined(&self) -> ImageBarrier {
-        ImageBarrier(
-            vk::ImageMemoryBarrier2::default()
-                .src_stage_mask(vk::PipelineStageFlags2::TOP_OF_PIPE)
-                .src_access_mask(vk::AccessFlags2::empty())
-                .old_layout(vk::ImageLayout::UNDEFINED)
-                .image(self.image)
-                .subresource_range(
-                    vk::ImageSubresourceRange::default()
-                        .aspect_mask(vk::ImageAspectFlags::COLOR)
-                        .level_count(1)
-                        .layer_count(1),
-                ),
-        )
+    // Records a barrier transitioning the image from whatever layout it was left in to the new
+    // one. Command buffers are recorded in the same order they are submitted in, so the layout
+    // remembered on the CPU side always matches the GPU one at the point of recording. Access masks
+    // are derived from the layouts alone, assuming the image is both read and written in them.
+    pub fn transition(
+        &self,
+        buf: vk::CommandBuffer,
+        new_layout: vk::ImageLayout,
+        src_stage: vk::PipelineStageFlags2,
+        dst_stage: vk::PipelineStageFlags2,
+        dev: &Dev,
+    ) {
+        let src_access = usage_access(self.current_layout.get(), true);
+        let dst_access = usage_access(new_layout, true);
+        self.transition_with_access(
+            buf, new_layout, src_stage, src_access, dst_stage, dst_access, dev,
+        );
+    }
+
+    // Same as transition, but with access masks given by the caller, for when it knows how the
+    // image is actually used rather than just which layout it's in.
+    #[allow(clippy::too_many_arguments)]
+    pub fn transition_with_access(
+        &self,
+        buf: vk::CommandBuffer,
+        new_layout: vk::ImageLayout,
+        src_stage: vk::PipelineStageFlags2,
+        src_access: vk::AccessFlags2,
+        dst_stage: vk::PipelineStageFlags2,
+        dst_access: vk::AccessFlags2,
+        dev: &Dev,
+    ) {
+        let old_layout = self.current_layout.get();
+        let aspect_mask = if is_depth_layout(old_layout) || is_depth_layout(new_layout) {
+            vk::ImageAspectFlags::DEPTH
+        } else {
+            vk::ImageAspectFlags::COLOR
+        };
+        let barrier = vk::ImageMemoryBarrier2::default()
+            .src_stage_mask(src_stage)
+            .src_access_mask(src_access)
+            .dst_stage_mask(dst_stage)
+            .dst_access_mask(dst_access)
+            .old_layout(old_layout)
+            .new_layout(new_layout)
+            .image(self.image)
+            .subresource_range(
+                vk::ImageSubresourceRange::default()
+                    .aspect_mask(aspect_mask)
+                    .level_count(1)
+                    .layer_count(vk::REMAINING_ARRAY_LAYERS),
+            );
+        let dependency_info =
+            vk::DependencyInfo::default().image_memory_barriers(std::array::from_ref(&barrier));
+        unsafe { dev.cmd_pipeline_barrier2(buf, &dependency_info) };
+        self.current_layout.set(new_layout);
+    }
+
+    // Marks the image contents as no longer needed, so that the next transition doesn't have to
+    // preserve them. This is also always correct for swapchain images after acquiring them.
+    pub fn discard(&self) {
+        self.current_layout.set(vk::ImageLayout::UNDEFINED);
     }
 
-    pub fn from_color_write(&self) -> ImageBarrier {
-        ImageBarrier(
-            vk::ImageMemoryBarrier2::default()
-                .src_stage_mask(vk::PipelineStageFlags2::COLOR_ATTACHMENT_OUTPUT)
-                .src_access_mask(vk::AccessFlags2::COLOR_ATTACHMENT_WRITE)
-                .old_layout(vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL)
-                .image(self.image)
-                .subresource_range(
-                    vk::ImageSubresourceRange::default()
-                        .aspect_mask(vk::ImageAspectFlags::COLOR)
-                        .level_count(1)
-                        .layer_count(1),
-                ),
-        )
+    pub fn debug_assert_layout(&self, layout: vk::ImageLayout) {
+        debug_assert_eq!(
+            self.current_layout.get(),
+            layout,
+            "image used in unexpected layout"
+        );
     }
 }
 
-impl ImageBarrier {
-    pub fn to_color_write(self) -> vk::ImageMemoryBarrier2<'static> {
-        self.0
-            .dst_stage_mask(vk::PipelineStageFlags2::COLOR_ATTACHMENT_OUTPUT)
-            .dst_access_mask(vk::AccessFlags2::COLOR_ATTACHMENT_WRITE)
-            .new_layout(vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL)
+fn is_depth_layout(layout: vk::ImageLayout) -> bool {
+    matches!(
+        layout,
+        vk::ImageLayout::DEPTH_ATTACHMENT_OPTIMAL
+            | vk::ImageLayout::DEPTH_READ_ONLY_OPTIMAL
+            | vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL
+            | vk::ImageLayout::DEPTH_STENCIL_READ_ONLY_OPTIMAL
+    )
+}
+
+// Access done by a pass using the image in the given layout. Only writes ever have to be made
+// available to later passes, so a barrier's source access is the write part of the previous usage,
+// and its destination access is everything the next usage does. Layouts that aren't listed are
+// still handled, as a barrier with all memory access is always correct, just slower. This way using
+// a new layout doesn't crash, and the table can be extended once it actually shows up in a profile.
+pub fn usage_access(layout: vk::ImageLayout, write: bool) -> vk::AccessFlags2 {
+    let (read_access, write_access) = match layout {
+        vk::ImageLayout::UNDEFINED | vk::ImageLayout::PRESENT_SRC_KHR => {
+            (vk::AccessFlags2::empty(), vk::AccessFlags2::empty())
+        }
+        vk::ImageLayout::PREINITIALIZED => {
+            (vk::AccessFlags2::empty(), vk::AccessFlags2::HOST_WRITE)
+        }
+        vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL => (
+            vk::AccessFlags2::COLOR_ATTACHMENT_READ,
+            vk::AccessFlags2::COLOR_ATTACHMENT_WRITE,
+        ),
+        vk::ImageLayout::DEPTH_ATTACHMENT_OPTIMAL
+        | vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL => (
+            vk::AccessFlags2::DEPTH_STENCIL_ATTACHMENT_READ,
+            vk::AccessFlags2::DEPTH_STENCIL_ATTACHMENT_WRITE,
+        ),
+        // Read-only depth is used both for depth testing without writes and for sampling shadow
+        // maps, and there's no separate layout to tell these apart.
+        vk::ImageLayout::DEPTH_READ_ONLY_OPTIMAL
+        | vk::ImageLayout::DEPTH_STENCIL_READ_ONLY_OPTIMAL => (
+            vk::AccessFlags2::DEPTH_STENCIL_ATTACHMENT_READ | vk::AccessFlags2::SHADER_SAMPLED_READ,
+            vk::AccessFlags2::empty(),
+        ),
+        vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL => (
+            vk::AccessFlags2::SHADER_SAMPLED_READ,
+            vk::AccessFlags2::empty(),
+        ),
+        vk::ImageLayout::GENERAL => (
+            vk::AccessFlags2::SHADER_STORAGE_READ | vk::AccessFlags2::SHADER_SAMPLED_READ,
+            vk::AccessFlags2::SHADER_STORAGE_WRITE,
+        ),
+        vk::ImageLayout::TRANSFER_SRC_OPTIMAL => {
+            (vk::AccessFlags2::TRANSFER_READ, vk::AccessFlags2::empty())
+        }
+        vk::ImageLayout::TRANSFER_DST_OPTIMAL => {
+            (vk::AccessFlags2::empty(), vk::AccessFlags2::TRANSFER_WRITE)
+        }
+        _ => (
+            vk::AccessFlags2::MEMORY_READ,
+            vk::AccessFlags2::MEMORY_WRITE,
+        ),
+    };
+    if write {
+        // Attachments written with blending or a load op are read as well, and storage images are
+        // usually written in place.
+        read_access | write_access
+    } else {
+        read_access
     }
+}
+
+pub fn write_access(access: vk::AccessFlags2) -> vk::AccessFlags2 {
+    access
+        & (vk::AccessFlags2::COLOR_ATTACHMENT_WRITE
+            | vk::AccessFlags2::DEPTH_STENCIL_ATTACHMENT_WRITE
+            | vk::AccessFlags2::SHADER_STORAGE_WRITE
+            | vk::AccessFlags2::TRANSFER_WRITE
+            | vk::AccessFlags2::HOST_WRITE
+            | vk::AccessFlags2::MEMORY_WRITE)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-    pub fn to_depth(mut self) -> vk::ImageMemoryBarrier2<'static> {
-        self.0.subresource_range.aspect_mask = vk::ImageAspectFlags::DEPTH;
-        self.0
-            .dst_stage_mask(
-                vk::PipelineStageFlags2::EARLY_FRAGMENT_TESTS
-                    | vk::PipelineStageFlags2::LATE_FRAGMENT_TESTS,
-            )
-            .dst_access_mask(
-                vk::AccessFlags2::DEPTH_STENCIL_ATTACHMENT_READ
-                    | vk::AccessFlags2::DEPTH_STENCIL_ATTACHMENT_WRITE,
-            )
-            .new_layout(vk::ImageLayout::DEPTH_ATTACHMENT_OPTIMAL)
+    #[test]
+    fn unlisted_layouts_use_all_access() {
+        for layout in [
+            vk::ImageLayout::ATTACHMENT_OPTIMAL,
+            vk::ImageLayout::READ_ONLY_OPTIMAL,
+            vk::ImageLayout::SHARED_PRESENT_KHR,
+        ] {
+            assert_eq!(
+                usage_access(layout, true),
+                vk::AccessFlags2::MEMORY_READ | vk::AccessFlags2::MEMORY_WRITE
+            );
+            assert_eq!(usage_access(layout, false), vk::AccessFlags2::MEMORY_READ);
+        }
     }
 
-    pub fn to_present(self) -> vk::ImageMemoryBarrier2<'static> {
-        self.0
-            .dst_stage_mask(vk::PipelineStageFlags2::BOTTOM_OF_PIPE)
-            .dst_access_mask(vk::AccessFlags2::empty())
-            .new_layout(vk::ImageLayout::PRESENT_SRC_KHR)
+    #[test]
+    fn depth_stencil_layouts_match_depth() {
+        assert_eq!(
+            usage_access(vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL, true),
+            usage_access(vk::ImageLayout::DEPTH_ATTACHMENT_OPTIMAL, true)
+        );
+        assert!(is_depth_layout(
+            vk::ImageLayout::DEPTH_STENCIL_READ_ONLY_OPTIMAL
+        ));
     }
 }
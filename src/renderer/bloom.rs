@@ -0,0 +1,58 @@
+use crate::renderer::{Renderer, RendererSettings};
+use ash::vk;
+
+impl Renderer {
+    // The scene is already tonemapped by the time it reaches the color attachment, so the
+    // threshold works on display values between 0 and 1 rather than on physical brightness.
+    pub(super) fn record_bloom_threshold(
+        &self,
+        buf: vk::CommandBuffer,
+        settings: &RendererSettings,
+    ) {
+        self.record_fullscreen(
+            buf,
+            &self.passes.bloom,
+            self.pipelines.bloom_threshold,
+            &self.bloom_images[0],
+            self.bloom_extent,
+            vk::AttachmentLoadOp::CLEAR,
+            settings.passes.bloom,
+        );
+    }
+
+    pub(super) fn record_bloom_blur(
+        &self,
+        buf: vk::CommandBuffer,
+        pipeline: vk::Pipeline,
+        target: usize,
+        settings: &RendererSettings,
+    ) {
+        self.record_fullscreen(
+            buf,
+            &self.passes.bloom,
+            pipeline,
+            &self.bloom_images[target],
+            self.bloom_extent,
+            vk::AttachmentLoadOp::CLEAR,
+            settings.passes.bloom,
+        );
+    }
+
+    // Blends the blurred image additively on top of the scene, upscaling it back to the render
+    // resolution with the bilinear sampler on the way.
+    pub(super) fn record_bloom_composite(
+        &self,
+        buf: vk::CommandBuffer,
+        settings: &RendererSettings,
+    ) {
+        self.record_fullscreen(
+            buf,
+            &self.passes.bloom,
+            self.pipelines.bloom_composite,
+            &self.color,
+            self.render_extent,
+            vk::AttachmentLoadOp::LOAD,
+            settings.passes.bloom && settings.bloom.intensity > 0.,
+        );
+    }
+}
@@ -0,0 +1,221 @@
+use crate::recording::Recorder;
+use crate::renderer::util::ReadbackRing;
+use crate::renderer::Renderer;
+use ash::vk;
+use log::{info, warn};
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::{Path, PathBuf};
+
+// Frames are copied out of the swapchain at the end of every frame and read back a few frames
+// later, once the next frame of the same flight index waits for them anyway. This keeps
+// recording from stalling the GPU, at the cost of the video lagging a frame per frame in flight
+// behind the screen.
+pub struct FrameCapture {
+    pub ring: ReadbackRing,
+    target: CaptureTarget,
+    // Whether the swapchain is in BGRA rather than RGBA order.
+    bgra: bool,
+}
+
+enum CaptureTarget {
+    Recording(Recorder),
+    // Screenshots go through the same ring and are saved from the first frame read back, after
+    // which the capture stops on its own.
+    Screenshot(PathBuf),
+    // Every frame is saved as a numbered PNG, for rendering without a window.
+    ImageSequence {
+        directory: PathBuf,
+        next_frame: usize,
+    },
+}
+
+impl Renderer {
+    pub fn start_recording(&mut self, path: &Path) {
+        if self.capture.is_some() {
+            self.stop_recording();
+        }
+        let extent = self.swapchain.extent;
+        let bgra = self.swapchain_is_bgra();
+        let Some(recorder) = Recorder::start(path, extent.width, extent.height, bgra) else {
+            return;
+        };
+        self.capture = Some(FrameCapture {
+            ring: ReadbackRing::new(extent, self.frames_in_flight, &self.dev),
+            target: CaptureTarget::Recording(recorder),
+            bgra,
+        });
+    }
+
+    pub fn start_image_sequence(&mut self, directory: &Path) -> Result<(), String> {
+        if self.capture.is_some() {
+            self.stop_recording();
+        }
+        std::fs::create_dir_all(directory)
+            .map_err(|error| format!("failed to create {}: {error}", directory.display()))?;
+        self.capture = Some(FrameCapture {
+            ring: ReadbackRing::new(self.swapchain.extent, self.frames_in_flight, &self.dev),
+            target: CaptureTarget::ImageSequence {
+                directory: directory.to_owned(),
+                next_frame: 0,
+            },
+            bgra: self.swapchain_is_bgra(),
+        });
+        Ok(())
+    }
+
+    // All frames that were already submitted still end up in the recording, so the device is
+    // waited for and the remaining copies are read starting from the oldest one.
+    pub fn stop_recording(&mut self) {
+        let Some(mut capture) = self.capture.take() else {
+            return;
+        };
+        unsafe { self.dev.device_wait_idle() }.unwrap();
+        if !matches!(capture.target, CaptureTarget::Screenshot(_)) {
+            for offset in 0..self.frames_in_flight {
+                let flight_index = (self.flight_index + offset) % self.frames_in_flight;
+                if let Some(pixels) = capture.ring.take(flight_index) {
+                    write_frame(
+                        &mut capture.target,
+                        pixels,
+                        capture.ring.extent,
+                        capture.bgra,
+                    );
+                }
+            }
+        }
+        capture.ring.cleanup(&self.dev);
+        match capture.target {
+            CaptureTarget::Recording(recorder) => recorder.finish(),
+            CaptureTarget::Screenshot(_) => (),
+            CaptureTarget::ImageSequence {
+                directory,
+                next_frame,
+            } => info!(
+                "image sequence saved, \x1B[1mdirectory\x1B[0m: {}, \x1B[1mframes\x1B[0m: {next_frame}",
+                directory.display()
+            ),
+        }
+    }
+
+    pub fn is_recording(&self) -> bool {
+        matches!(
+            self.capture,
+            Some(FrameCapture {
+                target: CaptureTarget::Recording(_),
+                ..
+            })
+        )
+    }
+
+    // Recordings already copy every frame, so rather than juggling two captures at once,
+    // screenshots are simply refused while recording.
+    #[cfg_attr(not(feature = "dev-menu"), allow(dead_code))]
+    pub fn take_screenshot(&mut self, path: &Path) -> Result<(), String> {
+        if self.capture.is_some() {
+            return Err("another capture is in progress".to_owned());
+        }
+        self.capture = Some(FrameCapture {
+            ring: ReadbackRing::new(self.swapchain.extent, self.frames_in_flight, &self.dev),
+            target: CaptureTarget::Screenshot(path.to_owned()),
+            bgra: self.swapchain_is_bgra(),
+        });
+        Ok(())
+    }
+
+    // Called right after waiting for the previous frame of the current flight index, so the copy
+    // recorded frames_in_flight frames ago is complete.
+    pub(super) fn read_back_frame(&mut self) {
+        let Some(capture) = &mut self.capture else {
+            return;
+        };
+        let Some(pixels) = capture.ring.take(self.flight_index) else {
+            return;
+        };
+        if let CaptureTarget::Screenshot(path) = &capture.target {
+            save_screenshot(path, pixels, capture.ring.extent, capture.bgra);
+            // The frames after it copied into the ring as well and might still be in flight.
+            self.stop_recording();
+        } else {
+            write_frame(
+                &mut capture.target,
+                pixels,
+                capture.ring.extent,
+                capture.bgra,
+            );
+        }
+    }
+
+    // The ring is sized for a single extent, and video encoders don't support changing the
+    // resolution midway anyway.
+    pub(super) fn stop_recording_on_resize(&mut self) {
+        let Some(capture) = &self.capture else {
+            return;
+        };
+        if capture.ring.extent != self.swapchain.extent {
+            warn!("window resized, stopping recording");
+            self.stop_recording();
+        }
+    }
+
+    fn swapchain_is_bgra(&self) -> bool {
+        self.swapchain.format.format == vk::Format::B8G8R8A8_SRGB
+    }
+}
+
+// Screenshots are handled separately, as saving one also ends the capture.
+fn write_frame(target: &mut CaptureTarget, pixels: &[u8], extent: vk::Extent2D, bgra: bool) {
+    match target {
+        CaptureTarget::Recording(recorder) => recorder.write_frame(pixels),
+        CaptureTarget::Screenshot(_) => unreachable!(),
+        CaptureTarget::ImageSequence {
+            directory,
+            next_frame,
+        } => {
+            let path = directory.join(format!("{next_frame:05}.png"));
+            if let Err(error) = write_png(&path, pixels, extent, bgra) {
+                warn!(
+                    "failed to save frame, \x1B[1mpath\x1B[0m: {}, \x1B[1merror\x1B[0m: {error}",
+                    path.display()
+                );
+            }
+            *next_frame += 1;
+        }
+    }
+}
+
+fn save_screenshot(path: &Path, pixels: &[u8], extent: vk::Extent2D, bgra: bool) {
+    match write_png(path, pixels, extent, bgra) {
+        Ok(()) => info!("screenshot saved, \x1B[1mpath\x1B[0m: {}", path.display()),
+        Err(error) => warn!(
+            "failed to save screenshot, \x1B[1mpath\x1B[0m: {}, \x1B[1merror\x1B[0m: {error}",
+            path.display()
+        ),
+    }
+}
+
+// Swapchain images are already sRGB encoded, so the bytes are written as they are, only with the
+// alpha channel dropped and the channels reordered if needed.
+fn write_png(
+    path: &Path,
+    pixels: &[u8],
+    extent: vk::Extent2D,
+    bgra: bool,
+) -> Result<(), png::EncodingError> {
+    let mut rgb = Vec::with_capacity(pixels.len() / 4 * 3);
+    for pixel in pixels.chunks_exact(4) {
+        if bgra {
+            rgb.extend_from_slice(&[pixel[2], pixel[1], pixel[0]]);
+        } else {
+            rgb.extend_from_slice(&pixel[..3]);
+        }
+    }
+    let file = BufWriter::new(File::create(path)?);
+    let mut encoder = png::Encoder::new(file, extent.width, extent.height);
+    encoder.set_color(png::ColorType::Rgb);
+    encoder.set_depth(png::BitDepth::Eight);
+    encoder.set_source_srgb(png::SrgbRenderingIntent::Perceptual);
+    let mut writer = encoder.write_header()?;
+    writer.write_image_data(&rgb)?;
+    writer.finish()
+}
@@ -0,0 +1,163 @@
+use crate::config::DEFAULT_OBJECT_MAX_COUNT;
+use crate::renderer::codegen::{dispatch_object_culling, dispatch_star_culling};
+use crate::renderer::debug::{begin_label, end_label};
+use crate::renderer::uniform::Culling;
+use crate::renderer::{Renderer, RendererSettings};
+use crate::world::World;
+use ash::vk;
+use nalgebra::{Matrix4, Vector4};
+
+impl Renderer {
+    // Has to be called after waiting for the previous frame of the flight index, as the GPU is then
+    // done with both the draw command from the previous use of the slot and the count written into
+    // it. Reading the count here means the statistics lag behind by a few frames, which is fine.
+    pub(super) fn reset_star_culling(&mut self) {
+        let index_count = 3 * self.mesh_objects[0].triangle_count as u32;
+        let first_instance = (self.flight_index * self.star_count) as u32;
+        let command = &mut self.star_draw_commands.mapped()[self.flight_index];
+        if self.frame_index >= self.frames_in_flight {
+            self.visible_star_count = unsafe { command.assume_init_read() }.instance_count;
+        }
+        command.write(vk::DrawIndexedIndirectCommand {
+            index_count,
+            instance_count: 0,
+            first_index: 0,
+            vertex_offset: 0,
+            first_instance,
+        });
+    }
+
+    // The new count is written when uploading the objects, as that's where culling on the CPU
+    // happens.
+    pub(super) fn reset_object_culling(&mut self) {
+        let count = &mut self.visible_object_counts.mapped()[self.flight_index];
+        if self.frame_index >= self.frames_in_flight {
+            self.visible_object_count = unsafe { count.assume_init_read() };
+        }
+    }
+
+    // Unlike objects, meshlets are only counted on the GPU, so the count also has to be reset here.
+    pub(super) fn reset_voxel_culling(&mut self) {
+        let count = &mut self.visible_meshlet_counts.mapped()[self.flight_index];
+        if self.frame_index >= self.frames_in_flight {
+            self.visible_meshlet_count = unsafe { count.assume_init_read() };
+        }
+        count.write(0);
+    }
+
+    // Stars are numerous enough for culling them one by one to matter, voxels are culled per meshlet
+    // in the task shader instead. The compute shader appends indices of visible stars to this
+    // frame's region of the list and counts them in the draw command, which the star draw then reads
+    // with an indirect draw.
+    pub(super) fn record_star_culling(&self, buf: vk::CommandBuffer) {
+        begin_label(buf, "Star culling", [213, 204, 184], &self.dev);
+        self.bind_compute_pipeline(buf, self.pipelines.star_culling);
+        unsafe {
+            self.dev.cmd_bind_descriptor_sets(
+                buf,
+                vk::PipelineBindPoint::COMPUTE,
+                self.pipeline_layout,
+                0,
+                &[self.descriptor_sets[self.flight_index]],
+                &[],
+            );
+        }
+        // The render graph only tracks images, so the barrier between culling and the draw that
+        // consumes its results is declared in renderer.kdl and recorded by the dispatch.
+        dispatch_star_culling(buf, [self.star_count as u32, 1, 1], &self.dev);
+        end_label(buf, &self.dev);
+    }
+
+    // Objects are drawn one indirect draw at a time, so unlike with stars the culled ones still cost
+    // a draw call on the CPU, but one that the GPU skips without any vertex work.
+    pub(super) fn record_object_culling(&self, buf: vk::CommandBuffer) {
+        begin_label(buf, "Object culling", [205, 133, 63], &self.dev);
+        self.bind_compute_pipeline(buf, self.pipelines.object_culling);
+        unsafe {
+            self.dev.cmd_bind_descriptor_sets(
+                buf,
+                vk::PipelineBindPoint::COMPUTE,
+                self.pipeline_layout,
+                0,
+                &[self.descriptor_sets[self.flight_index]],
+                &[],
+            );
+        }
+        dispatch_object_culling(buf, [self.object_draws.len() as u32, 1, 1], &self.dev);
+        end_label(buf, &self.dev);
+    }
+
+    // The planes go into the global uniform rather than push constants. Both culling dispatches
+    // read the same planes, so they are uploaded once per frame along with everything else, and the
+    // pipeline layout generated from renderer.kdl has no push constant ranges yet anyway.
+    pub(super) fn culling_uniform(&self, world: &World, settings: &RendererSettings) -> Culling {
+        Culling {
+            planes: frustum_planes(self.projection_matrix(settings) * world.view_matrix()),
+            star_count: self.star_count as u32,
+            object_count: self.object_draws.len() as u32,
+            first_object: (DEFAULT_OBJECT_MAX_COUNT * self.flight_index) as u32,
+            flight_index: self.flight_index as u32,
+            enable: settings.gpu_culling,
+            _pad0: [0; 3],
+        }
+    }
+}
+
+// Planes are extracted from the view-projection matrix using the Gribb-Hartmann method. The
+// projection uses OpenGL depth conventions, so the near plane is the sum of the last two rows.
+pub fn frustum_planes(view_projection: Matrix4<f32>) -> [Vector4<f32>; 6] {
+    let row = |i| view_projection.row(i).transpose();
+    [
+        row(3) + row(0),
+        row(3) - row(0),
+        row(3) + row(1),
+        row(3) - row(1),
+        row(3) + row(2),
+        row(3) - row(2),
+    ]
+    .map(|plane| plane / plane.xyz().norm())
+}
+
+// Same test as in the culling shaders, with the sphere center in XYZ and the radius in W. Used when
+// culling objects on the CPU.
+pub fn frustum_cull(planes: &[Vector4<f32>; 6], bounds: Vector4<f32>) -> bool {
+    planes
+        .iter()
+        .any(|plane| plane.xyz().dot(&bounds.xyz()) + plane.w < -bounds.w)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use nalgebra::{Point3, Vector3};
+
+    fn planes() -> [Vector4<f32>; 6] {
+        let projection = Matrix4::new_perspective(1., std::f32::consts::FRAC_PI_2, 0.1, 100.);
+        let view = Matrix4::look_at_rh(&Point3::origin(), &Point3::new(0., 0., -1.), &Vector3::y());
+        frustum_planes(projection * view)
+    }
+
+    #[test]
+    fn sphere_in_front_is_visible() {
+        assert!(!frustum_cull(&planes(), Vector4::new(0., 0., -10., 1.)));
+    }
+
+    #[test]
+    fn sphere_behind_is_culled() {
+        assert!(frustum_cull(&planes(), Vector4::new(0., 0., 10., 1.)));
+    }
+
+    #[test]
+    fn sphere_beyond_far_plane_is_culled() {
+        assert!(frustum_cull(&planes(), Vector4::new(0., 0., -110., 1.)));
+    }
+
+    #[test]
+    fn sphere_crossing_side_plane_is_visible() {
+        // With a 90 degree field of view the right plane passes through (10, 0, -10), so a sphere
+        // centered just outside of it is still partly inside.
+        let planes = planes();
+        assert!(!frustum_cull(&planes, Vector4::new(10.5, 0., -10., 1.)));
+        assert!(frustum_cull(&planes, Vector4::new(12., 0., -10., 1.)));
+    }
+}
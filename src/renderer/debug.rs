@@ -4,13 +4,18 @@ use ash::vk;
 use ash::vk::Handle;
 use std::ffi::{CStr, CString};
 
-pub fn create_debug_messenger(debug_ext: &debug_utils::Instance) -> vk::DebugUtilsMessengerEXT {
+pub fn create_debug_messenger(
+    verbose: bool,
+    debug_ext: &debug_utils::Instance,
+) -> vk::DebugUtilsMessengerEXT {
     // vulkan-tutorial.com also shows how to enable this for creating instances, but the ash
-    // example doesn't include this.
-    let severity_filter = vk::DebugUtilsMessageSeverityFlagsEXT::ERROR
+    // example doesn't include this. Info is always enabled, as debug printf messages use it.
+    let mut severity_filter = vk::DebugUtilsMessageSeverityFlagsEXT::ERROR
         | vk::DebugUtilsMessageSeverityFlagsEXT::WARNING
-        | vk::DebugUtilsMessageSeverityFlagsEXT::INFO
-        | vk::DebugUtilsMessageSeverityFlagsEXT::VERBOSE;
+        | vk::DebugUtilsMessageSeverityFlagsEXT::INFO;
+    if verbose {
+        severity_filter |= vk::DebugUtilsMessageSeverityFlagsEXT::VERBOSE;
+    }
     let type_filter = vk::DebugUtilsMessageTypeFlagsEXT::GENERAL
         | vk::DebugUtilsMessageTypeFlagsEXT::VALIDATION
         | vk::DebugUtilsMessageTypeFlagsEXT::PERFORMANCE;
@@ -37,9 +42,12 @@ unsafe extern "system" fn callback(
     // This can be NULL only in when using DEVICE_ADDRESS_BINDING message type.
     let message = CStr::from_ptr(callback_data.p_message).to_string_lossy();
 
-    if !callback_data.p_message_id_name.is_null() {
-        let message_id = CStr::from_ptr(callback_data.p_message_id_name).to_string_lossy();
-
+    let message_id = if callback_data.p_message_id_name.is_null() {
+        None
+    } else {
+        Some(CStr::from_ptr(callback_data.p_message_id_name).to_string_lossy())
+    };
+    if let Some(message_id) = &message_id {
         // mesa prints some pointless device/loader selection logs when info level is enabled. Info
         // also enables debug printf, so let's filter this out here instead.
         if message_id == "Loader Message" {
@@ -60,9 +68,21 @@ unsafe extern "system" fn callback(
     } else if message_severity.contains(vk::DebugUtilsMessageSeverityFlagsEXT::INFO) {
         log::Level::Info
     } else {
-        log::Level::Trace
+        log::Level::Debug
     };
-    log::log!(level, "{message}");
+    // The ID number is the same across validation layer versions, unlike the message text, so it's
+    // the easiest thing to search the log for.
+    let message_id_number = callback_data.message_id_number as u32;
+    match message_id {
+        Some(message_id) => log::log!(
+            level,
+            "{message}, \x1B[1mid\x1B[0m: {message_id} ({message_id_number:#010x})"
+        ),
+        None => log::log!(
+            level,
+            "{message}, \x1B[1mid\x1B[0m: {message_id_number:#010x}"
+        ),
+    }
     vk::FALSE
 }
 
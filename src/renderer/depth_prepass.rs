@@ -0,0 +1,61 @@
+use crate::renderer::debug::{begin_label, end_label};
+use crate::renderer::{Renderer, RendererSettings, VoxelRendering};
+use ash::vk;
+use std::sync::atomic::Ordering;
+
+impl Renderer {
+    // Only voxels drawn with mesh shaders and opaque objects are drawn, as that's where the overdraw
+    // is. Ray traced voxels are a single fullscreen draw which already shades every pixel once, and
+    // the sky and celestial bodies are behind everything anyway.
+    pub(super) fn record_depth_prepass(&self, buf: vk::CommandBuffer, settings: &RendererSettings) {
+        self.passes
+            .depth_prepass
+            .begin_depth_only(buf, &self.depth, self.render_extent, &self.dev);
+        unsafe {
+            self.dev.cmd_bind_descriptor_sets(
+                buf,
+                vk::PipelineBindPoint::GRAPHICS,
+                self.pipeline_layout,
+                0,
+                &[self.descriptor_sets[self.flight_index]],
+                &[],
+            );
+        }
+        let meshlet_count = self.voxel_meshlet_count.load(Ordering::SeqCst);
+        if settings.voxel_rendering == VoxelRendering::MeshShaders && meshlet_count > 0 {
+            begin_label(buf, "Voxel depth draws", [255, 0, 0], &self.dev);
+            unsafe {
+                self.dev.cmd_bind_pipeline(
+                    buf,
+                    vk::PipelineBindPoint::GRAPHICS,
+                    self.pipelines.voxel_prepass,
+                );
+                self.dev
+                    .mesh_ext
+                    .cmd_draw_mesh_tasks(buf, meshlet_count.div_ceil(64), 1, 1);
+            }
+            end_label(buf, &self.dev);
+        }
+        let object_draws = &self.object_draws[..self.opaque_object_count];
+        if !object_draws.is_empty() {
+            begin_label(buf, "Object depth draws", [205, 133, 63], &self.dev);
+            unsafe {
+                self.dev.cmd_bind_pipeline(
+                    buf,
+                    vk::PipelineBindPoint::GRAPHICS,
+                    self.pipelines.object_prepass,
+                );
+            }
+            for draw in object_draws {
+                draw.record(
+                    &self.mesh_objects,
+                    self.object_draw_calls.handle(),
+                    buf,
+                    &self.dev,
+                );
+            }
+            end_label(buf, &self.dev);
+        }
+        self.passes.depth_prepass.end(buf, &self.dev);
+    }
+}
@@ -1,3 +1,4 @@
+use crate::renderer::codegen::{COMPUTE_LOCAL_SIZES, PUSH_CONSTANT_SIZE};
 use crate::renderer::util::vulkan_str;
 use ash::khr::surface;
 use ash::{vk, Instance};
@@ -7,10 +8,12 @@ use log::{debug, warn};
 pub struct DeviceInfo {
     pub physical_device: vk::PhysicalDevice,
     pub queue_family: u32,
+    // Family with transfer support but no graphics, used for uploads if present.
+    pub transfer_queue_family: Option<u32>,
 }
 
 pub fn select_device(
-    surface: vk::SurfaceKHR,
+    surface: Option<vk::SurfaceKHR>,
     instance: &Instance,
     surface_ext: &surface::Instance,
 ) -> DeviceInfo {
@@ -33,12 +36,50 @@ pub fn select_device(
             continue;
         };
 
+        // The bindless texture array needs descriptor indexing. It's optional even in Vulkan 1.2, and
+        // creating the device with the features enabled would fail on GPUs without it.
+        if !supports_descriptor_indexing(instance, device) {
+            warn!("physical device rejected, no descriptor indexing, \x1B[1mname\x1B[0m: {name}");
+            continue;
+        }
+
+        // The pipeline layout is shared by every pipeline, so a device without enough push constant
+        // space can't create any of them. Every device supports at least 128 bytes.
+        if let Some(size) =
+            PUSH_CONSTANT_SIZE.filter(|size| *size > properties.limits.max_push_constants_size)
+        {
+            warn!(
+                "physical device rejected, push constants too large, \x1B[1mname\x1B[0m: {name}, \x1B[1msize\x1B[0m: {size}, \x1B[1mmax\x1B[0m: {}",
+                properties.limits.max_push_constants_size
+            );
+            continue;
+        }
+
+        // Same with workgroup sizes, though the guaranteed minimums of 128 invocations and 128×128×64
+        // are far above anything used now.
+        let limits = &properties.limits;
+        if let Some((compute, local_size)) = COMPUTE_LOCAL_SIZES.iter().find(|(_, local_size)| {
+            local_size.iter().product::<u32>() > limits.max_compute_work_group_invocations
+                || (0..3).any(|axis| local_size[axis] > limits.max_compute_work_group_size[axis])
+        }) {
+            warn!(
+                "physical device rejected, compute workgroup too large, \x1B[1mname\x1B[0m: {name}, \x1B[1mcompute\x1B[0m: {compute}, \x1B[1mlocal size\x1B[0m: {local_size:?}, \x1B[1mmax\x1B[0m: {:?}, \x1B[1mmax invocations\x1B[0m: {}",
+                limits.max_compute_work_group_size,
+                limits.max_compute_work_group_invocations
+            );
+            continue;
+        }
+
         // Let's just select the first GPU for now. Linux seems to sort them by itself, I should
         // think more about selection later.
-        debug!("physical device selected, \x1B[1mname\x1B[0m: {name}");
+        let transfer_queue_family = find_transfer_queue(&queue_families);
+        debug!(
+            "physical device selected, \x1B[1mname\x1B[0m: {name}, \x1B[1mtransfer queue\x1B[0m: {transfer_queue_family:?}"
+        );
         return DeviceInfo {
             physical_device: device,
             queue_family,
+            transfer_queue_family,
         };
     }
 
@@ -49,16 +90,17 @@ fn find_graphics_queue(
     queues: &[vk::QueueFamilyProperties],
     surface_extension: &surface::Instance,
     device: vk::PhysicalDevice,
-    surface: vk::SurfaceKHR,
+    surface: Option<vk::SurfaceKHR>,
 ) -> Option<u32> {
-    // Some devices have separate graphics and present queues, but let's ignore them for now.
+    // Some devices have separate graphics and present queues, but let's ignore them for now. Without
+    // a surface, nothing is presented at all.
     for (index, family) in queues.iter().enumerate() {
         let index = index as u32;
         let supports_graphics = family.queue_flags.contains(vk::QueueFlags::GRAPHICS);
-        let supports_present = unsafe {
-            surface_extension.get_physical_device_surface_support(device, index, surface)
-        }
-        .unwrap();
+        let supports_present = surface.is_none_or(|surface| {
+            unsafe { surface_extension.get_physical_device_surface_support(device, index, surface) }
+                .unwrap()
+        });
         if supports_graphics && supports_present {
             return Some(index);
         }
@@ -66,8 +108,33 @@ fn find_graphics_queue(
     None
 }
 
-#[allow(dead_code)]
-fn has_extension(extensions: &[vk::ExtensionProperties], name: &str) -> bool {
+fn supports_descriptor_indexing(instance: &Instance, device: vk::PhysicalDevice) -> bool {
+    let mut indexing = vk::PhysicalDeviceDescriptorIndexingFeatures::default();
+    let mut features = vk::PhysicalDeviceFeatures2::default().push_next(&mut indexing);
+    unsafe { instance.get_physical_device_features2(device, &mut features) };
+    indexing.runtime_descriptor_array == vk::TRUE
+        && indexing.shader_sampled_image_array_non_uniform_indexing == vk::TRUE
+        && indexing.descriptor_binding_partially_bound == vk::TRUE
+        && indexing.descriptor_binding_update_unused_while_pending == vk::TRUE
+}
+
+// Discrete GPUs usually have a family with only transfer support, backed by separate DMA engines.
+// Failing that, a compute family without graphics still runs asynchronously to the graphics queue.
+// Graphics families always support transfers too, but using them would gain nothing.
+fn find_transfer_queue(queues: &[vk::QueueFamilyProperties]) -> Option<u32> {
+    let candidates = || {
+        queues.iter().enumerate().filter(|(_, family)| {
+            family.queue_flags.contains(vk::QueueFlags::TRANSFER)
+                && !family.queue_flags.contains(vk::QueueFlags::GRAPHICS)
+        })
+    };
+    let dedicated = candidates()
+        .find(|(_, family)| !family.queue_flags.contains(vk::QueueFlags::COMPUTE))
+        .or_else(|| candidates().next());
+    dedicated.map(|(index, _)| index as u32)
+}
+
+pub fn has_extension(extensions: &[vk::ExtensionProperties], name: &str) -> bool {
     for ext in extensions {
         if vulkan_str(&ext.extension_name) == name {
             return true;
@@ -75,3 +142,27 @@ fn has_extension(extensions: &[vk::ExtensionProperties], name: &str) -> bool {
     }
     false
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn family(queue_flags: vk::QueueFlags) -> vk::QueueFamilyProperties {
+        vk::QueueFamilyProperties {
+            queue_flags,
+            queue_count: 1,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn transfer_queue_preference() {
+        let graphics =
+            family(vk::QueueFlags::GRAPHICS | vk::QueueFlags::COMPUTE | vk::QueueFlags::TRANSFER);
+        let compute = family(vk::QueueFlags::COMPUTE | vk::QueueFlags::TRANSFER);
+        let transfer = family(vk::QueueFlags::TRANSFER);
+        assert_eq!(find_transfer_queue(&[graphics, compute, transfer]), Some(2));
+        assert_eq!(find_transfer_queue(&[graphics, compute]), Some(1));
+        assert_eq!(find_transfer_queue(&[graphics]), None);
+    }
+}
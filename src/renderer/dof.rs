@@ -0,0 +1,30 @@
+use crate::renderer::{Renderer, RendererSettings};
+use ash::vk;
+
+impl Renderer {
+    pub(super) fn record_dof(&self, buf: vk::CommandBuffer, settings: &RendererSettings) {
+        self.record_fullscreen(
+            buf,
+            &self.passes.dof,
+            self.pipelines.dof,
+            &self.dof_image,
+            self.render_extent,
+            vk::AttachmentLoadOp::CLEAR,
+            settings.dof.enabled,
+        );
+    }
+
+    // The color attachment is loaded rather than cleared, so that when the effect is disabled the
+    // scene is left as it was instead of being replaced with the cleared image.
+    pub(super) fn record_dof_composite(&self, buf: vk::CommandBuffer, settings: &RendererSettings) {
+        self.record_fullscreen(
+            buf,
+            &self.passes.dof,
+            self.pipelines.dof_composite,
+            &self.color,
+            self.render_extent,
+            vk::AttachmentLoadOp::LOAD,
+            settings.dof.enabled,
+        );
+    }
+}
@@ -0,0 +1,210 @@
+use crate::config::DEFAULT_DEBUG_LINE_MAX_COUNT;
+use crate::renderer::codegen::Pipelines;
+use crate::renderer::debug::{begin_label, end_label};
+use crate::renderer::objects::ObjectDraw;
+use crate::renderer::util::Dev;
+use crate::renderer::{MeshObject, VoxelRendering, DEPTH_FORMAT};
+use ash::vk;
+
+// Draws in the main render pass are split into groups, each recorded into a separate secondary
+// command buffer on its own thread. The groups are ordered the same way the draws were when
+// recorded serially, as vkCmdExecuteCommands preserves submission order.
+#[derive(Clone, Copy, Debug)]
+pub enum DrawGroup {
+    Voxels,
+    Objects,
+    Celestial,
+    Skybox,
+    DebugLines,
+}
+
+// Everything needed for recording the draws, borrowed out of the renderer so that it can be shared
+// between recording threads. The renderer itself can't be shared, as image layouts are tracked in
+// cells and the interface renderer is only usable from the main thread. Nothing here is mutated
+// during recording, so no locking is necessary.
+pub struct DrawRecorder<'a> {
+    pub dev: &'a Dev,
+    pub pipelines: &'a Pipelines,
+    pub pipeline_layout: vk::PipelineLayout,
+    pub descriptor_set: vk::DescriptorSet,
+    pub color_format: vk::Format,
+    pub mesh_objects: &'a [MeshObject],
+    // Only the opaque objects, as the transparent ones are drawn in a separate pass.
+    pub object_draws: &'a [ObjectDraw],
+    pub voxel_rendering: VoxelRendering,
+    // Voxels drawn with mesh shaders and opaque objects already have their depth drawn, so they
+    // only shade fragments with exactly that depth.
+    pub depth_prepass: bool,
+    pub voxel_meshlet_count: u32,
+    pub object_draw_calls: vk::Buffer,
+    pub star_draw_commands: vk::Buffer,
+    pub debug_line_count: usize,
+    pub flight_index: usize,
+}
+
+impl DrawGroup {
+    pub const ALL: [DrawGroup; 5] = [
+        DrawGroup::Voxels,
+        DrawGroup::Objects,
+        DrawGroup::Celestial,
+        DrawGroup::Skybox,
+        DrawGroup::DebugLines,
+    ];
+}
+
+impl DrawRecorder<'_> {
+    pub fn record(&self, group: DrawGroup, buf: vk::CommandBuffer) {
+        let color_formats = [self.color_format];
+        let mut rendering_info = vk::CommandBufferInheritanceRenderingInfo::default()
+            .color_attachment_formats(&color_formats)
+            .depth_attachment_format(DEPTH_FORMAT)
+            .rasterization_samples(vk::SampleCountFlags::TYPE_1);
+        let inheritance_info =
+            vk::CommandBufferInheritanceInfo::default().push_next(&mut rendering_info);
+        let begin_info = vk::CommandBufferBeginInfo::default()
+            .flags(
+                vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT
+                    | vk::CommandBufferUsageFlags::RENDER_PASS_CONTINUE,
+            )
+            .inheritance_info(&inheritance_info);
+        unsafe { self.dev.begin_command_buffer(buf, &begin_info) }.unwrap();
+
+        // Secondary command buffers don't inherit any bound state from the primary one.
+        self.bind_descriptor_set(buf);
+        match group {
+            DrawGroup::Voxels => self.record_voxels(buf),
+            DrawGroup::Objects => self.record_objects(buf),
+            DrawGroup::Celestial => self.record_celestial(buf),
+            DrawGroup::Skybox => self.record_skybox(buf),
+            DrawGroup::DebugLines => self.record_debug_lines(buf),
+        }
+
+        unsafe { self.dev.end_command_buffer(buf) }.unwrap();
+    }
+
+    fn record_voxels(&self, buf: vk::CommandBuffer) {
+        match self.voxel_rendering {
+            VoxelRendering::Classic => todo!(),
+            VoxelRendering::MeshShaders => {
+                begin_label(buf, "Voxel draws (mesh shaders)", [255, 0, 0], self.dev);
+                let pipeline = if self.depth_prepass {
+                    self.pipelines.voxel_depth_equal
+                } else {
+                    self.pipelines.voxel
+                };
+                self.bind_graphics_pipeline(buf, pipeline);
+                self.draw_mesh_shaders(buf, self.voxel_meshlet_count.div_ceil(64));
+                end_label(buf, self.dev);
+
+                if self.voxel_meshlet_count > 0 {
+                    begin_label(buf, "Debug voxel triangle draw", [238, 186, 11], self.dev);
+                    self.bind_graphics_pipeline(buf, self.pipelines.debug_voxel_triangle);
+                    self.draw_mesh_shaders(buf, 1);
+                    end_label(buf, self.dev);
+
+                    begin_label(buf, "Debug voxel world bound draw", [255, 78, 0], self.dev);
+                    self.bind_graphics_pipeline(buf, self.pipelines.debug_voxel_world_bound);
+                    self.draw_mesh_shaders(buf, 1);
+                    end_label(buf, self.dev);
+
+                    begin_label(buf, "Debug voxel screen bound draw", [113, 0, 0], self.dev);
+                    self.bind_graphics_pipeline(buf, self.pipelines.debug_voxel_screen_bound);
+                    self.draw_mesh_shaders(buf, 1);
+                    end_label(buf, self.dev);
+                }
+            }
+            VoxelRendering::RayTracing => {
+                begin_label(buf, "Voxel draws (ray tracing)", [255, 0, 0], self.dev);
+                self.bind_graphics_pipeline(buf, self.pipelines.voxel_rt);
+                unsafe { self.dev.cmd_draw(buf, 6, 1, 0, 0) };
+                end_label(buf, self.dev);
+            }
+        }
+    }
+
+    fn record_objects(&self, buf: vk::CommandBuffer) {
+        if self.object_draws.is_empty() {
+            return;
+        }
+        begin_label(buf, "Object draws", [205, 133, 63], self.dev);
+        let pipeline = if self.depth_prepass {
+            self.pipelines.object_depth_equal
+        } else {
+            self.pipelines.object
+        };
+        self.bind_graphics_pipeline(buf, pipeline);
+        for draw in self.object_draws {
+            draw.record(self.mesh_objects, self.object_draw_calls, buf, self.dev);
+        }
+        end_label(buf, self.dev);
+    }
+
+    fn record_celestial(&self, buf: vk::CommandBuffer) {
+        begin_label(buf, "Sun draw", [156, 85, 35], self.dev);
+        self.bind_graphics_pipeline(buf, self.pipelines.sun);
+        self.mesh_objects[1].bind_vertex(buf, self.dev);
+        self.mesh_objects[1].draw(1, buf, self.dev);
+        end_label(buf, self.dev);
+
+        begin_label(buf, "Star draws", [213, 204, 184], self.dev);
+        self.bind_graphics_pipeline(buf, self.pipelines.star);
+        self.mesh_objects[0].bind_vertex(buf, self.dev);
+        self.mesh_objects[0].draw_indirect(
+            self.star_draw_commands,
+            self.flight_index,
+            buf,
+            self.dev,
+        );
+        end_label(buf, self.dev);
+    }
+
+    fn record_skybox(&self, buf: vk::CommandBuffer) {
+        begin_label(buf, "Skybox draw", [129, 147, 164], self.dev);
+        self.bind_graphics_pipeline(buf, self.pipelines.skybox);
+        unsafe { self.dev.cmd_draw(buf, 6, 1, 0, 0) };
+        end_label(buf, self.dev);
+    }
+
+    fn record_debug_lines(&self, buf: vk::CommandBuffer) {
+        if self.debug_line_count == 0 {
+            return;
+        }
+        begin_label(buf, "Debug line draws", [0, 255, 0], self.dev);
+        self.bind_graphics_pipeline(buf, self.pipelines.debug_line);
+        let first_vertex = 2 * DEFAULT_DEBUG_LINE_MAX_COUNT * self.flight_index;
+        unsafe {
+            self.dev.cmd_draw(
+                buf,
+                2 * self.debug_line_count as u32,
+                1,
+                first_vertex as u32,
+                0,
+            )
+        };
+        end_label(buf, self.dev);
+    }
+
+    fn bind_graphics_pipeline(&self, buf: vk::CommandBuffer, pipeline: vk::Pipeline) {
+        unsafe {
+            self.dev
+                .cmd_bind_pipeline(buf, vk::PipelineBindPoint::GRAPHICS, pipeline)
+        };
+    }
+
+    fn bind_descriptor_set(&self, buf: vk::CommandBuffer) {
+        unsafe {
+            self.dev.cmd_bind_descriptor_sets(
+                buf,
+                vk::PipelineBindPoint::GRAPHICS,
+                self.pipeline_layout,
+                0,
+                &[self.descriptor_set],
+                &[],
+            )
+        };
+    }
+
+    fn draw_mesh_shaders(&self, buf: vk::CommandBuffer, count: u32) {
+        unsafe { self.dev.mesh_ext.cmd_draw_mesh_tasks(buf, count, 1, 1) };
+    }
+}
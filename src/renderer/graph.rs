@@ -0,0 +1,367 @@
+use crate::renderer::barrier::{usage_access, write_access};
+use crate::renderer::util::{Dev, ImageResources};
+use ash::vk;
+
+// Describes the passes of a single frame together with the images they use, so that barriers
+// between them can be derived automatically instead of being written by hand. The graph is rebuilt
+// every frame, which is cheap as there are only a few passes and it avoids having to store
+// closures borrowing the renderer.
+//
+// Passes execute in the order they were added. Dependencies can only ever point at passes added
+// earlier, as that's how reads are matched with the writes they see, so sorting them wouldn't ever
+// change the order anyway. Keeping the order also keeps the timing overlay stable.
+pub struct RenderGraph<'a> {
+    resources: Vec<ResourceNode<'a>>,
+    passes: Vec<PassNode<'a>>,
+}
+
+pub struct ResourceNode<'a> {
+    pub image: &'a ImageResources,
+    // Stages that could still be accessing the image from before this graph, usually from the
+    // previous frame. These have to finish before the first pass using the image can start.
+    pub previous_stage: vk::PipelineStageFlags2,
+    // Whether the contents from before this graph are needed. Discarding them lets the driver skip
+    // preserving them during the first layout transition.
+    pub discard: bool,
+    // Layout and stage the image has to be transitioned to after all passes, like the present
+    // layout for swapchain images.
+    pub layout_at_end: Option<(vk::ImageLayout, vk::PipelineStageFlags2)>,
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct ResourceHandle(usize);
+
+#[derive(Clone, Copy, Debug)]
+pub struct ResourceUsage {
+    pub resource: ResourceHandle,
+    pub layout: vk::ImageLayout,
+    pub stage: vk::PipelineStageFlags2,
+}
+
+pub struct PassNode<'a> {
+    name: &'static str,
+    reads: Vec<ResourceUsage>,
+    writes: Vec<ResourceUsage>,
+    execute_fn: Box<dyn FnOnce(vk::CommandBuffer) + 'a>,
+}
+
+struct CompiledPass<'a> {
+    name: &'static str,
+    barriers: Vec<Barrier>,
+    reads: Vec<ResourceUsage>,
+    execute_fn: Box<dyn FnOnce(vk::CommandBuffer) + 'a>,
+}
+
+pub struct CompiledGraph<'a> {
+    resources: Vec<ResourceNode<'a>>,
+    passes: Vec<CompiledPass<'a>>,
+    final_barriers: Vec<Barrier>,
+}
+
+#[derive(Clone, Copy, Debug)]
+struct Barrier {
+    resource: ResourceHandle,
+    discard: bool,
+    layout: vk::ImageLayout,
+    src_stage: vk::PipelineStageFlags2,
+    src_access: vk::AccessFlags2,
+    dst_stage: vk::PipelineStageFlags2,
+    dst_access: vk::AccessFlags2,
+}
+
+// State of a resource during compilation, as of the passes visited so far.
+#[derive(Clone, Copy)]
+struct ResourceState {
+    used: bool,
+    layout: vk::ImageLayout,
+    stage: vk::PipelineStageFlags2,
+    written: bool,
+    // Writes of the last writing pass, which the next barrier has to make available. Empty after
+    // a barrier already did that.
+    access: vk::AccessFlags2,
+}
+
+impl ResourceHandle {
+    pub fn usage(self, layout: vk::ImageLayout, stage: vk::PipelineStageFlags2) -> ResourceUsage {
+        ResourceUsage {
+            resource: self,
+            layout,
+            stage,
+        }
+    }
+}
+
+impl<'a> RenderGraph<'a> {
+    pub fn new() -> RenderGraph<'a> {
+        RenderGraph {
+            resources: Vec::new(),
+            passes: Vec::new(),
+        }
+    }
+
+    pub fn add_resource(&mut self, resource: ResourceNode<'a>) -> ResourceHandle {
+        self.resources.push(resource);
+        ResourceHandle(self.resources.len() - 1)
+    }
+
+    // Passes only record their own commands, all barriers for the resources listed here are
+    // inserted by the graph. A resource that is both read and written should only be listed in
+    // writes. Passes run in the order they are added, and each one sees the results of the passes
+    // using the same resource that were added before it.
+    pub fn add_pass(
+        &mut self,
+        name: &'static str,
+        reads: &[ResourceUsage],
+        writes: &[ResourceUsage],
+        execute_fn: impl FnOnce(vk::CommandBuffer) + 'a,
+    ) {
+        self.passes.push(PassNode {
+            name,
+            reads: reads.to_vec(),
+            writes: writes.to_vec(),
+            execute_fn: Box::new(execute_fn),
+        });
+    }
+
+    pub fn compile(self) -> CompiledGraph<'a> {
+        // Nothing is known about how the images were used before the graph, so the first access
+        // always needs a barrier, the same as if it was written in its current layout.
+        let mut states: Vec<_> = self
+            .resources
+            .iter()
+            .map(|resource| {
+                let layout = resource.image.current_layout.get();
+                ResourceState {
+                    used: false,
+                    layout,
+                    stage: resource.previous_stage,
+                    written: true,
+                    access: write_access(usage_access(layout, true)),
+                }
+            })
+            .collect();
+
+        let mut compiled = Vec::new();
+        for pass in self.passes {
+            let mut barriers = Vec::new();
+            let usages = pass.reads.iter().map(|usage| (usage, false));
+            let usages = usages.chain(pass.writes.iter().map(|usage| (usage, true)));
+            for (usage, write) in usages {
+                let resource = &self.resources[usage.resource.0];
+                let state = &mut states[usage.resource.0];
+                let discard = !state.used && resource.discard;
+                // Reads following other reads in the same layout don't need any synchronization,
+                // every other combination is a hazard of some kind.
+                let access = usage_access(usage.layout, write);
+                if discard || usage.layout != state.layout || write || state.written {
+                    barriers.push(Barrier {
+                        resource: usage.resource,
+                        discard,
+                        layout: usage.layout,
+                        src_stage: state.stage,
+                        src_access: state.access,
+                        dst_stage: usage.stage,
+                        dst_access: access,
+                    });
+                    state.stage = usage.stage;
+                } else {
+                    state.stage |= usage.stage;
+                }
+                state.used = true;
+                state.layout = usage.layout;
+                state.written = write;
+                state.access = write_access(access);
+            }
+            compiled.push(CompiledPass {
+                name: pass.name,
+                barriers,
+                reads: pass.reads,
+                execute_fn: pass.execute_fn,
+            });
+        }
+
+        let mut final_barriers = Vec::new();
+        for (index, resource) in self.resources.iter().enumerate() {
+            if let Some((layout, stage)) = resource.layout_at_end {
+                final_barriers.push(Barrier {
+                    resource: ResourceHandle(index),
+                    discard: false,
+                    layout,
+                    src_stage: states[index].stage,
+                    src_access: states[index].access,
+                    dst_stage: stage,
+                    dst_access: usage_access(layout, false),
+                });
+            }
+        }
+
+        CompiledGraph {
+            resources: self.resources,
+            passes: compiled,
+            final_barriers,
+        }
+    }
+}
+
+impl CompiledGraph<'_> {
+    // Timestamps are written around every pass, so that the time each one takes can be shown in
+    // the interface. The barriers before a pass are counted as part of it, as that's usually where
+    // it waits for the passes before. The pass at position i in the returned execution order uses
+    // the timestamps 2i and 2i+1.
+    pub fn execute(
+        mut self,
+        buf: vk::CommandBuffer,
+        dev: &Dev,
+        mut write_timestamp: impl FnMut(vk::CommandBuffer, usize),
+    ) -> Vec<&'static str> {
+        let passes = std::mem::take(&mut self.passes);
+        let mut order = Vec::with_capacity(passes.len());
+        for (index, pass) in passes.into_iter().enumerate() {
+            write_timestamp(buf, 2 * index);
+            for barrier in &pass.barriers {
+                self.record_barrier(buf, barrier, dev);
+            }
+            self.debug_assert_reads(&pass);
+            (pass.execute_fn)(buf);
+            write_timestamp(buf, 2 * index + 1);
+            order.push(pass.name);
+        }
+        for barrier in &self.final_barriers {
+            self.record_barrier(buf, barrier, dev);
+        }
+        order
+    }
+
+    // Passes can still record transitions of their own, and reads after reads in the same layout
+    // get no barrier, so an image left in another layout by an earlier pass would otherwise reach a
+    // sampling pass unnoticed.
+    fn debug_assert_reads(&self, pass: &CompiledPass) {
+        for usage in &pass.reads {
+            self.resources[usage.resource.0]
+                .image
+                .debug_assert_layout(usage.layout);
+        }
+    }
+
+    fn record_barrier(&self, buf: vk::CommandBuffer, barrier: &Barrier, dev: &Dev) {
+        let image = self.resources[barrier.resource.0].image;
+        if barrier.discard {
+            image.discard();
+        }
+        image.transition_with_access(
+            buf,
+            barrier.layout,
+            barrier.src_stage,
+            barrier.src_access,
+            barrier.dst_stage,
+            barrier.dst_access,
+            dev,
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::Cell;
+
+    fn image() -> ImageResources {
+        ImageResources {
+            image: vk::Image::null(),
+            allocation: None,
+            view: vk::ImageView::null(),
+            current_layout: Cell::new(vk::ImageLayout::UNDEFINED),
+        }
+    }
+
+    fn resource(image: &ImageResources) -> ResourceNode<'_> {
+        ResourceNode {
+            image,
+            previous_stage: vk::PipelineStageFlags2::NONE,
+            discard: true,
+            layout_at_end: None,
+        }
+    }
+
+    fn attachment(handle: ResourceHandle) -> ResourceUsage {
+        handle.usage(
+            vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+            vk::PipelineStageFlags2::COLOR_ATTACHMENT_OUTPUT,
+        )
+    }
+
+    fn sampled(handle: ResourceHandle) -> ResourceUsage {
+        handle.usage(
+            vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+            vk::PipelineStageFlags2::FRAGMENT_SHADER,
+        )
+    }
+
+    #[test]
+    fn passes_run_in_add_order() {
+        let (a, b) = (image(), image());
+        let mut graph = RenderGraph::new();
+        let a = graph.add_resource(resource(&a));
+        let b = graph.add_resource(resource(&b));
+        graph.add_pass("first", &[], &[attachment(a)], |_| ());
+        graph.add_pass("independent", &[], &[attachment(b)], |_| ());
+        graph.add_pass("second", &[sampled(a)], &[], |_| ());
+        let compiled = graph.compile();
+        let names: Vec<_> = compiled.passes.iter().map(|pass| pass.name).collect();
+        assert_eq!(names, ["first", "independent", "second"]);
+    }
+
+    #[test]
+    fn access_masks_follow_usage() {
+        let color = image();
+        let mut graph = RenderGraph::new();
+        let color = graph.add_resource(ResourceNode {
+            layout_at_end: Some((
+                vk::ImageLayout::PRESENT_SRC_KHR,
+                vk::PipelineStageFlags2::BOTTOM_OF_PIPE,
+            )),
+            ..resource(&color)
+        });
+        graph.add_pass("write", &[], &[attachment(color)], |_| ());
+        graph.add_pass("read", &[sampled(color)], &[], |_| ());
+        graph.add_pass("read again", &[sampled(color)], &[], |_| ());
+        let compiled = graph.compile();
+
+        let first = compiled.passes[0].barriers[0];
+        assert!(first.discard);
+        assert_eq!(first.src_access, vk::AccessFlags2::empty());
+        assert_eq!(
+            first.dst_access,
+            vk::AccessFlags2::COLOR_ATTACHMENT_READ | vk::AccessFlags2::COLOR_ATTACHMENT_WRITE
+        );
+
+        let read = compiled.passes[1].barriers[0];
+        assert_eq!(read.src_access, vk::AccessFlags2::COLOR_ATTACHMENT_WRITE);
+        assert_eq!(read.dst_access, vk::AccessFlags2::SHADER_SAMPLED_READ);
+
+        // Reads after reads in the same layout don't need a barrier.
+        assert!(compiled.passes[2].barriers.is_empty());
+
+        // Nothing was written since the read barrier, so presenting only waits for the reads.
+        let present = compiled.final_barriers[0];
+        assert_eq!(present.src_access, vk::AccessFlags2::empty());
+        assert_eq!(present.src_stage, vk::PipelineStageFlags2::FRAGMENT_SHADER);
+        assert_eq!(present.dst_access, vk::AccessFlags2::empty());
+    }
+
+    #[test]
+    #[should_panic(expected = "image used in unexpected layout")]
+    fn read_in_wrong_layout_is_caught() {
+        let color = image();
+        let mut graph = RenderGraph::new();
+        let handle = graph.add_resource(resource(&color));
+        graph.add_pass("read", &[sampled(handle)], &[], |_| ());
+        graph.add_pass("read again", &[sampled(handle)], &[], |_| ());
+        let compiled = graph.compile();
+        // As if the first pass transitioned the image by hand after the graph's barrier.
+        color
+            .current_layout
+            .set(vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL);
+        compiled.debug_assert_reads(&compiled.passes[1]);
+    }
+}
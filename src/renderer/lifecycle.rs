@@ -1,70 +1,119 @@
 use crate::cli::Args;
 use crate::config::{
-    DEFAULT_VOXEL_MESHLET_MAX_COUNT, DEFAULT_VOXEL_OCTREE_MAX_COUNT,
+    DEFAULT_DEBUG_LINE_MAX_COUNT, DEFAULT_OBJECT_MAX_COUNT, DEFAULT_RENDERER_SETTINGS,
+    DEFAULT_STAGING_RING_SIZE, DEFAULT_VOXEL_MESHLET_MAX_COUNT, DEFAULT_VOXEL_OCTREE_MAX_COUNT,
     DEFAULT_VOXEL_TRIANGLE_MAX_COUNT, DEFAULT_VOXEL_VERTEX_MAX_COUNT,
 };
+use crate::logger::{set_renderer_context, update_renderer_swapchain_extent, RendererContext};
 use crate::mesh::MeshData;
+use crate::particles::ParticleSystem;
 use crate::renderer::codegen::{
-    alloc_descriptor_set, create_descriptor_pool, create_descriptor_set_layout, create_pipelines,
-    create_render_passes, create_samplers, create_shader_modules, create_shaders,
+    alloc_global_descriptor_set, create_descriptor_pool, create_descriptor_set_layouts,
+    create_pipeline_layout, create_pipelines, create_render_passes, create_samplers,
+    create_shader_modules, create_shaders, update_bloom_images_descriptor,
+    update_dof_image_descriptor, update_hiz_descriptor, update_scene_color_descriptor,
+    update_scene_depth_descriptor, update_shadow_map_descriptor, update_ssao_images_descriptor,
+    update_ssr_image_descriptor, update_taa_history_descriptor, GLOBAL_DESCRIPTOR_SET,
 };
 use crate::renderer::debug::create_debug_messenger;
-use crate::renderer::device::{select_device, DeviceInfo};
-use crate::renderer::swapchain::create_swapchain;
+use crate::renderer::device::{has_extension, select_device, DeviceInfo};
+use crate::renderer::draw::DrawGroup;
+use crate::renderer::lut::{create_color_lut, identity_color_lut};
+#[cfg(feature = "dev-menu")]
+use crate::renderer::shader_watch::ShaderWatcher;
+use crate::renderer::sky::SkyLighting;
+use crate::renderer::skybox::{create_skybox, placeholder_skybox};
+use crate::renderer::swapchain::{create_swapchain, Swapchain};
 use crate::renderer::uniform::Star;
-use crate::renderer::util::{vulkan_str, Buffer, Dev, ImageResources, StorageBuffer};
+use crate::renderer::util::{
+    create_timeline_semaphore, vulkan_str, Buffer, Ctx, Dev, GpuAllocator, ImageResources,
+    MemoryTag, StagingPool, StagingRing, StorageBuffer,
+};
 use crate::renderer::vertex::Vertex;
 use crate::renderer::{
-    DeviceSupport, MeshObject, Renderer, Synchronization, UniformBuffer, DEPTH_FORMAT,
-    FRAMES_IN_FLIGHT, VRAM_VIA_BAR,
+    atmosphere_samples, postprocess_effects, ssao_effects, DeviceSupport, MeshObject, RenderOutput,
+    Renderer, Synchronization, UniformBuffer, DEPTH_FORMAT, TIMESTAMPS_PER_FRAME, VRAM_VIA_BAR,
 };
 use crate::voxel::gpu::meshlets::VoxelMeshletMemory;
-use crate::voxel::gpu::{SvoNode, VoxelGpuMemory};
+use crate::voxel::gpu::SvoNode;
+use crate::voxel::material::MATERIAL_COUNT;
 use crate::world::World;
 use crate::{VULKAN_APP_NAME, VULKAN_APP_VERSION, VULKAN_ENGINE_NAME, VULKAN_ENGINE_VERSION};
 use ash::ext::{debug_utils, mesh_shader};
 use ash::khr::{surface, swapchain};
 use ash::{vk, Device, Entry, Instance};
 use log::{debug, warn};
+use nalgebra::{Matrix4, Vector4};
 use raw_window_handle::{HasDisplayHandle, HasWindowHandle};
-use std::ffi::CString;
-use std::sync::atomic::AtomicU32;
-use std::sync::Arc;
+use std::collections::HashMap;
+use std::ffi::{CString, OsString};
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU32, AtomicUsize};
+use std::sync::{Arc, Mutex, PoisonError};
 use winit::dpi::PhysicalSize;
 use winit::window::Window;
 
+// Identifies files written by this module, as opposed to raw cache data or anything else that
+// happened to be at the same path.
+const PIPELINE_CACHE_MAGIC: [u8; 16] = *b"vulkthing-pcache";
+const PIPELINE_CACHE_HEADER_SIZE: usize = PIPELINE_CACHE_MAGIC.len() + vk::UUID_SIZE;
+
+// Drivers are supposed to reject cache data from a different device or driver version on their
+// own, but not all of them do so gracefully. The UUID from the device properties is checked up
+// front instead, and any mismatch or read error just starts with an empty cache.
+pub struct PipelineCacheManager;
+
 impl Renderer {
     pub fn new(
-        window: &Window,
+        output: RenderOutput,
         meshes: &[&MeshData<Vertex>],
         world: &World,
         args: &Args,
     ) -> Renderer {
         let entry = unsafe { Entry::load() }.unwrap();
+        let (window, output_size) = match output {
+            RenderOutput::Window(window) => (Some(window), window.inner_size()),
+            RenderOutput::Offscreen(size) => (None, size),
+        };
         let instance = create_instance(window, &entry, args);
         let debug_ext_instance = debug_utils::Instance::new(&entry, &instance);
-        let debug_messenger = create_debug_messenger(&debug_ext_instance);
+        let debug_messenger = create_debug_messenger(args.vulkan_verbose, &debug_ext_instance);
         let surface_ext = surface::Instance::new(&entry, &instance);
-        let surface = create_surface(window, &entry, &instance);
+        let surface = window.map(|window| create_surface(window, &entry, &instance));
         let DeviceInfo {
             physical_device,
             queue_family,
+            transfer_queue_family,
         } = select_device(surface, &instance, &surface_ext);
         let properties = unsafe { instance.get_physical_device_properties(physical_device) };
         let mut ms_features = vk::PhysicalDeviceMeshShaderFeaturesEXT::default();
         let mut features = vk::PhysicalDeviceFeatures2::default().push_next(&mut ms_features);
         unsafe { instance.get_physical_device_features2(physical_device, &mut features) };
+        let extensions =
+            unsafe { instance.enumerate_device_extension_properties(physical_device) }.unwrap();
         let device_support = DeviceSupport {
             mesh_shaders: (ms_features.mesh_shader != 0) && (ms_features.task_shader != 0),
+            memory_budget: has_extension(&extensions, "VK_EXT_memory_budget"),
         };
         if !device_support.mesh_shaders {
             warn!("mesh shaders not available");
         }
-        let logical_device =
-            create_logical_device(queue_family, &instance, physical_device, &device_support);
+        if !device_support.memory_budget {
+            warn!("memory budget not available");
+        }
+        let logical_device = create_logical_device(
+            queue_family,
+            transfer_queue_family,
+            surface.is_some(),
+            &instance,
+            physical_device,
+            &device_support,
+        );
         let debug_ext = debug_utils::Device::new(&instance, &logical_device);
         let swapchain_ext = swapchain::Device::new(&instance, &logical_device);
         let mesh_ext = mesh_shader::Device::new(&instance, &logical_device);
+        let allocator = GpuAllocator::new(&instance, physical_device, &device_support);
         let dev = Dev {
             logical: logical_device,
             physical: physical_device,
@@ -75,85 +124,315 @@ impl Renderer {
             swapchain_ext,
             mesh_ext,
             support: device_support,
+            allocator,
         };
         let queue = unsafe { dev.get_device_queue(queue_family, 0) };
-        let command_pools = create_command_pools(queue_family, &dev);
+        let (transfer_queue, transfer_queue_family) = match transfer_queue_family {
+            Some(family) => (unsafe { dev.get_device_queue(family, 0) }, family),
+            None => (queue, queue_family),
+        };
+
+        let present_mode = DEFAULT_RENDERER_SETTINGS.present_mode;
+        let swapchain = create_swapchain(
+            surface,
+            output_size,
+            present_mode,
+            args.frames_in_flight,
+            &dev,
+        );
+        let frames_in_flight =
+            select_frames_in_flight(args.frames_in_flight, swapchain.images.len());
+
+        let command_pools = create_command_pools(queue_family, frames_in_flight, &dev);
         let command_buffers = create_command_buffers(&command_pools, &dev);
-        let sync = create_sync(&dev);
+        let secondary_command_pools =
+            create_secondary_command_pools(queue_family, frames_in_flight, &dev);
+        let secondary_command_buffers =
+            create_secondary_command_buffers(&secondary_command_pools, &dev);
+        let recording_threads = rayon::ThreadPoolBuilder::new()
+            .num_threads(DrawGroup::ALL.len())
+            .thread_name(|index| format!("render-{index}"))
+            .build()
+            .unwrap();
+        let sync = create_sync(frames_in_flight, &dev);
 
         let samplers = create_samplers(&dev);
 
-        let descriptor_set_layout = create_descriptor_set_layout(&samplers, &dev);
-        let descriptor_pool = create_descriptor_pool(descriptor_set_layout, &dev);
+        let descriptor_set_layouts = create_descriptor_set_layouts(&samplers, &dev);
+        let descriptor_pool = create_descriptor_pool(frames_in_flight, &dev);
 
-        let swapchain = create_swapchain(surface, window.inner_size(), &dev);
-        let depth = create_depth(swapchain.extent, &dev);
+        set_renderer_context(RendererContext {
+            device_name: vulkan_str(&properties.device_name).to_owned(),
+            swapchain_extent: swapchain.extent,
+            settings: None,
+        });
+        let render_scale = 1.;
+        let (render_extent, color, depth) = create_render_targets(&swapchain, render_scale, &dev);
+        let (bloom_extent, bloom_images) =
+            create_bloom_targets(swapchain.format.format, render_extent, &dev);
+        let (ssr_extent, hiz, ssr_image) = create_ssr_targets(render_extent, &dev);
+        let ssr_max_steps = DEFAULT_RENDERER_SETTINGS.ssr.max_steps;
+        let atmosphere_samples = atmosphere_samples(&DEFAULT_RENDERER_SETTINGS);
+        let ssao_images = create_ssao_targets(render_extent, &dev);
+        let ssao_effects = ssao_effects(&DEFAULT_RENDERER_SETTINGS.ssao);
+        let dof_image = create_color(swapchain.format.format, render_extent, &dev);
+        let bloom_radius = DEFAULT_RENDERER_SETTINGS.bloom.radius;
+        let postprocess_effects = postprocess_effects(&DEFAULT_RENDERER_SETTINGS.postprocess);
+        let shadow_resolution = DEFAULT_RENDERER_SETTINGS.shadows.resolution;
+        let shadow_map = create_shadow_map(shadow_resolution, &dev);
         let passes = create_render_passes(&swapchain, vk::SampleCountFlags::TYPE_1, &dev);
-        let pipeline_layout = create_pipeline_layout(descriptor_set_layout, &dev);
+        let pipeline_layout = create_pipeline_layout(&descriptor_set_layouts, &dev);
         let shaders = create_shaders(&dev.support);
         let shader_modules = create_shader_modules(&shaders, &dev);
+        let pipeline_cache_path = default_pipeline_cache_path();
+        let pipeline_cache = PipelineCacheManager::load(&dev, &pipeline_cache_path);
         let pipelines = create_pipelines(
             vk::SampleCountFlags::TYPE_1,
             &passes,
+            postprocess_effects[0],
+            postprocess_effects[1],
+            postprocess_effects[2],
+            postprocess_effects[3],
+            postprocess_effects[4],
+            ssao_effects.0,
+            ssao_effects.1,
+            atmosphere_samples.0,
+            atmosphere_samples.1,
+            ssr_max_steps,
+            bloom_radius,
+            0,
+            bloom_radius,
+            1,
             &swapchain,
+            render_extent,
             &shader_modules,
             pipeline_layout,
+            pipeline_cache,
             &dev,
         );
         shader_modules.cleanup(&dev);
 
+        // Meshes are uploaded in the background while the rest of the renderer is created. Every
+        // frame waits for the uploads submitted so far, so the first one doesn't need to wait for
+        // them explicitly.
+        let mut staging_ring = StagingRing::new(
+            DEFAULT_STAGING_RING_SIZE,
+            transfer_queue,
+            transfer_queue_family,
+            queue_family,
+            &dev,
+        );
         let mut mesh_objects = Vec::new();
         for mesh in meshes {
-            let vertex = create_vertex_buffer(&mesh.vertices, &dev);
-            let index = create_index_buffer(&mesh.indices, &dev);
+            let vertex = create_vertex_buffer(&mesh.vertices, &mut staging_ring, &dev);
+            let index = create_index_buffer(&mesh.indices, &mut staging_ring, &dev);
             mesh_objects.push(MeshObject {
-                triangle_count: mesh.vertices.len() / 3,
+                triangle_count: mesh.indices.len() / 3,
+                bounding_radius: mesh
+                    .vertices
+                    .iter()
+                    .map(|vertex| vertex.position.norm())
+                    .fold(0., f32::max),
                 vertex,
                 index,
+                base_color: mesh
+                    .primitives
+                    .first()
+                    .map_or(Vector4::from_element(1.), |primitive| primitive.base_color),
+                is_transparent: mesh
+                    .primitives
+                    .iter()
+                    .any(|primitive| primitive.base_color.w < 1.),
             });
         }
+        staging_ring.submit(&dev);
 
-        let mut stars = StorageBuffer::new_array(VRAM_VIA_BAR, world.stars.len(), &dev);
-        stars.generate(|i| Star {
-            model: world.stars[i].transform.model_matrix(),
-        });
+        // Stars never change, so there's no reason to keep them in the limited BAR memory.
+        let mut staging = StagingPool::new(frames_in_flight);
+        let stars = StorageBuffer::new_array(
+            vk::MemoryPropertyFlags::DEVICE_LOCAL,
+            world.stars.len(),
+            MemoryTag::Storage,
+            &dev,
+        );
+        let star_data: Vec<_> = world
+            .stars
+            .iter()
+            .map(|star| Star {
+                model: star.transform.model_matrix(),
+            })
+            .collect();
+        let ctx = Ctx {
+            dev: &dev,
+            queue,
+            command_pool: command_pools[0],
+        };
+        ctx.execute(|buf| staging.upload(buf, &stars, &star_data, 0, &dev));
+        let color_lut = create_color_lut(&identity_color_lut(), &mut staging, &ctx);
+        let skybox = create_skybox(&placeholder_skybox(), &mut staging, &ctx);
+        let sky = SkyLighting::create(&dev);
+
+        let debug_vertices = StorageBuffer::new_array(
+            VRAM_VIA_BAR,
+            2 * DEFAULT_DEBUG_LINE_MAX_COUNT * frames_in_flight,
+            MemoryTag::Storage,
+            &dev,
+        );
+
+        let objects = StorageBuffer::new_array(
+            VRAM_VIA_BAR,
+            DEFAULT_OBJECT_MAX_COUNT * frames_in_flight,
+            MemoryTag::Storage,
+            &dev,
+        );
+
+        let object_draw_calls =
+            StorageBuffer::new_indirect_array(DEFAULT_OBJECT_MAX_COUNT * frames_in_flight, &dev);
+        let visible_object_counts = StorageBuffer::new_array(
+            vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT,
+            frames_in_flight,
+            MemoryTag::Storage,
+            &dev,
+        );
+
+        let visible_meshlet_counts = StorageBuffer::new_array(
+            vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT,
+            frames_in_flight,
+            MemoryTag::Storage,
+            &dev,
+        );
+
+        let star_draw_commands = StorageBuffer::new_indirect_array(frames_in_flight, &dev);
+        let visible_stars = StorageBuffer::new_array(
+            VRAM_VIA_BAR,
+            world.stars.len() * frames_in_flight,
+            MemoryTag::Storage,
+            &dev,
+        );
 
-        let query_pool = create_query_pool(&dev);
+        let particles = ParticleSystem::new(frames_in_flight, &dev);
 
-        let voxel_vertex_buffer =
-            StorageBuffer::new_array(VRAM_VIA_BAR, DEFAULT_VOXEL_VERTEX_MAX_COUNT, &dev);
-        let voxel_triangle_buffer =
-            StorageBuffer::new_array(VRAM_VIA_BAR, DEFAULT_VOXEL_TRIANGLE_MAX_COUNT, &dev);
-        let voxel_meshlet_buffer =
-            StorageBuffer::new_array(VRAM_VIA_BAR, DEFAULT_VOXEL_MESHLET_MAX_COUNT, &dev);
-        let mut voxel_octree_buffer =
-            StorageBuffer::new_array(VRAM_VIA_BAR, DEFAULT_VOXEL_OCTREE_MAX_COUNT, &dev);
+        let query_pool = create_query_pool(frames_in_flight, &dev);
+
+        let voxel_materials = StorageBuffer::new_array(
+            VRAM_VIA_BAR,
+            MATERIAL_COUNT * frames_in_flight,
+            MemoryTag::Storage,
+            &dev,
+        );
+        let voxel_vertex_buffer = StorageBuffer::new_array(
+            VRAM_VIA_BAR,
+            DEFAULT_VOXEL_VERTEX_MAX_COUNT,
+            MemoryTag::VoxelChunks,
+            &dev,
+        );
+        let voxel_triangle_buffer = StorageBuffer::new_array(
+            VRAM_VIA_BAR,
+            DEFAULT_VOXEL_TRIANGLE_MAX_COUNT,
+            MemoryTag::VoxelChunks,
+            &dev,
+        );
+        let voxel_meshlet_buffer = StorageBuffer::new_array(
+            VRAM_VIA_BAR,
+            DEFAULT_VOXEL_MESHLET_MAX_COUNT,
+            MemoryTag::VoxelChunks,
+            &dev,
+        );
+        let mut voxel_octree_buffer = StorageBuffer::new_array(
+            VRAM_VIA_BAR,
+            DEFAULT_VOXEL_OCTREE_MAX_COUNT,
+            MemoryTag::VoxelChunks,
+            &dev,
+        );
         voxel_octree_buffer.generate(|_| SvoNode::EMPTY_ROOT);
 
-        let global = UniformBuffer::create(&dev);
-        let global_descriptor_sets = alloc_descriptor_set(
+        let global = UniformBuffer::create(frames_in_flight, &dev);
+        let global_descriptor_sets = alloc_global_descriptor_set(
             &global,
             &stars,
             &voxel_vertex_buffer,
             &voxel_triangle_buffer,
             &voxel_meshlet_buffer,
             &voxel_octree_buffer,
+            &debug_vertices,
+            shadow_map.view,
+            &star_draw_commands,
+            &visible_stars,
+            color.view,
+            color_lut.view,
+            depth.view,
+            hiz.view,
+            ssr_image.view,
+            &objects,
+            particles.particles(),
+            particles.emitted(),
+            particles.draw_commands(),
+            &object_draw_calls,
+            &visible_object_counts,
+            &voxel_materials,
+            skybox.view,
+            dof_image.view,
+            sky.capture.view,
+            sky.irradiance.view,
+            sky.irradiance.view,
+            &visible_meshlet_counts,
+            frames_in_flight,
             &dev,
-            descriptor_set_layout,
+            descriptor_set_layouts[GLOBAL_DESCRIPTOR_SET as usize],
             descriptor_pool,
         );
+        for (index, image) in bloom_images.iter().enumerate() {
+            update_bloom_images_descriptor(
+                &global_descriptor_sets,
+                index as u32,
+                image.view,
+                &samplers,
+                &dev,
+            );
+        }
+        for (index, image) in ssao_images.iter().enumerate() {
+            update_ssao_images_descriptor(
+                &global_descriptor_sets,
+                index as u32,
+                image.view,
+                &samplers,
+                &dev,
+            );
+        }
 
+        // Everything other than voxel chunks is allocated up front and has a fixed size (except for
+        // render targets changing with the window), so the limit is simulated by only letting the
+        // chunks use whatever is left of it. The voxel pools themselves stay full size, as they
+        // are allocated before anything is known about the limit anyway.
+        let voxel_budget = args.vram_limit.map(|limit| {
+            let other: u64 = MemoryTag::ALL
+                .iter()
+                .filter(|tag| **tag != MemoryTag::VoxelChunks)
+                .map(|tag| dev.allocator.tag_usage(*tag))
+                .sum();
+            let budget = limit.saturating_sub(other);
+            debug!(
+                "vram limit applied, \x1B[1mlimit\x1B[0m: {} MiB, \x1B[1mvoxel budget\x1B[0m: {} MiB",
+                limit / (1024 * 1024),
+                budget / (1024 * 1024)
+            );
+            budget as usize
+        });
         let voxel_meshlet_count = Arc::new(AtomicU32::new(0));
-        let voxel_gpu_memory = Box::new(VoxelMeshletMemory::new(
+        let voxel_frame_index = Arc::new(AtomicUsize::new(0));
+        let voxel_memory = Arc::new(Mutex::new(VoxelMeshletMemory::new(
             voxel_meshlet_count.clone(),
+            voxel_frame_index.clone(),
             voxel_vertex_buffer,
             voxel_triangle_buffer,
             voxel_meshlet_buffer,
             voxel_octree_buffer,
+            voxel_budget,
+            frames_in_flight,
             dev.clone(),
-        )) as Box<dyn VoxelGpuMemory>;
+        )));
 
-        Renderer {
+        let mut renderer = Renderer {
             _entry: entry,
             debug_messenger,
             surface,
@@ -161,30 +440,97 @@ impl Renderer {
             queue,
             properties,
             samplers,
-            descriptor_set_layout,
+            descriptor_set_layouts,
             descriptor_pool,
             pipeline_layout,
+            pipeline_cache,
+            pipeline_cache_path,
             passes,
             swapchain,
+            present_mode,
+            swapchain_outdated: false,
             pipelines,
+            render_scale,
+            render_extent,
+            color,
             depth,
+            bloom_extent,
+            bloom_images,
+            bloom_radius,
+            postprocess_effects,
+            ssr_extent,
+            hiz,
+            ssr_image,
+            ssr_max_steps,
+            atmosphere_samples,
+            ssao_images,
+            ssao_effects,
+            dof_image,
+            taa_history: None,
+            taa_history_valid: false,
+            previous_view_projection: Matrix4::identity(),
+            color_lut,
+            color_lut_path: None,
+            skybox,
+            skybox_path: None,
+            skybox_loaded: false,
+            sky,
+            shadow_resolution,
+            shadow_map,
+            frames_in_flight,
             command_pools,
             command_buffers,
+            secondary_command_pools,
+            secondary_command_buffers,
+            recording_threads,
             sync,
             flight_index: 0,
             mesh_objects,
+            staging,
+            staging_ring,
             stars,
+            debug_vertices,
+            debug_line_count: 0,
+            objects,
+            object_draws: Vec::new(),
+            opaque_object_count: 0,
+            object_draw_calls,
+            visible_object_counts,
+            visible_object_count: 0,
+            particles,
+            star_count: world.stars.len(),
+            star_draw_commands,
+            visible_stars,
+            visible_star_count: 0,
             global,
             descriptor_sets: global_descriptor_sets,
+            texture_handles: HashMap::new(),
+            next_texture_slot: 0,
+            textures: Vec::new(),
+            voxel_materials,
             voxel_meshlet_count,
-            voxel_gpu_memory: Some(voxel_gpu_memory),
+            visible_meshlet_counts,
+            visible_meshlet_count: 0,
+            voxel_frame_index,
+            voxel_memory,
+            active_voxel_session: 0,
             query_pool,
             frame_index: 0,
             frametime: None,
+            pass_times: Vec::new(),
+            timed_passes: vec![Vec::new(); frames_in_flight],
+            last_present: None,
+            present_interval: None,
+            memory_pressure: false,
             just_completed_first_render: false,
+            capture: None,
             #[cfg(feature = "dev-menu")]
             interface_renderer: None,
-        }
+            #[cfg(feature = "dev-menu")]
+            shader_watcher: args.watch_shaders.then(|| ShaderWatcher::new(shaders)),
+        };
+        renderer.create_material_textures();
+        renderer
     }
 
     #[cfg(feature = "dev-menu")]
@@ -198,11 +544,11 @@ impl Renderer {
                 self.command_pools[0],
                 imgui_rs_vulkan_renderer::DynamicRendering {
                     color_attachment_format: self.swapchain.format.format,
-                    depth_attachment_format: Some(DEPTH_FORMAT),
+                    depth_attachment_format: None,
                 },
                 imgui,
                 Some(imgui_rs_vulkan_renderer::Options {
-                    in_flight_frames: FRAMES_IN_FLIGHT,
+                    in_flight_frames: self.frames_in_flight,
                     enable_depth_test: false,
                     enable_depth_write: false,
                     subpass: 0,
@@ -214,6 +560,14 @@ impl Renderer {
     }
 
     pub fn recreate_swapchain(&mut self, window_size: PhysicalSize<u32>) {
+        // Swapchains can't have a zero extent, which is what minimized windows report. The old one
+        // is kept until the window is restored, and nothing is drawn in the meantime.
+        if window_size.width == 0 || window_size.height == 0 {
+            self.swapchain_outdated = true;
+            return;
+        }
+        self.swapchain_outdated = false;
+
         // First, wait for the GPU work to end. It's possible to pass an old swapchain while
         // creating the new one which results in a faster (?) transition, but in the interest of
         // simplicity let's skip that for now.
@@ -224,25 +578,88 @@ impl Renderer {
         // contain not only things like image formats, but also some sizes.
         self.cleanup_swapchain();
 
-        self.swapchain = create_swapchain(self.surface, window_size, &self.dev);
+        self.swapchain = create_swapchain(
+            self.surface,
+            window_size,
+            self.present_mode,
+            self.frames_in_flight,
+            &self.dev,
+        );
+        update_renderer_swapchain_extent(self.swapchain.extent);
+        self.stop_recording_on_resize();
         self.passes =
             create_render_passes(&self.swapchain, vk::SampleCountFlags::TYPE_1, &self.dev);
-        self.depth = create_depth(self.swapchain.extent, &self.dev);
+        self.create_render_targets();
+        self.recreate_pipelines();
+    }
 
+    // Changing the render scale doesn't affect the swapchain, so only the offscreen attachments
+    // and the pipelines (which have the viewport baked in) need to be recreated.
+    pub fn recreate_render_targets(&mut self, render_scale: f32) {
+        unsafe { self.dev.device_wait_idle() }.unwrap();
+        self.cleanup_render_targets();
+        self.render_scale = render_scale;
+        self.create_render_targets();
         self.recreate_pipelines();
     }
 
+    pub fn recreate_shadow_map(&mut self, resolution: u32) {
+        unsafe { self.dev.device_wait_idle() }.unwrap();
+        self.shadow_map.cleanup(&self.dev);
+        self.shadow_resolution = resolution;
+        self.shadow_map = create_shadow_map(resolution, &self.dev);
+        update_shadow_map_descriptor(&self.descriptor_sets, self.shadow_map.view, &self.dev);
+    }
+
+    // The history images are only needed while TAA is enabled, so unlike the other render targets
+    // they are allocated and freed whenever it's toggled.
+    pub fn recreate_taa_history(&mut self, enabled: bool) {
+        unsafe { self.dev.device_wait_idle() }.unwrap();
+        if let Some(history) = self.taa_history.take() {
+            for image in &history {
+                image.cleanup(&self.dev);
+            }
+        }
+        if enabled {
+            self.create_taa_history();
+        }
+    }
+
     pub fn recreate_pipelines(&mut self) {
         unsafe { self.dev.device_wait_idle() }.unwrap();
         self.pipelines.cleanup(&self.dev);
-        let shaders = create_shaders(&self.dev.support);
-        let shader_modules = create_shader_modules(&shaders, &self.dev);
+        // While watching, the kept shaders are up to date already, and compiling everything again
+        // would exit on any error the watcher just reported.
+        #[cfg(feature = "dev-menu")]
+        let watched_shaders = self.shader_watcher.as_ref().map(|watcher| &watcher.shaders);
+        #[cfg(not(feature = "dev-menu"))]
+        let watched_shaders = None;
+        let shader_modules = match watched_shaders {
+            Some(shaders) => create_shader_modules(shaders, &self.dev),
+            None => create_shader_modules(&create_shaders(&self.dev.support), &self.dev),
+        };
         self.pipelines = create_pipelines(
             vk::SampleCountFlags::TYPE_1,
             &self.passes,
+            self.postprocess_effects[0],
+            self.postprocess_effects[1],
+            self.postprocess_effects[2],
+            self.postprocess_effects[3],
+            self.postprocess_effects[4],
+            self.ssao_effects.0,
+            self.ssao_effects.1,
+            self.atmosphere_samples.0,
+            self.atmosphere_samples.1,
+            self.ssr_max_steps,
+            self.bloom_radius,
+            0,
+            self.bloom_radius,
+            1,
             &self.swapchain,
+            self.render_extent,
             &shader_modules,
             self.pipeline_layout,
+            self.pipeline_cache,
             &self.dev,
         );
         shader_modules.cleanup(&self.dev);
@@ -250,20 +667,92 @@ impl Renderer {
 
     fn cleanup_swapchain(&mut self) {
         self.swapchain.cleanup(&self.dev);
+        self.cleanup_render_targets();
+    }
+
+    // The bloom passes sample the color attachment and each other through the descriptor set, so
+    // it has to be pointed at the new images every time they're recreated.
+    fn create_render_targets(&mut self) {
+        (self.render_extent, self.color, self.depth) =
+            create_render_targets(&self.swapchain, self.render_scale, &self.dev);
+        (self.bloom_extent, self.bloom_images) =
+            create_bloom_targets(self.swapchain.format.format, self.render_extent, &self.dev);
+        (self.ssr_extent, self.hiz, self.ssr_image) =
+            create_ssr_targets(self.render_extent, &self.dev);
+        self.ssao_images = create_ssao_targets(self.render_extent, &self.dev);
+        self.dof_image = create_color(self.swapchain.format.format, self.render_extent, &self.dev);
+        update_scene_color_descriptor(&self.descriptor_sets, self.color.view, &self.dev);
+        update_scene_depth_descriptor(&self.descriptor_sets, self.depth.view, &self.dev);
+        update_hiz_descriptor(&self.descriptor_sets, self.hiz.view, &self.dev);
+        update_ssr_image_descriptor(&self.descriptor_sets, self.ssr_image.view, &self.dev);
+        update_dof_image_descriptor(&self.descriptor_sets, self.dof_image.view, &self.dev);
+        for (index, image) in self.bloom_images.iter().enumerate() {
+            update_bloom_images_descriptor(
+                &self.descriptor_sets,
+                index as u32,
+                image.view,
+                &self.samplers,
+                &self.dev,
+            );
+        }
+        for (index, image) in self.ssao_images.iter().enumerate() {
+            update_ssao_images_descriptor(
+                &self.descriptor_sets,
+                index as u32,
+                image.view,
+                &self.samplers,
+                &self.dev,
+            );
+        }
+        if self.taa_history.is_some() {
+            self.create_taa_history();
+        }
+    }
+
+    fn create_taa_history(&mut self) {
+        let history = [(); 2]
+            .map(|()| create_color(self.swapchain.format.format, self.render_extent, &self.dev));
+        for (index, image) in history.iter().enumerate() {
+            update_taa_history_descriptor(
+                &self.descriptor_sets,
+                index as u32,
+                image.view,
+                &self.samplers,
+                &self.dev,
+            );
+        }
+        self.taa_history = Some(history);
+        self.taa_history_valid = false;
+    }
+
+    fn cleanup_render_targets(&mut self) {
+        self.color.cleanup(&self.dev);
         self.depth.cleanup(&self.dev);
+        for image in &self.bloom_images {
+            image.cleanup(&self.dev);
+        }
+        self.hiz.cleanup(&self.dev);
+        self.ssr_image.cleanup(&self.dev);
+        for image in &self.ssao_images {
+            image.cleanup(&self.dev);
+        }
+        self.dof_image.cleanup(&self.dev);
+        if let Some(history) = &self.taa_history {
+            for image in history {
+                image.cleanup(&self.dev);
+            }
+        }
     }
 }
 
 impl Synchronization {
     fn cleanup(&self, dev: &Device) {
-        for fence in self.in_flight {
-            unsafe { dev.destroy_fence(fence, None) };
-        }
-        for semaphore in self.render_finished {
-            unsafe { dev.destroy_semaphore(semaphore, None) };
+        unsafe { dev.destroy_semaphore(self.frame_timeline, None) };
+        for semaphore in &self.render_finished {
+            unsafe { dev.destroy_semaphore(*semaphore, None) };
         }
-        for semaphore in self.image_available {
-            unsafe { dev.destroy_semaphore(semaphore, None) };
+        for semaphore in &self.image_available {
+            unsafe { dev.destroy_semaphore(*semaphore, None) };
         }
     }
 }
@@ -280,10 +769,26 @@ impl Drop for Renderer {
         unsafe {
             self.dev.device_wait_idle().unwrap();
 
+            self.stop_recording();
             #[cfg(feature = "dev-menu")]
             drop(self.interface_renderer.take());
             self.dev.destroy_query_pool(self.query_pool, None);
             self.stars.cleanup(&self.dev);
+            self.staging.cleanup(&self.dev);
+            self.staging_ring.cleanup(&self.dev);
+            self.debug_vertices.cleanup(&self.dev);
+            self.objects.cleanup(&self.dev);
+            self.particles.cleanup(&self.dev);
+            self.object_draw_calls.cleanup(&self.dev);
+            self.visible_object_counts.cleanup(&self.dev);
+            self.visible_meshlet_counts.cleanup(&self.dev);
+            self.voxel_memory
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .cleanup();
+            self.star_draw_commands.cleanup(&self.dev);
+            self.visible_stars.cleanup(&self.dev);
+            self.voxel_materials.cleanup(&self.dev);
             for mesh in &self.mesh_objects {
                 mesh.cleanup(&self.dev);
             }
@@ -292,15 +797,30 @@ impl Drop for Renderer {
             for pool in &self.command_pools {
                 self.dev.destroy_command_pool(*pool, None);
             }
+            for pool in self.secondary_command_pools.as_flattened() {
+                self.dev.destroy_command_pool(*pool, None);
+            }
             self.cleanup_swapchain();
+            self.shadow_map.cleanup(&self.dev);
+            self.color_lut.cleanup(&self.dev);
+            for texture in &self.textures {
+                texture.cleanup(&self.dev);
+            }
+            self.skybox.cleanup(&self.dev);
+            self.sky.cleanup(&self.dev);
             self.pipelines.cleanup(&self.dev);
+            self.dev.destroy_pipeline_cache(self.pipeline_cache, None);
             self.dev.destroy_pipeline_layout(self.pipeline_layout, None);
             self.dev.destroy_descriptor_pool(self.descriptor_pool, None);
-            self.dev
-                .destroy_descriptor_set_layout(self.descriptor_set_layout, None);
+            for layout in self.descriptor_set_layouts {
+                self.dev.destroy_descriptor_set_layout(layout, None);
+            }
             self.samplers.cleanup(&self.dev);
+            self.dev.allocator.cleanup(&self.dev);
             self.dev.destroy_device(None);
-            self.dev.surface_ext.destroy_surface(self.surface, None);
+            if let Some(surface) = self.surface {
+                self.dev.surface_ext.destroy_surface(surface, None);
+            }
             self.dev
                 .debug_ext_instance
                 .destroy_debug_utils_messenger(self.debug_messenger, None);
@@ -309,7 +829,89 @@ impl Drop for Renderer {
     }
 }
 
-fn create_instance(window: &Window, entry: &Entry, args: &Args) -> Instance {
+impl PipelineCacheManager {
+    pub fn load(dev: &Dev, cache_path: &Path) -> vk::PipelineCache {
+        let uuid = pipeline_cache_uuid(dev);
+        let file = match std::fs::read(cache_path) {
+            Ok(file) => file,
+            Err(error) => {
+                if error.kind() != ErrorKind::NotFound {
+                    warn!("failed to read pipeline cache, \x1B[1merror\x1B[0m: {error}");
+                }
+                Vec::new()
+            }
+        };
+        let initial_data = match strip_pipeline_cache_header(&file, &uuid) {
+            Some(data) => data,
+            None => {
+                if !file.is_empty() {
+                    warn!("pipeline cache is from a different device or driver, starting cold");
+                }
+                &[]
+            }
+        };
+        let create_info = vk::PipelineCacheCreateInfo::default().initial_data(initial_data);
+        match unsafe { dev.create_pipeline_cache(&create_info, None) } {
+            Ok(cache) => cache,
+            Err(error) => {
+                warn!("pipeline cache rejected by the driver, \x1B[1merror\x1B[0m: {error}");
+                let create_info = vk::PipelineCacheCreateInfo::default();
+                unsafe { dev.create_pipeline_cache(&create_info, None) }.unwrap()
+            }
+        }
+    }
+
+    // Written to a temporary file first, so that exiting midway doesn't leave a truncated cache
+    // behind.
+    pub fn save(dev: &Dev, cache: vk::PipelineCache, cache_path: &Path) {
+        let data = unsafe { dev.get_pipeline_cache_data(cache) }.unwrap();
+        let file = add_pipeline_cache_header(&data, &pipeline_cache_uuid(dev));
+        if let Some(parent) = cache_path.parent() {
+            if let Err(error) = std::fs::create_dir_all(parent) {
+                warn!("failed to create cache directory, \x1B[1merror\x1B[0m: {error}");
+            }
+        }
+        let mut temporary_path = OsString::from(cache_path);
+        temporary_path.push(".tmp");
+        let result = std::fs::write(&temporary_path, file)
+            .and_then(|()| std::fs::rename(&temporary_path, cache_path));
+        if let Err(error) = result {
+            warn!("failed to save pipeline cache, \x1B[1merror\x1B[0m: {error}");
+        }
+    }
+}
+
+fn default_pipeline_cache_path() -> PathBuf {
+    let cache_home = match std::env::var_os("XDG_CACHE_HOME") {
+        Some(path) if !path.is_empty() => Some(PathBuf::from(path)),
+        _ => std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".cache")),
+    };
+    match cache_home {
+        Some(cache_home) => cache_home.join("vulkthing").join("pipeline_cache.bin"),
+        None => PathBuf::from("pipeline_cache.bin"),
+    }
+}
+
+fn pipeline_cache_uuid(dev: &Dev) -> [u8; vk::UUID_SIZE] {
+    let properties = unsafe { dev.instance.get_physical_device_properties(dev.physical) };
+    properties.pipeline_cache_uuid
+}
+
+fn add_pipeline_cache_header(data: &[u8], uuid: &[u8; vk::UUID_SIZE]) -> Vec<u8> {
+    let mut file = Vec::with_capacity(PIPELINE_CACHE_HEADER_SIZE + data.len());
+    file.extend_from_slice(&PIPELINE_CACHE_MAGIC);
+    file.extend_from_slice(uuid);
+    file.extend_from_slice(data);
+    file
+}
+
+// Returns the cache data without the header, or None if the file wasn't written for this device.
+fn strip_pipeline_cache_header<'a>(file: &'a [u8], uuid: &[u8; vk::UUID_SIZE]) -> Option<&'a [u8]> {
+    let data = file.strip_prefix(&PIPELINE_CACHE_MAGIC)?;
+    data.strip_prefix(uuid)
+}
+
+fn create_instance(window: Option<&Window>, entry: &Entry, args: &Args) -> Instance {
     // Set metadata of the app and the engine. May be used by the drivers to enable game-specific
     // and engine-specific optimizations, which won't happen, but let's set it to something sensible
     // anyway.
@@ -355,11 +957,15 @@ fn create_instance(window: &Window, entry: &Entry, args: &Args) -> Instance {
     // Vulkan doesn't appear to have any interesting extensions at this level, physical device
     // extensions are the interesting ones with raytracing and other stuff. This is just for
     // OS-specific windowing system interactions, and enabling debug logging for the validation
-    // layers.
-    let mut extension_names =
-        ash_window::enumerate_required_extensions(window.display_handle().unwrap().as_raw())
-            .unwrap()
-            .to_vec();
+    // layers. Rendering offscreen needs none of the windowing ones.
+    let mut extension_names = match window {
+        Some(window) => {
+            ash_window::enumerate_required_extensions(window.display_handle().unwrap().as_raw())
+                .unwrap()
+                .to_vec()
+        }
+        None => Vec::new(),
+    };
     extension_names.push(debug_utils::NAME.as_ptr());
 
     let mut instance_create_info = vk::InstanceCreateInfo::default()
@@ -403,6 +1009,8 @@ fn create_surface(window: &Window, entry: &Entry, instance: &Instance) -> vk::Su
 
 fn create_logical_device(
     queue_family: u32,
+    transfer_queue_family: Option<u32>,
+    presentation: bool,
     instance: &Instance,
     physical_device: vk::PhysicalDevice,
     device_support: &DeviceSupport,
@@ -410,9 +1018,19 @@ fn create_logical_device(
     let queue_create = vk::DeviceQueueCreateInfo::default()
         .queue_family_index(queue_family)
         .queue_priorities(&[1.]);
-    let queues = [queue_create];
+    let mut queues = vec![queue_create];
+    if let Some(transfer_queue_family) = transfer_queue_family {
+        queues.push(
+            vk::DeviceQueueCreateInfo::default()
+                .queue_family_index(transfer_queue_family)
+                .queue_priorities(&[1.]),
+        );
+    }
 
-    let mut extensions = vec![swapchain::NAME.as_ptr()];
+    let mut extensions = Vec::new();
+    if presentation {
+        extensions.push(swapchain::NAME.as_ptr());
+    }
     if device_support.mesh_shaders {
         extensions.extend_from_slice(&[
             mesh_shader::NAME.as_ptr(),
@@ -420,6 +1038,9 @@ fn create_logical_device(
             ash::khr::spirv_1_4::NAME.as_ptr(),
         ]);
     }
+    if device_support.memory_budget {
+        extensions.push(ash::ext::memory_budget::NAME.as_ptr());
+    }
 
     let features = vk::PhysicalDeviceFeatures::default()
         .fill_mode_non_solid(true)
@@ -431,7 +1052,13 @@ fn create_logical_device(
     let mut vk12_features = vk::PhysicalDeviceVulkan12Features::default()
         .shader_int8(true)
         .storage_buffer8_bit_access(true)
-        .timeline_semaphore(true);
+        .timeline_semaphore(true)
+        // Descriptor indexing is needed for the bindless texture array. Not all slots are filled
+        // in, and new textures can be registered while frames using other slots are in flight.
+        .runtime_descriptor_array(true)
+        .shader_sampled_image_array_non_uniform_indexing(true)
+        .descriptor_binding_partially_bound(true)
+        .descriptor_binding_update_unused_while_pending(true);
     // TODO: Something in the task shader requires maintenance4, why?
     let mut vk13_features = vk::PhysicalDeviceVulkan13Features::default()
         .dynamic_rendering(true)
@@ -455,10 +1082,124 @@ fn create_logical_device(
     unsafe { instance.create_device(physical_device, &create_info, None) }.unwrap()
 }
 
-fn create_pipeline_layout(layout: vk::DescriptorSetLayout, dev: &Dev) -> vk::PipelineLayout {
-    let create_info =
-        vk::PipelineLayoutCreateInfo::default().set_layouts(std::array::from_ref(&layout));
-    unsafe { dev.create_pipeline_layout(&create_info, None).unwrap() }
+fn create_render_targets(
+    swapchain: &Swapchain,
+    render_scale: f32,
+    dev: &Dev,
+) -> (vk::Extent2D, ImageResources, ImageResources) {
+    let extent = vk::Extent2D {
+        width: ((swapchain.extent.width as f32 * render_scale).round() as u32).max(1),
+        height: ((swapchain.extent.height as f32 * render_scale).round() as u32).max(1),
+    };
+    let color = create_color(swapchain.format.format, extent, dev);
+    let depth = create_depth(extent, dev);
+    (extent, color, depth)
+}
+
+fn create_color(format: vk::Format, extent: vk::Extent2D, dev: &Dev) -> ImageResources {
+    ImageResources::create(
+        format,
+        vk::MemoryPropertyFlags::DEVICE_LOCAL,
+        vk::ImageTiling::OPTIMAL,
+        vk::ImageUsageFlags::COLOR_ATTACHMENT | vk::ImageUsageFlags::SAMPLED,
+        vk::ImageAspectFlags::COLOR,
+        extent,
+        vk::SampleCountFlags::TYPE_1,
+        MemoryTag::Attachment,
+        dev,
+    )
+}
+
+fn create_bloom_targets(
+    format: vk::Format,
+    render_extent: vk::Extent2D,
+    dev: &Dev,
+) -> (vk::Extent2D, [ImageResources; 2]) {
+    let extent = vk::Extent2D {
+        width: render_extent.width.div_ceil(2),
+        height: render_extent.height.div_ceil(2),
+    };
+    let create = || {
+        ImageResources::create(
+            format,
+            vk::MemoryPropertyFlags::DEVICE_LOCAL,
+            vk::ImageTiling::OPTIMAL,
+            vk::ImageUsageFlags::COLOR_ATTACHMENT | vk::ImageUsageFlags::SAMPLED,
+            vk::ImageAspectFlags::COLOR,
+            extent,
+            vk::SampleCountFlags::TYPE_1,
+            MemoryTag::Attachment,
+            dev,
+        )
+    };
+    (extent, [create(), create()])
+}
+
+fn create_ssr_targets(
+    render_extent: vk::Extent2D,
+    dev: &Dev,
+) -> (vk::Extent2D, ImageResources, ImageResources) {
+    let extent = vk::Extent2D {
+        width: render_extent.width.div_ceil(2),
+        height: render_extent.height.div_ceil(2),
+    };
+    let hiz = ImageResources::create(
+        vk::Format::R32_SFLOAT,
+        vk::MemoryPropertyFlags::DEVICE_LOCAL,
+        vk::ImageTiling::OPTIMAL,
+        vk::ImageUsageFlags::STORAGE,
+        vk::ImageAspectFlags::COLOR,
+        extent,
+        vk::SampleCountFlags::TYPE_1,
+        MemoryTag::Attachment,
+        dev,
+    );
+    let ssr = ImageResources::create(
+        vk::Format::R16G16B16A16_SFLOAT,
+        vk::MemoryPropertyFlags::DEVICE_LOCAL,
+        vk::ImageTiling::OPTIMAL,
+        vk::ImageUsageFlags::COLOR_ATTACHMENT | vk::ImageUsageFlags::SAMPLED,
+        vk::ImageAspectFlags::COLOR,
+        extent,
+        vk::SampleCountFlags::TYPE_1,
+        MemoryTag::Attachment,
+        dev,
+    );
+    (extent, hiz, ssr)
+}
+
+fn create_ssao_targets(render_extent: vk::Extent2D, dev: &Dev) -> [ImageResources; 2] {
+    let create = || {
+        ImageResources::create(
+            vk::Format::R8_UNORM,
+            vk::MemoryPropertyFlags::DEVICE_LOCAL,
+            vk::ImageTiling::OPTIMAL,
+            vk::ImageUsageFlags::COLOR_ATTACHMENT | vk::ImageUsageFlags::SAMPLED,
+            vk::ImageAspectFlags::COLOR,
+            render_extent,
+            vk::SampleCountFlags::TYPE_1,
+            MemoryTag::Attachment,
+            dev,
+        )
+    };
+    [create(), create()]
+}
+
+fn create_shadow_map(resolution: u32, dev: &Dev) -> ImageResources {
+    ImageResources::create(
+        DEPTH_FORMAT,
+        vk::MemoryPropertyFlags::DEVICE_LOCAL,
+        vk::ImageTiling::OPTIMAL,
+        vk::ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT | vk::ImageUsageFlags::SAMPLED,
+        vk::ImageAspectFlags::DEPTH,
+        vk::Extent2D {
+            width: resolution,
+            height: resolution,
+        },
+        vk::SampleCountFlags::TYPE_1,
+        MemoryTag::Attachment,
+        dev,
+    )
 }
 
 fn create_depth(extent: vk::Extent2D, dev: &Dev) -> ImageResources {
@@ -466,28 +1207,43 @@ fn create_depth(extent: vk::Extent2D, dev: &Dev) -> ImageResources {
         DEPTH_FORMAT,
         vk::MemoryPropertyFlags::DEVICE_LOCAL,
         vk::ImageTiling::OPTIMAL,
-        vk::ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT | vk::ImageUsageFlags::TRANSIENT_ATTACHMENT,
+        // Sampled by the Hi-Z, SSR and TAA passes after the scene is rendered, so it can't be
+        // transient.
+        vk::ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT | vk::ImageUsageFlags::SAMPLED,
         vk::ImageAspectFlags::DEPTH,
         extent,
         vk::SampleCountFlags::TYPE_1,
+        MemoryTag::Attachment,
         dev,
     )
 }
 
-fn create_command_pools(queue_family: u32, dev: &Dev) -> [vk::CommandPool; FRAMES_IN_FLIGHT] {
-    let command_pool_info = vk::CommandPoolCreateInfo::default().queue_family_index(queue_family);
-    let mut pools = [vk::CommandPool::null(); FRAMES_IN_FLIGHT];
-    for pool in &mut pools {
-        *pool = unsafe { dev.create_command_pool(&command_pool_info, None) }.unwrap();
+// The swapchain is created with an image for every frame in flight and one more, but the platform
+// can cap the image count below that. More frames than images would only make acquiring block,
+// adding latency without any throughput.
+fn select_frames_in_flight(requested: usize, swapchain_image_count: usize) -> usize {
+    let frames_in_flight = requested.clamp(1, swapchain_image_count);
+    if frames_in_flight != requested {
+        warn!(
+            "frames in flight limited by swapchain image count, \x1B[1mrequested\x1B[0m: {requested}, \x1B[1mused\x1B[0m: {frames_in_flight}"
+        );
     }
-    pools
+    frames_in_flight
 }
 
-fn create_command_buffers(
-    command_pools: &[vk::CommandPool; FRAMES_IN_FLIGHT],
+fn create_command_pools(
+    queue_family: u32,
+    frames_in_flight: usize,
     dev: &Dev,
-) -> [vk::CommandBuffer; FRAMES_IN_FLIGHT] {
-    let mut buffers = [vk::CommandBuffer::null(); FRAMES_IN_FLIGHT];
+) -> Vec<vk::CommandPool> {
+    let command_pool_info = vk::CommandPoolCreateInfo::default().queue_family_index(queue_family);
+    (0..frames_in_flight)
+        .map(|_| unsafe { dev.create_command_pool(&command_pool_info, None) }.unwrap())
+        .collect()
+}
+
+fn create_command_buffers(command_pools: &[vk::CommandPool], dev: &Dev) -> Vec<vk::CommandBuffer> {
+    let mut buffers = vec![vk::CommandBuffer::null(); command_pools.len()];
     for (i, buffer) in buffers.iter_mut().enumerate() {
         let buffer_info = vk::CommandBufferAllocateInfo::default()
             .command_pool(command_pools[i])
@@ -498,41 +1254,117 @@ fn create_command_buffers(
     buffers
 }
 
-pub fn create_vertex_buffer(vertex_data: &[Vertex], dev: &Dev) -> Buffer {
+fn create_secondary_command_pools(
+    queue_family: u32,
+    frames_in_flight: usize,
+    dev: &Dev,
+) -> Vec<[vk::CommandPool; DrawGroup::ALL.len()]> {
+    let command_pool_info = vk::CommandPoolCreateInfo::default().queue_family_index(queue_family);
+    let mut pools = vec![[vk::CommandPool::null(); DrawGroup::ALL.len()]; frames_in_flight];
+    for pool in pools.as_flattened_mut() {
+        *pool = unsafe { dev.create_command_pool(&command_pool_info, None) }.unwrap();
+    }
+    pools
+}
+
+fn create_secondary_command_buffers(
+    command_pools: &[[vk::CommandPool; DrawGroup::ALL.len()]],
+    dev: &Dev,
+) -> Vec<[vk::CommandBuffer; DrawGroup::ALL.len()]> {
+    let mut buffers = vec![[vk::CommandBuffer::null(); DrawGroup::ALL.len()]; command_pools.len()];
+    for (buffer, pool) in buffers
+        .as_flattened_mut()
+        .iter_mut()
+        .zip(command_pools.as_flattened())
+    {
+        let buffer_info = vk::CommandBufferAllocateInfo::default()
+            .command_pool(*pool)
+            .level(vk::CommandBufferLevel::SECONDARY)
+            .command_buffer_count(1);
+        *buffer = unsafe { dev.allocate_command_buffers(&buffer_info) }.unwrap()[0];
+    }
+    buffers
+}
+
+pub fn create_vertex_buffer(vertex_data: &[Vertex], ring: &mut StagingRing, dev: &Dev) -> Buffer {
     let size = std::mem::size_of_val(vertex_data);
-    let mut vertex = Buffer::create(VRAM_VIA_BAR, vk::BufferUsageFlags::VERTEX_BUFFER, size, dev);
-    vertex.fill_from_slice_host_visible(vertex_data, dev);
+    let vertex = Buffer::create(
+        vk::MemoryPropertyFlags::DEVICE_LOCAL,
+        vk::BufferUsageFlags::VERTEX_BUFFER | vk::BufferUsageFlags::TRANSFER_DST,
+        size,
+        MemoryTag::Mesh,
+        dev,
+    );
+    ring.upload_buffer(&vertex, vertex_data, dev);
     vertex
 }
 
-fn create_index_buffer(index_data: &[u32], dev: &Dev) -> Buffer {
+fn create_index_buffer(index_data: &[u32], ring: &mut StagingRing, dev: &Dev) -> Buffer {
     let size = std::mem::size_of_val(index_data);
-    let mut vertex = Buffer::create(VRAM_VIA_BAR, vk::BufferUsageFlags::INDEX_BUFFER, size, dev);
-    vertex.fill_from_slice_host_visible(index_data, dev);
-    vertex
+    let index = Buffer::create(
+        vk::MemoryPropertyFlags::DEVICE_LOCAL,
+        vk::BufferUsageFlags::INDEX_BUFFER | vk::BufferUsageFlags::TRANSFER_DST,
+        size,
+        MemoryTag::Mesh,
+        dev,
+    );
+    ring.upload_buffer(&index, index_data, dev);
+    index
 }
 
-fn create_sync(dev: &Dev) -> Synchronization {
+fn create_sync(frames_in_flight: usize, dev: &Dev) -> Synchronization {
     let semaphore_info = vk::SemaphoreCreateInfo::default();
-    let fence_info = vk::FenceCreateInfo::default().flags(vk::FenceCreateFlags::SIGNALED);
-    let mut image_available: [vk::Semaphore; FRAMES_IN_FLIGHT] = Default::default();
-    let mut render_finished: [vk::Semaphore; FRAMES_IN_FLIGHT] = Default::default();
-    let mut in_flight: [vk::Fence; FRAMES_IN_FLIGHT] = Default::default();
-    for i in 0..FRAMES_IN_FLIGHT {
-        image_available[i] = unsafe { dev.create_semaphore(&semaphore_info, None) }.unwrap();
-        render_finished[i] = unsafe { dev.create_semaphore(&semaphore_info, None) }.unwrap();
-        in_flight[i] = unsafe { dev.create_fence(&fence_info, None) }.unwrap();
+    let mut image_available = Vec::with_capacity(frames_in_flight);
+    let mut render_finished = Vec::with_capacity(frames_in_flight);
+    for _ in 0..frames_in_flight {
+        image_available.push(unsafe { dev.create_semaphore(&semaphore_info, None) }.unwrap());
+        render_finished.push(unsafe { dev.create_semaphore(&semaphore_info, None) }.unwrap());
     }
     Synchronization {
         image_available,
         render_finished,
-        in_flight,
+        frame_timeline: create_timeline_semaphore(dev),
     }
 }
 
-fn create_query_pool(dev: &Dev) -> vk::QueryPool {
+fn create_query_pool(frames_in_flight: usize, dev: &Dev) -> vk::QueryPool {
     let create_info = vk::QueryPoolCreateInfo::default()
         .query_type(vk::QueryType::TIMESTAMP)
-        .query_count((2 * FRAMES_IN_FLIGHT) as u32);
+        .query_count((TIMESTAMPS_PER_FRAME * frames_in_flight) as u32);
     unsafe { dev.create_query_pool(&create_info, None) }.unwrap()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pipeline_cache_header_round_trip() {
+        let uuid = [7; vk::UUID_SIZE];
+        let file = add_pipeline_cache_header(b"cache data", &uuid);
+        assert_eq!(file.len(), PIPELINE_CACHE_HEADER_SIZE + 10);
+        assert_eq!(
+            strip_pipeline_cache_header(&file, &uuid),
+            Some(&b"cache data"[..])
+        );
+    }
+
+    #[test]
+    fn pipeline_cache_uuid_mismatch_starts_cold() {
+        let uuid = [1; vk::UUID_SIZE];
+        let other_uuid = [2; vk::UUID_SIZE];
+        let file = add_pipeline_cache_header(b"cache data", &other_uuid);
+        assert!(strip_pipeline_cache_header(&file, &uuid).is_none());
+        let truncated = &file[..PIPELINE_CACHE_HEADER_SIZE - 1];
+        assert!(strip_pipeline_cache_header(truncated, &other_uuid).is_none());
+        assert!(strip_pipeline_cache_header(b"cache data", &uuid).is_none());
+    }
+
+    #[test]
+    fn frames_in_flight_clamped_to_swapchain() {
+        assert_eq!(select_frames_in_flight(2, 3), 2);
+        assert_eq!(select_frames_in_flight(3, 3), 3);
+        assert_eq!(select_frames_in_flight(4, 3), 3);
+        assert_eq!(select_frames_in_flight(0, 3), 1);
+    }
+}
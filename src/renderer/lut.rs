@@ -0,0 +1,135 @@
+use crate::assets::resolve_asset;
+use crate::renderer::codegen::update_color_lut_descriptor;
+use crate::renderer::util::{Ctx, ImageResources, StagingPool};
+use crate::renderer::Renderer;
+use ash::vk;
+use log::warn;
+use std::fs::File;
+use std::path::{Path, PathBuf};
+
+// Size of each side of the lookup table cube. Matches the common 1024x32 strip layout exported by
+// most image editors, where each of the 32 blue slices is laid out horizontally.
+pub const COLOR_LUT_SIZE: u32 = 32;
+
+const COLOR_LUT_FORMAT: vk::Format = vk::Format::R8G8B8A8_UNORM;
+
+const COLOR_LUT_EXTENT: vk::Extent3D = vk::Extent3D {
+    width: COLOR_LUT_SIZE,
+    height: COLOR_LUT_SIZE,
+    depth: COLOR_LUT_SIZE,
+};
+
+impl Renderer {
+    // Only the descriptor has to change, the postprocess pipeline reads the table through it, so
+    // there's no need to recreate anything else. Waiting for the device is simpler than keeping the
+    // old image alive until the frames in flight using it finish, and reloads are rare anyway.
+    pub(super) fn reload_color_lut(&mut self, path: Option<PathBuf>) {
+        unsafe { self.dev.device_wait_idle() }.unwrap();
+        let data = match &path {
+            Some(path) => load_color_lut(path),
+            None => identity_color_lut(),
+        };
+        let ctx = Ctx {
+            dev: &self.dev,
+            queue: self.queue,
+            command_pool: self.command_pools[0],
+        };
+        let lut = create_color_lut(&data, &mut self.staging, &ctx);
+        self.color_lut.cleanup(&self.dev);
+        self.color_lut = lut;
+        self.color_lut_path = path;
+        update_color_lut_descriptor(&self.descriptor_sets, self.color_lut.view, &self.dev);
+    }
+}
+
+pub fn create_color_lut(data: &[[u8; 4]], staging: &mut StagingPool, ctx: &Ctx) -> ImageResources {
+    let lut = ImageResources::create_3d(
+        COLOR_LUT_FORMAT,
+        vk::ImageUsageFlags::SAMPLED | vk::ImageUsageFlags::TRANSFER_DST,
+        COLOR_LUT_EXTENT,
+        ctx.dev,
+    );
+    ctx.execute(|buf| staging.upload_image(buf, &lut, COLOR_LUT_EXTENT, 1, data, 0, ctx.dev));
+    lut
+}
+
+// Used when no table is configured, so that the descriptor always points at something valid. The
+// shader skips the lookup entirely at zero strength, but the binding still has to be written.
+pub fn identity_color_lut() -> Vec<[u8; 4]> {
+    let mut data = Vec::with_capacity((COLOR_LUT_SIZE * COLOR_LUT_SIZE * COLOR_LUT_SIZE) as usize);
+    for b in 0..COLOR_LUT_SIZE {
+        for g in 0..COLOR_LUT_SIZE {
+            for r in 0..COLOR_LUT_SIZE {
+                data.push([
+                    identity_channel(r),
+                    identity_channel(g),
+                    identity_channel(b),
+                    255,
+                ]);
+            }
+        }
+    }
+    data
+}
+
+// Broken tables shouldn't take the whole game down, especially as they can be typed in from the
+// settings menu, so they are replaced with the identity table instead.
+pub fn load_color_lut(path: &Path) -> Vec<[u8; 4]> {
+    match read_color_lut(path) {
+        Ok(data) => data,
+        Err(error) => {
+            warn!(
+                "failed to load color lut, \x1B[1mpath\x1B[0m: {}, \x1B[1merror\x1B[0m: {error}",
+                path.display()
+            );
+            identity_color_lut()
+        }
+    }
+}
+
+// Relative paths are looked up in the asset base directories, so that tables shipped with the game
+// can be referred to the same way regardless of the working directory.
+fn read_color_lut(path: &Path) -> Result<Vec<[u8; 4]>, String> {
+    let path = resolve_asset(path).map_err(|error| error.to_string())?;
+    let file = File::open(path).map_err(|error| error.to_string())?;
+    let mut decoder = png::Decoder::new(file);
+    decoder.set_transformations(png::Transformations::normalize_to_color8());
+    let mut reader = decoder.read_info().map_err(|error| error.to_string())?;
+    let mut pixels = vec![0; reader.output_buffer_size()];
+    let info = reader
+        .next_frame(&mut pixels)
+        .map_err(|error| error.to_string())?;
+    if info.width != COLOR_LUT_SIZE * COLOR_LUT_SIZE || info.height != COLOR_LUT_SIZE {
+        return Err(format!(
+            "expected a {}x{} strip, got {}x{}",
+            COLOR_LUT_SIZE * COLOR_LUT_SIZE,
+            COLOR_LUT_SIZE,
+            info.width,
+            info.height
+        ));
+    }
+    let channels = match info.color_type {
+        png::ColorType::Rgb => 3,
+        png::ColorType::Rgba => 4,
+        color_type => return Err(format!("unsupported color type {color_type:?}")),
+    };
+
+    // The strip stores blue slices side by side, so pixel (b * 32 + r, g) holds the entry for
+    // (r, g, b). The 3D image wants red to vary fastest, then green, then blue.
+    let mut data = Vec::with_capacity((COLOR_LUT_SIZE * COLOR_LUT_SIZE * COLOR_LUT_SIZE) as usize);
+    for b in 0..COLOR_LUT_SIZE {
+        for g in 0..COLOR_LUT_SIZE {
+            for r in 0..COLOR_LUT_SIZE {
+                let x = b * COLOR_LUT_SIZE + r;
+                let offset = g as usize * info.line_size + x as usize * channels;
+                let pixel = &pixels[offset..offset + 3];
+                data.push([pixel[0], pixel[1], pixel[2], 255]);
+            }
+        }
+    }
+    Ok(data)
+}
+
+fn identity_channel(index: u32) -> u8 {
+    (index * 255 / (COLOR_LUT_SIZE - 1)) as u8
+}
@@ -0,0 +1,195 @@
+use crate::config::DEFAULT_OBJECT_MAX_COUNT;
+use crate::renderer::culling::{frustum_cull, frustum_planes};
+use crate::renderer::uniform::{ObjectDrawCall, ObjectInstance};
+use crate::renderer::util::Dev;
+use crate::renderer::{MeshObject, Renderer, RendererSettings};
+use crate::world::World;
+use ash::vk;
+use log::warn;
+use nalgebra::Vector3;
+
+// Objects are drawn one by one, each with the first instance pointing at its entry in the object
+// list, so that a single vertex shader works for any mesh. The draw commands come from the draw
+// call buffer, where culling zeroes the instance count of objects outside the frustum.
+pub struct ObjectDraw {
+    mesh: usize,
+    instance: u32,
+}
+
+impl Renderer {
+    // The object list is split into one region per frame in flight, like the debug vertices. With
+    // GPU culling disabled, objects are culled here instead, so that the two can be compared.
+    pub(super) fn upload_objects(&mut self, world: &World, settings: &RendererSettings) {
+        let mut instances = Vec::new();
+        let mut placement = Vec::new();
+        for object in &world.objects {
+            let mesh = &self.mesh_objects[object.mesh];
+            let instance = ObjectInstance {
+                model: object.transform.model_matrix(),
+                color: object.color.component_mul(&mesh.base_color),
+            };
+            let transparent = mesh.is_transparent || instance.color.w < 1.;
+            let radius = mesh.bounding_radius * object.transform.max_scale();
+            let bounds = object.transform.translation.push(radius);
+            instances.push((object.mesh, instance, bounds));
+            placement.push((object.transform.translation, transparent));
+        }
+        let (order, opaque_count) = draw_order(&placement, world.camera.position());
+        let count = order.len();
+        if count > DEFAULT_OBJECT_MAX_COUNT {
+            warn!(
+                "too many objects, \x1B[1mcount\x1B[0m: {count}, \x1B[1mmax\x1B[0m: {DEFAULT_OBJECT_MAX_COUNT}"
+            );
+        }
+        let planes = frustum_planes(self.projection_matrix(settings) * world.view_matrix());
+        let region_start = DEFAULT_OBJECT_MAX_COUNT * self.flight_index;
+        let region = region_start..region_start + DEFAULT_OBJECT_MAX_COUNT;
+        let objects = &mut self.objects.mapped()[region.clone()];
+        let draw_calls = &mut self.object_draw_calls.mapped()[region];
+        let mut visible_count = 0;
+        self.object_draws.clear();
+        let slots = objects.iter_mut().zip(draw_calls);
+        for (index, ((object_slot, draw_call_slot), &object)) in slots.zip(&order).enumerate() {
+            let (mesh, instance, bounds) = &instances[object];
+            let visible = settings.gpu_culling || !frustum_cull(&planes, *bounds);
+            visible_count += visible as u32;
+            let first_instance = (region_start + index) as u32;
+            object_slot.write(*instance);
+            draw_call_slot.write(ObjectDrawCall {
+                bounds: *bounds,
+                command: vk::DrawIndexedIndirectCommand {
+                    index_count: 3 * self.mesh_objects[*mesh].triangle_count as u32,
+                    instance_count: visible as u32,
+                    first_index: 0,
+                    vertex_offset: 0,
+                    first_instance,
+                },
+            });
+            self.object_draws.push(ObjectDraw {
+                mesh: *mesh,
+                instance: first_instance,
+            });
+        }
+        self.opaque_object_count = opaque_count.min(DEFAULT_OBJECT_MAX_COUNT);
+        // The compute shader only adds the objects it doesn't cull, so it starts from zero.
+        let count = if settings.gpu_culling {
+            0
+        } else {
+            visible_count
+        };
+        self.visible_object_counts.mapped()[self.flight_index].write(count);
+    }
+
+    pub(super) fn record_transparent_pass(
+        &self,
+        buf: vk::CommandBuffer,
+        settings: &RendererSettings,
+    ) {
+        let draws = &self.object_draws[self.opaque_object_count..];
+        self.passes.transparent.begin_transparent(
+            buf,
+            &self.color,
+            &self.depth,
+            self.render_extent,
+            &self.dev,
+        );
+        if settings.passes.transparent {
+            unsafe {
+                self.dev.cmd_bind_pipeline(
+                    buf,
+                    vk::PipelineBindPoint::GRAPHICS,
+                    self.pipelines.transparent_object,
+                );
+                self.dev.cmd_bind_descriptor_sets(
+                    buf,
+                    vk::PipelineBindPoint::GRAPHICS,
+                    self.pipeline_layout,
+                    0,
+                    &[self.descriptor_sets[self.flight_index]],
+                    &[],
+                );
+            }
+            for draw in draws {
+                draw.record(
+                    &self.mesh_objects,
+                    self.object_draw_calls.handle(),
+                    buf,
+                    &self.dev,
+                );
+            }
+            // Particles are blended additively, which doesn't depend on the order, so they aren't
+            // sorted together with the objects and are simply drawn over them.
+            self.record_particles_draw(buf);
+        }
+        self.passes.transparent.end(buf, &self.dev);
+    }
+}
+
+// Opaque objects come first in any order, followed by the transparent ones sorted back-to-front, as
+// blending is order-dependent and the nearest surface has to be blended last. Sorting by the object
+// origin is wrong for intersecting or large objects, but good enough for small ones. Returns the
+// indices of the objects in drawing order, and how many of them are opaque.
+fn draw_order(objects: &[(Vector3<f32>, bool)], camera: Vector3<f32>) -> (Vec<usize>, usize) {
+    let (mut transparent, opaque): (Vec<usize>, Vec<usize>) =
+        (0..objects.len()).partition(|&index| objects[index].1);
+    let distance = |index: usize| (objects[index].0 - camera).norm_squared();
+    transparent.sort_by(|&a, &b| distance(b).total_cmp(&distance(a)));
+    let opaque_count = opaque.len();
+    let mut order = opaque;
+    order.extend(transparent);
+    (order, opaque_count)
+}
+
+impl ObjectDraw {
+    pub fn record(
+        &self,
+        mesh_objects: &[MeshObject],
+        draw_calls: vk::Buffer,
+        buf: vk::CommandBuffer,
+        dev: &Dev,
+    ) {
+        let mesh = &mesh_objects[self.mesh];
+        mesh.bind_vertex(buf, dev);
+        mesh.draw_call(draw_calls, self.instance as usize, buf, dev);
+    }
+
+    // Objects outside the camera frustum can still cast shadows into it, so shadow maps draw all of
+    // them directly.
+    pub fn record_unculled(&self, mesh_objects: &[MeshObject], buf: vk::CommandBuffer, dev: &Dev) {
+        let mesh = &mesh_objects[self.mesh];
+        mesh.bind_vertex(buf, dev);
+        mesh.draw_instance(self.instance, buf, dev);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn transparent_in_front_of_opaque() {
+        // A semi-transparent cube between the camera and an opaque one only composites correctly if
+        // the opaque cube is already in the color and depth attachments when it's blended.
+        let objects = [
+            (Vector3::new(0., 5., 0.), true),
+            (Vector3::new(0., 10., 0.), false),
+        ];
+        let (order, opaque_count) = draw_order(&objects, Vector3::zeros());
+        assert_eq!(order, [1, 0]);
+        assert_eq!(opaque_count, 1);
+    }
+
+    #[test]
+    fn transparent_back_to_front() {
+        let objects = [
+            (Vector3::new(0., 2., 0.), true),
+            (Vector3::new(0., 0., 0.), false),
+            (Vector3::new(0., -8., 0.), true),
+            (Vector3::new(4., 0., 0.), true),
+            (Vector3::new(0., 1., 0.), false),
+        ];
+        let (order, opaque_count) = draw_order(&objects, Vector3::zeros());
+        assert_eq!(order, [1, 4, 2, 3, 0]);
+        assert_eq!(opaque_count, 2);
+    }
+}
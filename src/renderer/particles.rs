@@ -0,0 +1,106 @@
+use crate::renderer::codegen::dispatch_particles_update;
+use crate::renderer::debug::{begin_label, end_label};
+use crate::renderer::util::Dev;
+use crate::renderer::Renderer;
+use ash::vk;
+use std::mem::{offset_of, size_of};
+
+impl Renderer {
+    // The destination region is refilled from scratch every frame, so its count has to be reset
+    // before the update appends anything. Both the reset and the update have to wait for the
+    // previous frame, which drew from the region now being overwritten and read the one now being
+    // read from.
+    pub(super) fn record_particles_update(&self, buf: vk::CommandBuffer) {
+        begin_label(buf, "Particle simulation", [255, 183, 77], &self.dev);
+        let destination = self.particles.destination();
+        let draw_commands = self.particles.draw_commands().handle();
+        record_buffer_barrier(
+            buf,
+            vk::PipelineStageFlags2::COMPUTE_SHADER
+                | vk::PipelineStageFlags2::DRAW_INDIRECT
+                | vk::PipelineStageFlags2::VERTEX_SHADER,
+            vk::AccessFlags2::SHADER_STORAGE_WRITE,
+            vk::PipelineStageFlags2::CLEAR | vk::PipelineStageFlags2::COMPUTE_SHADER,
+            vk::AccessFlags2::TRANSFER_WRITE
+                | vk::AccessFlags2::SHADER_STORAGE_READ
+                | vk::AccessFlags2::SHADER_STORAGE_WRITE,
+            &self.dev,
+        );
+        unsafe {
+            self.dev.cmd_fill_buffer(
+                buf,
+                draw_commands,
+                (destination * size_of::<vk::DrawIndirectCommand>()
+                    + offset_of!(vk::DrawIndirectCommand, instance_count))
+                    as vk::DeviceSize,
+                size_of::<u32>() as vk::DeviceSize,
+                0,
+            );
+        }
+        record_buffer_barrier(
+            buf,
+            vk::PipelineStageFlags2::CLEAR,
+            vk::AccessFlags2::TRANSFER_WRITE,
+            vk::PipelineStageFlags2::COMPUTE_SHADER,
+            vk::AccessFlags2::SHADER_STORAGE_READ | vk::AccessFlags2::SHADER_STORAGE_WRITE,
+            &self.dev,
+        );
+
+        self.bind_compute_pipeline(buf, self.pipelines.particles_update);
+        unsafe {
+            self.dev.cmd_bind_descriptor_sets(
+                buf,
+                vk::PipelineBindPoint::COMPUTE,
+                self.pipeline_layout,
+                0,
+                &[self.descriptor_sets[self.flight_index]],
+                &[],
+            );
+        }
+        dispatch_particles_update(
+            buf,
+            [self.particles.dispatch_size() as u32, 1, 1],
+            &self.dev,
+        );
+        end_label(buf, &self.dev);
+    }
+
+    // Has to be recorded inside the transparent pass, with its descriptor set already bound.
+    pub(super) fn record_particles_draw(&self, buf: vk::CommandBuffer) {
+        unsafe {
+            self.dev.cmd_bind_pipeline(
+                buf,
+                vk::PipelineBindPoint::GRAPHICS,
+                self.pipelines.particles_draw,
+            );
+            self.dev.cmd_draw_indirect(
+                buf,
+                self.particles.draw_commands().handle(),
+                (self.particles.destination() * size_of::<vk::DrawIndirectCommand>())
+                    as vk::DeviceSize,
+                1,
+                0,
+            );
+        }
+    }
+}
+
+// The render graph only tracks images, and renderer.kdl can only declare the barrier after a
+// dispatch, so the ones before the particle update have to be recorded by hand.
+fn record_buffer_barrier(
+    buf: vk::CommandBuffer,
+    src_stage_mask: vk::PipelineStageFlags2,
+    src_access_mask: vk::AccessFlags2,
+    dst_stage_mask: vk::PipelineStageFlags2,
+    dst_access_mask: vk::AccessFlags2,
+    dev: &Dev,
+) {
+    let barrier = vk::MemoryBarrier2::default()
+        .src_stage_mask(src_stage_mask)
+        .src_access_mask(src_access_mask)
+        .dst_stage_mask(dst_stage_mask)
+        .dst_access_mask(dst_access_mask);
+    let dependency_info =
+        vk::DependencyInfo::default().memory_barriers(std::array::from_ref(&barrier));
+    unsafe { dev.cmd_pipeline_barrier2(buf, &dependency_info) };
+}
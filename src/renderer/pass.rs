@@ -8,11 +8,13 @@ pub struct Pass {
 }
 
 impl Pass {
+    // The depth is cleared, unless it was already drawn by the depth pre-pass.
     pub fn begin(
         &self,
         buf: vk::CommandBuffer,
         color: &ImageResources,
         depth: &ImageResources,
+        depth_load_op: vk::AttachmentLoadOp,
         extent: vk::Extent2D,
         dev: &Dev,
     ) {
@@ -31,12 +33,15 @@ impl Pass {
         let depth_attachment_info = vk::RenderingAttachmentInfo::default()
             .image_view(depth.view)
             .image_layout(vk::ImageLayout::DEPTH_ATTACHMENT_OPTIMAL)
-            .load_op(vk::AttachmentLoadOp::CLEAR)
-            .store_op(vk::AttachmentStoreOp::DONT_CARE)
+            .load_op(depth_load_op)
+            .store_op(vk::AttachmentStoreOp::STORE)
             .clear_value(vk::ClearValue {
                 depth_stencil: vk::ClearDepthStencilValue::default().depth(1.),
             });
+        // Draws are recorded into secondary command buffers in parallel, so the primary one can
+        // only execute them while inside the pass.
         let rendering_info = vk::RenderingInfo::default()
+            .flags(vk::RenderingFlags::CONTENTS_SECONDARY_COMMAND_BUFFERS)
             .render_area(vk::Rect2D {
                 offset: vk::Offset2D { x: 0, y: 0 },
                 extent,
@@ -47,6 +52,163 @@ impl Pass {
         unsafe { dev.cmd_begin_rendering(buf, &rendering_info) };
     }
 
+    // Draws over the results of the forward pass, testing against its depth without writing to it.
+    // Recorded directly into the primary command buffer, as transparent draws have to be in order
+    // anyway and there are few of them.
+    pub fn begin_transparent(
+        &self,
+        buf: vk::CommandBuffer,
+        color: &ImageResources,
+        depth: &ImageResources,
+        extent: vk::Extent2D,
+        dev: &Dev,
+    ) {
+        begin_label(buf, self.debug_name, self.debug_color, dev);
+
+        let color_attachment_info = vk::RenderingAttachmentInfo::default()
+            .image_view(color.view)
+            .image_layout(vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL)
+            .load_op(vk::AttachmentLoadOp::LOAD)
+            .store_op(vk::AttachmentStoreOp::STORE);
+        let depth_attachment_info = vk::RenderingAttachmentInfo::default()
+            .image_view(depth.view)
+            .image_layout(vk::ImageLayout::DEPTH_READ_ONLY_OPTIMAL)
+            .load_op(vk::AttachmentLoadOp::LOAD)
+            .store_op(vk::AttachmentStoreOp::NONE);
+        let rendering_info = vk::RenderingInfo::default()
+            .render_area(vk::Rect2D {
+                offset: vk::Offset2D { x: 0, y: 0 },
+                extent,
+            })
+            .color_attachments(std::array::from_ref(&color_attachment_info))
+            .layer_count(1)
+            .depth_attachment(&depth_attachment_info);
+        unsafe { dev.cmd_begin_rendering(buf, &rendering_info) };
+    }
+
+    // Renders only into a depth image, like shadow maps. These have their own resolution unrelated
+    // to the window size, so pipelines used in such passes have dynamic viewports which are set
+    // here to cover the whole image.
+    pub fn begin_depth_only(
+        &self,
+        buf: vk::CommandBuffer,
+        depth: &ImageResources,
+        extent: vk::Extent2D,
+        dev: &Dev,
+    ) {
+        begin_label(buf, self.debug_name, self.debug_color, dev);
+
+        let depth_attachment_info = vk::RenderingAttachmentInfo::default()
+            .image_view(depth.view)
+            .image_layout(vk::ImageLayout::DEPTH_ATTACHMENT_OPTIMAL)
+            .load_op(vk::AttachmentLoadOp::CLEAR)
+            .store_op(vk::AttachmentStoreOp::STORE)
+            .clear_value(vk::ClearValue {
+                depth_stencil: vk::ClearDepthStencilValue::default().depth(1.),
+            });
+        let render_area = vk::Rect2D {
+            offset: vk::Offset2D { x: 0, y: 0 },
+            extent,
+        };
+        let rendering_info = vk::RenderingInfo::default()
+            .render_area(render_area)
+            .layer_count(1)
+            .depth_attachment(&depth_attachment_info);
+        let viewport = vk::Viewport {
+            x: 0.,
+            y: 0.,
+            width: extent.width as f32,
+            height: extent.height as f32,
+            min_depth: 0.,
+            max_depth: 1.,
+        };
+        unsafe { dev.cmd_begin_rendering(buf, &rendering_info) };
+        unsafe { dev.cmd_set_viewport(buf, 0, &[viewport]) };
+        unsafe { dev.cmd_set_scissor(buf, 0, &[render_area]) };
+    }
+
+    // Used for fullscreen passes like bloom, which render into images of different sizes using the
+    // same pipelines, so the viewport is set here rather than baked in.
+    pub fn begin_color_only(
+        &self,
+        buf: vk::CommandBuffer,
+        color: &ImageResources,
+        extent: vk::Extent2D,
+        load_op: vk::AttachmentLoadOp,
+        dev: &Dev,
+    ) {
+        self.begin_color_view(buf, color.view, extent, load_op, dev);
+    }
+
+    // Same as above, but for views not owned by an ImageResources, like single faces of a cubemap.
+    pub fn begin_color_view(
+        &self,
+        buf: vk::CommandBuffer,
+        view: vk::ImageView,
+        extent: vk::Extent2D,
+        load_op: vk::AttachmentLoadOp,
+        dev: &Dev,
+    ) {
+        begin_label(buf, self.debug_name, self.debug_color, dev);
+
+        let color_attachment_info = vk::RenderingAttachmentInfo::default()
+            .image_view(view)
+            .image_layout(vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL)
+            .load_op(load_op)
+            .store_op(vk::AttachmentStoreOp::STORE)
+            .clear_value(vk::ClearValue {
+                color: vk::ClearColorValue {
+                    float32: [0., 0., 0., 0.],
+                },
+            });
+        let render_area = vk::Rect2D {
+            offset: vk::Offset2D { x: 0, y: 0 },
+            extent,
+        };
+        let rendering_info = vk::RenderingInfo::default()
+            .render_area(render_area)
+            .color_attachments(std::array::from_ref(&color_attachment_info))
+            .layer_count(1);
+        let viewport = vk::Viewport {
+            x: 0.,
+            y: 0.,
+            width: extent.width as f32,
+            height: extent.height as f32,
+            min_depth: 0.,
+            max_depth: 1.,
+        };
+        unsafe { dev.cmd_begin_rendering(buf, &rendering_info) };
+        unsafe { dev.cmd_set_viewport(buf, 0, &[viewport]) };
+        unsafe { dev.cmd_set_scissor(buf, 0, &[render_area]) };
+    }
+
+    // Draws on top of existing image contents without a depth buffer. Used for the interface,
+    // which is drawn at native resolution after the scene was already upscaled.
+    #[cfg(feature = "dev-menu")]
+    pub fn begin_overlay(
+        &self,
+        buf: vk::CommandBuffer,
+        color: &ImageResources,
+        extent: vk::Extent2D,
+        dev: &Dev,
+    ) {
+        begin_label(buf, self.debug_name, self.debug_color, dev);
+
+        let color_attachment_info = vk::RenderingAttachmentInfo::default()
+            .image_view(color.view)
+            .image_layout(vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL)
+            .load_op(vk::AttachmentLoadOp::LOAD)
+            .store_op(vk::AttachmentStoreOp::STORE);
+        let rendering_info = vk::RenderingInfo::default()
+            .render_area(vk::Rect2D {
+                offset: vk::Offset2D { x: 0, y: 0 },
+                extent,
+            })
+            .color_attachments(std::array::from_ref(&color_attachment_info))
+            .layer_count(1);
+        unsafe { dev.cmd_begin_rendering(buf, &rendering_info) };
+    }
+
     pub fn end(&self, buf: vk::CommandBuffer, dev: &Dev) {
         unsafe { dev.cmd_end_rendering(buf) };
         end_label(buf, dev);
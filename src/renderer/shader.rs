@@ -1,40 +1,80 @@
+use crate::assets::resolve_asset;
+use ash::vk;
 use log::{debug, error};
 use shaderc::{EnvVersion, Limit, ResolvedInclude, ShaderKind, TargetEnv};
 
-pub fn compile_glsl(glsl_path: &str, shader_kind: ShaderKind) -> Vec<u32> {
+// Shaders are compiled when the renderer is created, where there's no way to continue without them.
+pub fn compile_glsl_or_exit(glsl_path: &str, shader_kind: ShaderKind) -> Vec<u32> {
+    compile_glsl(glsl_path, shader_kind).unwrap_or_else(|error| {
+        log_shader_error(&error);
+        std::process::exit(1)
+    })
+}
+
+// The error is the diagnostic output of shaderc, or the reason the source couldn't be read. It is
+// returned rather than logged so that reloading shaders at runtime can keep the old ones running.
+pub fn compile_glsl(glsl_path: &str, shader_kind: ShaderKind) -> Result<Vec<u32>, String> {
     let compiler = shaderc::Compiler::new().unwrap();
     let mut options = shaderc::CompileOptions::new().unwrap();
     options.set_target_env(TargetEnv::Vulkan, EnvVersion::Vulkan1_3 as u32);
     options.set_include_callback(|path, _, _, _| {
+        let content = read_source(&format!("shaders/{path}"))?;
         Ok(ResolvedInclude {
             resolved_name: path.to_owned(),
-            content: std::fs::read_to_string(format!("shaders/{}", path)).unwrap(),
+            content,
         })
     });
     options.set_limit(Limit::MaxMeshWorkGroupSizeXExt, 256);
-    let glsl_text = std::fs::read_to_string(glsl_path).unwrap();
-    let compile_result =
-        compiler.compile_into_spirv(&glsl_text, shader_kind, glsl_path, "main", Some(&options));
-    let spirv_data = match compile_result {
-        Err(shaderc::Error::CompilationError(_, output)) => {
-            // TODO: Some error messages (debugPrintfEXT w/o extension decl) are multi-line.
-            for message in output.trim().split('\n') {
-                let (file, message) = message.split_once(':').unwrap();
-                let (line, message) = message.split_once(':').unwrap();
-                let message = message.strip_prefix(" error: '").unwrap();
-                let (token, message) = message.split_once("' :").unwrap();
-                let token_format = if token.is_empty() {
-                    String::new()
-                } else {
-                    format!("{token}, ")
-                };
-                let message = message.trim();
-                error!("shader compilation error, {token_format}{message}, \x1B[1mfile\x1B[0m: {file}, \x1B[1mline\x1B[0m: {line}");
-            }
-            std::process::exit(1);
-        }
-        result => result.unwrap(),
-    };
+    let glsl_text = read_source(glsl_path)?;
+    let spirv_data = compiler
+        .compile_into_spirv(&glsl_text, shader_kind, glsl_path, "main", Some(&options))
+        .map_err(|error| match error {
+            shaderc::Error::CompilationError(_, output) => output,
+            error => error.to_string(),
+        })?;
     debug!("shader GLSL compiled, \x1B[1mfile\x1B[0m: {glsl_path}");
-    spirv_data.as_binary().to_owned()
+    Ok(spirv_data.as_binary().to_owned())
+}
+
+pub fn log_shader_error(error: &str) {
+    // TODO: Some error messages (debugPrintfEXT w/o extension decl) are multi-line.
+    for message in error.trim().split('\n') {
+        log_compilation_error(message);
+    }
+}
+
+// Pipeline creation shouldn't fail once the shaders compiled, but if the driver rejects one, naming
+// it is a lot more useful than a null handle crashing somewhere in command recording.
+pub fn check_pipeline(result: vk::Result, pipeline: &str) {
+    if result != vk::Result::SUCCESS {
+        error!("pipeline creation failed, \x1B[1mpipeline\x1B[0m: {pipeline}, \x1B[1mresult\x1B[0m: {result}");
+        std::process::exit(1);
+    }
+}
+
+// Editors can replace files while saving them, so sources can be missing for a moment while shaders
+// are being reloaded.
+fn read_source(path: &str) -> Result<String, String> {
+    let resolved = resolve_asset(path).map_err(|error| error.to_string())?;
+    std::fs::read_to_string(resolved).map_err(|error| format!("failed to read {path}: {error}"))
+}
+
+// Messages not in the usual format, like failed includes, are logged as they are.
+fn log_compilation_error(message: &str) {
+    let parsed = message.split_once(':').and_then(|(file, message)| {
+        let (line, message) = message.split_once(':')?;
+        let message = message.strip_prefix(" error: '")?;
+        let (token, message) = message.split_once("' :")?;
+        Some((file, line, token, message.trim()))
+    });
+    let Some((file, line, token, message)) = parsed else {
+        error!("shader compilation error, {message}");
+        return;
+    };
+    let token_format = if token.is_empty() {
+        String::new()
+    } else {
+        format!("{token}, ")
+    };
+    error!("shader compilation error, {token_format}{message}, \x1B[1mfile\x1B[0m: {file}, \x1B[1mline\x1B[0m: {line}");
 }
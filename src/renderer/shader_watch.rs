@@ -0,0 +1,136 @@
+use crate::assets::asset_path;
+use crate::renderer::codegen::{recompile_shader, Shaders, RUNTIME_SHADERS};
+use crate::renderer::Renderer;
+use log::{info, warn};
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime};
+
+// Walking the directory every frame would show up in frame times, and saving a file takes longer
+// than this anyway.
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+// Watches the shaders directory for changes by polling modification times, which is cheap enough
+// for a few dozen files that it doesn't need a separate thread.
+pub struct ShaderWatcher {
+    // Pipelines are created from all shaders at once, so the SPIR-V of the unchanged ones is kept
+    // around instead of compiling everything again.
+    pub shaders: Shaders,
+    // Resolved once, as resolving a missing asset exits, and files can go missing while saved.
+    directory: PathBuf,
+    modified: HashMap<PathBuf, SystemTime>,
+    last_poll: Instant,
+}
+
+impl ShaderWatcher {
+    pub fn new(shaders: Shaders) -> ShaderWatcher {
+        let directory = asset_path("shaders");
+        let mut modified = HashMap::new();
+        scan_directory(&directory, &mut modified);
+        ShaderWatcher {
+            shaders,
+            directory,
+            modified,
+            last_poll: Instant::now(),
+        }
+    }
+
+    // Returns the runtime shaders affected by files changed since the last poll, including changes
+    // to the files they include.
+    fn poll(&mut self) -> Vec<&'static str> {
+        if self.last_poll.elapsed() < POLL_INTERVAL {
+            return Vec::new();
+        }
+        self.last_poll = Instant::now();
+        let mut modified = HashMap::new();
+        scan_directory(&self.directory, &mut modified);
+        let changed: HashSet<&PathBuf> = modified
+            .iter()
+            .filter(|(path, time)| self.modified.get(*path) != Some(*time))
+            .map(|(path, _)| path)
+            .collect();
+        let affected = if changed.is_empty() {
+            Vec::new()
+        } else {
+            RUNTIME_SHADERS
+                .iter()
+                .copied()
+                .filter(|shader| {
+                    let relative = Path::new(shader).strip_prefix("shaders").unwrap();
+                    let mut dependencies = HashSet::new();
+                    collect_dependencies(
+                        &self.directory.join(relative),
+                        &self.directory,
+                        &mut dependencies,
+                    );
+                    dependencies.iter().any(|path| changed.contains(path))
+                })
+                .collect()
+        };
+        self.modified = modified;
+        affected
+    }
+}
+
+impl Renderer {
+    // Called at the start of every frame. The pipelines are only recreated if every affected shader
+    // compiled, so a typo leaves the old ones running until it's fixed.
+    pub(super) fn reload_changed_shaders(&mut self) {
+        let Some(watcher) = &mut self.shader_watcher else {
+            return;
+        };
+        let changed = watcher.poll();
+        if changed.is_empty() {
+            return;
+        }
+        let mut compiled = true;
+        for path in &changed {
+            compiled &= recompile_shader(&mut watcher.shaders, path, &self.dev.support);
+        }
+        if !compiled {
+            warn!("shader reload failed, keeping old pipelines");
+            return;
+        }
+        self.recreate_pipelines();
+        info!(
+            "shaders reloaded, \x1B[1mfiles\x1B[0m: {}",
+            changed.join(", ")
+        );
+    }
+}
+
+// Editors often save by replacing the file, so files can be missing for a moment. These are just
+// skipped, and picked up by the next poll.
+fn scan_directory(directory: &Path, modified: &mut HashMap<PathBuf, SystemTime>) {
+    let Ok(entries) = std::fs::read_dir(directory) else {
+        return;
+    };
+    for entry in entries.flatten() {
+        let Ok(metadata) = entry.metadata() else {
+            continue;
+        };
+        if metadata.is_dir() {
+            scan_directory(&entry.path(), modified);
+        } else if let Ok(time) = metadata.modified() {
+            modified.insert(entry.path(), time);
+        }
+    }
+}
+
+// Same as the dependency tracking in the build script, include paths are resolved relative to the
+// shaders directory.
+fn collect_dependencies(path: &Path, directory: &Path, dependencies: &mut HashSet<PathBuf>) {
+    if !dependencies.insert(path.to_owned()) {
+        return;
+    }
+    let Ok(source) = std::fs::read_to_string(path) else {
+        return;
+    };
+    for line in source.lines() {
+        let Some(include) = line.trim().strip_prefix("#include") else {
+            continue;
+        };
+        let include = include.trim().trim_matches('"');
+        collect_dependencies(&directory.join(include), directory, dependencies);
+    }
+}
@@ -0,0 +1,164 @@
+use crate::renderer::debug::{begin_label, end_label};
+use crate::renderer::uniform::{Shadow, ShadowCascade, SHADOW_CASCADE_MAX_COUNT};
+use crate::renderer::{Renderer, RendererSettings, VoxelRendering};
+use crate::world::World;
+use ash::vk;
+use nalgebra::{Matrix4, Point3, Vector3};
+use std::f32::consts::FRAC_PI_4;
+use std::sync::atomic::Ordering;
+
+// Meshlets are dispatched as a 2D grid, as only 65535 workgroups are guaranteed to be supported in
+// a single dimension, and there can be a lot more meshlets than that. Has to match the constant in
+// voxel_shadow.mesh.
+const MESHLET_GRID_WIDTH: u32 = 1024;
+
+impl Renderer {
+    // Voxels and opaque objects cast shadows. The sun is the light source itself, stars are far
+    // enough away that they would only waste the shadow map resolution, and transparent objects
+    // would need partial shadows. The pass still runs with shadows disabled, so that the shadow map
+    // is always in the layout the descriptor set expects.
+    pub(super) fn record_shadow_pass(&self, buf: vk::CommandBuffer, settings: &RendererSettings) {
+        let extent = vk::Extent2D {
+            width: self.shadow_resolution,
+            height: self.shadow_resolution,
+        };
+        self.passes
+            .shadow
+            .begin_depth_only(buf, &self.shadow_map, extent, &self.dev);
+        if !settings.shadows.enable || !settings.passes.shadow {
+            self.passes.shadow.end(buf, &self.dev);
+            return;
+        }
+        unsafe {
+            self.dev.cmd_bind_descriptor_sets(
+                buf,
+                vk::PipelineBindPoint::GRAPHICS,
+                self.pipeline_layout,
+                0,
+                &[self.descriptor_sets[self.flight_index]],
+                &[],
+            );
+        }
+        let meshlet_count = self.voxel_meshlet_count.load(Ordering::SeqCst);
+        if settings.voxel_rendering == VoxelRendering::MeshShaders && meshlet_count > 0 {
+            begin_label(buf, "Voxel shadow draws", [255, 0, 0], &self.dev);
+            unsafe {
+                self.dev.cmd_bind_pipeline(
+                    buf,
+                    vk::PipelineBindPoint::GRAPHICS,
+                    self.pipelines.voxel_shadow,
+                );
+                self.dev.mesh_ext.cmd_draw_mesh_tasks(
+                    buf,
+                    meshlet_count.min(MESHLET_GRID_WIDTH),
+                    meshlet_count.div_ceil(MESHLET_GRID_WIDTH),
+                    1,
+                );
+            }
+            end_label(buf, &self.dev);
+        }
+        let object_draws = &self.object_draws[..self.opaque_object_count];
+        if !object_draws.is_empty() {
+            begin_label(buf, "Object shadow draws", [205, 133, 63], &self.dev);
+            unsafe {
+                self.dev.cmd_bind_pipeline(
+                    buf,
+                    vk::PipelineBindPoint::GRAPHICS,
+                    self.pipelines.object_shadow,
+                );
+            }
+            for draw in object_draws {
+                draw.record_unculled(&self.mesh_objects, buf, &self.dev);
+            }
+            end_label(buf, &self.dev);
+        }
+        self.passes.shadow.end(buf, &self.dev);
+    }
+
+    pub(super) fn shadow_uniform(&self, world: &World, settings: &RendererSettings) -> Shadow {
+        let unused_cascade = ShadowCascade {
+            view_projection: Matrix4::identity(),
+            split_distance: 0.,
+            _pad0: [0.; 3],
+        };
+        let mut cascades = [unused_cascade; SHADOW_CASCADE_MAX_COUNT];
+        cascades[0] = ShadowCascade {
+            view_projection: self.cascade_view_projection(
+                world,
+                settings.depth_near,
+                settings.shadows.distance,
+            ),
+            split_distance: settings.shadows.distance,
+            _pad0: [0.; 3],
+        };
+        Shadow {
+            cascades,
+            cascade_count: 1,
+            texel_size: 1. / self.shadow_resolution as f32,
+            bias: settings.shadows.bias,
+            enable: settings.shadows.enable,
+            _pad0: [0; 3],
+        }
+    }
+
+    // Fits an orthographic projection looking from the sun around the part of the camera frustum
+    // between the given distances. The sun is close enough that its light isn't really parallel,
+    // but treating it as a directional light is a good approximation for the small area covered.
+    fn cascade_view_projection(&self, world: &World, near: f32, far: f32) -> Matrix4<f32> {
+        let aspect_ratio = self.swapchain.extent.width as f32 / self.swapchain.extent.height as f32;
+        let camera_projection = Matrix4::new_perspective(aspect_ratio, FRAC_PI_4, near, far);
+        let inverse_camera = (camera_projection * world.view_matrix())
+            .try_inverse()
+            .unwrap();
+        let mut corners = Vec::new();
+        for x in [-1., 1.] {
+            for y in [-1., 1.] {
+                for z in [-1., 1.] {
+                    corners.push(inverse_camera.transform_point(&Point3::new(x, y, z)).coords);
+                }
+            }
+        }
+        let center = corners.iter().sum::<Vector3<f32>>() / corners.len() as f32;
+
+        // Using a bounding sphere rather than a tight box keeps the projection size constant when
+        // the camera rotates. Together with moving the projection only in whole texel increments,
+        // this stops shadow edges from shimmering when the camera moves.
+        let radius = corners
+            .iter()
+            .map(|corner| (corner - center).norm())
+            .fold(0., f32::max)
+            .ceil();
+        let light_direction = world.sun_direction();
+        let up = if light_direction.z.abs() > 0.99 {
+            Vector3::x()
+        } else {
+            Vector3::z()
+        };
+        let light_rotation =
+            Matrix4::look_at_rh(&Point3::from(light_direction), &Point3::origin(), &up);
+        let texel_size = 2. * radius / self.shadow_resolution as f32;
+        let mut center_light_space = light_rotation.transform_point(&Point3::from(center));
+        center_light_space.x = (center_light_space.x / texel_size).floor() * texel_size;
+        center_light_space.y = (center_light_space.y / texel_size).floor() * texel_size;
+        let center = light_rotation
+            .try_inverse()
+            .unwrap()
+            .transform_point(&center_light_space);
+
+        // Terrain outside of the sphere can still cast shadows into it, so the near plane is moved
+        // towards the sun to include it. Depth precision isn't a concern with a linear depth
+        // buffer and such a short range.
+        let caster_range = 2. * radius;
+        let depth_range = 2. * radius + caster_range;
+        let eye = center + light_direction * (radius + caster_range);
+        let light_view = Matrix4::look_at_rh(&eye, &center, &up);
+        #[rustfmt::skip]
+        let light_projection = Matrix4::new(
+            1. / radius, 0., 0., 0.,
+            0., -1. / radius, 0., 0.,
+            0., 0., -1. / depth_range, 0.,
+            0., 0., 0., 1.,
+        );
+        light_projection * light_view
+    }
+}
@@ -0,0 +1,255 @@
+use crate::renderer::codegen::{
+    dispatch_sky_irradiance, push_sky_capture_constants_typed, SkyCapturePushConstants,
+};
+use crate::renderer::debug::{begin_label, end_label};
+use crate::renderer::uniform::Ambient;
+use crate::renderer::util::{Dev, ImageResources};
+use crate::renderer::{Renderer, RendererSettings};
+use ash::vk;
+use nalgebra::Vector3;
+
+// The capture only feeds the irradiance, which has no sharp detail whatsoever, so both can be tiny.
+const SKY_CAPTURE_SIZE: u32 = 64;
+const SKY_IRRADIANCE_SIZE: u32 = 16;
+
+const SKY_FORMAT: vk::Format = vk::Format::R16G16B16A16_SFLOAT;
+
+// Angle the sun has to move by before the sky is captured again. With the default day length that's
+// every third of a second or so, which is plenty for a change as gradual as a sunset.
+const SKY_RECAPTURE_ANGLE: f32 = 2. * std::f32::consts::PI / 180.;
+
+// The sky is rendered into a cubemap one face per frame, so that the capture doesn't show up as a
+// spike in frame times, and the irradiance is convolved from it in the same frame as the last face.
+// Until then, shading keeps using the irradiance of the previous capture.
+pub struct SkyLighting {
+    pub capture: ImageResources,
+    // Views of the individual faces, as a cube view can't be rendered into.
+    face_views: [vk::ImageView; 6],
+    pub irradiance: ImageResources,
+    schedule: SkySchedule,
+}
+
+// Everything the captured sky depends on, other than the sky color which only changes with the sun
+// and the skybox texture which invalidates the capture when reloaded.
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct SkyKey {
+    sun_direction: Vector3<f32>,
+    atmosphere: bool,
+}
+
+#[derive(Default)]
+struct SkySchedule {
+    // Sky of the capture in progress, or of the last finished one.
+    captured: Option<SkyKey>,
+    // Face rendered next, if a capture is in progress.
+    next_face: Option<usize>,
+    // Whether the irradiance holds a finished capture yet.
+    irradiance_valid: bool,
+}
+
+// Work recorded in a single frame.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct SkyWork {
+    pub face: Option<usize>,
+    pub convolve: bool,
+}
+
+impl SkyLighting {
+    pub fn create(dev: &Dev) -> SkyLighting {
+        let capture = ImageResources::create_cubemap(
+            SKY_FORMAT,
+            vk::ImageUsageFlags::COLOR_ATTACHMENT | vk::ImageUsageFlags::SAMPLED,
+            SKY_CAPTURE_SIZE,
+            dev,
+        );
+        let face_views = std::array::from_fn(|face| {
+            let view_info = vk::ImageViewCreateInfo::default()
+                .image(capture.image)
+                .view_type(vk::ImageViewType::TYPE_2D)
+                .format(SKY_FORMAT)
+                .subresource_range(
+                    vk::ImageSubresourceRange::default()
+                        .aspect_mask(vk::ImageAspectFlags::COLOR)
+                        .level_count(1)
+                        .base_array_layer(face as u32)
+                        .layer_count(1),
+                );
+            unsafe { dev.create_image_view(&view_info, None) }.unwrap()
+        });
+        let irradiance = ImageResources::create_cubemap(
+            SKY_FORMAT,
+            vk::ImageUsageFlags::STORAGE | vk::ImageUsageFlags::SAMPLED,
+            SKY_IRRADIANCE_SIZE,
+            dev,
+        );
+        SkyLighting {
+            capture,
+            face_views,
+            irradiance,
+            schedule: SkySchedule::default(),
+        }
+    }
+
+    // Makes the next frame start a new capture, even if the sun didn't move. Used when the sky
+    // changes in a way the schedule doesn't track, like loading a different skybox.
+    pub fn invalidate(&mut self) {
+        self.schedule.captured = None;
+    }
+
+    pub fn cleanup(&self, dev: &Dev) {
+        for view in self.face_views {
+            unsafe { dev.destroy_image_view(view, None) };
+        }
+        self.capture.cleanup(dev);
+        self.irradiance.cleanup(dev);
+    }
+}
+
+impl SkySchedule {
+    fn advance(&mut self, key: SkyKey) -> SkyWork {
+        if self.next_face.is_none() && self.captured.is_none_or(|captured| is_stale(captured, key))
+        {
+            self.captured = Some(key);
+            self.next_face = Some(0);
+        }
+        let Some(face) = self.next_face else {
+            return SkyWork::default();
+        };
+        let convolve = face == 5;
+        self.next_face = (!convolve).then_some(face + 1);
+        self.irradiance_valid |= convolve;
+        SkyWork {
+            face: Some(face),
+            convolve,
+        }
+    }
+}
+
+fn is_stale(captured: SkyKey, current: SkyKey) -> bool {
+    captured.atmosphere != current.atmosphere
+        || captured.sun_direction.dot(&current.sun_direction) < SKY_RECAPTURE_ANGLE.cos()
+}
+
+impl Renderer {
+    // Called once per frame before recording. Nothing is captured while the ambient term is
+    // disabled, and the capture resumes where it left off once it's enabled again.
+    pub(super) fn schedule_sky_capture(
+        &mut self,
+        sun_direction: Vector3<f32>,
+        settings: &RendererSettings,
+    ) -> SkyWork {
+        if !settings.sky_lighting.enabled {
+            return SkyWork::default();
+        }
+        self.sky.schedule.advance(SkyKey {
+            sun_direction,
+            atmosphere: settings.enable_atmosphere,
+        })
+    }
+
+    pub(super) fn ambient_uniform(&self, settings: &RendererSettings) -> Ambient {
+        Ambient {
+            enable: settings.sky_lighting.enabled && self.sky.schedule.irradiance_valid,
+            _pad0: [0; 3],
+            intensity: settings.sky_lighting.intensity,
+        }
+    }
+
+    // Every texel of the face is overwritten, so its previous contents aren't loaded.
+    pub(super) fn record_sky_capture(&self, buf: vk::CommandBuffer, face: usize) {
+        let extent = vk::Extent2D {
+            width: SKY_CAPTURE_SIZE,
+            height: SKY_CAPTURE_SIZE,
+        };
+        let pass = &self.passes.sky_capture;
+        pass.begin_color_view(
+            buf,
+            self.sky.face_views[face],
+            extent,
+            vk::AttachmentLoadOp::DONT_CARE,
+            &self.dev,
+        );
+        unsafe {
+            self.dev.cmd_bind_pipeline(
+                buf,
+                vk::PipelineBindPoint::GRAPHICS,
+                self.pipelines.sky_capture,
+            );
+            self.dev.cmd_bind_descriptor_sets(
+                buf,
+                vk::PipelineBindPoint::GRAPHICS,
+                self.pipeline_layout,
+                0,
+                &[self.descriptor_sets[self.flight_index]],
+                &[],
+            );
+        }
+        let constants = SkyCapturePushConstants { face: face as u32 };
+        push_sky_capture_constants_typed(buf, self.pipeline_layout, &constants, &self.dev);
+        unsafe { self.dev.cmd_draw(buf, 6, 1, 0, 0) };
+        pass.end(buf, &self.dev);
+    }
+
+    pub(super) fn record_sky_irradiance(&self, buf: vk::CommandBuffer) {
+        begin_label(buf, "Sky irradiance", [79, 195, 247], &self.dev);
+        self.bind_compute_pipeline(buf, self.pipelines.sky_irradiance);
+        unsafe {
+            self.dev.cmd_bind_descriptor_sets(
+                buf,
+                vk::PipelineBindPoint::COMPUTE,
+                self.pipeline_layout,
+                0,
+                &[self.descriptor_sets[self.flight_index]],
+                &[],
+            );
+        }
+        dispatch_sky_irradiance(
+            buf,
+            [SKY_IRRADIANCE_SIZE, SKY_IRRADIANCE_SIZE, 6],
+            &self.dev,
+        );
+        end_label(buf, &self.dev);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(angle: f32) -> SkyKey {
+        SkyKey {
+            sun_direction: Vector3::new(angle.sin(), 0., angle.cos()),
+            atmosphere: true,
+        }
+    }
+
+    #[test]
+    fn capture_spread_over_frames() {
+        let mut schedule = SkySchedule::default();
+        for face in 0..6 {
+            let work = schedule.advance(key(0.));
+            assert_eq!(work.face, Some(face));
+            assert_eq!(work.convolve, face == 5);
+            assert_eq!(schedule.irradiance_valid, face == 5);
+        }
+        assert_eq!(schedule.advance(key(0.)), SkyWork::default());
+        // Small movements of the sun don't trigger a new capture, larger ones do.
+        assert_eq!(schedule.advance(key(0.01)), SkyWork::default());
+        assert_eq!(schedule.advance(key(0.1)).face, Some(0));
+        // The capture in progress is finished before the sun is checked again.
+        assert_eq!(schedule.advance(key(1.)).face, Some(1));
+    }
+
+    #[test]
+    fn atmosphere_toggle_recaptures() {
+        let mut schedule = SkySchedule::default();
+        for _ in 0..6 {
+            schedule.advance(key(0.));
+        }
+        let without_atmosphere = SkyKey {
+            atmosphere: false,
+            ..key(0.)
+        };
+        assert_eq!(schedule.advance(without_atmosphere).face, Some(0));
+    }
+}
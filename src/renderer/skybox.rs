@@ -0,0 +1,134 @@
+use crate::assets::resolve_asset;
+use crate::renderer::codegen::update_skybox_descriptor;
+use crate::renderer::util::{Ctx, ImageResources, StagingPool};
+use crate::renderer::Renderer;
+use ash::vk;
+use log::warn;
+use std::fs::File;
+use std::path::{Path, PathBuf};
+
+// Sky textures are authored in sRGB like any other color texture, so they are converted to linear
+// when sampled, before being lit and tonemapped like the rest of the scene.
+const SKYBOX_FORMAT: vk::Format = vk::Format::R8G8B8A8_SRGB;
+
+// File names of the faces in the skybox directory, in the order of cubemap layers. These follow the
+// usual Y-up convention of skybox assets, with py being the top of the sky.
+const SKYBOX_FACES: [&str; 6] = ["px", "nx", "py", "ny", "pz", "nz"];
+
+pub struct SkyboxData {
+    pub size: u32,
+    // All faces one after another, in the order of cubemap layers.
+    pub pixels: Vec<[u8; 4]>,
+}
+
+impl Renderer {
+    // Same as with the color lookup table, only the descriptor refers to the image, so it's enough
+    // to wait for the device and swap it.
+    pub(super) fn reload_skybox(&mut self, path: Option<PathBuf>) {
+        unsafe { self.dev.device_wait_idle() }.unwrap();
+        let data = path.as_deref().and_then(load_skybox);
+        self.skybox_loaded = data.is_some();
+        let data = data.unwrap_or_else(placeholder_skybox);
+        let ctx = Ctx {
+            dev: &self.dev,
+            queue: self.queue,
+            command_pool: self.command_pools[0],
+        };
+        let skybox = create_skybox(&data, &mut self.staging, &ctx);
+        self.skybox.cleanup(&self.dev);
+        self.skybox = skybox;
+        self.skybox_path = path;
+        update_skybox_descriptor(&self.descriptor_sets, self.skybox.view, &self.dev);
+        self.sky.invalidate();
+    }
+}
+
+pub fn create_skybox(data: &SkyboxData, staging: &mut StagingPool, ctx: &Ctx) -> ImageResources {
+    let skybox = ImageResources::create_cubemap(
+        SKYBOX_FORMAT,
+        vk::ImageUsageFlags::SAMPLED | vk::ImageUsageFlags::TRANSFER_DST,
+        data.size,
+        ctx.dev,
+    );
+    let extent = vk::Extent3D {
+        width: data.size,
+        height: data.size,
+        depth: 1,
+    };
+    ctx.execute(|buf| staging.upload_image(buf, &skybox, extent, 6, &data.pixels, 0, ctx.dev));
+    skybox
+}
+
+// The descriptor has to point at a valid cubemap even when no texture is configured, in which case
+// the shaders fall back to the plain sky color and never sample it.
+pub fn placeholder_skybox() -> SkyboxData {
+    SkyboxData {
+        size: 1,
+        pixels: vec![[0, 0, 0, 255]; 6],
+    }
+}
+
+// Falls back to the plain sky color on errors, as the path can be typed in from the settings menu.
+fn load_skybox(path: &Path) -> Option<SkyboxData> {
+    match read_skybox(path) {
+        Ok(data) => Some(data),
+        Err(error) => {
+            warn!(
+                "failed to load skybox, \x1B[1mpath\x1B[0m: {}, \x1B[1merror\x1B[0m: {error}",
+                path.display()
+            );
+            None
+        }
+    }
+}
+
+// Resolved the same way as color lookup tables.
+fn read_skybox(directory: &Path) -> Result<SkyboxData, String> {
+    let directory = resolve_asset(directory).map_err(|error| error.to_string())?;
+    let mut size = None;
+    let mut pixels = Vec::new();
+    for face in SKYBOX_FACES {
+        let path = directory.join(format!("{face}.png"));
+        let (face_size, face_pixels) =
+            read_face(&path).map_err(|error| format!("{}: {error}", path.display()))?;
+        if *size.get_or_insert(face_size) != face_size {
+            return Err(format!("{face} face has a different size than the others"));
+        }
+        pixels.extend(face_pixels);
+    }
+    Ok(SkyboxData {
+        size: size.unwrap(),
+        pixels,
+    })
+}
+
+fn read_face(path: &Path) -> Result<(u32, Vec<[u8; 4]>), String> {
+    let file = File::open(path).map_err(|error| error.to_string())?;
+    let mut decoder = png::Decoder::new(file);
+    decoder.set_transformations(png::Transformations::normalize_to_color8());
+    let mut reader = decoder.read_info().map_err(|error| error.to_string())?;
+    let mut buffer = vec![0; reader.output_buffer_size()];
+    let info = reader
+        .next_frame(&mut buffer)
+        .map_err(|error| error.to_string())?;
+    if info.width != info.height {
+        return Err(format!(
+            "face is not square, got {}x{}",
+            info.width, info.height
+        ));
+    }
+    let channels = match info.color_type {
+        png::ColorType::Rgb => 3,
+        png::ColorType::Rgba => 4,
+        color_type => return Err(format!("unsupported color type {color_type:?}")),
+    };
+    let mut pixels = Vec::with_capacity((info.width * info.height) as usize);
+    for y in 0..info.height as usize {
+        let row = &buffer[y * info.line_size..];
+        for x in 0..info.width as usize {
+            let pixel = &row[x * channels..x * channels + 3];
+            pixels.push([pixel[0], pixel[1], pixel[2], 255]);
+        }
+    }
+    Ok((info.width, pixels))
+}
@@ -0,0 +1,30 @@
+use crate::renderer::{Renderer, RendererSettings};
+use ash::vk;
+
+impl Renderer {
+    // Occlusion is cleared to zero when disabled, but postprocessing doesn't sample it then, as the
+    // intensity it's specialized with is zero as well.
+    pub(super) fn record_ssao(&self, buf: vk::CommandBuffer, settings: &RendererSettings) {
+        self.record_fullscreen(
+            buf,
+            &self.passes.ssao,
+            self.pipelines.ssao,
+            &self.ssao_images[0],
+            self.render_extent,
+            vk::AttachmentLoadOp::CLEAR,
+            settings.ssao.enabled,
+        );
+    }
+
+    pub(super) fn record_ssao_blur(&self, buf: vk::CommandBuffer, settings: &RendererSettings) {
+        self.record_fullscreen(
+            buf,
+            &self.passes.ssao,
+            self.pipelines.ssao_blur,
+            &self.ssao_images[1],
+            self.render_extent,
+            vk::AttachmentLoadOp::CLEAR,
+            settings.ssao.enabled,
+        );
+    }
+}
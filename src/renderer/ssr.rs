@@ -0,0 +1,46 @@
+use crate::renderer::codegen::dispatch_hiz;
+use crate::renderer::debug::{begin_label, end_label};
+use crate::renderer::{Renderer, RendererSettings};
+use ash::vk;
+
+impl Renderer {
+    // Nothing reads the Hi-Z buffer when reflections are disabled, as the SSR pass then only clears
+    // its output, so the dispatch is skipped as well.
+    pub(super) fn record_hiz(&self, buf: vk::CommandBuffer, settings: &RendererSettings) {
+        if !settings.ssr.enabled {
+            return;
+        }
+        begin_label(buf, "Hi-Z", [120, 144, 156], &self.dev);
+        self.bind_compute_pipeline(buf, self.pipelines.hiz);
+        unsafe {
+            self.dev.cmd_bind_descriptor_sets(
+                buf,
+                vk::PipelineBindPoint::COMPUTE,
+                self.pipeline_layout,
+                0,
+                &[self.descriptor_sets[self.flight_index]],
+                &[],
+            );
+        }
+        dispatch_hiz(
+            buf,
+            [self.ssr_extent.width, self.ssr_extent.height, 1],
+            &self.dev,
+        );
+        end_label(buf, &self.dev);
+    }
+
+    // Reflections are traced at half the render resolution, the same as the Hi-Z buffer, and
+    // blended over the scene by the postprocess pass.
+    pub(super) fn record_ssr(&self, buf: vk::CommandBuffer, settings: &RendererSettings) {
+        self.record_fullscreen(
+            buf,
+            &self.passes.ssr,
+            self.pipelines.ssr,
+            &self.ssr_image,
+            self.ssr_extent,
+            vk::AttachmentLoadOp::CLEAR,
+            settings.ssr.enabled,
+        );
+    }
+}
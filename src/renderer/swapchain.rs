@@ -1,30 +1,50 @@
-use crate::renderer::util::{create_image_view, Dev, ImageResources};
+use crate::renderer::util::{create_image_view, Dev, ImageResources, MemoryTag};
+use crate::renderer::PresentMode;
 use ash::khr::swapchain;
 use ash::vk;
+use log::warn;
+use std::cell::Cell;
 use winit::dpi::PhysicalSize;
 
+// Rendering without a window goes through the same struct, with images owned by the renderer in
+// place of the presentable ones, so that the passes don't need to know the difference.
 pub struct Swapchain {
-    pub handle: vk::SwapchainKHR,
+    // None when rendering offscreen, in which case there's nothing to acquire from or present to.
+    pub handle: Option<vk::SwapchainKHR>,
     pub format: vk::SurfaceFormatKHR,
     pub extent: vk::Extent2D,
+    // Mode actually used, which can differ from the requested one if it's not supported.
+    pub present_mode: vk::PresentModeKHR,
     pub images: Vec<ImageResources>,
 }
 
 impl Swapchain {
     pub fn cleanup(&self, dev: &Dev) {
+        let Some(handle) = self.handle else {
+            for image in &self.images {
+                image.cleanup(dev);
+            }
+            return;
+        };
         let swapchain_ext = swapchain::Device::new(&dev.instance, dev);
         for image in &self.images {
             unsafe { dev.destroy_image_view(image.view, None) };
         }
-        unsafe { swapchain_ext.destroy_swapchain(self.handle, None) };
+        unsafe { swapchain_ext.destroy_swapchain(handle, None) };
     }
 }
 
+// Creates offscreen images of the requested size when there's no surface.
 pub fn create_swapchain(
-    surface: vk::SurfaceKHR,
+    surface: Option<vk::SurfaceKHR>,
     window_size: PhysicalSize<u32>,
+    present_mode: PresentMode,
+    frames_in_flight: usize,
     dev: &Dev,
 ) -> Swapchain {
+    let Some(surface) = surface else {
+        return create_offscreen(window_size, frames_in_flight, dev);
+    };
     let capabilities = unsafe {
         dev.surface_ext
             .get_physical_device_surface_capabilities(dev.physical, surface)
@@ -37,28 +57,81 @@ pub fn create_swapchain(
         }
         .unwrap()
     };
-    let image_count = select_image_count(capabilities);
+    let present_modes = unsafe {
+        dev.surface_ext
+            .get_physical_device_surface_present_modes(dev.physical, surface)
+    }
+    .unwrap();
+    let image_count = select_image_count(capabilities, frames_in_flight);
     let format = select_format(&formats);
     let extent = select_extent(capabilities, window_size);
-    let handle = create_handle(surface, image_count, format, extent, capabilities, dev);
+    let present_mode = select_present_mode(&present_modes, present_mode);
+    let handle = create_handle(
+        surface,
+        image_count,
+        format,
+        extent,
+        present_mode,
+        capabilities,
+        dev,
+    );
     let images = create_pseudo_image_resources(handle, format.format, dev);
     Swapchain {
-        handle,
+        handle: Some(handle),
         format,
         extent,
+        present_mode,
         images,
     }
 }
 
-fn select_image_count(capabilities: vk::SurfaceCapabilitiesKHR) -> usize {
+// Every frame in flight gets its own image, so one is never rendered into while an earlier frame
+// is still being copied out of it. The format matches what a surface would usually pick.
+fn create_offscreen(size: PhysicalSize<u32>, frames_in_flight: usize, dev: &Dev) -> Swapchain {
+    let format = vk::SurfaceFormatKHR {
+        format: vk::Format::R8G8B8A8_SRGB,
+        color_space: vk::ColorSpaceKHR::SRGB_NONLINEAR,
+    };
+    let extent = vk::Extent2D {
+        width: size.width,
+        height: size.height,
+    };
+    let images = (0..frames_in_flight)
+        .map(|_| {
+            ImageResources::create(
+                format.format,
+                vk::MemoryPropertyFlags::DEVICE_LOCAL,
+                vk::ImageTiling::OPTIMAL,
+                vk::ImageUsageFlags::COLOR_ATTACHMENT | vk::ImageUsageFlags::TRANSFER_SRC,
+                vk::ImageAspectFlags::COLOR,
+                extent,
+                vk::SampleCountFlags::TYPE_1,
+                MemoryTag::Attachment,
+                dev,
+            )
+        })
+        .collect();
+    Swapchain {
+        handle: None,
+        format,
+        extent,
+        present_mode: vk::PresentModeKHR::FIFO,
+        images,
+    }
+}
+
+fn select_image_count(capabilities: vk::SurfaceCapabilitiesKHR, frames_in_flight: usize) -> usize {
     // Use triple buffering, even if the platform allows to only use double buffering. The Vulkan
     // tutorial recommends setting this to min_image_count + 1 to prevent waiting for the image due
     // to driver overhead, but I think that after triple buffering, adding more images shouldn't be
     // able to fix any internal driver problems. It's also not covered by the Khronos
     // recommendation.
     // https://github.com/KhronosGroup/Vulkan-Samples
+    // Running more frames in flight than that needs an image for each of them, plus the one being
+    // shown.
     let no_image_limit = capabilities.max_image_count == 0;
-    let preferred_image_count = capabilities.min_image_count.max(3) as usize;
+    let preferred_image_count =
+        (capabilities.min_image_count.max(3) as usize).max(frames_in_flight + 1);
     if no_image_limit {
         preferred_image_count
     } else {
@@ -86,6 +159,22 @@ fn select_format(formats: &[vk::SurfaceFormatKHR]) -> vk::SurfaceFormatKHR {
     panic!("surface doesn't support SRGB color space with a desired format");
 }
 
+// FIFO is the only mode required to be supported by the Vulkan specification, so it's always safe to
+// fall back to.
+fn select_present_mode(
+    present_modes: &[vk::PresentModeKHR],
+    requested: PresentMode,
+) -> vk::PresentModeKHR {
+    let requested = requested.vk();
+    if present_modes.contains(&requested) {
+        return requested;
+    }
+    warn!(
+        "present mode not supported, falling back to FIFO, \x1B[1mrequested\x1B[0m: {requested:?}"
+    );
+    vk::PresentModeKHR::FIFO
+}
+
 fn select_extent(
     capabilities: vk::SurfaceCapabilitiesKHR,
     window_size: PhysicalSize<u32>,
@@ -110,6 +199,7 @@ fn create_handle(
     image_count: usize,
     format: vk::SurfaceFormatKHR,
     extent: vk::Extent2D,
+    present_mode: vk::PresentModeKHR,
     capabilities: vk::SurfaceCapabilitiesKHR,
     dev: &Dev,
 ) -> vk::SwapchainKHR {
@@ -120,11 +210,13 @@ fn create_handle(
         .image_color_space(format.color_space)
         .image_extent(extent)
         .image_array_layers(1)
-        .image_usage(vk::ImageUsageFlags::COLOR_ATTACHMENT)
+        // Frames are copied out of the swapchain images directly when recording video, so that the
+        // recording includes the interface and matches what's on screen exactly.
+        .image_usage(vk::ImageUsageFlags::COLOR_ATTACHMENT | vk::ImageUsageFlags::TRANSFER_SRC)
         .image_sharing_mode(vk::SharingMode::EXCLUSIVE)
         .pre_transform(capabilities.current_transform)
         .composite_alpha(vk::CompositeAlphaFlagsKHR::OPAQUE)
-        .present_mode(vk::PresentModeKHR::FIFO)
+        .present_mode(present_mode)
         .clipped(true)
         .old_swapchain(vk::SwapchainKHR::null());
     unsafe { dev.swapchain_ext.create_swapchain(&create_info, None) }.unwrap()
@@ -141,8 +233,9 @@ fn create_pseudo_image_resources(
         let view = create_image_view(image, format, vk::ImageAspectFlags::COLOR, dev);
         image_views.push(ImageResources {
             image,
-            memory: vk::DeviceMemory::null(),
+            allocation: None,
             view,
+            current_layout: Cell::new(vk::ImageLayout::UNDEFINED),
         });
     }
     image_views
@@ -0,0 +1,125 @@
+use crate::renderer::uniform::Taa;
+use crate::renderer::util::ImageResources;
+use crate::renderer::{Renderer, RendererSettings};
+use crate::world::World;
+use ash::vk;
+use nalgebra::{Matrix4, Vector2};
+
+// Long enough to cover the pixel evenly, while short enough for the history to see the whole
+// sequence before older frames fade out of it.
+const JITTER_SEQUENCE_LENGTH: usize = 8;
+
+impl Renderer {
+    pub(super) fn record_taa_resolve(&self, buf: vk::CommandBuffer, history: &ImageResources) {
+        self.record_fullscreen(
+            buf,
+            &self.passes.taa,
+            self.pipelines.taa_resolve,
+            history,
+            self.render_extent,
+            vk::AttachmentLoadOp::CLEAR,
+            true,
+        );
+    }
+
+    // Every pixel of the scene is overwritten, so its previous contents aren't loaded.
+    pub(super) fn record_taa_composite(&self, buf: vk::CommandBuffer) {
+        self.record_fullscreen(
+            buf,
+            &self.passes.taa,
+            self.pipelines.taa_composite,
+            &self.color,
+            self.render_extent,
+            vk::AttachmentLoadOp::DONT_CARE,
+            true,
+        );
+    }
+
+    // Offsets the projection by a different subpixel amount every frame, so that over a few frames
+    // the history sees each pixel sampled at several positions. Culling keeps using the unjittered
+    // matrix, as the offset is far too small to matter there.
+    pub(super) fn jittered_projection_matrix(&self, settings: &RendererSettings) -> Matrix4<f32> {
+        let mut projection = self.projection_matrix(settings);
+        if settings.taa.enabled {
+            let jitter = jitter_offset(self.frame_index, self.render_extent);
+            // Clip space w is the negated view space depth, so this shifts the whole image by the
+            // offset after the perspective divide.
+            projection[(0, 2)] -= jitter.x;
+            projection[(1, 2)] -= jitter.y;
+        }
+        projection
+    }
+
+    // Called once per frame, as it also remembers this frame's camera for reprojecting the next
+    // one.
+    pub(super) fn taa_uniform(&mut self, world: &World, settings: &RendererSettings) -> Taa {
+        let taa = Taa {
+            previous_view_projection: self.previous_view_projection,
+            history_index: (self.frame_index % 2) as u32,
+            history_weight: settings.taa.history_weight,
+            history_valid: self.taa_history_valid,
+            _pad0: [0; 3],
+            debug_view: settings.taa.debug_view,
+            _pad1: [0; 3],
+        };
+        self.previous_view_projection = self.projection_matrix(settings) * world.view_matrix();
+        self.taa_history_valid = self.taa_history.is_some();
+        taa
+    }
+}
+
+// Offset in normalized device coordinates, within half a pixel in each direction. Uses the Halton
+// sequence in bases 2 and 3, which covers the pixel evenly for any prefix of the sequence. The
+// first element is skipped, as it's zero in every base.
+fn jitter_offset(frame_index: usize, extent: vk::Extent2D) -> Vector2<f32> {
+    let index = frame_index % JITTER_SEQUENCE_LENGTH + 1;
+    Vector2::new(
+        (halton(index, 2) - 0.5) * 2. / extent.width as f32,
+        (halton(index, 3) - 0.5) * 2. / extent.height as f32,
+    )
+}
+
+fn halton(mut index: usize, base: usize) -> f32 {
+    let mut result = 0.;
+    let mut fraction = 1.;
+    while index > 0 {
+        fraction /= base as f32;
+        result += fraction * (index % base) as f32;
+        index /= base;
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn halton_sequence() {
+        let base2: Vec<_> = (1..=4).map(|index| halton(index, 2)).collect();
+        assert_eq!(base2, [0.5, 0.25, 0.75, 0.125]);
+        let base3: Vec<_> = (1..=4).map(|index| halton(index, 3)).collect();
+        let expected = [1. / 3., 2. / 3., 1. / 9., 4. / 9.];
+        for (value, expected) in base3.iter().zip(expected) {
+            assert!((value - expected).abs() < 1e-6);
+        }
+    }
+
+    #[test]
+    fn jitter_stays_within_pixel() {
+        let extent = vk::Extent2D {
+            width: 1920,
+            height: 1080,
+        };
+        let offsets: Vec<_> = (0..JITTER_SEQUENCE_LENGTH)
+            .map(|frame| jitter_offset(frame, extent))
+            .collect();
+        for offset in &offsets {
+            assert!(offset.x.abs() <= 1. / 1920.);
+            assert!(offset.y.abs() <= 1. / 1080.);
+        }
+        // The sequence repeats, but never stands still between consecutive frames.
+        assert_eq!(jitter_offset(JITTER_SEQUENCE_LENGTH, extent), offsets[0]);
+        assert!(offsets.windows(2).all(|pair| pair[0] != pair[1]));
+    }
+}
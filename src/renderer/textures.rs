@@ -0,0 +1,74 @@
+use crate::renderer::util::{Ctx, ImageResources, MemoryTag};
+use crate::renderer::Renderer;
+use crate::voxel::material::VoxelMaterialId;
+use ash::vk;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+// Set in the flags of materials that have a texture, with its index in the lowest 16 bits. Has to
+// match the constant in util/textures.glsl.
+pub const MATERIAL_TEXTURED: u32 = 1 << 16;
+
+const GRAIN_SIZE: u32 = 64;
+
+// Voxel materials sampling a texture through the bindless array, which multiplies their albedo.
+const MATERIAL_TEXTURES: [(VoxelMaterialId, &str); 1] = [(VoxelMaterialId::SAND, "sand_grain")];
+
+impl Renderer {
+    // There are no texture assets yet, so the only texture is generated at startup.
+    pub(super) fn create_material_textures(&mut self) {
+        let ctx = Ctx {
+            dev: &self.dev,
+            queue: self.queue,
+            command_pool: self.command_pools[0],
+        };
+        let grain = ImageResources::create(
+            vk::Format::R8G8B8A8_UNORM,
+            vk::MemoryPropertyFlags::DEVICE_LOCAL,
+            vk::ImageTiling::OPTIMAL,
+            vk::ImageUsageFlags::SAMPLED | vk::ImageUsageFlags::TRANSFER_DST,
+            vk::ImageAspectFlags::COLOR,
+            vk::Extent2D {
+                width: GRAIN_SIZE,
+                height: GRAIN_SIZE,
+            },
+            vk::SampleCountFlags::TYPE_1,
+            MemoryTag::Texture,
+            &self.dev,
+        );
+        let extent = vk::Extent3D {
+            width: GRAIN_SIZE,
+            height: GRAIN_SIZE,
+            depth: 1,
+        };
+        let pixels = grain_pixels();
+        let staging = &mut self.staging;
+        ctx.execute(|buf| staging.upload_image(buf, &grain, extent, 1, &pixels, 0, ctx.dev));
+        self.register_texture("sand_grain", grain.view);
+        self.textures.push(grain);
+    }
+
+    pub(super) fn material_flags(&self, material: VoxelMaterialId) -> u32 {
+        MATERIAL_TEXTURES
+            .iter()
+            .find(|(textured, _)| *textured == material)
+            .and_then(|(_, name)| self.texture_handle(name))
+            .map_or(0, |handle| MATERIAL_TEXTURED | handle)
+    }
+}
+
+// Mostly white with darker specks, so that multiplying the albedo by it keeps the material's color
+// while breaking up large flat areas.
+fn grain_pixels() -> Vec<[u8; 4]> {
+    let mut rng = StdRng::seed_from_u64(0);
+    (0..GRAIN_SIZE * GRAIN_SIZE)
+        .map(|_| {
+            let value = if rng.gen_bool(0.1) {
+                rng.gen_range(150..200)
+            } else {
+                rng.gen_range(225..=255)
+            };
+            [value, value, value, 255]
+        })
+        .collect()
+}
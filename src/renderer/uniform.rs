@@ -1,16 +1,26 @@
 #[cfg(feature = "dev-menu")]
 use crate::interface::EnumInterface;
-use nalgebra::{Matrix4, Vector2, Vector3};
+use ash::vk;
+use nalgebra::{Matrix4, Vector2, Vector3, Vector4};
+use serde::{Deserialize, Serialize};
 
 #[repr(C)]
 #[derive(Clone, Copy)]
 pub struct Global {
     pub voxels: Voxels,
     pub light: Light,
+    pub lights: [PointLight; POINT_LIGHT_MAX_COUNT],
+    pub light_count: u32,
+    pub shadow: Shadow,
     pub atmosphere: Atmosphere,
+    pub ambient: Ambient,
     pub postprocessing: PostprocessUniform,
+    pub ssr: Ssr,
+    pub dof: Dof,
+    pub taa: Taa,
+    pub particles: ParticlesUniform,
     pub camera: Camera,
-    pub materials: [VoxelMaterial; 256],
+    pub culling: Culling,
     pub debug: Debug,
 }
 
@@ -22,6 +32,10 @@ pub struct Voxels {
     pub root_svo_index: u32,
     pub root_svo_side: u32,
     pub root_svo_base: Vector3<u32>,
+    pub ambient_occlusion_strength: f32,
+    // Start of this frame's region of the material palette.
+    pub first_material: u32,
+    pub active_session: u32,
 }
 
 #[repr(C, align(16))]
@@ -33,21 +47,73 @@ pub struct Light {
     pub scale: f32,
 }
 
+// Point lights are passed as a fixed-size array in the global uniform, which is plenty for placing
+// lamps around and keeps the lighting a simple loop in the fragment shaders. More would be better
+// served by a storage buffer with some kind of culling.
+pub const POINT_LIGHT_MAX_COUNT: usize = 64;
+
+#[repr(C, align(16))]
+#[derive(Clone, Copy, Debug)]
+pub struct PointLight {
+    pub position: Vector3<f32>,
+    // Distance at which the light fades out completely, so that it only affects nearby surfaces.
+    pub radius: f32,
+    pub color: Vector3<f32>,
+    pub intensity: f32,
+}
+
+// Only a single cascade is rendered for now, but the layout already allows for more so that adding
+// them later only requires changes on the CPU side and in the cascade selection in shaders.
+pub const SHADOW_CASCADE_MAX_COUNT: usize = 4;
+
+#[repr(C, align(16))]
+#[derive(Clone, Copy)]
+pub struct Shadow {
+    pub cascades: [ShadowCascade; SHADOW_CASCADE_MAX_COUNT],
+    pub cascade_count: u32,
+    pub texel_size: f32,
+    pub bias: f32,
+    pub enable: bool,
+    pub _pad0: [u8; 3],
+}
+
+#[repr(C, align(16))]
+#[derive(Clone, Copy)]
+pub struct ShadowCascade {
+    pub view_projection: Matrix4<f32>,
+    // Distance from the camera up to which this cascade is used.
+    pub split_distance: f32,
+    pub _pad0: [f32; 3],
+}
+
 #[repr(C, align(16))]
 #[derive(Clone, Copy)]
 pub struct Atmosphere {
     pub enable: bool,
     pub _pad0: [u8; 3],
-    pub scatter_point_count: u32,
-    pub optical_depth_point_count: u32,
     pub density_falloff: f32,
+    pub _pad1: [f32; 2],
     pub planet_position: Vector3<f32>,
     pub planet_radius: f32,
-    pub sun_position: Vector3<f32>,
+    pub sun_direction: Vector3<f32>,
     pub scale: f32,
     pub wavelengths: Vector3<f32>,
     pub scattering_strength: f32,
     pub henyey_greenstein_g: f32,
+    pub _pad2: [f32; 3],
+    // Used by the skybox when the atmosphere is disabled, unless a skybox texture is loaded.
+    pub sky_color: Vector3<f32>,
+    pub skybox_texture: bool,
+    pub _pad3: [u8; 3],
+}
+
+#[repr(C, align(16))]
+#[derive(Clone, Copy)]
+pub struct Ambient {
+    // Only set once the sky irradiance holds a finished capture, there's no ambient term before.
+    pub enable: bool,
+    pub _pad0: [u8; 3],
+    pub intensity: f32,
 }
 
 #[repr(C, align(4))]
@@ -56,6 +122,56 @@ pub struct PostprocessUniform {
     pub exposure: f32,
     pub tonemapper: Tonemapper,
     pub gamma: f32,
+    pub bloom_threshold: f32,
+    pub bloom_knee: f32,
+    pub bloom_intensity: f32,
+    pub time: f32,
+    // Of the swapchain rather than the render targets, as that's what postprocessing draws to.
+    pub aspect_ratio: f32,
+}
+
+#[repr(C, align(16))]
+#[derive(Clone, Copy)]
+pub struct ParticlesUniform {
+    pub source: u32,
+    pub emitted_offset: u32,
+    pub emitted_count: u32,
+    pub capacity: u32,
+    pub delta_time: f32,
+    pub gravity: f32,
+}
+
+#[repr(C, align(16))]
+#[derive(Clone, Copy)]
+pub struct Ssr {
+    pub step_size: f32,
+    pub thickness: f32,
+    pub fade_distance: f32,
+    pub debug_overlay: bool,
+    pub _pad0: [u8; 3],
+}
+
+#[repr(C, align(16))]
+#[derive(Clone, Copy)]
+pub struct Dof {
+    pub focus_distance: f32,
+    pub aperture: f32,
+    pub autofocus: bool,
+    pub _pad0: [u8; 3],
+}
+
+#[repr(C, align(16))]
+#[derive(Clone, Copy)]
+pub struct Taa {
+    // Without the jitter, as the history converges towards the unjittered image.
+    pub previous_view_projection: Matrix4<f32>,
+    // History image written this frame, with the other one holding the previous frame's.
+    pub history_index: u32,
+    pub history_weight: f32,
+    pub history_valid: bool,
+    pub _pad0: [u8; 3],
+    pub debug_view: bool,
+    pub _pad1: [u8; 3],
 }
 
 #[repr(C, align(16))]
@@ -75,11 +191,29 @@ pub struct Camera {
 
 #[repr(C, align(16))]
 #[derive(Clone, Copy)]
+pub struct Culling {
+    // Left, right, bottom, top, near and far planes, with normals pointing inside the frustum.
+    pub planes: [Vector4<f32>; 6],
+    pub star_count: u32,
+    pub object_count: u32,
+    // Start of this frame's region of the object draw calls.
+    pub first_object: u32,
+    pub flight_index: u32,
+    pub enable: bool,
+    pub _pad0: [u8; 3],
+}
+
+#[repr(C, align(16))]
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize)]
 pub struct VoxelMaterial {
     pub albedo: Vector3<f32>,
     pub roughness: f32,
     pub emit: Vector3<f32>,
     pub metallic: f32,
+    // Filled in by the renderer when uploading the palette, based on the textures it registered for
+    // each material, so it's always zero in the settings and isn't saved with them.
+    #[serde(skip)]
+    pub flags: u32,
 }
 
 #[repr(C, align(4))]
@@ -89,7 +223,7 @@ pub struct Debug {
 }
 
 #[repr(u32)]
-#[derive(Clone, Copy, Eq, PartialEq)]
+#[derive(Clone, Copy, Debug, Deserialize, Eq, PartialEq, Serialize)]
 #[allow(dead_code)]
 pub enum Tonemapper {
     RgbClamping = 0,
@@ -110,6 +244,31 @@ pub struct Star {
     pub model: Matrix4<f32>,
 }
 
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct ObjectInstance {
+    pub model: Matrix4<f32>,
+    pub color: Vector4<f32>,
+}
+
+// The draw command of a single object, along with its world space bounding sphere for culling. The
+// command is drawn straight from the buffer, so culling an object only zeroes its instance count.
+#[repr(C, align(16))]
+#[derive(Clone, Copy)]
+pub struct ObjectDrawCall {
+    pub bounds: Vector4<f32>,
+    pub command: vk::DrawIndexedIndirectCommand,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct DebugVertex {
+    pub position: Vector3<f32>,
+    pub _pad0: f32,
+    pub color: Vector3<f32>,
+    pub _pad1: f32,
+}
+
 #[cfg(feature = "dev-menu")]
 impl EnumInterface for Tonemapper {
     const VALUES: &'static [Self] = &[
@@ -1,12 +1,20 @@
-use crate::renderer::{DeviceSupport, FRAMES_IN_FLIGHT};
+use crate::renderer::DeviceSupport;
 use ash::ext::{debug_utils, mesh_shader};
 use ash::khr::{buffer_device_address, surface, swapchain};
 use ash::{vk, Device, Instance};
+use log::debug;
+use std::cell::Cell;
+use std::collections::VecDeque;
 use std::ffi::CStr;
+use std::marker::PhantomData;
 use std::mem::MaybeUninit;
 use std::ops::Deref;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
 use std::time::Duration;
 
+const GPU_ARENA_SIZE: u64 = 256 * 1024 * 1024;
+
 pub trait AsDescriptor {
     fn descriptor(&self, flight_index: usize) -> vk::DescriptorBufferInfo;
 }
@@ -28,24 +36,135 @@ pub struct Dev {
     pub swapchain_ext: swapchain::Device,
     pub mesh_ext: mesh_shader::Device,
     pub support: DeviceSupport,
+    pub allocator: Arc<GpuAllocator>,
+}
+
+// Vulkan implementations are allowed to limit the number of memory allocations to as little as
+// 4096, and allocating memory is slow anyway. So instead, memory is allocated in large arenas and
+// resources are placed inside them. Resources larger than an arena just get a dedicated one.
+pub struct GpuAllocator {
+    arenas: Mutex<Vec<GpuArena>>,
+    granularity: u64,
+    // Bytes currently allocated for each tag, indexed by the tag discriminant. Kept outside the
+    // arena lock, as these are read by the interface every frame.
+    tag_usage: [AtomicU64; MemoryTag::ALL.len()],
+    // Memory budget extension is optional, so without it only the heap sizes are known.
+    memory_budget: bool,
+}
+
+// What an allocation is used for, so that the interface can show where the memory goes. This is
+// just for bookkeeping, memory with different tags is still placed in the same arenas.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MemoryTag {
+    Mesh,
+    VoxelChunks,
+    Attachment,
+    Texture,
+    Uniform,
+    Storage,
+    Staging,
+    Readback,
+}
+
+// Fractions of the memory budget above which usage is worth attention. Crossing the critical one is
+// also logged, as running out of VRAM usually shows up as stutter rather than errors.
+#[cfg(feature = "dev-menu")]
+pub const MEMORY_BUDGET_WARNING: f64 = 0.75;
+pub const MEMORY_BUDGET_CRITICAL: f64 = 0.9;
+
+#[derive(Clone, Debug)]
+pub struct MemoryStats {
+    pub tags: Vec<(MemoryTag, u64)>,
+    pub heaps: Vec<HeapStats>,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct HeapStats {
+    pub device_local: bool,
+    pub size: u64,
+    // Memory allocated in arenas on this heap by us, including the free space inside the arenas.
+    pub allocated: u64,
+    // Free space inside the arenas, and the largest single block of it. A resource larger than the
+    // largest block needs a new arena even if the total free space would be enough.
+    pub free: u64,
+    pub largest_free_block: u64,
+    // Usage and budget as reported by the driver, which include other processes. Only available
+    // with VK_EXT_memory_budget.
+    pub usage: Option<u64>,
+    pub budget: Option<u64>,
+}
+
+struct GpuArena {
+    memory: vk::DeviceMemory,
+    memory_type_index: u32,
+    host_visible: bool,
+    device_address: bool,
+    size: u64,
+    // Host-visible arenas are mapped persistently, as Vulkan doesn't allow mapping the same memory
+    // object twice and there could be multiple resources inside.
+    mapping: *mut u8,
+    // Free blocks as (offset, size) pairs, sorted by offset with adjacent blocks always merged.
+    free: Vec<(u64, u64)>,
+}
+
+// Free space inside an arena, or summed over several of them. A resource larger than the largest
+// block needs a new arena even if the total free space would be enough.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+struct FreeSpace {
+    bytes: u64,
+    blocks: usize,
+    largest_block: u64,
+}
+
+pub struct GpuAllocation {
+    pub memory: vk::DeviceMemory,
+    pub offset: u64,
+    pub size: u64,
+    mapping: *mut u8,
+    arena: usize,
+    tag: MemoryTag,
+    allocator: Arc<GpuAllocator>,
+    // Set once the memory was returned together with the resource bound to it, so that dropping
+    // the allocation afterwards doesn't free it again.
+    released: AtomicBool,
 }
 
 pub struct Buffer {
     pub buffer: vk::Buffer,
-    pub memory: vk::DeviceMemory,
+    pub allocation: GpuAllocation,
     pub size: usize,
 }
 
 pub struct ImageResources {
     pub image: vk::Image,
-    pub memory: vk::DeviceMemory,
+    // Swapchain images are owned by the swapchain, so they don't have any memory allocated.
+    pub allocation: Option<GpuAllocation>,
     pub view: vk::ImageView,
+    // Layout the image will be in once all the commands recorded so far execute. Only ever changed
+    // by barrier recording functions.
+    pub current_layout: Cell<vk::ImageLayout>,
+}
+
+// Host-visible copies of an image, one per frame in flight. The copy recorded by a frame is only
+// read once the next frame of the same flight index waits for it, so reading back never stalls the
+// GPU.
+pub struct ReadbackRing {
+    buffers: Vec<Buffer>,
+    // Whether the buffer holds a copy that hasn't been read yet.
+    pending: Vec<Cell<bool>>,
+    pub extent: vk::Extent2D,
 }
 
+// Uniforms are read by nearly every shader invocation, so they are kept in device-local memory and
+// updated with a copy from a staging buffer. The copy is recorded at the start of the frame, while
+// the value is only written into the staging buffer right before submitting, once everything it
+// depends on is known.
 pub struct UniformBuffer<T> {
     buffer: Buffer,
-    mapping: *mut T,
     aligned_size: usize,
+    // Staging buffers with a recorded copy that haven't been written yet, one per flight index.
+    pending: Vec<Option<Buffer>>,
+    _marker: PhantomData<T>,
 }
 
 pub struct StorageBuffer<T: ?Sized> {
@@ -53,11 +172,69 @@ pub struct StorageBuffer<T: ?Sized> {
     mapping: *mut T,
 }
 
+// Host-visible buffers used as sources of copies into device-local memory. Allocating and freeing
+// these for every upload would put the driver allocator on the critical path, so instead they are
+// kept around and reused once the frame that used them is finished. Star data is only uploaded once
+// at startup, so a frame only takes one buffer for the global uniforms no matter how many objects
+// there are.
+pub struct StagingPool {
+    // Sorted by size, so that the smallest buffer that fits can be found with a binary search.
+    available: Vec<Buffer>,
+    in_flight: Vec<Vec<Buffer>>,
+}
+
+// Persistently mapped ring for uploads that don't belong to any frame, like meshes loaded at startup.
+// Copies are batched into command buffers that are submitted without waiting, and the ring space a
+// batch reads from is only reused once the timeline reaches its serial. The only point where
+// uploading blocks is when the ring is full, in which case it waits for the oldest batch.
+pub struct StagingRing {
+    buffer: Buffer,
+    space: RingSpace,
+    // A dedicated transfer queue if the device has one, so that uploads run alongside rendering
+    // instead of being serialized with it. Otherwise, the graphics queue.
+    queue: vk::Queue,
+    queue_family: u32,
+    graphics_queue_family: u32,
+    command_pool: vk::CommandPool,
+    // Signalled with the serial of each batch once its copies are done.
+    timeline: vk::Semaphore,
+    // Batch currently being recorded, along with the ring bytes its copies read from and the
+    // ownership acquires matching the releases recorded in it.
+    recording: Option<vk::CommandBuffer>,
+    recording_bytes: usize,
+    recording_acquires: Vec<vk::BufferMemoryBarrier2<'static>>,
+    // Acquires of submitted batches, not yet recorded on the graphics queue.
+    acquires: Vec<vk::BufferMemoryBarrier2<'static>>,
+    // Submitted batches, oldest first.
+    pending: VecDeque<StagingBatch>,
+    next_serial: u64,
+}
+
+struct StagingBatch {
+    serial: u64,
+    command_buffer: vk::CommandBuffer,
+    // Ring offset right after the last byte read by the batch.
+    end: usize,
+    // Bytes held by the batch, including the ones skipped when wrapping around.
+    bytes: usize,
+}
+
+// Tracks which bytes of the staging ring are in use, separately from any Vulkan objects. Space is
+// always freed in the order it was allocated, so it's enough to remember the two ends.
+#[derive(Debug)]
+struct RingSpace {
+    capacity: usize,
+    head: usize,
+    tail: usize,
+    used: usize,
+}
+
 impl Buffer {
     pub fn create(
         properties: vk::MemoryPropertyFlags,
         usage: vk::BufferUsageFlags,
         size: usize,
+        tag: MemoryTag,
         dev: &Dev,
     ) -> Buffer {
         let create_info = vk::BufferCreateInfo::default()
@@ -66,63 +243,376 @@ impl Buffer {
             .sharing_mode(vk::SharingMode::EXCLUSIVE);
         let buffer = unsafe { dev.create_buffer(&create_info, None) }.unwrap();
         let requirements = unsafe { dev.get_buffer_memory_requirements(buffer) };
+        let device_address = usage.contains(vk::BufferUsageFlags::SHADER_DEVICE_ADDRESS);
+        let allocation = dev
+            .allocator
+            .alloc(requirements, properties, device_address, tag, dev);
+        unsafe { dev.bind_buffer_memory(buffer, allocation.memory, allocation.offset) }.unwrap();
+        Buffer {
+            buffer,
+            allocation,
+            size,
+        }
+    }
+
+    #[allow(dead_code)]
+    pub fn device_address(&self, buffer_device_address_ext: &buffer_device_address::Device) -> u64 {
+        let info = vk::BufferDeviceAddressInfoKHR::default().buffer(self.buffer);
+        unsafe { buffer_device_address_ext.get_buffer_device_address(&info) }
+    }
+
+    pub fn cleanup(&self, dev: &Device) {
+        unsafe { dev.destroy_buffer(self.buffer, None) };
+        self.allocation.release();
+    }
+}
+
+impl GpuAllocator {
+    pub fn new(
+        instance: &Instance,
+        physical: vk::PhysicalDevice,
+        support: &DeviceSupport,
+    ) -> Arc<GpuAllocator> {
+        let properties = unsafe { instance.get_physical_device_properties(physical) };
+        Arc::new(GpuAllocator {
+            arenas: Mutex::new(Vec::new()),
+            // Buffers and optimal tiling images can't share a page of this size, but keeping
+            // track of which is which isn't worth it. So just align everything to it instead.
+            granularity: properties.limits.buffer_image_granularity,
+            tag_usage: std::array::from_fn(|_| AtomicU64::new(0)),
+            memory_budget: support.memory_budget,
+        })
+    }
+
+    pub fn alloc(
+        self: &Arc<Self>,
+        requirements: vk::MemoryRequirements,
+        properties: vk::MemoryPropertyFlags,
+        device_address: bool,
+        tag: MemoryTag,
+        dev: &Dev,
+    ) -> GpuAllocation {
         let memory_type_index = find_memory_type(properties, requirements.memory_type_bits, dev);
+        let host_visible = properties.contains(vk::MemoryPropertyFlags::HOST_VISIBLE);
+        let alignment = requirements.alignment.max(self.granularity);
+        let size = requirements.size.next_multiple_of(self.granularity);
+        let mut arenas = self.arenas.lock().unwrap();
+        for (arena_index, arena) in arenas.iter_mut().enumerate() {
+            if arena.memory_type_index == memory_type_index
+                && arena.host_visible == host_visible
+                && arena.device_address == device_address
+            {
+                if let Some(offset) = arena.take(size, alignment) {
+                    return self.allocation(arena, arena_index, offset, size, tag);
+                }
+            }
+        }
+        // None of the arenas had a large enough block, either because they are full or because their
+        // free space is fragmented, and the log says which one it was.
+        let free = arenas
+            .iter()
+            .filter(|arena| arena.memory_type_index == memory_type_index)
+            .map(GpuArena::free_space)
+            .fold(FreeSpace::default(), FreeSpace::merge);
+        let arena_size = size.max(GPU_ARENA_SIZE);
+        debug!(
+            "gpu arena allocated, \x1B[1msize\x1B[0m: {arena_size}, \x1B[1mfree\x1B[0m: {}, \x1B[1mfree blocks\x1B[0m: {}, \x1B[1mlargest free block\x1B[0m: {}",
+            free.bytes, free.blocks, free.largest_block
+        );
+        let mut arena = GpuArena::new(
+            memory_type_index,
+            host_visible,
+            device_address,
+            arena_size,
+            dev,
+        );
+        let offset = arena.take(size, alignment).unwrap();
+        let allocation = self.allocation(&arena, arenas.len(), offset, size, tag);
+        arenas.push(arena);
+        allocation
+    }
+
+    pub fn tag_usage(&self, tag: MemoryTag) -> u64 {
+        self.tag_usage[tag as usize].load(Ordering::Relaxed)
+    }
+
+    // Queries the driver for the current budget, which is cheap enough to do every frame.
+    pub fn stats(&self, dev: &Dev) -> MemoryStats {
+        let tags = MemoryTag::ALL
+            .iter()
+            .map(|&tag| (tag, self.tag_usage(tag)))
+            .collect();
+        let mut budget = vk::PhysicalDeviceMemoryBudgetPropertiesEXT::default();
+        let mut properties = vk::PhysicalDeviceMemoryProperties2::default();
+        if self.memory_budget {
+            properties = properties.push_next(&mut budget);
+        }
+        unsafe {
+            dev.instance
+                .get_physical_device_memory_properties2(dev.physical, &mut properties)
+        };
+        let memory = properties.memory_properties;
+        let mut heaps: Vec<_> = memory
+            .memory_heaps_as_slice()
+            .iter()
+            .enumerate()
+            .map(|(index, heap)| HeapStats {
+                device_local: heap.flags.contains(vk::MemoryHeapFlags::DEVICE_LOCAL),
+                size: heap.size,
+                allocated: 0,
+                free: 0,
+                largest_free_block: 0,
+                usage: self.memory_budget.then_some(budget.heap_usage[index]),
+                budget: self.memory_budget.then_some(budget.heap_budget[index]),
+            })
+            .collect();
+        for arena in self.arenas.lock().unwrap().iter() {
+            let heap = memory.memory_types[arena.memory_type_index as usize].heap_index;
+            let heap = &mut heaps[heap as usize];
+            heap.allocated += arena.size;
+            let free = arena.free_space();
+            heap.free += free.bytes;
+            heap.largest_free_block = heap.largest_free_block.max(free.largest_block);
+        }
+        MemoryStats { tags, heaps }
+    }
+
+    // Has to be called before destroying the device, and all allocations have to be dead by then.
+    // Dropping them later is fine though, as that only touches the free lists.
+    pub fn cleanup(&self, dev: &Device) {
+        for arena in self.arenas.lock().unwrap().iter() {
+            unsafe { dev.free_memory(arena.memory, None) };
+        }
+    }
+
+    fn allocation(
+        self: &Arc<Self>,
+        arena: &GpuArena,
+        arena_index: usize,
+        offset: u64,
+        size: u64,
+        tag: MemoryTag,
+    ) -> GpuAllocation {
+        self.tag_usage[tag as usize].fetch_add(size, Ordering::Relaxed);
+        let mapping = if arena.mapping.is_null() {
+            std::ptr::null_mut()
+        } else {
+            unsafe { arena.mapping.add(offset as usize) }
+        };
+        GpuAllocation {
+            memory: arena.memory,
+            offset,
+            size,
+            mapping,
+            arena: arena_index,
+            tag,
+            allocator: self.clone(),
+            released: AtomicBool::new(false),
+        }
+    }
+
+    fn free(&self, arena: usize, offset: u64, size: u64, tag: MemoryTag) {
+        self.arenas.lock().unwrap()[arena].give_back(offset, size);
+        self.tag_usage[tag as usize].fetch_sub(size, Ordering::Relaxed);
+    }
+}
+
+impl MemoryTag {
+    pub const ALL: [MemoryTag; 8] = [
+        MemoryTag::Mesh,
+        MemoryTag::VoxelChunks,
+        MemoryTag::Attachment,
+        MemoryTag::Texture,
+        MemoryTag::Uniform,
+        MemoryTag::Storage,
+        MemoryTag::Staging,
+        MemoryTag::Readback,
+    ];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            MemoryTag::Mesh => "Meshes",
+            MemoryTag::VoxelChunks => "Voxel chunks",
+            MemoryTag::Attachment => "Attachments",
+            MemoryTag::Texture => "Textures",
+            MemoryTag::Uniform => "Uniforms",
+            MemoryTag::Storage => "Storage buffers",
+            MemoryTag::Staging => "Staging",
+            MemoryTag::Readback => "Readback",
+        }
+    }
+}
+
+impl GpuArena {
+    fn new(
+        memory_type_index: u32,
+        host_visible: bool,
+        device_address: bool,
+        size: u64,
+        dev: &Dev,
+    ) -> GpuArena {
         let mut memory_info = vk::MemoryAllocateInfo::default()
-            .allocation_size(requirements.size)
+            .allocation_size(size)
             .memory_type_index(memory_type_index);
-
         let mut allocate_flags;
-        if usage.contains(vk::BufferUsageFlags::SHADER_DEVICE_ADDRESS) {
+        if device_address {
             allocate_flags = vk::MemoryAllocateFlagsInfoKHR::default()
                 .flags(vk::MemoryAllocateFlags::DEVICE_ADDRESS);
             memory_info = memory_info.push_next(&mut allocate_flags);
         }
-
         let memory = unsafe { dev.allocate_memory(&memory_info, None) }.unwrap();
-        unsafe { dev.bind_buffer_memory(buffer, memory, 0) }.unwrap();
-        Buffer {
-            buffer,
+        let mapping = if host_visible {
+            let flags = vk::MemoryMapFlags::empty();
+            unsafe { dev.map_memory(memory, 0, size, flags) }.unwrap() as *mut u8
+        } else {
+            std::ptr::null_mut()
+        };
+        GpuArena {
             memory,
+            memory_type_index,
+            host_visible,
+            device_address,
             size,
+            mapping,
+            free: vec![(0, size)],
         }
     }
 
-    pub fn fill_from_slice_host_visible<T: Copy>(&mut self, data: &[T], dev: &Dev) {
-        assert_eq!(std::mem::size_of_val(data), self.size);
-        self.with_mapped(dev, |mapped: *mut [T]| {
-            unsafe { std::ptr::copy_nonoverlapping(data.as_ptr(), mapped as *mut T, data.len()) };
-        });
+    fn take(&mut self, size: u64, alignment: u64) -> Option<u64> {
+        for index in 0..self.free.len() {
+            let (block_offset, block_size) = self.free[index];
+            let offset = block_offset.next_multiple_of(alignment);
+            let block_end = block_offset + block_size;
+            if offset + size > block_end {
+                continue;
+            }
+            self.free.remove(index);
+            if offset + size < block_end {
+                self.free
+                    .insert(index, (offset + size, block_end - offset - size));
+            }
+            if block_offset < offset {
+                self.free
+                    .insert(index, (block_offset, offset - block_offset));
+            }
+            return Some(offset);
+        }
+        None
     }
 
-    pub fn with_mapped<T, R>(&mut self, dev: &Dev, f: impl FnOnce(*mut [T]) -> R) -> R {
-        let memory = self.map_memory(dev);
-        let r = f(memory);
-        unsafe { dev.unmap_memory(self.memory) };
-        r
+    fn free_space(&self) -> FreeSpace {
+        FreeSpace {
+            bytes: self.free.iter().map(|(_, size)| size).sum(),
+            blocks: self.free.len(),
+            largest_block: self.free.iter().map(|(_, size)| *size).max().unwrap_or(0),
+        }
     }
 
-    pub fn map_memory<T>(&mut self, dev: &Dev) -> *mut [T] {
-        let count = self.size / std::mem::size_of::<T>();
-        let flags = vk::MemoryMapFlags::empty();
-        let ptr = unsafe { dev.map_memory(self.memory, 0, self.size as u64, flags) }.unwrap();
-        let slice = unsafe { std::slice::from_raw_parts_mut(ptr as *mut MaybeUninit<T>, count) };
-        slice as *mut [MaybeUninit<T>] as *mut [T]
+    fn give_back(&mut self, offset: u64, size: u64) {
+        assert!(offset + size <= self.size);
+        let index = self
+            .free
+            .partition_point(|(free_offset, _)| *free_offset < offset);
+        let overlaps_previous = index > 0 && {
+            let (previous_offset, previous_size) = self.free[index - 1];
+            previous_offset + previous_size > offset
+        };
+        let overlaps_next = index < self.free.len() && offset + size > self.free[index].0;
+        assert!(
+            !overlaps_previous && !overlaps_next,
+            "gpu memory block freed twice, offset: {offset}, size: {size}"
+        );
+        self.free.insert(index, (offset, size));
+        if index + 1 < self.free.len() && offset + size == self.free[index + 1].0 {
+            self.free[index].1 += self.free[index + 1].1;
+            self.free.remove(index + 1);
+        }
+        if index > 0 && self.free[index - 1].0 + self.free[index - 1].1 == offset {
+            self.free[index - 1].1 += self.free[index].1;
+            self.free.remove(index);
+        }
     }
+}
 
-    #[allow(dead_code)]
-    pub fn device_address(&self, buffer_device_address_ext: &buffer_device_address::Device) -> u64 {
-        let info = vk::BufferDeviceAddressInfoKHR::default().buffer(self.buffer);
-        unsafe { buffer_device_address_ext.get_buffer_device_address(&info) }
+impl FreeSpace {
+    fn merge(self, other: FreeSpace) -> FreeSpace {
+        FreeSpace {
+            bytes: self.bytes + other.bytes,
+            blocks: self.blocks + other.blocks,
+            largest_block: self.largest_block.max(other.largest_block),
+        }
+    }
+}
+
+impl GpuAllocation {
+    pub fn mapping(&self) -> *mut u8 {
+        assert!(!self.mapping.is_null(), "gpu memory is not host visible");
+        self.mapping
     }
 
-    pub fn cleanup(&self, dev: &Device) {
-        unsafe { dev.destroy_buffer(self.buffer, None) };
-        unsafe { dev.free_memory(self.memory, None) };
+    // Called when destroying the resource bound to the memory, so that the memory is only reused
+    // once the handle is gone. Allocations that were never bound to anything can just be dropped.
+    pub fn release(&self) {
+        if !self.released.swap(true, Ordering::Relaxed) {
+            self.allocator
+                .free(self.arena, self.offset, self.size, self.tag);
+        }
     }
 }
 
+impl MemoryStats {
+    // Sum of all tags, which covers every buffer and image allocated through the allocator.
+    pub fn tracked(&self) -> u64 {
+        self.tags.iter().map(|(_, bytes)| bytes).sum()
+    }
+
+    // Usage and budget summed over the device local heaps, if the driver reports them.
+    pub fn vram_budget(&self) -> Option<(u64, u64)> {
+        let mut vram = self.heaps.iter().filter(|heap| heap.device_local);
+        vram.try_fold((0, 0), |(usage, budget), heap| {
+            Some((usage + heap.usage?, budget + heap.budget?))
+        })
+    }
+
+    // Falls back to comparing our own allocations against the heap sizes without the budget
+    // extension, which misses other processes but still catches the game filling VRAM by itself.
+    pub fn vram_pressure(&self) -> f64 {
+        let (usage, limit) = self.vram_budget().unwrap_or_else(|| {
+            let vram = self.heaps.iter().filter(|heap| heap.device_local);
+            vram.fold((0, 0), |(allocated, size), heap| {
+                (allocated + heap.allocated, size + heap.size)
+            })
+        });
+        usage as f64 / limit.max(1) as f64
+    }
+}
+
+impl HeapStats {
+    // Share of the free space that isn't in the largest free block, so zero when all of it is
+    // contiguous.
+    pub fn fragmentation(&self) -> f32 {
+        if self.free == 0 {
+            return 0.;
+        }
+        1. - self.largest_free_block as f32 / self.free as f32
+    }
+}
+
+impl Drop for GpuAllocation {
+    fn drop(&mut self) {
+        self.release();
+    }
+}
+
+// The raw pointers are just persistent mappings of Vulkan memory, which can be accessed from any
+// thread as long as the resources themselves are synchronized.
+unsafe impl Send for GpuArena {}
+
+unsafe impl Send for GpuAllocation {}
+
+unsafe impl Sync for GpuAllocation {}
+
 impl Ctx<'_> {
-    #[allow(dead_code)]
     pub fn execute<R>(&self, f: impl FnOnce(vk::CommandBuffer) -> R) -> R {
         let command_info = vk::CommandBufferAllocateInfo::default()
             .level(vk::CommandBufferLevel::PRIMARY)
@@ -161,14 +651,117 @@ impl ImageResources {
         aspect: vk::ImageAspectFlags,
         extent: vk::Extent2D,
         samples: vk::SampleCountFlags,
+        tag: MemoryTag,
         dev: &Dev,
     ) -> ImageResources {
-        let (image, memory) = create_image(format, memory, tiling, usage, extent, samples, dev);
+        let (image, allocation) =
+            create_image(format, memory, tiling, usage, extent, samples, tag, dev);
         let view = create_image_view(image, format, aspect, dev);
         ImageResources {
             image,
-            memory,
+            allocation: Some(allocation),
+            view,
+            current_layout: Cell::new(vk::ImageLayout::UNDEFINED),
+        }
+    }
+
+    // Only used for lookup tables sampled in shaders, so the tiling, memory and aspect are fixed.
+    pub fn create_3d(
+        format: vk::Format,
+        usage: vk::ImageUsageFlags,
+        extent: vk::Extent3D,
+        dev: &Dev,
+    ) -> ImageResources {
+        let image_info = vk::ImageCreateInfo::default()
+            .image_type(vk::ImageType::TYPE_3D)
+            .extent(extent)
+            .mip_levels(1)
+            .array_layers(1)
+            .format(format)
+            .tiling(vk::ImageTiling::OPTIMAL)
+            .initial_layout(vk::ImageLayout::UNDEFINED)
+            .usage(usage)
+            .sharing_mode(vk::SharingMode::EXCLUSIVE)
+            .samples(vk::SampleCountFlags::TYPE_1);
+        let image = unsafe { dev.create_image(&image_info, None) }.unwrap();
+        let requirements = unsafe { dev.get_image_memory_requirements(image) };
+        let allocation = dev.allocator.alloc(
+            requirements,
+            vk::MemoryPropertyFlags::DEVICE_LOCAL,
+            false,
+            MemoryTag::Texture,
+            dev,
+        );
+        unsafe { dev.bind_image_memory(image, allocation.memory, allocation.offset) }.unwrap();
+        let view_info = vk::ImageViewCreateInfo::default()
+            .image(image)
+            .view_type(vk::ImageViewType::TYPE_3D)
+            .format(format)
+            .subresource_range(
+                vk::ImageSubresourceRange::default()
+                    .aspect_mask(vk::ImageAspectFlags::COLOR)
+                    .level_count(1)
+                    .layer_count(1),
+            );
+        let view = unsafe { dev.create_image_view(&view_info, None) }.unwrap();
+        ImageResources {
+            image,
+            allocation: Some(allocation),
+            view,
+            current_layout: Cell::new(vk::ImageLayout::UNDEFINED),
+        }
+    }
+
+    // Faces are stored as six array layers in the order +X, -X, +Y, -Y, +Z, -Z. Like the lookup
+    // tables, these are only ever sampled, so the tiling, memory and aspect are fixed.
+    pub fn create_cubemap(
+        format: vk::Format,
+        usage: vk::ImageUsageFlags,
+        size: u32,
+        dev: &Dev,
+    ) -> ImageResources {
+        let image_info = vk::ImageCreateInfo::default()
+            .flags(vk::ImageCreateFlags::CUBE_COMPATIBLE)
+            .image_type(vk::ImageType::TYPE_2D)
+            .extent(vk::Extent3D {
+                width: size,
+                height: size,
+                depth: 1,
+            })
+            .mip_levels(1)
+            .array_layers(6)
+            .format(format)
+            .tiling(vk::ImageTiling::OPTIMAL)
+            .initial_layout(vk::ImageLayout::UNDEFINED)
+            .usage(usage)
+            .sharing_mode(vk::SharingMode::EXCLUSIVE)
+            .samples(vk::SampleCountFlags::TYPE_1);
+        let image = unsafe { dev.create_image(&image_info, None) }.unwrap();
+        let requirements = unsafe { dev.get_image_memory_requirements(image) };
+        let allocation = dev.allocator.alloc(
+            requirements,
+            vk::MemoryPropertyFlags::DEVICE_LOCAL,
+            false,
+            MemoryTag::Texture,
+            dev,
+        );
+        unsafe { dev.bind_image_memory(image, allocation.memory, allocation.offset) }.unwrap();
+        let view_info = vk::ImageViewCreateInfo::default()
+            .image(image)
+            .view_type(vk::ImageViewType::CUBE)
+            .format(format)
+            .subresource_range(
+                vk::ImageSubresourceRange::default()
+                    .aspect_mask(vk::ImageAspectFlags::COLOR)
+                    .level_count(1)
+                    .layer_count(6),
+            );
+        let view = unsafe { dev.create_image_view(&view_info, None) }.unwrap();
+        ImageResources {
+            image,
+            allocation: Some(allocation),
             view,
+            current_layout: Cell::new(vk::ImageLayout::UNDEFINED),
         }
     }
 
@@ -176,40 +769,136 @@ impl ImageResources {
         unsafe {
             dev.destroy_image_view(self.view, None);
             dev.destroy_image(self.image, None);
-            dev.free_memory(self.memory, None);
+        }
+        if let Some(allocation) = &self.allocation {
+            allocation.release();
+        }
+    }
+}
+
+impl ReadbackRing {
+    // Only meant for 4-byte color formats like the swapchain ones.
+    pub fn new(extent: vk::Extent2D, frames_in_flight: usize, dev: &Dev) -> ReadbackRing {
+        let size = 4 * extent.width as usize * extent.height as usize;
+        ReadbackRing {
+            buffers: (0..frames_in_flight)
+                .map(|_| {
+                    Buffer::create(
+                        vk::MemoryPropertyFlags::HOST_VISIBLE
+                            | vk::MemoryPropertyFlags::HOST_COHERENT,
+                        vk::BufferUsageFlags::TRANSFER_DST,
+                        size,
+                        MemoryTag::Readback,
+                        dev,
+                    )
+                })
+                .collect(),
+            pending: (0..frames_in_flight).map(|_| Cell::new(false)).collect(),
+            extent,
+        }
+    }
+
+    // The image has to already be in the transfer source layout, which is up to the caller.
+    pub fn record_copy(
+        &self,
+        buf: vk::CommandBuffer,
+        image: &ImageResources,
+        flight_index: usize,
+        dev: &Dev,
+    ) {
+        image.debug_assert_layout(vk::ImageLayout::TRANSFER_SRC_OPTIMAL);
+        let region = vk::BufferImageCopy::default()
+            .image_subresource(
+                vk::ImageSubresourceLayers::default()
+                    .aspect_mask(vk::ImageAspectFlags::COLOR)
+                    .layer_count(1),
+            )
+            .image_extent(vk::Extent3D {
+                width: self.extent.width,
+                height: self.extent.height,
+                depth: 1,
+            });
+        unsafe {
+            dev.cmd_copy_image_to_buffer(
+                buf,
+                image.image,
+                vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+                self.buffers[flight_index].buffer,
+                &[region],
+            )
+        };
+        self.pending[flight_index].set(true);
+    }
+
+    // Has to be called after waiting for the previous frame of the given flight index.
+    pub fn take(&self, flight_index: usize) -> Option<&[u8]> {
+        if !self.pending[flight_index].replace(false) {
+            return None;
+        }
+        let buffer = &self.buffers[flight_index];
+        Some(unsafe { std::slice::from_raw_parts(buffer.allocation.mapping(), buffer.size) })
+    }
+
+    pub fn cleanup(&self, dev: &Device) {
+        for buffer in &self.buffers {
+            buffer.cleanup(dev);
         }
     }
 }
 
 impl<T: Copy> UniformBuffer<T> {
-    pub fn create(dev: &Dev) -> UniformBuffer<T> {
+    pub fn create(frames_in_flight: usize, dev: &Dev) -> UniformBuffer<T> {
         let properties = unsafe { dev.instance.get_physical_device_properties(dev.physical) };
         let data_size = std::mem::size_of::<T>();
         let aligned_size = data_size
             .next_multiple_of(properties.limits.min_uniform_buffer_offset_alignment as usize);
-        let size = aligned_size * FRAMES_IN_FLIGHT;
+        let size = aligned_size * frames_in_flight;
         let buffer = Buffer::create(
-            vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT,
-            vk::BufferUsageFlags::UNIFORM_BUFFER,
+            vk::MemoryPropertyFlags::DEVICE_LOCAL,
+            vk::BufferUsageFlags::UNIFORM_BUFFER | vk::BufferUsageFlags::TRANSFER_DST,
             size,
+            MemoryTag::Uniform,
             dev,
         );
-        let flags = vk::MemoryMapFlags::empty();
-        let mapping =
-            unsafe { dev.map_memory(buffer.memory, 0, size as u64, flags) }.unwrap() as *mut T;
         UniformBuffer {
             buffer,
-            mapping,
             aligned_size,
+            pending: (0..frames_in_flight).map(|_| None).collect(),
+            _marker: PhantomData,
         }
     }
 
-    pub fn write(&self, flight_index: usize, value: &T) {
-        unsafe {
-            self.mapping
-                .byte_add(self.aligned_size * flight_index)
-                .write_volatile(*value)
-        };
+    pub fn record_upload(
+        &mut self,
+        buf: vk::CommandBuffer,
+        flight_index: usize,
+        staging: &mut StagingPool,
+        dev: &Dev,
+    ) {
+        let size = std::mem::size_of::<T>();
+        let source = staging.acquire(size, dev);
+        let region = vk::BufferCopy::default()
+            .dst_offset((self.aligned_size * flight_index) as u64)
+            .size(size as u64);
+        unsafe { dev.cmd_copy_buffer(buf, source.buffer, self.buffer.buffer, &[region]) };
+        let barrier = vk::MemoryBarrier2::default()
+            .src_stage_mask(vk::PipelineStageFlags2::TRANSFER)
+            .src_access_mask(vk::AccessFlags2::TRANSFER_WRITE)
+            .dst_stage_mask(vk::PipelineStageFlags2::ALL_COMMANDS)
+            .dst_access_mask(vk::AccessFlags2::UNIFORM_READ);
+        let dependency_info =
+            vk::DependencyInfo::default().memory_barriers(std::array::from_ref(&barrier));
+        unsafe { dev.cmd_pipeline_barrier2(buf, &dependency_info) };
+        self.pending[flight_index] = Some(source);
+    }
+
+    // Has to be called after recording the upload for the flight index, and before submitting it.
+    pub fn write(&mut self, flight_index: usize, value: &T, staging: &mut StagingPool) {
+        let source = self.pending[flight_index]
+            .take()
+            .expect("uniform written without a recorded upload");
+        unsafe { (source.allocation.mapping() as *mut T).write_volatile(*value) };
+        staging.release(source, flight_index);
     }
 
     pub fn cleanup(&self, dev: &Device) {
@@ -218,6 +907,10 @@ impl<T: Copy> UniformBuffer<T> {
 }
 
 impl<T: ?Sized> StorageBuffer<T> {
+    pub fn handle(&self) -> vk::Buffer {
+        self.buffer.buffer
+    }
+
     pub fn cleanup(&self, dev: &Device) {
         self.buffer.cleanup(dev);
     }
@@ -226,10 +919,9 @@ impl<T: ?Sized> StorageBuffer<T> {
 impl<T: Copy> StorageBuffer<T> {
     pub fn new(flags: vk::MemoryPropertyFlags, dev: &Dev) -> StorageBuffer<T> {
         let size = std::mem::size_of::<T>();
-        let buffer = Buffer::create(flags, vk::BufferUsageFlags::STORAGE_BUFFER, size, dev);
-        let flags = vk::MemoryMapFlags::empty();
-        let mapping =
-            unsafe { dev.map_memory(buffer.memory, 0, size as u64, flags) }.unwrap() as *mut T;
+        let usage = vk::BufferUsageFlags::STORAGE_BUFFER;
+        let buffer = Buffer::create(flags, usage, size, MemoryTag::Storage, dev);
+        let mapping = buffer.allocation.mapping() as *mut T;
         StorageBuffer { buffer, mapping }
     }
 }
@@ -238,13 +930,33 @@ impl<T: Copy> StorageBuffer<[T]> {
     pub fn new_array(
         flags: vk::MemoryPropertyFlags,
         count: usize,
+        tag: MemoryTag,
         dev: &Dev,
     ) -> StorageBuffer<[T]> {
         let size = std::mem::size_of::<T>() * count;
-        let buffer = Buffer::create(flags, vk::BufferUsageFlags::STORAGE_BUFFER, size, dev);
-        let flags = vk::MemoryMapFlags::empty();
-        let raw_mapping = unsafe { dev.map_memory(buffer.memory, 0, size as u64, flags) }.unwrap();
-        let mapping = unsafe { std::slice::from_raw_parts_mut(raw_mapping as *mut T, count) };
+        let usage = vk::BufferUsageFlags::STORAGE_BUFFER | vk::BufferUsageFlags::TRANSFER_DST;
+        let buffer = Buffer::create(flags, usage, size, tag, dev);
+        // Device-local buffers can't be mapped, and have to be filled using StagingPool instead.
+        let raw_mapping = if flags.contains(vk::MemoryPropertyFlags::HOST_VISIBLE) {
+            buffer.allocation.mapping()
+        } else {
+            std::ptr::null_mut()
+        };
+        let mapping = std::ptr::slice_from_raw_parts_mut(raw_mapping as *mut T, count);
+        StorageBuffer { buffer, mapping }
+    }
+
+    // Indirect draw commands are written by compute shaders and read back on the CPU for statistics,
+    // so unlike other storage buffers these are always host visible.
+    pub fn new_indirect_array(count: usize, dev: &Dev) -> StorageBuffer<[T]> {
+        let size = std::mem::size_of::<T>() * count;
+        let usage = vk::BufferUsageFlags::STORAGE_BUFFER
+            | vk::BufferUsageFlags::INDIRECT_BUFFER
+            | vk::BufferUsageFlags::TRANSFER_DST;
+        let flags = vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT;
+        let buffer = Buffer::create(flags, usage, size, MemoryTag::Storage, dev);
+        let mapping =
+            std::ptr::slice_from_raw_parts_mut(buffer.allocation.mapping() as *mut T, count);
         StorageBuffer { buffer, mapping }
     }
 
@@ -257,10 +969,387 @@ impl<T: Copy> StorageBuffer<[T]> {
 
 impl<T> StorageBuffer<[T]> {
     pub fn mapped(&mut self) -> &mut [MaybeUninit<T>] {
+        assert!(
+            !self.mapping.is_null(),
+            "storage buffer is not host visible"
+        );
         unsafe { &mut *(self.mapping as *mut [MaybeUninit<T>]) }
     }
 }
 
+impl StagingPool {
+    pub fn new(frames_in_flight: usize) -> StagingPool {
+        StagingPool {
+            available: Vec::new(),
+            in_flight: (0..frames_in_flight).map(|_| Vec::new()).collect(),
+        }
+    }
+
+    pub fn acquire(&mut self, size: usize, dev: &Dev) -> Buffer {
+        let index = self.available.partition_point(|buffer| buffer.size < size);
+        if index < self.available.len() {
+            return self.available.remove(index);
+        }
+        Buffer::create(
+            vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT,
+            vk::BufferUsageFlags::TRANSFER_SRC,
+            size,
+            MemoryTag::Staging,
+            dev,
+        )
+    }
+
+    // The buffer can't be reused until the commands using it finish executing, so it's only
+    // returned to the pool by the reclaim call for the same flight index.
+    pub fn release(&mut self, buffer: Buffer, flight_index: usize) {
+        self.in_flight[flight_index].push(buffer);
+    }
+
+    // Has to be called after waiting for the previous frame of the given flight index.
+    pub fn reclaim(&mut self, flight_index: usize) {
+        for buffer in self.in_flight[flight_index].drain(..) {
+            let index = self
+                .available
+                .partition_point(|other| other.size < buffer.size);
+            self.available.insert(index, buffer);
+        }
+    }
+
+    pub fn upload<T: Copy>(
+        &mut self,
+        buf: vk::CommandBuffer,
+        dst: &StorageBuffer<[T]>,
+        data: &[T],
+        flight_index: usize,
+        dev: &Dev,
+    ) {
+        let size = std::mem::size_of_val(data);
+        assert!(size <= dst.buffer.size);
+        let staging = self.acquire(size, dev);
+        let mapping = staging.allocation.mapping() as *mut T;
+        unsafe { std::ptr::copy_nonoverlapping(data.as_ptr(), mapping, data.len()) };
+        let region = vk::BufferCopy::default().size(size as u64);
+        unsafe { dev.cmd_copy_buffer(buf, staging.buffer, dst.buffer.buffer, &[region]) };
+        let barrier = vk::MemoryBarrier2::default()
+            .src_stage_mask(vk::PipelineStageFlags2::TRANSFER)
+            .src_access_mask(vk::AccessFlags2::TRANSFER_WRITE)
+            .dst_stage_mask(vk::PipelineStageFlags2::ALL_COMMANDS)
+            .dst_access_mask(vk::AccessFlags2::MEMORY_READ);
+        let dependency_info =
+            vk::DependencyInfo::default().memory_barriers(std::array::from_ref(&barrier));
+        unsafe { dev.cmd_pipeline_barrier2(buf, &dependency_info) };
+        self.release(staging, flight_index);
+    }
+
+    // Replaces the whole image, which is left ready for sampling in fragment shaders.
+    // Layers are read from the data one after another, each tightly packed with the given extent.
+    pub fn upload_image<T: Copy>(
+        &mut self,
+        buf: vk::CommandBuffer,
+        dst: &ImageResources,
+        extent: vk::Extent3D,
+        layers: u32,
+        data: &[T],
+        flight_index: usize,
+        dev: &Dev,
+    ) {
+        let size = std::mem::size_of_val(data);
+        let staging = self.acquire(size, dev);
+        let mapping = staging.allocation.mapping() as *mut T;
+        unsafe { std::ptr::copy_nonoverlapping(data.as_ptr(), mapping, data.len()) };
+        dst.discard();
+        dst.transition(
+            buf,
+            vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+            vk::PipelineStageFlags2::NONE,
+            vk::PipelineStageFlags2::TRANSFER,
+            dev,
+        );
+        let region = vk::BufferImageCopy::default()
+            .image_subresource(
+                vk::ImageSubresourceLayers::default()
+                    .aspect_mask(vk::ImageAspectFlags::COLOR)
+                    .layer_count(layers),
+            )
+            .image_extent(extent);
+        unsafe {
+            dev.cmd_copy_buffer_to_image(
+                buf,
+                staging.buffer,
+                dst.image,
+                vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                &[region],
+            )
+        };
+        dst.transition(
+            buf,
+            vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+            vk::PipelineStageFlags2::TRANSFER,
+            vk::PipelineStageFlags2::FRAGMENT_SHADER,
+            dev,
+        );
+        self.release(staging, flight_index);
+    }
+
+    pub fn cleanup(&mut self, dev: &Device) {
+        for buffer in self.available.drain(..) {
+            buffer.cleanup(dev);
+        }
+        for in_flight in &mut self.in_flight {
+            for buffer in in_flight.drain(..) {
+                buffer.cleanup(dev);
+            }
+        }
+    }
+}
+
+impl StagingRing {
+    pub fn new(
+        capacity: usize,
+        queue: vk::Queue,
+        queue_family: u32,
+        graphics_queue_family: u32,
+        dev: &Dev,
+    ) -> StagingRing {
+        let buffer = Buffer::create(
+            vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT,
+            vk::BufferUsageFlags::TRANSFER_SRC,
+            capacity,
+            MemoryTag::Staging,
+            dev,
+        );
+        let command_pool_info = vk::CommandPoolCreateInfo::default()
+            .flags(vk::CommandPoolCreateFlags::TRANSIENT)
+            .queue_family_index(queue_family);
+        let command_pool = unsafe { dev.create_command_pool(&command_pool_info, None) }.unwrap();
+        StagingRing {
+            buffer,
+            space: RingSpace::new(capacity),
+            queue,
+            queue_family,
+            graphics_queue_family,
+            command_pool,
+            timeline: create_timeline_semaphore(dev),
+            recording: None,
+            recording_bytes: 0,
+            recording_acquires: Vec::new(),
+            acquires: Vec::new(),
+            pending: VecDeque::new(),
+            next_serial: 1,
+        }
+    }
+
+    // Records copying the data to the start of the buffer, split into chunks if it's larger than the
+    // ring. The copies only execute after the next submit call. The destination must be exclusive
+    // to the graphics queue, and not used by it until the acquires are recorded.
+    pub fn upload_buffer<T: Copy>(&mut self, dst: &Buffer, data: &[T], dev: &Dev) {
+        let size = std::mem::size_of_val(data);
+        assert!(size <= dst.size);
+        let bytes = unsafe { std::slice::from_raw_parts(data.as_ptr() as *const u8, size) };
+        for (index, chunk) in bytes.chunks(self.space.capacity).enumerate() {
+            let offset = self.reserve(chunk.len(), dev);
+            let mapping = unsafe { self.buffer.allocation.mapping().add(offset) };
+            unsafe { std::ptr::copy_nonoverlapping(chunk.as_ptr(), mapping, chunk.len()) };
+            let region = vk::BufferCopy::default()
+                .src_offset(offset as u64)
+                .dst_offset((index * self.space.capacity) as u64)
+                .size(chunk.len() as u64);
+            let buf = self.command_buffer(dev);
+            unsafe { dev.cmd_copy_buffer(buf, self.buffer.buffer, dst.buffer, &[region]) };
+        }
+        if self.queue_family != self.graphics_queue_family {
+            self.release_to_graphics(dst.buffer, dev);
+        }
+    }
+
+    // Exclusive buffers written on the transfer queue have to be handed over to the graphics queue
+    // explicitly, or their contents are undefined there. The release goes at the end of the batch,
+    // and the matching acquire into the first frame recorded after the batch is submitted, which
+    // already waits for it through wait_info. The semaphore orders the copies before the frame, so
+    // the acquire only needs the destination stages.
+    fn release_to_graphics(&mut self, buffer: vk::Buffer, dev: &Dev) {
+        let release = vk::BufferMemoryBarrier2::default()
+            .src_stage_mask(vk::PipelineStageFlags2::COPY)
+            .src_access_mask(vk::AccessFlags2::TRANSFER_WRITE)
+            .src_queue_family_index(self.queue_family)
+            .dst_queue_family_index(self.graphics_queue_family)
+            .buffer(buffer)
+            .size(vk::WHOLE_SIZE);
+        let dependency_info =
+            vk::DependencyInfo::default().buffer_memory_barriers(std::slice::from_ref(&release));
+        let buf = self.command_buffer(dev);
+        unsafe { dev.cmd_pipeline_barrier2(buf, &dependency_info) };
+        self.recording_acquires.push(
+            vk::BufferMemoryBarrier2::default()
+                .dst_stage_mask(vk::PipelineStageFlags2::ALL_COMMANDS)
+                .dst_access_mask(vk::AccessFlags2::MEMORY_READ)
+                .src_queue_family_index(self.queue_family)
+                .dst_queue_family_index(self.graphics_queue_family)
+                .buffer(buffer)
+                .size(vk::WHOLE_SIZE),
+        );
+    }
+
+    // Records the ownership acquires of all batches submitted so far. Must be called on every
+    // graphics command buffer that waits for wait_info, before anything reading the uploads.
+    pub fn record_acquires(&mut self, buf: vk::CommandBuffer, dev: &Dev) {
+        if self.acquires.is_empty() {
+            return;
+        }
+        let dependency_info = vk::DependencyInfo::default().buffer_memory_barriers(&self.acquires);
+        unsafe { dev.cmd_pipeline_barrier2(buf, &dependency_info) };
+        self.acquires.clear();
+    }
+
+    // Submits the copies recorded so far. Commands that need the uploaded data have to wait for the
+    // submitted serial, see wait_info.
+    pub fn submit(&mut self, dev: &Dev) {
+        let Some(command_buffer) = self.recording.take() else {
+            return;
+        };
+        unsafe { dev.end_command_buffer(command_buffer) }.unwrap();
+        let serial = self.next_serial;
+        let command_buffer_info =
+            vk::CommandBufferSubmitInfo::default().command_buffer(command_buffer);
+        let signal_info = vk::SemaphoreSubmitInfo::default()
+            .semaphore(self.timeline)
+            .value(serial)
+            .stage_mask(vk::PipelineStageFlags2::ALL_TRANSFER);
+        let submit_info = vk::SubmitInfo2::default()
+            .command_buffer_infos(std::slice::from_ref(&command_buffer_info))
+            .signal_semaphore_infos(std::slice::from_ref(&signal_info));
+        unsafe { dev.queue_submit2(self.queue, &[submit_info], vk::Fence::null()) }.unwrap();
+        self.next_serial += 1;
+        self.pending.push_back(StagingBatch {
+            serial,
+            command_buffer,
+            end: self.space.head,
+            bytes: std::mem::take(&mut self.recording_bytes),
+        });
+        self.acquires.append(&mut self.recording_acquires);
+    }
+
+    // Makes a submit wait for all batches submitted so far. The semaphore wait also makes the
+    // copied data visible, so the batches don't need to end with a memory barrier, other than the
+    // ownership release when they run on a separate transfer queue.
+    pub fn wait_info(&self) -> vk::SemaphoreSubmitInfo<'static> {
+        vk::SemaphoreSubmitInfo::default()
+            .semaphore(self.timeline)
+            .value(self.next_serial - 1)
+            .stage_mask(vk::PipelineStageFlags2::ALL_COMMANDS)
+    }
+
+    // Frees the ring space of batches that already finished, without blocking. Called every frame.
+    pub fn poll(&mut self, dev: &Dev) {
+        let completed = timeline_value(self.timeline, dev);
+        while self
+            .pending
+            .front()
+            .is_some_and(|batch| batch.serial <= completed)
+        {
+            self.retire_oldest(dev);
+        }
+    }
+
+    // Returns the used and total bytes of the ring.
+    pub fn occupancy(&self) -> (usize, usize) {
+        (self.space.used, self.space.capacity)
+    }
+
+    fn reserve(&mut self, size: usize, dev: &Dev) -> usize {
+        loop {
+            if let Some((offset, bytes)) = self.space.alloc(size) {
+                self.recording_bytes += bytes;
+                return offset;
+            }
+            // If nothing was submitted yet, the space is held by the batch being recorded, which has
+            // to be submitted before it can be waited for.
+            if self.pending.is_empty() {
+                self.submit(dev);
+            }
+            self.retire_oldest(dev);
+        }
+    }
+
+    fn command_buffer(&mut self, dev: &Dev) -> vk::CommandBuffer {
+        if let Some(command_buffer) = self.recording {
+            return command_buffer;
+        }
+        let allocate_info = vk::CommandBufferAllocateInfo::default()
+            .command_pool(self.command_pool)
+            .level(vk::CommandBufferLevel::PRIMARY)
+            .command_buffer_count(1);
+        let command_buffer = unsafe { dev.allocate_command_buffers(&allocate_info) }.unwrap()[0];
+        let begin_info = vk::CommandBufferBeginInfo::default()
+            .flags(vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT);
+        unsafe { dev.begin_command_buffer(command_buffer, &begin_info) }.unwrap();
+        self.recording = Some(command_buffer);
+        command_buffer
+    }
+
+    // Waits for the oldest batch if it's still executing.
+    fn retire_oldest(&mut self, dev: &Dev) {
+        let batch = self.pending.pop_front().unwrap();
+        wait_timeline(self.timeline, batch.serial, dev);
+        self.space.free(batch.end, batch.bytes);
+        unsafe { dev.free_command_buffers(self.command_pool, &[batch.command_buffer]) };
+    }
+
+    pub fn cleanup(&mut self, dev: &Dev) {
+        self.submit(dev);
+        while !self.pending.is_empty() {
+            self.retire_oldest(dev);
+        }
+        unsafe { dev.destroy_semaphore(self.timeline, None) };
+        unsafe { dev.destroy_command_pool(self.command_pool, None) };
+        self.buffer.cleanup(dev);
+    }
+}
+
+impl RingSpace {
+    fn new(capacity: usize) -> RingSpace {
+        RingSpace {
+            capacity,
+            head: 0,
+            tail: 0,
+            used: 0,
+        }
+    }
+
+    // Copies need contiguous source memory, so allocations never wrap around the end of the ring.
+    // If the space left at the end is too small, it's skipped and stays used until the tail passes
+    // it. Returns the offset and the number of bytes taken, including the skipped ones.
+    fn alloc(&mut self, size: usize) -> Option<(usize, usize)> {
+        if self.used == 0 {
+            self.head = 0;
+            self.tail = 0;
+        }
+        let wrapped = self.head < self.tail || (self.head == self.tail && self.used > 0);
+        if !wrapped && self.capacity - self.head < size {
+            if self.tail < size {
+                return None;
+            }
+            let skipped = self.capacity - self.head;
+            self.head = size;
+            self.used += skipped + size;
+            return Some((0, skipped + size));
+        }
+        if wrapped && self.tail - self.head < size {
+            return None;
+        }
+        let offset = self.head;
+        self.head += size;
+        self.used += size;
+        Some((offset, size))
+    }
+
+    // Everything before the end offset is free afterwards, as space is freed in allocation order.
+    fn free(&mut self, end: usize, bytes: usize) {
+        self.tail = end;
+        self.used -= bytes;
+    }
+}
+
 impl Deref for Dev {
     type Target = Device;
 
@@ -282,7 +1371,7 @@ impl<T: ?Sized> AsDescriptor for StorageBuffer<T> {
     fn descriptor(&self, _flight_index: usize) -> vk::DescriptorBufferInfo {
         vk::DescriptorBufferInfo::default()
             .buffer(self.buffer.buffer)
-            .range(unsafe { std::mem::size_of_val(&*self.mapping) } as u64)
+            .range(self.buffer.size as u64)
     }
 }
 
@@ -298,8 +1387,9 @@ pub fn create_image(
     usage: vk::ImageUsageFlags,
     extent: vk::Extent2D,
     samples: vk::SampleCountFlags,
+    tag: MemoryTag,
     dev: &Dev,
-) -> (vk::Image, vk::DeviceMemory) {
+) -> (vk::Image, GpuAllocation) {
     let image_info = vk::ImageCreateInfo::default()
         .image_type(vk::ImageType::TYPE_2D)
         .extent(vk::Extent3D {
@@ -318,14 +1408,10 @@ pub fn create_image(
     let image = unsafe { dev.create_image(&image_info, None) }.unwrap();
 
     let requirements = unsafe { dev.get_image_memory_requirements(image) };
-    let memory_type = find_memory_type(memory, requirements.memory_type_bits, dev);
-    let alloc_info = vk::MemoryAllocateInfo::default()
-        .allocation_size(requirements.size)
-        .memory_type_index(memory_type);
-    let image_memory = unsafe { dev.allocate_memory(&alloc_info, None) }.unwrap();
-    unsafe { dev.bind_image_memory(image, image_memory, 0) }.unwrap();
+    let allocation = dev.allocator.alloc(requirements, memory, false, tag, dev);
+    unsafe { dev.bind_image_memory(image, allocation.memory, allocation.offset) }.unwrap();
 
-    (image, image_memory)
+    (image, allocation)
 }
 
 pub fn create_image_view(
@@ -388,6 +1474,27 @@ fn find_memory_type(properties: vk::MemoryPropertyFlags, type_filter: u32, dev:
     );
 }
 
+// Timeline semaphores count up to the value of the latest submit that signalled them, which lets a
+// single semaphore track any number of submits, unlike fences and binary semaphores.
+pub fn create_timeline_semaphore(dev: &Dev) -> vk::Semaphore {
+    let mut type_info = vk::SemaphoreTypeCreateInfo::default()
+        .semaphore_type(vk::SemaphoreType::TIMELINE)
+        .initial_value(0);
+    let create_info = vk::SemaphoreCreateInfo::default().push_next(&mut type_info);
+    unsafe { dev.create_semaphore(&create_info, None) }.unwrap()
+}
+
+pub fn wait_timeline(semaphore: vk::Semaphore, value: u64, dev: &Dev) {
+    let wait_info = vk::SemaphoreWaitInfo::default()
+        .semaphores(std::slice::from_ref(&semaphore))
+        .values(std::slice::from_ref(&value));
+    unsafe { dev.wait_semaphores(&wait_info, u64::MAX) }.unwrap();
+}
+
+pub fn timeline_value(semaphore: vk::Semaphore, dev: &Dev) -> u64 {
+    unsafe { dev.get_semaphore_counter_value(semaphore) }.unwrap()
+}
+
 pub fn vulkan_str(slice: &[i8]) -> &str {
     unsafe { CStr::from_ptr(slice.as_ptr()) }.to_str().unwrap()
 }
@@ -400,3 +1507,166 @@ pub fn timestamp_difference_to_duration(
         timestamp as f64 * properties.limits.timestamp_period as f64 / 1_000_000_000.,
     )
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn arena(size: u64) -> GpuArena {
+        GpuArena {
+            memory: vk::DeviceMemory::null(),
+            memory_type_index: 0,
+            host_visible: false,
+            device_address: false,
+            size,
+            mapping: std::ptr::null_mut(),
+            free: vec![(0, size)],
+        }
+    }
+
+    #[test]
+    fn take_respects_alignment() {
+        let mut arena = arena(1024);
+        assert_eq!(arena.take(10, 1), Some(0));
+        assert_eq!(arena.take(16, 256), Some(256));
+        // The padding before the aligned block stays available.
+        assert_eq!(arena.free, vec![(10, 246), (272, 752)]);
+        assert_eq!(arena.take(100, 2), Some(10));
+    }
+
+    #[test]
+    fn give_back_merges_neighbours() {
+        let mut arena = arena(300);
+        let a = arena.take(100, 1).unwrap();
+        let b = arena.take(100, 1).unwrap();
+        let c = arena.take(100, 1).unwrap();
+        assert_eq!(arena.take(1, 1), None);
+        arena.give_back(a, 100);
+        arena.give_back(c, 100);
+        assert_eq!(arena.free, vec![(0, 100), (200, 100)]);
+        assert_eq!(arena.take(150, 1), None);
+        arena.give_back(b, 100);
+        assert_eq!(arena.free, vec![(0, 300)]);
+        assert_eq!(arena.take(300, 1), Some(0));
+    }
+
+    #[test]
+    fn free_space() {
+        let mut arena = arena(400);
+        let offsets: Vec<_> = (0..4).map(|_| arena.take(100, 1).unwrap()).collect();
+        arena.give_back(offsets[0], 100);
+        arena.give_back(offsets[2], 100);
+        let fragmented = FreeSpace {
+            bytes: 200,
+            blocks: 2,
+            largest_block: 100,
+        };
+        assert_eq!(arena.free_space(), fragmented);
+        arena.give_back(offsets[1], 100);
+        let merged = FreeSpace {
+            bytes: 300,
+            blocks: 1,
+            largest_block: 300,
+        };
+        assert_eq!(arena.free_space(), merged);
+        assert_eq!(
+            merged.merge(fragmented),
+            FreeSpace {
+                bytes: 500,
+                blocks: 3,
+                largest_block: 300,
+            }
+        );
+    }
+
+    #[test]
+    fn fragmentation() {
+        let mut arena = arena(400);
+        let offsets: Vec<_> = (0..4).map(|_| arena.take(100, 1).unwrap()).collect();
+        arena.give_back(offsets[0], 100);
+        arena.give_back(offsets[2], 100);
+        let free = arena.free_space();
+        let heap = HeapStats {
+            device_local: true,
+            size: 400,
+            allocated: 400,
+            free: free.bytes,
+            largest_free_block: free.largest_block,
+            usage: None,
+            budget: None,
+        };
+        assert_eq!(heap.fragmentation(), 0.5);
+    }
+
+    #[test]
+    fn vram_pressure_without_budget() {
+        let heap = |device_local, size, allocated, usage, budget| HeapStats {
+            device_local,
+            size,
+            allocated,
+            free: 0,
+            largest_free_block: 0,
+            usage,
+            budget,
+        };
+        let mut memory = MemoryStats {
+            tags: vec![(MemoryTag::Mesh, 100), (MemoryTag::Texture, 300)],
+            heaps: vec![
+                heap(true, 1000, 500, Some(950), Some(1000)),
+                heap(false, 4000, 4000, Some(4000), Some(4000)),
+            ],
+        };
+        assert_eq!(memory.tracked(), 400);
+        assert_eq!(memory.vram_budget(), Some((950, 1000)));
+        assert!(memory.vram_pressure() > MEMORY_BUDGET_CRITICAL);
+        memory.heaps[0].usage = None;
+        assert_eq!(memory.vram_budget(), None);
+        assert_eq!(memory.vram_pressure(), 0.5);
+    }
+
+    #[test]
+    fn ring_space_wraps_around() {
+        let mut ring = RingSpace::new(100);
+        assert_eq!(ring.alloc(40), Some((0, 40)));
+        assert_eq!(ring.alloc(40), Some((40, 40)));
+        assert_eq!(ring.alloc(30), None);
+        ring.free(40, 40);
+        // The 20 bytes left at the end are skipped, and count as used until freed.
+        assert_eq!(ring.alloc(30), Some((0, 50)));
+        assert_eq!(ring.used, 90);
+        assert_eq!(ring.alloc(11), None);
+        assert_eq!(ring.alloc(10), Some((30, 10)));
+        ring.free(80, 40);
+        ring.free(40, 60);
+        assert_eq!(ring.used, 0);
+        assert_eq!(ring.alloc(100), Some((0, 100)));
+    }
+
+    #[test]
+    fn ring_space_full() {
+        let mut ring = RingSpace::new(64);
+        assert_eq!(ring.alloc(32), Some((0, 32)));
+        assert_eq!(ring.alloc(32), Some((32, 32)));
+        assert_eq!(ring.alloc(1), None);
+        ring.free(32, 32);
+        assert_eq!(ring.alloc(32), Some((0, 32)));
+        assert_eq!(ring.alloc(1), None);
+    }
+
+    #[test]
+    #[should_panic(expected = "freed twice")]
+    fn double_free() {
+        let mut arena = arena(256);
+        let offset = arena.take(64, 1).unwrap();
+        arena.give_back(offset, 64);
+        arena.give_back(offset, 64);
+    }
+
+    #[test]
+    #[should_panic(expected = "freed twice")]
+    fn overlapping_free() {
+        let mut arena = arena(256);
+        arena.take(128, 1).unwrap();
+        arena.give_back(64, 128);
+    }
+}
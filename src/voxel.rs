@@ -6,31 +6,55 @@ pub mod material;
 pub mod meshing;
 pub mod meshlet;
 pub mod neighbourhood;
+mod snapshot;
 mod sparse_octree;
 mod thread;
 mod world_generation;
 
 use crate::voxel::chunk_priority::{ChunkPriority, ChunkPriorityAlgorithm};
+use crate::voxel::gpu::allocator::PoolStats;
 use crate::voxel::gpu::VoxelGpuMemory;
 use crate::voxel::meshing::MeshingAlgorithmKind;
+use crate::voxel::snapshot::{Snapshot, SnapshotCell};
 use crate::voxel::sparse_octree::SparseOctree;
-use crate::voxel::thread::voxel_thread;
-use bracket_noise::prelude::{FastNoise, NoiseType};
+use crate::voxel::thread::{voxel_thread, MAX_CHUNK_RETRIES};
+use crate::voxel::world_generation::heightmap_noise;
+use bracket_noise::prelude::FastNoise;
+use log::warn;
 use nalgebra::{DMatrix, Vector2, Vector3};
-use std::collections::HashMap;
-use std::sync::{Arc, Condvar, Mutex};
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard, PoisonError, TryLockError};
 use std::thread::JoinHandle;
 
+// Number of most recently generated chunks the timings in the interface are computed from.
+const CHUNK_TIMING_HISTORY: usize = 100;
+
+// Number of unloaded chunks whose octrees are kept around, in case the camera turns back. Enough for
+// a couple of slabs at the default render distance. Only regenerating the terrain is avoided, the
+// mesh is still rebuilt and uploaded again.
+const UNLOADED_CHUNK_CACHE_SIZE: usize = 256;
+
 pub struct Voxels {
     shared: Arc<VoxelsShared>,
     handles: Vec<JoinHandle<()>>,
-    // TODO: Deduplicate?
-    camera: Vector3<i64>,
     config: VoxelsConfig,
 }
 
+// The main thread only talks to the workers through the snapshot and the shutdown and pause flags,
+// so that it never blocks on the state lock while the workers are busy with it. Config changes still
+// take the lock to reset the state, but these are rare.
 pub struct VoxelsShared {
-    camera: Mutex<Vector3<i64>>,
+    snapshot: SnapshotCell,
+    shutdown: AtomicBool,
+    // Set while the session is inactive. Workers finish the chunk they are on, but don't start new
+    // ones, so that only the active session competes for the CPU.
+    paused: AtomicBool,
+    // Chunks the workers can still start in the current frame. Refilled rather than accumulated by
+    // every camera update, so that frames where nothing was started don't add up to a burst later.
+    chunk_budget: AtomicU32,
     state: Mutex<VoxelsState>,
     wake: Condvar,
 }
@@ -42,20 +66,136 @@ pub struct VoxelsState {
     loaded_heightmaps: HashMap<Vector2<i64>, Arc<DMatrix<i64>>>,
     gpu_memory: Box<dyn VoxelGpuMemory>,
     config: VoxelsConfig,
-    config_generation: u64,
-    shutdown: bool,
+    // Revision the config above belongs to. Lags behind the snapshot for a moment while a config
+    // change is being applied.
+    config_revision: u64,
+    failed_attempts: HashMap<Vector3<i64>, usize>,
+    // Chunks currently being generated or meshed by a worker, only tracked for the interface.
+    generating: HashSet<Vector3<i64>>,
+    health: VoxelsHealth,
+    timings: VecDeque<ChunkTimings>,
+    // Octrees of the most recently unloaded chunks, oldest first.
+    unloaded_svos: VecDeque<(Vector3<i64>, Arc<SparseOctree>)>,
+    // Camera chunk that distant chunks were last unloaded around.
+    unloaded_around: Vector3<i64>,
+    streaming: VoxelsStreaming,
+}
+
+// Counts of problems in the worker threads, so that they can be noticed in the interface instead of
+// just showing up as holes in the world.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct VoxelsHealth {
+    pub failed_chunks: usize,
+    pub retried_chunks: usize,
+    pub dead_workers: usize,
+    // Not a problem by itself, but chunks getting evicted all the time means GPU memory is too
+    // small for the render distance.
+    pub evicted_chunks: usize,
+    // Also expected, as chunks that were being generated when the config changed are discarded
+    // rather than mixed into the new world.
+    pub stale_chunks: usize,
+}
+
+// Counts of chunks moving in and out of memory as the camera moves. Loads growing without the
+// camera going anywhere new means chunks are being unloaded too eagerly.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct VoxelsStreaming {
+    pub loads: usize,
+    pub unloads: usize,
+    // Octrees of unloaded chunks that were needed again before being dropped from the cache.
+    pub cache_readds: usize,
 }
 
-#[derive(Clone)]
+// Snapshot of the voxel state for the interface. Meshlet counts are tracked by the renderer, as
+// they are needed there every frame anyway.
+#[derive(Clone, Debug)]
+pub struct VoxelsStats {
+    pub loaded_chunks: usize,
+    // Chunks queued or being generated. Chunks further out aren't queued until the closer ones are
+    // done, so this doesn't count everything left to load within the render distance.
+    pub pending_chunks: usize,
+    pub vertex_count: usize,
+    pub triangle_count: usize,
+    pub pools: Vec<(&'static str, PoolStats)>,
+    pub health: VoxelsHealth,
+    pub timings: ChunkTimingStats,
+    pub streaming: VoxelsStreaming,
+}
+
+// Time spent on each step of generating a single chunk, in microseconds. Heightmaps and octrees of
+// the neighbouring chunks generated along the way count towards the chunk that needed them, and
+// steps skipped because their results were already loaded count as zero.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct ChunkTimings {
+    pub heightmap_us: u64,
+    pub svo_us: u64,
+    pub mesh_us: u64,
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+pub struct ChunkTimingStats {
+    pub average: ChunkTimings,
+    pub max: ChunkTimings,
+}
+
+// Seeds are saved in the TOML config, which only has signed 64-bit integers, so larger ones couldn't
+// be saved.
+pub const MAX_SEED: u64 = i64::MAX as u64;
+
+// Copy of a single entry of the chunk table for the interface. Chunks are tracked in several
+// places depending on how far along they are, so this is assembled from all of them at once.
+#[derive(Clone, Copy, Debug)]
+pub struct ChunkInfo {
+    pub chunk: Vector3<i64>,
+    pub state: ChunkState,
+    pub triangle_count: usize,
+    pub gpu_memory: usize,
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum ChunkState {
+    Queued,
+    Generating,
+    // Terrain was generated because a neighbouring chunk needed it for meshing, but the chunk
+    // itself wasn't meshed yet.
+    Generated,
+    Uploaded,
+    Failed,
+}
+
+#[derive(Clone, Deserialize, Serialize)]
 pub struct VoxelsConfig {
     pub seed: u64,
     pub chunk_size: usize,
     pub heightmap_amplitude: f32,
     pub heightmap_frequency: f32,
     pub heightmap_bias: f32,
+    pub sand_max_altitude: i64,
+    pub snow_min_altitude: i64,
+    pub rock_min_slope: i64,
     pub render_distance_horizontal: usize,
     pub render_distance_vertical: usize,
+    // Chunks are only unloaded once they are this far away, so that a camera moving back and forth
+    // across a chunk border doesn't keep unloading and loading the chunks at the edge. Always
+    // treated as at least one chunk more than the render distance.
+    pub unload_distance_horizontal: usize,
+    pub unload_distance_vertical: usize,
+    // Limits how many chunks the workers start per frame, as uploading a lot of them at once shows
+    // up as a spike in frame times. Closer chunks are still started first.
+    pub max_chunks_per_frame: u32,
     pub meshing_algorithm: MeshingAlgorithmKind,
+    // Ambient occlusion is always baked into the meshes, as computing it is cheap compared to the
+    // rest of meshing. These only control how it's applied during shading, so changing them
+    // doesn't require remeshing anything.
+    pub ambient_occlusion: bool,
+    pub ambient_occlusion_strength: f32,
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum VoxelCollision {
+    Free,
+    Solid,
+    Unloaded,
 }
 
 pub const DIRECTIONS: [Vector3<i64>; 6] = [
@@ -75,11 +215,15 @@ impl Voxels {
         thread_count: usize,
     ) -> Voxels {
         let camera = chunk_from_position(camera, config.chunk_size);
-        let mut noise = FastNoise::seeded(config.seed);
-        noise.set_noise_type(NoiseType::Perlin);
-        noise.set_frequency(1.);
+        let noise = heightmap_noise(config.seed);
         let shared = Arc::new(VoxelsShared {
-            camera: Mutex::new(camera),
+            snapshot: SnapshotCell::new(Snapshot {
+                camera,
+                revision: 0,
+            }),
+            shutdown: AtomicBool::new(false),
+            paused: AtomicBool::new(false),
+            chunk_budget: AtomicU32::new(config.max_chunks_per_frame),
             state: Mutex::new(VoxelsState {
                 chunk_priority: ChunkPriority::new(
                     camera,
@@ -93,8 +237,14 @@ impl Voxels {
                 loaded_heightmaps: HashMap::new(),
                 gpu_memory,
                 config: config.clone(),
-                config_generation: 0,
-                shutdown: false,
+                config_revision: 0,
+                failed_attempts: HashMap::new(),
+                generating: HashSet::new(),
+                health: VoxelsHealth::default(),
+                timings: VecDeque::new(),
+                unloaded_svos: VecDeque::new(),
+                unloaded_around: camera,
+                streaming: VoxelsStreaming::default(),
             }),
             wake: Condvar::new(),
         });
@@ -106,26 +256,55 @@ impl Voxels {
         Voxels {
             shared,
             handles,
-            camera,
             config,
         }
     }
 
-    pub fn update_camera(&self, new_position: Vector3<f32>) {
+    // Skipped when a worker holds the lock, as the next frame will try again anyway and the render
+    // thread shouldn't wait for chunk uploads.
+    pub fn reclaim_gpu_memory(&self) {
+        let mut state = match self.shared.state.try_lock() {
+            Ok(state) => state,
+            Err(TryLockError::Poisoned(poisoned)) => poisoned.into_inner(),
+            Err(TryLockError::WouldBlock) => return,
+        };
+        state.gpu_memory.reclaim();
+    }
+
+    // Called every frame, so nothing here waits for the workers. Notifying doesn't take the state
+    // lock, which is why the workers also wake up on their own every now and then. The chunk limit
+    // is passed here rather than with the config, as changing it doesn't require regenerating.
+    pub fn update_camera(&self, new_position: Vector3<f32>, max_chunks_per_frame: u32) {
         let new_chunk = chunk_from_position(new_position, self.config.chunk_size);
-        let mut camera = self.shared.camera.lock().unwrap();
-        let old_chunk = *camera;
-        *camera = new_chunk;
-        drop(camera);
-        if new_chunk != old_chunk {
+        let snapshot = self.shared.snapshot.load();
+        let budget = self
+            .shared
+            .chunk_budget
+            .swap(max_chunks_per_frame, Ordering::Relaxed);
+        if new_chunk != snapshot.camera {
+            self.shared.snapshot.store(Snapshot {
+                camera: new_chunk,
+                ..snapshot
+            });
+            self.shared.wake.notify_all();
+        } else if budget == 0 {
             self.shared.wake.notify_all();
         }
     }
 
+    // The new revision is published before taking the lock, so that workers in the middle of a
+    // chunk notice at their next check and stop, instead of finishing it only for it to be thrown
+    // away.
     pub fn update_config(&self, new_config: VoxelsConfig) {
-        let mut state = self.shared.state.lock().unwrap();
+        let snapshot = self.shared.snapshot.load();
+        let revision = snapshot.revision + 1;
+        self.shared.snapshot.store(Snapshot {
+            revision,
+            ..snapshot
+        });
+        let mut state = self.shared.lock_state();
         state.chunk_priority.clear(
-            self.camera,
+            snapshot.camera,
             new_config
                 .render_distance_horizontal
                 .div_ceil(new_config.chunk_size) as i64,
@@ -133,29 +312,338 @@ impl Voxels {
                 .render_distance_vertical
                 .div_ceil(new_config.chunk_size) as i64,
         );
-        let mut noise = FastNoise::seeded(new_config.seed);
-        noise.set_noise_type(NoiseType::Perlin);
-        noise.set_frequency(1.);
-        state.heightmap_noise = Arc::new(noise);
+        state.heightmap_noise = Arc::new(heightmap_noise(new_config.seed));
         state.loaded_svos.clear();
         state.loaded_heightmaps.clear();
+        state.failed_attempts.clear();
+        state.timings.clear();
+        state.unloaded_svos.clear();
         state.gpu_memory.clear();
         state.config = new_config;
-        state.config_generation += 1;
+        state.config_revision = revision;
         drop(state);
         self.shared.wake.notify_all();
     }
 
+    pub fn pause(&self) {
+        self.shared.paused.store(true, Ordering::Relaxed);
+    }
+
+    // Chunks evicted while paused were loaded before, so they are queued again right away instead
+    // of waiting for the camera to move.
+    pub fn resume(&self) {
+        let mut state = self.shared.lock_state();
+        let evicted = state.gpu_memory.take_evicted();
+        state.health.evicted_chunks += evicted.len();
+        for chunk in evicted {
+            state.chunk_priority.retry(chunk);
+        }
+        self.shared.paused.store(false, Ordering::Relaxed);
+        drop(state);
+        self.shared.wake.notify_all();
+    }
+
+    // Checks whether the box intersects any solid voxel. Voxels in chunks that weren't generated
+    // yet are reported separately, so that callers can avoid falling through the terrain while it's
+    // still loading. The state lock is only taken once, as this is called many times per frame.
+    pub fn collide_aabb(&self, min: Vector3<f32>, max: Vector3<f32>) -> VoxelCollision {
+        let min = min.map(|coord| coord.floor() as i64);
+        let max = max.map(|coord| coord.ceil() as i64);
+        let state = self.shared.lock_state();
+        let chunk_size = state.config.chunk_size as i64;
+        let mut collision = VoxelCollision::Free;
+        for z in min.z..max.z {
+            for y in min.y..max.y {
+                for x in min.x..max.x {
+                    let voxel = Vector3::new(x, y, z);
+                    let chunk = voxel.map(|coord| coord.div_euclid(chunk_size));
+                    let Some(svo) = state.loaded_svos.get(&chunk) else {
+                        collision = VoxelCollision::Unloaded;
+                        continue;
+                    };
+                    let local = voxel.map(|coord| coord.rem_euclid(chunk_size));
+                    if !svo.at(local, chunk_size).is_air() {
+                        return VoxelCollision::Solid;
+                    }
+                }
+            }
+        }
+        collision
+    }
+
+    // Without any workers, with only the given chunks loaded and filled according to the closure,
+    // so that collisions can be tested against a small hand-built world.
+    #[cfg(test)]
+    pub fn from_fn(
+        chunk_size: usize,
+        chunks: &[Vector3<i64>],
+        solid: impl Fn(Vector3<i64>) -> bool,
+    ) -> Voxels {
+        let config = VoxelsConfig {
+            chunk_size,
+            ..crate::config::DEFAULT_VOXEL_CONFIG
+        };
+        let voxels = Voxels::new(config, Vector3::zeros(), Box::new(gpu::NullMemory), 0);
+        let mut state = voxels.shared.lock_state();
+        for &chunk in chunks {
+            let svo = SparseOctree::from_fn(chunk_size as i64, &|local| {
+                if solid(chunk * chunk_size as i64 + local) {
+                    material::VoxelMaterialId::STONE
+                } else {
+                    material::VoxelMaterialId::AIR
+                }
+            });
+            state.loaded_svos.insert(chunk, Arc::new(svo));
+        }
+        drop(state);
+        voxels
+    }
+
+    // Same as collide_aabb, but only voxels whose box is actually within the radius count, so that
+    // rounded shapes can slide past edges and corners instead of catching on them.
+    pub fn collide_sphere(&self, center: Vector3<f32>, radius: f32) -> VoxelCollision {
+        let min = (center - Vector3::from_element(radius)).map(|coord| coord.floor() as i64);
+        let max = (center + Vector3::from_element(radius)).map(|coord| coord.ceil() as i64);
+        let state = self.shared.lock_state();
+        let chunk_size = state.config.chunk_size as i64;
+        let mut collision = VoxelCollision::Free;
+        for z in min.z..max.z {
+            for y in min.y..max.y {
+                for x in min.x..max.x {
+                    let voxel = Vector3::new(x, y, z);
+                    let voxel_min = voxel.map(|coord| coord as f32);
+                    let closest = center.sup(&voxel_min).inf(&voxel_min.add_scalar(1.));
+                    if (closest - center).norm_squared() >= radius * radius {
+                        continue;
+                    }
+                    let chunk = voxel.map(|coord| coord.div_euclid(chunk_size));
+                    let Some(svo) = state.loaded_svos.get(&chunk) else {
+                        collision = VoxelCollision::Unloaded;
+                        continue;
+                    };
+                    let local = voxel.map(|coord| coord.rem_euclid(chunk_size));
+                    if !svo.at(local, chunk_size).is_air() {
+                        return VoxelCollision::Solid;
+                    }
+                }
+            }
+        }
+        collision
+    }
+
+    pub fn stats(&self) -> VoxelsStats {
+        let state = self.shared.lock_state();
+        VoxelsStats {
+            loaded_chunks: state.loaded_svos.len(),
+            pending_chunks: state.chunk_priority.queued().len() + state.generating.len(),
+            vertex_count: state.gpu_memory.vertex_count(),
+            triangle_count: state.gpu_memory.triangle_count(),
+            pools: state.gpu_memory.pool_stats(),
+            health: VoxelsHealth {
+                dead_workers: self
+                    .handles
+                    .iter()
+                    .filter(|handle| handle.is_finished())
+                    .count(),
+                ..state.health
+            },
+            timings: timing_stats(&state.timings),
+            streaming: state.streaming,
+        }
+    }
+
+    // The lock is only held while copying, so that the interface can keep the snapshot around for
+    // as long as it wants without stalling the workers.
+    pub fn chunk_snapshot(&self) -> Vec<ChunkInfo> {
+        let state = self.shared.lock_state();
+        let mut chunks = HashMap::new();
+        let mut set_state = |chunk: Vector3<i64>, chunk_state| {
+            chunks
+                .entry(chunk)
+                .or_insert(ChunkInfo {
+                    chunk,
+                    state: chunk_state,
+                    triangle_count: 0,
+                    gpu_memory: 0,
+                })
+                .state = chunk_state;
+        };
+        for chunk in state.loaded_svos.keys() {
+            set_state(*chunk, ChunkState::Generated);
+        }
+        for (chunk, attempts) in &state.failed_attempts {
+            if *attempts > MAX_CHUNK_RETRIES {
+                set_state(*chunk, ChunkState::Failed);
+            }
+        }
+        for chunk in state.chunk_priority.queued() {
+            set_state(*chunk, ChunkState::Queued);
+        }
+        for chunk in &state.generating {
+            set_state(*chunk, ChunkState::Generating);
+        }
+        for memory in state.gpu_memory.chunk_memory() {
+            chunks.insert(
+                memory.chunk,
+                ChunkInfo {
+                    chunk: memory.chunk,
+                    state: ChunkState::Uploaded,
+                    triangle_count: memory.triangle_count,
+                    gpu_memory: memory.bytes,
+                },
+            );
+        }
+        drop(state);
+        chunks.into_values().collect()
+    }
+
+    // Workers that died despite catching panics in chunk generation are already finished, so
+    // joining them doesn't block, and their panics were already logged by the panic hook. Workers
+    // in the middle of a chunk check the flag between its steps, so they don't finish it first.
     pub fn shutdown(self) {
-        self.shared.state.lock().unwrap().shutdown = true;
+        self.shared.shutdown.store(true, Ordering::Relaxed);
+        // Any worker that checked the flag before it was set is waiting by the time the lock is
+        // released, so none of them can miss the notification.
+        drop(self.shared.lock_state());
         self.shared.wake.notify_all();
         for handle in self.handles {
-            handle.join().unwrap();
+            if handle.join().is_err() {
+                warn!("voxel worker thread died before shutdown");
+            }
         }
-        self.shared.state.lock().unwrap().gpu_memory.cleanup();
     }
 }
 
-fn chunk_from_position(position: Vector3<f32>, chunk_size: usize) -> Vector3<i64> {
+impl VoxelsState {
+    // Called by the workers before selecting a chunk. Unloading only happens when the camera moves
+    // to another chunk, as nothing can get further away otherwise.
+    fn update_camera(&mut self, camera: Vector3<i64>) {
+        self.chunk_priority.update_camera(camera);
+        if camera == self.unloaded_around {
+            return;
+        }
+        self.unloaded_around = camera;
+        let (horizontal, vertical) = unload_distance(&self.config);
+        let outside = |chunk: Vector3<i64>| {
+            let offset = (chunk - camera).abs();
+            offset.x > horizontal || offset.y > horizontal || offset.z > vertical
+        };
+        for chunk in self.chunk_priority.unload_outside(&outside) {
+            self.gpu_memory.unload(chunk);
+            self.streaming.unloads += 1;
+        }
+        let distant: Vec<_> = self
+            .loaded_svos
+            .keys()
+            .copied()
+            .filter(|chunk| outside(*chunk))
+            .collect();
+        for chunk in distant {
+            let svo = self.loaded_svos.remove(&chunk).unwrap();
+            if self.unloaded_svos.len() == UNLOADED_CHUNK_CACHE_SIZE {
+                self.unloaded_svos.pop_front();
+            }
+            self.unloaded_svos.push_back((chunk, svo));
+        }
+        // Octrees are generated from the heightmaps of the neighbouring columns too, so these are
+        // kept one column further.
+        self.loaded_heightmaps.retain(|column, _| {
+            let offset = (column - camera.xy()).abs();
+            offset.x <= horizontal + 1 && offset.y <= horizontal + 1
+        });
+    }
+
+    fn restore_svo(&mut self, chunk: Vector3<i64>) -> Option<Arc<SparseOctree>> {
+        let index = self
+            .unloaded_svos
+            .iter()
+            .position(|(unloaded, _)| *unloaded == chunk)?;
+        let (_, svo) = self.unloaded_svos.remove(index).unwrap();
+        self.loaded_svos.insert(chunk, svo.clone());
+        self.streaming.cache_readds += 1;
+        Some(svo)
+    }
+
+    fn record_timings(&mut self, timings: ChunkTimings) {
+        if self.timings.len() == CHUNK_TIMING_HISTORY {
+            self.timings.pop_front();
+        }
+        self.timings.push_back(timings);
+    }
+}
+
+impl VoxelsShared {
+    // Chunk generation panics are caught while the lock is released, but anything panicking while
+    // holding it would poison it. The state is still usable in that case, at worst with a chunk
+    // missing, so the poisoning is ignored rather than spreading the panic to every thread.
+    fn lock_state(&self) -> MutexGuard<VoxelsState> {
+        self.state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    // Whether a chunk started against the given config revision should be abandoned, either
+    // because its results would be thrown away or because the workers are shutting down. Doesn't
+    // take any locks, so it's cheap enough to check between every step of a chunk.
+    fn abandoned(&self, config_revision: u64) -> bool {
+        self.shutdown.load(Ordering::Relaxed) || self.snapshot.load().revision != config_revision
+    }
+}
+
+pub fn random_seed() -> u64 {
+    rand::thread_rng().gen_range(0..=MAX_SEED)
+}
+
+// In chunks, with the same rounding as the render distance.
+fn unload_distance(config: &VoxelsConfig) -> (i64, i64) {
+    let chunks = |distance: usize| distance.div_ceil(config.chunk_size) as i64;
+    (
+        chunks(config.unload_distance_horizontal)
+            .max(chunks(config.render_distance_horizontal) + 1),
+        chunks(config.unload_distance_vertical).max(chunks(config.render_distance_vertical) + 1),
+    )
+}
+
+fn timing_stats(history: &VecDeque<ChunkTimings>) -> ChunkTimingStats {
+    let mut stats = ChunkTimingStats::default();
+    for timings in history {
+        stats.average.heightmap_us += timings.heightmap_us;
+        stats.average.svo_us += timings.svo_us;
+        stats.average.mesh_us += timings.mesh_us;
+        stats.max.heightmap_us = stats.max.heightmap_us.max(timings.heightmap_us);
+        stats.max.svo_us = stats.max.svo_us.max(timings.svo_us);
+        stats.max.mesh_us = stats.max.mesh_us.max(timings.mesh_us);
+    }
+    let count = history.len().max(1) as u64;
+    stats.average.heightmap_us /= count;
+    stats.average.svo_us /= count;
+    stats.average.mesh_us /= count;
+    stats
+}
+
+pub fn chunk_from_position(position: Vector3<f32>, chunk_size: usize) -> Vector3<i64> {
     position.map(|coord| coord.div_euclid(chunk_size as f32) as i64)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn aabb_collision() {
+        let voxels = Voxels::from_fn(8, &[Vector3::zeros()], |voxel| voxel.z < 2);
+        let collide = |min: [f32; 3], max: [f32; 3]| {
+            voxels.collide_aabb(Vector3::from(min), Vector3::from(max))
+        };
+        assert_eq!(collide([1., 1., 2.], [2., 2., 4.]), VoxelCollision::Free);
+        assert_eq!(collide([1., 1., 1.9], [2., 2., 4.]), VoxelCollision::Solid);
+        // Touching the floor exactly isn't a collision, as the boxes only share a face.
+        assert_eq!(
+            collide([1.5, 1.5, 2.], [1.6, 1.6, 2.5]),
+            VoxelCollision::Free
+        );
+        assert_eq!(
+            collide([7., 7., 2.], [8.5, 8., 3.]),
+            VoxelCollision::Unloaded
+        );
+        assert_eq!(collide([7., 7., 1.], [8.5, 8., 3.]), VoxelCollision::Solid);
+    }
+}
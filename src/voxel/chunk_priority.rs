@@ -8,6 +8,20 @@ pub trait ChunkPriorityAlgorithm {
 
     fn update_camera(&mut self, camera: Vector3<i64>);
 
+    // Chunks that will be selected next, without the ones further out that aren't queued yet.
+    fn queued(&self) -> &[Vector3<i64>];
+
+    // Makes a chunk that was already selected get selected again, for when generating it failed.
+    fn retry(&mut self, chunk: Vector3<i64>);
+
+    // Makes a chunk that was evicted from GPU memory get selected again, but only once the area
+    // around it is queued again because of camera movement.
+    fn unload(&mut self, chunk: Vector3<i64>);
+
+    // Forgets all selected chunks outside the given area and returns them, so that they get
+    // selected again once they are back within the render distance.
+    fn unload_outside(&mut self, outside: &dyn Fn(Vector3<i64>) -> bool) -> Vec<Vector3<i64>>;
+
     fn clear(
         &mut self,
         camera: Vector3<i64>,
@@ -97,6 +111,10 @@ impl ChunkPriorityAlgorithm for ChunkPriority {
         }
     }
 
+    fn queued(&self) -> &[Vector3<i64>] {
+        &self.queue
+    }
+
     fn update_camera(&mut self, camera: Vector3<i64>) {
         self.camera = camera;
         if !self.stable.contains(camera) {
@@ -105,6 +123,36 @@ impl ChunkPriorityAlgorithm for ChunkPriority {
         }
     }
 
+    // The chunk is also unmarked as loaded, so that it gets queued again if the queue is cleared by
+    // camera movement before it's selected.
+    fn retry(&mut self, chunk: Vector3<i64>) {
+        self.loaded.remove(&chunk);
+        self.queue.push(chunk);
+    }
+
+    fn unload(&mut self, chunk: Vector3<i64>) {
+        self.loaded.remove(&chunk);
+    }
+
+    // Unlike with evictions, the area selected so far is forgotten too, as the camera can come back
+    // to the unloaded chunks without ever leaving it.
+    fn unload_outside(&mut self, outside: &dyn Fn(Vector3<i64>) -> bool) -> Vec<Vector3<i64>> {
+        let unloaded: Vec<_> = self
+            .loaded
+            .iter()
+            .copied()
+            .filter(|chunk| outside(*chunk))
+            .collect();
+        if !unloaded.is_empty() {
+            for chunk in &unloaded {
+                self.loaded.remove(chunk);
+            }
+            self.stable = Cuboid::new_empty();
+            self.queue.clear();
+        }
+        unloaded
+    }
+
     fn clear(
         &mut self,
         camera: Vector3<i64>,
@@ -1,18 +1,62 @@
+pub mod allocator;
 pub mod meshlets;
 
+use crate::voxel::gpu::allocator::PoolStats;
 use crate::voxel::local_mesh::LocalMesh;
-use crate::voxel::material::Material;
+use crate::voxel::material::VoxelMaterialId;
 use crate::voxel::sparse_octree::SparseOctree;
 use nalgebra::Vector3;
 
 pub trait VoxelGpuMemory: Send + 'static {
     fn prepare_func(&self) -> fn(LocalMesh, &SparseOctree, Vector3<i64>) -> Box<dyn std::any::Any>;
 
-    fn upload(&mut self, prepared: Box<dyn std::any::Any>);
+    // Replaces the previous mesh of the same chunk, if there was one. Chunks further from the
+    // camera than the uploaded one can be evicted to make space for it.
+    fn upload(&mut self, prepared: Box<dyn std::any::Any>, camera: Vector3<i64>) -> UploadResult;
+
+    // Frees the mesh of a chunk that got too far from the camera, if it has one. Same as with
+    // evictions, the memory is only reused once the frames in flight are done with it.
+    fn unload(&mut self, chunk: Vector3<i64>);
 
     fn clear(&mut self);
 
-    fn cleanup(&mut self);
+    // Finishes freeing memory of replaced chunks that frames in flight no longer use. Called every
+    // frame, so that it happens even when no new chunks are being uploaded.
+    fn reclaim(&mut self);
+
+    fn vertex_count(&self) -> usize;
+
+    fn triangle_count(&self) -> usize;
+
+    fn pool_stats(&self) -> Vec<(&'static str, PoolStats)>;
+
+    fn chunk_memory(&self) -> Vec<ChunkMemory>;
+
+    // Chunks evicted by uploads of other users of the same memory since the last call, which have
+    // to be generated again. Only the uploading side learns about evictions from the upload result.
+    fn take_evicted(&mut self) -> Vec<Vector3<i64>>;
+}
+
+// Accepts every upload without keeping anything, so that chunks can be generated without a
+// renderer in tests.
+#[cfg(test)]
+pub struct NullMemory;
+
+pub enum UploadResult {
+    Uploaded,
+    // Memory of evicted chunks can still be used by frames in flight, so the upload has to be
+    // retried later. The list can also be empty, if memory of chunks evicted earlier is enough.
+    Deferred { evicted: Vec<Vector3<i64>> },
+    // There was nothing further from the camera left to evict, so the chunk was left out.
+    OutOfMemory,
+}
+
+// GPU memory used by a single chunk's mesh, for inspecting chunks in the interface.
+#[derive(Clone, Copy, Debug)]
+pub struct ChunkMemory {
+    pub chunk: Vector3<i64>,
+    pub triangle_count: usize,
+    pub bytes: usize,
 }
 
 #[allow(dead_code)]
@@ -30,7 +74,7 @@ pub struct SvoChild {
 
 impl SvoNode {
     pub const EMPTY_ROOT: SvoNode = SvoNode {
-        children: [SvoChild::new_uniform(Material::Air); 8],
+        children: [SvoChild::new_uniform(VoxelMaterialId::AIR); 8],
         parent: 0,
     };
 
@@ -40,9 +84,9 @@ impl SvoNode {
 }
 
 impl SvoChild {
-    pub const fn new_uniform(material: Material) -> SvoChild {
+    pub const fn new_uniform(material: VoxelMaterialId) -> SvoChild {
         SvoChild {
-            packed: (1 << 31) | (material as u8 as u32),
+            packed: (1 << 31) | (material.0 as u32),
         }
     }
 
@@ -51,3 +95,45 @@ impl SvoChild {
         SvoChild { packed: pointer }
     }
 }
+
+#[cfg(test)]
+impl VoxelGpuMemory for NullMemory {
+    fn prepare_func(&self) -> fn(LocalMesh, &SparseOctree, Vector3<i64>) -> Box<dyn std::any::Any> {
+        prepare_nothing
+    }
+
+    fn upload(&mut self, _: Box<dyn std::any::Any>, _: Vector3<i64>) -> UploadResult {
+        UploadResult::Uploaded
+    }
+
+    fn unload(&mut self, _: Vector3<i64>) {}
+
+    fn clear(&mut self) {}
+
+    fn reclaim(&mut self) {}
+
+    fn vertex_count(&self) -> usize {
+        0
+    }
+
+    fn triangle_count(&self) -> usize {
+        0
+    }
+
+    fn pool_stats(&self) -> Vec<(&'static str, PoolStats)> {
+        Vec::new()
+    }
+
+    fn chunk_memory(&self) -> Vec<ChunkMemory> {
+        Vec::new()
+    }
+
+    fn take_evicted(&mut self) -> Vec<Vector3<i64>> {
+        Vec::new()
+    }
+}
+
+#[cfg(test)]
+fn prepare_nothing(_: LocalMesh, _: &SparseOctree, _: Vector3<i64>) -> Box<dyn std::any::Any> {
+    Box::new(())
+}
@@ -0,0 +1,174 @@
+use std::ops::Range;
+
+// First-fit allocator handing out ranges of elements in a fixed-size buffer, so that chunks can be
+// freed individually without compacting the whole buffer. Nothing in here touches GPU memory, the
+// ranges are only used as offsets by the owner.
+pub struct RangeAllocator {
+    capacity: usize,
+    frames_in_flight: usize,
+    // Sorted by offset and never adjacent to each other, as neighbouring ranges are merged when
+    // they are returned.
+    free: Vec<Range<usize>>,
+    // Ranges freed by the CPU could still be read by frames in flight, so they are only returned
+    // to the free list once enough frames have been rendered since.
+    pending: Vec<(usize, Range<usize>)>,
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+pub struct PoolStats {
+    pub used: usize,
+    pub capacity: usize,
+    pub free_ranges: usize,
+    pub largest_free_range: usize,
+}
+
+impl RangeAllocator {
+    // The free list starts out as a single range covering the whole buffer, not as a list of every
+    // index in it.
+    #[allow(clippy::single_range_in_vec_init)]
+    pub fn new(capacity: usize, frames_in_flight: usize) -> RangeAllocator {
+        RangeAllocator {
+            capacity,
+            frames_in_flight,
+            free: vec![0..capacity],
+            pending: Vec::new(),
+        }
+    }
+
+    pub fn alloc(&mut self, size: usize, frame_index: usize) -> Option<Range<usize>> {
+        self.reclaim(frame_index);
+        if size == 0 {
+            return Some(0..0);
+        }
+        let index = self.free.iter().position(|range| range.len() >= size)?;
+        let range = &mut self.free[index];
+        let allocated = range.start..range.start + size;
+        range.start += size;
+        if range.start == range.end {
+            self.free.remove(index);
+        }
+        Some(allocated)
+    }
+
+    pub fn free(&mut self, range: Range<usize>, frame_index: usize) {
+        if !range.is_empty() {
+            self.pending.push((frame_index, range));
+        }
+    }
+
+    // End of the last range that is or might still be in use. Elements past it are never read by
+    // the GPU, so it's used as the element count for dispatches covering the whole buffer.
+    pub fn high_watermark(&self) -> usize {
+        match self.free.last() {
+            Some(last) if last.end == self.capacity => last.start,
+            _ => self.capacity,
+        }
+    }
+
+    // Space that is free or will be once the frames in flight that could use it finish, so unlike
+    // the used count this doesn't change by waiting.
+    pub fn available(&self) -> usize {
+        let free: usize = self.free.iter().map(Range::len).sum();
+        let pending: usize = self.pending.iter().map(|(_, range)| range.len()).sum();
+        free + pending
+    }
+
+    pub fn has_pending(&self) -> bool {
+        !self.pending.is_empty()
+    }
+
+    pub fn used(&self) -> usize {
+        self.capacity - self.free.iter().map(Range::len).sum::<usize>()
+    }
+
+    pub fn stats(&self) -> PoolStats {
+        PoolStats {
+            used: self.used(),
+            capacity: self.capacity,
+            free_ranges: self.free.len(),
+            largest_free_range: self.free.iter().map(Range::len).max().unwrap_or(0),
+        }
+    }
+
+    // The renderer waits for the frame as many frames back as there are frames in flight to finish
+    // before recording a new one, so after that many more frames no command buffer can refer to
+    // the old contents.
+    fn reclaim(&mut self, frame_index: usize) {
+        let mut index = 0;
+        while index < self.pending.len() {
+            if self.pending[index].0 + self.frames_in_flight < frame_index {
+                let (_, range) = self.pending.swap_remove(index);
+                self.insert_free(range);
+            } else {
+                index += 1;
+            }
+        }
+    }
+
+    fn insert_free(&mut self, range: Range<usize>) {
+        let index = self.free.partition_point(|other| other.start < range.start);
+        let merges_previous = index > 0 && self.free[index - 1].end == range.start;
+        let merges_next = index < self.free.len() && self.free[index].start == range.end;
+        match (merges_previous, merges_next) {
+            (true, true) => {
+                self.free[index - 1].end = self.free[index].end;
+                self.free.remove(index);
+            }
+            (true, false) => self.free[index - 1].end = range.end,
+            (false, true) => self.free[index].start = range.start,
+            (false, false) => self.free.insert(index, range),
+        }
+    }
+}
+
+impl PoolStats {
+    // Fraction of free space that is outside of the largest free range, so zero when all free
+    // space is contiguous and close to one when it's split into many small pieces.
+    pub fn fragmentation(&self) -> f32 {
+        let free = self.capacity - self.used;
+        if free == 0 {
+            return 0.;
+        }
+        1. - self.largest_free_range as f32 / free as f32
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn free_merges_neighbours() {
+        let mut allocator = RangeAllocator::new(30, 2);
+        let a = allocator.alloc(10, 0).unwrap();
+        let b = allocator.alloc(10, 0).unwrap();
+        let c = allocator.alloc(10, 0).unwrap();
+        allocator.free(a, 0);
+        allocator.free(c, 0);
+        allocator.alloc(0, 3);
+        assert_eq!(allocator.stats().free_ranges, 2);
+        allocator.free(b, 3);
+        allocator.alloc(0, 6);
+        assert_eq!(allocator.free, vec![0..30]);
+        assert_eq!(allocator.used(), 0);
+    }
+
+    #[test]
+    fn reclaim_waits_for_frames_in_flight() {
+        let mut allocator = RangeAllocator::new(10, 2);
+        let range = allocator.alloc(10, 0).unwrap();
+        allocator.free(range, 5);
+        assert_eq!(allocator.alloc(10, 7), None);
+        assert_eq!(allocator.alloc(10, 8), Some(0..10));
+    }
+
+    #[test]
+    fn exhaustion() {
+        let mut allocator = RangeAllocator::new(10, 2);
+        assert_eq!(allocator.alloc(6, 0), Some(0..6));
+        assert_eq!(allocator.alloc(6, 0), None);
+        assert_eq!(allocator.alloc(4, 0), Some(6..10));
+        assert_eq!(allocator.alloc(1, 0), None);
+        assert_eq!(allocator.high_watermark(), 10);
+    }
+}
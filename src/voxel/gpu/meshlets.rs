@@ -1,111 +1,408 @@
+use crate::config::{
+    DEFAULT_VOXEL_MESHLET_MAX_COUNT, DEFAULT_VOXEL_TRIANGLE_MAX_COUNT,
+    DEFAULT_VOXEL_VERTEX_MAX_COUNT,
+};
 use crate::renderer::util::{Dev, StorageBuffer};
-use crate::voxel::gpu::{SvoChild, SvoNode, VoxelGpuMemory};
+use crate::voxel::gpu::allocator::{PoolStats, RangeAllocator};
+use crate::voxel::gpu::{ChunkMemory, SvoChild, SvoNode, UploadResult, VoxelGpuMemory};
 use crate::voxel::local_mesh::LocalMesh;
 use crate::voxel::meshlet;
 use crate::voxel::meshlet::{VoxelMesh, VoxelMeshlet, VoxelTriangle, VoxelVertex};
 use crate::voxel::sparse_octree::SparseOctree;
+use log::{info, warn};
 use nalgebra::Vector3;
-use std::mem::MaybeUninit;
-use std::sync::atomic::{AtomicU32, Ordering};
-use std::sync::Arc;
+use std::collections::HashMap;
+use std::mem::{size_of, MaybeUninit};
+use std::ops::Range;
+use std::sync::atomic::{AtomicU32, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
 
+// All chunks share the same vertex, triangle and meshlet buffers, which are bound once in the global
+// descriptor set, so drawing all loaded chunks is a single mesh shader dispatch over the meshlet
+// buffer. Chunks get their own ranges of each buffer, so that they can be replaced or unloaded
+// without touching the others. There is no separate chunk table on the GPU, as meshlets already
+// carry absolute offsets into the other buffers along with their chunk. Meshlets of unloaded chunks
+// are overwritten with empty ones, as the dispatch covers everything up to the last used meshlet.
+//
+// The buffers have a fixed capacity and never grow. Growing them would mean recreating the buffers
+// and rewriting the global descriptor sets of every frame in flight, so for now a chunk that doesn't
+// fit evicts the chunks furthest from the camera instead, and the capacities are set generously in
+// the config.
+//
+// The buffers are also shared between sessions, with every meshlet tagged with the session it belongs
+// to, and the shaders skipping meshlets of sessions other than the active one. This keeps inactive
+// sessions resident, so that switching back to them is instant, while still letting the active one
+// take their memory when it runs out.
 pub struct VoxelMeshletMemory {
     meshlet_count: Arc<AtomicU32>,
+    frame_index: Arc<AtomicUsize>,
     vertex_buffer: StorageBuffer<[VoxelVertex]>,
-    vertex_count: usize,
+    vertex_allocator: RangeAllocator,
     triangle_buffer: StorageBuffer<[VoxelTriangle]>,
-    triangle_count: usize,
+    triangle_allocator: RangeAllocator,
     meshlet_buffer: StorageBuffer<[VoxelMeshlet]>,
+    meshlet_allocator: RangeAllocator,
+    // Meshlet ranges of replaced chunks, with the frame index they were freed at. Frames in flight
+    // may still draw them, so they are only emptied and given back to the allocator once those
+    // frames are finished.
+    retired_meshlets: Vec<(usize, Range<usize>)>,
     octree_buffer: StorageBuffer<[SvoNode]>,
-    wrote_octree: bool,
+    chunks: HashMap<(u8, Vector3<i64>), ChunkAllocation>,
+    // Limit on the total size of chunk meshes in bytes, for simulating GPUs with less memory. The
+    // buffers are still allocated at full size, so without a limit the pools are what runs out.
+    budget: Option<usize>,
+    used_bytes: usize,
+    frames_in_flight: usize,
+    active_session: u8,
+    // The octree buffer only has room for a single chunk, so the octree of the chunk at the origin
+    // is kept for every session and written again when switching to it.
+    octrees: HashMap<u8, SparseOctree>,
+    // Chunks evicted by uploads of other sessions, waiting for their session to pick them up.
+    evicted: HashMap<u8, Vec<Vector3<i64>>>,
     dev: Dev,
 }
 
+// View of the shared memory for a single session, handed to the session's voxel workers.
+pub struct VoxelSessionMemory {
+    memory: Arc<Mutex<VoxelMeshletMemory>>,
+    session: u8,
+}
+
+struct ChunkAllocation {
+    vertices: Range<usize>,
+    triangles: Range<usize>,
+    meshlets: Range<usize>,
+}
+
+const EMPTY_MESHLET: VoxelMeshlet = VoxelMeshlet {
+    vertex_offset: 0,
+    vertex_count: 0,
+    triangle_offset: 0,
+    triangle_count: 0,
+    chunk: Vector3::new(0, 0, 0),
+    _pad0: 0,
+    bound_base: Vector3::new(0, 0, 0),
+    normals: 0,
+    bound_size: Vector3::new(0, 0, 0),
+    session: 0,
+};
+
 impl VoxelMeshletMemory {
     pub fn new(
         meshlet_count: Arc<AtomicU32>,
+        frame_index: Arc<AtomicUsize>,
         vertex_buffer: StorageBuffer<[VoxelVertex]>,
         triangle_buffer: StorageBuffer<[VoxelTriangle]>,
         meshlet_buffer: StorageBuffer<[VoxelMeshlet]>,
         octree_buffer: StorageBuffer<[SvoNode]>,
+        budget: Option<usize>,
+        frames_in_flight: usize,
         dev: Dev,
     ) -> VoxelMeshletMemory {
         VoxelMeshletMemory {
             meshlet_count,
+            frame_index,
+            vertex_allocator: RangeAllocator::new(DEFAULT_VOXEL_VERTEX_MAX_COUNT, frames_in_flight),
             vertex_buffer,
-            vertex_count: 0,
+            triangle_allocator: RangeAllocator::new(
+                DEFAULT_VOXEL_TRIANGLE_MAX_COUNT,
+                frames_in_flight,
+            ),
             triangle_buffer,
-            triangle_count: 0,
+            meshlet_allocator: RangeAllocator::new(
+                DEFAULT_VOXEL_MESHLET_MAX_COUNT,
+                frames_in_flight,
+            ),
             meshlet_buffer,
+            retired_meshlets: Vec::new(),
             octree_buffer,
-            wrote_octree: false,
+            chunks: HashMap::new(),
+            budget,
+            used_bytes: 0,
+            frames_in_flight,
+            active_session: 0,
+            octrees: HashMap::new(),
+            evicted: HashMap::new(),
             dev,
         }
     }
-}
 
-impl VoxelGpuMemory for VoxelMeshletMemory {
-    fn prepare_func(&self) -> fn(LocalMesh, &SparseOctree, Vector3<i64>) -> Box<dyn std::any::Any> {
-        |mesh, octree, chunk| Box::new(prepare(mesh, octree, chunk))
+    // Allocates all three ranges or none of them.
+    fn alloc_chunk(&mut self, mesh: &VoxelMesh, frame_index: usize) -> Option<ChunkAllocation> {
+        self.reclaim_meshlets(frame_index);
+        if !self.within_budget(mesh_bytes(mesh)) {
+            return None;
+        }
+        let vertices = self
+            .vertex_allocator
+            .alloc(mesh.vertices.len(), frame_index)?;
+        let Some(triangles) = self
+            .triangle_allocator
+            .alloc(mesh.triangles.len(), frame_index)
+        else {
+            self.vertex_allocator.free(vertices, frame_index);
+            return None;
+        };
+        let Some(meshlets) = self
+            .meshlet_allocator
+            .alloc(mesh.meshlets.len(), frame_index)
+        else {
+            self.vertex_allocator.free(vertices, frame_index);
+            self.triangle_allocator.free(triangles, frame_index);
+            return None;
+        };
+        Some(ChunkAllocation {
+            vertices,
+            triangles,
+            meshlets,
+        })
+    }
+
+    fn free_chunk(&mut self, allocation: ChunkAllocation, frame_index: usize) {
+        self.used_bytes -= allocation.bytes();
+        self.vertex_allocator.free(allocation.vertices, frame_index);
+        self.triangle_allocator
+            .free(allocation.triangles, frame_index);
+        if !allocation.meshlets.is_empty() {
+            self.retired_meshlets
+                .push((frame_index, allocation.meshlets));
+        }
+    }
+
+    // Uses the same condition as the allocator, so the emptied ranges are handed to it with their
+    // original frame index and become available right away.
+    fn reclaim_meshlets(&mut self, frame_index: usize) {
+        let mut index = 0;
+        while index < self.retired_meshlets.len() {
+            if self.retired_meshlets[index].0 + self.frames_in_flight < frame_index {
+                let (freed_at, range) = self.retired_meshlets.swap_remove(index);
+                for meshlet in &mut self.meshlet_buffer.mapped()[range.clone()] {
+                    meshlet.write(EMPTY_MESHLET);
+                }
+                self.meshlet_allocator.free(range, freed_at);
+            } else {
+                index += 1;
+            }
+        }
+    }
+
+    fn retired_meshlet_count(&self) -> usize {
+        self.retired_meshlets
+            .iter()
+            .map(|(_, range)| range.len())
+            .sum()
+    }
+
+    fn within_budget(&self, bytes: usize) -> bool {
+        self.budget
+            .is_none_or(|budget| self.used_bytes + bytes <= budget)
     }
 
-    fn upload(&mut self, mesh: Box<dyn std::any::Any>) {
-        let mut mesh = mesh.downcast::<VoxelMesh>().unwrap();
-        let old_meshlet_count = self.meshlet_count.load(Ordering::SeqCst) as usize;
-        let new_vertex_count = self.vertex_count + mesh.vertices.len();
-        let new_triangle_count = self.triangle_count + mesh.triangles.len();
-        let new_meshlet_count = (old_meshlet_count as u32)
-            .checked_add(mesh.meshlets.len() as u32)
-            .unwrap() as usize;
+    // Whether the mesh will fit once memory freed recently stops being used by frames in flight.
+    // Fragmentation is ignored, so this can be wrong, but then the next attempt just evicts more.
+    fn will_fit(&self, mesh: &VoxelMesh) -> bool {
+        self.within_budget(mesh_bytes(mesh))
+            && self.vertex_allocator.available() >= mesh.vertices.len()
+            && self.triangle_allocator.available() >= mesh.triangles.len()
+            && self.meshlet_allocator.available() + self.retired_meshlet_count()
+                >= mesh.meshlets.len()
+    }
+
+    // Evicts the chunks furthest from the camera until the mesh will fit. Only chunks further than
+    // the uploaded one are considered, as otherwise the evicted chunks would get generated again
+    // right away and evict this one in turn. Chunks of inactive sessions go first regardless of
+    // distance, and chunks of the active session are never evicted for another session. Returns
+    // the evicted chunks of the uploading session, the others are queued for their own sessions.
+    fn evict_for(
+        &mut self,
+        session: u8,
+        mesh: &VoxelMesh,
+        camera: Vector3<i64>,
+        frame_index: usize,
+    ) -> Vec<Vector3<i64>> {
+        let distance = |chunk: Vector3<i64>| (chunk - camera).map(|coord| coord * coord).sum();
+        let active_session = self.active_session;
+        let mut candidates: Vec<_> = self
+            .chunks
+            .keys()
+            .copied()
+            .filter(|(owner, chunk)| {
+                if *owner == session {
+                    distance(*chunk) > distance(mesh.chunk)
+                } else {
+                    *owner != active_session
+                }
+            })
+            .collect();
+        candidates.sort_by_key(|(owner, chunk)| (*owner != session, distance(*chunk)));
+        let mut evicted = Vec::new();
+        while !self.will_fit(mesh) {
+            let Some((owner, chunk)) = candidates.pop() else {
+                break;
+            };
+            let allocation = self.chunks.remove(&(owner, chunk)).unwrap();
+            info!(
+                "voxel chunk evicted, \x1B[1mchunk\x1B[0m: {} {} {}, \x1B[1mbytes\x1B[0m: {}, \x1B[1mfor\x1B[0m: {} {} {}, \x1B[1msession\x1B[0m: {owner}",
+                chunk.x,
+                chunk.y,
+                chunk.z,
+                allocation.bytes(),
+                mesh.chunk.x,
+                mesh.chunk.y,
+                mesh.chunk.z
+            );
+            self.free_chunk(allocation, frame_index);
+            if owner == session {
+                evicted.push(chunk);
+            } else {
+                self.evicted.entry(owner).or_default().push(chunk);
+            }
+        }
+        evicted
+    }
+
+    fn update_meshlet_count(&self) {
+        self.meshlet_count.store(
+            self.meshlet_allocator.high_watermark() as u32,
+            Ordering::SeqCst,
+        );
+    }
+
+    fn upload(&mut self, session: u8, mut mesh: VoxelMesh, camera: Vector3<i64>) -> UploadResult {
+        let frame_index = self.frame_index.load(Ordering::SeqCst);
+        // The previous mesh of the chunk is only freed once the new one has memory, so that a
+        // failed upload leaves the chunk outdated rather than missing. Its ranges are still in use
+        // by the frames in flight anyway, so freeing it first wouldn't help the allocation.
+        let Some(allocation) = self.alloc_chunk(&mesh, frame_index) else {
+            // Without any memory waiting for frames in flight, fitting but failing to allocate means
+            // the pools are too fragmented, and waiting wouldn't change anything.
+            let waiting = self.vertex_allocator.has_pending()
+                || self.triangle_allocator.has_pending()
+                || self.meshlet_allocator.has_pending()
+                || !self.retired_meshlets.is_empty();
+            let result = if waiting && self.will_fit(&mesh) {
+                UploadResult::Deferred {
+                    evicted: Vec::new(),
+                }
+            } else {
+                let chunk_count = self.chunks.len();
+                let evicted = self.evict_for(session, &mesh, camera, frame_index);
+                if self.chunks.len() == chunk_count {
+                    warn!(
+                        "voxel gpu memory exhausted, \x1B[1mchunk\x1B[0m: {} {} {}",
+                        mesh.chunk.x, mesh.chunk.y, mesh.chunk.z
+                    );
+                    UploadResult::OutOfMemory
+                } else {
+                    UploadResult::Deferred { evicted }
+                }
+            };
+            self.update_meshlet_count();
+            return result;
+        };
 
         // The argument uses offsets local to the chunk mesh because the generation shouldn't deal
         // with the multithreading directly, so we need to fix them up now. Indices are local to the
         // meshlet, so they don't need to be fixed.
         for meshlet in &mut mesh.meshlets {
-            meshlet.vertex_offset += self.vertex_count as u32;
-            meshlet.triangle_offset += self.triangle_count as u32;
+            meshlet.vertex_offset += allocation.vertices.start as u32;
+            meshlet.triangle_offset += allocation.triangles.start as u32;
+            meshlet.session = session;
         }
 
-        let vertex_memory = &mut self.vertex_buffer.mapped()[self.vertex_count..new_vertex_count];
+        let vertex_memory = &mut self.vertex_buffer.mapped()[allocation.vertices.clone()];
         for (vertex_memory, mesh_vertex) in vertex_memory.iter_mut().zip(mesh.vertices.iter()) {
             vertex_memory.write(*mesh_vertex);
         }
 
-        let triangle_memory =
-            &mut self.triangle_buffer.mapped()[self.triangle_count..new_triangle_count];
+        let triangle_memory = &mut self.triangle_buffer.mapped()[allocation.triangles.clone()];
         for (triangle_memory, mesh_triangle) in
             triangle_memory.iter_mut().zip(mesh.triangles.iter())
         {
             triangle_memory.write(*mesh_triangle);
         }
 
-        let meshlet_memory =
-            &mut self.meshlet_buffer.mapped()[old_meshlet_count..new_meshlet_count];
+        let meshlet_memory = &mut self.meshlet_buffer.mapped()[allocation.meshlets.clone()];
         for (meshlet_memory, mesh_meshlet) in meshlet_memory.iter_mut().zip(mesh.meshlets.iter()) {
             meshlet_memory.write(*mesh_meshlet);
         }
 
         if mesh.chunk == Vector3::zeros() {
-            let octree_memory = self.octree_buffer.mapped();
-            write_octree(&mesh.octree, octree_memory);
-            self.wrote_octree = true;
+            if session == self.active_session {
+                write_octree(&mesh.octree, self.octree_buffer.mapped());
+            }
+            self.octrees.insert(session, mesh.octree);
         }
 
-        self.vertex_count = new_vertex_count;
-        self.triangle_count = new_triangle_count;
-        self.meshlet_count
-            .store(new_meshlet_count as u32, Ordering::SeqCst);
+        self.used_bytes += allocation.bytes();
+        if let Some(old) = self.chunks.insert((session, mesh.chunk), allocation) {
+            self.free_chunk(old, frame_index);
+        }
+        self.update_meshlet_count();
+        UploadResult::Uploaded
     }
 
-    fn clear(&mut self) {
-        // Holding the lock while updating the atomic is necessary, so leftover operations don't
-        // mess up.
-        self.vertex_count = 0;
-        self.triangle_count = 0;
-        self.meshlet_count.store(0, Ordering::SeqCst);
+    fn unload(&mut self, session: u8, chunk: Vector3<i64>) {
+        let Some(allocation) = self.chunks.remove(&(session, chunk)) else {
+            return;
+        };
+        let frame_index = self.frame_index.load(Ordering::SeqCst);
+        self.free_chunk(allocation, frame_index);
+        self.update_meshlet_count();
+    }
+
+    // Frees every chunk of the session. Other sessions keep their chunks, so unlike unloading
+    // everything this can't reset the allocators, and the memory only becomes available once the
+    // frames in flight are done with it.
+    fn clear(&mut self, session: u8) {
+        let frame_index = self.frame_index.load(Ordering::SeqCst);
+        let chunks: Vec<_> = self
+            .chunks
+            .keys()
+            .copied()
+            .filter(|(owner, _)| *owner == session)
+            .collect();
+        for key in chunks {
+            let allocation = self.chunks.remove(&key).unwrap();
+            self.free_chunk(allocation, frame_index);
+        }
+        self.octrees.remove(&session);
+        self.evicted.remove(&session);
+        self.update_meshlet_count();
+    }
+
+    fn reclaim(&mut self) {
+        self.reclaim_meshlets(self.frame_index.load(Ordering::SeqCst));
+    }
+
+    // Switching only changes which meshlets the shaders skip, other than the octree which only has
+    // room for a single session.
+    pub fn set_active_session(&mut self, session: u8) {
+        self.active_session = session;
+        if let Some(octree) = self.octrees.get(&session) {
+            write_octree(octree, self.octree_buffer.mapped());
+        }
+    }
+
+    fn session_chunks(
+        &self,
+        session: u8,
+    ) -> impl Iterator<Item = (Vector3<i64>, &ChunkAllocation)> {
+        self.chunks
+            .iter()
+            .filter(move |((owner, _), _)| *owner == session)
+            .map(|((_, chunk), allocation)| (*chunk, allocation))
     }
 
-    fn cleanup(&mut self) {
+    fn pool_stats(&self) -> Vec<(&'static str, PoolStats)> {
+        vec![
+            ("Vertices", self.vertex_allocator.stats()),
+            ("Triangles", self.triangle_allocator.stats()),
+            ("Meshlets", self.meshlet_allocator.stats()),
+        ]
+    }
+
+    pub fn cleanup(&mut self) {
         self.vertex_buffer.cleanup(&self.dev);
         self.triangle_buffer.cleanup(&self.dev);
         self.meshlet_buffer.cleanup(&self.dev);
@@ -113,6 +410,103 @@ impl VoxelGpuMemory for VoxelMeshletMemory {
     }
 }
 
+impl VoxelSessionMemory {
+    pub fn new(memory: Arc<Mutex<VoxelMeshletMemory>>, session: u8) -> VoxelSessionMemory {
+        VoxelSessionMemory { memory, session }
+    }
+
+    // Same as for the voxel state, a panic while holding the lock leaves the memory usable.
+    fn lock(&self) -> MutexGuard<VoxelMeshletMemory> {
+        self.memory.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl VoxelGpuMemory for VoxelSessionMemory {
+    fn prepare_func(&self) -> fn(LocalMesh, &SparseOctree, Vector3<i64>) -> Box<dyn std::any::Any> {
+        |mesh, octree, chunk| Box::new(prepare(mesh, octree, chunk))
+    }
+
+    fn upload(&mut self, mesh: Box<dyn std::any::Any>, camera: Vector3<i64>) -> UploadResult {
+        let mesh = *mesh.downcast::<VoxelMesh>().unwrap();
+        let session = self.session;
+        self.lock().upload(session, mesh, camera)
+    }
+
+    fn unload(&mut self, chunk: Vector3<i64>) {
+        let session = self.session;
+        self.lock().unload(session, chunk);
+    }
+
+    fn clear(&mut self) {
+        let session = self.session;
+        self.lock().clear(session);
+    }
+
+    // The memory is shared, so this also reclaims meshlets of the other sessions.
+    fn reclaim(&mut self) {
+        self.lock().reclaim();
+    }
+
+    fn vertex_count(&self) -> usize {
+        self.lock()
+            .session_chunks(self.session)
+            .map(|(_, allocation)| allocation.vertices.len())
+            .sum()
+    }
+
+    fn triangle_count(&self) -> usize {
+        self.lock()
+            .session_chunks(self.session)
+            .map(|(_, allocation)| allocation.triangles.len())
+            .sum()
+    }
+
+    // The pools are shared by all sessions, so these include the inactive ones.
+    fn pool_stats(&self) -> Vec<(&'static str, PoolStats)> {
+        self.lock().pool_stats()
+    }
+
+    fn chunk_memory(&self) -> Vec<ChunkMemory> {
+        self.lock()
+            .session_chunks(self.session)
+            .map(|(chunk, allocation)| ChunkMemory {
+                chunk,
+                triangle_count: allocation.triangles.len(),
+                bytes: allocation.bytes(),
+            })
+            .collect()
+    }
+
+    fn take_evicted(&mut self) -> Vec<Vector3<i64>> {
+        let session = self.session;
+        self.lock().evicted.remove(&session).unwrap_or_default()
+    }
+}
+
+impl ChunkAllocation {
+    fn bytes(&self) -> usize {
+        element_bytes(
+            self.vertices.len(),
+            self.triangles.len(),
+            self.meshlets.len(),
+        )
+    }
+}
+
+fn mesh_bytes(mesh: &VoxelMesh) -> usize {
+    element_bytes(
+        mesh.vertices.len(),
+        mesh.triangles.len(),
+        mesh.meshlets.len(),
+    )
+}
+
+fn element_bytes(vertices: usize, triangles: usize, meshlets: usize) -> usize {
+    vertices * size_of::<VoxelVertex>()
+        + triangles * size_of::<VoxelTriangle>()
+        + meshlets * size_of::<VoxelMeshlet>()
+}
+
 fn prepare(raw_mesh: LocalMesh, svo: &SparseOctree, chunk: Vector3<i64>) -> VoxelMesh {
     let mut mesh = meshlet::from_unclustered_mesh(&raw_mesh, svo, chunk);
     for meshlet in &mut mesh.meshlets {
@@ -1,4 +1,4 @@
-use crate::voxel::material::Material;
+use crate::voxel::material::VoxelMaterialId;
 use nalgebra::Vector3;
 use std::collections::{hash_map, HashMap};
 
@@ -7,17 +7,19 @@ pub struct LocalMesh {
     pub faces: Vec<LocalFace>,
 }
 
+// Materials are stored per vertex rather than per face, so that deduplication only merges vertices
+// of faces with the same material.
 #[derive(Clone, Debug, Eq, Hash, PartialEq)]
 pub struct LocalVertex {
     pub position: Vector3<u8>,
     pub ambient_occlusion: u8,
+    pub material: VoxelMaterialId,
 }
 
 #[derive(Clone)]
 pub struct LocalFace {
     pub indices: [u32; 4],
     pub normal_index: u8,
-    pub material: Material,
 }
 
 impl LocalMesh {
@@ -46,9 +48,36 @@ impl LocalMesh {
                     .indices
                     .map(|index| mapping[&self.vertices[index as usize]]),
                 normal_index: face.normal_index,
-                material: face.material,
             });
         }
         LocalMesh { vertices, faces }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn vertices_of_different_materials_stay_separate() {
+        let vertex = |material| LocalVertex {
+            position: Vector3::new(1, 2, 3),
+            ambient_occlusion: 0,
+            material,
+        };
+        let mesh = LocalMesh {
+            vertices: vec![
+                vertex(VoxelMaterialId::GRASS),
+                vertex(VoxelMaterialId::GRASS),
+                vertex(VoxelMaterialId::STONE),
+            ],
+            faces: vec![LocalFace {
+                indices: [0, 1, 2, 1],
+                normal_index: 0,
+            }],
+        };
+        let mesh = mesh.remove_duplicate_vertices();
+        assert_eq!(mesh.vertices.len(), 2);
+        assert_eq!(mesh.faces[0].indices, [0, 0, 1, 0]);
+    }
+}
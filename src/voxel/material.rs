@@ -1,14 +1,41 @@
-#[derive(Clone, Copy, Debug, Eq, PartialEq)]
-#[repr(u8)]
-pub enum Material {
-    Air = 0,
-    Stone = 1,
-    Dirt = 2,
-    Grass = 3,
-}
+// Stored for every voxel, and used as the index into the material palette when shading. Only the
+// first MATERIAL_COUNT ids have a palette entry, and the sparse octree format on the GPU only has
+// room for 32 of them.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+#[repr(transparent)]
+pub struct VoxelMaterialId(pub u8);
+
+pub const MATERIAL_COUNT: usize = 6;
+
+impl VoxelMaterialId {
+    pub const AIR: VoxelMaterialId = VoxelMaterialId(0);
+    pub const STONE: VoxelMaterialId = VoxelMaterialId(1);
+    pub const DIRT: VoxelMaterialId = VoxelMaterialId(2);
+    pub const GRASS: VoxelMaterialId = VoxelMaterialId(3);
+    pub const SAND: VoxelMaterialId = VoxelMaterialId(4);
+    pub const SNOW: VoxelMaterialId = VoxelMaterialId(5);
+
+    pub const SOLID: [VoxelMaterialId; MATERIAL_COUNT - 1] = [
+        VoxelMaterialId::STONE,
+        VoxelMaterialId::DIRT,
+        VoxelMaterialId::GRASS,
+        VoxelMaterialId::SAND,
+        VoxelMaterialId::SNOW,
+    ];
 
-impl Material {
     pub fn is_air(&self) -> bool {
-        matches!(self, Material::Air)
+        *self == VoxelMaterialId::AIR
+    }
+
+    pub fn label(&self) -> &'static str {
+        match *self {
+            VoxelMaterialId::AIR => "Air",
+            VoxelMaterialId::STONE => "Stone",
+            VoxelMaterialId::DIRT => "Dirt",
+            VoxelMaterialId::GRASS => "Grass",
+            VoxelMaterialId::SAND => "Sand",
+            VoxelMaterialId::SNOW => "Snow",
+            _ => "Unknown",
+        }
     }
 }
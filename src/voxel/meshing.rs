@@ -4,18 +4,20 @@ mod greedy_meshing;
 #[cfg(feature = "dev-menu")]
 use crate::interface::EnumInterface;
 use crate::voxel::local_mesh::LocalMesh;
-use crate::voxel::material::Material;
+use crate::voxel::material::VoxelMaterialId;
 use crate::voxel::meshing::culled_meshing::CulledMeshing;
 use crate::voxel::meshing::greedy_meshing::GreedyMeshing;
 use crate::voxel::neighbourhood::Neighbourhood;
 use crate::voxel::sparse_octree::SparseOctree;
 use crate::voxel::VoxelsConfig;
+use nalgebra::Vector3;
+use serde::{Deserialize, Serialize};
 
 trait MeshingAlgorithm {
     fn mesh(svos: &Neighbourhood, chunk_size: usize) -> LocalMesh;
 }
 
-#[derive(Clone, Copy, Eq, PartialEq)]
+#[derive(Clone, Copy, Deserialize, Eq, PartialEq, Serialize)]
 pub enum MeshingAlgorithmKind {
     Culled,
     Greedy,
@@ -39,10 +41,10 @@ pub fn generate_mesh(svos: &Neighbourhood, config: &VoxelsConfig) -> LocalMesh {
         kind: chunk_uniform,
     } = svos.chunk()
     {
-        if *chunk_uniform == Material::Air {
+        if *chunk_uniform == VoxelMaterialId::AIR {
             return LocalMesh::new_empty();
         }
-        if svos.neighbour_chunks_manhattan().iter().all(|neighbour_svo| matches!(neighbour_svo, SparseOctree::Uniform { kind } if *kind != Material::Air)) {
+        if svos.neighbour_chunks_manhattan().iter().all(|neighbour_svo| matches!(neighbour_svo, SparseOctree::Uniform { kind } if *kind != VoxelMaterialId::AIR)) {
             return LocalMesh::new_empty();
         }
     }
@@ -53,3 +55,138 @@ pub fn generate_mesh(svos: &Neighbourhood, config: &VoxelsConfig) -> LocalMesh {
     let mesh = meshing_algorithm(svos, config.chunk_size);
     mesh.remove_duplicate_vertices()
 }
+
+// Classic per-vertex ambient occlusion, based on the three voxels in front of the face that touch
+// the given corner. These are the two neighbours of the air voxel the face is looking into, in the
+// direction of the corner, and the voxel diagonal to it. The positions can reach one voxel into the
+// neighbouring chunks, so that there are no seams along chunk borders. Returns a value from 0 (not
+// occluded at all) to 3.
+fn vertex_ambient_occlusion(
+    svos: &Neighbourhood,
+    corner: Vector3<i64>,
+    air: Vector3<i64>,
+    normal: Vector3<i64>,
+) -> u8 {
+    let towards_corner = |axis: Vector3<i64>| {
+        if corner.dot(&axis) > air.dot(&axis) {
+            axis
+        } else {
+            -axis
+        }
+    };
+    let u = towards_corner(normal.zxy().abs());
+    let v = towards_corner(normal.yzx().abs());
+    let side1 = !svos.at(air + u).is_air();
+    let side2 = !svos.at(air + v).is_air();
+    let corner = !svos.at(air + u + v).is_air();
+    if side1 && side2 {
+        3
+    } else {
+        side1 as u8 + side2 as u8 + corner as u8
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::voxel::DIRECTIONS;
+    use std::sync::Arc;
+
+    const CHUNK_SIZE: i64 = 4;
+
+    // A single chunk with a stone floor and some blocks on top of it, surrounded by air.
+    fn svos(blocks: &[Vector3<i64>]) -> Vec<Arc<SparseOctree>> {
+        let chunk = SparseOctree::from_fn(CHUNK_SIZE, &|position| {
+            if position.y == 0 || blocks.contains(&position) {
+                VoxelMaterialId::STONE
+            } else {
+                VoxelMaterialId::AIR
+            }
+        });
+        let air = Arc::new(SparseOctree::Uniform {
+            kind: VoxelMaterialId::AIR,
+        });
+        let mut svos = vec![air; 27];
+        svos[13] = Arc::new(chunk);
+        svos
+    }
+
+    // Two blocks touching diagonally.
+    fn floor_ambient_occlusion(corner: [i64; 2], air: [i64; 2]) -> u8 {
+        let svos = svos(&[Vector3::new(1, 1, 1), Vector3::new(2, 1, 2)]);
+        let svos = Neighbourhood::new(&svos, CHUNK_SIZE);
+        vertex_ambient_occlusion(
+            &svos,
+            Vector3::new(corner[0], 1, corner[1]),
+            Vector3::new(air[0], 1, air[1]),
+            Vector3::new(0, 1, 0),
+        )
+    }
+
+    #[test]
+    fn ambient_occlusion_in_corner() {
+        assert_eq!(floor_ambient_occlusion([2, 2], [2, 1]), 3);
+    }
+
+    #[test]
+    fn ambient_occlusion_next_to_side() {
+        assert_eq!(floor_ambient_occlusion([3, 3], [3, 2]), 1);
+    }
+
+    #[test]
+    fn ambient_occlusion_next_to_diagonal() {
+        assert_eq!(floor_ambient_occlusion([3, 3], [3, 3]), 1);
+    }
+
+    #[test]
+    fn ambient_occlusion_unoccluded() {
+        assert_eq!(floor_ambient_occlusion([0, 3], [0, 3]), 0);
+        assert_eq!(floor_ambient_occlusion([4, 0], [3, 0]), 0);
+    }
+
+    #[test]
+    fn greedy_meshing_keeps_ambient_occlusion_apart() {
+        // A wall across the whole chunk, so that the rows of floor faces next to it have the same
+        // uneven occlusion along it.
+        let wall: Vec<_> = (0..CHUNK_SIZE).map(|x| Vector3::new(x, 1, 2)).collect();
+        let svos = svos(&wall);
+        let svos = Neighbourhood::new(&svos, CHUNK_SIZE);
+        let mesh = greedy_meshing::GreedyMeshing::mesh(&svos, CHUNK_SIZE as usize);
+        let mut merged_faces = 0;
+        for face in &mesh.faces {
+            let vertices = face.indices.map(|index| &mesh.vertices[index as usize]);
+            let positions = vertices.map(|vertex| vertex.position.cast::<i64>());
+            let min = positions.iter().fold(positions[0], |a, b| a.inf(b));
+            let max = positions.iter().fold(positions[0], |a, b| a.sup(b));
+            if max - min == Vector3::new(1, 1, 1) - DIRECTIONS[face.normal_index as usize].abs() {
+                continue;
+            }
+            merged_faces += 1;
+
+            // Every voxel face covered by the merged one has to have the same occlusion in all its
+            // corners as the merged face has.
+            let normal = DIRECTIONS[face.normal_index as usize];
+            let [u, v] = [normal.zxy().abs(), normal.yzx().abs()];
+            let air_offset = if normal.sum() > 0 {
+                Vector3::zeros()
+            } else {
+                normal
+            };
+            let ambient_occlusion = vertices[0].ambient_occlusion;
+            assert!(vertices
+                .iter()
+                .all(|vertex| vertex.ambient_occlusion == ambient_occlusion));
+            for i in 0..(max - min).dot(&u) {
+                for j in 0..(max - min).dot(&v) {
+                    let cell = min + i * u + j * v;
+                    for corner in [cell, cell + u, cell + v, cell + u + v] {
+                        let ao = vertex_ambient_occlusion(&svos, corner, cell + air_offset, normal);
+                        assert_eq!(ao, ambient_occlusion, "corner: {corner:?}");
+                    }
+                }
+            }
+        }
+        // The bottom of the floor is unoccluded, so it should still be merged.
+        assert!(merged_faces > 0);
+    }
+}
@@ -1,6 +1,7 @@
 use crate::voxel::binary_cube::BinaryCube;
 use crate::voxel::local_mesh::{LocalFace, LocalMesh, LocalVertex};
-use crate::voxel::meshing::MeshingAlgorithm;
+use crate::voxel::material::VoxelMaterialId;
+use crate::voxel::meshing::{vertex_ambient_occlusion, MeshingAlgorithm};
 use crate::voxel::neighbourhood::Neighbourhood;
 use crate::voxel::sparse_octree::SparseOctree;
 use crate::voxel::DIRECTIONS;
@@ -86,10 +87,11 @@ impl State<'_> {
         let i2 = base_index + 1;
         let i3 = base_index + 2;
         let i4 = base_index + 3;
-        let v1 = self.make_vertex(base, normal);
-        let v2 = self.make_vertex(base + rot1, normal);
-        let v3 = self.make_vertex(base + rot2, normal);
-        let v4 = self.make_vertex(base + rot1 + rot2, normal);
+        let air = position + normal;
+        let v1 = self.make_vertex(base, air, normal, material);
+        let v2 = self.make_vertex(base + rot1, air, normal, material);
+        let v3 = self.make_vertex(base + rot2, air, normal, material);
+        let v4 = self.make_vertex(base + rot1 + rot2, air, normal, material);
         let indices = if v1.ambient_occlusion + v4.ambient_occlusion
             >= v2.ambient_occlusion + v3.ambient_occlusion
         {
@@ -104,31 +106,20 @@ impl State<'_> {
         self.faces.push(LocalFace {
             indices,
             normal_index: normal_index as u8,
-            material,
         });
     }
 
-    fn make_vertex(&self, position: Vector3<i64>, normal: Vector3<i64>) -> LocalVertex {
-        // TODO: Not sure if correct for other normals than 0,0,1.
-        let occluder_base = if normal.sum() < 0 {
-            position + normal
-        } else {
-            position
-        };
-        let u = normal.zxy().abs();
-        let v = normal.yzx().abs();
-        let side1 = !self.svos.at(occluder_base - u).is_air();
-        let side2 = !self.svos.at(occluder_base - v).is_air();
-        let corner = (!self.svos.at(occluder_base).is_air())
-            || (!self.svos.at(occluder_base - u - v).is_air());
-        let ambient_occlusion = if side1 && side2 {
-            3
-        } else {
-            (if side1 { 1 } else { 0 }) + (if side2 { 1 } else { 0 }) + (if corner { 1 } else { 0 })
-        };
+    fn make_vertex(
+        &self,
+        position: Vector3<i64>,
+        air: Vector3<i64>,
+        normal: Vector3<i64>,
+        material: VoxelMaterialId,
+    ) -> LocalVertex {
         LocalVertex {
             position: position.try_cast::<u8>().unwrap(),
-            ambient_occlusion,
+            ambient_occlusion: vertex_ambient_occlusion(self.svos, position, air, normal),
+            material,
         }
     }
 }
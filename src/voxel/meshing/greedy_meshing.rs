@@ -1,6 +1,6 @@
 use crate::voxel::local_mesh::{LocalFace, LocalMesh, LocalVertex};
-use crate::voxel::material::Material;
-use crate::voxel::meshing::MeshingAlgorithm;
+use crate::voxel::material::VoxelMaterialId;
+use crate::voxel::meshing::{vertex_ambient_occlusion, MeshingAlgorithm};
 use crate::voxel::neighbourhood::Neighbourhood;
 use crate::voxel::DIRECTIONS;
 use nalgebra::{Vector2, Vector3};
@@ -85,13 +85,23 @@ impl State<'_> {
                     continue;
                 };
 
+                // Ambient occlusion is only interpolated between the corners of a quad, so merging
+                // faces is only correct if the occlusion is the same in all their corners. Otherwise,
+                // the face is emitted on its own.
+                let ambient_occlusion = wall_info.2;
+                let mergeable = ambient_occlusion
+                    .iter()
+                    .all(|ao| *ao == ambient_occlusion[0]);
+
                 let mut x2 = x1 + 1;
-                while self.wall(Vector2::new(x2, y1)) == Some(wall_info) {
+                while mergeable && self.wall(Vector2::new(x2, y1)) == Some(wall_info) {
                     x2 += 1;
                 }
 
                 let mut y2 = y1 + 1;
-                while (x1..x2).all(|x| self.wall(Vector2::new(x, y2)) == Some(wall_info)) {
+                while mergeable
+                    && (x1..x2).all(|x| self.wall(Vector2::new(x, y2)) == Some(wall_info))
+                {
                     y2 += 1;
                 }
 
@@ -121,22 +131,25 @@ impl State<'_> {
                     WallNormal::AlongSliceNormal => self.slice_normal,
                     WallNormal::AlongMinusSliceNormal => -self.slice_normal,
                 };
-                // TODO: Compute ambient occlusion value.
                 let v1 = LocalVertex {
                     position: top_left,
-                    ambient_occlusion: 0,
+                    ambient_occlusion: ambient_occlusion[0],
+                    material: wall_info.1,
                 };
                 let v2 = LocalVertex {
                     position: top_right,
-                    ambient_occlusion: 0,
+                    ambient_occlusion: ambient_occlusion[1],
+                    material: wall_info.1,
                 };
                 let v3 = LocalVertex {
                     position: bottom_left,
-                    ambient_occlusion: 0,
+                    ambient_occlusion: ambient_occlusion[2],
+                    material: wall_info.1,
                 };
                 let v4 = LocalVertex {
                     position: bottom_right,
-                    ambient_occlusion: 0,
+                    ambient_occlusion: ambient_occlusion[3],
+                    material: wall_info.1,
                 };
                 let base_index = self.vertices.len() as u32;
                 let (io2, io3) = if self.slice_right.cross(&self.slice_down) == normal_i64 {
@@ -148,25 +161,32 @@ impl State<'_> {
                 let i2 = base_index + io2;
                 let i3 = base_index + io3;
                 let i4 = base_index + 3;
+                let indices = if v1.ambient_occlusion + v4.ambient_occlusion
+                    >= v2.ambient_occlusion + v3.ambient_occlusion
+                {
+                    [i1, i2, i3, i4]
+                } else {
+                    [i2, i4, i1, i3]
+                };
                 self.vertices.push(v1);
                 self.vertices.push(v2);
                 self.vertices.push(v3);
                 self.vertices.push(v4);
                 self.faces.push(LocalFace {
-                    indices: [i1, i2, i3, i4],
+                    indices,
                     normal_index: match wall_info.0 {
                         WallNormal::AlongSliceNormal => self.slice_normal_index as u8,
                         WallNormal::AlongMinusSliceNormal => self.slice_minus_normal_index as u8,
                     },
-                    material: wall_info.1,
                 });
             }
         }
     }
 
     /// Checks whether a wall should be placed between a voxel position and a voxel a minus normal apart from it. Also
-    /// checks the desired orientation of the wall, and the material the wall should be made of.
-    fn wall(&self, voxel_2d: Vector2<i64>) -> Option<(WallNormal, Material)> {
+    /// checks the desired orientation of the wall, the material the wall should be made of, and the ambient occlusion in
+    /// its corners.
+    fn wall(&self, voxel_2d: Vector2<i64>) -> Option<(WallNormal, VoxelMaterialId, [u8; 4])> {
         // Note this assert and the following condition refer to 2D coordinates, not 3D. The out of bounds checks later
         // are related only to the normal axis, so the only reason 2D coordinates would be out of bounds is because of
         // the closed-open interval convention used in mesh_slice function.
@@ -202,17 +222,48 @@ impl State<'_> {
         // shouldn't be generated along the slice normal for the same reason.
         if !voxel_kind.is_air() && neighbour_kind.is_air() && !self.out_of_bounds_positive(voxel_3d)
         {
-            Some((WallNormal::AlongMinusSliceNormal, voxel_kind))
+            let ambient_occlusion =
+                self.wall_ambient_occlusion(voxel_2d, neighbour_3d, -self.slice_normal);
+            Some((
+                WallNormal::AlongMinusSliceNormal,
+                voxel_kind,
+                ambient_occlusion,
+            ))
         } else if voxel_kind.is_air()
             && !neighbour_kind.is_air()
             && !self.out_of_bounds_negative(neighbour_3d)
         {
-            Some((WallNormal::AlongSliceNormal, neighbour_kind))
+            let ambient_occlusion =
+                self.wall_ambient_occlusion(voxel_2d, voxel_3d, self.slice_normal);
+            Some((
+                WallNormal::AlongSliceNormal,
+                neighbour_kind,
+                ambient_occlusion,
+            ))
         } else {
             None
         }
     }
 
+    /// Computes ambient occlusion in the corners of a single voxel wall, in the same order as the quad vertices.
+    fn wall_ambient_occlusion(
+        &self,
+        voxel_2d: Vector2<i64>,
+        air: Vector3<i64>,
+        normal: Vector3<i64>,
+    ) -> [u8; 4] {
+        [
+            Vector2::new(0, 0),
+            Vector2::new(1, 0),
+            Vector2::new(0, 1),
+            Vector2::new(1, 1),
+        ]
+        .map(|offset| {
+            let corner = self.convert_2d_to_3d(voxel_2d + offset);
+            vertex_ambient_occlusion(self.svos, corner, air, normal)
+        })
+    }
+
     fn convert_2d_to_3d(&self, voxel: Vector2<i64>) -> Vector3<i64> {
         self.slice_offset * self.slice_normal
             + voxel.y * self.slice_down
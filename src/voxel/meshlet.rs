@@ -1,6 +1,7 @@
 use crate::voxel::local_mesh::LocalMesh;
-use crate::voxel::material::Material;
+use crate::voxel::material::VoxelMaterialId;
 use crate::voxel::sparse_octree::SparseOctree;
+use crate::voxel::DIRECTIONS;
 use meshopt::{build_meshlets, typed_to_bytes, VertexDataAdapter};
 use nalgebra::Vector3;
 use std::collections::HashMap;
@@ -24,9 +25,12 @@ pub struct VoxelMeshlet {
     pub chunk: Vector3<i16>,
     pub _pad0: i16,
     pub bound_base: Vector3<u8>,
-    pub _pad1: u8,
+    // Bit mask of the face directions in the meshlet, matching the convention of DIRECTIONS, used
+    // by the task shader to cull meshlets facing away from the camera.
+    pub normals: u8,
     pub bound_size: Vector3<u8>,
-    pub _pad2: u8,
+    // Set when uploading, as all sessions share the same meshlet buffer.
+    pub session: u8,
 }
 
 #[repr(C, align(4))]
@@ -53,23 +57,24 @@ struct MeshoptVertex {
 }
 
 impl VoxelVertex {
-    fn new(position: Vector3<u8>, ambient_occlusion: u8) -> VoxelVertex {
+    fn new(position: Vector3<u8>, ambient_occlusion: u8, material: VoxelMaterialId) -> VoxelVertex {
         assert!(ambient_occlusion < 4);
+        assert!(material.0 < 64);
         VoxelVertex {
             position,
-            data: ambient_occlusion,
+            data: ambient_occlusion | (material.0 << 2),
         }
     }
 }
 
 impl VoxelTriangle {
-    fn new(indices: [u8; 3], normal: u8, material: Material) -> VoxelTriangle {
+    fn new(indices: [u8; 3], normal: u8) -> VoxelTriangle {
         assert!(normal < 6);
         VoxelTriangle {
             index0: indices[0],
             index1: indices[1],
             index2: indices[2],
-            data: normal | ((material as u8) << 3),
+            data: normal,
         }
     }
 }
@@ -99,54 +104,61 @@ pub fn from_unclustered_mesh(
             face_index,
         );
     }
-    let raw_meshlets = build_raw_meshlets(mesh);
     let mut meshlets = Vec::new();
     let mut vertices = Vec::new();
     let mut triangles = Vec::new();
-    for meshlet in raw_meshlets.iter() {
-        let vertex_offset = vertices.len() as u32;
-        let triangle_offset = triangles.len() as u32;
-        let mut min_coords = Vector3::from_element(255);
-        let mut max_coords = Vector3::from_element(0);
-        for &vertex in meshlet.vertices {
-            let vertex = &mesh.vertices[vertex as usize];
-            vertices.push(VoxelVertex::new(vertex.position, vertex.ambient_occlusion));
-            min_coords.x = min_coords.x.min(vertex.position.x);
-            min_coords.y = min_coords.y.min(vertex.position.y);
-            min_coords.z = min_coords.z.min(vertex.position.z);
-            max_coords.x = max_coords.x.max(vertex.position.x);
-            max_coords.y = max_coords.y.max(vertex.position.y);
-            max_coords.z = max_coords.z.max(vertex.position.z);
+    // Meshlets are built separately for each face direction, as a meshlet mixing them would only be
+    // culled if all of its directions faced away from the camera, which is rarely the case.
+    let raw_meshlets = (0..DIRECTIONS.len() as u8)
+        .filter_map(|normal_index| build_raw_meshlets(mesh, normal_index));
+    for raw_meshlets in raw_meshlets {
+        for meshlet in raw_meshlets.iter() {
+            let vertex_offset = vertices.len() as u32;
+            let triangle_offset = triangles.len() as u32;
+            let mut min_coords = Vector3::from_element(255);
+            let mut max_coords = Vector3::from_element(0);
+            for &vertex in meshlet.vertices {
+                let vertex = &mesh.vertices[vertex as usize];
+                vertices.push(VoxelVertex::new(
+                    vertex.position,
+                    vertex.ambient_occlusion,
+                    vertex.material,
+                ));
+                min_coords.x = min_coords.x.min(vertex.position.x);
+                min_coords.y = min_coords.y.min(vertex.position.y);
+                min_coords.z = min_coords.z.min(vertex.position.z);
+                max_coords.x = max_coords.x.max(vertex.position.x);
+                max_coords.y = max_coords.y.max(vertex.position.y);
+                max_coords.z = max_coords.z.max(vertex.position.z);
+            }
+            let bound_base = min_coords;
+            let bound_size = max_coords - min_coords;
+            let mut normals = 0;
+            for mi012 in meshlet.triangles.chunks(3) {
+                let &[mi0, mi1, mi2] = mi012 else {
+                    unreachable!()
+                };
+                let i0 = meshlet.vertices[mi0 as usize];
+                let i1 = meshlet.vertices[mi1 as usize];
+                let i2 = meshlet.vertices[mi2 as usize];
+                let face_index = triangle_to_face[&[i0, i1, i2]];
+                let face = &mesh.faces[face_index];
+                triangles.push(VoxelTriangle::new([mi0, mi1, mi2], face.normal_index));
+                normals |= 1 << face.normal_index;
+            }
+            meshlets.push(VoxelMeshlet {
+                vertex_offset,
+                vertex_count: meshlet.vertices.len() as u32,
+                triangle_offset,
+                triangle_count: meshlet.triangles.len() as u32 / 3,
+                chunk: Vector3::zeros(),
+                _pad0: 0,
+                bound_base,
+                normals,
+                bound_size,
+                session: 0,
+            });
         }
-        let bound_base = min_coords;
-        let bound_size = max_coords - min_coords;
-        for mi012 in meshlet.triangles.chunks(3) {
-            let &[mi0, mi1, mi2] = mi012 else {
-                unreachable!()
-            };
-            let i0 = meshlet.vertices[mi0 as usize];
-            let i1 = meshlet.vertices[mi1 as usize];
-            let i2 = meshlet.vertices[mi2 as usize];
-            let face_index = triangle_to_face[&[i0, i1, i2]];
-            let face = &mesh.faces[face_index];
-            triangles.push(VoxelTriangle::new(
-                [mi0, mi1, mi2],
-                face.normal_index,
-                face.material,
-            ));
-        }
-        meshlets.push(VoxelMeshlet {
-            vertex_offset,
-            vertex_count: meshlet.vertices.len() as u32,
-            triangle_offset,
-            triangle_count: meshlet.triangles.len() as u32 / 3,
-            chunk: Vector3::zeros(),
-            _pad0: 0,
-            bound_base,
-            _pad1: 0,
-            bound_size,
-            _pad2: 0,
-        });
     }
     VoxelMesh {
         meshlets,
@@ -157,9 +169,14 @@ pub fn from_unclustered_mesh(
     }
 }
 
-fn build_raw_meshlets(mesh: &LocalMesh) -> meshopt::Meshlets {
+// Only covers the faces in a single direction, returns None if there are none.
+fn build_raw_meshlets(mesh: &LocalMesh, normal_index: u8) -> Option<meshopt::Meshlets> {
     let mut meshopt_indices = Vec::new();
-    for face in &mesh.faces {
+    for face in mesh
+        .faces
+        .iter()
+        .filter(|face| face.normal_index == normal_index)
+    {
         meshopt_indices.extend_from_slice(&[
             face.indices[0],
             face.indices[1],
@@ -169,6 +186,9 @@ fn build_raw_meshlets(mesh: &LocalMesh) -> meshopt::Meshlets {
             face.indices[2],
         ]);
     }
+    if meshopt_indices.is_empty() {
+        return None;
+    }
     let mut meshopt_vertices = Vec::new();
     for vertex in &mesh.vertices {
         meshopt_vertices.push(MeshoptVertex {
@@ -181,5 +201,55 @@ fn build_raw_meshlets(mesh: &LocalMesh) -> meshopt::Meshlets {
         0,
     )
     .unwrap();
-    build_meshlets(&meshopt_indices, &vertices, 128, 256, 0.)
+    Some(build_meshlets(&meshopt_indices, &vertices, 128, 256, 0.))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::voxel::local_mesh::{LocalFace, LocalVertex};
+
+    #[test]
+    fn meshlets_have_a_single_direction() {
+        let vertex = |x, y, z| LocalVertex {
+            position: Vector3::new(x, y, z),
+            ambient_occlusion: 0,
+            material: VoxelMaterialId::STONE,
+        };
+        // Top and side face of the same voxel, sharing two vertices.
+        let mesh = LocalMesh {
+            vertices: vec![
+                vertex(0, 1, 0),
+                vertex(1, 1, 0),
+                vertex(0, 1, 1),
+                vertex(1, 1, 1),
+                vertex(1, 0, 0),
+                vertex(1, 0, 1),
+            ],
+            faces: vec![
+                LocalFace {
+                    indices: [0, 1, 2, 3],
+                    normal_index: 2,
+                },
+                LocalFace {
+                    indices: [4, 1, 5, 3],
+                    normal_index: 0,
+                },
+            ],
+        };
+        let octree = SparseOctree::Uniform {
+            kind: VoxelMaterialId::STONE,
+        };
+        let mesh = from_unclustered_mesh(&mesh, &octree, Vector3::zeros());
+        let normals: Vec<u8> = mesh
+            .meshlets
+            .iter()
+            .map(|meshlet| meshlet.normals)
+            .collect();
+        assert_eq!(normals, [1 << 0, 1 << 2]);
+        assert!(mesh
+            .meshlets
+            .iter()
+            .all(|meshlet| meshlet.triangle_count == 2));
+    }
 }
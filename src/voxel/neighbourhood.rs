@@ -1,4 +1,4 @@
-use crate::voxel::material::Material;
+use crate::voxel::material::VoxelMaterialId;
 use crate::voxel::sparse_octree::SparseOctree;
 use crate::voxel::DIRECTIONS;
 use nalgebra::Vector3;
@@ -15,7 +15,7 @@ impl<'a> Neighbourhood<'a> {
         Neighbourhood { svos, chunk_size }
     }
 
-    pub fn at(&self, mut position: Vector3<i64>) -> Material {
+    pub fn at(&self, mut position: Vector3<i64>) -> VoxelMaterialId {
         let mut chunk = Vector3::new(0, 0, 0);
         if position.x < 0 {
             chunk.x = -1;
@@ -0,0 +1,98 @@
+use nalgebra::Vector3;
+use std::hint::spin_loop;
+use std::sync::atomic::{fence, AtomicI64, AtomicU64, Ordering};
+
+// Everything the workers need to know from the main thread at the start of each job. The config
+// itself is only read under the state lock, as it's too large to publish this way and changes
+// rarely, so only its revision is here.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Snapshot {
+    pub camera: Vector3<i64>,
+    // Bumped by every config change. Results computed against an older revision are thrown away.
+    pub revision: u64,
+}
+
+// The main thread publishes a snapshot every frame, so it must never wait for a worker. This is a
+// sequence lock: the writer only ever stores atomics, and readers retry in the rare case they
+// overlapped with a write, which is a handful of stores long. Only a single writer is supported,
+// which holds as the main thread is the only one with access to the Voxels handle.
+pub struct SnapshotCell {
+    // Odd while a write is in progress.
+    sequence: AtomicU64,
+    camera: [AtomicI64; 3],
+    revision: AtomicU64,
+}
+
+impl SnapshotCell {
+    pub fn new(snapshot: Snapshot) -> SnapshotCell {
+        SnapshotCell {
+            sequence: AtomicU64::new(0),
+            camera: std::array::from_fn(|i| AtomicI64::new(snapshot.camera[i])),
+            revision: AtomicU64::new(snapshot.revision),
+        }
+    }
+
+    pub fn load(&self) -> Snapshot {
+        loop {
+            let before = self.sequence.load(Ordering::Acquire);
+            if before % 2 == 1 {
+                spin_loop();
+                continue;
+            }
+            let camera = Vector3::from_fn(|i, _| self.camera[i].load(Ordering::Relaxed));
+            let revision = self.revision.load(Ordering::Relaxed);
+            fence(Ordering::Acquire);
+            if self.sequence.load(Ordering::Relaxed) == before {
+                return Snapshot { camera, revision };
+            }
+        }
+    }
+
+    pub fn store(&self, snapshot: Snapshot) {
+        let sequence = self.sequence.load(Ordering::Relaxed);
+        self.sequence.store(sequence + 1, Ordering::Relaxed);
+        fence(Ordering::Release);
+        for (atomic, value) in self.camera.iter().zip(snapshot.camera.iter()) {
+            atomic.store(*value, Ordering::Relaxed);
+        }
+        self.revision.store(snapshot.revision, Ordering::Relaxed);
+        self.sequence.store(sequence + 2, Ordering::Release);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::AtomicBool;
+
+    fn snapshot(value: i64) -> Snapshot {
+        Snapshot {
+            camera: Vector3::new(value, -value, 2 * value),
+            revision: value as u64,
+        }
+    }
+
+    #[test]
+    fn readers_never_see_torn_writes() {
+        let cell = SnapshotCell::new(snapshot(0));
+        let done = AtomicBool::new(false);
+        std::thread::scope(|scope| {
+            for _ in 0..4 {
+                scope.spawn(|| {
+                    let mut last = 0;
+                    while !done.load(Ordering::Relaxed) {
+                        let loaded = cell.load();
+                        assert_eq!(loaded, snapshot(loaded.revision as i64));
+                        assert!(loaded.revision >= last);
+                        last = loaded.revision;
+                    }
+                });
+            }
+            for value in 1..=100_000 {
+                cell.store(snapshot(value));
+            }
+            done.store(true, Ordering::Relaxed);
+        });
+        assert_eq!(cell.load(), snapshot(100_000));
+    }
+}
@@ -1,14 +1,14 @@
-use crate::voxel::material::Material;
+use crate::voxel::material::VoxelMaterialId;
 use nalgebra::Vector3;
 
 #[derive(Clone, Debug, Eq, PartialEq)]
 pub enum SparseOctree {
-    Uniform { kind: Material },
+    Uniform { kind: VoxelMaterialId },
     Mixed { children: Box<[SparseOctree; 8]> },
 }
 
 impl SparseOctree {
-    pub fn at(&self, point: Vector3<i64>, local_size: i64) -> Material {
+    pub fn at(&self, point: Vector3<i64>, local_size: i64) -> VoxelMaterialId {
         match self {
             SparseOctree::Uniform { kind } => *kind,
             SparseOctree::Mixed { children } => {
@@ -32,4 +32,33 @@ impl SparseOctree {
             }
         }
     }
+
+    // Builds the octree voxel by voxel, without merging uniform children, which is only fast
+    // enough for the small hand-built chunks used in tests.
+    #[cfg(test)]
+    pub fn from_fn(size: i64, material: &impl Fn(Vector3<i64>) -> VoxelMaterialId) -> SparseOctree {
+        SparseOctree::from_fn_at(size, Vector3::zeros(), material)
+    }
+
+    #[cfg(test)]
+    fn from_fn_at(
+        size: i64,
+        offset: Vector3<i64>,
+        material: &impl Fn(Vector3<i64>) -> VoxelMaterialId,
+    ) -> SparseOctree {
+        if size == 1 {
+            return SparseOctree::Uniform {
+                kind: material(offset),
+            };
+        }
+        let child_size = size / 2;
+        let children = std::array::from_fn(|index| {
+            let index = index as i64;
+            let child_offset = Vector3::new(index & 1, (index >> 1) & 1, index >> 2) * child_size;
+            SparseOctree::from_fn_at(child_size, offset + child_offset, material)
+        });
+        SparseOctree::Mixed {
+            children: Box::new(children),
+        }
+    }
 }
@@ -1,71 +1,302 @@
+use crate::logger::catch_unwind_silently;
 use crate::voxel::chunk_priority::ChunkPriorityAlgorithm;
+use crate::voxel::gpu::UploadResult;
 use crate::voxel::meshing::generate_mesh;
 use crate::voxel::neighbourhood::Neighbourhood;
-use crate::voxel::world_generation::{generate_chunk_svo, generate_heightmap};
-use crate::voxel::VoxelsShared;
-use nalgebra::Vector3;
-use std::sync::Arc;
+use crate::voxel::world_generation::{
+    generate_chunk_svo, generate_heightmap, HeightmapNeighbourhood,
+};
+use crate::voxel::{ChunkTimings, VoxelsConfig, VoxelsShared, VoxelsState};
+use bracket_noise::prelude::FastNoise;
+use log::error;
+use nalgebra::{Vector2, Vector3};
+use std::any::Any;
+use std::sync::atomic::Ordering;
+use std::sync::{Arc, PoisonError};
+use std::time::{Duration, Instant};
+
+// Panics are usually caused by bugs that happen every time for a given chunk, but retrying once is
+// cheap and makes it easier to tell these apart from problems caused by concurrency.
+pub const MAX_CHUNK_RETRIES: usize = 1;
+
+// The camera is published without taking the state lock, so its notification can arrive between a
+// worker finding nothing to do and starting to wait. Idle workers recheck this often, which bounds
+// how long loading can lag behind in that case.
+const IDLE_RECHECK_INTERVAL: Duration = Duration::from_millis(100);
 
 pub fn voxel_thread(shared: &VoxelsShared) {
-    let mut state = shared.state.lock().unwrap();
-    loop {
-        if state.shutdown {
-            break;
+    let mut state = shared.lock_state();
+    while !shared.shutdown.load(Ordering::Relaxed) {
+        // Checked before selecting, as a selected chunk can't be put back without retrying it.
+        if shared.paused.load(Ordering::Relaxed) || shared.chunk_budget.load(Ordering::Relaxed) == 0
+        {
+            state = shared
+                .wake
+                .wait_timeout(state, IDLE_RECHECK_INTERVAL)
+                .unwrap_or_else(PoisonError::into_inner)
+                .0;
+            continue;
         }
-
         let config = state.config.clone();
-        let config_generation = state.config_generation;
+        let config_revision = state.config_revision;
         let noise = state.heightmap_noise.clone();
 
-        state
-            .chunk_priority
-            .update_camera(*shared.camera.lock().unwrap());
+        state.update_camera(shared.snapshot.load().camera);
         let Some(chunk) = state.chunk_priority.select() else {
-            state = shared.wake.wait(state).unwrap();
+            state = shared
+                .wake
+                .wait_timeout(state, IDLE_RECHECK_INTERVAL)
+                .unwrap_or_else(PoisonError::into_inner)
+                .0;
             continue;
         };
+        // The camera update can refill the budget concurrently, as it doesn't take the lock, so
+        // this saturates instead of assuming the value checked above.
+        let _ = shared
+            .chunk_budget
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |budget| {
+                budget.checked_sub(1)
+            });
+        state.generating.insert(chunk);
+        drop(state);
+
+        // A panic in world generation or meshing would otherwise kill the thread, leaving a hole
+        // in the world and fewer threads for generating the rest of it. The chunk itself can't be
+        // generated anyway, so it's just retried or skipped while the thread keeps going.
+        let result = catch_unwind_silently(|| {
+            generate_chunk(shared, chunk, &config, config_revision, &noise)
+        });
+        state = shared.lock_state();
+        state.generating.remove(&chunk);
+        if shared.snapshot.load().revision != config_revision {
+            state.health.stale_chunks += 1;
+        } else if let Err(payload) = result {
+            handle_chunk_panic(&mut state, chunk, payload);
+        }
+    }
+}
 
-        let mut svos = Vec::new();
-        for oz in -1..=1 {
-            for oy in -1..=1 {
-                for ox in -1..=1 {
-                    let offset = Vector3::new(ox, oy, oz);
-                    let chunk = chunk + offset;
-                    let svo = if let Some(svo) = state.loaded_svos.get(&chunk) {
-                        svo.clone()
-                    } else {
+fn generate_chunk(
+    shared: &VoxelsShared,
+    chunk: Vector3<i64>,
+    config: &VoxelsConfig,
+    config_revision: u64,
+    noise: &FastNoise,
+) {
+    // Anything inserted into the state after a config change would mix the old world into the new
+    // one, so the revision is checked every time the lock is taken again, before touching it.
+    let mut state = shared.lock_state();
+    let mut timings = ChunkTimings::default();
+    let mut svos = Vec::new();
+    for oz in -1..=1 {
+        for oy in -1..=1 {
+            for ox in -1..=1 {
+                if shared.abandoned(config_revision) {
+                    return;
+                }
+                let offset = Vector3::new(ox, oy, oz);
+                let chunk = chunk + offset;
+                let svo = if let Some(svo) = state.loaded_svos.get(&chunk) {
+                    svo.clone()
+                } else if let Some(svo) = state.restore_svo(chunk) {
+                    svo
+                } else {
+                    let mut heightmaps = Vec::new();
+                    for column_offset in [
+                        Vector2::new(0, 0),
+                        Vector2::new(-1, 0),
+                        Vector2::new(1, 0),
+                        Vector2::new(0, -1),
+                        Vector2::new(0, 1),
+                    ] {
+                        let column = chunk.xy() + column_offset;
                         let heightmap =
-                            if let Some(heightmap) = state.loaded_heightmaps.get(&chunk.xy()) {
+                            if let Some(heightmap) = state.loaded_heightmaps.get(&column) {
                                 heightmap.clone()
                             } else {
                                 drop(state);
-                                let heightmap =
-                                    Arc::new(generate_heightmap(chunk.xy(), &noise, &config));
-                                state = shared.state.lock().unwrap();
-                                state
-                                    .loaded_heightmaps
-                                    .insert(chunk.xy(), heightmap.clone());
+                                let start = Instant::now();
+                                let heightmap = Arc::new(generate_heightmap(column, noise, config));
+                                timings.heightmap_us += micros_since(start);
+                                // Sharing a seed is only useful if it reproduces the same world,
+                                // so debug builds check that generation doesn't depend on anything
+                                // else.
+                                debug_assert_eq!(
+                                    *heightmap,
+                                    generate_heightmap(column, noise, config),
+                                    "heightmap generation is nondeterministic"
+                                );
+                                state = shared.lock_state();
+                                if shared.abandoned(config_revision) {
+                                    return;
+                                }
+                                state.loaded_heightmaps.insert(column, heightmap.clone());
                                 heightmap
                             };
-                        drop(state);
-                        let chunk_svo = Arc::new(generate_chunk_svo(chunk, &heightmap, &config));
-                        state = shared.state.lock().unwrap();
-                        state.loaded_svos.insert(chunk, chunk_svo.clone());
-                        chunk_svo
+                        heightmaps.push(heightmap);
+                    }
+                    drop(state);
+                    let heightmaps = HeightmapNeighbourhood {
+                        center: &heightmaps[0],
+                        neighbours: std::array::from_fn(|i| &*heightmaps[i + 1]),
                     };
-                    svos.push(svo);
-                }
+                    let start = Instant::now();
+                    let chunk_svo = Arc::new(generate_chunk_svo(chunk, &heightmaps, config));
+                    timings.svo_us += micros_since(start);
+                    state = shared.lock_state();
+                    if shared.abandoned(config_revision) {
+                        return;
+                    }
+                    state.loaded_svos.insert(chunk, chunk_svo.clone());
+                    chunk_svo
+                };
+                svos.push(svo);
             }
         }
-        let neighbourhood = Neighbourhood::new(&svos, config.chunk_size as i64);
-        let prepare_func = state.gpu_memory.prepare_func();
-        drop(state);
-        let raw_mesh = generate_mesh(&neighbourhood, &config);
-        let mesh = prepare_func(raw_mesh, neighbourhood.chunk(), chunk);
-        state = shared.state.lock().unwrap();
-        if config_generation != state.config_generation {
-            continue;
+    }
+    let neighbourhood = Neighbourhood::new(&svos, config.chunk_size as i64);
+    let prepare_func = state.gpu_memory.prepare_func();
+    drop(state);
+    let start = Instant::now();
+    let raw_mesh = generate_mesh(&neighbourhood, config);
+    let mesh = prepare_func(raw_mesh, neighbourhood.chunk(), chunk);
+    timings.mesh_us = micros_since(start);
+    let camera = shared.snapshot.load().camera;
+    let mut state = shared.lock_state();
+    if shared.abandoned(config_revision) {
+        return;
+    }
+    state.record_timings(timings);
+    // The chunk is generated again when retried, as keeping the mesh around until memory frees up
+    // would need another place to keep track of it, and this only happens when memory runs out.
+    match state.gpu_memory.upload(mesh, camera) {
+        UploadResult::Uploaded => state.streaming.loads += 1,
+        UploadResult::Deferred { evicted } => {
+            state.health.evicted_chunks += evicted.len();
+            for evicted in evicted {
+                state.chunk_priority.unload(evicted);
+            }
+            state.chunk_priority.retry(chunk);
+        }
+        UploadResult::OutOfMemory => (),
+    }
+}
+
+fn micros_since(start: Instant) -> u64 {
+    start.elapsed().as_micros() as u64
+}
+
+fn handle_chunk_panic(state: &mut VoxelsState, chunk: Vector3<i64>, payload: Box<dyn Any + Send>) {
+    let message = if let Some(message) = payload.downcast_ref::<&str>() {
+        message
+    } else if let Some(message) = payload.downcast_ref::<String>() {
+        message.as_str()
+    } else {
+        "unknown panic payload"
+    };
+    let attempts = state.failed_attempts.entry(chunk).or_insert(0);
+    *attempts += 1;
+    let retry = *attempts <= MAX_CHUNK_RETRIES;
+    error!(
+        "voxel chunk generation panicked, \x1B[1mchunk\x1B[0m: {} {} {}, \x1B[1mretry\x1B[0m: {retry}, \x1B[1mmessage\x1B[0m: {message}",
+        chunk.x, chunk.y, chunk.z
+    );
+    if retry {
+        state.health.retried_chunks += 1;
+        state.chunk_priority.retry(chunk);
+    } else {
+        state.health.failed_chunks += 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::DEFAULT_VOXEL_CONFIG;
+    use crate::voxel::gpu::NullMemory;
+    use crate::voxel::world_generation::heightmap_noise;
+    use crate::voxel::Voxels;
+
+    // Same as the workers, but on the calling thread and until everything around the camera is
+    // loaded.
+    fn load_around(voxels: &Voxels, position: Vector3<f32>) {
+        voxels.update_camera(position, u32::MAX);
+        loop {
+            let mut state = voxels.shared.lock_state();
+            let config = state.config.clone();
+            let noise = state.heightmap_noise.clone();
+            state.update_camera(voxels.shared.snapshot.load().camera);
+            let Some(chunk) = state.chunk_priority.select() else {
+                break;
+            };
+            drop(state);
+            generate_chunk(&voxels.shared, chunk, &config, 0, &noise);
+        }
+    }
+
+    fn small_world() -> VoxelsConfig {
+        VoxelsConfig {
+            chunk_size: 16,
+            render_distance_horizontal: 32,
+            render_distance_vertical: 16,
+            unload_distance_horizontal: 48,
+            unload_distance_vertical: 32,
+            ..DEFAULT_VOXEL_CONFIG
+        }
+    }
+
+    #[test]
+    fn oscillating_camera_loads_edge_once() {
+        let config = small_world();
+        let voxels = Voxels::new(config, Vector3::zeros(), Box::new(NullMemory), 0);
+        let near = Vector3::new(15.5, 0., 0.);
+        let far = Vector3::new(16.5, 0., 0.);
+        load_around(&voxels, near);
+        load_around(&voxels, far);
+        let loads = voxels.stats().streaming.loads;
+        // Everything within the render distance of either position, which are a chunk apart.
+        assert_eq!(loads, 6 * 5 * 3);
+        for _ in 0..10 {
+            load_around(&voxels, near);
+            load_around(&voxels, far);
         }
-        state.gpu_memory.upload(mesh);
+        let streaming = voxels.stats().streaming;
+        assert_eq!(streaming.loads, loads);
+        assert_eq!(streaming.unloads, 0);
+    }
+
+    #[test]
+    fn unloaded_chunks_come_back_from_cache() {
+        let config = small_world();
+        let voxels = Voxels::new(config, Vector3::zeros(), Box::new(NullMemory), 0);
+        let start = Vector3::new(8., 0., 0.);
+        load_around(&voxels, start);
+        assert_eq!(voxels.stats().streaming.loads, 5 * 5 * 3);
+        // Two chunks over, so that the slab furthest behind leaves the unload distance.
+        load_around(&voxels, Vector3::new(40., 0., 0.));
+        let streaming = voxels.stats().streaming;
+        assert_eq!(streaming.loads, 7 * 5 * 3);
+        assert_eq!(streaming.unloads, 5 * 3);
+        load_around(&voxels, start);
+        let streaming = voxels.stats().streaming;
+        assert_eq!(streaming.loads, 8 * 5 * 3);
+        assert_eq!(streaming.unloads, 2 * 5 * 3);
+        // The unloaded slab and the one behind it, which was only generated for meshing it.
+        assert_eq!(streaming.cache_readds, 2 * 7 * 5);
+    }
+
+    #[test]
+    fn chunk_steps_are_timed() {
+        let config = DEFAULT_VOXEL_CONFIG;
+        let voxels = Voxels::new(config.clone(), Vector3::zeros(), Box::new(NullMemory), 0);
+        let noise = heightmap_noise(config.seed);
+        // Nothing is loaded yet, so none of the steps can be skipped.
+        generate_chunk(&voxels.shared, Vector3::zeros(), &config, 0, &noise);
+        let state = voxels.shared.lock_state();
+        assert_eq!(state.timings.len(), 1);
+        let timings = state.timings[0];
+        assert!(timings.heightmap_us > 0, "{timings:?}");
+        assert!(timings.svo_us > 0, "{timings:?}");
+        assert!(timings.mesh_us > 0, "{timings:?}");
     }
 }
@@ -1,9 +1,16 @@
-use crate::voxel::material::Material;
+use crate::voxel::material::VoxelMaterialId;
 use crate::voxel::sparse_octree::SparseOctree;
 use crate::voxel::VoxelsConfig;
-use bracket_noise::prelude::FastNoise;
+use bracket_noise::prelude::{FastNoise, NoiseType};
 use nalgebra::{DMatrix, Vector2, Vector3};
 
+pub fn heightmap_noise(seed: u64) -> FastNoise {
+    let mut noise = FastNoise::seeded(seed);
+    noise.set_noise_type(NoiseType::Perlin);
+    noise.set_frequency(1.);
+    noise
+}
+
 pub fn generate_heightmap(
     chunk_column: Vector2<i64>,
     noise: &FastNoise,
@@ -23,19 +30,50 @@ pub fn generate_heightmap(
     heightmap
 }
 
+// Heightmap of a chunk column together with the four columns next to it. Surface materials depend
+// on the slope, so columns on the chunk border need their neighbours' heights just like meshing
+// needs the neighbouring chunks' voxels. Diagonal columns aren't used, so they aren't included.
+pub struct HeightmapNeighbourhood<'a> {
+    pub center: &'a DMatrix<i64>,
+    // Ordered -X, +X, -Y, +Y.
+    pub neighbours: [&'a DMatrix<i64>; 4],
+}
+
+impl HeightmapNeighbourhood<'_> {
+    // Columns can be at most one step outside of the center heightmap, in one axis at a time.
+    fn height(&self, x: i64, y: i64) -> i64 {
+        let n = self.center.nrows() as i64;
+        let (heightmap, x, y) = if x < 0 {
+            (self.neighbours[0], x + n, y)
+        } else if x >= n {
+            (self.neighbours[1], x - n, y)
+        } else if y < 0 {
+            (self.neighbours[2], x, y + n)
+        } else if y >= n {
+            (self.neighbours[3], x, y - n)
+        } else {
+            (self.center, x, y)
+        };
+        heightmap[(x as usize, y as usize)]
+    }
+}
+
 pub fn generate_chunk_svo(
     chunk: Vector3<i64>,
-    heightmap: &DMatrix<i64>,
+    heightmaps: &HeightmapNeighbourhood,
     config: &VoxelsConfig,
 ) -> SparseOctree {
-    assert_eq!(heightmap.nrows(), config.chunk_size);
-    assert_eq!(heightmap.ncols(), config.chunk_size);
+    for heightmap in std::iter::once(heightmaps.center).chain(heightmaps.neighbours) {
+        assert_eq!(heightmap.nrows(), config.chunk_size);
+        assert_eq!(heightmap.ncols(), config.chunk_size);
+    }
     recursive_generate_svo(
         0,
         0,
         chunk.z * config.chunk_size as i64,
         config.chunk_size,
-        heightmap,
+        heightmaps,
+        config,
     )
 }
 
@@ -44,15 +82,16 @@ fn recursive_generate_svo(
     y: usize,
     z: i64,
     n: usize,
-    heightmap: &DMatrix<i64>,
+    heightmaps: &HeightmapNeighbourhood,
+    config: &VoxelsConfig,
 ) -> SparseOctree {
     'check_all_same: {
-        let material = material_from_height(heightmap[(x, y)], z);
+        let material = material_from_height(x, y, z, heightmaps, config);
         for ly in y..y + n {
             for lx in x..x + n {
-                let height = heightmap[(lx, ly)];
-                let low_material = material_from_height(height, z);
-                let high_material = material_from_height(height, z + n as i64 - 1);
+                let low_material = material_from_height(lx, ly, z, heightmaps, config);
+                let high_material =
+                    material_from_height(lx, ly, z + n as i64 - 1, heightmaps, config);
                 if low_material != material || high_material != material {
                     break 'check_all_same;
                 }
@@ -69,20 +108,89 @@ fn recursive_generate_svo(
             y + dy * n / 2,
             z + dz as i64 * n as i64 / 2,
             n / 2,
-            heightmap,
+            heightmaps,
+            config,
         )
     }));
     SparseOctree::Mixed { children }
 }
 
-fn material_from_height(height: i64, z: i64) -> Material {
+// Surface material only depends on the column and not on z, so every column is still made of
+// contiguous layers. This is what lets the uniformity check above only look at the lowest and
+// highest voxel of each column.
+fn material_from_height(
+    x: usize,
+    y: usize,
+    z: i64,
+    heightmaps: &HeightmapNeighbourhood,
+    config: &VoxelsConfig,
+) -> VoxelMaterialId {
+    let height = heightmaps.center[(x, y)];
     if height <= z {
-        Material::Air
+        VoxelMaterialId::AIR
     } else if height <= z + 1 {
-        Material::Grass
+        surface_material(x, y, heightmaps, config)
     } else if height <= z + 5 {
-        Material::Dirt
+        VoxelMaterialId::DIRT
+    } else {
+        VoxelMaterialId::STONE
+    }
+}
+
+fn surface_material(
+    x: usize,
+    y: usize,
+    heightmaps: &HeightmapNeighbourhood,
+    config: &VoxelsConfig,
+) -> VoxelMaterialId {
+    let height = heightmaps.center[(x, y)];
+    if column_slope(x, y, heightmaps) >= config.rock_min_slope {
+        VoxelMaterialId::STONE
+    } else if height >= config.snow_min_altitude {
+        VoxelMaterialId::SNOW
+    } else if height <= config.sand_max_altitude {
+        VoxelMaterialId::SAND
     } else {
-        Material::Stone
+        VoxelMaterialId::GRASS
+    }
+}
+
+fn column_slope(x: usize, y: usize, heightmaps: &HeightmapNeighbourhood) -> i64 {
+    let (x, y) = (x as i64, y as i64);
+    let height = heightmaps.height(x, y);
+    [(x - 1, y), (x + 1, y), (x, y - 1), (x, y + 1)]
+        .into_iter()
+        .map(|(nx, ny)| (heightmaps.height(nx, ny) - height).abs())
+        .max()
+        .unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::DEFAULT_VOXEL_CONFIG;
+
+    #[test]
+    fn heightmap_is_deterministic() {
+        let config = DEFAULT_VOXEL_CONFIG;
+        let column = Vector2::new(-3, 7);
+        let first = generate_heightmap(column, &heightmap_noise(config.seed), &config);
+        let second = generate_heightmap(column, &heightmap_noise(config.seed), &config);
+        let other = generate_heightmap(column, &heightmap_noise(config.seed + 1), &config);
+        assert_eq!(first, second);
+        assert_ne!(first, other);
+    }
+
+    #[test]
+    fn slope_on_chunk_border_uses_neighbour() {
+        let flat = DMatrix::from_element(4, 4, 10);
+        let cliff = DMatrix::from_element(4, 4, 30);
+        let heightmaps = HeightmapNeighbourhood {
+            center: &flat,
+            neighbours: [&flat, &cliff, &flat, &flat],
+        };
+        assert_eq!(column_slope(3, 1, &heightmaps), 20);
+        assert_eq!(column_slope(2, 1, &heightmaps), 0);
+        assert_eq!(column_slope(0, 0, &heightmaps), 0);
     }
 }
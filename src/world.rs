@@ -1,35 +1,83 @@
 use crate::camera::Camera;
 use crate::config::{
-    DEFAULT_CAMERA, DEFAULT_STAR_COUNT, DEFAULT_STAR_MAX_SCALE, DEFAULT_STAR_MIN_SCALE,
-    DEFAULT_STAR_RADIUS, DEFAULT_SUN_POSITION, DEFAULT_SUN_RADIUS, DEFAULT_SUN_SPEED,
+    DEFAULT_ATMOSPHERE_SCATTERING_STRENGTH, DEFAULT_AXIAL_TILT, DEFAULT_CAMERA,
+    DEFAULT_DAY_LENGTH_SECONDS, DEFAULT_STAR_COUNT, DEFAULT_STAR_MAX_SCALE, DEFAULT_STAR_MIN_SCALE,
+    DEFAULT_STAR_RADIUS, DEFAULT_SUN_POSITION, DEFAULT_SUN_RADIUS,
 };
+#[cfg(feature = "dev-menu")]
+use crate::debug_draw::DebugDraw;
 use crate::input::InputState;
-use crate::physics::Physics;
-use crate::renderer::uniform::Light;
+use crate::particles::{EmitSettings, Particle};
+use crate::physics::walking::Walker;
+use crate::physics::{sweep_sphere, Physics, PhysicsMode, PHYSICS_DT};
+use crate::renderer::uniform::{Light, PointLight, POINT_LIGHT_MAX_COUNT};
 use crate::util::{RandomDirection, RandomRotation};
-use nalgebra::{Matrix4, UnitQuaternion, Vector3};
-use rand::Rng;
+use crate::voxel::VoxelCollision;
+use crate::voxel::Voxels;
+use log::warn;
+use nalgebra::{Matrix4, UnitQuaternion, Vector3, Vector4};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
 use rapier3d::prelude::*;
+use std::f32::consts::PI;
 
 pub struct World {
     pub camera: Box<dyn Camera>,
     camera_rigid_body_handle: RigidBodyHandle,
     pub entities: Vec<Entity>,
+    pub objects: Vec<Object>,
+    // The sun is lit separately as a directional light, these are only the local lights.
+    pub point_lights: Vec<PointLight>,
+    // Index of the point light moved along with the camera every frame, for looking at how the
+    // lighting reacts to a moving light without having to drag it around.
+    pub camera_light: Option<usize>,
+    // Particles emitted since the last frame, which the renderer hands over to the GPU simulation.
+    // Cleared after every frame, as the particles only exist on the GPU afterwards.
+    pub emitted_particles: Vec<Particle>,
     pub stars: Vec<Star>,
     physics: Physics,
+    physics_mode: PhysicsMode,
+    walker: Walker,
+    // Time not simulated yet, always less than a tick after an update.
+    physics_accumulator: f32,
+    // States at the end of the last two ticks, which the camera is interpolated between.
+    previous_physics_state: PhysicsState,
+    physics_state: PhysicsState,
+    // Jumps pressed during frames too short for a tick, applied by the next one.
+    pending_jumps: usize,
+    // Flying goes through the terrain by default, which is usually what's wanted when looking
+    // around the world, but it's sometimes useful to see what the terrain looks like up close.
+    pub fly_collision: bool,
     pub time: f32,
+    // Fraction of the day that has passed, starting from midnight, so noon is at 0.5.
     pub time_of_day: f32,
+    pub day_length_seconds: f32,
+    pub axial_tilt: f32,
     pub sun_intensity: f32,
     pub sun_pause: bool,
     pub sun_radius: f32,
-    pub sun_speed: f32,
     pub atmosphere: Atmosphere,
 }
 
+// Everything the camera follows from a physics tick, which is just the position of the player's
+// eyes.
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct PhysicsState {
+    position: Vector3<f32>,
+}
+
 pub struct Entity {
     pub transform: Transform,
 }
 
+// Meshes placed in the world, referring to the meshes passed to the renderer by index. Objects are
+// drawn in the transparent pass if either their mesh or their color isn't fully opaque.
+pub struct Object {
+    pub transform: Transform,
+    pub mesh: usize,
+    pub color: Vector4<f32>,
+}
+
 pub struct Transform {
     pub translation: Vector3<f32>,
     rotation: UnitQuaternion<f32>,
@@ -51,9 +99,19 @@ pub struct Atmosphere {
 const AVERAGE_MALE_HEIGHT: f32 = 1.74;
 const AVERAGE_MALE_EYE_HEIGHT: f32 = 1.63;
 const AVERAGE_MALE_SHOULDER_WIDTH: f32 = 0.465;
+// Keeps the near plane from clipping into the terrain when flying right next to it.
+const CAMERA_COLLISION_RADIUS: f32 = 0.3;
+// Frames longer than this many ticks, like ones spent loading or paused in a debugger, drop the rest
+// of their time instead of catching up, which would only make the next frame even longer.
+const PHYSICS_MAX_TICKS_PER_FRAME: usize = 8;
+#[cfg(feature = "dev-menu")]
+const OBJECT_SPAWN_DISTANCE: f32 = 4.;
+const BENCHMARK_OBJECT_SPACING: f32 = 3.;
 
 impl World {
-    pub fn new() -> World {
+    // Stars are generated from the world seed, so that sharing the seed reproduces the sky along
+    // with the terrain.
+    pub fn new(seed: u64) -> World {
         let camera = Box::new(DEFAULT_CAMERA);
         let mut physics = Physics::new();
         let camera_rigid_body = RigidBodyBuilder::dynamic()
@@ -78,58 +136,120 @@ impl World {
                 scale: Vector3::from_element(50.),
             },
         };
+        let walker = Walker::new(
+            camera.position - Vector3::new(0., 0., AVERAGE_MALE_EYE_HEIGHT),
+            AVERAGE_MALE_SHOULDER_WIDTH,
+            AVERAGE_MALE_HEIGHT,
+        );
         let entities = vec![sun];
-        let mut stars = Vec::new();
-        let mut rng = rand::thread_rng();
-        for _ in 0..DEFAULT_STAR_COUNT {
-            stars.push(Star {
-                transform: Transform {
-                    translation: DEFAULT_STAR_RADIUS * rng.sample(RandomDirection),
-                    rotation: rng.sample(RandomRotation),
-                    scale: Vector3::from_element(
-                        rng.gen_range(DEFAULT_STAR_MIN_SCALE..DEFAULT_STAR_MAX_SCALE),
-                    ),
-                },
-            });
-        }
+        let stars = generate_stars(seed);
+        let physics_state = PhysicsState {
+            position: camera.position,
+        };
         World {
             camera,
             camera_rigid_body_handle,
             entities,
+            objects: Vec::new(),
+            point_lights: Vec::new(),
+            camera_light: None,
+            emitted_particles: Vec::new(),
             stars,
             physics,
+            physics_mode: PhysicsMode::Flying,
+            walker,
+            physics_accumulator: 0.,
+            previous_physics_state: physics_state,
+            physics_state,
+            pending_jumps: 0,
+            fly_collision: false,
             time: 0.,
-            time_of_day: 0.,
+            time_of_day: 0.5,
+            day_length_seconds: DEFAULT_DAY_LENGTH_SECONDS,
+            axial_tilt: DEFAULT_AXIAL_TILT,
             sun_intensity: 1.,
             sun_pause: true,
             sun_radius: DEFAULT_SUN_RADIUS,
-            sun_speed: DEFAULT_SUN_SPEED,
             atmosphere: Atmosphere {
                 density_falloff: 6.,
                 scale: 1.5,
-                scattering_strength: 0.03,
+                scattering_strength: DEFAULT_ATMOSPHERE_SCATTERING_STRENGTH,
                 henyey_greenstein_g: 0.,
                 planet_radius: 1000.,
             },
         }
     }
 
-    pub fn update(&mut self, delta_time: f32, input_state: &InputState) {
+    pub fn update(
+        &mut self,
+        delta_time: f32,
+        time_scale: f32,
+        input_state: &InputState,
+        voxels: &Voxels,
+    ) {
+        if input_state.physics_mode_toggles() % 2 == 1 {
+            self.set_physics_mode(match self.physics_mode {
+                PhysicsMode::Flying => PhysicsMode::Walking,
+                PhysicsMode::Walking => PhysicsMode::Flying,
+            });
+        }
         self.camera.apply_input(input_state, delta_time);
-        self.update_player(input_state);
-        self.physics.step(delta_time);
+        self.pending_jumps += input_state.movement_jumps();
+        for _ in 0..physics_ticks(&mut self.physics_accumulator, delta_time) {
+            self.previous_physics_state = self.physics_state;
+            self.physics_state = self.step_physics(input_state, voxels);
+        }
+        // The camera lags up to a tick behind the simulation, in exchange for moving smoothly at any
+        // frame rate rather than in steps of whole ticks.
+        let alpha = self.physics_accumulator / PHYSICS_DT;
         self.camera.set_position(
-            self.physics.get_translation(self.camera_rigid_body_handle)
-                + Vector3::new(0., 0., AVERAGE_MALE_EYE_HEIGHT / 2.),
+            self.previous_physics_state
+                .position
+                .lerp(&self.physics_state.position, alpha),
         );
         if !self.sun_pause {
-            self.time_of_day += self.sun_speed * delta_time;
+            self.time_of_day += time_scale * delta_time / self.day_length_seconds;
+            self.time_of_day = self.time_of_day.rem_euclid(1.);
         }
         self.update_sun();
+        if let Some(index) = self.camera_light {
+            self.point_lights[index].position = self.camera.position();
+        }
         self.time += delta_time;
     }
 
-    pub fn update_player(&mut self, input_state: &InputState) {
+    // Advances the player by a single tick, always of the same length no matter the frame rate, so
+    // that movement and collisions behave the same on every machine.
+    fn step_physics(&mut self, input_state: &InputState, voxels: &Voxels) -> PhysicsState {
+        let jumps = std::mem::take(&mut self.pending_jumps);
+        let position = match self.physics_mode {
+            PhysicsMode::Flying => {
+                let previous = self.physics_state.position;
+                self.update_player(jumps);
+                self.physics.step(PHYSICS_DT);
+                let target = self.physics.get_translation(self.camera_rigid_body_handle)
+                    + Vector3::new(0., 0., AVERAGE_MALE_EYE_HEIGHT / 2.);
+                if self.fly_collision {
+                    self.collide_flying(previous, target, voxels)
+                } else {
+                    target
+                }
+            }
+            PhysicsMode::Walking => {
+                self.walker.update(
+                    self.camera.walk_direction(),
+                    input_state.movement_sprint(),
+                    jumps,
+                    PHYSICS_DT,
+                    voxels,
+                );
+                self.walker.feet + Vector3::new(0., 0., AVERAGE_MALE_EYE_HEIGHT)
+            }
+        };
+        PhysicsState { position }
+    }
+
+    fn update_player(&mut self, jumps: usize) {
         let rigid_body = self
             .physics
             .rigid_body_set
@@ -142,22 +262,153 @@ impl World {
         if can_accelerate {
             rigid_body.add_force(16. * self.camera.walk_direction(), true);
         }
-        if input_state.movement_jumps() > 0 {
+        if jumps > 0 {
             rigid_body.apply_impulse(Vector3::new(0., 0., 4.), true);
         }
     }
 
+    // Moving along each axis separately makes the camera slide along surfaces instead of stopping
+    // dead on any contact. Only solid voxels block, as flying into terrain that isn't generated yet
+    // is fine and should only be prevented once it's there.
+    fn collide_flying(
+        &mut self,
+        previous: Vector3<f32>,
+        target: Vector3<f32>,
+        voxels: &Voxels,
+    ) -> Vector3<f32> {
+        let rigid_body = self
+            .physics
+            .rigid_body_set
+            .get_mut(self.camera_rigid_body_handle)
+            .unwrap();
+        let mut position = previous;
+        let mut velocity = *rigid_body.linvel();
+        for axis in 0..3 {
+            let mut motion = Vector3::zeros();
+            motion[axis] = target[axis] - previous[axis];
+            let sweep = sweep_sphere(position, CAMERA_COLLISION_RADIUS, motion, false, voxels);
+            if sweep.collision == VoxelCollision::Solid {
+                position += sweep.fraction * motion;
+                velocity[axis] = 0.;
+            } else {
+                position += motion;
+            }
+        }
+        rigid_body.set_translation(
+            position - Vector3::new(0., 0., AVERAGE_MALE_EYE_HEIGHT / 2.),
+            true,
+        );
+        rigid_body.set_linvel(velocity, true);
+        position
+    }
+
+    #[cfg(feature = "dev-menu")]
+    pub fn physics_mode(&self) -> PhysicsMode {
+        self.physics_mode
+    }
+
+    // Both modes keep their own idea of where the player is, so the one being switched to has to
+    // be moved to wherever the camera currently is.
+    pub fn set_physics_mode(&mut self, physics_mode: PhysicsMode) {
+        if physics_mode == self.physics_mode {
+            return;
+        }
+        self.move_player_to_camera(physics_mode);
+        self.physics_mode = physics_mode;
+    }
+
+    // The camera follows the player every frame, so it's the player that has to be moved.
+    #[cfg(feature = "dev-menu")]
+    pub fn teleport(&mut self, position: Vector3<f32>) {
+        self.camera.set_position(position);
+        self.move_player_to_camera(self.physics_mode);
+    }
+
+    fn move_player_to_camera(&mut self, physics_mode: PhysicsMode) {
+        let camera = self.camera.position();
+        match physics_mode {
+            PhysicsMode::Flying => {
+                let rigid_body = self
+                    .physics
+                    .rigid_body_set
+                    .get_mut(self.camera_rigid_body_handle)
+                    .unwrap();
+                rigid_body.set_translation(
+                    camera - Vector3::new(0., 0., AVERAGE_MALE_EYE_HEIGHT / 2.),
+                    true,
+                );
+                rigid_body.set_linvel(Vector3::zeros(), true);
+            }
+            PhysicsMode::Walking => self
+                .walker
+                .teleport(camera - Vector3::new(0., 0., AVERAGE_MALE_EYE_HEIGHT)),
+        }
+        // Otherwise the camera would slide over from wherever the player was before.
+        self.physics_state = PhysicsState { position: camera };
+        self.previous_physics_state = self.physics_state;
+    }
+
+    #[cfg(feature = "dev-menu")]
+    pub fn debug_draw(&self, debug: &mut DebugDraw) {
+        self.physics.debug_draw(debug);
+    }
+
     pub fn update_sun(&mut self) {
-        let translation = &mut self.entities[0].transform.translation;
-        translation.x = self.sun_radius * self.time_of_day.sin();
-        translation.z = self.sun_radius * self.time_of_day.cos();
+        self.entities[0].transform.translation = self.sun_radius * self.sun_direction();
+    }
+
+    // The sun moves in a circle around the X axis, rising in the east (+X) and culminating at noon.
+    // Tilting the plane of that circle towards the south makes the noon sun not pass directly
+    // overhead, like it does outside of the tropics.
+    pub fn sun_direction(&self) -> Vector3<f32> {
+        let angle = 2. * PI * (self.time_of_day - 0.5);
+        Vector3::new(
+            angle.sin(),
+            -angle.cos() * self.axial_tilt.sin(),
+            angle.cos() * self.axial_tilt.cos(),
+        )
+    }
+
+    // Sine of the angle between the sun and the horizon, negative at night.
+    pub fn sun_elevation(&self) -> f32 {
+        self.sun_direction().z
+    }
+
+    // Sunlight travels through more atmosphere when the sun is low, so it gets redder towards the
+    // horizon. This just picks a color temperature based on the elevation instead of simulating
+    // that, and fades the light out once the sun sets.
+    pub fn sun_color(&self) -> Vector3<f32> {
+        let elevation = self.sun_elevation();
+        let daylight = smoothstep(0., 0.5, elevation);
+        let temperature = 2000. + (5800. - 2000.) * daylight;
+        blackbody_color(temperature) * smoothstep(-0.1, 0.05, elevation)
+    }
+
+    // Sky color when the atmosphere isn't simulated, blended between keyframes for night, sunrise,
+    // noon and sunset.
+    pub fn sky_color(&self) -> Vector3<f32> {
+        const KEYFRAMES: [(f32, Vector3<f32>); 5] = [
+            (0., Vector3::new(0.002, 0.003, 0.01)),
+            (0.25, Vector3::new(0.8, 0.4, 0.2)),
+            (0.5, Vector3::new(0.3, 0.5, 0.9)),
+            (0.75, Vector3::new(0.9, 0.35, 0.15)),
+            (1., Vector3::new(0.002, 0.003, 0.01)),
+        ];
+        let next = KEYFRAMES
+            .iter()
+            .position(|(time, _)| *time > self.time_of_day)
+            .unwrap_or(KEYFRAMES.len() - 1);
+        let (start_time, start_color) = KEYFRAMES[next - 1];
+        let (end_time, end_color) = KEYFRAMES[next];
+        let t = (self.time_of_day - start_time) / (end_time - start_time);
+        start_color.lerp(&end_color, smoothstep(0., 1., t))
     }
 
     pub fn light(&self) -> Light {
         Light {
             position: self.sun().transform.translation,
             intensity: self.sun_intensity,
-            color: Vector3::new(1., 1., 1.),
+            color: self.sun_color(),
             scale: 50.,
         }
     }
@@ -169,6 +420,105 @@ impl World {
     pub fn sun(&self) -> &Entity {
         &self.entities[0]
     }
+
+    // Places the object a few meters in front of the camera, which is mostly useful for looking at
+    // how things render up close.
+    #[cfg(feature = "dev-menu")]
+    pub fn spawn_object(&mut self, mesh: usize, color: Vector4<f32>) {
+        let translation =
+            self.camera.position() + OBJECT_SPAWN_DISTANCE * self.camera.view_direction();
+        self.objects.push(Object {
+            transform: Transform {
+                translation,
+                rotation: UnitQuaternion::identity(),
+                scale: Vector3::from_element(1.),
+            },
+            mesh,
+            color,
+        });
+    }
+
+    // Fills a cube centered on the camera with objects, so that only the ones in front of it are
+    // visible and the rest have to be culled. Used for measuring how drawing and culling scale with
+    // the object count.
+    pub fn spawn_benchmark_objects(&mut self, count: usize, mesh: usize) {
+        let side = (count as f32).cbrt().ceil() as usize;
+        let center = self.camera.position();
+        let offset = (side as f32 - 1.) / 2.;
+        for index in 0..count {
+            let cell = Vector3::new(index % side, index / side % side, index / side / side);
+            let translation = center
+                + (cell.cast::<f32>() - Vector3::from_element(offset)) * BENCHMARK_OBJECT_SPACING;
+            self.objects.push(Object {
+                transform: Transform {
+                    translation,
+                    rotation: UnitQuaternion::identity(),
+                    scale: Vector3::from_element(1.),
+                },
+                mesh,
+                color: Vector4::new(0.8, 0.8, 0.8, 1.),
+            });
+        }
+    }
+
+    // The renderer only has room for a fixed number of lights, so adding more than that only logs a
+    // warning instead of adding a light that would never be drawn. Only the dev menu adds lights
+    // for now.
+    #[cfg_attr(not(feature = "dev-menu"), allow(dead_code))]
+    pub fn add_point_light(&mut self, light: PointLight) {
+        if self.point_lights.len() >= POINT_LIGHT_MAX_COUNT {
+            warn!("too many point lights, \x1B[1mmax\x1B[0m: {POINT_LIGHT_MAX_COUNT}");
+            return;
+        }
+        self.point_lights.push(light);
+    }
+
+    #[cfg_attr(not(feature = "dev-menu"), allow(dead_code))]
+    pub fn remove_point_light(&mut self, index: usize) {
+        self.point_lights.remove(index);
+        self.camera_light = match self.camera_light {
+            Some(attached) if attached == index => None,
+            Some(attached) if attached > index => Some(attached - 1),
+            attached => attached,
+        };
+    }
+
+    // Nothing in the game emits particles yet, only the dev menu does.
+    #[cfg_attr(not(feature = "dev-menu"), allow(dead_code))]
+    pub fn emit_particles(&mut self, origin: Vector3<f32>, count: usize, settings: EmitSettings) {
+        let mut rng = rand::thread_rng();
+        self.emitted_particles
+            .extend((0..count).map(|_| settings.particle(origin, &mut rng)));
+    }
+}
+
+// Adds the frame time to the accumulator and takes out as many whole ticks as fit into it.
+fn physics_ticks(accumulator: &mut f32, delta_time: f32) -> usize {
+    *accumulator += delta_time;
+    let ticks = (*accumulator / PHYSICS_DT) as usize;
+    if ticks > PHYSICS_MAX_TICKS_PER_FRAME {
+        *accumulator = 0.;
+        return PHYSICS_MAX_TICKS_PER_FRAME;
+    }
+    *accumulator = (*accumulator - ticks as f32 * PHYSICS_DT).max(0.);
+    ticks
+}
+
+fn generate_stars(seed: u64) -> Vec<Star> {
+    let mut rng = StdRng::seed_from_u64(seed);
+    let mut stars = Vec::new();
+    for _ in 0..DEFAULT_STAR_COUNT {
+        stars.push(Star {
+            transform: Transform {
+                translation: DEFAULT_STAR_RADIUS * rng.sample(RandomDirection),
+                rotation: rng.sample(RandomRotation),
+                scale: Vector3::from_element(
+                    rng.gen_range(DEFAULT_STAR_MIN_SCALE..DEFAULT_STAR_MAX_SCALE),
+                ),
+            },
+        });
+    }
+    stars
 }
 
 impl Transform {
@@ -176,4 +526,111 @@ impl Transform {
         Matrix4::new_translation(&self.translation).prepend_nonuniform_scaling(&self.scale)
             * self.rotation.to_homogeneous()
     }
+
+    // Largest factor any distance is scaled by, which bounding spheres have to be scaled with.
+    pub fn max_scale(&self) -> f32 {
+        self.scale.abs().max()
+    }
+}
+
+fn smoothstep(edge0: f32, edge1: f32, x: f32) -> f32 {
+    let t = ((x - edge0) / (edge1 - edge0)).clamp(0., 1.);
+    t * t * (3. - 2. * t)
+}
+
+// Approximation of the color of a black body of the given temperature, normalized so that the
+// brightest channel is 1. Based on Tanner Helland's curve fit, which is good enough between 1000K
+// and 40000K.
+// https://tannerhelland.com/2012/09/18/convert-temperature-rgb-algorithm-code.html
+fn blackbody_color(kelvin: f32) -> Vector3<f32> {
+    let t = kelvin / 100.;
+    let red = if t <= 66. {
+        255.
+    } else {
+        329.699 * (t - 60.).powf(-0.133_204_76)
+    };
+    let green = if t <= 66. {
+        99.470_8 * t.ln() - 161.119_57
+    } else {
+        288.122_17 * (t - 60.).powf(-0.075_514_85)
+    };
+    let blue = if t >= 66. {
+        255.
+    } else if t <= 19. {
+        0.
+    } else {
+        138.517_73 * (t - 10.).ln() - 305.044_8
+    };
+    Vector3::new(red, green, blue).map(|channel| channel.clamp(0., 255.) / 255.)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stars_are_deterministic() {
+        let transforms = |seed| {
+            generate_stars(seed)
+                .iter()
+                .map(|star| star.transform.model_matrix())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(transforms(907), transforms(907));
+        assert_ne!(transforms(907), transforms(908));
+    }
+
+    #[test]
+    fn physics_ticks_accumulate() {
+        let mut accumulator = 0.;
+        assert_eq!(physics_ticks(&mut accumulator, PHYSICS_DT / 2.), 0);
+        assert_eq!(physics_ticks(&mut accumulator, PHYSICS_DT / 2.), 1);
+        assert_eq!(accumulator, 0.);
+        assert_eq!(physics_ticks(&mut accumulator, 2.5 * PHYSICS_DT), 2);
+        assert!((accumulator - PHYSICS_DT / 2.).abs() < 1e-6);
+        assert_eq!(
+            physics_ticks(&mut accumulator, 10.),
+            PHYSICS_MAX_TICKS_PER_FRAME
+        );
+        assert_eq!(accumulator, 0.);
+        // Frames of exactly one tick, like in benchmarks, never leave anything behind.
+        for _ in 0..1000 {
+            assert_eq!(physics_ticks(&mut accumulator, PHYSICS_DT), 1);
+            assert_eq!(accumulator, 0.);
+        }
+    }
+
+    fn point_light(intensity: f32) -> PointLight {
+        PointLight {
+            position: Vector3::zeros(),
+            radius: 16.,
+            color: Vector3::new(1., 1., 1.),
+            intensity,
+        }
+    }
+
+    #[test]
+    fn point_lights_past_limit_are_ignored() {
+        let mut world = World::new(0);
+        for index in 0..POINT_LIGHT_MAX_COUNT + 3 {
+            world.add_point_light(point_light(index as f32));
+        }
+        assert_eq!(world.point_lights.len(), POINT_LIGHT_MAX_COUNT);
+        let last = world.point_lights.last().unwrap();
+        assert_eq!(last.intensity, (POINT_LIGHT_MAX_COUNT - 1) as f32);
+    }
+
+    #[test]
+    fn removing_point_light_keeps_camera_light() {
+        let mut world = World::new(0);
+        for index in 0..3 {
+            world.add_point_light(point_light(index as f32));
+        }
+        world.camera_light = Some(2);
+        world.remove_point_light(0);
+        assert_eq!(world.camera_light, Some(1));
+        assert_eq!(world.point_lights[1].intensity, 2.);
+        world.remove_point_light(1);
+        assert_eq!(world.camera_light, None);
+    }
 }